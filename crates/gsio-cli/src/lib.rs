@@ -0,0 +1,875 @@
+//! GSIO Command-Line Tool
+//!
+//! This library implements the `gsio-cli` commands for working with GSIO
+//! nodes and wallets from a shell, built on `gsio-client` and `gsio-wallet`.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Parser, Subcommand};
+use gsio_client::{relay_token, Address, GsioClient, GsioClientError, LedgerEntry};
+use gsio_wallet::{verify_message, Transaction, TransactionType, Wallet, WalletError};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+
+mod output;
+
+pub use output::{Format, Table};
+
+/// Error type for GSIO command-line operations
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error(transparent)]
+    ClientError(#[from] GsioClientError),
+
+    #[error(transparent)]
+    WalletError(#[from] WalletError),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+}
+
+/// Command-line arguments
+#[derive(Debug, Parser)]
+#[command(name = "gsio-cli", about = "Command-line tool for GSIO-Net nodes and wallets")]
+pub struct Cli {
+    /// URL of a node; may be repeated to fail over between nodes
+    #[arg(long = "node", env = "GSIO_NODE", value_delimiter = ',', default_value = "http://localhost:3000", global = true)]
+    pub nodes: Vec<String>,
+    /// API key for nodes that require authentication
+    #[arg(long, env = "GSIO_API_KEY", hide_env_values = true, global = true)]
+    pub api_key: Option<String>,
+    /// How results are printed
+    #[arg(long, short, value_enum, default_value_t = Format::Table, global = true)]
+    pub output: Format,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add and list ledger entries
+    #[command(subcommand)]
+    Ledger(LedgerCommand),
+    /// List the nodes a node knows about
+    #[command(subcommand)]
+    Nodes(NodesCommand),
+    /// Create wallets and send transactions
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+    /// Issue access to gsio-relay channels
+    #[command(subcommand)]
+    Relay(RelayCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LedgerCommand {
+    /// Add an entry holding the given JSON data
+    Add {
+        /// Entry data, such as '{"message": "hello"}'
+        data: String,
+    },
+    /// List entries, oldest first
+    List {
+        /// Number of entries to skip
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        /// Most entries to list
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NodesCommand {
+    /// List the IDs of the nodes the node knows about
+    List,
+    /// Introduce the node to another node, for when discovery can't connect them; needs the admin key as `--api-key`
+    Join {
+        /// A blob ticket the other node served, or its iroh node ID
+        ticket: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WalletCommand {
+    /// Create a wallet file with one account
+    Create {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// Derive the account from a new mnemonic, which is printed as a backup
+        #[arg(long)]
+        mnemonic: bool,
+    },
+    /// Send a transfer from a wallet account and wait for it to be confirmed
+    Send {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// Address or contact alias to send to
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee to pay; defaults to the fee the node asks for
+        #[arg(long)]
+        fee: Option<u64>,
+        /// Account to send from; may be left out if the wallet has only one
+        #[arg(long)]
+        from: Option<String>,
+    },
+    /// Keep an address book of aliases to send to
+    #[command(subcommand)]
+    Contacts(ContactsCommand),
+    /// Add a new random key to a wallet, creating the wallet file if there isn't one
+    Keygen {
+        #[command(flatten)]
+        wallet: WalletArgs,
+    },
+    /// Add a key printed by `wallet export` to a wallet, creating the wallet file if there isn't one
+    Import {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// The exported key: hex, or JSON if it was exported with --encrypted; `-` reads it from stdin
+        key: String,
+        /// Passphrase an encrypted key was exported with; defaults to the wallet's
+        #[arg(long, env = "GSIO_KEY_PASSPHRASE", hide_env_values = true)]
+        key_passphrase: Option<String>,
+    },
+    /// Print an account's secret key
+    Export {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// Account to export; may be left out if the wallet has only one
+        #[arg(long)]
+        address: Option<String>,
+        /// Encrypt the key under a passphrase instead of printing it in the clear
+        #[arg(long)]
+        encrypted: bool,
+        /// Passphrase to encrypt the key under with --encrypted; defaults to the wallet's
+        #[arg(long, env = "GSIO_KEY_PASSPHRASE", hide_env_values = true)]
+        key_passphrase: Option<String>,
+    },
+    /// Sign a message with an account's key
+    Sign {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// Account to sign with; may be left out if the wallet has only one
+        #[arg(long)]
+        address: Option<String>,
+        #[command(flatten)]
+        message: MessageArgs,
+    },
+    /// Replace an account's key with a new one and record the rotation on the node's ledger
+    Rotate {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        /// Account to rotate; may be left out if the wallet has only one
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Check a signature made by `wallet sign`
+    Verify {
+        /// Hex-encoded public key of the signer
+        #[arg(long)]
+        public_key: String,
+        /// Hex-encoded signature
+        #[arg(long)]
+        signature: String,
+        #[command(flatten)]
+        message: MessageArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ContactsCommand {
+    /// Save an address under an alias, replacing what the alias pointed at before
+    Add {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        alias: String,
+        address: String,
+    },
+    /// List the address book
+    List {
+        #[command(flatten)]
+        wallet: WalletArgs,
+    },
+    /// Remove a contact
+    Remove {
+        #[command(flatten)]
+        wallet: WalletArgs,
+        alias: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RelayCommand {
+    /// Issue a token that lets a node join a relay channel
+    Token {
+        /// Node ID the token is issued to
+        node_id: String,
+        /// Channel the token is for
+        #[arg(long)]
+        channel: String,
+        /// Secret the channel was created with
+        #[arg(long, env = "GSIO_RELAY_SECRET", hide_env_values = true)]
+        secret: String,
+        /// Hours until the token expires
+        #[arg(long, default_value_t = 720)]
+        valid_for: u64,
+    },
+}
+
+/// Where a wallet is kept and how to unlock it
+#[derive(Debug, Args)]
+pub struct WalletArgs {
+    /// Wallet file
+    #[arg(long, env = "GSIO_WALLET", default_value = "wallet.json")]
+    pub wallet: PathBuf,
+    /// Passphrase the wallet's keys are encrypted with
+    #[arg(long, env = "GSIO_WALLET_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: String,
+}
+
+/// The message `wallet sign` and `wallet verify` work on
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct MessageArgs {
+    /// Message, as text
+    pub message: Option<String>,
+    /// Read the message from a file instead
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+}
+
+impl MessageArgs {
+    fn read(&self) -> Result<Vec<u8>, CliError> {
+        match (&self.message, &self.file) {
+            (_, Some(path)) => Ok(std::fs::read(path)?),
+            (Some(message), None) => Ok(message.clone().into_bytes()),
+            (None, None) => Err(CliError::InvalidInput("No message given".to_string())),
+        }
+    }
+}
+
+/// A newly created wallet
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedWallet {
+    pub path: PathBuf,
+    pub address: String,
+    /// Phrase that recovers the wallet, if it was created from one
+    pub mnemonic: Option<String>,
+}
+
+/// A key added to or exported from a wallet
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletKey {
+    pub address: String,
+    pub public_key: String,
+    /// The exported secret key: hex, or JSON if it was encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// A message signature
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageSignature {
+    pub address: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// An account whose key was rotated
+#[derive(Debug, Clone, Serialize)]
+pub struct RotatedAccount {
+    pub old_address: String,
+    pub new_address: String,
+    pub new_public_key: String,
+    /// ID of the ledger entry recording the rotation
+    pub entry_id: String,
+}
+
+/// An address book entry
+#[derive(Debug, Clone, Serialize)]
+pub struct Contact {
+    pub alias: String,
+    pub address: String,
+}
+
+/// A token for a relay channel
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedToken {
+    pub token: String,
+    /// Unix time in seconds after which the relay refuses the token
+    pub expires: u64,
+}
+
+/// Run `cli`, printing the result to `out`
+pub async fn run(cli: Cli, out: &mut impl Write) -> Result<(), CliError> {
+    let format = cli.output;
+    let client = || -> Result<GsioClient, CliError> {
+        let client = GsioClient::new_multi(&cli.nodes)?;
+        Ok(match &cli.api_key {
+            Some(key) => client.with_api_key(key.clone()),
+            None => client,
+        })
+    };
+
+    match cli.command {
+        Command::Ledger(LedgerCommand::Add { data }) => {
+            let data: JsonValue =
+                serde_json::from_str(&data).map_err(|e| CliError::InvalidInput(format!("Entry data isn't JSON: {e}")))?;
+            let entry = client()?.add_ledger_entry(data).await?;
+            output::print(out, format, &entry, |entry| entry_table(std::slice::from_ref(entry)))?;
+        }
+        Command::Ledger(LedgerCommand::List { offset, limit }) => {
+            let entries = client()?.get_ledger_paginated(offset, limit).await?;
+            output::print(out, format, &entries, |entries| entry_table(entries))?;
+        }
+        Command::Nodes(NodesCommand::List) => {
+            let nodes = client()?.get_known_nodes().await?;
+            output::print(out, format, &nodes, |nodes| {
+                nodes.iter().fold(Table::new(&["NODE"]), |table, node| table.row([node.clone()]))
+            })?;
+        }
+        Command::Nodes(NodesCommand::Join { ticket }) => {
+            let joined = client()?.join_peer(&ticket).await?;
+            output::print(out, format, &joined, |joined| {
+                Table::new(&["NODE", "URL", "LEARNED"]).row([
+                    joined.peer.node_id.clone(),
+                    joined.peer.url.clone(),
+                    joined.learned.to_string(),
+                ])
+            })?;
+        }
+        Command::Wallet(WalletCommand::Create { wallet, mnemonic }) => {
+            let created = create_wallet(wallet, mnemonic)?;
+            output::print(out, format, &created, |created| {
+                let table = Table::new(&["FIELD", "VALUE"])
+                    .row(["path".to_string(), created.path.display().to_string()])
+                    .row(["address".to_string(), created.address.clone()]);
+                match &created.mnemonic {
+                    Some(mnemonic) => table.row(["mnemonic".to_string(), mnemonic.clone()]),
+                    None => table,
+                }
+            })?;
+        }
+        Command::Wallet(WalletCommand::Send { wallet, to, amount, fee, from }) => {
+            let transaction = send(&client()?, wallet, from, &to, amount, fee).await?;
+            output::print(out, format, &transaction, |transaction| {
+                Table::new(&["ID", "FROM", "TO", "AMOUNT", "FEE", "STATUS"]).row([
+                    transaction.id.clone(),
+                    transaction.sender.clone(),
+                    transaction.recipient.clone(),
+                    transaction.amount.to_string(),
+                    transaction.fee.to_string(),
+                    format!("{:?}", transaction.status),
+                ])
+            })?;
+        }
+        Command::Wallet(WalletCommand::Contacts(command)) => {
+            let contacts = contacts(command)?;
+            output::print(out, format, &contacts, |contacts| {
+                contacts.iter().fold(Table::new(&["ALIAS", "ADDRESS"]), |table, contact| {
+                    table.row([contact.alias.clone(), contact.address.clone()])
+                })
+            })?;
+        }
+        Command::Wallet(WalletCommand::Keygen { wallet }) => {
+            let key = add_key(&wallet, |opened| opened.generate_keypair())?;
+            output::print(out, format, &key, key_table)?;
+        }
+        Command::Wallet(WalletCommand::Import { wallet, key, key_passphrase }) => {
+            let key = if key == "-" { io::read_to_string(io::stdin())? } else { key };
+            let passphrase = key_passphrase.as_deref().unwrap_or(&wallet.passphrase);
+            let key = add_key(&wallet, |opened| opened.import_key(&key, Some(passphrase)))?;
+            output::print(out, format, &key, key_table)?;
+        }
+        Command::Wallet(WalletCommand::Export { wallet, address, encrypted, key_passphrase }) => {
+            let opened = open_wallet(&wallet)?;
+            let address = pick_account(&opened, address, "--address")?;
+            let exported = if encrypted {
+                opened.export_encrypted_key(&address, key_passphrase.as_deref().unwrap_or(&wallet.passphrase))?
+            } else {
+                opened.export_key(&address)?
+            };
+            let key = wallet_key(&opened, address, Some(exported))?;
+            output::print(out, format, &key, key_table)?;
+        }
+        Command::Wallet(WalletCommand::Sign { wallet, address, message }) => {
+            let opened = open_wallet(&wallet)?;
+            let address = pick_account(&opened, address, "--address")?;
+            let signature = opened.sign_message(&address, &message.read()?)?;
+            let public_key = opened.get_account(&address)?.public_key.clone();
+            let signed = MessageSignature { address, public_key, signature };
+            output::print(out, format, &signed, |signed| {
+                Table::new(&["FIELD", "VALUE"])
+                    .row(["address".to_string(), signed.address.clone()])
+                    .row(["public_key".to_string(), signed.public_key.clone()])
+                    .row(["signature".to_string(), signed.signature.clone()])
+            })?;
+        }
+        Command::Wallet(WalletCommand::Rotate { wallet, address }) => {
+            let rotated = rotate(&client()?, wallet, address).await?;
+            output::print(out, format, &rotated, |rotated| {
+                Table::new(&["FIELD", "VALUE"])
+                    .row(["old_address".to_string(), rotated.old_address.clone()])
+                    .row(["new_address".to_string(), rotated.new_address.clone()])
+                    .row(["new_public_key".to_string(), rotated.new_public_key.clone()])
+                    .row(["entry_id".to_string(), rotated.entry_id.clone()])
+            })?;
+        }
+        Command::Wallet(WalletCommand::Verify { public_key, signature, message }) => {
+            verify_message(&message.read()?, &signature, &public_key)?;
+            let key = WalletKey { address: address_of(&public_key)?, public_key, key: None };
+            output::print(out, format, &key, key_table)?;
+        }
+        Command::Relay(RelayCommand::Token { node_id, channel, secret, valid_for }) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let expires = now + valid_for * 60 * 60;
+            let issued = IssuedToken { token: relay_token(&secret, &channel, &node_id, expires), expires };
+            output::print(out, format, &issued, |issued| {
+                Table::new(&["TOKEN", "EXPIRES"]).row([issued.token.clone(), issued.expires.to_string()])
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn entry_table(entries: &[LedgerEntry]) -> Table {
+    entries.iter().fold(Table::new(&["ID", "TIMESTAMP", "CREATOR", "DATA"]), |table, entry| {
+        table.row([
+            entry.id.clone(),
+            entry.timestamp.to_rfc3339(),
+            entry.creator_node_id.clone(),
+            output::truncate(&entry.data.to_string()),
+        ])
+    })
+}
+
+fn key_table(key: &WalletKey) -> Table {
+    let table = Table::new(&["FIELD", "VALUE"])
+        .row(["address".to_string(), key.address.clone()])
+        .row(["public_key".to_string(), key.public_key.clone()]);
+    match &key.key {
+        Some(exported) => table.row(["key".to_string(), exported.clone()]),
+        None => table,
+    }
+}
+
+/// Load the wallet `args` names
+fn open_wallet(args: &WalletArgs) -> Result<Wallet, CliError> {
+    let mut wallet = Wallet::new();
+    wallet.load(&args.wallet, &args.passphrase)?;
+    Ok(wallet)
+}
+
+/// The account named by `address`, which may be left out, given with `flag`,
+/// when the wallet has only one
+fn pick_account(wallet: &Wallet, address: Option<String>, flag: &str) -> Result<String, CliError> {
+    match address {
+        Some(address) => Ok(address),
+        None => match wallet.accounts().as_slice() {
+            [account] => Ok(account.address.clone()),
+            [] => Err(CliError::InvalidInput("The wallet has no accounts".to_string())),
+            _ => Err(CliError::InvalidInput(format!("The wallet has several accounts; pick one with {flag}"))),
+        },
+    }
+}
+
+/// The address of a hex-encoded public key
+fn address_of(public_key: &str) -> Result<String, CliError> {
+    let bytes: [u8; 32] = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| CliError::InvalidInput("The public key isn't 32 hex-encoded bytes".to_string()))?;
+    Ok(Address::from_public_key(&bytes).to_string())
+}
+
+/// Add a key to the wallet `args` names with `add`, creating the wallet if it doesn't exist
+fn add_key(
+    args: &WalletArgs,
+    add: impl FnOnce(&mut Wallet) -> Result<String, WalletError>,
+) -> Result<WalletKey, CliError> {
+    let mut wallet = if args.wallet.exists() {
+        open_wallet(args)?
+    } else {
+        let mut wallet = Wallet::new();
+        wallet.set_path(&args.wallet);
+        wallet
+    };
+    let address = add(&mut wallet)?;
+    wallet.save(&args.passphrase)?;
+    wallet_key(&wallet, address, None)
+}
+
+fn wallet_key(wallet: &Wallet, address: String, key: Option<String>) -> Result<WalletKey, CliError> {
+    let public_key = wallet.get_account(&address)?.public_key.clone();
+    Ok(WalletKey { address, public_key, key })
+}
+
+/// Create and save a wallet, refusing to overwrite an existing file
+fn create_wallet(args: WalletArgs, mnemonic: bool) -> Result<CreatedWallet, CliError> {
+    if args.wallet.exists() {
+        return Err(CliError::InvalidInput(format!("{} already exists", args.wallet.display())));
+    }
+
+    let mut wallet = Wallet::new();
+    let (address, mnemonic) = if mnemonic {
+        let phrase = wallet.generate_with_mnemonic()?;
+        (wallet.accounts()[0].address.clone(), Some(phrase))
+    } else {
+        (wallet.generate_keypair()?, None)
+    };
+    wallet.set_path(&args.wallet);
+    wallet.save(&args.passphrase)?;
+
+    Ok(CreatedWallet { path: args.wallet, address, mnemonic })
+}
+
+/// Change or list a wallet's address book, returning the contacts the command is about
+fn contacts(command: ContactsCommand) -> Result<Vec<Contact>, CliError> {
+    let args = match &command {
+        ContactsCommand::Add { wallet, .. } | ContactsCommand::List { wallet } | ContactsCommand::Remove { wallet, .. } => {
+            wallet
+        }
+    };
+    let mut wallet = open_wallet(args)?;
+
+    let contacts = match &command {
+        ContactsCommand::Add { alias, address, .. } => {
+            wallet.add_contact(alias, address)?;
+            wallet.save(&args.passphrase)?;
+            vec![Contact { alias: alias.clone(), address: address.clone() }]
+        }
+        ContactsCommand::List { .. } => wallet
+            .contacts()
+            .iter()
+            .map(|(alias, address)| Contact { alias: alias.clone(), address: address.clone() })
+            .collect(),
+        ContactsCommand::Remove { alias, .. } => {
+            let address = wallet
+                .remove_contact(alias)
+                .ok_or_else(|| CliError::InvalidInput(format!("No contact named {alias}")))?;
+            wallet.save(&args.passphrase)?;
+            vec![Contact { alias: alias.clone(), address }]
+        }
+    };
+    Ok(contacts)
+}
+
+/// Rotate an account's key, saving the new key before the rotation is submitted so it can't be lost
+async fn rotate(client: &GsioClient, args: WalletArgs, address: Option<String>) -> Result<RotatedAccount, CliError> {
+    let mut wallet = open_wallet(&args)?;
+    let old_address = pick_account(&wallet, address, "--address")?;
+    let (new_address, rotation) = wallet.rotate_key(&old_address)?;
+    wallet.save(&args.passphrase)?;
+
+    let data = wallet.sign_entry(&old_address, rotation.to_entry_data())?;
+    let entry = client.add_ledger_entry(data).await?;
+    Ok(RotatedAccount { old_address, new_address, new_public_key: rotation.new_key, entry_id: entry.id })
+}
+
+/// Sync the wallet with the node, then sign and submit a transfer
+async fn send(
+    client: &GsioClient,
+    args: WalletArgs,
+    from: Option<String>,
+    to: &str,
+    amount: u64,
+    fee: Option<u64>,
+) -> Result<Transaction, CliError> {
+    let mut wallet = open_wallet(&args)?;
+    let from = pick_account(&wallet, from, "--from")?;
+
+    // Balances are only known from the ledger
+    wallet.sync(client).await?;
+    let fee = match fee {
+        Some(fee) => fee,
+        None => {
+            wallet.fetch_fee_rules(client).await?;
+            wallet.fetch_fee_floor(client).await?;
+            wallet.estimate_fee(&TransactionType::Transfer, 0)?
+        }
+    };
+    let mut transaction = wallet.create_transaction(&from, to, amount, fee, TransactionType::Transfer, None)?;
+    wallet.sign_transaction(&mut transaction)?;
+    wallet.submit_transaction(client, &mut transaction).await?;
+    wallet.save(&args.passphrase)?;
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use gsio_node::api;
+    use gsio_node::fees::{self, FeeConfig};
+    use gsio_node::ledger::SharedLedger;
+    use gsio_node::p2p::P2PManager;
+    use gsio_wallet::TransactionStatus;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+    const RECIPIENT: &str = "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w8";
+
+    async fn start_node() -> String {
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+        p2p.ledger.add_known_node("test-node-2".to_string());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = api::router(p2p).merge(fees::router(Arc::new(FeeConfig::default())));
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{addr}")
+    }
+
+    /// Run a command line against `url`, returning what it printed
+    async fn run_args(url: &str, args: &[&str]) -> Result<String, CliError> {
+        let cli = Cli::try_parse_from(["gsio-cli", "--node", url].iter().chain(args)).unwrap();
+        let mut out = Vec::new();
+        run(cli, &mut out).await?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn temp_wallet_path() -> PathBuf {
+        std::env::temp_dir().join(format!("gsio-cli-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_ledger_and_nodes() {
+        let url = start_node().await;
+
+        let printed = run_args(&url, &["ledger", "add", r#"{"message": "Test entry 1"}"#, "-o", "json"]).await.unwrap();
+        let entry: LedgerEntry = serde_json::from_str(&printed).unwrap();
+        assert_eq!(entry.data["message"], "Test entry 1");
+        assert!(matches!(
+            run_args(&url, &["ledger", "add", "not json"]).await,
+            Err(CliError::InvalidInput(_))
+        ));
+
+        let printed = run_args(&url, &["ledger", "list"]).await.unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("ID"));
+        assert!(lines[1].starts_with(&entry.id));
+        assert!(lines[1].contains(r#"{"message":"Test entry 1"}"#));
+
+        let printed = run_args(&url, &["ledger", "list", "--offset", "1", "--output", "json"]).await.unwrap();
+        assert_eq!(printed.trim(), "[]");
+
+        let printed = run_args(&url, &["nodes", "list"]).await.unwrap();
+        assert!(printed.lines().any(|line| line == "test-node-2"));
+    }
+
+    #[tokio::test]
+    async fn test_wallet_create_and_send() {
+        let url = start_node().await;
+        let path = temp_wallet_path();
+        let wallet_args = ["--wallet", path.to_str().unwrap(), "--passphrase", PASSPHRASE];
+
+        let create = ["wallet", "create", "--mnemonic", "-o", "json"];
+        let printed = run_args(&url, &[&create[..], &wallet_args[..]].concat()).await.unwrap();
+        let created: JsonValue = serde_json::from_str(&printed).unwrap();
+        let address = created["address"].as_str().unwrap().to_string();
+        assert_eq!(created["mnemonic"].as_str().unwrap().split_whitespace().count(), 24);
+        // An existing wallet is never overwritten
+        assert!(matches!(
+            run_args(&url, &[&create[..], &wallet_args[..]].concat()).await,
+            Err(CliError::InvalidInput(_))
+        ));
+
+        // Nothing to send until the account is credited on the ledger
+        let send = ["wallet", "send", "--to", RECIPIENT, "--amount", "100", "--fee", "1", "-o", "json"];
+        assert!(matches!(
+            run_args(&url, &[&send[..], &wallet_args[..]].concat()).await,
+            Err(CliError::WalletError(WalletError::InsufficientFunds(101, 0)))
+        ));
+
+        let client = GsioClient::new(&url).unwrap();
+        let mut funder = Wallet::new();
+        let funder_address = funder.generate_keypair().unwrap();
+        let mut funding = Transaction {
+            id: uuid::Uuid::new_v4().to_string(),
+            transaction_type: TransactionType::Transfer,
+            amount: 500,
+            fee: 0,
+            nonce: 0,
+            sender: funder_address,
+            recipient: address.clone(),
+            timestamp: Utc::now(),
+            status: TransactionStatus::Pending,
+            signature: None,
+            data: None,
+        };
+        funder.sign_transaction(&mut funding).unwrap();
+        funder.submit_transaction(&client, &mut funding).await.unwrap();
+
+        let printed = run_args(&url, &[&send[..], &wallet_args[..]].concat()).await.unwrap();
+        let transaction: Transaction = serde_json::from_str(&printed).unwrap();
+        assert_eq!((transaction.sender.as_str(), transaction.amount), (address.as_str(), 100));
+        assert!(matches!(transaction.status, TransactionStatus::Confirmed));
+
+        // The saved wallet has both transfers in its history
+        let mut wallet = Wallet::new();
+        wallet.load(&path, PASSPHRASE).unwrap();
+        assert_eq!(wallet.get_balance(&address).unwrap(), 399);
+        assert_eq!(wallet.get_transaction_history(&address).unwrap(), vec![funding.id, transaction.id]);
+
+        // Without --fee the node's fee is paid, and the next nonce is used
+        let add = ["wallet", "contacts", "add", "alice", RECIPIENT];
+        run_args(&url, &[&add[..], &wallet_args[..]].concat()).await.unwrap();
+        let send = ["wallet", "send", "--to", "alice", "--amount", "50", "-o", "json"];
+        let printed = run_args(&url, &[&send[..], &wallet_args[..]].concat()).await.unwrap();
+        let transaction: Transaction = serde_json::from_str(&printed).unwrap();
+        assert_eq!((transaction.fee, transaction.nonce), (FeeConfig::default().base, 1));
+        assert_eq!(transaction.recipient, RECIPIENT);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wallet_contacts() {
+        let path = temp_wallet_path();
+        let wallet_args = ["--wallet", path.to_str().unwrap(), "--passphrase", PASSPHRASE];
+        run_args("http://unused", &[&["wallet", "create"][..], &wallet_args[..]].concat()).await.unwrap();
+
+        let add = |alias: &'static str, address: &'static str| ["wallet", "contacts", "add", alias, address];
+        run_args("http://unused", &[&add("bob", RECIPIENT)[..], &wallet_args[..]].concat()).await.unwrap();
+        run_args("http://unused", &[&add("alice", RECIPIENT)[..], &wallet_args[..]].concat()).await.unwrap();
+        assert!(matches!(
+            run_args("http://unused", &[&add("carol", "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w9")[..], &wallet_args[..]].concat()).await,
+            Err(CliError::WalletError(WalletError::InvalidAddress(_)))
+        ));
+
+        let list = ["wallet", "contacts", "list"];
+        let printed = run_args("http://unused", &[&list[..], &wallet_args[..]].concat()).await.unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("alice") && lines[1].ends_with(RECIPIENT));
+        assert!(lines[2].starts_with("bob"));
+
+        let remove = ["wallet", "contacts", "remove", "bob", "-o", "json"];
+        let printed = run_args("http://unused", &[&remove[..], &wallet_args[..]].concat()).await.unwrap();
+        let removed: JsonValue = serde_json::from_str(&printed).unwrap();
+        assert_eq!(removed, serde_json::json!([{ "alias": "bob", "address": RECIPIENT }]));
+        assert!(matches!(
+            run_args("http://unused", &[&remove[..], &wallet_args[..]].concat()).await,
+            Err(CliError::InvalidInput(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wallet_keys() {
+        let path = temp_wallet_path();
+        let wallet_args = ["--wallet", path.to_str().unwrap(), "--passphrase", PASSPHRASE];
+
+        // keygen creates the wallet, then adds to it
+        let keygen = ["wallet", "keygen", "-o", "json"];
+        let printed = run_args("http://unused", &[&keygen[..], &wallet_args[..]].concat()).await.unwrap();
+        let first: JsonValue = serde_json::from_str(&printed).unwrap();
+        let address = first["address"].as_str().unwrap();
+        run_args("http://unused", &[&keygen[..], &wallet_args[..]].concat()).await.unwrap();
+        assert!(matches!(
+            run_args("http://unused", &[&["wallet", "export"][..], &wallet_args[..]].concat()).await,
+            Err(CliError::InvalidInput(_))
+        ));
+
+        let export = ["wallet", "export", "--address", address, "-o", "json"];
+        let printed = run_args("http://unused", &[&export[..], &wallet_args[..]].concat()).await.unwrap();
+        let plain: JsonValue = serde_json::from_str(&printed).unwrap();
+        let encrypted = ["--encrypted", "--key-passphrase", "export"];
+        let printed = run_args("http://unused", &[&export[..], &encrypted[..], &wallet_args[..]].concat()).await.unwrap();
+        let exported: JsonValue = serde_json::from_str(&printed).unwrap();
+        assert_eq!(exported["address"], address);
+        assert!(!exported["key"].as_str().unwrap().contains(plain["key"].as_str().unwrap()));
+
+        // The key moves to a new wallet, under that wallet's passphrase
+        let other = temp_wallet_path();
+        let other_args = ["--wallet", other.to_str().unwrap(), "--passphrase", "other"];
+        let import = ["wallet", "import", exported["key"].as_str().unwrap(), "--key-passphrase", "export"];
+        run_args("http://unused", &[&import[..], &other_args[..]].concat()).await.unwrap();
+        let mut imported = Wallet::new();
+        imported.load(&other, "other").unwrap();
+        assert_eq!(imported.export_key(address).unwrap(), plain["key"].as_str().unwrap());
+
+        // Signatures verify against the message they were made over
+        let sign = ["wallet", "sign", "hello", "-o", "json"];
+        let printed = run_args("http://unused", &[&sign[..], &other_args[..]].concat()).await.unwrap();
+        let signed: JsonValue = serde_json::from_str(&printed).unwrap();
+        let (public_key, signature) = (signed["public_key"].as_str().unwrap(), signed["signature"].as_str().unwrap());
+        let verify = ["wallet", "verify", "--public-key", public_key, "--signature", signature];
+        let verified = run_args("http://unused", &[&verify[..], &["hello", "-o", "json"]].concat()).await.unwrap();
+        assert_eq!(serde_json::from_str::<JsonValue>(&verified).unwrap()["address"], address);
+        assert!(matches!(
+            run_args("http://unused", &[&verify[..], &["hello!"]].concat()).await,
+            Err(CliError::WalletError(WalletError::SignatureError(_)))
+        ));
+
+        let message = temp_wallet_path();
+        std::fs::write(&message, "hello").unwrap();
+        run_args("http://unused", &[&verify[..], &["--file", message.to_str().unwrap()]].concat()).await.unwrap();
+
+        for path in [path, other, message] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wallet_rotate() {
+        let url = start_node().await;
+        let path = temp_wallet_path();
+        let wallet_args = ["--wallet", path.to_str().unwrap(), "--passphrase", PASSPHRASE];
+        let printed = run_args(&url, &[&["wallet", "create", "-o", "json"][..], &wallet_args[..]].concat()).await.unwrap();
+        let address = serde_json::from_str::<JsonValue>(&printed).unwrap()["address"].as_str().unwrap().to_string();
+
+        let rotate = ["wallet", "rotate", "-o", "json"];
+        let printed = run_args(&url, &[&rotate[..], &wallet_args[..]].concat()).await.unwrap();
+        let rotated: JsonValue = serde_json::from_str(&printed).unwrap();
+        assert_eq!(rotated["old_address"], address);
+
+        // The rotation is on the ledger and the new key is saved in the wallet
+        let client = GsioClient::new(&url).unwrap();
+        let entry = client.get_entry_by_id(rotated["entry_id"].as_str().unwrap()).await.unwrap().unwrap();
+        assert_eq!(entry.data["key_rotation"]["new_key"], rotated["new_public_key"]);
+        let mut wallet = Wallet::new();
+        wallet.load(&path, PASSPHRASE).unwrap();
+        let new_address = rotated["new_address"].as_str().unwrap();
+        assert_eq!(wallet.get_account(new_address).unwrap().public_key, rotated["new_public_key"]);
+
+        // The node refuses to rotate the old key again
+        let again = ["wallet", "rotate", "--address", &address];
+        assert!(matches!(
+            run_args(&url, &[&again[..], &wallet_args[..]].concat()).await,
+            Err(CliError::ClientError(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+        #[tokio::test]
+    async fn test_relay_token() {
+        let token = ["relay", "token", "node-a", "--channel", "mesh", "--secret", PASSPHRASE, "--valid-for", "2"];
+        let printed = run_args("http://unused", &[&token[..], &["-o", "json"]].concat()).await.unwrap();
+        let issued: JsonValue = serde_json::from_str(&printed).unwrap();
+        let expires = issued["expires"].as_u64().unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!((now + 7_100..=now + 7_200).contains(&expires));
+        assert_eq!(issued["token"], relay_token(PASSPHRASE, "mesh", "node-a", expires));
+
+        let printed = run_args("http://unused", &token).await.unwrap();
+        assert!(printed.lines().nth(1).unwrap().starts_with(&format!("node-a.{expires}.")));
+    }
+}
@@ -0,0 +1,118 @@
+//! Printing command results as JSON or as a table.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Widest a cell passed through [`truncate`] gets
+const MAX_CELL_WIDTH: usize = 64;
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for reading in a terminal
+    #[default]
+    Table,
+    /// Pretty-printed JSON for scripts
+    Json,
+}
+
+/// Rows of text under a header, printed with aligned columns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Create an empty table with the given column headers
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row, putting each cell on one line; cells past the number of columns are dropped
+    pub fn row(mut self, cells: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let mut row: Vec<String> = cells.into_iter().map(|cell| cell.into().replace(['\n', '\r'], " ")).collect();
+        row.resize(self.headers.len(), String::new());
+        self.rows.push(row);
+        self
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers = self.headers.iter().map(|header| header.to_string());
+        for row in std::iter::once(headers.collect::<Vec<_>>()).chain(self.rows.iter().cloned()) {
+            let line: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{cell:width$}")).collect();
+            writeln!(f, "{}", line.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Shorten a cell that can be arbitrarily long, such as entry data, to [`MAX_CELL_WIDTH`] characters
+pub fn truncate(cell: &str) -> String {
+    if cell.chars().count() <= MAX_CELL_WIDTH {
+        return cell.to_string();
+    }
+    let mut short: String = cell.chars().take(MAX_CELL_WIDTH - 3).collect();
+    short.push_str("...");
+    short
+}
+
+/// Print `value` in `format`, building the table for it with `table`
+pub fn print<T: Serialize>(
+    out: &mut impl Write,
+    format: Format,
+    value: &T,
+    table: impl FnOnce(&T) -> Table,
+) -> io::Result<()> {
+    match format {
+        Format::Table => write!(out, "{}", table(value)),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, value)?;
+            writeln!(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_columns_are_aligned() {
+        let table = Table::new(&["ID", "NODE"]).row(["1", "test-node-1"]).row(["1234", "n2"]);
+        assert_eq!(table.to_string(), "ID    NODE\n1     test-node-1\n1234  n2\n");
+    }
+
+    #[test]
+    fn test_long_cells_are_cut_short() {
+        let table = Table::new(&["DATA"]).row([truncate(&"x".repeat(100))]).row(["a\nb"]);
+        let text = table.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1].chars().count(), MAX_CELL_WIDTH);
+        assert!(lines[1].ends_with("..."));
+        assert_eq!(lines[2], "a b");
+    }
+
+    #[test]
+    fn test_print_json() {
+        let mut out = Vec::new();
+        print(&mut out, Format::Json, &vec!["test-node-1"], |_| unreachable!()).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value, serde_json::json!(["test-node-1"]));
+    }
+}
@@ -0,0 +1,234 @@
+//! gsio addresses.
+//!
+//! An address is the first 20 bytes of an account's Ed25519 public key,
+//! written in bech32m (BIP-350) with the human-readable part `gsio`, such as
+//! `gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w8`. The checksum catches any
+//! typo of up to four characters, so mistyped addresses are refused before
+//! funds move. Addresses used to be written `gsio_<40 hex digits>`; those
+//! carry no checksum and are only read, by [`Address::parse_any`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+/// Human-readable part of every address
+pub const ADDRESS_HRP: &str = "gsio";
+
+/// Prefix of addresses written before they had a checksum
+const LEGACY_PREFIX: &str = "gsio_";
+
+/// Characters of the bech32 alphabet, by value
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// What the bech32m checksum of a valid address works out to
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+/// Number of checksum characters
+const CHECKSUM_LEN: usize = 6;
+
+/// Number of 5-bit characters the 20 address bytes take
+const DATA_LEN: usize = 32;
+
+/// Why a string isn't an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum AddressError {
+    #[error("address must start with {ADDRESS_HRP}1")]
+    Prefix,
+    #[error("address has the wrong length")]
+    Length,
+    #[error("address contains a character addresses can't have")]
+    Character,
+    #[error("address mixes upper and lower case")]
+    MixedCase,
+    #[error("address checksum doesn't match, so it has a typo")]
+    Checksum,
+}
+
+/// An account address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address([u8; 20]);
+
+impl Address {
+    /// The address of an Ed25519 public key
+    pub fn from_public_key(public_key: &[u8; 32]) -> Self {
+        let mut bytes = [0u8; 20];
+        bytes.copy_from_slice(&public_key[..20]);
+        Self(bytes)
+    }
+
+    /// The 20 bytes the address stands for
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// Parse a checksummed address; all upper case is accepted, mixed case isn't
+    pub fn parse(address: &str) -> Result<Self, AddressError> {
+        let lower = address.to_ascii_lowercase();
+        if lower != address && address.to_ascii_uppercase() != address {
+            return Err(AddressError::MixedCase);
+        }
+        let data = lower
+            .strip_prefix(ADDRESS_HRP)
+            .and_then(|rest| rest.strip_prefix('1'))
+            .ok_or(AddressError::Prefix)?;
+        if data.len() != DATA_LEN + CHECKSUM_LEN {
+            return Err(AddressError::Length);
+        }
+
+        let values = data
+            .bytes()
+            .map(|c| CHARSET.iter().position(|&v| v == c).map(|v| v as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(AddressError::Character)?;
+        if polymod(&[hrp_expand(), values.clone()].concat()) != BECH32M_CONST {
+            return Err(AddressError::Checksum);
+        }
+
+        let bytes = from_five_bits(&values[..DATA_LEN]).ok_or(AddressError::Length)?;
+        Ok(Self(bytes.try_into().map_err(|_| AddressError::Length)?))
+    }
+
+    /// Parse a checksummed address, or one written the old `gsio_<hex>` way
+    pub fn parse_any(address: &str) -> Result<Self, AddressError> {
+        let Some(hex) = address.strip_prefix(LEGACY_PREFIX) else {
+            return Self::parse(address);
+        };
+        let valid = hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        let bytes = hex::decode(hex).ok().filter(|_| valid).ok_or(AddressError::Character)?;
+        Ok(Self(bytes.try_into().map_err(|_| AddressError::Length)?))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data = to_five_bits(&self.0);
+        let checksum = polymod(&[hrp_expand(), data.clone(), vec![0; CHECKSUM_LEN]].concat()) ^ BECH32M_CONST;
+        let checksum = (0..CHECKSUM_LEN).map(|i| ((checksum >> (5 * (CHECKSUM_LEN - 1 - i))) & 31) as u8);
+        let encoded: String = data.into_iter().chain(checksum).map(|v| CHARSET[v as usize] as char).collect();
+        write!(f, "{ADDRESS_HRP}1{encoded}")
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::parse(address)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        Self::parse(&address).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The human-readable part as the checksum sees it
+fn hrp_expand() -> Vec<u8> {
+    let hrp = ADDRESS_HRP.as_bytes();
+    hrp.iter().map(|c| c >> 5).chain([0]).chain(hrp.iter().map(|c| c & 31)).collect()
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+    values.iter().fold(1u32, |checksum, &value| {
+        let top = checksum >> 25;
+        let checksum = ((checksum & 0x01ff_ffff) << 5) ^ u32::from(value);
+        (0..5).filter(|i| (top >> i) & 1 == 1).fold(checksum, |checksum, i| checksum ^ GENERATOR[i])
+    })
+}
+
+/// Regroup bytes into 5-bit values, padding the last one with zeros
+fn to_five_bits(bytes: &[u8]) -> Vec<u8> {
+    let (mut acc, mut bits, mut out) = (0u32, 0u32, Vec::new());
+    for &byte in bytes {
+        acc = (acc << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        out.push(((acc << (5 - bits)) & 31) as u8);
+    }
+    out
+}
+
+/// Regroup 5-bit values into bytes, refusing padding that isn't zeros
+fn from_five_bits(values: &[u8]) -> Option<Vec<u8>> {
+    let (mut acc, mut bits, mut out) = (0u32, 0u32, Vec::new());
+    for &value in values {
+        acc = (acc << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push(((acc >> bits) & 0xff) as u8);
+        }
+    }
+    (bits < 5 && (acc << (8 - bits)) & 0xff == 0).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> Address {
+        Address((0..20).collect::<Vec<u8>>().try_into().unwrap())
+    }
+
+    #[test]
+    fn test_addresses_round_trip() {
+        let encoded = address().to_string();
+        assert_eq!(encoded, "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w8");
+        assert_eq!(Address::parse(&encoded), Ok(address()));
+        assert_eq!(Address::parse(&encoded.to_uppercase()), Ok(address()));
+        assert_eq!(serde_json::to_value(address()).unwrap(), serde_json::json!(encoded));
+    }
+
+    #[test]
+    fn test_checksum_matches_bip_350() {
+        // A BIP-350 test vector, checked by the same polymod and constant
+        let values = "a1lqfn3a".bytes().skip(2).map(|c| CHARSET.iter().position(|&v| v == c).unwrap() as u8);
+        let hrp: Vec<u8> = [b'a' >> 5, 0, b'a' & 31].to_vec();
+        assert_eq!(polymod(&hrp.into_iter().chain(values).collect::<Vec<u8>>()), BECH32M_CONST);
+    }
+
+    #[test]
+    fn test_typos_are_caught() {
+        let encoded = address().to_string();
+        for i in 5..encoded.len() {
+            let mut typo = encoded.clone().into_bytes();
+            typo[i] = if typo[i] == b'q' { b'p' } else { b'q' };
+            assert_eq!(Address::parse(std::str::from_utf8(&typo).unwrap()), Err(AddressError::Checksum), "{i}");
+        }
+        let swapped = format!("{}{}{}{}", &encoded[..10], &encoded[11..12], &encoded[10..11], &encoded[12..]);
+        if swapped != encoded {
+            assert_eq!(Address::parse(&swapped), Err(AddressError::Checksum));
+        }
+
+        assert_eq!(Address::parse(&encoded[..42]), Err(AddressError::Length));
+        assert_eq!(Address::parse(&encoded.replace("gsio1", "gsoi1")), Err(AddressError::Prefix));
+        assert_eq!(Address::parse(&format!("{}b", &encoded[..42])), Err(AddressError::Character));
+        assert_eq!(Address::parse(&encoded.replacen("gsio", "GSIO", 1)), Err(AddressError::MixedCase));
+    }
+
+    #[test]
+    fn test_legacy_addresses() {
+        let legacy = format!("gsio_{}", hex::encode(address().as_bytes()));
+        assert_eq!(Address::parse_any(&legacy), Ok(address()));
+        assert_eq!(Address::parse_any(&address().to_string()), Ok(address()));
+        assert_eq!(Address::parse(&legacy), Err(AddressError::Prefix));
+        assert!(Address::parse_any("gsio_0011").is_err());
+        assert!(Address::parse_any(&legacy.to_uppercase().replace("GSIO_", "gsio_")).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Credentials presented to nodes that require clients to authenticate.
+
+use ed25519_dalek::{Signer, SigningKey};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::GsioClientError;
+
+/// How the client proves who it is
+#[derive(Clone)]
+pub(crate) enum Credentials {
+    /// A static key sent with every request
+    ApiKey(String),
+    /// A key used to sign a node's challenge in exchange for a session token
+    Keypair(SigningKey),
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    challenge: String,
+}
+
+#[derive(Deserialize)]
+struct SessionToken {
+    token: String,
+    expires_in: u64,
+}
+
+/// Log in to the node at `node_url` by signing a challenge, returning a
+/// session token and how long it is valid for.
+///
+/// Connection failures are returned as [`GsioClientError::HttpError`] so the
+/// caller can try another node; a refused key is an authentication error.
+pub(crate) async fn login(
+    client: &HttpClient,
+    node_url: &str,
+    key: &SigningKey,
+) -> Result<(String, Duration), GsioClientError> {
+    let response = client.post(format!("{}/api/auth/challenge", node_url)).send().await?;
+    if !response.status().is_success() {
+        let error_text = response.text().await?;
+        return Err(GsioClientError::AuthError(format!("Node refused to issue a challenge: {}", error_text)));
+    }
+    let Challenge { challenge } = response.json().await?;
+
+    let signature = key.sign(challenge.as_bytes());
+    let response = client
+        .post(format!("{}/api/auth/token", node_url))
+        .json(&json!({
+            "public_key": hex::encode(key.verifying_key().to_bytes()),
+            "challenge": challenge,
+            "signature": hex::encode(signature.to_bytes()),
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let error_text = response.text().await?;
+        return Err(GsioClientError::AuthError(format!("Node refused the key: {}", error_text)));
+    }
+    let SessionToken { token, expires_in } = response.json().await?;
+
+    Ok((token, Duration::from_secs(expires_in)))
+}
@@ -0,0 +1,77 @@
+//! Ledger responses cached by the `ETag` nodes send with them.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use gsio_types::LedgerEntry;
+
+/// Distinct ledger requests whose responses are kept, least recently used dropped first
+pub const LEDGER_CACHE_SIZE: usize = 16;
+
+/// Ledger responses kept with the `ETag` the node tagged them with, so a
+/// poll of an unchanged ledger is answered with `304 Not Modified` instead
+/// of the entries again
+#[derive(Debug, Default)]
+pub(crate) struct LedgerCache(Mutex<VecDeque<CachedLedger>>);
+
+#[derive(Debug, Clone)]
+struct CachedLedger {
+    /// Path and query of the request, e.g. `/api/ledger?offset=0&limit=10`
+    request: String,
+    etag: String,
+    entries: Vec<LedgerEntry>,
+}
+
+impl LedgerCache {
+    /// `ETag` of the cached response to `request`, to send in `If-None-Match`
+    pub(crate) fn etag(&self, request: &str) -> Option<String> {
+        let cached = self.0.lock().unwrap();
+        cached.iter().find(|c| c.request == request).map(|c| c.etag.clone())
+    }
+
+    /// Entries of the cached response to `request` if it is still tagged
+    /// `etag`, marking it recently used
+    pub(crate) fn get(&self, request: &str, etag: &str) -> Option<Vec<LedgerEntry>> {
+        let mut cached = self.0.lock().unwrap();
+        let index = cached.iter().position(|c| c.request == request && c.etag == etag)?;
+        let hit = cached.remove(index)?;
+        let entries = hit.entries.clone();
+        cached.push_back(hit);
+        Some(entries)
+    }
+
+    /// Keep the response to `request`, replacing any older one
+    pub(crate) fn insert(&self, request: String, etag: String, entries: Vec<LedgerEntry>) {
+        let mut cached = self.0.lock().unwrap();
+        cached.retain(|c| c.request != request);
+        if cached.len() >= LEDGER_CACHE_SIZE {
+            cached.pop_front();
+        }
+        cached.push_back(CachedLedger { request, etag, entries });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ledger_cache() {
+        let cache = LedgerCache::default();
+        let entry = LedgerEntry::new(json!({ "message": "Test entry" }), "0".to_string(), "test-node-1".to_string());
+        cache.insert("/api/ledger".to_string(), "\"a\"".to_string(), vec![entry.clone()]);
+
+        assert_eq!(cache.etag("/api/ledger").as_deref(), Some("\"a\""));
+        assert_eq!(cache.get("/api/ledger", "\"a\"").unwrap()[0].id, entry.id);
+        assert!(cache.get("/api/ledger", "\"b\"").is_none());
+        assert!(cache.etag("/api/ledger?offset=1").is_none());
+
+        // The least recently used request is dropped once the cache is full
+        for i in 0..LEDGER_CACHE_SIZE {
+            cache.insert(format!("/api/ledger?offset={i}"), "\"a\"".to_string(), Vec::new());
+        }
+        assert!(cache.etag("/api/ledger").is_none());
+        assert!(cache.etag("/api/ledger?offset=0").is_some());
+    }
+}
@@ -4,12 +4,56 @@
 //! It allows connecting to nodes, adding entries to the ledger,
 //! and retrieving ledger data.
 
-use reqwest::{Client as HttpClient, Error as ReqwestError};
+use chrono::{DateTime, Utc};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{
+    Certificate, Client as HttpClient, Error as ReqwestError, Identity, Method, NoProxy, Proxy, RequestBuilder,
+    Response, StatusCode,
+};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+mod address;
+mod auth;
+mod cache;
+mod light;
+mod nodes;
+mod queue;
+mod relay;
+mod retry;
+mod socket;
+mod typed;
+
+pub use address::{Address, AddressError, ADDRESS_HRP};
+use auth::Credentials;
+use cache::LedgerCache;
+pub use cache::LEDGER_CACHE_SIZE;
+pub use ed25519_dalek::SigningKey;
+pub use gsio_types::{
+    merkle_root, verify_proof, EntryHeader, ErrorCode, Filter, FilterOp, LedgerEntry, MerkleProof, ProofStep, Query,
+    QueryPage, Side, StakeInfo, Unbonding, EMPTY_ROOT,
+};
+pub use light::LightClient;
+pub use nodes::NodeHealth;
+pub use relay::relay_token;
+pub use retry::{CircuitBreaker, RetryPolicy};
+use nodes::Node;
+use queue::OfflineQueue;
+pub use queue::QueuedEntry;
+pub use socket::{EntrySubscription, GsioSocketClient};
+pub use typed::{KindSchema, TypedEntry};
+
+/// Header carrying the key under which a node adds a write at most once
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
 
 /// Error type for GSIO client operations
 #[derive(Error, Debug)]
@@ -23,54 +67,672 @@ pub enum GsioClientError {
     #[error("Connection error: {0}")]
     ConnectionError(String),
 
-    #[error("Server error: {0}")]
-    ServerError(String),
+    /// The node refused the request; `code` is its machine-readable reason,
+    /// when it sent one this client knows
+    #[error("Server error: {message}")]
+    ServerError { code: Option<ErrorCode>, message: String },
+
+    #[error("Verification error: {0}")]
+    VerificationError(String),
+
+    #[error("Circuit breaker open for {0}")]
+    CircuitOpen(String),
+
+    #[error("Authentication error: {0}")]
+    AuthError(String),
+
+    /// No node could be reached, so the entry was queued to be sent under
+    /// this idempotency key once one can
+    #[error("No node reachable, entry queued under key {0}")]
+    Queued(String),
+
+    #[error("Offline queue error: {0}")]
+    QueueError(String),
+}
+
+impl GsioClientError {
+    /// An error the node reported without a code
+    pub fn server(message: impl Into<String>) -> Self {
+        GsioClientError::ServerError { code: None, message: message.into() }
+    }
+
+    /// The node's machine-readable reason for refusing the request, if any
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            GsioClientError::ServerError { code, .. } => *code,
+            _ => None,
+        }
+    }
+
+    /// Whether the request failed because no node could be reached, rather than being refused
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            GsioClientError::HttpError(e) => e.is_connect() || e.is_timeout(),
+            GsioClientError::CircuitOpen(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Read a node's `{ "error": ..., "code": ... }` body into a `ServerError`,
+/// keeping the raw text if it isn't one
+fn parse_server_error(body: &str) -> GsioClientError {
+    let Ok(error) = serde_json::from_str::<JsonValue>(body) else {
+        return GsioClientError::server(format!("Server returned error: {body}"));
+    };
+    let message = error.get("error").and_then(|e| e.as_str()).map(str::to_string).unwrap_or_else(|| body.to_string());
+    let code = error.get("code").cloned().and_then(|code| serde_json::from_value(code).ok());
+    GsioClientError::ServerError { code, message }
 }
 
-/// A ledger entry
+/// Error for a response with a failure status
+async fn server_error(response: Response) -> GsioClientError {
+    match response.text().await {
+        Ok(body) => parse_server_error(&body),
+        Err(e) => e.into(),
+    }
+}
+
+/// The first of `names` set to a non-empty value in the environment
+fn env_var(names: &[&str]) -> Option<String> {
+    names.iter().filter_map(|name| std::env::var(name).ok()).find(|value| !value.is_empty())
+}
+
+/// A page of entry headers along with the node's Merkle root over its chain
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LedgerEntry {
-    pub id: String,
-    pub timestamp: String,
-    pub data: JsonValue,
+pub struct LedgerHeaders {
+    /// Number of entries in the chain
+    pub length: usize,
+    /// Merkle root over all `length` entries
+    pub root: String,
+    pub headers: Vec<EntryHeader>,
+}
+
+/// An inclusion proof along with the chain the node took it from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryProof {
+    /// Number of entries in the chain
+    pub length: usize,
+    /// Merkle root the proof leads to
+    pub root: String,
+    pub proof: MerkleProof,
+}
+
+/// A node as the network's peer discovery knows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredPeer {
     pub node_id: String,
-    pub hash: String,
+    /// The node's public URL, which peers dial
+    pub url: String,
+    /// When the node last announced itself
+    pub updated: DateTime<Utc>,
+}
+
+/// The result of introducing a node to another by hand
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinedPeer {
+    /// The node that was joined
+    pub peer: DiscoveredPeer,
+    /// Number of peers the node hadn't known before
+    pub learned: usize,
+}
+
+/// The fees a node asks transactions to pay
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRules {
+    /// Fee every transaction pays
+    pub base: u64,
+    /// Fee per byte of transaction data, serialized as JSON
+    pub per_byte: u64,
+    /// Base fees for particular transaction types, such as `Stake`, instead of `base`
+    #[serde(default)]
+    pub types: BTreeMap<String, u64>,
+}
+
+impl FeeRules {
+    /// The fee for a transaction of `transaction_type` carrying `payload_size` bytes of data
+    pub fn fee(&self, transaction_type: &str, payload_size: usize) -> u64 {
+        let base = self.types.get(transaction_type).copied().unwrap_or(self.base);
+        base.saturating_add(self.per_byte.saturating_mul(payload_size as u64))
+    }
+}
+
+/// What a node takes to sequence a transaction, from `GET /api/fees/floor`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeFloor {
+    /// Fee below which the node refuses transactions
+    pub min_fee: u64,
+    /// Fee per byte that gets ahead of a transaction waiting in a full
+    /// mempool, or 0 while it has room
+    pub fee_per_byte: f64,
+    /// Submissions waiting to be sequenced
+    pub pending: usize,
+    /// Submissions the mempool holds before clients wait for room
+    pub capacity: usize,
+}
+
+/// Builder for a [`GsioClient`] with custom timeouts and retry behaviour
+#[derive(Debug, Clone)]
+pub struct GsioClientBuilder {
+    node_urls: Vec<String>,
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    circuit_breaker: Option<(u32, Duration)>,
+    /// PEM CA certificates trusted on top of the system's
+    root_certificates: Vec<Vec<u8>>,
+    /// PEM client certificate chain and private key
+    client_identity: Option<Vec<u8>>,
+    accept_invalid_certs: bool,
+    /// `http://`, `https://` or `socks5://` URL requests are sent through
+    proxy: Option<String>,
+    /// Username and password for the proxy
+    proxy_auth: Option<(String, String)>,
+    /// Hosts reached directly rather than through the proxy
+    no_proxy: Vec<String>,
+    /// File entries written while no node is reachable are queued in
+    offline_queue: Option<PathBuf>,
+}
+
+impl GsioClientBuilder {
+    /// Start building a client for the node at `node_url`
+    pub fn new(node_url: &str) -> Self {
+        Self::new_multi(&[node_url])
+    }
+
+    /// Start building a client that fails over between several nodes, in order of preference
+    pub fn new_multi(node_urls: &[impl AsRef<str>]) -> Self {
+        Self {
+            node_urls: node_urls.iter().map(|url| url.as_ref().to_string()).collect(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: None,
+            retry: RetryPolicy::default(),
+            circuit_breaker: None,
+            root_certificates: Vec::new(),
+            client_identity: None,
+            accept_invalid_certs: false,
+            proxy: None,
+            proxy_auth: None,
+            no_proxy: Vec::new(),
+            offline_queue: None,
+        }
+    }
+
+    /// Time allowed for each attempt at a request, 30 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time allowed to establish a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Number of times a failed request is retried against each node, 3 by default
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.retry.max_retries = retries;
+        self
+    }
+
+    /// Delay before the first retry and the cap it doubles up to
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.retry.initial_backoff = initial;
+        self.retry.max_backoff = max;
+        self
+    }
+
+    /// Whether to randomize retry delays, on by default
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.retry.jitter = jitter;
+        self
+    }
+
+    /// Replace the whole retry policy
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Stop sending requests to a node for `reset_timeout` after
+    /// `failure_threshold` requests to it in a row fail
+    pub fn circuit_breaker(mut self, failure_threshold: u32, reset_timeout: Duration) -> Self {
+        self.circuit_breaker = Some((failure_threshold, reset_timeout));
+        self
+    }
+
+    /// Trust nodes whose certificate is signed by the CA certificate in
+    /// `pem`, on top of the system's CAs; may be called for each CA
+    pub fn add_root_certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.root_certificates.push(pem.into());
+        self
+    }
+
+    /// Present a client certificate to nodes that require one. `pem` holds
+    /// the certificate chain, leaf first, followed by its private key.
+    pub fn client_certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.client_identity = Some(pem.into());
+        self
+    }
+
+    /// Accept any certificate a node presents, including self-signed and
+    /// expired ones. Anyone in between can then read and alter requests, so
+    /// only use this in development.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Send requests to nodes through the proxy at `url`, which may be
+    /// `http://`, `https://` or `socks5://`. Without one the proxies named by
+    /// `HTTP_PROXY` and `HTTPS_PROXY`, if any, are used, except for the hosts
+    /// in `NO_PROXY`.
+    ///
+    /// Socket.IO connections made with [`GsioSocketClient`] don't go through
+    /// the proxy.
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Log in to the proxy with a username and password
+    pub fn proxy_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.proxy_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Reach these hosts directly rather than through the proxy, whether it
+    /// was given with [`proxy`](Self::proxy) or taken from the environment,
+    /// where they add to `NO_PROXY`. Entries are host names, which also match
+    /// their subdomains, IP addresses or CIDR blocks such as `10.0.0.0/8`.
+    pub fn no_proxy(mut self, hosts: &[impl AsRef<str>]) -> Self {
+        self.no_proxy.extend(hosts.iter().map(|host| host.as_ref().to_string()));
+        self
+    }
+
+    /// Queue entries written while no node can be reached in the file at
+    /// `path` instead of failing, to be sent with
+    /// [`GsioClient::flush_queue`] once a node can be. Entries left in the
+    /// file from an earlier run are sent too.
+    pub fn offline_queue(mut self, path: impl Into<PathBuf>) -> Self {
+        self.offline_queue = Some(path.into());
+        self
+    }
+
+    /// Proxies to set on the HTTP client, each skipping the `no_proxy` hosts.
+    ///
+    /// reqwest reads the environment's proxies itself, but only as long as
+    /// none are set, and then knows nothing of `no_proxy`, so with `no_proxy`
+    /// hosts they're read here instead.
+    fn proxies(&self) -> Result<Vec<Proxy>, GsioClientError> {
+        let invalid = |url: &str, e: ReqwestError| GsioClientError::ConnectionError(format!("Invalid proxy URL {url}: {e}"));
+        let mut no_proxy = self.no_proxy.clone();
+        let proxies = match &self.proxy {
+            Some(url) => vec![Proxy::all(url).map_err(|e| invalid(url, e))?],
+            None if self.no_proxy.is_empty() => return Ok(Vec::new()),
+            None => {
+                no_proxy.extend(env_var(&["NO_PROXY", "no_proxy"]));
+                let mut proxies = Vec::new();
+                if let Some(url) = env_var(&["HTTP_PROXY", "http_proxy"]) {
+                    proxies.push(Proxy::http(&url).map_err(|e| invalid(&url, e))?);
+                }
+                if let Some(url) = env_var(&["HTTPS_PROXY", "https_proxy"]) {
+                    proxies.push(Proxy::https(&url).map_err(|e| invalid(&url, e))?);
+                }
+                proxies
+            }
+        };
+        let no_proxy = no_proxy.join(",");
+        Ok(proxies.into_iter().map(|proxy| proxy.no_proxy(NoProxy::from_string(&no_proxy))).collect())
+    }
+
+    /// Build the client
+    pub fn build(self) -> Result<GsioClient, GsioClientError> {
+        if self.node_urls.is_empty() {
+            return Err(GsioClientError::ConnectionError("No node URLs given".to_string()));
+        }
+
+        let mut builder = HttpClient::builder().timeout(self.timeout);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if !self.root_certificates.is_empty() || self.client_identity.is_some() || self.accept_invalid_certs {
+            // Client certificates are read with rustls
+            builder = builder.use_rustls_tls();
+        }
+        for pem in &self.root_certificates {
+            let certificate = Certificate::from_pem(pem)
+                .map_err(|e| GsioClientError::ConnectionError(format!("Invalid CA certificate: {e}")))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(pem) = &self.client_identity {
+            let identity = Identity::from_pem(pem)
+                .map_err(|e| GsioClientError::ConnectionError(format!("Invalid client certificate: {e}")))?;
+            builder = builder.identity(identity);
+        }
+        if self.accept_invalid_certs {
+            warn!("TLS certificate verification is off");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        for mut proxy in self.proxies()? {
+            if let Some((username, password)) = &self.proxy_auth {
+                proxy = proxy.basic_auth(username, password);
+            }
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))?;
+        let queue = self.offline_queue.map(OfflineQueue::open).transpose()?;
+
+        Ok(GsioClient {
+            client,
+            nodes: self.node_urls.iter().map(|url| Node::new(url, self.circuit_breaker)).collect(),
+            preferred: AtomicUsize::new(0),
+            retry: self.retry,
+            credentials: None,
+            channel: None,
+            cache: LedgerCache::default(),
+            queue,
+        })
+    }
+}
+
+/// Result of sending a request to one node
+struct Attempt {
+    result: Result<Response, ReqwestError>,
+    /// The node failed in a way that makes it safe to send the request elsewhere
+    resendable: bool,
 }
 
 /// GSIO Client for interacting with GSIO nodes
 pub struct GsioClient {
     client: HttpClient,
-    node_url: String,
+    /// Nodes in order of preference
+    nodes: Vec<Node>,
+    /// Node requests go to first; moves to the next node that answers after a failure
+    preferred: AtomicUsize,
+    retry: RetryPolicy,
+    credentials: Option<Credentials>,
+    /// Channel whose ledger requests go to, or `None` for the main ledger
+    channel: Option<String>,
+    /// Ledger responses kept by their `ETag`
+    cache: LedgerCache,
+    /// Entries waiting for a node to be reachable
+    queue: Option<OfflineQueue>,
 }
 
 impl GsioClient {
-    /// Create a new GSIO client
+    /// Create a new GSIO client with the default timeout and retry policy
     pub fn new(node_url: &str) -> Result<Self, GsioClientError> {
-        let client = HttpClient::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))?;
+        GsioClientBuilder::new(node_url).build()
+    }
 
-        Ok(Self {
-            client,
-            node_url: node_url.to_string(),
-        })
+    /// Create a client that fails over between several nodes.
+    ///
+    /// Requests go to one node at a time. When it is unreachable or keeps
+    /// returning server errors, the request is sent to the next node, which
+    /// then handles requests until it fails in turn.
+    pub fn new_multi(node_urls: &[impl AsRef<str>]) -> Result<Self, GsioClientError> {
+        GsioClientBuilder::new_multi(node_urls).build()
+    }
+
+    /// Start building a client with custom timeouts and retry behaviour
+    pub fn builder(node_url: &str) -> GsioClientBuilder {
+        GsioClientBuilder::new(node_url)
+    }
+
+    /// Authenticate to nodes with a static API key
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.credentials = Some(Credentials::ApiKey(api_key.into()));
+        self
+    }
+
+    /// Authenticate to nodes by signing their challenges with `key`.
+    ///
+    /// The client logs in to each node the first time it sends it a request
+    /// and logs in again when the session expires or the node forgets it.
+    pub fn with_keypair(mut self, key: SigningKey) -> Self {
+        self.credentials = Some(Credentials::Keypair(key));
+        self
+    }
+
+    /// Send ledger requests to the channel called `channel` instead of the
+    /// node's main ledger.
+    ///
+    /// Entries, queries, headers, proofs and known nodes are scoped to the
+    /// channel; blobs, schemas, fees and entry subscriptions aren't.
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// URL of `path` under the API of the ledger requests go to on `node`
+    fn ledger_url(&self, node: &str, path: &str) -> String {
+        match &self.channel {
+            Some(channel) => format!("{}/api/channels/{}/{}", node, channel, path),
+            None => format!("{}/api/{}", node, path),
+        }
+    }
+
+    /// URL of the node requests are currently sent to
+    pub fn node_url(&self) -> &str {
+        &self.nodes[self.preferred.load(Ordering::Relaxed)].url
     }
 
-    /// Add an entry to the ledger
+    /// How each node has been responding, in order of preference
+    pub fn node_health(&self) -> Vec<NodeHealth> {
+        self.nodes.iter().map(Node::health).collect()
+    }
+
+    /// Indices of the nodes to try, starting with the preferred one
+    fn node_order(&self) -> impl Iterator<Item = usize> + '_ {
+        let preferred = self.preferred.load(Ordering::Relaxed);
+        (0..self.nodes.len()).map(move |offset| (preferred + offset) % self.nodes.len())
+    }
+
+    /// Send the request built by `build` for a node URL, failing over between
+    /// nodes and retrying each under the retry policy.
+    ///
+    /// Any response that isn't a server error is returned for the caller to
+    /// interpret; a server error is returned once every node has failed.
+    async fn send(&self, build: impl Fn(&HttpClient, &str) -> RequestBuilder) -> Result<Response, GsioClientError> {
+        let mut last = None;
+        for index in self.node_order() {
+            let node = &self.nodes[index];
+            if !node.allow_request() {
+                last = Some(Err(GsioClientError::CircuitOpen(node.url.clone())));
+                continue;
+            }
+
+            let attempt = self.send_to(node, &build).await?;
+            if !attempt.resendable {
+                if attempt.result.as_ref().is_ok_and(|r| !r.status().is_server_error()) {
+                    self.preferred.store(index, Ordering::Relaxed);
+                }
+                let response = attempt.result?;
+                if response.status() == StatusCode::UNAUTHORIZED {
+                    let error_text = response.text().await?;
+                    return Err(GsioClientError::AuthError(error_text));
+                }
+                return Ok(response);
+            }
+            if self.nodes.len() > 1 {
+                warn!("Node {} failed, trying the next node", node.url);
+            }
+            last = Some(attempt.result.map_err(GsioClientError::from));
+        }
+
+        last.expect("a client always has at least one node")
+    }
+
+    /// Send a request to one node, retrying it under the retry policy
+    async fn send_to(
+        &self,
+        node: &Node,
+        build: &impl Fn(&HttpClient, &str) -> RequestBuilder,
+    ) -> Result<Attempt, GsioClientError> {
+        let mut attempt = 0;
+        let mut logged_in_again = false;
+        loop {
+            let (result, idempotent, keyed) = match self.credential(node).await {
+                Ok(credential) => {
+                    let mut request = build(&self.client, &node.url);
+                    if let Some(credential) = credential {
+                        request = request.bearer_auth(credential);
+                    }
+                    let request = request.build()?;
+                    // A write that reached the node may have been applied, so only
+                    // retry it if the connection couldn't be made, unless it
+                    // carries a key the node drops repeats of
+                    let idempotent = request.method() != Method::POST;
+                    let keyed = request.headers().contains_key(IDEMPOTENCY_KEY);
+                    (self.client.execute(request).await, idempotent, keyed)
+                }
+                // Logging in failed, so the request itself was never sent
+                Err(GsioClientError::HttpError(e)) => (Err(e), true, false),
+                Err(e) => return Err(e),
+            };
+
+            // A node that restarted has forgotten the session, so log in once more
+            if result.as_ref().is_ok_and(|r| r.status() == StatusCode::UNAUTHORIZED)
+                && !logged_in_again
+                && node.clear_session_token()
+            {
+                logged_in_again = true;
+                continue;
+            }
+
+            let resendable = match &result {
+                Ok(response) => idempotent && response.status().is_server_error(),
+                Err(e) => e.is_connect() || (idempotent && e.is_timeout()),
+            };
+            // Only the node that saw a keyed write knows its key, so it's
+            // retried there but not sent elsewhere
+            let retryable = resendable
+                || (keyed && result.as_ref().map_or_else(|e| e.is_timeout(), |r| r.status().is_server_error()));
+            if !retryable || attempt >= self.retry.max_retries {
+                match &result {
+                    Ok(response) if !response.status().is_server_error() => node.record_success(),
+                    Ok(response) => node.record_failure(format!("Server returned {}", response.status())),
+                    Err(e) => node.record_failure(e.to_string()),
+                }
+                return Ok(Attempt { result, resendable });
+            }
+
+            let delay = self.retry.backoff(attempt);
+            warn!("Request to {} failed, retrying in {:?}", node.url, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// The credential to present to `node`, logging in to it first if needed
+    async fn credential(&self, node: &Node) -> Result<Option<String>, GsioClientError> {
+        match &self.credentials {
+            None => Ok(None),
+            Some(Credentials::ApiKey(key)) => Ok(Some(key.clone())),
+            Some(Credentials::Keypair(key)) => {
+                if let Some(token) = node.session_token() {
+                    return Ok(Some(token));
+                }
+                let (token, valid_for) = auth::login(&self.client, &node.url, key).await?;
+                node.set_session_token(token.clone(), valid_for);
+                Ok(Some(token))
+            }
+        }
+    }
+
+    /// Add an entry to the ledger.
+    ///
+    /// The request carries a fresh idempotency key, so retrying it after a
+    /// timeout or server error can't add the entry twice.
     pub async fn add_ledger_entry(&self, data: JsonValue) -> Result<LedgerEntry, GsioClientError> {
-        info!("Adding ledger entry: {:?}", data);
+        self.add_ledger_entry_with_key(data, &Uuid::new_v4().to_string()).await
+    }
+
+    /// Add an entry to the ledger under `key`. The node adds it at most once
+    /// per key and answers repeats with the entry it added, so callers can
+    /// resend a write whose outcome they never saw.
+    ///
+    /// With an [offline queue](GsioClientBuilder::offline_queue), an entry
+    /// no node can be reached for is queued instead, and so is every entry
+    /// written while others are still queued, so they reach the node in the
+    /// order they were written. Either fails with
+    /// [`GsioClientError::Queued`].
+    pub async fn add_ledger_entry_with_key(&self, data: JsonValue, key: &str) -> Result<LedgerEntry, GsioClientError> {
+        let Some(queue) = &self.queue else {
+            return self.submit_entry(&data, key).await;
+        };
+        if queue.is_empty() {
+            match self.submit_entry(&data, key).await {
+                Err(e) if e.is_unreachable() => warn!("Queueing entry {}: {}", key, e),
+                result => return result,
+            }
+        }
+        queue.push(key, data)?;
+        Err(GsioClientError::Queued(key.to_string()))
+    }
+
+    /// Entries waiting in the offline queue, oldest first
+    pub fn queued_entries(&self) -> Vec<QueuedEntry> {
+        self.queue.as_ref().map(OfflineQueue::entries).unwrap_or_default()
+    }
+
+    /// Send the entries in the offline queue, oldest first, under the keys
+    /// they were queued with, returning how many the node added.
+    ///
+    /// Stops at the first entry no node can be reached for, leaving it and
+    /// those after it queued. An entry the node refuses would be refused
+    /// again, so it's dropped from the queue with a warning.
+    pub async fn flush_queue(&self) -> Result<usize, GsioClientError> {
+        let Some(queue) = &self.queue else {
+            return Ok(0);
+        };
+        let mut added = 0;
+        while let Some(entry) = queue.front() {
+            match self.submit_entry(&entry.data, &entry.key).await {
+                Ok(_) => added += 1,
+                Err(e) if e.is_unreachable() => return Err(e),
+                Err(e) => warn!("Dropping queued entry {}: {}", entry.key, e),
+            }
+            queue.remove(&entry.key)?;
+        }
+        if added > 0 {
+            info!("Sent {} queued entries", added);
+        }
+        Ok(added)
+    }
 
-        let url = format!("{}/api/ledger", self.node_url);
+    /// Flush the offline queue every `interval` in the background, so queued
+    /// entries are sent soon after a node can be reached again
+    pub fn spawn_queue_flusher(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let client = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if client.queue.as_ref().is_none_or(OfflineQueue::is_empty) {
+                    continue;
+                }
+                if let Err(e) = client.flush_queue().await {
+                    warn!("Couldn't send queued entries: {}", e);
+                }
+            }
+        })
+    }
 
-        let response = self.client.post(&url)
-            .json(&data)
-            .send()
+    /// Send an entry to the node under `key`
+    async fn submit_entry(&self, data: &JsonValue, key: &str) -> Result<LedgerEntry, GsioClientError> {
+        info!("Adding ledger entry: {:?}", data);
+
+        let response = self.send(|client, node| client.post(self.ledger_url(node, "ledger"))
+            .header(IDEMPOTENCY_KEY, key)
+            .json(data))
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(GsioClientError::ServerError(format!("Server returned error: {}", error_text)));
+            return Err(server_error(response).await);
         }
 
         let entry: LedgerEntry = response.json().await?;
@@ -78,50 +740,403 @@ impl GsioClient {
         Ok(entry)
     }
 
+    /// Add an entry tagged with `kind` whose payload is `payload`.
+    ///
+    /// The node refuses it if a schema is registered for the kind and the
+    /// payload doesn't match it.
+    pub async fn add_typed_entry<T: Serialize + DeserializeOwned>(
+        &self,
+        kind: &str,
+        payload: &T,
+    ) -> Result<TypedEntry<T>, GsioClientError> {
+        let entry = self.add_ledger_entry(TypedEntry::data(kind, payload)?).await?;
+        TypedEntry::from_entry(entry)
+    }
+
+    /// Get every entry of `kind` in the ledger.
+    ///
+    /// Fails if any entry of the kind has a payload that isn't a `T`, which
+    /// can only happen if the node has no schema registered for the kind.
+    pub async fn get_typed_entries<T: Serialize + DeserializeOwned>(
+        &self,
+        kind: &str,
+    ) -> Result<Vec<TypedEntry<T>>, GsioClientError> {
+        self.get_ledger()
+            .await?
+            .into_iter()
+            .filter(|entry| TypedEntry::<T>::kind_of(entry) == Some(kind))
+            .map(TypedEntry::from_entry)
+            .collect()
+    }
+
+    /// Register an entry kind with the node, along with the JSON Schema its payloads must match if given.
+    ///
+    /// Registering a kind again with the same schema succeeds; the node refuses a different one.
+    pub async fn register_schema(&self, kind: &str, schema: Option<JsonValue>) -> Result<KindSchema, GsioClientError> {
+        info!("Registering schema for {}", kind);
+
+        let body = serde_json::json!({ "schema": schema });
+        let response = self.send(|client, node| client.put(format!("{}/api/schemas/{}", node, kind))
+            .json(&body))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let registered: KindSchema = response.json().await?;
+
+        Ok(registered)
+    }
+
+    /// Get the schema registered for `kind`, or `None` if the kind isn't registered
+    pub async fn get_schema(&self, kind: &str) -> Result<Option<KindSchema>, GsioClientError> {
+        info!("Getting schema for {}", kind);
+
+        let response = self.send(|client, node| client.get(format!("{}/api/schemas/{}", node, kind)))
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let schema: KindSchema = response.json().await?;
+
+        Ok(Some(schema))
+    }
+
     /// Get all entries in the ledger
     pub async fn get_ledger(&self) -> Result<Vec<LedgerEntry>, GsioClientError> {
         info!("Getting ledger entries");
 
-        let url = format!("{}/api/ledger", self.node_url);
+        self.get_entries(&[]).await
+    }
+
+    /// Get up to `limit` entries starting at position `offset` in the chain
+    pub async fn get_ledger_paginated(&self, offset: usize, limit: usize) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries {} to {}", offset, offset.saturating_add(limit));
+
+        self.get_entries(&[("offset", offset.to_string()), ("limit", limit.to_string())]).await
+    }
+
+    /// Get up to `limit` entries following the one with `hash` in the chain,
+    /// or `None` if the node's chain doesn't hold it, as after a reorg
+    pub async fn get_ledger_after(&self, hash: &str, limit: usize) -> Result<Option<Vec<LedgerEntry>>, GsioClientError> {
+        info!("Getting up to {} ledger entries after {}", limit, hash);
+
+        match self.get_entries(&[("after", hash.to_string()), ("limit", limit.to_string())]).await {
+            Ok(entries) => Ok(Some(entries)),
+            Err(e) if e.code() == Some(ErrorCode::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get all entries created strictly after `since`
+    pub async fn get_entries_since(&self, since: DateTime<Utc>) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries since {}", since);
+
+        self.get_entries(&[("since", since.to_rfc3339())]).await
+    }
+
+    /// Get the entries created by the node `creator`, oldest first
+    pub async fn find_by_creator(&self, creator: &str) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries created by {}", creator);
+
+        self.get_entries(&[("creator", creator.to_string())]).await
+    }
+
+    /// Get a page of the entries matching `query`, built with [`Query::new`]
+    /// and its filters. Ask for the next page with the returned `next_offset`.
+    pub async fn query_entries(&self, query: Query) -> Result<QueryPage, GsioClientError> {
+        info!("Querying ledger entries: {:?}", query);
+
+        let response = self.send(|client, node| client.post(self.ledger_url(node, "ledger/query"))
+            .json(&query))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let mut page: QueryPage = response.json().await?;
+        page.entries = self.rehydrate(page.entries).await?;
 
-        let response = self.client.get(&url)
-            .send()
+        Ok(page)
+    }
+
+    /// Get a single entry by its ID, or `None` if the node doesn't have it
+    pub async fn get_entry_by_id(&self, id: &str) -> Result<Option<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entry {}", id);
+
+        let response = self.send(|client, node| client.get(self.ledger_url(node, &format!("ledger/{}", id))))
             .await?;
 
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(GsioClientError::ServerError(format!("Server returned error: {}", error_text)));
+            return Err(server_error(response).await);
         }
 
-        let entries: Vec<LedgerEntry> = response.json().await?;
+        let entry: LedgerEntry = response.json().await?;
+        let mut entries = self.rehydrate(vec![entry]).await?;
+
+        Ok(entries.pop())
+    }
 
+    /// Get the data stored in a blob by an entry the node offloaded, or
+    /// `None` if the node can't find it; the data is checked against `hash`
+    pub async fn get_blob(&self, hash: &str) -> Result<Option<JsonValue>, GsioClientError> {
+        info!("Getting blob {}", hash);
+
+        let response = self.send(|client, node| client.get(format!("{}/api/blobs/{}", node, hash)))
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let bytes = response.bytes().await?;
+        if blake3::hash(&bytes).to_hex().as_str() != hash {
+            return Err(GsioClientError::VerificationError(format!("Blob {hash} doesn't match its hash")));
+        }
+
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    /// Get entries from `/ledger` with `query`. A response the node tagged
+    /// with an `ETag` is cached, and the tag sent back with the next request
+    /// for it, so an unchanged ledger is answered with `304 Not Modified`
+    /// instead of being downloaded again.
+    async fn get_entries(&self, query: &[(&str, String)]) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        let request = query
+            .iter()
+            .fold(self.ledger_url("", "ledger"), |request, (name, value)| format!("{request}&{name}={value}"));
+        let mut etag = self.cache.etag(&request);
+        loop {
+            let response = self.send(|client, node| {
+                let builder = client.get(self.ledger_url(node, "ledger")).query(query);
+                match &etag {
+                    Some(etag) => builder.header(IF_NONE_MATCH, etag),
+                    None => builder,
+                }
+            })
+            .await?;
+
+            if response.status() == StatusCode::NOT_MODIFIED {
+                if let Some(entries) = etag.as_deref().and_then(|etag| self.cache.get(&request, etag)) {
+                    return Ok(entries);
+                }
+                // Dropped from the cache in the meantime, so ask for the entries
+                etag = None;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(server_error(response).await);
+            }
+
+            let tag = response.headers().get(ETAG).and_then(|tag| tag.to_str().ok()).map(str::to_string);
+            let entries: Vec<LedgerEntry> = response.json().await?;
+            let entries = self.rehydrate(entries).await?;
+            if let Some(tag) = tag {
+                self.cache.insert(request, tag, entries.clone());
+            }
+            return Ok(entries);
+        }
+    }
+
+    /// Fetch the data of entries the node returned with their blob reference
+    /// in place; entries whose blob the node can't find are left as they are
+    async fn rehydrate(&self, mut entries: Vec<LedgerEntry>) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        for entry in &mut entries {
+            let Some(hash) = entry.pending_blob().map(str::to_string) else { continue };
+            if let Some(data) = self.get_blob(&hash).await? {
+                entry.blob = Some(std::mem::replace(&mut entry.data, data));
+            }
+        }
         Ok(entries)
     }
 
+    /// Get up to `limit` entry headers starting at position `offset` in the chain
+    pub async fn get_headers(&self, offset: usize, limit: usize) -> Result<LedgerHeaders, GsioClientError> {
+        info!("Getting ledger headers {} to {}", offset, offset.saturating_add(limit));
+
+        let response = self.send(|client, node| client.get(self.ledger_url(node, "ledger/headers"))
+            .query(&[("offset", offset), ("limit", limit)]))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let headers: LedgerHeaders = response.json().await?;
+
+        Ok(headers)
+    }
+
+    /// Get an inclusion proof for an entry, or `None` if the node doesn't have it
+    pub async fn get_entry_proof(&self, id: &str) -> Result<Option<EntryProof>, GsioClientError> {
+        info!("Getting inclusion proof for {}", id);
+
+        let response = self.send(|client, node| client.get(self.ledger_url(node, &format!("ledger/{}/proof", id))))
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let proof: EntryProof = response.json().await?;
+
+        Ok(Some(proof))
+    }
+
+    /// Subscribe to entries as the node appends them to its chain.
+    ///
+    /// Opens a Socket.IO connection to the node; entries appended before the
+    /// subscription is confirmed aren't delivered, so fetch those with
+    /// `get_entries_since` if needed.
+    pub async fn subscribe_entries(&self) -> Result<EntrySubscription, GsioClientError> {
+        self.subscribe_entries_matching(serde_json::Map::new()).await
+    }
+
+    /// Subscribe to new entries whose data has each of `fields` with the
+    /// given value, like `{ "kind": "sensor" }`.
+    ///
+    /// The node drops other entries before sending them, so this saves
+    /// bandwidth on busy ledgers compared to filtering the stream locally.
+    pub async fn subscribe_entries_matching(
+        &self,
+        fields: serde_json::Map<String, JsonValue>,
+    ) -> Result<EntrySubscription, GsioClientError> {
+        info!("Subscribing to ledger entries");
+        let filter = JsonValue::Object(fields);
+
+        let mut last = None;
+        for index in self.node_order() {
+            let node = &self.nodes[index];
+            let subscription = match self.credential(node).await {
+                Ok(token) => EntrySubscription::connect(&node.url, token.as_deref(), &filter, Duration::from_secs(30)).await,
+                Err(e) => Err(e),
+            };
+            match subscription {
+                Ok(subscription) => {
+                    node.record_success();
+                    self.preferred.store(index, Ordering::Relaxed);
+                    return Ok(subscription);
+                }
+                Err(e) => {
+                    node.record_failure(e.to_string());
+                    last = Some(e);
+                }
+            }
+        }
+        Err(last.expect("a client always has at least one node"))
+    }
+
     /// Get all known nodes in the network
     pub async fn get_known_nodes(&self) -> Result<Vec<String>, GsioClientError> {
         info!("Getting known nodes");
 
-        let url = format!("{}/api/nodes", self.node_url);
-
-        let response = self.client.get(&url)
-            .send()
+        let response = self.send(|client, node| client.get(self.ledger_url(node, "nodes")))
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(GsioClientError::ServerError(format!("Server returned error: {}", error_text)));
+            return Err(server_error(response).await);
         }
 
         let data: JsonValue = response.json().await?;
 
         let nodes = data.get("nodes")
-            .ok_or_else(|| GsioClientError::ServerError("Invalid response format".to_string()))?;
+            .ok_or_else(|| GsioClientError::server("Invalid response format"))?;
 
         let nodes: Vec<String> = serde_json::from_value(nodes.clone())?;
 
         Ok(nodes)
     }
+
+    /// Get the fees the node asks transactions to pay
+    pub async fn get_fee_rules(&self) -> Result<FeeRules, GsioClientError> {
+        info!("Getting fee rules");
+
+        let response = self.send(|client, node| client.get(format!("{}/api/fees", node)))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let rules: FeeRules = response.json().await?;
+
+        Ok(rules)
+    }
+
+    /// Get the minimum fee the node takes and how busy its mempool is
+    pub async fn get_fee_floor(&self) -> Result<FeeFloor, GsioClientError> {
+        info!("Getting fee floor");
+
+        let response = self.send(|client, node| client.get(format!("{}/api/fees/floor", node)))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let floor: FeeFloor = response.json().await?;
+
+        Ok(floor)
+    }
+
+    /// Get the balance and stake the node counts for `address`
+    pub async fn get_stake(&self, address: &str) -> Result<StakeInfo, GsioClientError> {
+        info!("Getting stake of {}", address);
+
+        let response = self.send(|client, node| client.get(format!("{}/api/staking/{}", node, address)))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let stake: StakeInfo = response.json().await?;
+
+        Ok(stake)
+    }
+
+    /// Introduce the node to another node by hand, given a blob ticket the
+    /// other node served or its iroh node ID.
+    ///
+    /// The node swaps peer tables with the other node and starts dialing it.
+    /// This is an admin route, so the client needs the node's admin key as
+    /// its API key.
+    pub async fn join_peer(&self, ticket: &str) -> Result<JoinedPeer, GsioClientError> {
+        info!("Joining peer {}", ticket);
+
+        let body = serde_json::json!({ "ticket": ticket });
+        let response = self.send(|client, node| client.post(format!("{}/admin/peers/join", node))
+            .json(&body))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(server_error(response).await);
+        }
+
+        let joined: JoinedPeer = response.json().await?;
+
+        Ok(joined)
+    }
 }
 
 #[cfg(test)]
@@ -131,7 +1146,54 @@ mod tests {
     #[test]
     fn test_client_creation() {
         let client = GsioClient::new("http://localhost:3000").unwrap();
-        assert_eq!(client.node_url, "http://localhost:3000");
+        assert_eq!(client.node_url(), "http://localhost:3000");
+    }
+
+    #[test]
+    fn test_proxy() {
+        let client = GsioClient::builder("http://localhost:3000")
+            .proxy("socks5://proxy.example.com:1080")
+            .proxy_auth("user", "secret")
+            .no_proxy(&["localhost", "10.0.0.0/8"])
+            .build();
+        assert!(client.is_ok());
+
+        let error = GsioClient::builder("http://localhost:3000").proxy("http://[::1").build().err().unwrap();
+        assert!(matches!(error, GsioClientError::ConnectionError(message) if message.contains("Invalid proxy URL")));
+    }
+
+    #[test]
+    fn test_server_error_keeps_code() {
+        let error = parse_server_error(r#"{"error": "Entry abc not found", "code": "not_found"}"#);
+        assert_eq!(error.code(), Some(ErrorCode::NotFound));
+        assert_eq!(error.to_string(), "Server error: Entry abc not found");
+
+        // Codes from newer nodes, and bodies that aren't JSON, keep the message
+        let error = parse_server_error(r#"{"error": "Slow down", "code": "some_new_code"}"#);
+        assert!(matches!(&error, GsioClientError::ServerError { code: None, message } if message == "Slow down"));
+        let error = parse_server_error("Bad Gateway");
+        assert!(matches!(&error, GsioClientError::ServerError { code: None, message } if message.contains("Bad Gateway")));
+    }
+
+    #[test]
+    fn test_fee_rules() {
+        let rules: FeeRules = serde_json::from_str(r#"{"base": 1, "per_byte": 2, "types": {"Stake": 10}}"#).unwrap();
+        assert_eq!(rules.fee("Transfer", 0), 1);
+        assert_eq!(rules.fee("Transfer", 5), 11);
+        assert_eq!(rules.fee("Stake", 5), 20);
+        assert_eq!(rules.fee("Stake", usize::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_channel_urls() {
+        let client = GsioClient::new("http://localhost:3000").unwrap();
+        assert_eq!(client.ledger_url("http://localhost:3000", "ledger"), "http://localhost:3000/api/ledger");
+        let client = client.with_channel("payments");
+        assert_eq!(
+            client.ledger_url("http://localhost:3000", "ledger/query"),
+            "http://localhost:3000/api/channels/payments/ledger/query"
+        );
+        assert_eq!(client.ledger_url("http://localhost:3000", "nodes"), "http://localhost:3000/api/channels/payments/nodes");
     }
 
     // More tests would be added here in a real implementation
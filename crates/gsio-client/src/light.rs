@@ -0,0 +1,164 @@
+//! Light client that tracks the chain through entry headers and checks the
+//! entries it fetches against Merkle proofs, instead of downloading and
+//! replaying the whole ledger.
+
+use tracing::{info, warn};
+
+use crate::{merkle_root, verify_proof, EntryHeader, EntryProof, GsioClient, GsioClientError, LedgerEntry};
+
+/// Number of headers requested per page while syncing
+const HEADER_PAGE_SIZE: usize = 1000;
+
+/// Client that holds only entry headers and verifies entries on demand
+pub struct LightClient {
+    client: GsioClient,
+    /// Headers of the chain, oldest first
+    headers: Vec<EntryHeader>,
+    /// Merkle root computed locally over `headers`
+    root: String,
+}
+
+impl LightClient {
+    /// Create a light client for the node at `node_url`
+    pub fn new(node_url: &str) -> Result<Self, GsioClientError> {
+        Ok(Self::with_client(GsioClient::new(node_url)?))
+    }
+
+    /// Create a light client that talks to the node through `client`
+    pub fn with_client(client: GsioClient) -> Self {
+        Self {
+            client,
+            headers: Vec::new(),
+            root: merkle_root([]),
+        }
+    }
+
+    /// Headers synced so far, oldest first
+    pub fn headers(&self) -> &[EntryHeader] {
+        &self.headers
+    }
+
+    /// Merkle root over the synced headers
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Fetch headers the client doesn't have yet and return how many were added.
+    ///
+    /// If the node's chain no longer extends the headers already held, e.g.
+    /// after a reorg or pruning, the headers are fetched again from the start.
+    pub async fn sync_headers(&mut self) -> Result<usize, GsioClientError> {
+        let headers = match self.fetch_headers(self.headers.clone()).await {
+            Err(GsioClientError::VerificationError(e)) if !self.headers.is_empty() => {
+                warn!("Refetching headers: {}", e);
+                self.fetch_headers(Vec::new()).await?
+            }
+            result => result?,
+        };
+
+        let added = headers.len().saturating_sub(self.headers.len());
+        self.root = merkle_root(headers.iter().map(|h| h.hash.as_str()));
+        self.headers = headers;
+        info!("Synced {} headers, {} in total", added, self.headers.len());
+
+        Ok(added)
+    }
+
+    /// Fetch an entry and verify it belongs to the chain, or `None` if the
+    /// node doesn't have it.
+    ///
+    /// The entry's contents must match its hash, and its inclusion proof must
+    /// lead to the root computed from the synced headers. Headers are synced
+    /// first if the proof refers to a longer chain than the client holds.
+    pub async fn get_verified_entry(&mut self, id: &str) -> Result<Option<LedgerEntry>, GsioClientError> {
+        let Some(proof) = self.client.get_entry_proof(id).await? else {
+            return Ok(None);
+        };
+
+        if let Err(e) = self.check_proof(&proof) {
+            // The client may be behind the node, or holding a stale chain
+            info!("Syncing headers before verifying {}: {}", id, e);
+            self.sync_headers().await?;
+            self.check_proof(&proof)?;
+        }
+
+        let Some(entry) = self.client.get_entry_by_id(id).await? else {
+            return Ok(None);
+        };
+        if entry.id != proof.proof.entry_id || entry.hash != proof.proof.entry_hash {
+            return Err(GsioClientError::VerificationError(format!("Entry {} doesn't match its proof", id)));
+        }
+        if !entry.is_valid() {
+            return Err(GsioClientError::VerificationError(format!("Entry {} doesn't match its hash", id)));
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Page through headers from the end of `headers`, checking that each one
+    /// links to the one before it and that the result matches the node's root
+    async fn fetch_headers(&self, mut headers: Vec<EntryHeader>) -> Result<Vec<EntryHeader>, GsioClientError> {
+        loop {
+            let page = self.client.get_headers(headers.len(), HEADER_PAGE_SIZE).await?;
+            if page.length < headers.len() {
+                return Err(GsioClientError::VerificationError(format!(
+                    "Node holds {} entries but {} headers are known",
+                    page.length,
+                    headers.len()
+                )));
+            }
+
+            for header in page.headers {
+                if let Some(previous) = headers.last()
+                    && header.previous_hash != previous.hash
+                {
+                    return Err(GsioClientError::VerificationError(format!(
+                        "Header {} doesn't link to {}",
+                        header.id, previous.id
+                    )));
+                }
+                headers.push(header);
+            }
+
+            if headers.len() >= page.length {
+                let root = merkle_root(headers.iter().map(|h| h.hash.as_str()));
+                if root != page.root {
+                    return Err(GsioClientError::VerificationError(format!(
+                        "Headers lead to root {} but the node reported {}",
+                        root, page.root
+                    )));
+                }
+                return Ok(headers);
+            }
+        }
+    }
+
+    /// Check a proof against the synced headers
+    fn check_proof(&self, proof: &EntryProof) -> Result<(), GsioClientError> {
+        let Some(headers) = self.headers.get(..proof.length) else {
+            return Err(GsioClientError::VerificationError(format!(
+                "Proof covers {} entries but {} headers are known",
+                proof.length,
+                self.headers.len()
+            )));
+        };
+
+        let header = headers.get(proof.proof.index);
+        if header.is_none_or(|h| h.id != proof.proof.entry_id || h.hash != proof.proof.entry_hash) {
+            return Err(GsioClientError::VerificationError(format!(
+                "Entry {} isn't at position {} of the chain",
+                proof.proof.entry_id, proof.proof.index
+            )));
+        }
+
+        let root = merkle_root(headers.iter().map(|h| h.hash.as_str()));
+        if !verify_proof(&proof.proof, &root) {
+            return Err(GsioClientError::VerificationError(format!(
+                "Proof for {} doesn't lead to root {}",
+                proof.proof.entry_id, root
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! Health of the nodes a client can send requests to.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::retry::CircuitBreaker;
+
+/// How a node has been responding to this client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeHealth {
+    /// URL of the node
+    pub url: String,
+    /// Requests in a row that failed with a connection or server error
+    pub consecutive_failures: u32,
+    /// The most recent failure, cleared when a request succeeds
+    pub last_error: Option<String>,
+    /// When a request to the node last succeeded
+    pub last_success: Option<Instant>,
+    /// Whether the node's circuit breaker is refusing requests
+    pub circuit_open: bool,
+}
+
+impl NodeHealth {
+    /// Whether the last request to the node succeeded, or none has been sent yet
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+/// A node the client can send requests to
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub(crate) url: String,
+    breaker: Option<CircuitBreaker>,
+    health: Mutex<NodeHealth>,
+    /// Session token from logging in with a keypair, and when it expires
+    session: Mutex<Option<(String, Instant)>>,
+}
+
+impl Node {
+    pub(crate) fn new(url: &str, circuit_breaker: Option<(u32, Duration)>) -> Self {
+        let url = url.trim_end_matches('/').to_string();
+        Self {
+            breaker: circuit_breaker.map(|(threshold, reset_timeout)| CircuitBreaker::new(threshold, reset_timeout)),
+            health: Mutex::new(NodeHealth {
+                url: url.clone(),
+                consecutive_failures: 0,
+                last_error: None,
+                last_success: None,
+                circuit_open: false,
+            }),
+            session: Mutex::new(None),
+            url,
+        }
+    }
+
+    /// Whether a request may be sent to the node now
+    pub(crate) fn allow_request(&self) -> bool {
+        self.breaker.as_ref().is_none_or(CircuitBreaker::allow_request)
+    }
+
+    pub(crate) fn record_success(&self) {
+        if let Some(breaker) = &self.breaker {
+            breaker.record_success();
+        }
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures = 0;
+        health.last_error = None;
+        health.last_success = Some(Instant::now());
+    }
+
+    pub(crate) fn record_failure(&self, error: String) {
+        if let Some(breaker) = &self.breaker {
+            breaker.record_failure();
+        }
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        health.last_error = Some(error);
+    }
+
+    /// The session token for the node, if one is held and hasn't expired
+    pub(crate) fn session_token(&self) -> Option<String> {
+        let session = self.session.lock().unwrap();
+        session
+            .as_ref()
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(token, _)| token.clone())
+    }
+
+    pub(crate) fn set_session_token(&self, token: String, valid_for: Duration) {
+        *self.session.lock().unwrap() = Some((token, Instant::now() + valid_for));
+    }
+
+    /// Forget the session token, returning whether one was held
+    pub(crate) fn clear_session_token(&self) -> bool {
+        self.session.lock().unwrap().take().is_some()
+    }
+
+    pub(crate) fn health(&self) -> NodeHealth {
+        let mut health = self.health.lock().unwrap().clone();
+        health.circuit_open = self.breaker.as_ref().is_some_and(CircuitBreaker::is_open);
+        health
+    }
+}
@@ -0,0 +1,134 @@
+//! Entries written while no node could be reached, kept on disk until they're sent.
+//!
+//! The queue is a file of JSON lines, one [`QueuedEntry`] each, oldest
+//! first. It's rewritten whole on every change, through a temporary file
+//! renamed over it, so a client that crashes midway finds either the old
+//! queue or the new one when it starts again.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::GsioClientError;
+
+/// An entry waiting to be sent to a node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedEntry {
+    /// Idempotency key the entry is sent under, so sending it again can't add it twice
+    pub key: String,
+    pub data: JsonValue,
+    /// When the entry was queued
+    pub queued_at: DateTime<Utc>,
+}
+
+/// Entries waiting to be sent, in the order they were written, backed by a file
+#[derive(Debug)]
+pub(crate) struct OfflineQueue {
+    path: PathBuf,
+    entries: Mutex<VecDeque<QueuedEntry>>,
+}
+
+impl OfflineQueue {
+    /// Open the queue kept at `path`, starting an empty one if the file doesn't exist
+    pub(crate) fn open(path: impl Into<PathBuf>) -> Result<Self, GsioClientError> {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(queue_error(&path, e)),
+        };
+        Ok(Self { path, entries: Mutex::new(entries) })
+    }
+
+    /// Every queued entry, oldest first
+    pub(crate) fn entries(&self) -> Vec<QueuedEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// The oldest queued entry
+    pub(crate) fn front(&self) -> Option<QueuedEntry> {
+        self.entries.lock().unwrap().front().cloned()
+    }
+
+    /// Whether nothing is waiting to be sent
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Queue `data` to be sent under `key`
+    pub(crate) fn push(&self, key: &str, data: JsonValue) -> Result<(), GsioClientError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(QueuedEntry { key: key.to_string(), data, queued_at: Utc::now() });
+        self.save(&entries).inspect_err(|_| {
+            entries.pop_back();
+        })
+    }
+
+    /// Drop the entry queued under `key` once it's been sent
+    pub(crate) fn remove(&self, key: &str) -> Result<(), GsioClientError> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(index) = entries.iter().position(|entry| entry.key == key) else {
+            return Ok(());
+        };
+        let removed = entries.remove(index);
+        self.save(&entries).inspect_err(|_| {
+            if let Some(entry) = removed {
+                entries.insert(index, entry);
+            }
+        })
+    }
+
+    /// Write `entries` to the file in place of what it held
+    fn save(&self, entries: &VecDeque<QueuedEntry>) -> Result<(), GsioClientError> {
+        let mut contents = String::new();
+        for entry in entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, contents).map_err(|e| queue_error(&temporary, e))?;
+        fs::rename(&temporary, &self.path).map_err(|e| queue_error(&self.path, e))
+    }
+}
+
+fn queue_error(path: &Path, error: std::io::Error) -> GsioClientError {
+    GsioClientError::QueueError(format!("{}: {error}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_offline_queue() {
+        let path = std::env::temp_dir().join(format!("gsio-queue-{}.jsonl", uuid::Uuid::new_v4()));
+        let queue = OfflineQueue::open(&path).unwrap();
+        assert!(queue.is_empty());
+        queue.push("key-1", json!({ "message": "Test entry 1" })).unwrap();
+        queue.push("key-2", json!({ "message": "Test entry 2" })).unwrap();
+        queue.push("key-3", json!({ "message": "Test entry 3" })).unwrap();
+        queue.remove("key-2").unwrap();
+        queue.remove("key-4").unwrap();
+
+        // The queue outlives the client that wrote it
+        let reopened = OfflineQueue::open(&path).unwrap();
+        let keys: Vec<String> = reopened.entries().into_iter().map(|entry| entry.key).collect();
+        assert_eq!(keys, vec!["key-1".to_string(), "key-3".to_string()]);
+        assert_eq!(reopened.front().unwrap().data, json!({ "message": "Test entry 1" }));
+
+        fs::write(&path, "not json\n").unwrap();
+        assert!(OfflineQueue::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
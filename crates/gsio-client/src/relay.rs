@@ -0,0 +1,31 @@
+//! Access tokens for gsio-relay channels.
+//!
+//! Whoever created a relay channel holds its secret, and issues each node a
+//! token that lets it join the channel under its own node ID. Mirrors the
+//! relay's check: a token is `<node_id>.<expires>.<signature>`, signed with
+//! HMAC-SHA256 over `<channel>\n<node_id>\n<expires>`.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Issue a token that lets `node_id` join relay `channel` until the Unix time
+/// `expires`, in seconds
+pub fn relay_token(secret: &str, channel: &str, node_id: &str, expires: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{channel}\n{node_id}\n{expires}").as_bytes());
+    format!("{node_id}.{expires}.{}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_token_matches_gsio_relay() {
+        // gsio-relay's token tests accept this same token
+        assert_eq!(
+            relay_token("correct horse battery staple", "mesh", "node-a", 1_700_000_000),
+            "node-a.1700000000.d75d6c0f2ff0ca076d5d9d613d15a176e70cd3a6c25ee1bc205644c06c01533b"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Retry and circuit breaker settings for the HTTP client.
+
+use rand::Rng;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How failed requests are retried.
+///
+/// Reads are retried on connection errors, timeouts and 5xx responses.
+/// Writes are only retried when the connection couldn't be made, since the
+/// node may otherwise have applied the write already, unless they carry an
+/// idempotency key; those are also retried on timeouts and 5xx responses,
+/// but only against the node they were first sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; `0` disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each one after
+    pub initial_backoff: Duration,
+    /// Longest delay between retries
+    pub max_backoff: Duration,
+    /// Randomize each delay between zero and its full length
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt`, counting from zero
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        if self.jitter && !delay.is_zero() {
+            delay.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Stops sending requests to a node that keeps failing.
+///
+/// After `failure_threshold` requests in a row fail, the breaker opens and
+/// requests fail straight away for `reset_timeout`. Then one request is let
+/// through: if it succeeds the breaker closes, otherwise it opens again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            reset_timeout,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether a request may be sent now
+    pub fn allow_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.reset_timeout => false,
+            Some(_) => {
+                // Let one trial request through; its outcome decides the state
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Whether requests are currently being refused
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.opened_at.is_some_and(|opened_at| opened_at.elapsed() < self.reset_timeout)
+    }
+
+    /// Record a request that succeeded
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        state.opened_at = None;
+    }
+
+    /// Record a request that failed, opening the breaker at the threshold
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+
+        let jittered = RetryPolicy::default();
+        for attempt in 0..8 {
+            assert!(jittered.backoff(attempt) <= policy.backoff(attempt));
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        breaker.record_failure();
+        assert!(breaker.allow_request());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.allow_request());
+
+        // After the timeout a single trial request goes through
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        assert!(!breaker.allow_request());
+
+        // A failed trial reopens the breaker, a successful one closes it
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow_request());
+    }
+}
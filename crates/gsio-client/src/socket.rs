@@ -0,0 +1,379 @@
+//! Socket.IO transport for talking to a GSIO node over the root `/` namespace.
+
+use chrono::{DateTime, Utc};
+use futures::{FutureExt, Stream};
+use rust_socketio::{
+    asynchronous::{Client as SocketClient, ClientBuilder},
+    Event, Payload,
+};
+use serde_json::{json, Value as JsonValue};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::{GsioClientError, LedgerEntry};
+
+/// Events the node emits in response to client requests
+const RESPONSE_EVENTS: [&str; 4] = ["ledger_entry_added", "ledger_entries", "ledger_entry", "known_nodes"];
+
+/// Request waiting for the node to answer with `event`
+struct PendingRequest {
+    event: &'static str,
+    tx: oneshot::Sender<Result<JsonValue, GsioClientError>>,
+}
+
+type Pending = Arc<Mutex<Option<PendingRequest>>>;
+
+/// GSIO client that talks to a node over Socket.IO instead of HTTP
+pub struct GsioSocketClient {
+    socket: SocketClient,
+    pending: Pending,
+    /// Serializes requests so each response is matched to the request that caused it
+    request_lock: tokio::sync::Mutex<()>,
+    timeout: Duration,
+}
+
+impl GsioSocketClient {
+    /// Connect to the root namespace of a node
+    pub async fn connect(node_url: &str) -> Result<Self, GsioClientError> {
+        Self::connect_with(node_url, None).await
+    }
+
+    /// Connect to a node that requires authentication, presenting an API key
+    /// or a session token
+    pub async fn connect_with_token(node_url: &str, token: &str) -> Result<Self, GsioClientError> {
+        Self::connect_with(node_url, Some(token)).await
+    }
+
+    async fn connect_with(node_url: &str, token: Option<&str>) -> Result<Self, GsioClientError> {
+        let pending: Pending = Arc::new(Mutex::new(None));
+        let (ready, ready_rx) = ready_channel();
+
+        let mut builder = socket_builder(node_url, token);
+        for event in RESPONSE_EVENTS {
+            let pending = pending.clone();
+            builder = builder.on(event, move |payload, _| {
+                resolve(&pending, event, Ok(first_value(payload)));
+                async {}.boxed()
+            });
+        }
+
+        let error_pending = pending.clone();
+        let error_ready = ready.clone();
+        builder = builder.on("error", move |payload, _| {
+            let error = first_value(payload);
+            let message = error_message(&error);
+            // An error before the greeting means the node refused the connection
+            signal_ready(&error_ready, Err(message.clone()));
+            let code = error.get("code").cloned().and_then(|code| serde_json::from_value(code).ok());
+            resolve_any(&error_pending, Err(GsioClientError::ServerError { code, message }));
+            async {}.boxed()
+        });
+
+        // The node greets each client with "auth" once its handlers are registered
+        builder = builder.on("auth", move |_, _| {
+            signal_ready(&ready, Ok(()));
+            async {}.boxed()
+        });
+
+        let socket = builder
+            .connect()
+            .await
+            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))?;
+
+        let timeout = Duration::from_secs(30);
+        wait_ready(&socket, ready_rx, timeout).await?;
+
+        Ok(Self {
+            socket,
+            pending,
+            request_lock: tokio::sync::Mutex::new(()),
+            timeout,
+        })
+    }
+
+    /// Set how long to wait for the node to respond to a request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Add an entry to the ledger
+    pub async fn add_ledger_entry(&self, data: JsonValue) -> Result<LedgerEntry, GsioClientError> {
+        info!("Adding ledger entry over Socket.IO: {:?}", data);
+
+        let entry = self.request("add_ledger_entry", data, "ledger_entry_added").await?;
+        Ok(serde_json::from_value(entry)?)
+    }
+
+    /// Get all entries in the ledger
+    pub async fn get_ledger(&self) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries over Socket.IO");
+
+        let entries = self.request("get_ledger", json!({}), "ledger_entries").await?;
+        Ok(serde_json::from_value(entries)?)
+    }
+
+    /// Get up to `limit` entries starting at position `offset` in the chain
+    pub async fn get_ledger_paginated(&self, offset: usize, limit: usize) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries {} to {} over Socket.IO", offset, offset.saturating_add(limit));
+
+        let request = json!({ "offset": offset, "limit": limit });
+        let entries = self.request("get_ledger_page", request, "ledger_entries").await?;
+        Ok(serde_json::from_value(entries)?)
+    }
+
+    /// Get all entries created strictly after `since`
+    pub async fn get_entries_since(&self, since: DateTime<Utc>) -> Result<Vec<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entries since {} over Socket.IO", since);
+
+        let request = json!({ "since": since });
+        let entries = self.request("get_entries_since", request, "ledger_entries").await?;
+        Ok(serde_json::from_value(entries)?)
+    }
+
+    /// Get a single entry by its ID, or `None` if the node doesn't have it
+    pub async fn get_entry_by_id(&self, id: &str) -> Result<Option<LedgerEntry>, GsioClientError> {
+        info!("Getting ledger entry {} over Socket.IO", id);
+
+        let entry = self.request("get_entry", json!({ "id": id }), "ledger_entry").await?;
+        Ok(serde_json::from_value(entry)?)
+    }
+
+    /// Get all known nodes in the network
+    pub async fn get_known_nodes(&self) -> Result<Vec<String>, GsioClientError> {
+        info!("Getting known nodes over Socket.IO");
+
+        let data = self.request("get_known_nodes", json!({}), "known_nodes").await?;
+        let nodes = data.get("nodes")
+            .ok_or_else(|| GsioClientError::server("Invalid response format"))?;
+
+        Ok(serde_json::from_value(nodes.clone())?)
+    }
+
+    /// Close the connection to the node
+    pub async fn disconnect(&self) -> Result<(), GsioClientError> {
+        self.socket
+            .disconnect()
+            .await
+            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))
+    }
+
+    /// Emit `event` and wait for the node to answer with `response_event`
+    async fn request(
+        &self,
+        event: &str,
+        data: JsonValue,
+        response_event: &'static str,
+    ) -> Result<JsonValue, GsioClientError> {
+        let _guard = self.request_lock.lock().await;
+
+        // Register before emitting so a fast response can't be missed
+        let (tx, rx) = oneshot::channel();
+        *self.pending.lock().unwrap() = Some(PendingRequest { event: response_event, tx });
+
+        if let Err(e) = self.socket.emit(event, data).await {
+            self.pending.lock().unwrap().take();
+            return Err(GsioClientError::ConnectionError(e.to_string()));
+        }
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(GsioClientError::ConnectionError("Connection closed".to_string())),
+            Err(_) => {
+                self.pending.lock().unwrap().take();
+                Err(GsioClientError::ConnectionError(format!(
+                    "Timed out waiting for {}",
+                    response_event
+                )))
+            }
+        }
+    }
+}
+
+/// Stream of entries a node pushes as they are appended to its chain.
+///
+/// The stream ends when the node closes the connection. Dropping it
+/// disconnects from the node.
+pub struct EntrySubscription {
+    socket: SocketClient,
+    entries: mpsc::UnboundedReceiver<LedgerEntry>,
+}
+
+impl EntrySubscription {
+    /// Connect to a node and subscribe to new entries matching `filter`,
+    /// presenting `token` if the node requires authentication
+    pub(crate) async fn connect(
+        node_url: &str,
+        token: Option<&str>,
+        filter: &JsonValue,
+        timeout: Duration,
+    ) -> Result<Self, GsioClientError> {
+        let (entry_tx, entries) = mpsc::unbounded_channel();
+        // Dropped when the connection closes, which ends the stream
+        let entry_tx = Arc::new(Mutex::new(Some(entry_tx)));
+        let (ready, ready_rx) = ready_channel();
+        let error_ready = ready.clone();
+        let (subscribed_tx, subscribed_rx) = oneshot::channel();
+        let subscribed_tx = Mutex::new(Some(subscribed_tx));
+
+        let appended_tx = entry_tx.clone();
+        let socket = socket_builder(node_url, token)
+            .on("auth", move |_, _| {
+                signal_ready(&ready, Ok(()));
+                async {}.boxed()
+            })
+            .on("error", move |payload, _| {
+                signal_ready(&error_ready, Err(error_message(&first_value(payload))));
+                async {}.boxed()
+            })
+            .on("entries_subscribed", move |_, _| {
+                if let Some(tx) = subscribed_tx.lock().unwrap().take() {
+                    tx.send(()).ok();
+                }
+                async {}.boxed()
+            })
+            .on("entry_appended", move |payload, _| {
+                match serde_json::from_value::<LedgerEntry>(first_value(payload)) {
+                    Ok(entry) => {
+                        if let Some(tx) = appended_tx.lock().unwrap().as_ref() {
+                            tx.send(entry).ok();
+                        }
+                    }
+                    Err(e) => warn!("Ignoring malformed entry: {}", e),
+                }
+                async {}.boxed()
+            })
+            .on(Event::Close, move |_, _| {
+                entry_tx.lock().unwrap().take();
+                async {}.boxed()
+            })
+            .connect()
+            .await
+            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))?;
+
+        // Handlers are only registered on the node once it sends "auth"
+        wait_ready(&socket, ready_rx, timeout).await?;
+
+        if let Err(e) = socket.emit("subscribe_entries", filter.clone()).await {
+            socket.disconnect().await.ok();
+            return Err(GsioClientError::ConnectionError(e.to_string()));
+        }
+        if !matches!(tokio::time::timeout(timeout, subscribed_rx).await, Ok(Ok(()))) {
+            socket.disconnect().await.ok();
+            return Err(GsioClientError::ConnectionError(
+                "Node did not confirm the subscription".to_string(),
+            ));
+        }
+
+        info!("Subscribed to new entries from {}", node_url);
+        Ok(Self { socket, entries })
+    }
+
+    /// Close the connection to the node
+    pub async fn close(self) -> Result<(), GsioClientError> {
+        self.socket
+            .disconnect()
+            .await
+            .map_err(|e| GsioClientError::ConnectionError(e.to_string()))
+    }
+}
+
+impl Stream for EntrySubscription {
+    type Item = LedgerEntry;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.entries.poll_recv(cx)
+    }
+}
+
+impl Drop for EntrySubscription {
+    fn drop(&mut self) {
+        // Disconnecting is async, so leave it to the runtime if there is one
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let socket = self.socket.clone();
+            runtime.spawn(async move {
+                socket.disconnect().await.ok();
+            });
+        }
+    }
+}
+
+/// Signalled once the node greets the client, or refuses the connection
+type Ready = Arc<Mutex<Option<oneshot::Sender<Result<(), String>>>>>;
+
+fn ready_channel() -> (Ready, oneshot::Receiver<Result<(), String>>) {
+    let (tx, rx) = oneshot::channel();
+    (Arc::new(Mutex::new(Some(tx))), rx)
+}
+
+/// Report the outcome of connecting, unless it has been reported already
+fn signal_ready(ready: &Ready, result: Result<(), String>) {
+    if let Some(tx) = ready.lock().unwrap().take() {
+        tx.send(result).ok();
+    }
+}
+
+/// Wait for the node to greet the client, disconnecting if it doesn't
+async fn wait_ready(
+    socket: &SocketClient,
+    ready: oneshot::Receiver<Result<(), String>>,
+    timeout: Duration,
+) -> Result<(), GsioClientError> {
+    let error = match tokio::time::timeout(timeout, ready).await {
+        Ok(Ok(Ok(()))) => return Ok(()),
+        Ok(Ok(Err(message))) => GsioClientError::AuthError(message),
+        _ => GsioClientError::ConnectionError("Node did not acknowledge the connection".to_string()),
+    };
+    socket.disconnect().await.ok();
+    Err(error)
+}
+
+/// Builder for a connection to the root namespace, authenticating with `token` if given
+fn socket_builder(node_url: &str, token: Option<&str>) -> ClientBuilder {
+    let builder = ClientBuilder::new(node_url).namespace("/");
+    match token {
+        Some(token) => builder.auth(json!({ "token": token })),
+        None => builder,
+    }
+}
+
+/// Complete the pending request if it is waiting for `event`
+fn resolve(pending: &Pending, event: &str, result: Result<JsonValue, GsioClientError>) {
+    let mut pending = pending.lock().unwrap();
+    if pending.as_ref().is_some_and(|p| p.event == event) {
+        resolve_any_locked(&mut pending, result);
+    }
+}
+
+/// Complete the pending request, whatever it is waiting for
+fn resolve_any(pending: &Pending, result: Result<JsonValue, GsioClientError>) {
+    resolve_any_locked(&mut pending.lock().unwrap(), result);
+}
+
+fn resolve_any_locked(pending: &mut Option<PendingRequest>, result: Result<JsonValue, GsioClientError>) {
+    if let Some(request) = pending.take() {
+        request.tx.send(result).ok();
+    }
+}
+
+/// Message of an error the node emitted or a refused connection
+fn error_message(error: &JsonValue) -> String {
+    error
+        .get("error")
+        .and_then(|e| e.as_str())
+        .or(error.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| error.to_string())
+}
+
+/// The node emits a single JSON value per event
+fn first_value(payload: Payload) -> JsonValue {
+    match payload {
+        Payload::Text(mut values) if !values.is_empty() => values.swap_remove(0),
+        _ => JsonValue::Null,
+    }
+}
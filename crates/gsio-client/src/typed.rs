@@ -0,0 +1,108 @@
+//! Ledger entries whose data is a payload of a known type.
+//!
+//! Typed entries carry their data as `{"kind": "<kind>", "payload": ...}`.
+//! Nodes check the payload against the JSON Schema registered for the kind,
+//! if any; the client checks it deserializes into `T`.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{GsioClientError, LedgerEntry};
+
+/// A ledger entry whose payload deserializes into `T`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedEntry<T> {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub previous_hash: String,
+    pub creator_node_id: String,
+    pub hash: String,
+    /// Kind the entry was tagged with
+    pub kind: String,
+    pub payload: T,
+}
+
+impl<T: Serialize + DeserializeOwned> TypedEntry<T> {
+    /// Entry data tagging `payload` with `kind`
+    pub fn data(kind: &str, payload: &T) -> Result<JsonValue, GsioClientError> {
+        Ok(json!({ "kind": kind, "payload": serde_json::to_value(payload)? }))
+    }
+
+    /// The kind of an entry, if its data is tagged with one
+    pub fn kind_of(entry: &LedgerEntry) -> Option<&str> {
+        entry.data.get("kind").and_then(JsonValue::as_str)
+    }
+
+    /// Read a typed entry, failing if it has no kind or its payload isn't a `T`
+    pub fn from_entry(entry: LedgerEntry) -> Result<Self, GsioClientError> {
+        let kind = Self::kind_of(&entry)
+            .ok_or_else(|| GsioClientError::VerificationError(format!("Entry {} has no kind", entry.id)))?
+            .to_string();
+        let payload = serde_json::from_value(entry.data.get("payload").cloned().unwrap_or(JsonValue::Null))?;
+
+        Ok(Self {
+            id: entry.id,
+            timestamp: entry.timestamp,
+            previous_hash: entry.previous_hash,
+            creator_node_id: entry.creator_node_id,
+            hash: entry.hash,
+            kind,
+            payload,
+        })
+    }
+}
+
+/// A kind registered with a node, with the schema its payloads must match if any
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindSchema {
+    pub kind: String,
+    pub schema: Option<JsonValue>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct ChatMessage {
+        author: String,
+        text: String,
+    }
+
+    fn entry(data: JsonValue) -> LedgerEntry {
+        LedgerEntry {
+            id: "entry-1".to_string(),
+            ..LedgerEntry::new(data, "0".repeat(64), "test-node-1".to_string())
+        }
+    }
+
+    #[test]
+    fn test_typed_entry_round_trip() {
+        let message = ChatMessage { author: "alice".to_string(), text: "hello".to_string() };
+        let data = TypedEntry::data("chat_message", &message).unwrap();
+        assert_eq!(data, json!({ "kind": "chat_message", "payload": { "author": "alice", "text": "hello" } }));
+
+        let typed = TypedEntry::<ChatMessage>::from_entry(entry(data)).unwrap();
+        assert_eq!(typed.kind, "chat_message");
+        assert_eq!(typed.payload, message);
+        assert_eq!(typed.id, "entry-1");
+    }
+
+    #[test]
+    fn test_malformed_entries_are_refused() {
+        let untyped = entry(json!({ "message": "hello" }));
+        assert!(TypedEntry::<ChatMessage>::kind_of(&untyped).is_none());
+        assert!(matches!(
+            TypedEntry::<ChatMessage>::from_entry(untyped),
+            Err(GsioClientError::VerificationError(_))
+        ));
+
+        let wrong_payload = entry(json!({ "kind": "chat_message", "payload": { "author": 1 } }));
+        assert!(matches!(
+            TypedEntry::<ChatMessage>::from_entry(wrong_payload),
+            Err(GsioClientError::SerializationError(_))
+        ));
+    }
+}
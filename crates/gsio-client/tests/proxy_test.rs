@@ -0,0 +1,72 @@
+//! Runs in its own process, as it sets the proxy environment variables.
+
+use std::sync::{Arc, Mutex};
+
+use gsio_client::GsioClient;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A server answering every request with an empty ledger, recording the
+/// request targets it was sent: absolute URLs when it's used as a proxy
+async fn start_server() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let targets = Arc::new(Mutex::new(Vec::new()));
+
+    let seen = targets.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let target = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+            seen.lock().unwrap().push(target);
+            let headers = "content-type: application/json\r\ncontent-length: 2\r\nconnection: close";
+            stream.write_all(format!("HTTP/1.1 200 OK\r\n{headers}\r\n\r\n[]").as_bytes()).await.ok();
+        }
+    });
+    (url, targets)
+}
+
+#[tokio::test]
+async fn test_no_proxy_bypasses_proxies() {
+    let (node, direct) = start_server().await;
+    let (proxy, proxied) = start_server().await;
+    let (env_proxy, env_proxied) = start_server().await;
+    // Set before any client is built, as reqwest reads them once
+    unsafe {
+        std::env::set_var("HTTP_PROXY", &env_proxy);
+        for name in ["http_proxy", "HTTPS_PROXY", "https_proxy", "NO_PROXY", "no_proxy"] {
+            std::env::remove_var(name);
+        }
+    }
+    let remote = "http://node.example:3000";
+
+    // With a proxy given, the hosts in no_proxy are reached directly
+    for url in [remote, node.as_str()] {
+        let client = GsioClient::builder(url).proxy(&proxy).no_proxy(&["127.0.0.1"]).build().unwrap();
+        client.get_ledger().await.unwrap();
+    }
+    assert_eq!(*proxied.lock().unwrap(), vec![format!("{remote}/api/ledger")]);
+    assert_eq!(*direct.lock().unwrap(), vec!["/api/ledger".to_string()]);
+
+    // The proxy from the environment is used for every host without no_proxy
+    GsioClient::new(&node).unwrap().get_ledger().await.unwrap();
+    assert_eq!(*env_proxied.lock().unwrap(), vec![format!("{node}/api/ledger")]);
+
+    // And skipped for the hosts in it
+    for url in [remote, node.as_str()] {
+        let client = GsioClient::builder(url).no_proxy(&["127.0.0.1"]).build().unwrap();
+        client.get_ledger().await.unwrap();
+    }
+    assert_eq!(env_proxied.lock().unwrap().last().unwrap(), &format!("{remote}/api/ledger"));
+    assert_eq!(env_proxied.lock().unwrap().len(), 2);
+    assert_eq!(direct.lock().unwrap().len(), 2);
+}
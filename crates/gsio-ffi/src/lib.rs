@@ -0,0 +1,198 @@
+//! GSIO Language Bindings
+//!
+//! This library exposes `gsio-client` to services written in other languages,
+//! so they can add ledger entries and follow new ones without reimplementing
+//! the node protocol. The `python` feature builds a Python extension module
+//! with pyo3 and the `node` feature a Node.js addon with napi-rs. Both wrap
+//! the [`Client`] and [`Subscription`] defined here, which pass entries
+//! around as JSON values.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use gsio_client::{EntrySubscription, GsioClient, GsioClientError, Query};
+use serde_json::{Map, Value as JsonValue};
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "python")]
+mod python;
+
+/// Error type for calls made through the bindings
+#[derive(Error, Debug)]
+pub enum FfiError {
+    #[error(transparent)]
+    ClientError(#[from] GsioClientError),
+
+    #[error("JSON serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+}
+
+/// Client for a node, or for several nodes it fails over between
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<GsioClient>,
+}
+
+impl Client {
+    /// Create a client for `node_urls`, tried in order, authenticating with
+    /// `api_key` and sending ledger requests to `channel` if they are given
+    pub fn new(node_urls: &[String], api_key: Option<String>, channel: Option<String>) -> Result<Self, FfiError> {
+        if node_urls.is_empty() {
+            return Err(FfiError::InvalidInput("at least one node URL is required".to_string()));
+        }
+        let mut client = GsioClient::new_multi(node_urls)?;
+        if let Some(api_key) = api_key {
+            client = client.with_api_key(api_key);
+        }
+        if let Some(channel) = channel {
+            client = client.with_channel(channel);
+        }
+        Ok(Self { inner: Arc::new(client) })
+    }
+
+    /// URL of the node requests are currently sent to
+    pub fn node_url(&self) -> String {
+        self.inner.node_url().to_string()
+    }
+
+    /// Add an entry holding `data`, returning the entry the node created
+    pub async fn add_entry(&self, data: JsonValue) -> Result<JsonValue, FfiError> {
+        let entry = self.inner.add_ledger_entry(data).await?;
+        Ok(serde_json::to_value(entry)?)
+    }
+
+    /// Get every entry in the ledger, oldest first
+    pub async fn get_ledger(&self) -> Result<JsonValue, FfiError> {
+        let entries = self.inner.get_ledger().await?;
+        Ok(serde_json::to_value(entries)?)
+    }
+
+    /// Get the entry with `id`, or `null` if the node doesn't know it
+    pub async fn get_entry(&self, id: &str) -> Result<JsonValue, FfiError> {
+        let entry = self.inner.get_entry_by_id(id).await?;
+        Ok(serde_json::to_value(entry)?)
+    }
+
+    /// Run a query shaped like the body of `POST /api/query`, returning a
+    /// page of matching entries
+    pub async fn query(&self, query: JsonValue) -> Result<JsonValue, FfiError> {
+        let query: Query = serde_json::from_value(query)?;
+        let page = self.inner.query_entries(query).await?;
+        Ok(serde_json::to_value(page)?)
+    }
+
+    /// Get all known nodes in the network
+    pub async fn get_known_nodes(&self) -> Result<Vec<String>, FfiError> {
+        Ok(self.inner.get_known_nodes().await?)
+    }
+
+    /// Subscribe to new entries, only those whose data has each of the
+    /// fields in `filter` with the given value if one is given
+    pub async fn subscribe(&self, filter: Option<JsonValue>) -> Result<Subscription, FfiError> {
+        let fields = match filter {
+            None | Some(JsonValue::Null) => Map::new(),
+            Some(JsonValue::Object(fields)) => fields,
+            Some(_) => return Err(FfiError::InvalidInput("subscription filter must be an object".to_string())),
+        };
+        let entries = self.inner.subscribe_entries_matching(fields).await?;
+        Ok(Subscription { entries: Arc::new(Mutex::new(Some(entries))) })
+    }
+}
+
+/// Entries a node pushes as they are appended to its chain
+#[derive(Clone)]
+pub struct Subscription {
+    /// `None` once the subscription is closed
+    entries: Arc<Mutex<Option<EntrySubscription>>>,
+}
+
+impl Subscription {
+    /// Wait for the next entry, or `None` once the subscription has ended
+    pub async fn next(&self) -> Result<Option<JsonValue>, FfiError> {
+        let mut entries = self.entries.lock().await;
+        let Some(subscription) = entries.as_mut() else { return Ok(None) };
+        match subscription.next().await {
+            Some(entry) => Ok(Some(serde_json::to_value(entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Disconnect from the node; later calls to [`Subscription::next`] return `None`
+    pub async fn close(&self) -> Result<(), FfiError> {
+        if let Some(subscription) = self.entries.lock().await.take() {
+            subscription.close().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gsio_node::api;
+    use gsio_node::ledger::SharedLedger;
+    use gsio_node::p2p::P2PManager;
+    use gsio_node::socket;
+    use serde_json::json;
+    use socketioxide::SocketIo;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    async fn start_node(p2p: Arc<P2PManager>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (layer, io) = SocketIo::builder().build_layer();
+        socket::register_root_namespace(&io, p2p.clone(), None);
+        tokio::spawn(async move {
+            axum::serve(listener, api::router(p2p).layer(layer)).await.unwrap();
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_entries_round_trip_as_json() {
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id.clone())));
+        let client = Client::new(&[start_node(p2p).await], None, None).unwrap();
+
+        let entry = client.add_entry(json!({ "kind": "sensor", "reading": 21.5 })).await.unwrap();
+        assert_eq!(entry["data"]["reading"], 21.5);
+        assert_eq!(entry["creator_node_id"], node_id);
+
+        let ledger = client.get_ledger().await.unwrap();
+        assert_eq!(ledger.as_array().unwrap().len(), 1);
+        assert_eq!(client.get_entry(entry["id"].as_str().unwrap()).await.unwrap()["hash"], entry["hash"]);
+        assert_eq!(client.get_entry("missing").await.unwrap(), JsonValue::Null);
+
+        let page = client.query(json!({ "filters": [{ "path": "$.kind", "op": "eq", "value": "sensor" }] })).await.unwrap();
+        assert_eq!(page["entries"][0]["id"], entry["id"]);
+        assert!(matches!(client.query(json!({ "unknown": 1 })).await, Err(FfiError::SerializationError(_))));
+
+        assert_eq!(client.get_known_nodes().await.unwrap(), vec![node_id]);
+        assert!(matches!(Client::new(&[], None, None), Err(FfiError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_subscription() {
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+        let client = Client::new(&[start_node(p2p.clone()).await], None, None).unwrap();
+
+        assert!(matches!(client.subscribe(Some(json!(["sensor"]))).await, Err(FfiError::InvalidInput(_))));
+        let subscription = client.subscribe(Some(json!({ "kind": "sensor" }))).await.unwrap();
+
+        p2p.ledger.add_entry(json!({ "kind": "transfer" })).unwrap();
+        let entry = p2p.ledger.add_entry(json!({ "kind": "sensor" })).unwrap();
+        let next = tokio::time::timeout(Duration::from_secs(5), subscription.next()).await.unwrap().unwrap();
+        assert_eq!(next.unwrap()["id"], entry.id);
+
+        subscription.close().await.unwrap();
+        assert_eq!(subscription.next().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! The Node.js addon
+//!
+//! Every call returns a promise, resolved on napi-rs's Tokio runtime, and
+//! entries are passed to and from JavaScript as plain objects.
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::Value as JsonValue;
+
+use crate::{Client, FfiError, Subscription};
+
+impl From<FfiError> for napi::Error {
+    fn from(e: FfiError) -> Self {
+        let status = match e {
+            FfiError::SerializationError(_) | FfiError::InvalidInput(_) => Status::InvalidArg,
+            FfiError::ClientError(_) => Status::GenericFailure,
+        };
+        napi::Error::new(status, e.to_string())
+    }
+}
+
+#[napi(object)]
+pub struct ClientOptions {
+    pub api_key: Option<String>,
+    pub channel: Option<String>,
+}
+
+/// Client for a GSIO node, or for several nodes it fails over between
+#[napi(js_name = "Client")]
+pub struct JsClient {
+    inner: Client,
+}
+
+#[napi]
+impl JsClient {
+    #[napi(constructor)]
+    pub fn new(nodes: Vec<String>, options: Option<ClientOptions>) -> Result<Self> {
+        let (api_key, channel) = options.map(|o| (o.api_key, o.channel)).unwrap_or_default();
+        Ok(Self { inner: Client::new(&nodes, api_key, channel)? })
+    }
+
+    #[napi(getter)]
+    pub fn node_url(&self) -> String {
+        self.inner.node_url()
+    }
+
+    #[napi]
+    pub async fn add_entry(&self, data: JsonValue) -> Result<JsonValue> {
+        Ok(self.inner.add_entry(data).await?)
+    }
+
+    #[napi]
+    pub async fn get_ledger(&self) -> Result<JsonValue> {
+        Ok(self.inner.get_ledger().await?)
+    }
+
+    #[napi]
+    pub async fn get_entry(&self, id: String) -> Result<JsonValue> {
+        Ok(self.inner.get_entry(&id).await?)
+    }
+
+    #[napi]
+    pub async fn query(&self, query: JsonValue) -> Result<JsonValue> {
+        Ok(self.inner.query(query).await?)
+    }
+
+    #[napi]
+    pub async fn get_known_nodes(&self) -> Result<Vec<String>> {
+        Ok(self.inner.get_known_nodes().await?)
+    }
+
+    #[napi]
+    pub async fn subscribe(&self, filter: Option<JsonValue>) -> Result<JsSubscription> {
+        Ok(JsSubscription { inner: self.inner.subscribe(filter).await? })
+    }
+}
+
+/// Entries a node pushes as they are appended to its chain; `next()`
+/// resolves to `null` once the subscription has ended
+#[napi(js_name = "Subscription")]
+pub struct JsSubscription {
+    inner: Subscription,
+}
+
+#[napi]
+impl JsSubscription {
+    #[napi]
+    pub async fn next(&self) -> Result<Option<JsonValue>> {
+        Ok(self.inner.next().await?)
+    }
+
+    #[napi]
+    pub async fn close(&self) -> Result<()> {
+        Ok(self.inner.close().await?)
+    }
+}
@@ -0,0 +1,149 @@
+//! The `gsio` Python extension module
+//!
+//! Calls block the calling thread, with the GIL released, while a shared
+//! Tokio runtime drives the client. Entries are passed to and from Python as
+//! the objects `json.loads` produces.
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use gsio_client::GsioClientError;
+use pyo3::exceptions::{PyConnectionError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use serde_json::Value as JsonValue;
+use tokio::runtime::Runtime;
+
+use crate::{Client, FfiError, Subscription};
+
+impl From<FfiError> for PyErr {
+    fn from(e: FfiError) -> Self {
+        match e {
+            FfiError::ClientError(GsioClientError::ConnectionError(_) | GsioClientError::HttpError(_)) => {
+                PyConnectionError::new_err(e.to_string())
+            }
+            FfiError::SerializationError(_) | FfiError::InvalidInput(_) => PyValueError::new_err(e.to_string()),
+            FfiError::ClientError(_) => PyRuntimeError::new_err(e.to_string()),
+        }
+    }
+}
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("failed to start the Tokio runtime"))
+}
+
+/// Run `future` to completion without holding the GIL
+fn block_on<F: Future + Send>(py: Python<'_>, future: F) -> F::Output
+where
+    F::Output: Send,
+{
+    py.allow_threads(|| runtime().block_on(future))
+}
+
+fn to_json(py: Python<'_>, value: &Bound<'_, PyAny>) -> PyResult<JsonValue> {
+    let text: String = py.import("json")?.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&text).map_err(|e| PyErr::from(FfiError::from(e)))
+}
+
+fn to_py(py: Python<'_>, value: &JsonValue) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+/// Client for a GSIO node, or for several nodes it fails over between
+#[pyclass(name = "Client", module = "gsio")]
+struct PyClient {
+    inner: Client,
+}
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    #[pyo3(signature = (nodes, api_key = None, channel = None))]
+    fn new(nodes: Vec<String>, api_key: Option<String>, channel: Option<String>) -> PyResult<Self> {
+        Ok(Self { inner: Client::new(&nodes, api_key, channel)? })
+    }
+
+    #[getter]
+    fn node_url(&self) -> String {
+        self.inner.node_url()
+    }
+
+    fn add_entry(&self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let data = to_json(py, data)?;
+        let entry = block_on(py, self.inner.add_entry(data))?;
+        to_py(py, &entry)
+    }
+
+    fn get_ledger(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let entries = block_on(py, self.inner.get_ledger())?;
+        to_py(py, &entries)
+    }
+
+    fn get_entry(&self, py: Python<'_>, id: &str) -> PyResult<PyObject> {
+        let entry = block_on(py, self.inner.get_entry(id))?;
+        to_py(py, &entry)
+    }
+
+    fn query(&self, py: Python<'_>, query: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let query = to_json(py, query)?;
+        let page = block_on(py, self.inner.query(query))?;
+        to_py(py, &page)
+    }
+
+    fn get_known_nodes(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        Ok(block_on(py, self.inner.get_known_nodes())?)
+    }
+
+    #[pyo3(signature = (filter = None))]
+    fn subscribe(&self, py: Python<'_>, filter: Option<&Bound<'_, PyAny>>) -> PyResult<PySubscription> {
+        let filter = filter.map(|filter| to_json(py, filter)).transpose()?;
+        let inner = block_on(py, self.inner.subscribe(filter))?;
+        Ok(PySubscription { inner: Some(inner) })
+    }
+}
+
+/// Iterator over entries a node pushes as they are appended to its chain
+#[pyclass(name = "Subscription", module = "gsio")]
+struct PySubscription {
+    /// Only taken when the object is dropped
+    inner: Option<Subscription>,
+}
+
+impl PySubscription {
+    fn inner(&self) -> &Subscription {
+        self.inner.as_ref().expect("subscription is only taken on drop")
+    }
+}
+
+#[pymethods]
+impl PySubscription {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        match block_on(py, self.inner().next())? {
+            Some(entry) => Ok(Some(to_py(py, &entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        Ok(block_on(py, self.inner().close())?)
+    }
+}
+
+impl Drop for PySubscription {
+    fn drop(&mut self) {
+        // The connection is closed by a task spawned on drop, which needs a runtime
+        let _guard = runtime().enter();
+        self.inner.take();
+    }
+}
+
+#[pymodule]
+fn gsio(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyClient>()?;
+    m.add_class::<PySubscription>()?;
+    Ok(())
+}
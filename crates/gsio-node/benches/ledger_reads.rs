@@ -0,0 +1,88 @@
+//! Read-heavy workload against `SharedLedger`: several reader threads query
+//! the ledger while one writer appends. Compares reading by cloning the chain
+//! (`get_entries`) with the clone-free paths (`with_entries`, `entry_count`,
+//! `get_entry_by_id`).
+//!
+//! Run with `cargo bench --bench ledger_reads`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use gsio_node::ledger::SharedLedger;
+use serde_json::json;
+
+const ENTRIES: usize = 5_000;
+const READERS: usize = 8;
+const READS_PER_READER: usize = 200;
+
+fn ledger() -> SharedLedger {
+    let ledger = SharedLedger::new("bench-node".to_string());
+    for i in 0..ENTRIES {
+        ledger.add_entry(json!({ "index": i, "message": "benchmark entry" })).unwrap();
+    }
+    ledger
+}
+
+/// Time `READERS` threads each calling `read` `READS_PER_READER` times while
+/// another thread keeps appending entries. `read` is given the ID of an entry
+/// halfway along the chain.
+fn run(name: &str, read: impl Fn(&SharedLedger, &str) + Sync) {
+    let ledger = ledger();
+    let id = ledger.with_entries(|entries| entries[ENTRIES / 2].id.clone());
+    let done = AtomicBool::new(false);
+    let mut appended = 0;
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(|| {
+            let mut appended = 0;
+            while !done.load(Ordering::Relaxed) {
+                ledger.add_entry(json!({ "message": "concurrent write" })).unwrap();
+                appended += 1;
+                std::thread::sleep(Duration::from_micros(100));
+            }
+            appended
+        });
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                scope.spawn(|| {
+                    for _ in 0..READS_PER_READER {
+                        read(&ledger, &id);
+                    }
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        appended = writer.join().unwrap();
+    });
+    let elapsed = start.elapsed();
+
+    let reads = READERS * READS_PER_READER;
+    println!(
+        "{name:<24} {reads} reads in {elapsed:>10.2?} ({:>8.2?}/read), {appended} concurrent writes",
+        elapsed / reads as u32
+    );
+}
+
+fn main() {
+    println!("{ENTRIES} entries, {READERS} readers, 1 writer");
+
+    run("get_entries (clone)", |ledger, _| {
+        std::hint::black_box(ledger.get_entries().len());
+    });
+    run("with_entries", |ledger, _| {
+        std::hint::black_box(ledger.with_entries(|entries| entries.len()));
+    });
+    run("entry_count", |ledger, _| {
+        std::hint::black_box(ledger.entry_count());
+    });
+    run("find by id (clone)", |ledger, id| {
+        std::hint::black_box(ledger.get_entries().into_iter().find(|e| e.id == id));
+    });
+    run("get_entry_by_id", |ledger, id| {
+        std::hint::black_box(ledger.get_entry_by_id(id));
+    });
+}
@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Compile with protox so building doesn't require a system protoc. The
+    // proto lives at the workspace root so clients in other languages can share it.
+    let file_descriptors = protox::compile(["../../proto/gsio.proto"], ["../../proto"])?;
+    tonic_prost_build::configure().compile_fds(file_descriptors)?;
+
+    println!("cargo:rerun-if-changed=../../proto/gsio.proto");
+    Ok(())
+}
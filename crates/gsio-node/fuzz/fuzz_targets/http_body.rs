@@ -0,0 +1,43 @@
+//! Request bodies sent to the HTTP API: the first byte picks the endpoint,
+//! and the rest is the body. Bad bodies must come back as client errors.
+
+#![no_main]
+
+use std::sync::{Arc, LazyLock};
+
+use axum::body::Body;
+use axum::http::{header, Method, Request};
+use ed25519_dalek::SigningKey;
+use gsio_node::api;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use libfuzzer_sys::fuzz_target;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+const ENDPOINTS: [(Method, &str); 3] = [
+    (Method::POST, "/api/ledger"),
+    (Method::POST, "/api/ledger/query"),
+    (Method::PUT, "/api/schemas/fuzz"),
+];
+
+static RUNTIME: LazyLock<Runtime> =
+    LazyLock::new(|| tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap());
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&pick, body)) = data.split_first() else {
+        return;
+    };
+    let (method, path) = &ENDPOINTS[pick as usize % ENDPOINTS.len()];
+    let request = Request::builder()
+        .method(method)
+        .uri(*path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_vec()))
+        .unwrap();
+
+    let ledger = SharedLedger::with_signing_key("fuzz-node".to_string(), SigningKey::from_bytes(&[1; 32]));
+    let app = api::router(Arc::new(P2PManager::new("fuzz-node".to_string(), ledger)));
+    let response = RUNTIME.block_on(app.oneshot(request)).unwrap();
+    assert!(!response.status().is_server_error(), "{} {path} answered {}", method, response.status());
+});
@@ -0,0 +1,26 @@
+//! Entries as a peer sends them: parsed from JSON, checked, then queued and
+//! processed the way an announced entry is.
+
+#![no_main]
+
+use ed25519_dalek::SigningKey;
+use gsio_node::ledger::{Ledger, LedgerEntry};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(entry) = serde_json::from_slice::<LedgerEntry>(data) else {
+        return;
+    };
+    entry.is_valid();
+    entry.header();
+
+    let mut ledger = Ledger::with_signing_key("fuzz-node".to_string(), SigningKey::from_bytes(&[1; 32]));
+    if ledger.check_chain(&entry).is_err() || ledger.check_limits(&entry).is_err() {
+        return;
+    }
+    ledger.validate(&entry.data).ok();
+    ledger.add_pending_entry(entry);
+    ledger.process_pending_entries();
+    ledger.resolve_forks();
+    ledger.add_entry(serde_json::json!({ "message": "after" })).ok();
+});
@@ -0,0 +1,31 @@
+//! P2P messages as they come off a connection: the first byte picks a JSON
+//! or msgpack frame, and a message that decodes is handled by a node.
+
+#![no_main]
+
+use ed25519_dalek::SigningKey;
+use gsio_node::codec::Frame;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&kind, bytes)) = data.split_first() else {
+        return;
+    };
+    let frame = if kind % 2 == 0 {
+        match serde_json::from_slice(bytes) {
+            Ok(value) => Frame::Json(value),
+            Err(_) => return,
+        }
+    } else {
+        Frame::Binary(bytes.to_vec())
+    };
+    let Ok(message) = frame.decode() else {
+        return;
+    };
+
+    let ledger = SharedLedger::with_signing_key("fuzz-node".to_string(), SigningKey::from_bytes(&[1; 32]));
+    let node = P2PManager::new("fuzz-node".to_string(), ledger);
+    node.handle_message(message);
+});
@@ -0,0 +1,266 @@
+//! Write access control lists for channels.
+//!
+//! A [channel](crate::channels) with an `[acl.<name>]` section in the config
+//! only takes entries created by holders of the public keys listed there:
+//! the node refuses to create an entry unless its own key is on the list,
+//! and an entry from a peer only joins the chain if its creator signature
+//! verifies under one of them.
+//!
+//! The list changes through governance entries carrying an `acl_update`,
+//! which writers add like any other entry. Writing doesn't make a key an
+//! admin, though: an update only applies when at least `threshold` of the
+//! `admins` listed in the config have approved it, by signing it along with
+//! the list it changes (see [`WriteAcl::approval_bytes`]), so an approval
+//! can't be replayed against a later list. Without admins the list can't
+//! change. An update takes effect for the entries after it once it is on the
+//! chain, so every node replaying the chain arrives at the same list.
+//!
+//! A [key rotation](crate::rotation) on the channel's chain moves the old
+//! key's place on the list to the new key. Admins are rotated by updating
+//! the config of every node hosting the channel.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use gsio_types::parse_public_key;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tracing::{info, warn};
+
+use crate::ledger::LedgerEntry;
+use crate::rotation::KeyRotation;
+
+/// Key of the ACL update in the data of a governance entry
+pub const ACL_UPDATE_KEY: &str = "acl_update";
+
+/// An `[acl.<channel>]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclConfig {
+    /// Hex-encoded public keys that may create entries in the channel
+    pub writers: Vec<String>,
+    /// Hex-encoded public keys that approve changes to the writers
+    pub admins: Vec<String>,
+    /// Number of admins that have to approve a change
+    pub threshold: usize,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            writers: Vec::new(),
+            admins: Vec::new(),
+            threshold: 1,
+        }
+    }
+}
+
+/// Change to a write ACL, stored under [`ACL_UPDATE_KEY`] in entry data
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclUpdate {
+    /// Hex-encoded public keys to allow
+    pub add: Vec<String>,
+    /// Hex-encoded public keys to no longer allow
+    pub remove: Vec<String>,
+    /// Hex-encoded signatures over [`WriteAcl::approval_bytes`], by the admin key that made them
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub approvals: BTreeMap<String, String>,
+}
+
+impl AclUpdate {
+    /// Approve the update to `acl` as the admin holding `key`
+    pub fn approve(mut self, acl: &WriteAcl, key: &SigningKey) -> Self {
+        let signature = key.sign(&acl.approval_bytes(&self));
+        self.approvals.insert(hex::encode(key.verifying_key().to_bytes()), hex::encode(signature.to_bytes()));
+        self
+    }
+
+    /// Entry data that applies this update once it's on the chain
+    pub fn to_entry_data(&self) -> JsonValue {
+        json!({ ACL_UPDATE_KEY: self })
+    }
+
+    /// The update carried by entry data, if any
+    pub fn from_data(data: &JsonValue) -> Option<Result<Self, String>> {
+        let update = data.get(ACL_UPDATE_KEY)?;
+        Some(serde_json::from_value(update.clone()).map_err(|e| format!("Invalid ACL update: {e}")))
+    }
+}
+
+/// Public keys allowed to create entries in a channel, and the admins who change them
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteAcl {
+    /// Keys by their hex encoding
+    writers: BTreeMap<String, VerifyingKey>,
+    /// Admin keys by their hex encoding
+    admins: BTreeMap<String, VerifyingKey>,
+    threshold: usize,
+    /// Number of updates applied since the list was configured
+    sequence: u64,
+}
+
+impl WriteAcl {
+    /// Create the list the chain starts with
+    pub fn new(config: &AclConfig) -> Result<Self, String> {
+        if config.writers.is_empty() {
+            return Err("A write ACL needs at least one writer".to_string());
+        }
+        let writers = config.writers.iter().map(|key| parse_acl_key("writer", key)).collect::<Result<_, String>>()?;
+        let admins: BTreeMap<_, _> =
+            config.admins.iter().map(|key| parse_acl_key("admin", key)).collect::<Result<_, String>>()?;
+        if config.threshold == 0 || (!admins.is_empty() && config.threshold > admins.len()) {
+            return Err(format!("ACL threshold must be between 1 and the {} admins", admins.len()));
+        }
+        Ok(Self { writers, admins, threshold: config.threshold, sequence: 0 })
+    }
+
+    /// The list after `sequence` updates, as a snapshot records it
+    pub fn at_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Hex-encoded public keys of the writers
+    pub fn writers(&self) -> Vec<String> {
+        self.writers.keys().cloned().collect()
+    }
+
+    /// Settings the list was configured with, with its writers as they are now
+    pub fn config(&self) -> AclConfig {
+        AclConfig { writers: self.writers(), admins: self.admins.keys().cloned().collect(), threshold: self.threshold }
+    }
+
+    /// Number of updates applied since the list was configured
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Bytes admins sign to approve `update` to this list: the update and
+    /// the list as it stands, so the approval is spent once it applies
+    pub fn approval_bytes(&self, update: &AclUpdate) -> Vec<u8> {
+        format!(
+            "gsio-acl-update:{}:{}:{}:{}",
+            self.sequence,
+            self.writers().join(","),
+            update.add.join(","),
+            update.remove.join(",")
+        )
+        .into_bytes()
+    }
+
+    /// Whether `key` may create entries
+    pub fn allows_key(&self, key: &VerifyingKey) -> bool {
+        self.writers.values().any(|writer| writer == key)
+    }
+
+    /// Whether `entry`'s creator signature was made with a writer's key
+    pub fn allows(&self, entry: &LedgerEntry) -> bool {
+        self.writers.values().any(|key| entry.verify_signature(&entry.creator_node_id, key))
+    }
+
+    /// The list `update` would leave, failing if it's malformed, removes
+    /// every writer or isn't approved by enough admins
+    pub fn updated(&self, update: &AclUpdate) -> Result<Self, String> {
+        self.check_approvals(update)?;
+        let mut writers = self.writers.clone();
+        for key in &update.remove {
+            writers.remove(&parse_acl_key("writer", key)?.0);
+        }
+        for key in &update.add {
+            let (hex, key) = parse_acl_key("writer", key)?;
+            writers.insert(hex, key);
+        }
+        if writers.is_empty() {
+            return Err("ACL update would remove every writer".to_string());
+        }
+        Ok(Self { writers, sequence: self.sequence + 1, ..self.clone() })
+    }
+
+    /// Require `threshold` admins to have signed the update
+    fn check_approvals(&self, update: &AclUpdate) -> Result<(), String> {
+        if self.admins.is_empty() {
+            return Err("The write ACL has no admins, so it can't be changed".to_string());
+        }
+        let message = self.approval_bytes(update);
+        let approved: BTreeSet<&String> = update
+            .approvals
+            .iter()
+            .filter_map(|(admin, signature)| {
+                let (admin, key) = self.admins.get_key_value(&parse_acl_key("admin", admin).ok()?.0)?;
+                let signature = hex::decode(signature).ok().and_then(|b| Signature::from_slice(&b).ok())?;
+                key.verify(&message, &signature).is_ok().then_some(admin)
+            })
+            .collect();
+        if approved.len() < self.threshold {
+            return Err(format!(
+                "ACL update is approved by {} of the {} admins it needs",
+                approved.len(),
+                self.threshold
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the ACL update in entry data, if any, against this list
+    pub fn check(&self, data: &JsonValue) -> Result<(), String> {
+        match AclUpdate::from_data(data) {
+            None => Ok(()),
+            Some(update) => self.updated(&update?).map(|_| ()),
+        }
+    }
+
+    /// Apply the ACL update or key rotation an entry on the chain carries, if any.
+    ///
+    /// Updates that are malformed or would leave no writers are ignored.
+    /// Applying the same update twice has the same effect as applying it once.
+    pub fn apply(&mut self, entry: &LedgerEntry) {
+        if let Some(Ok(rotation)) = KeyRotation::from_data(&entry.data) {
+            self.rotate(entry, &rotation);
+            return;
+        }
+        let update = match AclUpdate::from_data(&entry.data) {
+            None => return,
+            Some(Ok(update)) => update,
+            Some(Err(e)) => {
+                warn!(entry_id = entry.id, "Ignoring ACL update: {}", e);
+                return;
+            }
+        };
+        match self.updated(&update) {
+            Ok(acl) => {
+                *self = acl;
+                info!(entry_id = entry.id, writers = self.writers.len(), "Write ACL changed");
+            }
+            Err(e) => warn!(entry_id = entry.id, "Ignoring ACL update: {}", e),
+        }
+    }
+
+    /// Replace a writer's key with the one it was rotated to, if the rotation verifies
+    fn rotate(&mut self, entry: &LedgerEntry, rotation: &KeyRotation) {
+        let Ok((old_key, _)) = parse_acl_key("writer", &rotation.old_key) else {
+            return;
+        };
+        if !self.writers.contains_key(&old_key) {
+            return;
+        }
+        if let Err(e) = rotation.verify() {
+            warn!(entry_id = entry.id, "Ignoring key rotation: {}", e);
+            return;
+        }
+        match parse_acl_key("writer", &rotation.new_key) {
+            Ok((hex, key)) => {
+                self.writers.remove(&old_key);
+                self.writers.insert(hex, key);
+                info!(entry_id = entry.id, old_key, new_key = rotation.new_key, "Writer key rotated");
+            }
+            Err(e) => warn!(entry_id = entry.id, "Ignoring key rotation: {}", e),
+        }
+    }
+}
+
+/// Parse a hex-encoded `role` key, returning it along with its lowercase encoding
+fn parse_acl_key(role: &str, key: &str) -> Result<(String, VerifyingKey), String> {
+    let parsed = parse_public_key(key).map_err(|_| format!("Invalid {role} key {key}"))?;
+    Ok((hex::encode(parsed.to_bytes()), parsed))
+}
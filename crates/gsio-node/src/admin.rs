@@ -0,0 +1,376 @@
+//! Admin API for operators, under `/admin`.
+//!
+//! The routes are only served when the `[admin]` section of the config file
+//! lists API keys, and every request has to carry one of them as
+//! `Authorization: Bearer <key>`. Client credentials from `[auth]` don't
+//! grant access.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::api::ApiError;
+use crate::audit::{self, AuditEvent, AuditEventType};
+use crate::auth::{self, AuthConfig, Authenticator};
+use crate::backup::Backup;
+use crate::codec::Codec;
+use crate::config;
+use crate::discovery::{self, Discovery};
+use crate::error::GsioNodeError;
+use crate::ledger::{LedgerStats, RetentionPolicy};
+use crate::p2p::P2PManager;
+use crate::validation::ValidationConfig;
+
+/// The `[admin]` section of the config file; the admin API is off unless keys are listed
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Keys operators send as bearer credentials
+    pub api_keys: Vec<String>,
+}
+
+impl AdminConfig {
+    /// Whether the admin API is served
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty()
+    }
+}
+
+/// Settings that can be changed while the node runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RuntimeConfig {
+    pub retention: RetentionPolicy,
+    pub validation: ValidationConfig,
+}
+
+/// Changes sent to `PATCH /admin/config`; settings left out are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    pub retention: Option<RetentionPolicy>,
+    pub validation: Option<ValidationConfig>,
+}
+
+/// A peer that connected to this node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundPeer {
+    pub node_id: String,
+    /// Seconds since the peer connected
+    pub connected_secs: u64,
+    /// Seconds since the peer was last heard from
+    pub idle_secs: u64,
+    /// P2P protocol version agreed with the peer
+    pub protocol_version: Option<u32>,
+    /// Encoding agreed with the peer
+    pub codec: Option<Codec>,
+}
+
+/// A node this node connected to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundPeer {
+    pub url: String,
+    /// The peer's node ID, once it has proven it
+    pub node_id: Option<String>,
+    /// P2P protocol version agreed with the peer, once it has proven its ID
+    pub protocol_version: Option<u32>,
+    /// Encoding agreed with the peer, once it has proven its ID
+    pub codec: Option<Codec>,
+}
+
+/// Peers as listed by `GET /admin/peers`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerList {
+    pub inbound: Vec<InboundPeer>,
+    pub outbound: Vec<OutboundPeer>,
+    /// URLs being dialed, whether or not they are connected
+    pub dialing: Vec<String>,
+    /// IDs of the nodes refused as peers
+    pub banned: Vec<String>,
+}
+
+/// Outcome of `POST /admin/key/rotate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotatedKey {
+    /// Hex-encoded key that was retired
+    pub old_key: String,
+    /// Hex-encoded key that replaced it, which the node uses once restarted
+    pub new_key: String,
+    /// ID of the entry recording the rotation
+    pub entry_id: String,
+}
+
+/// Runs operator requests against a node
+pub struct Admin {
+    p2p: Arc<P2PManager>,
+    config: Mutex<RuntimeConfig>,
+    shutdown: Notify,
+    /// File the node's key is kept in, which a key rotation replaces
+    key_file: Option<PathBuf>,
+    /// Discovery service operators introduce nodes through
+    discovery: Option<Discovery>,
+}
+
+impl Admin {
+    /// Manage the node behind `p2p`, which runs with `config`
+    pub fn new(p2p: Arc<P2PManager>, config: RuntimeConfig) -> Self {
+        Self {
+            p2p,
+            config: Mutex::new(config),
+            shutdown: Notify::new(),
+            key_file: None,
+            discovery: None,
+        }
+    }
+
+    /// Let the node's key, kept in `path`, be rotated
+    pub fn with_key_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.key_file = Some(path.into());
+        self
+    }
+
+    /// Let operators introduce nodes to this one through `discovery`
+    pub fn with_discovery(mut self, discovery: Discovery) -> Self {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// List the node's peers
+    pub fn peers(&self) -> PeerList {
+        let mut inbound: Vec<InboundPeer> = self
+            .p2p
+            .peer_health()
+            .into_iter()
+            .map(|(node_id, health)| InboundPeer {
+                protocol_version: self.p2p.peer_version(&node_id),
+                codec: self.p2p.peer_codec(&node_id),
+                node_id,
+                connected_secs: health.connected_at.elapsed().as_secs(),
+                idle_secs: health.last_seen.elapsed().as_secs(),
+            })
+            .collect();
+        inbound.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        let mut outbound: Vec<OutboundPeer> = self
+            .p2p
+            .outbound_peer_ids()
+            .into_iter()
+            .map(|(url, node_id)| OutboundPeer {
+                protocol_version: node_id.as_deref().and_then(|id| self.p2p.peer_version(id)),
+                codec: node_id.as_deref().and_then(|id| self.p2p.peer_codec(id)),
+                url,
+                node_id,
+            })
+            .collect();
+        outbound.sort_by(|a, b| a.url.cmp(&b.url));
+        let mut dialing = self.p2p.dialed_peers();
+        dialing.sort();
+        let mut banned = self.p2p.banned_peers();
+        banned.sort();
+
+        PeerList { inbound, outbound, dialing, banned }
+    }
+
+    /// The settings the node currently runs with
+    pub fn config(&self) -> RuntimeConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Apply new settings without restarting, returning the resulting ones
+    pub fn update_config(&self, update: ConfigUpdate) -> RuntimeConfig {
+        let mut config = self.config.lock().unwrap();
+        if let Some(retention) = update.retention {
+            info!(?retention, "Retention policy changed");
+            self.p2p.ledger.set_retention_policy(retention.clone());
+            self.record("set_retention", None, json!({ "retention": retention }));
+            config.retention = retention;
+        }
+        if let Some(validation) = update.validation {
+            let policy = validation.policy();
+            info!(rules = policy.len(), "Entry validation changed");
+            self.p2p.ledger.set_validation_policy(Arc::new(policy));
+            self.record("set_validation", None, json!({ "validation": validation }));
+            config.validation = validation;
+        }
+        config.clone()
+    }
+
+    /// Retire the node's key in favour of a newly generated one.
+    ///
+    /// The rotation, signed with the current key, is added to the ledger, and
+    /// the new key replaces the one in the key file. The node keeps running
+    /// with the old key, which the ledger no longer lets it create entries
+    /// with, until it's restarted. The new key is written beside the key file
+    /// before the entry is added, so it isn't lost if the node stops in between.
+    pub async fn rotate_key(&self) -> Result<RotatedKey, GsioNodeError> {
+        let Some(key_file) = &self.key_file else {
+            return Err(GsioNodeError::Conflict("The node's key isn't kept in a file, so it can't be rotated".to_string()));
+        };
+        let new_key = SigningKey::generate(&mut OsRng);
+        let pending = key_file.with_extension("next");
+        config::save_key(&pending, &new_key).map_err(GsioNodeError::Internal)?;
+
+        let rotation = self.p2p.ledger.key_rotation(&new_key.verifying_key());
+        let entry = match self.p2p.add_entry_data(rotation.to_entry_data()).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                std::fs::remove_file(&pending).ok();
+                return Err(e);
+            }
+        };
+        if let Err(e) = std::fs::rename(&pending, key_file) {
+            warn!(path = %pending.display(), "Failed to replace the node key, move the new key into place by hand: {}", e);
+            return Err(GsioNodeError::Internal(format!(
+                "Key rotated, but the new key is still in {}: {e}",
+                pending.display()
+            )));
+        }
+
+        info!(old_key = rotation.old_key, new_key = rotation.new_key, "Node key rotated, restart the node to use the new key");
+        let rotated = RotatedKey { old_key: rotation.old_key, new_key: rotation.new_key, entry_id: entry.id };
+        self.record("rotate_key", None, json!(rotated));
+        Ok(rotated)
+    }
+
+    /// Back the node up, see [`crate::backup`]
+    pub async fn backup(&self) -> Result<Vec<u8>, GsioNodeError> {
+        let backup = Backup::take(self.p2p.clone(), self.key_file.as_deref()).await.map_err(GsioNodeError::Internal)?;
+        let details = json!({ "height": backup.manifest.height, "has_key": backup.manifest.has_key });
+        let archive = tokio::task::spawn_blocking(move || backup.to_archive())
+            .await
+            .map_err(|e| GsioNodeError::Internal(e.to_string()))?
+            .map_err(GsioNodeError::Internal)?;
+        self.record("backup", None, details);
+        Ok(archive)
+    }
+
+    /// Ask the node to shut down
+    pub fn request_shutdown(&self) {
+        info!("Shutdown requested through the admin API");
+        self.record("shutdown", None, json!({}));
+        self.shutdown.notify_one();
+    }
+
+    /// Record an admin action in the node's audit log
+    fn record(&self, action: &str, peer_id: Option<&str>, mut details: JsonValue) {
+        details["action"] = json!(action);
+        let mut event = AuditEvent::new(AuditEventType::AdminAction).with_details(details);
+        event.peer_id = peer_id.map(str::to_string);
+        self.p2p.audit().record(event);
+    }
+
+    /// Resolves once a shutdown has been requested
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+}
+
+/// Build the `/admin` routes, accepting only the keys in `config`
+pub fn router(admin: Arc<Admin>, config: &AdminConfig) -> Result<Router, String> {
+    let keys = AuthConfig { api_keys: config.api_keys.clone(), ..AuthConfig::default() };
+    let auth = Arc::new(Authenticator::new(&keys)?);
+    let audit = admin.p2p.audit();
+    let discovery = admin.discovery.clone();
+
+    Ok(Router::new()
+        .route("/admin/peers", get(list_peers))
+        .route("/admin/peers/{node_id}/disconnect", post(disconnect_peer))
+        .route("/admin/peers/{node_id}/ban", post(ban_peer).delete(unban_peer))
+        .route("/admin/ledger/stats", get(ledger_stats))
+        .route("/admin/config", get(get_config).patch(update_config))
+        .route("/admin/key/rotate", post(rotate_key))
+        .route("/admin/backup", get(backup))
+        .route("/admin/shutdown", post(shutdown))
+        .with_state(admin)
+        .merge(audit::router(audit))
+        .merge(discovery.map(discovery::router).unwrap_or_default())
+        .route_layer(middleware::from_fn_with_state(auth, auth::require_auth)))
+}
+
+async fn list_peers(State(admin): State<Arc<Admin>>) -> Json<PeerList> {
+    Json(admin.peers())
+}
+
+async fn disconnect_peer(State(admin): State<Arc<Admin>>, Path(node_id): Path<String>) -> Result<StatusCode, ApiError> {
+    if admin.p2p.disconnect_peer(&node_id).await {
+        admin.record("disconnect_peer", Some(&node_id), json!({}));
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::new(StatusCode::NOT_FOUND, format!("Not connected to {node_id}")))
+    }
+}
+
+async fn ban_peer(State(admin): State<Arc<Admin>>, Path(node_id): Path<String>) -> StatusCode {
+    admin.p2p.ban_peer(&node_id).await;
+    admin.record("ban_peer", Some(&node_id), json!({}));
+    StatusCode::NO_CONTENT
+}
+
+async fn unban_peer(State(admin): State<Arc<Admin>>, Path(node_id): Path<String>) -> Result<StatusCode, ApiError> {
+    if admin.p2p.unban_peer(&node_id) {
+        admin.record("unban_peer", Some(&node_id), json!({}));
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::new(StatusCode::NOT_FOUND, format!("{node_id} isn't banned")))
+    }
+}
+
+async fn ledger_stats(State(admin): State<Arc<Admin>>) -> Json<LedgerStats> {
+    Json(admin.p2p.ledger.stats())
+}
+
+/// The runtime settings along with fixed ones operators usually want to see next to them
+fn config_view(admin: &Admin, config: RuntimeConfig) -> Json<JsonValue> {
+    Json(json!({
+        "node_id": admin.p2p.node_id(),
+        "read_only": admin.p2p.mode().is_read_only(),
+        "consensus": admin.p2p.ledger.consensus_name(),
+        "retention": config.retention,
+        "validation": config.validation,
+    }))
+}
+
+async fn get_config(State(admin): State<Arc<Admin>>) -> Json<JsonValue> {
+    config_view(&admin, admin.config())
+}
+
+async fn update_config(
+    State(admin): State<Arc<Admin>>,
+    update: Result<Json<ConfigUpdate>, JsonRejection>,
+) -> Result<Json<JsonValue>, ApiError> {
+    let Json(update) = update?;
+    let config = admin.update_config(update);
+    Ok(config_view(&admin, config))
+}
+
+async fn rotate_key(State(admin): State<Arc<Admin>>) -> Result<Json<JsonValue>, ApiError> {
+    let rotated = admin.rotate_key().await?;
+    Ok(Json(json!({
+        "old_key": rotated.old_key,
+        "new_key": rotated.new_key,
+        "entry_id": rotated.entry_id,
+        "restart_required": true,
+    })))
+}
+
+async fn backup(State(admin): State<Arc<Admin>>) -> Result<Response, ApiError> {
+    let archive = admin.backup().await?;
+    Ok(([(header::CONTENT_TYPE, "application/zstd")], archive).into_response())
+}
+
+async fn shutdown(State(admin): State<Arc<Admin>>) -> (StatusCode, Json<JsonValue>) {
+    admin.request_shutdown();
+    (StatusCode::ACCEPTED, Json(json!({ "status": "shutting_down" })))
+}
@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::GsioNodeError;
+use crate::genesis::GenesisInfo;
+use crate::ledger::{EntryProof, LedgerEntry, LedgerHeaders, Query as EntryQuery, QueryPage, Snapshot};
+use crate::mempool::FeeFloor;
+use crate::offload::is_blob_hash;
+use crate::p2p::{P2PManager, PeerInfo};
+use crate::schema::{KindSchema, SchemaError};
+use crate::staking::StakeInfo;
+use crate::validation::ErrorCode;
+
+/// Header carrying the key under which `POST /api/ledger` adds an entry at
+/// most once
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Query candidates read from the ledger at a time
+pub const QUERY_BATCH_SIZE: usize = 256;
+
+/// Headers `GET /api/ledger/headers` returns when no limit is given
+pub const HEADERS_PAGE_SIZE: usize = 100;
+
+/// Most headers `GET /api/ledger/headers` returns at once
+pub const MAX_HEADERS_PAGE_SIZE: usize = 1000;
+
+/// Error returned by the REST API as a JSON body
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    /// Machine-readable reason, sent as `code` alongside the message
+    code: ErrorCode,
+    /// Node the client should retry against, sent as a `Location` header
+    redirect: Option<String>,
+}
+
+impl ApiError {
+    /// Create a new API error, with the code that goes with `status`
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            code: code_for_status(status),
+            redirect: None,
+        }
+    }
+
+    /// Attach a more specific machine-readable error code
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+}
+
+/// Code sent with an error that doesn't name a more specific one
+fn code_for_status(status: StatusCode) -> ErrorCode {
+    match status {
+        StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+        StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+        StatusCode::NOT_FOUND => ErrorCode::NotFound,
+        StatusCode::CONFLICT => ErrorCode::Conflict,
+        StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            ErrorCode::Unavailable
+        }
+        status if status.is_client_error() => ErrorCode::BadRequest,
+        _ => ErrorCode::Internal,
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "error": self.message, "code": self.code }));
+        match self.redirect {
+            Some(location) => (self.status, [(header::LOCATION, location)], body).into_response(),
+            None => (self.status, body).into_response(),
+        }
+    }
+}
+
+impl From<GsioNodeError> for ApiError {
+    fn from(error: GsioNodeError) -> Self {
+        Self {
+            status: error.status(),
+            message: error.to_string(),
+            code: error.code(),
+            // Point the client at the writable node's ledger
+            redirect: error.redirect().map(|url| format!("{}/api/ledger", url.trim_end_matches('/'))),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, rejection.body_text())
+    }
+}
+
+/// Build the `/api` routes served alongside the Socket.IO layer
+pub fn router(p2p: Arc<P2PManager>) -> Router {
+    Router::new()
+        .nest("/api", ledger_routes())
+        .route("/api/blobs/{hash}", get(get_blob))
+        .route("/api/fees/floor", get(get_fee_floor))
+        .route("/api/genesis", get(get_genesis))
+        .route("/api/peers/{id}", get(get_peer))
+        .route("/api/schemas", get(get_schemas))
+        .route("/api/schemas/{kind}", put(register_schema).get(get_schema))
+        .route("/api/staking/{address}", get(get_stake))
+        .with_state(p2p)
+}
+
+/// Routes for reading and writing one ledger and listing its known nodes,
+/// `/ledger` and `/nodes` relative to where they're nested
+pub fn ledger_routes() -> Router<Arc<P2PManager>> {
+    Router::new()
+        .route("/ledger", get(get_ledger).post(add_ledger_entry))
+        .route("/ledger/headers", get(get_ledger_headers))
+        .route("/ledger/query", post(query_ledger))
+        .route("/ledger/snapshot", get(get_ledger_snapshot))
+        .route("/ledger/{id}", get(get_ledger_entry))
+        .route("/ledger/{id}/proof", get(get_entry_proof))
+        .route("/nodes", get(get_known_nodes))
+}
+
+/// Optional filters for `GET /api/ledger`
+#[derive(Debug, Default, Deserialize)]
+pub struct LedgerQuery {
+    /// Only return entries created strictly after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only return entries created by this node
+    pub creator: Option<String>,
+    /// Only return the entries after the one with this hash, which has to be
+    /// on the chain, so a client paging through it notices when it changes
+    pub after: Option<String>,
+    /// Number of entries to skip
+    pub offset: Option<usize>,
+    /// Maximum number of entries to return
+    pub limit: Option<usize>,
+}
+
+/// Entries on the chain, tagged with an `ETag` from the ledger's version so
+/// polling clients that send it back in `If-None-Match` get `304 Not Modified`
+/// until the chain changes
+async fn get_ledger(
+    State(p2p): State<Arc<P2PManager>>,
+    headers: HeaderMap,
+    query: Result<Query<LedgerQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    if query.after.is_some() && (query.creator.is_some() || query.since.is_some()) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "after can't be combined with since or creator"));
+    }
+    // Tagged before reading, so an entry added in between only makes the tag stale, never the body
+    let etag = format!("\"{}\"", p2p.ledger.version());
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(usize::MAX);
+
+    let entries = match (query.creator, query.since, query.after) {
+        (Some(creator), since, _) => p2p
+            .ledger
+            .find_by_creator(&creator)
+            .into_iter()
+            .filter(|e| since.is_none_or(|since| e.timestamp > since))
+            .skip(offset)
+            .take(limit)
+            .collect(),
+        (None, Some(since), _) => p2p.ledger.get_entries_since(since).into_iter().skip(offset).take(limit).collect(),
+        (None, None, Some(after)) => p2p
+            .ledger
+            .get_entries_after_paginated(&after, offset.saturating_add(limit))
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Entry {after} isn't on the chain")))?
+            .into_iter()
+            .skip(offset)
+            .collect(),
+        (None, None, None) => p2p.ledger.get_entries_paginated(offset, limit),
+    };
+    Ok(([(header::ETAG, etag)], Json(p2p.rehydrate(entries).await)).into_response())
+}
+
+/// Whether the request's `If-None-Match` lists `etag`, or `*`
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Evaluate an [`EntryQuery`], fetching the data of offloaded entries to check the filters against
+async fn query_ledger(
+    State(p2p): State<Arc<P2PManager>>,
+    query: Result<Json<EntryQuery>, JsonRejection>,
+) -> Result<Json<QueryPage>, ApiError> {
+    let Json(query) = query?;
+    query.validate().map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+
+    // Candidates are read a batch at a time until one match past the page
+    // shows there's another, fetching offloaded data only where the filters
+    // need it or the entry is on the page
+    let page_size = query.page_size();
+    let wanted = query.offset + page_size + 1;
+    let (mut matched, mut entries, mut after) = (0, Vec::new(), 0);
+    'candidates: loop {
+        let batch = p2p.ledger.query(&query, after, QUERY_BATCH_SIZE);
+        let last_batch = batch.len() < QUERY_BATCH_SIZE;
+        for (height, entry) in batch {
+            after = height;
+            let on_page = matched >= query.offset && entries.len() < page_size;
+            let entry = match entry.pending_blob() {
+                Some(_) if on_page || !query.filters.is_empty() => p2p.rehydrate_entry(entry).await,
+                _ => entry,
+            };
+            if !query.matches_data(&entry.data) {
+                continue;
+            }
+            if on_page {
+                entries.push(entry);
+            }
+            matched += 1;
+            if matched == wanted {
+                break 'candidates;
+            }
+        }
+        if last_batch {
+            break;
+        }
+    }
+
+    Ok(Json(QueryPage {
+        entries,
+        next_offset: (matched == wanted).then_some(query.offset + page_size),
+    }))
+}
+
+async fn get_ledger_entry(
+    State(p2p): State<Arc<P2PManager>>,
+    Path(id): Path<String>,
+) -> Result<Json<LedgerEntry>, ApiError> {
+    let entry = p2p
+        .find_entry(&id)
+        .await
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Entry {id} not found")))?;
+    Ok(Json(p2p.rehydrate_entry(entry).await))
+}
+
+/// Serve the data of an offloaded entry as the JSON it was stored as, so clients can check its hash
+async fn get_blob(State(p2p): State<Arc<P2PManager>>, Path(hash): Path<String>) -> Result<Response, ApiError> {
+    if !is_blob_hash(&hash) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid blob hash {hash}")));
+    }
+    match p2p.blob(&hash).await {
+        Ok(Some(bytes)) => Ok(([(header::CONTENT_TYPE, "application/json")], bytes).into_response()),
+        Ok(None) => Err(ApiError::new(StatusCode::NOT_FOUND, format!("Blob {hash} not found"))),
+        Err(e) => Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// Pagination for `GET /api/ledger/headers`
+#[derive(Debug, Default, Deserialize)]
+pub struct HeadersQuery {
+    /// Number of headers to skip
+    pub offset: Option<usize>,
+    /// Maximum number of headers to return, [`HEADERS_PAGE_SIZE`] if unset
+    /// and at most [`MAX_HEADERS_PAGE_SIZE`]
+    pub limit: Option<usize>,
+}
+
+async fn get_ledger_headers(
+    State(p2p): State<Arc<P2PManager>>,
+    query: Result<Query<HeadersQuery>, QueryRejection>,
+) -> Result<Json<LedgerHeaders>, ApiError> {
+    let Query(query) = query?;
+    let limit = query.limit.unwrap_or(HEADERS_PAGE_SIZE).min(MAX_HEADERS_PAGE_SIZE);
+    let headers = p2p.ledger.get_headers(query.offset.unwrap_or(0), limit);
+    Ok(Json(headers))
+}
+
+/// Height for `GET /api/ledger/snapshot`
+#[derive(Debug, Default, Deserialize)]
+pub struct SnapshotQuery {
+    /// Height of the checkpoint entry; the tip if unset
+    pub height: Option<usize>,
+}
+
+/// Take the snapshot a [`SnapshotQuery`] asks for
+pub fn take_snapshot(p2p: &P2PManager, query: &SnapshotQuery) -> Result<Snapshot, ApiError> {
+    if p2p.ledger.get_last_entry().is_none() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Ledger is empty"));
+    }
+    let snapshot = match query.height {
+        Some(height) => p2p.ledger.create_snapshot(height),
+        None => p2p.ledger.snapshot_tip(),
+    };
+    snapshot.map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))
+}
+
+async fn get_ledger_snapshot(
+    State(p2p): State<Arc<P2PManager>>,
+    query: Result<Query<SnapshotQuery>, QueryRejection>,
+) -> Result<Json<Snapshot>, ApiError> {
+    let Query(query) = query?;
+    take_snapshot(&p2p, &query).map(Json)
+}
+
+async fn get_entry_proof(
+    State(p2p): State<Arc<P2PManager>>,
+    Path(id): Path<String>,
+) -> Result<Json<EntryProof>, ApiError> {
+    p2p.ledger
+        .get_entry_proof(&id)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Entry {id} not found")))
+}
+
+async fn add_ledger_entry(
+    State(p2p): State<Arc<P2PManager>>,
+    headers: HeaderMap,
+    body: Result<Json<JsonValue>, JsonRejection>,
+) -> Result<(StatusCode, Json<LedgerEntry>), ApiError> {
+    let Json(data) = body?;
+
+    let entry = match headers.get(IDEMPOTENCY_KEY) {
+        Some(key) => {
+            let key = key.to_str().map_err(|_| GsioNodeError::BadRequest("idempotency key isn't ASCII".to_string()))?;
+            p2p.add_entry_data_once(key, data).await?
+        }
+        None => p2p.add_entry_data(data).await?,
+    };
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+async fn get_known_nodes(State(p2p): State<Arc<P2PManager>>) -> Json<JsonValue> {
+    let nodes = p2p.ledger.get_known_nodes();
+    // URLs of the peers this node is connected to, so other nodes can dial them too
+    let peers = p2p.outbound_peers();
+    // Where the nodes it knows of can be reached, as far as it knows
+    let contacts = p2p.contacts().all();
+    let liveness = p2p.node_liveness();
+    Json(json!({ "nodes": nodes, "peers": peers, "contacts": contacts, "liveness": liveness }))
+}
+
+async fn get_peer(State(p2p): State<Arc<P2PManager>>, Path(id): Path<String>) -> Result<Json<PeerInfo>, ApiError> {
+    p2p.peer_info(&id)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Not connected to {id}")))
+}
+
+async fn get_fee_floor(State(p2p): State<Arc<P2PManager>>) -> Json<FeeFloor> {
+    Json(p2p.fee_floor())
+}
+
+async fn get_genesis(State(p2p): State<Arc<P2PManager>>) -> Json<GenesisInfo> {
+    Json(p2p.ledger.genesis())
+}
+
+async fn get_stake(State(p2p): State<Arc<P2PManager>>, Path(address): Path<String>) -> Result<Json<StakeInfo>, ApiError> {
+    p2p.ledger.stake(&address).map(Json).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))
+}
+
+/// Registered kinds as returned by `GET /api/schemas`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaList {
+    /// Whether entries without a kind are rejected
+    pub require_kind: bool,
+    /// Whether entries of kinds that aren't registered are accepted
+    pub allow_unknown_kinds: bool,
+    /// Schema of every registered kind; `null` for kinds registered without one
+    pub kinds: BTreeMap<String, Option<JsonValue>>,
+}
+
+async fn get_schemas(State(p2p): State<Arc<P2PManager>>) -> Json<SchemaList> {
+    let schemas = p2p.ledger.schemas();
+    Json(SchemaList {
+        require_kind: schemas.requires_kind(),
+        allow_unknown_kinds: schemas.allows_unknown_kinds(),
+        kinds: schemas.kinds(),
+    })
+}
+
+async fn get_schema(State(p2p): State<Arc<P2PManager>>, Path(kind): Path<String>) -> Result<Json<KindSchema>, ApiError> {
+    match p2p.ledger.schemas().get(&kind) {
+        Some(schema) => Ok(Json(KindSchema { kind, schema })),
+        None => Err(ApiError::new(StatusCode::NOT_FOUND, format!("Kind {kind} isn't registered"))),
+    }
+}
+
+/// Body of `PUT /api/schemas/{kind}`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaRegistration {
+    /// JSON Schema payloads of the kind must match; any payload is accepted if unset
+    pub schema: Option<JsonValue>,
+}
+
+async fn register_schema(
+    State(p2p): State<Arc<P2PManager>>,
+    Path(kind): Path<String>,
+    body: Result<Json<SchemaRegistration>, JsonRejection>,
+) -> Result<(StatusCode, Json<KindSchema>), ApiError> {
+    let Json(registration) = body?;
+
+    match p2p.ledger.schemas().register(&kind, registration.schema.clone()) {
+        Ok(created) => {
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            Ok((status, Json(KindSchema { kind, schema: registration.schema })))
+        }
+        Err(e @ SchemaError::Invalid(_)) => Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string())),
+        Err(e @ SchemaError::Conflict(_)) => Err(ApiError::new(StatusCode::CONFLICT, e.to_string())),
+    }
+}
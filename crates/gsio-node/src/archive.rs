@@ -0,0 +1,158 @@
+//! Archiving entries before retention prunes them.
+//!
+//! Retention keeps a long-running node's chain from growing without bound,
+//! but throws the pruned history away. With `[archive] enabled = true`, each
+//! retention round instead writes the entries it's about to prune to the blob
+//! store as one archive segment, a JSON array of the entries, and only prunes
+//! them once the segment is stored. The node remembers which segment holds
+//! each entry, so `GET /api/ledger/{id}` still finds an archived entry by
+//! fetching its segment on demand.
+//!
+//! Segments and the blobs their entries' offloaded data is stored in are
+//! kept by blob garbage collection for as long as the node runs. The list of
+//! segments is held in memory, like the chain itself.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::ledger::{LedgerEntry, SharedLedger};
+use crate::offload::{blob_hash, BlobRef, BlobStore};
+
+/// The `[archive]` section of the config file; pruned entries are discarded unless `enabled` is set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Store entries in the blob store before retention prunes them
+    pub enabled: bool,
+}
+
+/// A run of pruned entries stored as one blob
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveSegment {
+    /// Height of the first entry in the segment
+    pub from: usize,
+    /// Height of the last entry in the segment
+    pub to: usize,
+    /// Hash of the last entry, which the first entry left on the chain links to
+    pub last_hash: String,
+    /// Hex-encoded BLAKE3 hash of the blob holding the entries
+    pub blob: String,
+    /// Hashes of the blobs the segment's entries offloaded their data to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_blobs: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct ArchiveIndex {
+    segments: Vec<ArchiveSegment>,
+    /// Entry IDs, by the position of their segment in `segments`
+    by_id: HashMap<String, usize>,
+}
+
+/// Archive segments written by this node
+pub struct Archive {
+    store: Arc<dyn BlobStore>,
+    index: Mutex<ArchiveIndex>,
+    /// The segment read last, since neighbouring entries tend to be read together
+    cached: Mutex<Option<(usize, Arc<Vec<LedgerEntry>>)>>,
+}
+
+impl Archive {
+    /// Create an archive storing segments in `store`
+    pub fn new(store: Arc<dyn BlobStore>) -> Self {
+        Self {
+            store,
+            index: Mutex::new(ArchiveIndex::default()),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Archive the entries outside `ledger`'s retention policy, then prune
+    /// them, returning how many were pruned
+    pub async fn apply_retention(&self, ledger: &SharedLedger) -> Result<usize, String> {
+        let (from, expired) = ledger.expired_entries();
+        let Some(last) = expired.last() else { return Ok(0) };
+        let last_hash = last.hash.clone();
+
+        let bytes = serde_json::to_vec(&expired).map_err(|e| e.to_string())?;
+        let blob = self.store.put(bytes).await?;
+        let segment = ArchiveSegment {
+            from,
+            to: from + expired.len() - 1,
+            last_hash: last_hash.clone(),
+            blob,
+            data_blobs: expired.iter().filter_map(|e| BlobRef::from_data(&e.data)).map(|r| r.hash).collect(),
+        };
+
+        // The chain may have been reorganized while the segment was stored
+        let pruned = ledger.prune_through(&last_hash);
+        if pruned != expired.len() {
+            return Err(format!("Chain changed while archiving heights {} to {}", segment.from, segment.to));
+        }
+        info!(from = segment.from, to = segment.to, blob = segment.blob, "Archived entries");
+
+        let mut index = self.index.lock().unwrap();
+        let position = index.segments.len();
+        index.by_id.extend(expired.into_iter().map(|entry| (entry.id, position)));
+        index.segments.push(segment);
+        Ok(pruned)
+    }
+
+    /// The segments archived so far, oldest first
+    pub fn segments(&self) -> Vec<ArchiveSegment> {
+        self.index.lock().unwrap().segments.clone()
+    }
+
+    /// Whether the entry with `id` has been archived
+    pub fn contains(&self, id: &str) -> bool {
+        self.index.lock().unwrap().by_id.contains_key(id)
+    }
+
+    /// Fetch an archived entry, or `None` if it isn't in the archive
+    pub async fn get_entry(&self, id: &str) -> Result<Option<LedgerEntry>, String> {
+        let Some(position) = self.index.lock().unwrap().by_id.get(id).copied() else { return Ok(None) };
+        let entries = self.load_segment(position).await?;
+        Ok(entries.iter().find(|entry| entry.id == id).cloned())
+    }
+
+    /// Hashes of every blob the archive needs: segments and the offloaded data of archived entries
+    pub fn referenced_blobs(&self) -> HashSet<String> {
+        let index = self.index.lock().unwrap();
+        index
+            .segments
+            .iter()
+            .flat_map(|segment| std::iter::once(&segment.blob).chain(&segment.data_blobs))
+            .cloned()
+            .collect()
+    }
+
+    async fn load_segment(&self, position: usize) -> Result<Arc<Vec<LedgerEntry>>, String> {
+        if let Some((cached, entries)) = &*self.cached.lock().unwrap()
+            && *cached == position
+        {
+            return Ok(entries.clone());
+        }
+
+        let blob = self.index.lock().unwrap().segments[position].blob.clone();
+        let bytes = self.store.get(blob.clone(), None).await?.ok_or_else(|| format!("Archive segment {blob} is missing"))?;
+        if blob_hash(&bytes) != blob {
+            return Err(format!("Archive segment {blob} doesn't match its hash"));
+        }
+        let entries: Vec<LedgerEntry> =
+            serde_json::from_slice(&bytes).map_err(|e| format!("Invalid archive segment {blob}: {e}"))?;
+        let entries = Arc::new(entries);
+        *self.cached.lock().unwrap() = Some((position, entries.clone()));
+        Ok(entries)
+    }
+}
+
+/// Build the `/api/ledger/archive` route listing the archive's segments
+pub fn router(archive: Arc<Archive>) -> Router {
+    Router::new()
+        .route("/api/ledger/archive", get(|State(archive): State<Arc<Archive>>| async move { Json(archive.segments()) }))
+        .with_state(archive)
+}
@@ -0,0 +1,423 @@
+//! Audit log of operational events.
+//!
+//! Peers connecting and disconnecting, entries accepted and rejected, and
+//! actions taken through the admin API are appended to the log as they
+//! happen. The log is kept apart from the ledger: it records what this node
+//! saw, not what the network agreed on. With `[audit] path` set, events are
+//! appended to that file as JSON lines and survive restarts, the file being
+//! rotated once it reaches `max_bytes`; otherwise the node keeps the most
+//! recent [`MEMORY_EVENTS`] in memory. Events are served to operators at
+//! `GET /admin/audit`, filtered by time range and event type.
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::warn;
+
+use crate::api::ApiError;
+
+/// Events kept in memory when the log isn't written to a file
+pub const MEMORY_EVENTS: usize = 10_000;
+
+/// Events `GET /admin/audit` returns unless asked for a different number
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// Size a log file grows to before it's rotated, unless set in the config
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Rotated log files kept, unless set in the config
+pub const DEFAULT_KEEP_FILES: usize = 4;
+
+/// The `[audit]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// File events are appended to; events are only kept in memory if unset
+    pub path: Option<PathBuf>,
+    /// Size in bytes the file grows to before it's moved to `<path>.1`
+    pub max_bytes: u64,
+    /// Rotated files kept as `<path>.1` (newest) to `<path>.<keep_files>`; older ones are deleted
+    pub keep_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { path: None, max_bytes: DEFAULT_MAX_BYTES, keep_files: DEFAULT_KEEP_FILES }
+    }
+}
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    /// A peer proved its identity and was connected
+    PeerConnected,
+    /// A peer's connection closed
+    PeerDisconnected,
+    /// An entry was added to the chain
+    EntryAccepted,
+    /// An entry from a client or peer failed validation
+    EntryRejected,
+    /// An operator changed something through the admin API
+    AdminAction,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::PeerConnected => "peer_connected",
+            AuditEventType::PeerDisconnected => "peer_disconnected",
+            AuditEventType::EntryAccepted => "entry_accepted",
+            AuditEventType::EntryRejected => "entry_rejected",
+            AuditEventType::AdminAction => "admin_action",
+        }
+    }
+}
+
+impl fmt::Display for AuditEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peer_connected" => Ok(AuditEventType::PeerConnected),
+            "peer_disconnected" => Ok(AuditEventType::PeerDisconnected),
+            "entry_accepted" => Ok(AuditEventType::EntryAccepted),
+            "entry_rejected" => Ok(AuditEventType::EntryRejected),
+            "admin_action" => Ok(AuditEventType::AdminAction),
+            other => Err(format!("Unknown audit event type: {other}")),
+        }
+    }
+}
+
+/// An event in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Position in the log, counting from 1
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub event: AuditEventType,
+    /// Peer the event is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// Entry the event is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_id: Option<String>,
+    /// Anything else worth knowing, like why an entry was rejected
+    #[serde(default, skip_serializing_if = "JsonValue::is_null")]
+    pub details: JsonValue,
+}
+
+impl AuditEvent {
+    /// An event of type `event`, numbered and timestamped when it's recorded
+    pub fn new(event: AuditEventType) -> Self {
+        Self {
+            seq: 0,
+            timestamp: Utc::now(),
+            event,
+            peer_id: None,
+            entry_id: None,
+            details: JsonValue::Null,
+        }
+    }
+
+    /// Name the peer the event is about
+    pub fn with_peer(mut self, peer_id: impl Into<String>) -> Self {
+        self.peer_id = Some(peer_id.into());
+        self
+    }
+
+    /// Name the entry the event is about
+    pub fn with_entry(mut self, entry_id: impl Into<String>) -> Self {
+        self.entry_id = Some(entry_id.into());
+        self
+    }
+
+    /// Attach details
+    pub fn with_details(mut self, details: JsonValue) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Filters for `GET /admin/audit`
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Only return events at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only return events before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Comma-separated event types to return, like `peer_connected,peer_disconnected`
+    pub event: Option<String>,
+    /// Maximum number of events to return, the most recent ones
+    pub limit: Option<usize>,
+}
+
+/// A parsed [`AuditQuery`]
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Event types to return; every type if empty
+    pub events: Vec<AuditEventType>,
+}
+
+impl AuditFilter {
+    /// Whether `event` passes the filter
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+            && (self.events.is_empty() || self.events.contains(&event.event))
+    }
+}
+
+impl TryFrom<&AuditQuery> for AuditFilter {
+    type Error = String;
+
+    fn try_from(query: &AuditQuery) -> Result<Self, Self::Error> {
+        let events = match &query.event {
+            Some(events) => events.split(',').map(str::trim).filter(|e| !e.is_empty()).map(str::parse).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self { since: query.since, until: query.until, events })
+    }
+}
+
+/// Where recorded events go
+struct AuditState {
+    /// Sequence number of the last event recorded
+    last_seq: u64,
+    /// Open log file, if the log is persisted
+    file: Option<File>,
+    /// Bytes written to `file`
+    size: u64,
+    /// Recent events, when the log isn't persisted
+    recent: VecDeque<AuditEvent>,
+}
+
+/// Append-only log of operational events
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    max_bytes: u64,
+    keep_files: usize,
+    state: Mutex<AuditState>,
+}
+
+impl AuditLog {
+    /// A log kept in memory, holding the most recent [`MEMORY_EVENTS`]
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            max_bytes: DEFAULT_MAX_BYTES,
+            keep_files: DEFAULT_KEEP_FILES,
+            state: Mutex::new(AuditState { last_seq: 0, file: None, size: 0, recent: VecDeque::new() }),
+        }
+    }
+
+    /// A log appended to the file at `path`, continuing the numbering of the events already in it
+    pub fn open(path: &Path) -> Result<Self, String> {
+        // Right after a rotation the current file is empty, and the last event is in the newest rotated one
+        let mut last_seq = 0;
+        for file in [path.to_path_buf(), rotated_path(path, 1)] {
+            if let Some(file) = open_existing(&file)? {
+                read_events(file, path, |event| last_seq = event.seq)?;
+            }
+            if last_seq > 0 {
+                break;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open audit log {}: {e}", path.display()))?;
+        let size = file.metadata().map_err(|e| format!("Failed to open audit log {}: {e}", path.display()))?.len();
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            max_bytes: DEFAULT_MAX_BYTES,
+            keep_files: DEFAULT_KEEP_FILES,
+            state: Mutex::new(AuditState { last_seq, file: Some(file), size, recent: VecDeque::new() }),
+        })
+    }
+
+    /// Rotate the file once it reaches `max_bytes`, keeping `keep_files` rotated files
+    pub fn with_rotation(mut self, max_bytes: u64, keep_files: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.keep_files = keep_files;
+        self
+    }
+
+    /// The log `config` asks for
+    pub fn from_config(config: &AuditConfig) -> Result<Self, String> {
+        match &config.path {
+            Some(path) => Ok(Self::open(path)?.with_rotation(config.max_bytes, config.keep_files)),
+            None => Ok(Self::in_memory()),
+        }
+    }
+
+    /// File the log is appended to, if it's persisted
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Number and timestamp an event and append it to the log
+    pub fn record(&self, mut event: AuditEvent) {
+        let mut state = self.state.lock().unwrap();
+        state.last_seq += 1;
+        event.seq = state.last_seq;
+        event.timestamp = Utc::now();
+
+        let Some(file) = state.file.as_mut() else {
+            if state.recent.len() == MEMORY_EVENTS {
+                state.recent.pop_front();
+            }
+            state.recent.push_back(event);
+            return;
+        };
+        let mut line = serde_json::to_vec(&event).expect("events serialize");
+        line.push(b'\n');
+        if let Err(e) = file.write_all(&line) {
+            warn!(seq = event.seq, event = event.event.as_str(), "Failed to write audit event: {e}");
+            return;
+        }
+        state.size += line.len() as u64;
+        if state.size >= self.max_bytes
+            && let Some(path) = &self.path
+            && let Err(e) = self.rotate(path, &mut state)
+        {
+            warn!(path = %path.display(), "Failed to rotate audit log: {e}");
+        }
+    }
+
+    /// Move the full log file to `<path>.1`, moving older ones a number up and dropping the oldest
+    fn rotate(&self, path: &Path, state: &mut AuditState) -> std::io::Result<()> {
+        if self.keep_files == 0 {
+            fs::remove_file(path)?;
+        } else {
+            fs::remove_file(rotated_path(path, self.keep_files)).ok();
+            for n in (1..self.keep_files).rev() {
+                let rotated = rotated_path(path, n);
+                if rotated.exists() {
+                    fs::rename(&rotated, rotated_path(path, n + 1))?;
+                }
+            }
+            fs::rename(path, rotated_path(path, 1))?;
+        }
+        state.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        state.size = 0;
+        Ok(())
+    }
+
+    /// The most recent `limit` events passing `filter`, oldest first.
+    ///
+    /// A persisted log is read from its files, so this blocks; call it off
+    /// the async workers.
+    pub fn query(&self, filter: &AuditFilter, limit: usize) -> Result<Vec<AuditEvent>, String> {
+        let mut matching = VecDeque::new();
+        let mut keep = |event: AuditEvent| {
+            if filter.matches(&event) {
+                if matching.len() == limit {
+                    matching.pop_front();
+                }
+                if limit > 0 {
+                    matching.push_back(event);
+                }
+            }
+        };
+        let Some(path) = &self.path else {
+            self.state.lock().unwrap().recent.iter().cloned().for_each(keep);
+            return Ok(matching.into());
+        };
+
+        // Open the files and note how much of each is written while holding
+        // the lock, then read them without it. Writers aren't held up, and
+        // neither a write landing halfway through nor a rotation is seen.
+        let files = {
+            let state = self.state.lock().unwrap();
+            let mut files = Vec::new();
+            for n in (1..=self.keep_files).rev() {
+                let rotated = rotated_path(path, n);
+                if let Some(file) = open_existing(&rotated)? {
+                    let len = file.metadata().map_err(|e| format!("Failed to read audit log {}: {e}", rotated.display()))?;
+                    files.push((file, len.len()));
+                }
+            }
+            if let Some(file) = open_existing(path)? {
+                files.push((file, state.size));
+            }
+            files
+        };
+        for (file, len) in files {
+            read_events(file.take(len), path, &mut keep)?;
+        }
+        Ok(matching.into())
+    }
+}
+
+/// Where the `n`th newest rotated file of the log at `path` is kept
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{n}"));
+    PathBuf::from(rotated)
+}
+
+/// Open a log file for reading, if it exists
+fn open_existing(path: &Path) -> Result<Option<File>, String> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read audit log {}: {e}", path.display())),
+    }
+}
+
+/// Pass each event in a log file to `each`, oldest first. Lines that
+/// can't be read, like one cut short by a crash, are skipped.
+fn read_events(file: impl Read, path: &Path, mut each: impl FnMut(AuditEvent)) -> Result<(), String> {
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Failed to read audit log {}: {e}", path.display()))?;
+        match serde_json::from_str(&line) {
+            Ok(event) => each(event),
+            Err(e) => warn!(path = %path.display(), "Skipping unreadable audit event: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Build the `GET /admin/audit` route, which [`crate::admin::router`] mounts with the admin routes
+pub fn router(audit: Arc<AuditLog>) -> Router {
+    Router::new().route("/admin/audit", get(get_audit_events)).with_state(audit)
+}
+
+async fn get_audit_events(
+    State(audit): State<Arc<AuditLog>>,
+    query: Result<Query<AuditQuery>, QueryRejection>,
+) -> Result<Json<Vec<AuditEvent>>, ApiError> {
+    let Query(query) = query?;
+    let filter = AuditFilter::try_from(&query).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+    let events = tokio::task::spawn_blocking(move || audit.query(&filter, limit))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(events))
+}
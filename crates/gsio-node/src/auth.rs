@@ -0,0 +1,221 @@
+//! Authentication for the client-facing `/api` routes and the root Socket.IO
+//! namespace.
+//!
+//! Clients present a bearer credential: either a static API key from the
+//! `[auth]` section of the config file, or a session token obtained by signing
+//! a challenge with one of the ed25519 keys listed there. HTTP requests carry
+//! it in the `Authorization: Bearer` header, Socket.IO connections as
+//! `{ "token": ... }` in the connect auth data.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{rejection::JsonRejection, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use gsio_types::parse_public_key;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::api::ApiError;
+
+/// How long a challenge can be answered for
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(60);
+/// How long a session token stays valid
+pub const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+/// Most challenges left unanswered at once; issuing another drops the oldest
+pub const MAX_CHALLENGES: usize = 10_000;
+
+/// The `[auth]` section of the config file; authentication is off when both lists are empty
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Keys clients may send as bearer credentials
+    pub api_keys: Vec<String>,
+    /// Hex-encoded ed25519 public keys allowed to log in with a signed challenge
+    pub authorized_keys: Vec<String>,
+}
+
+impl AuthConfig {
+    /// Whether clients have to authenticate
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || !self.authorized_keys.is_empty()
+    }
+}
+
+/// Challenge issued by `POST /api/auth/challenge`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    pub challenge: String,
+    /// Seconds left to answer it
+    pub expires_in: u64,
+}
+
+/// Signed challenge sent to `POST /api/auth/token`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRequest {
+    /// Hex-encoded ed25519 public key
+    pub public_key: String,
+    pub challenge: String,
+    /// Hex-encoded signature over the challenge string
+    pub signature: String,
+}
+
+/// Session token returned by `POST /api/auth/token`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionToken {
+    pub token: String,
+    /// Seconds until the token expires
+    pub expires_in: u64,
+}
+
+/// Checks the credentials clients present and hands out session tokens
+#[derive(Debug)]
+pub struct Authenticator {
+    api_keys: HashSet<String>,
+    authorized_keys: Vec<VerifyingKey>,
+    /// Outstanding challenges and when they were issued
+    challenges: Mutex<HashMap<String, Instant>>,
+    /// Session tokens and when they expire
+    tokens: Mutex<HashMap<String, Instant>>,
+}
+
+impl Authenticator {
+    /// Create an authenticator for the keys in `config`
+    pub fn new(config: &AuthConfig) -> Result<Self, String> {
+        let authorized_keys = config
+            .authorized_keys
+            .iter()
+            .map(|key| parse_public_key(key))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            api_keys: config.api_keys.iter().cloned().collect(),
+            authorized_keys,
+            challenges: Mutex::new(HashMap::new()),
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Issue a challenge for a client to sign
+    pub fn issue_challenge(&self) -> Challenge {
+        let challenge = random_hex();
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, issued| issued.elapsed() < CHALLENGE_TTL);
+        // Anyone may ask for challenges, so they can't be allowed to pile up
+        if challenges.len() >= MAX_CHALLENGES
+            && let Some(oldest) = challenges.iter().min_by_key(|(_, issued)| **issued).map(|(c, _)| c.clone())
+        {
+            challenges.remove(&oldest);
+        }
+        challenges.insert(challenge.clone(), Instant::now());
+
+        Challenge {
+            challenge,
+            expires_in: CHALLENGE_TTL.as_secs(),
+        }
+    }
+
+    /// Exchange a challenge signed by an authorized key for a session token.
+    ///
+    /// Each challenge can only be answered once.
+    pub fn issue_token(&self, request: &TokenRequest) -> Result<SessionToken, String> {
+        let issued = self.challenges.lock().unwrap().remove(&request.challenge);
+        if issued.is_none_or(|issued| issued.elapsed() >= CHALLENGE_TTL) {
+            return Err("Unknown or expired challenge".to_string());
+        }
+
+        let key = parse_public_key(&request.public_key)?;
+        if !self.authorized_keys.contains(&key) {
+            return Err("Public key is not authorized".to_string());
+        }
+        let signature = hex::decode(&request.signature)
+            .ok()
+            .and_then(|b| Signature::from_slice(&b).ok())
+            .ok_or("Invalid signature encoding")?;
+        key.verify(request.challenge.as_bytes(), &signature)
+            .map_err(|_| "Signature doesn't match the challenge")?;
+
+        let token = random_hex();
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, expires| *expires > Instant::now());
+        tokens.insert(token.clone(), Instant::now() + TOKEN_TTL);
+
+        Ok(SessionToken {
+            token,
+            expires_in: TOKEN_TTL.as_secs(),
+        })
+    }
+
+    /// Whether `credential` is an API key or a live session token
+    pub fn is_authorized(&self, credential: &str) -> bool {
+        self.api_keys.contains(credential)
+            || self
+                .tokens
+                .lock()
+                .unwrap()
+                .get(credential)
+                .is_some_and(|expires| *expires > Instant::now())
+    }
+
+    /// Check the auth data a Socket.IO client connected with
+    pub fn check_socket_auth(&self, data: &JsonValue) -> Result<(), String> {
+        match data.get("token").and_then(JsonValue::as_str) {
+            Some(token) if self.is_authorized(token) => Ok(()),
+            Some(_) => Err("Invalid credentials".to_string()),
+            None => Err("Authentication required".to_string()),
+        }
+    }
+}
+
+/// Build the `/api/auth` routes clients log in with; these are left unprotected
+pub fn router(auth: Arc<Authenticator>) -> Router {
+    Router::new()
+        .route("/api/auth/challenge", post(issue_challenge))
+        .route("/api/auth/token", post(issue_token))
+        .with_state(auth)
+}
+
+/// Middleware rejecting requests without a valid bearer credential
+pub async fn require_auth(State(auth): State<Arc<Authenticator>>, request: Request, next: Next) -> Response {
+    let credential = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match credential {
+        Some(credential) if auth.is_authorized(credential) => next.run(request).await,
+        Some(_) => ApiError::new(StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
+        None => ApiError::new(StatusCode::UNAUTHORIZED, "Authentication required").into_response(),
+    }
+}
+
+async fn issue_challenge(State(auth): State<Arc<Authenticator>>) -> Json<Challenge> {
+    Json(auth.issue_challenge())
+}
+
+async fn issue_token(
+    State(auth): State<Arc<Authenticator>>,
+    request: Result<Json<TokenRequest>, JsonRejection>,
+) -> Result<Json<SessionToken>, ApiError> {
+    let Json(request) = request?;
+    auth.issue_token(&request)
+        .map(Json)
+        .map_err(|e| ApiError::new(StatusCode::UNAUTHORIZED, e))
+}
+
+/// 32 random bytes, hex-encoded
+pub(crate) fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
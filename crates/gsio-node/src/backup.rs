@@ -0,0 +1,243 @@
+//! Backing a node up and restoring it.
+//!
+//! `GET /admin/backup` packs what a node needs to come back as itself into a
+//! zstd-compressed tarball: a manifest, the node's key when it's kept in a
+//! file, an [export](crate::export) of the chain with offloaded data inlined,
+//! so the blobs the chain refers to come along, and the URLs of the nodes it
+//! has contacts for. The chain is exported as of the tip when the backup
+//! starts, while the node keeps running.
+//!
+//! `gsio-node backup` fetches such an archive, and `gsio-node restore` puts
+//! its key and ledger where a stopped node's `node_key` and `import` settings
+//! point, so the node starts from them next time.
+
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::SigningKey;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::{self, NodeConfig};
+use crate::export::{self, ExportHeader};
+use crate::p2p::P2PManager;
+
+/// Value of `format` in a backup's manifest
+pub const BACKUP_FORMAT: &str = "gsio-backup";
+
+/// Version of the backup format written by this node
+pub const BACKUP_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const KEY_FILE: &str = "node_key";
+const LEDGER_FILE: &str = "ledger.ndjson";
+const PEERS_FILE: &str = "peers.json";
+
+/// What a backup holds, stored as its first file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Always [`BACKUP_FORMAT`]
+    pub format: String,
+    /// Version of the format the backup is written in
+    pub version: u32,
+    /// Node the backup was taken from
+    pub node_id: String,
+    /// Height of the chain in the backup
+    pub height: usize,
+    /// When the backup was taken
+    pub created_at: DateTime<Utc>,
+    /// Whether the node's key is in the backup
+    pub has_key: bool,
+}
+
+/// The contents of a backup archive
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub manifest: BackupManifest,
+    /// The node's key, if it was kept in a file
+    pub key: Option<SigningKey>,
+    /// Export of the chain, offloaded data included
+    pub ledger: Vec<u8>,
+    /// URLs of the nodes the node had contacts for
+    pub peers: Vec<String>,
+}
+
+impl Backup {
+    /// Take a backup of the node behind `p2p`, whose key is kept in `key_file`
+    pub async fn take(p2p: Arc<P2PManager>, key_file: Option<&Path>) -> Result<Self, String> {
+        let key = match key_file {
+            Some(path) => {
+                let contents = tokio::fs::read(path).await.map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+                Some(parse_key(&contents).ok_or_else(|| format!("Invalid node key in {}", path.display()))?)
+            }
+            None => None,
+        };
+
+        let mut ledger = Vec::new();
+        let mut export = Box::pin(export::export(p2p.clone(), true)?);
+        while let Some(chunk) = export.next().await {
+            ledger.extend_from_slice(&chunk?);
+        }
+        let header = ledger.split(|b| *b == b'\n').next().unwrap_or_default();
+        let header: ExportHeader = serde_json::from_slice(header).map_err(|e| e.to_string())?;
+
+        let peers = p2p.contacts().all().into_iter().filter_map(|contact| contact.url).collect();
+        let manifest = BackupManifest {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            node_id: p2p.node_id().to_string(),
+            height: header.height,
+            created_at: Utc::now(),
+            has_key: key.is_some(),
+        };
+        info!(height = manifest.height, has_key = manifest.has_key, "Took a backup");
+        Ok(Self { manifest, key, ledger, peers })
+    }
+
+    /// Pack the backup into a `.tar.zst` archive
+    pub fn to_archive(&self) -> Result<Vec<u8>, String> {
+        let mut files = vec![
+            (MANIFEST_FILE, serde_json::to_vec(&self.manifest).map_err(|e| e.to_string())?, 0o644),
+            (LEDGER_FILE, self.ledger.clone(), 0o644),
+            (PEERS_FILE, serde_json::to_vec(&self.peers).map_err(|e| e.to_string())?, 0o644),
+        ];
+        if let Some(key) = &self.key {
+            files.push((KEY_FILE, hex::encode(key.to_bytes()).into_bytes(), 0o600));
+        }
+
+        let encoder = zstd::Encoder::new(Vec::new(), 0).map_err(|e| e.to_string())?;
+        let mut archive = tar::Builder::new(encoder);
+        for (name, contents, mode) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(mode);
+            header.set_mtime(self.manifest.created_at.timestamp().max(0) as u64);
+            header.set_cksum();
+            archive.append_data(&mut header, name, contents.as_slice()).map_err(|e| e.to_string())?;
+        }
+        archive.into_inner().and_then(|encoder| encoder.finish()).map_err(|e| e.to_string())
+    }
+
+    /// Read a backup from a `.tar.zst` archive
+    pub fn from_archive(archive: impl Read) -> Result<Self, String> {
+        let invalid = |e: std::io::Error| format!("Invalid backup: {e}");
+        let decoder = zstd::Decoder::new(archive).map_err(invalid)?;
+        let mut archive = tar::Archive::new(decoder);
+
+        let (mut manifest, mut key, mut ledger, mut peers) = (None, None, None, Vec::new());
+        for file in archive.entries().map_err(invalid)? {
+            let mut file = file.map_err(invalid)?;
+            let name = file.path().map_err(invalid)?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).map_err(invalid)?;
+            match name.as_str() {
+                MANIFEST_FILE => {
+                    let parsed: BackupManifest =
+                        serde_json::from_slice(&contents).map_err(|e| format!("Invalid backup manifest: {e}"))?;
+                    if parsed.format != BACKUP_FORMAT || parsed.version != BACKUP_VERSION {
+                        return Err(format!("Unsupported backup format {} version {}", parsed.format, parsed.version));
+                    }
+                    manifest = Some(parsed);
+                }
+                KEY_FILE => key = Some(parse_key(&contents).ok_or("Invalid node key in the backup")?),
+                LEDGER_FILE => ledger = Some(contents),
+                PEERS_FILE => peers = serde_json::from_slice(&contents).map_err(|e| format!("Invalid peer list: {e}"))?,
+                _ => return Err(format!("Unexpected file {name} in the backup")),
+            }
+        }
+
+        let manifest = manifest.ok_or("The backup has no manifest")?;
+        let ledger = ledger.ok_or("The backup has no ledger")?;
+        if manifest.has_key != key.is_some() {
+            return Err("The backup's node key doesn't match its manifest".to_string());
+        }
+        Ok(Self { manifest, key, ledger, peers })
+    }
+}
+
+/// URL the node configured by `config` serves its admin API at, on this machine
+pub fn node_url(config: &NodeConfig) -> String {
+    let scheme = if config.tls.is_enabled() { "https" } else { "http" };
+    let mut address = config.listen_address;
+    if address.ip().is_unspecified() {
+        address = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), address.port());
+    }
+    format!("{scheme}://{address}")
+}
+
+/// Download a backup from the node at `url` into `out`, authenticating with an admin key.
+///
+/// The archive is checked before it's written, and written beside `out`
+/// first, so `out` is either left alone or holds the whole backup.
+pub async fn fetch(url: &str, admin_key: &str, out: &Path) -> Result<BackupManifest, String> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/backup", url.trim_end_matches('/')))
+        .bearer_auth(admin_key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {url}: {e}"))?;
+    let status = response.status();
+    let archive = response.bytes().await.map_err(|e| format!("Failed to download the backup: {e}"))?;
+    if !status.is_success() {
+        return Err(format!("The node refused the backup with {status}: {}", String::from_utf8_lossy(&archive)));
+    }
+    let backup = Backup::from_archive(archive.as_ref())?;
+
+    let partial = staged(out);
+    std::fs::write(&partial, &archive).map_err(|e| format!("Failed to write {}: {e}", partial.display()))?;
+    std::fs::rename(&partial, out).map_err(|e| format!("Failed to write {}: {e}", out.display()))?;
+    Ok(backup.manifest)
+}
+
+/// Restore a backup for the node configured by `config`, which shouldn't be running.
+///
+/// The ledger is written to the `import` file and the key, if the backup has
+/// one, to the `node_key` file, so the node loads both when it next starts.
+/// Existing files are only replaced with `force`. Every file is written
+/// beside its destination before any is moved into place.
+pub fn restore(archive: &Path, config: &NodeConfig, force: bool) -> Result<Backup, String> {
+    let file = std::fs::File::open(archive).map_err(|e| format!("Failed to open {}: {e}", archive.display()))?;
+    let backup = Backup::from_archive(std::io::BufReader::new(file))?;
+
+    let import = config.import.as_ref().ok_or("Set import to the file the ledger should be restored to")?;
+    let key_file = match (&backup.key, &config.node_key) {
+        (Some(_), None) => {
+            return Err("The backup holds a node key; set node_key to the file it should be restored to".to_string());
+        }
+        (Some(_), Some(path)) => Some(path),
+        (None, _) => None,
+    };
+    if !force && let Some(path) = [Some(import), key_file].into_iter().flatten().find(|path| path.exists()) {
+        return Err(format!("{} already exists; restore with --force to replace it", path.display()));
+    }
+
+    let staged_ledger = staged(import);
+    std::fs::write(&staged_ledger, &backup.ledger)
+        .map_err(|e| format!("Failed to write {}: {e}", staged_ledger.display()))?;
+    if let (Some(key), Some(path)) = (&backup.key, key_file) {
+        let staged_key = staged(path);
+        config::save_key(&staged_key, key)?;
+        std::fs::rename(&staged_key, path).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    }
+    std::fs::rename(&staged_ledger, import).map_err(|e| format!("Failed to write {}: {e}", import.display()))?;
+
+    info!(node_id = backup.manifest.node_id, height = backup.manifest.height, "Restored a backup");
+    Ok(backup)
+}
+
+/// Read a hex-encoded secret key, as kept in a key file
+fn parse_key(contents: &[u8]) -> Option<SigningKey> {
+    let bytes: [u8; 32] = hex::decode(String::from_utf8_lossy(contents).trim()).ok()?.try_into().ok()?;
+    Some(SigningKey::from_bytes(&bytes))
+}
+
+/// Where a file is written before it's moved to `path`
+fn staged(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
@@ -0,0 +1,124 @@
+//! Bandwidth limits on ledger sync traffic.
+//!
+//! Syncing a long chain moves far more data than anything else a node does,
+//! enough to saturate a constrained link. Sync pages sent to peers count as
+//! upload and sync pages received from them as download. Each direction has
+//! a token bucket of bytes shared by all peers and one for each peer, each
+//! holding a second's worth of bytes and refilling at its rate. Rather than
+//! refusing a page, the node [reserves](Bandwidth::reserve) its bytes, which
+//! may leave a bucket owing, and waits until the debt would be paid off
+//! before sending the page or asking for the next one, so the average rate
+//! holds however large pages are.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// Number of peer buckets above which those that have filled up again are dropped
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// The `[bandwidth]` section of the config file, in bytes per second, where 0 means no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthConfig {
+    /// Sync bytes sent to all peers together
+    pub upload: u64,
+    /// Sync bytes received from all peers together
+    pub download: u64,
+    /// Sync bytes sent to each peer
+    pub peer_upload: u64,
+    /// Sync bytes received from each peer
+    pub peer_download: u64,
+}
+
+impl BandwidthConfig {
+    /// Whether any traffic is limited
+    pub fn is_enabled(&self) -> bool {
+        self.upload > 0 || self.download > 0 || self.peer_upload > 0 || self.peer_download > 0
+    }
+}
+
+/// Which way sync traffic flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Bytes that may be moved right away; negative while reservations are owed
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Take `bytes` from a bucket filling at `rate`, returning how long until it's out of debt
+    fn reserve(bucket: Option<Bucket>, rate: u64, bytes: usize, now: Instant) -> (Bucket, Duration) {
+        let rate = rate as f64;
+        let bucket = bucket.unwrap_or(Bucket { tokens: rate, updated: now });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        let tokens = (bucket.tokens + elapsed * rate).min(rate) - bytes as f64;
+        let wait = if tokens < 0.0 { Duration::from_secs_f64(-tokens / rate) } else { Duration::ZERO };
+        (Bucket { tokens, updated: now }, wait)
+    }
+}
+
+/// Token buckets for sync traffic, overall and per peer
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+    config: BandwidthConfig,
+    global: Mutex<HashMap<Direction, Bucket>>,
+    peers: Mutex<HashMap<(String, Direction), Bucket>>,
+}
+
+impl Bandwidth {
+    /// Create buckets for the limits in `config`
+    pub fn new(config: BandwidthConfig) -> Self {
+        Self { config, ..Self::default() }
+    }
+
+    /// Whether traffic in `direction` is limited at all
+    pub fn is_limited(&self, direction: Direction) -> bool {
+        let (global, peer) = self.rates(direction);
+        global > 0 || peer > 0
+    }
+
+    /// Reserve `bytes` of traffic in `direction` with `peer_id`, returning
+    /// how long to wait before moving them to stay within the limits
+    pub fn reserve(&self, peer_id: &str, direction: Direction, bytes: usize) -> Duration {
+        let (global_rate, peer_rate) = self.rates(direction);
+        let now = Instant::now();
+        let mut wait = Duration::ZERO;
+        if global_rate > 0 {
+            let mut global = self.global.lock().unwrap();
+            let (bucket, global_wait) = Bucket::reserve(global.get(&direction).copied(), global_rate, bytes, now);
+            global.insert(direction, bucket);
+            wait = wait.max(global_wait);
+        }
+        if peer_rate > 0 {
+            let mut peers = self.peers.lock().unwrap();
+            if peers.len() > MAX_IDLE_BUCKETS {
+                peers.retain(|(_, direction), bucket| {
+                    let rate = self.rates(*direction).1 as f64;
+                    bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * rate < rate
+                });
+            }
+            let key = (peer_id.to_string(), direction);
+            let (bucket, peer_wait) = Bucket::reserve(peers.get(&key).copied(), peer_rate, bytes, now);
+            peers.insert(key, bucket);
+            wait = wait.max(peer_wait);
+        }
+        wait
+    }
+
+    /// Overall and per-peer rate in `direction`
+    fn rates(&self, direction: Direction) -> (u64, u64) {
+        match direction {
+            Direction::Upload => (self.config.upload, self.config.peer_upload),
+            Direction::Download => (self.config.download, self.config.peer_download),
+        }
+    }
+}
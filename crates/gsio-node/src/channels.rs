@@ -0,0 +1,127 @@
+//! Channels: separate ledgers hosted by one node.
+//!
+//! Each channel named in the `channels` setting gets its own chain and
+//! known-node set, managed by its own [`P2PManager`], so independent
+//! applications can share a deployment without their entries mixing. A
+//! channel's ledger is served under `/api/channels/<name>` with the same
+//! routes as the main ledger, and peers sync it over the `/p2p/<name>`
+//! Socket.IO namespace, which only nodes hosting the channel serve.
+//!
+//! Channels start with the main ledger's node key, consensus, retention,
+//! validation and schemas, and sync with the bootstrap peers. Writes to a
+//! channel can be restricted with a [write ACL](crate::acl). They don't
+//! offload entry data, archive pruned entries, or use peer discovery or the
+//! relay.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api;
+use crate::p2p::P2PManager;
+
+/// Longest name a channel may have
+pub const MAX_CHANNEL_NAME_LEN: usize = 64;
+
+/// Check that `name` can be used as a channel name: lowercase ASCII letters,
+/// digits, `-` and `_`, at most [`MAX_CHANNEL_NAME_LEN`] long
+pub fn validate_channel_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_CHANNEL_NAME_LEN {
+        return Err(format!("Channel name must be 1 to {MAX_CHANNEL_NAME_LEN} characters long"));
+    }
+    if !name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_') {
+        return Err(format!("Channel name {name:?} may only hold lowercase letters, digits, '-' and '_'"));
+    }
+    Ok(())
+}
+
+/// A channel as listed by `GET /api/channels`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    /// Name of the channel
+    pub name: String,
+    /// Height of the channel's chain
+    pub height: usize,
+    /// Nodes the channel's ledger knows
+    pub known_nodes: usize,
+    /// Hex-encoded keys allowed to write to the channel, if writes are restricted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writers: Option<Vec<String>>,
+}
+
+/// The channels a node hosts, by name
+#[derive(Default)]
+pub struct Channels {
+    channels: BTreeMap<String, Arc<P2PManager>>,
+}
+
+impl Channels {
+    /// Create an empty set of channels
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the channel managed by `p2p`, which has to have been given a
+    /// channel name no other channel has
+    pub fn insert(&mut self, p2p: Arc<P2PManager>) -> Result<(), String> {
+        let name = p2p.channel().ok_or("Only a channel's P2P manager can be added")?.to_string();
+        validate_channel_name(&name)?;
+        if self.channels.contains_key(&name) {
+            return Err(format!("Channel {name} is configured twice"));
+        }
+        self.channels.insert(name, p2p);
+        Ok(())
+    }
+
+    /// The manager of the channel called `name`
+    pub fn get(&self, name: &str) -> Option<&Arc<P2PManager>> {
+        self.channels.get(name)
+    }
+
+    /// Managers of every channel, by channel name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<P2PManager>)> {
+        self.channels.iter().map(|(name, p2p)| (name.as_str(), p2p))
+    }
+
+    /// Number of channels
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Whether the node hosts no channels
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Each channel's name, height, number of known nodes and writers
+    pub fn info(&self) -> Vec<ChannelInfo> {
+        self.iter()
+            .map(|(name, p2p)| ChannelInfo {
+                name: name.to_string(),
+                height: p2p.ledger.chain_tip().height,
+                known_nodes: p2p.ledger.get_known_nodes().len(),
+                writers: p2p.ledger.write_acl().map(|acl| acl.writers()),
+            })
+            .collect()
+    }
+
+    /// Say goodbye to every channel's peers
+    pub async fn leave(&self) {
+        for p2p in self.channels.values() {
+            p2p.leave().await;
+        }
+    }
+}
+
+/// Build `GET /api/channels` and each channel's ledger routes under `/api/channels/<name>`
+pub fn router(channels: Arc<Channels>) -> Router {
+    let mut router = Router::new()
+        .route("/api/channels", get(|State(channels): State<Arc<Channels>>| async move { Json(channels.info()) }))
+        .with_state(channels.clone());
+    for (name, p2p) in channels.iter() {
+        router = router.nest(&format!("/api/channels/{name}"), api::ledger_routes().with_state(p2p.clone()));
+    }
+    router
+}
@@ -0,0 +1,194 @@
+//! Encodings for P2P messages on direct connections.
+//!
+//! Messages travel as JSON unless both sides of a connection opt into
+//! msgpack and agree on it during the handshake, in which case they are sent
+//! as Socket.IO binary attachments. A message is always read according to how it arrived,
+//! so a JSON message on a msgpack connection, like a handshake rejection, is
+//! still understood.
+//!
+//! The Socket.IO client nodes dial with splits every frame it receives on the
+//! Engine.IO record separator, binary ones included, so msgpack frames are
+//! byte-stuffed to keep that byte out of them: `0x1e` is sent as `0x1f 0x01`
+//! and `0x1f` as `0x1f 0x02`. Stuffed frames aren't plain msgpack, so only
+//! gsio nodes read them, and JSON stays the default.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value as JsonValue;
+
+use crate::p2p::P2PMessage;
+
+/// Engine.IO's record separator, which never appears in a binary frame
+const SEPARATOR: u8 = 0x1e;
+
+/// Marks a stuffed byte in a binary frame
+const ESCAPE: u8 = 0x1f;
+
+/// How messages on a connection are encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// JSON text, which every node reads
+    #[default]
+    Json,
+    /// msgpack, keyed like the JSON
+    Msgpack,
+}
+
+impl Codec {
+    /// Encode a message for the wire
+    pub fn encode(self, message: &P2PMessage) -> Result<Frame, String> {
+        match self {
+            Codec::Json => serde_json::to_value(message).map(Frame::Json).map_err(|e| e.to_string()),
+            Codec::Msgpack => {
+                let value = rmpv::ext::to_value(message).map_err(|e| e.to_string())?;
+                let mut bytes = Vec::new();
+                rmpv::encode::write_value(&mut bytes, &value).map_err(|e| e.to_string())?;
+                Ok(Frame::Binary(stuff(&bytes)))
+            }
+        }
+    }
+
+    /// The codecs a node preferring `self` offers, most preferred first
+    pub fn offered(self) -> Vec<Codec> {
+        match self {
+            Codec::Json => vec![Codec::Json],
+            Codec::Msgpack => vec![Codec::Msgpack, Codec::Json],
+        }
+    }
+
+    /// The codec to use with a peer that offered `codecs` in its handshake
+    /// data. Peers that offer none, or none this node reads, get JSON.
+    pub fn negotiate(self, codecs: Option<&JsonValue>) -> Codec {
+        let Some(JsonValue::Array(codecs)) = codecs else {
+            return Codec::Json;
+        };
+        let mut offered = codecs.iter().filter_map(|codec| serde_json::from_value::<Codec>(codec.clone()).ok());
+        offered.find(|codec| self.offered().contains(codec)).unwrap_or(Codec::Json)
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Json => write!(f, "json"),
+            Codec::Msgpack => write!(f, "msgpack"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Codec::Json),
+            "msgpack" => Ok(Codec::Msgpack),
+            other => Err(format!("Unknown codec: {other}")),
+        }
+    }
+}
+
+/// A message as it travels over a Socket.IO connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// A JSON object
+    Json(JsonValue),
+    /// A msgpack-encoded message, byte-stuffed and sent as a binary attachment
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    /// Bytes the message in the frame takes on the wire
+    pub fn size(&self) -> usize {
+        match self {
+            Frame::Json(value) => value.to_string().len(),
+            Frame::Binary(bytes) => bytes.len(),
+        }
+    }
+
+    /// Read the message in the frame, whichever codec it was encoded with
+    pub fn decode(self) -> Result<P2PMessage, String> {
+        match self {
+            Frame::Json(value) => serde_json::from_value(value).map_err(|e| e.to_string()),
+            Frame::Binary(bytes) => {
+                let bytes = unstuff(&bytes)?;
+                let value = rmpv::decode::read_value(&mut bytes.as_slice()).map_err(|e| e.to_string())?;
+                rmpv::ext::from_value(value).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Replace the bytes Engine.IO would split a frame on
+fn stuff(bytes: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            SEPARATOR => stuffed.extend([ESCAPE, 0x01]),
+            ESCAPE => stuffed.extend([ESCAPE, 0x02]),
+            byte => stuffed.push(byte),
+        }
+    }
+    stuffed
+}
+
+/// Undo [`stuff`]
+fn unstuff(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut unstuffed = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ESCAPE => match bytes.next() {
+                Some(0x01) => unstuffed.push(SEPARATOR),
+                Some(0x02) => unstuffed.push(ESCAPE),
+                _ => return Err("Binary frame has a malformed escape".to_string()),
+            },
+            byte => unstuffed.push(byte),
+        }
+    }
+    Ok(unstuffed)
+}
+
+impl Serialize for Frame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Frame::Json(value) => value.serialize(serializer),
+            // Socket.IO sends bytes as a binary attachment
+            Frame::Binary(bytes) => serializer.serialize_bytes(bytes),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Frame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FrameVisitor;
+
+        impl<'de> Visitor<'de> for FrameVisitor {
+            type Value = Frame;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a message object or binary attachment")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Frame, E> {
+                Ok(Frame::Binary(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Frame, E> {
+                Ok(Frame::Binary(bytes))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Frame, A::Error> {
+                JsonValue::deserialize(MapAccessDeserializer::new(map)).map(Frame::Json)
+            }
+        }
+
+        deserializer.deserialize_any(FrameVisitor)
+    }
+}
+
@@ -0,0 +1,792 @@
+//! Node configuration, loaded from a TOML file, environment variables and
+//! command-line flags, in increasing order of precedence.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Deserializer};
+
+use crate::acl::AclConfig;
+use crate::admin::AdminConfig;
+use crate::archive::ArchiveConfig;
+use crate::audit::AuditConfig;
+use crate::auth::AuthConfig;
+use crate::bandwidth::BandwidthConfig;
+use crate::codec::Codec;
+use crate::consensus::{ConsensusConfig, ConsensusStrategy};
+use crate::discovery::DiscoveryConfig;
+use crate::fees::FeeConfig;
+use crate::gc::BlobGcConfig;
+use crate::genesis::Genesis;
+use crate::ledger::{LedgerMode, Limits, RetentionPolicy};
+use crate::offload::OffloadConfig;
+use crate::mempool::MempoolConfig;
+use crate::p2p::NodeMode;
+use crate::pex::PeerExchangeConfig;
+use crate::ratelimit::RateLimitConfig;
+use crate::rendezvous::RendezvousConfig;
+use crate::schema::SchemaConfig;
+use crate::staking::StakingConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::tls::TlsConfig;
+use crate::validation::ValidationConfig;
+
+/// Command-line flags; any flag given overrides the file and environment
+#[derive(Debug, Default, Parser)]
+#[command(name = "gsio-node", about = "GSIO-Net distributed ledger node")]
+pub struct Cli {
+    /// Path to a TOML configuration file
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// Address the HTTP and Socket.IO server listens on
+    #[arg(long)]
+    pub listen: Option<SocketAddr>,
+    /// Address the gRPC server listens on
+    #[arg(long)]
+    pub grpc: Option<SocketAddr>,
+    /// PEM certificate chain to serve HTTPS and gRPC over TLS with
+    #[arg(long)]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the TLS certificate
+    #[arg(long)]
+    pub tls_key: Option<PathBuf>,
+    /// Name to label the node with in logs; its ID is its public key
+    #[arg(long)]
+    pub name: Option<String>,
+    /// URL of the iroh relay
+    #[arg(long)]
+    pub relay: Option<String>,
+    /// Directory to keep blobs in instead of memory
+    #[arg(long)]
+    pub blob_path: Option<PathBuf>,
+    /// Node URL to connect to on startup; may be repeated
+    #[arg(long = "peer")]
+    pub peers: Vec<String>,
+    /// URL other nodes reach this node's HTTP server at
+    #[arg(long)]
+    pub public_url: Option<String>,
+    /// Iroh node ID to gossip with for peer discovery; may be repeated
+    #[arg(long = "discovery-peer")]
+    pub discovery_peers: Vec<String>,
+    /// WebSocket URL of a gsio-relay to reach peers through
+    #[arg(long)]
+    pub rendezvous_url: Option<String>,
+    /// Access token for the relay channel
+    #[arg(long)]
+    pub rendezvous_token: Option<String>,
+    /// Seconds between peer advertisements
+    #[arg(long)]
+    pub advertisement_interval: Option<u64>,
+    /// Seconds between retries of pending entries
+    #[arg(long)]
+    pub pending_retry_interval: Option<u64>,
+    /// Seconds between runs of the retention policy
+    #[arg(long)]
+    pub retention_interval: Option<u64>,
+    /// Seconds between peer exchange rounds; 0 turns peer exchange off
+    #[arg(long)]
+    pub peer_exchange_interval: Option<u64>,
+    /// Seconds a node may go unheard from and of before it counts as stale
+    #[arg(long)]
+    pub peer_stale_after: Option<u64>,
+    /// Seconds a node may go unheard from and of before it's forgotten; 0 keeps nodes forever
+    #[arg(long)]
+    pub peer_expiry: Option<u64>,
+    /// Peers the initial ledger sync fetches from at once; 0 syncs with each peer on its own
+    #[arg(long)]
+    pub sync_peers: Option<usize>,
+    /// Bytes per second of sync pages sent to all peers together
+    #[arg(long)]
+    pub upload_limit: Option<u64>,
+    /// Bytes per second of sync pages received from all peers together
+    #[arg(long)]
+    pub download_limit: Option<u64>,
+    /// `writer` or `follower`
+    #[arg(long)]
+    pub mode: Option<NodeMode>,
+    /// Writable node that followers redirect writes to
+    #[arg(long)]
+    pub writable_node: Option<String>,
+    /// `forever`, `days:<n>` or `last:<n>`
+    #[arg(long)]
+    pub retention: Option<RetentionPolicy>,
+    /// Archive entries to the blob store before retention prunes them
+    #[arg(long)]
+    pub archive: bool,
+    /// Name of a separate ledger to host alongside the main one; may be repeated
+    #[arg(long = "channel")]
+    pub channels: Vec<String>,
+    /// Encrypt messages to peers, refusing peers that won't
+    #[arg(long)]
+    pub p2p_encryption: bool,
+    /// `msgpack` or `json`; encoding offered to peers for direct connections
+    #[arg(long)]
+    pub p2p_codec: Option<Codec>,
+    /// File holding the node's signing key; created if missing
+    #[arg(long)]
+    pub node_key: Option<PathBuf>,
+    /// Genesis file the chain starts from
+    #[arg(long)]
+    pub genesis: Option<PathBuf>,
+    /// Network the node is on, e.g. mainnet or testnet
+    #[arg(long)]
+    pub chain_id: Option<String>,
+    /// `longest_chain` or `proof_of_authority`
+    #[arg(long)]
+    pub consensus: Option<ConsensusStrategy>,
+    /// Blob ticket of a ledger snapshot to start from
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+    /// Public key whose snapshots the node restores from, besides validators'; may be repeated
+    #[arg(long = "checkpoint-key")]
+    pub checkpoint_keys: Vec<String>,
+    /// Ledger export to load into the empty ledger on startup
+    #[arg(long)]
+    pub import: Option<PathBuf>,
+    /// Requests per second each client may make to the API
+    #[arg(long)]
+    pub rate_limit: Option<f64>,
+    /// Store entry data larger than this many bytes as a blob
+    #[arg(long)]
+    pub offload_threshold: Option<usize>,
+    /// Seconds between deletions of blobs no entry refers to
+    #[arg(long)]
+    pub blob_gc_interval: Option<u64>,
+    /// File to append the audit log to
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    /// OTLP/HTTP collector to export spans to
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+    /// Fee every transaction pays
+    #[arg(long)]
+    pub fee_base: Option<u64>,
+    /// Fee per byte of transaction data
+    #[arg(long)]
+    pub fee_per_byte: Option<u64>,
+    /// Fee client transactions have to pay to be queued
+    #[arg(long)]
+    pub min_fee: Option<u64>,
+    /// Run under the Windows service control manager
+    #[arg(long, hide = true)]
+    pub windows_service: bool,
+    /// Run a subcommand instead of the node
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands, which use the same settings as the node they act on
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Save a running node's key, ledger and peers to a `.tar.zst` archive through its admin API
+    Backup {
+        /// Archive to write
+        #[arg(long)]
+        out: PathBuf,
+        /// URL of the node's HTTP server; defaults to the listen address
+        #[arg(long)]
+        node: Option<String>,
+    },
+    /// Put a backup's key and ledger where a stopped node loads them from on startup
+    Restore {
+        /// Archive written by `backup`
+        #[arg(long)]
+        from: PathBuf,
+        /// Replace the key file and import file if they exist
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Settings a node starts with
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Address the HTTP and Socket.IO server listens on
+    pub listen_address: SocketAddr,
+    /// Address the gRPC server listens on
+    pub grpc_address: SocketAddr,
+    /// Certificate the HTTP and gRPC servers are served over TLS with
+    pub tls: TlsConfig,
+    /// Label for the node in logs; the node ID is its public key (see [`crate::identity`])
+    pub node_name: Option<String>,
+    /// URL of the iroh relay
+    pub relay_address: Option<String>,
+    /// Directory for the persistent blob store; blobs stay in memory if unset
+    pub blob_path: Option<PathBuf>,
+    /// Node URLs to connect to on startup
+    pub bootstrap_peers: Vec<String>,
+    /// URL other nodes reach this node's HTTP server at; the node isn't announced to them if unset
+    pub public_url: Option<String>,
+    /// How nodes find each other over iroh
+    pub discovery: DiscoveryConfig,
+    /// gsio-relay to reach peers through when they can't be reached directly
+    pub rendezvous: RendezvousConfig,
+    /// Seconds between peer advertisements
+    pub advertisement_interval: u64,
+    /// Seconds between retries of pending entries
+    pub pending_retry_interval: u64,
+    /// Seconds between runs of the retention policy
+    pub retention_interval: u64,
+    /// How nodes pass on the addresses of their peers
+    pub peer_exchange: PeerExchangeConfig,
+    /// Seconds a node may go unheard from and of before it counts as stale
+    pub peer_stale_after: u64,
+    /// Seconds a node may go unheard from and of before it's forgotten, or 0 to keep nodes forever
+    pub peer_expiry: u64,
+    /// Peers the initial ledger sync fetches from at once, or 0 to sync with each peer on its own
+    pub sync_peers: usize,
+    /// How fast sync pages are sent to and received from peers
+    pub bandwidth: BandwidthConfig,
+    #[serde(deserialize_with = "from_str")]
+    pub mode: NodeMode,
+    /// Writable node that followers redirect writes to
+    pub writable_node: Option<String>,
+    #[serde(deserialize_with = "from_str")]
+    pub retention: RetentionPolicy,
+    /// Whether entries retention prunes are archived first
+    pub archive: ArchiveConfig,
+    /// Names of the separate ledgers hosted alongside the main one
+    pub channels: Vec<String>,
+    /// Keys allowed to write to each channel that restricts writes, by channel name
+    pub acl: BTreeMap<String, AclConfig>,
+    /// Channels whose ledgers aren't chains, by channel name; see [`LedgerMode`]
+    pub channel_modes: BTreeMap<String, LedgerMode>,
+    /// Rules entry data has to satisfy
+    pub validation: ValidationConfig,
+    /// Entry kinds and the schemas their payloads must match
+    pub schemas: SchemaConfig,
+    /// Credentials clients must present
+    pub auth: AuthConfig,
+    /// Credentials operators use for the admin API
+    pub admin: AdminConfig,
+    /// Encrypt messages to peers, refusing peers that won't
+    pub p2p_encryption: bool,
+    /// Encoding offered to peers for direct connections; peers that don't read it are sent JSON
+    pub p2p_codec: Codec,
+    /// File holding the node's signing key; a new key is generated on every start if unset
+    pub node_key: Option<PathBuf>,
+    /// Genesis file the chain starts from; the chain links to the zero hash if unset
+    pub genesis: Option<PathBuf>,
+    /// Network the node is on; peers and entries from other networks are
+    /// refused. Defaults to the genesis chain ID.
+    pub chain_id: Option<String>,
+    /// Consensus strategy and proof-of-authority validators
+    pub consensus: ConsensusConfig,
+    /// Blob ticket of a ledger snapshot a new node starts from instead of syncing the whole chain
+    pub checkpoint: Option<String>,
+    /// Hex-encoded public keys trusted to sign snapshots, besides the validators'
+    pub checkpoint_keys: Vec<String>,
+    /// NDJSON export from `GET /api/ledger/export` the empty ledger is loaded from on startup
+    pub import: Option<PathBuf>,
+    /// How fast clients may send requests
+    pub rate_limit: RateLimitConfig,
+    /// How much each ledger takes in from clients and peers
+    pub limits: Limits,
+    /// Which entry data is stored as a blob instead of inline
+    pub offload: OffloadConfig,
+    /// When blobs no entry refers to are deleted
+    pub blob_gc: BlobGcConfig,
+    /// Fees transactions are asked to pay
+    pub fees: FeeConfig,
+    /// How long unstaked amounts stay locked
+    pub staking: StakingConfig,
+    /// Minimum fee of client transactions
+    pub mempool: MempoolConfig,
+    /// Where operational events are recorded
+    pub audit: AuditConfig,
+    /// Where spans are exported
+    pub telemetry: TelemetryConfig,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            grpc_address: SocketAddr::from(([0, 0, 0, 0], 50051)),
+            tls: TlsConfig::default(),
+            node_name: None,
+            relay_address: None,
+            blob_path: None,
+            bootstrap_peers: Vec::new(),
+            public_url: None,
+            discovery: DiscoveryConfig::default(),
+            rendezvous: RendezvousConfig::default(),
+            advertisement_interval: 30,
+            pending_retry_interval: 30,
+            retention_interval: 60,
+            peer_stale_after: 300,
+            peer_expiry: 86_400,
+            sync_peers: 4,
+            bandwidth: BandwidthConfig::default(),
+            peer_exchange: PeerExchangeConfig::default(),
+            mode: NodeMode::Writer,
+            writable_node: None,
+            retention: RetentionPolicy::KeepForever,
+            archive: ArchiveConfig::default(),
+            channels: Vec::new(),
+            acl: BTreeMap::new(),
+            channel_modes: BTreeMap::new(),
+            validation: ValidationConfig::default(),
+            schemas: SchemaConfig::default(),
+            auth: AuthConfig::default(),
+            admin: AdminConfig::default(),
+            p2p_encryption: false,
+            p2p_codec: Codec::default(),
+            node_key: None,
+            genesis: None,
+            chain_id: None,
+            consensus: ConsensusConfig::default(),
+            checkpoint: None,
+            checkpoint_keys: Vec::new(),
+            import: None,
+            rate_limit: RateLimitConfig::default(),
+            limits: Limits::default(),
+            offload: OffloadConfig::default(),
+            blob_gc: BlobGcConfig::default(),
+            fees: FeeConfig::default(),
+            staking: StakingConfig::default(),
+            mempool: MempoolConfig::default(),
+            audit: AuditConfig::default(),
+            telemetry: TelemetryConfig::default(),
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Load the configuration for a node started with `cli`.
+    ///
+    /// The file comes from `--config` or `GSIO_CONFIG`; without one the
+    /// defaults are used. Environment variables override the file and flags
+    /// override both.
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let path = cli.config.clone().or_else(|| std::env::var_os("GSIO_CONFIG").map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.apply_cli(cli);
+        Ok(config)
+    }
+
+    /// Read a TOML configuration file; missing settings keep their defaults
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Override settings from environment variables, looked up with `var`
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(address) = var("LISTEN_ADDRESS") {
+            self.listen_address = parse_var("LISTEN_ADDRESS", &address)?;
+        }
+        if let Some(address) = var("GRPC_ADDRESS") {
+            self.grpc_address = parse_var("GRPC_ADDRESS", &address)?;
+        }
+        if let Some(path) = var("TLS_CERT_PATH") {
+            self.tls.cert_path = Some(PathBuf::from(path));
+        }
+        if let Some(path) = var("TLS_KEY_PATH") {
+            self.tls.key_path = Some(PathBuf::from(path));
+        }
+        if let Some(path) = var("TLS_CLIENT_CA_PATH") {
+            self.tls.client_ca_path = Some(PathBuf::from(path));
+        }
+        if let Some(name) = var("NODE_NAME") {
+            self.node_name = Some(name);
+        }
+        if let Some(relay) = var("RELAY_ADDRESS") {
+            self.relay_address = Some(relay);
+        }
+        if let Some(path) = var("BLOB_PATH") {
+            self.blob_path = Some(PathBuf::from(path));
+        }
+        if let Some(peers) = var("PEERS") {
+            self.bootstrap_peers = split_list(&peers);
+        }
+        if let Some(url) = var("PUBLIC_URL") {
+            self.public_url = Some(url);
+        }
+        if let Some(peers) = var("DISCOVERY_PEERS") {
+            self.discovery.peers = split_list(&peers);
+        }
+        if let Some(url) = var("RENDEZVOUS_URL") {
+            self.rendezvous.url = Some(url);
+        }
+        if let Some(token) = var("RENDEZVOUS_TOKEN") {
+            self.rendezvous.token = Some(token);
+        }
+        if let Some(interval) = var("ADVERTISEMENT_INTERVAL") {
+            self.advertisement_interval = parse_var("ADVERTISEMENT_INTERVAL", &interval)?;
+        }
+        if let Some(interval) = var("PENDING_RETRY_INTERVAL") {
+            self.pending_retry_interval = parse_var("PENDING_RETRY_INTERVAL", &interval)?;
+        }
+        if let Some(interval) = var("RETENTION_INTERVAL") {
+            self.retention_interval = parse_var("RETENTION_INTERVAL", &interval)?;
+        }
+        if let Some(interval) = var("PEER_EXCHANGE_INTERVAL") {
+            self.peer_exchange.interval = parse_var("PEER_EXCHANGE_INTERVAL", &interval)?;
+        }
+        if let Some(seconds) = var("PEER_STALE_AFTER") {
+            self.peer_stale_after = parse_var("PEER_STALE_AFTER", &seconds)?;
+        }
+        if let Some(seconds) = var("PEER_EXPIRY") {
+            self.peer_expiry = parse_var("PEER_EXPIRY", &seconds)?;
+        }
+        if let Some(peers) = var("SYNC_PEERS") {
+            self.sync_peers = parse_var("SYNC_PEERS", &peers)?;
+        }
+        if let Some(rate) = var("BANDWIDTH_UPLOAD") {
+            self.bandwidth.upload = parse_var("BANDWIDTH_UPLOAD", &rate)?;
+        }
+        if let Some(rate) = var("BANDWIDTH_DOWNLOAD") {
+            self.bandwidth.download = parse_var("BANDWIDTH_DOWNLOAD", &rate)?;
+        }
+        if let Some(rate) = var("BANDWIDTH_PEER_UPLOAD") {
+            self.bandwidth.peer_upload = parse_var("BANDWIDTH_PEER_UPLOAD", &rate)?;
+        }
+        if let Some(rate) = var("BANDWIDTH_PEER_DOWNLOAD") {
+            self.bandwidth.peer_download = parse_var("BANDWIDTH_PEER_DOWNLOAD", &rate)?;
+        }
+        if let Some(mode) = var("NODE_MODE") {
+            self.mode = parse_var("NODE_MODE", &mode)?;
+        }
+        if let Some(url) = var("WRITABLE_NODE_URL") {
+            self.writable_node = Some(url);
+        }
+        if let Some(retention) = var("LEDGER_RETENTION") {
+            self.retention = parse_var("LEDGER_RETENTION", &retention)?;
+        }
+        if let Some(archive) = var("LEDGER_ARCHIVE") {
+            self.archive.enabled = parse_var("LEDGER_ARCHIVE", &archive)?;
+        }
+        if let Some(channels) = var("LEDGER_CHANNELS") {
+            self.channels = split_list(&channels);
+        }
+        if let Some(keys) = var("API_KEYS") {
+            self.auth.api_keys = split_list(&keys);
+        }
+        if let Some(keys) = var("ADMIN_API_KEYS") {
+            self.admin.api_keys = split_list(&keys);
+        }
+        if let Some(encryption) = var("P2P_ENCRYPTION") {
+            self.p2p_encryption = parse_var("P2P_ENCRYPTION", &encryption)?;
+        }
+        if let Some(codec) = var("P2P_CODEC") {
+            self.p2p_codec = parse_var("P2P_CODEC", &codec)?;
+        }
+        if let Some(path) = var("NODE_KEY_FILE") {
+            self.node_key = Some(PathBuf::from(path));
+        }
+        if let Some(path) = var("GENESIS_FILE") {
+            self.genesis = Some(PathBuf::from(path));
+        }
+        if let Some(chain_id) = var("CHAIN_ID") {
+            self.chain_id = Some(chain_id);
+        }
+        if let Some(strategy) = var("CONSENSUS") {
+            self.consensus.strategy = Some(parse_var("CONSENSUS", &strategy)?);
+        }
+        if let Some(ticket) = var("CHECKPOINT") {
+            self.checkpoint = Some(ticket);
+        }
+        if let Some(keys) = var("CHECKPOINT_KEYS") {
+            self.checkpoint_keys = split_list(&keys);
+        }
+        if let Some(path) = var("IMPORT_FILE") {
+            self.import = Some(PathBuf::from(path));
+        }
+        if let Some(rate) = var("RATE_LIMIT") {
+            self.rate_limit.requests_per_second = parse_var("RATE_LIMIT", &rate)?;
+        }
+        if let Some(threshold) = var("OFFLOAD_THRESHOLD") {
+            self.offload.threshold_bytes = parse_var("OFFLOAD_THRESHOLD", &threshold)?;
+        }
+        if let Some(interval) = var("BLOB_GC_INTERVAL") {
+            self.blob_gc.interval = parse_var("BLOB_GC_INTERVAL", &interval)?;
+        }
+        if let Some(grace) = var("BLOB_GC_GRACE") {
+            self.blob_gc.grace = parse_var("BLOB_GC_GRACE", &grace)?;
+        }
+        if let Some(fee) = var("FEE_BASE") {
+            self.fees.base = parse_var("FEE_BASE", &fee)?;
+        }
+        if let Some(fee) = var("FEE_PER_BYTE") {
+            self.fees.per_byte = parse_var("FEE_PER_BYTE", &fee)?;
+        }
+        if let Some(period) = var("UNBONDING_PERIOD") {
+            self.staking.unbonding_period = parse_var("UNBONDING_PERIOD", &period)?;
+        }
+        if let Some(fee) = var("MIN_FEE") {
+            self.mempool.min_fee = parse_var("MIN_FEE", &fee)?;
+        }
+        if let Some(path) = var("AUDIT_LOG") {
+            self.audit.path = Some(PathBuf::from(path));
+        }
+        if let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.telemetry.otlp_endpoint = Some(endpoint);
+        }
+        if let Some(name) = var("OTEL_SERVICE_NAME") {
+            self.telemetry.service_name = name;
+        }
+        Ok(())
+    }
+
+    /// Override settings with any flags given on the command line
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(address) = cli.listen {
+            self.listen_address = address;
+        }
+        if let Some(address) = cli.grpc {
+            self.grpc_address = address;
+        }
+        if let Some(path) = &cli.tls_cert {
+            self.tls.cert_path = Some(path.clone());
+        }
+        if let Some(path) = &cli.tls_key {
+            self.tls.key_path = Some(path.clone());
+        }
+        if let Some(name) = &cli.name {
+            self.node_name = Some(name.clone());
+        }
+        if let Some(relay) = &cli.relay {
+            self.relay_address = Some(relay.clone());
+        }
+        if let Some(path) = &cli.blob_path {
+            self.blob_path = Some(path.clone());
+        }
+        if !cli.peers.is_empty() {
+            self.bootstrap_peers = cli.peers.clone();
+        }
+        if let Some(url) = &cli.public_url {
+            self.public_url = Some(url.clone());
+        }
+        if !cli.discovery_peers.is_empty() {
+            self.discovery.peers = cli.discovery_peers.clone();
+        }
+        if let Some(url) = &cli.rendezvous_url {
+            self.rendezvous.url = Some(url.clone());
+        }
+        if let Some(token) = &cli.rendezvous_token {
+            self.rendezvous.token = Some(token.clone());
+        }
+        if let Some(interval) = cli.advertisement_interval {
+            self.advertisement_interval = interval;
+        }
+        if let Some(interval) = cli.pending_retry_interval {
+            self.pending_retry_interval = interval;
+        }
+        if let Some(interval) = cli.retention_interval {
+            self.retention_interval = interval;
+        }
+        if let Some(interval) = cli.peer_exchange_interval {
+            self.peer_exchange.interval = interval;
+        }
+        if let Some(seconds) = cli.peer_stale_after {
+            self.peer_stale_after = seconds;
+        }
+        if let Some(seconds) = cli.peer_expiry {
+            self.peer_expiry = seconds;
+        }
+        if let Some(peers) = cli.sync_peers {
+            self.sync_peers = peers;
+        }
+        if let Some(rate) = cli.upload_limit {
+            self.bandwidth.upload = rate;
+        }
+        if let Some(rate) = cli.download_limit {
+            self.bandwidth.download = rate;
+        }
+        if let Some(mode) = &cli.mode {
+            self.mode = mode.clone();
+        }
+        if let Some(url) = &cli.writable_node {
+            self.writable_node = Some(url.clone());
+        }
+        if let Some(retention) = &cli.retention {
+            self.retention = retention.clone();
+        }
+        if cli.archive {
+            self.archive.enabled = true;
+        }
+        if !cli.channels.is_empty() {
+            self.channels = cli.channels.clone();
+        }
+        if cli.p2p_encryption {
+            self.p2p_encryption = true;
+        }
+        if let Some(codec) = cli.p2p_codec {
+            self.p2p_codec = codec;
+        }
+        if let Some(path) = &cli.node_key {
+            self.node_key = Some(path.clone());
+        }
+        if let Some(path) = &cli.genesis {
+            self.genesis = Some(path.clone());
+        }
+        if let Some(chain_id) = &cli.chain_id {
+            self.chain_id = Some(chain_id.clone());
+        }
+        if let Some(strategy) = cli.consensus {
+            self.consensus.strategy = Some(strategy);
+        }
+        if let Some(ticket) = &cli.checkpoint {
+            self.checkpoint = Some(ticket.clone());
+        }
+        if !cli.checkpoint_keys.is_empty() {
+            self.checkpoint_keys = cli.checkpoint_keys.clone();
+        }
+        if let Some(path) = &cli.import {
+            self.import = Some(path.clone());
+        }
+        if let Some(rate) = cli.rate_limit {
+            self.rate_limit.requests_per_second = rate;
+        }
+        if let Some(threshold) = cli.offload_threshold {
+            self.offload.threshold_bytes = threshold;
+        }
+        if let Some(interval) = cli.blob_gc_interval {
+            self.blob_gc.interval = interval;
+        }
+        if let Some(fee) = cli.fee_base {
+            self.fees.base = fee;
+        }
+        if let Some(fee) = cli.fee_per_byte {
+            self.fees.per_byte = fee;
+        }
+        if let Some(fee) = cli.min_fee {
+            self.mempool.min_fee = fee;
+        }
+        if let Some(path) = &cli.audit_log {
+            self.audit.path = Some(path.clone());
+        }
+        if let Some(endpoint) = &cli.otlp_endpoint {
+            self.telemetry.otlp_endpoint = Some(endpoint.clone());
+        }
+    }
+
+    /// The mode to run in, with followers pointed at the writable node
+    pub fn node_mode(&self) -> NodeMode {
+        match self.mode {
+            NodeMode::Follower { .. } => NodeMode::Follower {
+                writable_node: self.writable_node.clone(),
+            },
+            NodeMode::Writer => NodeMode::Writer,
+        }
+    }
+
+    /// Time between peer advertisements
+    pub fn advertisement_interval(&self) -> Duration {
+        Duration::from_secs(self.advertisement_interval)
+    }
+
+    /// Time between retries of pending entries
+    pub fn pending_retry_interval(&self) -> Duration {
+        Duration::from_secs(self.pending_retry_interval)
+    }
+
+    /// Time between runs of the retention policy
+    pub fn retention_interval(&self) -> Duration {
+        Duration::from_secs(self.retention_interval)
+    }
+
+    /// Time a node may go unheard from and of before it counts as stale
+    pub fn peer_stale_after(&self) -> Duration {
+        Duration::from_secs(self.peer_stale_after)
+    }
+
+    /// Time a node may go unheard from and of before it's forgotten, if nodes are forgotten at all
+    pub fn peer_expiry(&self) -> Option<Duration> {
+        (self.peer_expiry > 0).then(|| Duration::from_secs(self.peer_expiry))
+    }
+
+    /// The genesis from the `genesis` file, if set
+    pub fn load_genesis(&self) -> Result<Option<Genesis>, String> {
+        self.genesis.as_deref().map(Genesis::from_file).transpose()
+    }
+
+    /// The key from the `node_key` file, if set, generating and saving one if the file doesn't exist yet
+    pub fn signing_key(&self) -> Result<Option<SigningKey>, String> {
+        self.node_key.as_deref().map(load_or_create_key).transpose()
+    }
+}
+
+/// Read a hex-encoded ed25519 secret key from `path`, creating the file with a new key if it doesn't exist
+pub fn load_or_create_key(path: &Path) -> Result<SigningKey, String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let bytes: [u8; 32] = hex::decode(contents.trim())
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| format!("Invalid node key in {}", path.display()))?;
+            Ok(SigningKey::from_bytes(&bytes))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = SigningKey::generate(&mut OsRng);
+            write_secret(path, &hex::encode(key.to_bytes()))
+                .map_err(|e| format!("Failed to write node key {}: {e}", path.display()))?;
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read node key {}: {e}", path.display())),
+    }
+}
+
+/// Write a node key to `path`, replacing any file already there
+pub fn save_key(path: &Path, key: &SigningKey) -> Result<(), String> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to replace node key {}: {e}", path.display()));
+        }
+        _ => {}
+    }
+    write_secret(path, &hex::encode(key.to_bytes())).map_err(|e| format!("Failed to write node key {}: {e}", path.display()))
+}
+
+/// Write a file only the current user can read
+fn write_secret(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// Split a comma-separated list, dropping empty items
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| format!("Invalid {name}: {e}"))
+}
+
+/// Deserialize a value from its string form, e.g. `mode = "follower"`
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
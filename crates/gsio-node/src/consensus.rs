@@ -0,0 +1,409 @@
+//! Consensus strategies.
+//!
+//! A [`Consensus`] decides who may create entries, when an entry may join the
+//! chain and which branch wins a fork; the [`Ledger`](crate::ledger::Ledger)
+//! only stores entries and walks branches. Two strategies are built in,
+//! selected by `strategy` in the `[consensus]` section of the config file:
+//!
+//! - [`LongestChain`]: any node appends entries, and forks are settled by the
+//!   longest chain, ties going to the lowest tip hash.
+//! - [`ProofOfAuthority`]: an entry only joins the chain once a quorum of
+//!   validators has signed it. Only validators create and sign entries; other
+//!   nodes relay them. The validator set itself changes through ledger
+//!   entries carrying a `validator_update`, which take effect for the entries
+//!   after them once they are approved.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use ed25519_dalek::VerifyingKey;
+use gsio_types::parse_public_key;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tracing::{info, warn};
+
+use crate::ledger::{Fork, LedgerEntry};
+
+/// Decisions that differ between consensus strategies
+pub trait Consensus: fmt::Debug + Send + Sync {
+    /// Name the strategy is selected by in the config
+    fn name(&self) -> &'static str;
+
+    /// Whether `node_id` may create entries
+    fn can_propose(&self, node_id: &str) -> bool;
+
+    /// Whether an entry with a valid hash and creator signature may join the chain
+    fn is_approved(&self, entry: &LedgerEntry) -> bool;
+
+    /// Pick the fork to switch the chain to, if any beats it.
+    ///
+    /// Each fork's branch replaces the chain entries after its fork point.
+    fn choose_fork(&self, chain: &[LedgerEntry], forks: Vec<Fork>) -> Option<Fork>;
+
+    /// Whether entries circulate between nodes collecting signatures before they're approved
+    fn collects_signatures(&self) -> bool {
+        false
+    }
+
+    /// Whether `node_id` signs entries other nodes propose
+    fn endorses(&self, _node_id: &str) -> bool {
+        false
+    }
+
+    /// Key `node_id`'s signatures are checked with, if the strategy assigns one
+    fn signer_key(&self, _node_id: &str) -> Option<&VerifyingKey> {
+        None
+    }
+
+    /// Called for every entry appended to the chain, in order
+    fn entry_appended(&mut self, _entry: &LedgerEntry) {}
+
+    /// The current validator set, for strategies that have one
+    fn validators(&self) -> Option<&ValidatorSet> {
+        None
+    }
+
+    /// State to carry in a ledger snapshot, for strategies that keep any
+    fn snapshot_state(&self) -> Option<JsonValue> {
+        None
+    }
+
+    /// Restore the state a ledger snapshot carries
+    fn restore_state(&mut self, _state: &JsonValue) -> Result<(), String> {
+        Err(format!("{} consensus keeps no state to restore", self.name()))
+    }
+}
+
+/// Built-in consensus strategies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusStrategy {
+    LongestChain,
+    ProofOfAuthority,
+}
+
+impl fmt::Display for ConsensusStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusStrategy::LongestChain => write!(f, "longest_chain"),
+            ConsensusStrategy::ProofOfAuthority => write!(f, "proof_of_authority"),
+        }
+    }
+}
+
+impl FromStr for ConsensusStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "longest_chain" => Ok(ConsensusStrategy::LongestChain),
+            "proof_of_authority" | "poa" => Ok(ConsensusStrategy::ProofOfAuthority),
+            other => Err(format!("Unknown consensus strategy: {other}")),
+        }
+    }
+}
+
+/// Key in entry data that marks a change to the validator set
+pub const VALIDATOR_UPDATE_KEY: &str = "validator_update";
+
+/// A node allowed to sign entries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Validator {
+    pub node_id: String,
+    /// Hex-encoded ed25519 public key the node signs entries with
+    pub public_key: String,
+}
+
+/// The `[consensus]` section of the config file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsensusConfig {
+    /// Strategy to run; proof of authority if validators are listed, otherwise longest chain
+    pub strategy: Option<ConsensusStrategy>,
+    /// Validators the chain starts with under proof of authority
+    pub validators: Vec<Validator>,
+    /// Validator signatures an entry needs; a majority of the set if unset
+    pub quorum: Option<usize>,
+}
+
+impl ConsensusConfig {
+    /// The strategy to run
+    pub fn strategy(&self) -> ConsensusStrategy {
+        self.strategy.unwrap_or(if self.validators.is_empty() {
+            ConsensusStrategy::LongestChain
+        } else {
+            ConsensusStrategy::ProofOfAuthority
+        })
+    }
+
+    /// Create the configured strategy
+    pub fn build(&self) -> Result<Box<dyn Consensus>, String> {
+        match self.strategy() {
+            ConsensusStrategy::LongestChain if !self.validators.is_empty() || self.quorum.is_some() => {
+                Err("Validators only apply to proof_of_authority consensus".to_string())
+            }
+            ConsensusStrategy::LongestChain => Ok(Box::new(LongestChain)),
+            ConsensusStrategy::ProofOfAuthority if self.validators.is_empty() => {
+                Err("Proof-of-authority consensus needs at least one validator".to_string())
+            }
+            ConsensusStrategy::ProofOfAuthority => Ok(Box::new(ProofOfAuthority::new(ValidatorSet::new(self)?))),
+        }
+    }
+}
+
+/// Any node appends entries, and the longest chain wins
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl Consensus for LongestChain {
+    fn name(&self) -> &'static str {
+        "longest_chain"
+    }
+
+    fn can_propose(&self, _node_id: &str) -> bool {
+        true
+    }
+
+    fn is_approved(&self, _entry: &LedgerEntry) -> bool {
+        true
+    }
+
+    /// The longest resulting chain wins. Chains of equal length are decided by
+    /// the lowest tip hash so every node settles on the same branch.
+    fn choose_fork(&self, chain: &[LedgerEntry], forks: Vec<Fork>) -> Option<Fork> {
+        let kept = |fork: &Fork| chain.iter().position(|e| e.hash == fork.fork_point).map_or(0, |i| i + 1);
+
+        forks
+            .into_iter()
+            .map(|fork| (kept(&fork), fork))
+            .filter(|(kept, fork)| {
+                match (kept + fork.branch.len()).cmp(&chain.len()) {
+                    std::cmp::Ordering::Greater => true,
+                    std::cmp::Ordering::Equal => tip(&fork.branch) < tip(&chain[*kept..]),
+                    std::cmp::Ordering::Less => false,
+                }
+            })
+            .max_by(|(a_kept, a), (b_kept, b)| {
+                (a_kept + a.branch.len())
+                    .cmp(&(b_kept + b.branch.len()))
+                    .then_with(|| tip(&b.branch).cmp(tip(&a.branch)))
+            })
+            .map(|(_, fork)| fork)
+    }
+}
+
+/// Entries need a quorum of validator signatures, and approved entries are final
+#[derive(Debug, Clone)]
+pub struct ProofOfAuthority {
+    validators: ValidatorSet,
+}
+
+impl ProofOfAuthority {
+    /// Run proof of authority starting from `validators`
+    pub fn new(validators: ValidatorSet) -> Self {
+        Self { validators }
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn name(&self) -> &'static str {
+        "proof_of_authority"
+    }
+
+    fn can_propose(&self, node_id: &str) -> bool {
+        self.validators.contains(node_id)
+    }
+
+    fn is_approved(&self, entry: &LedgerEntry) -> bool {
+        self.validators.is_approved(entry)
+    }
+
+    fn choose_fork(&self, _chain: &[LedgerEntry], _forks: Vec<Fork>) -> Option<Fork> {
+        None
+    }
+
+    fn collects_signatures(&self) -> bool {
+        true
+    }
+
+    fn endorses(&self, node_id: &str) -> bool {
+        self.validators.contains(node_id)
+    }
+
+    fn signer_key(&self, node_id: &str) -> Option<&VerifyingKey> {
+        self.validators.key(node_id)
+    }
+
+    fn entry_appended(&mut self, entry: &LedgerEntry) {
+        self.validators.apply(entry);
+    }
+
+    fn validators(&self) -> Option<&ValidatorSet> {
+        Some(&self.validators)
+    }
+
+    /// The validator set, in the form of the `[consensus]` section
+    fn snapshot_state(&self) -> Option<JsonValue> {
+        let config = ConsensusConfig {
+            strategy: None,
+            validators: self.validators.members(),
+            quorum: self.validators.quorum,
+        };
+        Some(serde_json::to_value(config).expect("consensus config always serializes"))
+    }
+
+    fn restore_state(&mut self, state: &JsonValue) -> Result<(), String> {
+        let config: ConsensusConfig =
+            serde_json::from_value(state.clone()).map_err(|e| format!("Invalid validator set in snapshot: {e}"))?;
+        if config.validators.is_empty() {
+            return Err("Snapshot has no validators".to_string());
+        }
+        self.validators = ValidatorSet::new(&config)?;
+        Ok(())
+    }
+}
+
+/// Hash of the last entry in a branch, used to break ties deterministically
+pub(crate) fn tip(branch: &[LedgerEntry]) -> &str {
+    branch.last().map_or("", |e| e.hash.as_str())
+}
+
+/// Change to the validator set, stored under [`VALIDATOR_UPDATE_KEY`] in entry data
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidatorUpdate {
+    /// Validators to add
+    pub add: Vec<Validator>,
+    /// Node IDs of validators to remove
+    pub remove: Vec<String>,
+}
+
+impl ValidatorUpdate {
+    /// Entry data that applies this update once approved
+    pub fn to_entry_data(&self) -> JsonValue {
+        json!({ VALIDATOR_UPDATE_KEY: self })
+    }
+
+    /// The update carried by an entry, if any
+    pub fn from_entry(entry: &LedgerEntry) -> Option<Result<Self, String>> {
+        let update = entry.data.get(VALIDATOR_UPDATE_KEY)?;
+        Some(serde_json::from_value(update.clone()).map_err(|e| format!("Invalid validator update: {e}")))
+    }
+}
+
+/// The validators whose signatures count, and how many of them an entry needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatorSet {
+    validators: BTreeMap<String, VerifyingKey>,
+    quorum: Option<usize>,
+}
+
+impl ValidatorSet {
+    /// Create the set the chain starts with
+    pub fn new(config: &ConsensusConfig) -> Result<Self, String> {
+        if config.quorum == Some(0) {
+            return Err("Consensus quorum must be at least 1".to_string());
+        }
+
+        let validators = config
+            .validators
+            .iter()
+            .map(|v| Ok((v.node_id.clone(), validator_key(&v.public_key)?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            validators,
+            quorum: config.quorum,
+        })
+    }
+
+    /// Whether `node_id` is a validator
+    pub fn contains(&self, node_id: &str) -> bool {
+        self.validators.contains_key(node_id)
+    }
+
+    /// Node IDs of the validators
+    pub fn node_ids(&self) -> Vec<String> {
+        self.validators.keys().cloned().collect()
+    }
+
+    /// The validators with their public keys
+    pub fn members(&self) -> Vec<Validator> {
+        self.validators
+            .iter()
+            .map(|(node_id, key)| Validator {
+                node_id: node_id.clone(),
+                public_key: hex::encode(key.to_bytes()),
+            })
+            .collect()
+    }
+
+    /// Validator signatures an entry needs, never more than there are validators
+    pub fn quorum(&self) -> usize {
+        let majority = self.validators.len() / 2 + 1;
+        self.quorum.unwrap_or(majority).min(self.validators.len())
+    }
+
+    /// Number of validators with a valid signature on `entry`
+    pub fn approvals(&self, entry: &LedgerEntry) -> usize {
+        self.validators
+            .iter()
+            .filter(|(node_id, key)| entry.verify_signature(node_id, key))
+            .count()
+    }
+
+    /// Public key of a validator
+    pub fn key(&self, node_id: &str) -> Option<&VerifyingKey> {
+        self.validators.get(node_id)
+    }
+
+    /// Whether a validator created and signed `entry` and a quorum of validators signed it
+    pub fn is_approved(&self, entry: &LedgerEntry) -> bool {
+        self.key(&entry.creator_node_id)
+            .is_some_and(|key| entry.verify_signature(&entry.creator_node_id, key))
+            && self.approvals(entry) >= self.quorum()
+    }
+
+    /// Apply the validator update an approved entry carries, if any.
+    ///
+    /// Updates that are malformed or would leave no validators are ignored.
+    pub fn apply(&mut self, entry: &LedgerEntry) {
+        let update = match ValidatorUpdate::from_entry(entry) {
+            None => return,
+            Some(Ok(update)) => update,
+            Some(Err(e)) => {
+                warn!(entry_id = entry.id, "Ignoring validator update: {}", e);
+                return;
+            }
+        };
+
+        let mut validators = self.validators.clone();
+        for node_id in &update.remove {
+            validators.remove(node_id);
+        }
+        for validator in &update.add {
+            match validator_key(&validator.public_key) {
+                Ok(key) => {
+                    validators.insert(validator.node_id.clone(), key);
+                }
+                Err(e) => {
+                    warn!(entry_id = entry.id, "Ignoring validator update: {}", e);
+                    return;
+                }
+            }
+        }
+        if validators.is_empty() {
+            warn!(entry_id = entry.id, "Ignoring validator update that removes every validator");
+            return;
+        }
+
+        self.validators = validators;
+        info!(entry_id = entry.id, validators = ?self.node_ids(), "Validator set changed");
+    }
+}
+
+fn validator_key(key: &str) -> Result<VerifyingKey, String> {
+    parse_public_key(key).map_err(|_| format!("Invalid validator key {key}"))
+}
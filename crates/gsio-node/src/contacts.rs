@@ -0,0 +1,166 @@
+//! How to reach known nodes.
+//!
+//! The ledger knows nodes by ID only. Next to that, each [`P2PManager`]
+//! keeps a [`Contacts`] registry of where those nodes can be dialed: the URL
+//! they serve their `/p2p` namespace at and their iroh [`NodeAddr`], whose
+//! relay URL and direct addresses are the hints iroh needs to reach them,
+//! along with when they were last heard from. Contacts are filled in from
+//! handshakes, outbound connections, discovery and peer exchange, and the
+//! outbound dialer falls back on them to reconnect.
+//!
+//! A node is [live](Liveness::Live) while it sends messages or peers keep
+//! passing it on, and turns [stale](Liveness::Stale) once neither has
+//! happened for a while. Nodes that stay quiet long enough after that are
+//! [expired](Contacts::expire), so dead nodes don't linger in node lists.
+//!
+//! [`P2PManager`]: crate::p2p::P2PManager
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use iroh::NodeAddr;
+use serde::{Deserialize, Serialize};
+
+/// Nodes a registry holds at most; nodes it hears of beyond that are dropped
+const MAX_CONTACTS: usize = 1024;
+
+/// Where a node can be reached
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeContact {
+    pub node_id: String,
+    /// Base URL the node serves its `/p2p` namespace at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Where to reach the node over iroh, with its relay and direct addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addr: Option<NodeAddr>,
+    /// When a message from the node last arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// When a peer last passed the node on, or this node first heard of it
+    pub last_heard_of: DateTime<Utc>,
+}
+
+/// Whether a node still seems to be around
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    /// Heard from or of recently
+    Live,
+    /// Neither heard from nor of for a while
+    Stale,
+}
+
+impl NodeContact {
+    fn new(node_id: &str) -> Self {
+        Self { node_id: node_id.to_string(), url: None, addr: None, last_seen: None, last_heard_of: Utc::now() }
+    }
+
+    /// Whether the node can be dialed at all
+    pub fn is_dialable(&self) -> bool {
+        self.url.is_some() || self.addr.is_some()
+    }
+
+    /// When the node was last heard from or of
+    pub fn last_active(&self) -> DateTime<Utc> {
+        self.last_seen.map_or(self.last_heard_of, |seen| seen.max(self.last_heard_of))
+    }
+
+    /// Whether the node was heard from or of within `stale_after` of `now`
+    pub fn liveness(&self, stale_after: Duration, now: DateTime<Utc>) -> Liveness {
+        if is_older(self.last_active(), stale_after, now) { Liveness::Stale } else { Liveness::Live }
+    }
+}
+
+/// Whether `time` lies more than `age` before `now`
+fn is_older(time: DateTime<Utc>, age: Duration, now: DateTime<Utc>) -> bool {
+    chrono::Duration::from_std(age).is_ok_and(|age| time < now - age)
+}
+
+/// Contacts of known nodes by node ID; clones share the registry
+#[derive(Debug, Clone, Default)]
+pub struct Contacts {
+    contacts: Arc<Mutex<BTreeMap<String, NodeContact>>>,
+}
+
+impl Contacts {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The contact of `node_id`, if it's known
+    pub fn get(&self, node_id: &str) -> Option<NodeContact> {
+        self.contacts.lock().unwrap().get(node_id).cloned()
+    }
+
+    /// Every contact, ordered by node ID
+    pub fn all(&self) -> Vec<NodeContact> {
+        self.contacts.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Record that `node_id` serves its `/p2p` namespace at `url`, returning
+    /// whether the node was new or moved
+    pub fn set_url(&self, node_id: &str, url: String) -> bool {
+        if url.is_empty() {
+            return false;
+        }
+        self.update(node_id, |contact| {
+            let moved = contact.url.as_ref() != Some(&url);
+            contact.url = Some(url);
+            moved
+        })
+    }
+
+    /// Record where `node_id` is reached over iroh, returning whether that's news
+    pub fn set_addr(&self, node_id: &str, addr: NodeAddr) -> bool {
+        self.update(node_id, |contact| {
+            let moved = contact.addr.as_ref() != Some(&addr);
+            contact.addr = Some(addr);
+            moved
+        })
+    }
+
+    /// Record that a message from `node_id` just arrived
+    pub fn seen(&self, node_id: &str) {
+        self.update(node_id, |contact| {
+            contact.last_seen = Some(Utc::now());
+            false
+        });
+    }
+
+    /// Record that a peer just passed `node_id` on
+    pub fn heard_of(&self, node_id: &str) {
+        self.update(node_id, |contact| {
+            contact.last_heard_of = Utc::now();
+            false
+        });
+    }
+
+    /// Drop the contacts of nodes neither heard from nor of within `expiry`,
+    /// except those `keep` holds on to, returning their node IDs
+    pub fn expire(&self, expiry: Duration, keep: impl Fn(&str) -> bool) -> Vec<String> {
+        let now = Utc::now();
+        let mut contacts = self.contacts.lock().unwrap();
+        let expired: Vec<String> = contacts
+            .values()
+            .filter(|contact| is_older(contact.last_active(), expiry, now) && !keep(&contact.node_id))
+            .map(|contact| contact.node_id.clone())
+            .collect();
+        for node_id in &expired {
+            contacts.remove(node_id);
+        }
+        expired
+    }
+
+    /// Apply `update` to the contact of `node_id`, adding it unless the registry is full
+    fn update(&self, node_id: &str, update: impl FnOnce(&mut NodeContact) -> bool) -> bool {
+        let mut contacts = self.contacts.lock().unwrap();
+        if !contacts.contains_key(node_id) && contacts.len() >= MAX_CONTACTS {
+            return false;
+        }
+        update(contacts.entry(node_id.to_string()).or_insert_with(|| NodeContact::new(node_id)))
+    }
+}
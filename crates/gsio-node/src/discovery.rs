@@ -0,0 +1,391 @@
+//! Peer discovery over iroh.
+//!
+//! Nodes gossip a table of the peers they know on the `gsio-discovery`
+//! topic, over their own ALPN on the iroh endpoint. Each record holds a
+//! node's ID, the URL its `/p2p` namespace is served at and its iroh
+//! address, whose `NodeId` is the node's ID (see [`crate::identity`]).
+//! Every round a node swaps tables with the nodes it knows, its
+//! configured discovery peers, and nodes iroh finds on the local network,
+//! so a node that reaches one member of the mesh soon learns about all of
+//! them. Peers learned this way are sent to the caller to dial.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{extract::rejection::JsonRejection, extract::State, http::StatusCode, routing::post, Json, Router};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use iroh::endpoint::Connection;
+use iroh::protocol::ProtocolHandler;
+use iroh::{Endpoint, NodeAddr, NodeId};
+use iroh_blobs::ticket::BlobTicket;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::api::ApiError;
+use crate::identity;
+use crate::supervisor::{RestartPolicy, Supervisor, Tasks};
+
+/// ALPN of the discovery protocol
+pub const DISCOVERY_ALPN: &[u8] = b"gsio/discovery/0";
+
+/// Topic nodes gossip on unless configured otherwise
+pub const DISCOVERY_TOPIC: &str = "gsio-discovery";
+
+/// Largest peer table accepted from another node
+const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
+/// Rounds a record may go without being refreshed before it's forgotten
+const RECORD_TTL_ROUNDS: u32 = 10;
+
+/// The `[discovery]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// Whether to gossip with other nodes at all
+    pub enabled: bool,
+    /// Nodes only gossip with nodes on the same topic
+    pub topic: String,
+    /// Seconds between gossip rounds
+    pub interval: u64,
+    /// Iroh node IDs to gossip with besides the ones found automatically
+    pub peers: Vec<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            topic: DISCOVERY_TOPIC.to_string(),
+            interval: 30,
+            peers: Vec::new(),
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    /// Whether this node takes part in discovery
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Time between gossip rounds
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+
+    /// The configured discovery peers
+    pub fn peer_ids(&self) -> Result<Vec<NodeId>, String> {
+        self.peers
+            .iter()
+            .map(|id| id.parse().map_err(|e| format!("Invalid discovery peer {id}: {e}")))
+            .collect()
+    }
+}
+
+/// A node as other nodes learn about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    /// The node's ID in the ledger network, its iroh `NodeId` hex-encoded
+    pub node_id: String,
+    /// Base URL the node serves its `/p2p` namespace at
+    pub url: String,
+    /// Where to reach the node over iroh
+    pub addr: NodeAddr,
+    /// When the node last announced itself
+    pub updated: DateTime<Utc>,
+}
+
+/// Read the iroh address in a blob ticket, or an iroh node ID
+pub fn parse_node_addr(address: &str) -> Result<NodeAddr, String> {
+    let address = address.trim();
+    if let Ok(ticket) = BlobTicket::from_str(address) {
+        return Ok(ticket.node_addr().clone());
+    }
+    NodeId::from_str(address)
+        .map(NodeAddr::new)
+        .map_err(|_| format!("{address} is neither a blob ticket nor an iroh node ID"))
+}
+
+/// What nodes send each other in a gossip round
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DiscoveryMessage {
+    topic: String,
+    peers: Vec<PeerRecord>,
+}
+
+/// The node's side of the discovery gossip; clones share the peer table
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    endpoint: Endpoint,
+    topic: String,
+    /// This node's public URL, if it can be dialed
+    public_url: Option<String>,
+    /// Known peers by iroh node ID
+    peers: Arc<Mutex<HashMap<NodeId, PeerRecord>>>,
+    /// Where newly learned peers are sent
+    learned: mpsc::UnboundedSender<PeerRecord>,
+    record_ttl: Duration,
+}
+
+impl Discovery {
+    /// Create the discovery protocol for `endpoint` on `topic`.
+    ///
+    /// Nodes announce themselves under their endpoint's ID with
+    /// `public_url` if given; nodes without a public URL still pass on what
+    /// they learn. Peers this node hasn't heard of before are sent on the
+    /// returned channel.
+    pub fn new(
+        endpoint: Endpoint,
+        topic: &str,
+        public_url: Option<String>,
+        interval: Duration,
+    ) -> (Self, mpsc::UnboundedReceiver<PeerRecord>) {
+        let (learned, receiver) = mpsc::unbounded_channel();
+        let discovery = Self {
+            endpoint,
+            topic: topic.to_string(),
+            public_url,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            learned,
+            record_ttl: interval * RECORD_TTL_ROUNDS,
+        };
+        (discovery, receiver)
+    }
+
+    /// The peers this node knows, ordered by node ID
+    pub fn peers(&self) -> Vec<PeerRecord> {
+        let mut peers: Vec<PeerRecord> = self.peers.lock().unwrap().values().cloned().collect();
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        peers
+    }
+
+    /// Swap peer tables with the node at `addr`, returning how many peers
+    /// this node hadn't known
+    pub async fn exchange(&self, addr: NodeAddr) -> Result<usize, String> {
+        let connection = self.endpoint.connect(addr, DISCOVERY_ALPN).await.map_err(|e| e.to_string())?;
+        let (mut send, mut recv) = connection.open_bi().await.map_err(|e| e.to_string())?;
+        send.write_all(&self.message().await?).await.map_err(|e| e.to_string())?;
+        send.finish().map_err(|e| e.to_string())?;
+        let reply = recv.read_to_end(MAX_MESSAGE_BYTES).await.map_err(|e| e.to_string())?;
+        connection.close(0u32.into(), b"done");
+        self.merge(&reply)
+    }
+
+    /// Introduce this node to the node at `addr`, returning its record and
+    /// how many peers were learned.
+    ///
+    /// The node is sent to be dialed even if it was known already, and fails
+    /// to join if it doesn't announce a public URL.
+    pub async fn join(&self, addr: NodeAddr) -> Result<(PeerRecord, usize), JoinError> {
+        let node_id = addr.node_id;
+        if node_id == self.endpoint.node_id() {
+            return Err(JoinError::NoUrl("Can't join this node itself".to_string()));
+        }
+        let learned = self.exchange(addr).await.map_err(JoinError::Unreachable)?;
+        let record = self.peers.lock().unwrap().get(&node_id).cloned();
+        let record = record.ok_or_else(|| JoinError::NoUrl(format!("Node {node_id} doesn't announce a public URL")))?;
+        self.learned.send(record.clone()).ok();
+        Ok((record, learned))
+    }
+
+    /// Gossip every `interval` with the known peers and `bootstrap`, and with
+    /// nodes iroh discovers, until the task is aborted; its health is reported to `tasks`
+    pub fn spawn(self, bootstrap: Vec<NodeId>, interval: Duration, tasks: &Tasks) -> JoinHandle<()> {
+        Supervisor::new("discovery")
+            .with_policy(RestartPolicy::Always)
+            .with_tasks(tasks)
+            .spawn(move || self.clone().run(bootstrap.clone(), interval))
+    }
+
+    async fn run(self, bootstrap: Vec<NodeId>, interval: Duration) {
+        let mut found = self.endpoint.discovery_stream();
+        let mut rounds = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = rounds.tick() => self.gossip(&bootstrap).await,
+                Some(Ok(item)) = found.next() => {
+                    let known = self.peers.lock().unwrap().contains_key(&item.node_id());
+                    if !known && item.node_id() != self.endpoint.node_id() {
+                        self.exchange_logged(NodeAddr::new(item.node_id())).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// One gossip round with every known peer and `bootstrap`
+    async fn gossip(&self, bootstrap: &[NodeId]) {
+        self.forget_stale();
+        let mut targets: HashMap<NodeId, NodeAddr> =
+            bootstrap.iter().map(|id| (*id, NodeAddr::new(*id))).collect();
+        for record in self.peers.lock().unwrap().values() {
+            targets.insert(record.addr.node_id, record.addr.clone());
+        }
+        targets.remove(&self.endpoint.node_id());
+        for addr in targets.into_values() {
+            self.exchange_logged(addr).await;
+        }
+    }
+
+    /// Answer an exchange started by another node
+    async fn respond(&self, connection: Connection) -> Result<(), String> {
+        let (mut send, mut recv) = connection.accept_bi().await.map_err(|e| e.to_string())?;
+        let message = recv.read_to_end(MAX_MESSAGE_BYTES).await.map_err(|e| e.to_string())?;
+        // Nodes on other topics get nothing back
+        let merged = self.merge(&message);
+        if merged.is_ok() {
+            send.write_all(&self.message().await?).await.map_err(|e| e.to_string())?;
+        }
+        send.finish().map_err(|e| e.to_string())?;
+        // The other node closes the connection once it has read the reply
+        connection.closed().await;
+        merged.map(|_| ())
+    }
+
+    async fn exchange_logged(&self, addr: NodeAddr) {
+        let peer = addr.node_id;
+        match self.exchange(addr).await {
+            Ok(0) => {}
+            Ok(learned) => info!(%peer, "Learned {learned} peers through discovery"),
+            // Nodes found on the local network needn't be gsio nodes
+            Err(e) => debug!(%peer, "Discovery exchange failed: {e}"),
+        }
+    }
+
+    /// This node's peer table, with its own record if it has a public URL
+    async fn message(&self) -> Result<Vec<u8>, String> {
+        let mut peers = self.peers();
+        if let Some(url) = &self.public_url {
+            let addr = self.endpoint.node_addr().await.map_err(|e| e.to_string())?;
+            let node_id = identity::from_iroh(&addr.node_id);
+            peers.push(PeerRecord { node_id, url: url.clone(), addr, updated: Utc::now() });
+        }
+        let message = DiscoveryMessage { topic: self.topic.clone(), peers };
+        serde_json::to_vec(&message).map_err(|e| e.to_string())
+    }
+
+    /// Merge a peer table from another node, returning how many peers were new
+    fn merge(&self, message: &[u8]) -> Result<usize, String> {
+        let message: DiscoveryMessage =
+            serde_json::from_slice(message).map_err(|e| format!("Invalid discovery message: {e}"))?;
+        if message.topic != self.topic {
+            return Err(format!("Peer gossips on topic {}", message.topic));
+        }
+
+        let own_id = self.endpoint.node_id();
+        let mut peers = self.peers.lock().unwrap();
+        let mut learned = 0;
+        for record in message.peers {
+            let id = record.addr.node_id;
+            if id == own_id || Utc::now() - record.updated > self.record_ttl() {
+                continue;
+            }
+            // A record has to name the node its address reaches
+            if record.node_id != identity::from_iroh(&id) {
+                debug!(peer = %id, node_id = record.node_id, "Ignoring record whose node ID isn't its iroh ID");
+                continue;
+            }
+            match peers.get(&id) {
+                Some(known) if known.updated >= record.updated => continue,
+                Some(known) if known.url == record.url => {}
+                // The peer is new or moved to another URL
+                _ => {
+                    learned += 1;
+                    self.learned.send(record.clone()).ok();
+                }
+            }
+            peers.insert(id, record);
+        }
+        Ok(learned)
+    }
+
+    /// Drop records their node hasn't refreshed in a while
+    fn forget_stale(&self) {
+        let ttl = self.record_ttl();
+        self.peers.lock().unwrap().retain(|_, record| Utc::now() - record.updated <= ttl);
+    }
+
+    fn record_ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.record_ttl).unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+/// Why a node couldn't be joined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    /// The discovery exchange with the node failed
+    Unreachable(String),
+    /// The node can't be dialed
+    NoUrl(String),
+}
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinError::Unreachable(e) => write!(f, "Failed to reach node: {e}"),
+            JoinError::NoUrl(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Body of `POST /admin/peers/join`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JoinRequest {
+    /// A blob ticket served by the node, or its iroh node ID
+    pub ticket: String,
+}
+
+/// Response to `POST /admin/peers/join`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JoinedPeer {
+    /// The node that was joined, which this node is now dialing
+    pub peer: PeerRecord,
+    /// Peers this node hadn't known, including the joined node if it was new
+    pub learned: usize,
+}
+
+/// Build the `POST /admin/peers/join` route for introducing nodes by hand,
+/// which [`crate::admin::router`] mounts with the admin routes
+pub fn router(discovery: Discovery) -> Router {
+    Router::new()
+        .route("/admin/peers/join", post(join_peer))
+        .with_state(discovery)
+}
+
+async fn join_peer(
+    State(discovery): State<Discovery>,
+    body: Result<Json<JoinRequest>, JsonRejection>,
+) -> Result<Json<JoinedPeer>, ApiError> {
+    let Json(request) = body?;
+    let addr = parse_node_addr(&request.ticket).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    match discovery.join(addr).await {
+        Ok((peer, learned)) => {
+            info!(peer_id = peer.node_id, peer_url = peer.url, "Joined peer");
+            Ok(Json(JoinedPeer { peer, learned }))
+        }
+        Err(e @ JoinError::Unreachable(_)) => Err(ApiError::new(StatusCode::BAD_GATEWAY, e.to_string())),
+        Err(e @ JoinError::NoUrl(_)) => Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())),
+    }
+}
+
+impl ProtocolHandler for Discovery {
+    fn accept(&self, connection: Connection) -> BoxFuture<'static, anyhow::Result<()>> {
+        let discovery = self.clone();
+        Box::pin(async move {
+            let peer = connection.remote_node_id()?;
+            if let Err(e) = discovery.respond(connection).await {
+                debug!(%peer, "Discovery exchange failed: {e}");
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,211 @@
+//! Encrypted envelopes for P2P messages.
+//!
+//! Each side of a handshake makes a fresh X25519 key pair, an [`EphemeralKey`],
+//! and sends its public half along; both sides sign the handshake transcript,
+//! ephemeral keys included, with their Ed25519 identity keys. The agreement
+//! between the two ephemeral keys is stretched with HKDF-SHA256, salted with
+//! the transcript, into one key per direction, so a node's identity key
+//! leaking later doesn't open the traffic it exchanged before.
+//!
+//! Messages are sealed with ChaCha20-Poly1305 under a counter that goes up by
+//! one per message, with the sender and recipient IDs and the message ID
+//! authenticated alongside, so an intermediary can neither read nor alter nor
+//! redirect them. Each counter is accepted once, and only while it is within
+//! [`REPLAY_WINDOW`] of the highest one seen, so envelopes can't be replayed.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::p2p::{MessageType, P2PMessage};
+
+/// How far behind the highest counter received an envelope may be and still be opened
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// A one-off X25519 key pair for a single handshake
+#[derive(Clone)]
+pub struct EphemeralKey {
+    secret: [u8; 32],
+}
+
+impl EphemeralKey {
+    /// Make a fresh key pair
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self { secret }
+    }
+
+    /// The public half, hex-encoded, as sent in the handshake
+    pub fn public_key(&self) -> String {
+        hex::encode(x25519_dalek::x25519(self.secret, x25519_dalek::X25519_BASEPOINT_BYTES))
+    }
+
+    /// X25519 shared secret with the peer's hex-encoded ephemeral public key
+    pub fn agree(&self, peer_key: &str) -> Result<[u8; 32], String> {
+        let peer_key: [u8; 32] = hex::decode(peer_key)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or("Invalid ephemeral key")?;
+        let secret = x25519_dalek::x25519(self.secret, peer_key);
+        // Low-order points give a secret anyone could compute
+        if secret == [0u8; 32] {
+            return Err("Invalid ephemeral key".to_string());
+        }
+        Ok(secret)
+    }
+}
+
+/// Counters received so far: the highest one, and which of the
+/// [`REPLAY_WINDOW`] below it have been seen, bit `n` standing for `highest - n`
+#[derive(Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Refuse a counter that was received already or is too far behind
+    fn check(&self, counter: u64) -> Result<(), String> {
+        let Some(highest) = self.highest else {
+            return Ok(());
+        };
+        if counter > highest {
+            return Ok(());
+        }
+        let behind = highest - counter;
+        if behind >= REPLAY_WINDOW {
+            return Err(format!("Envelope {counter} is too old, the channel is at {highest}"));
+        }
+        if self.seen & (1 << behind) != 0 {
+            return Err(format!("Envelope {counter} was received already"));
+        }
+        Ok(())
+    }
+
+    /// Remember a counter that opened
+    fn accept(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => self.seen |= 1 << (highest - counter),
+            Some(highest) => {
+                let shift = counter - highest;
+                self.seen = if shift >= REPLAY_WINDOW { 1 } else { (self.seen << shift) | 1 };
+                self.highest = Some(counter);
+            }
+            None => {
+                self.seen = 1;
+                self.highest = Some(counter);
+            }
+        }
+    }
+}
+
+/// Seals and opens the messages exchanged with one peer.
+///
+/// Clones share their counters, so a channel can be cloned along with the session it belongs to.
+#[derive(Clone)]
+pub struct SecureChannel {
+    sealing: ChaCha20Poly1305,
+    opening: ChaCha20Poly1305,
+    local_id: String,
+    peer_id: String,
+    sent: Arc<AtomicU64>,
+    received: Arc<Mutex<ReplayWindow>>,
+}
+
+impl SecureChannel {
+    /// Create a channel from the ephemeral secret shared with `peer_id` and
+    /// the hash of the handshake transcript; both sides derive the same keys
+    pub fn new(shared_secret: &[u8; 32], transcript: &str, local_id: &str, peer_id: &str) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(Some(transcript.as_bytes()), shared_secret);
+        let key = |from: &str, to: &str| {
+            let mut key = Key::default();
+            hkdf.expand(format!("gsio-p2p-envelope:{from}:{to}").as_bytes(), &mut key)
+                .expect("32 bytes is a valid HKDF-SHA256 output length");
+            ChaCha20Poly1305::new(&key)
+        };
+
+        Self {
+            sealing: key(local_id, peer_id),
+            opening: key(peer_id, local_id),
+            local_id: local_id.to_string(),
+            peer_id: peer_id.to_string(),
+            sent: Arc::new(AtomicU64::new(0)),
+            received: Arc::new(Mutex::new(ReplayWindow::default())),
+        }
+    }
+
+    /// Node ID of the peer on the other end
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// Wrap a message for the peer in an `Encrypted` envelope
+    pub fn seal(&self, message: &P2PMessage) -> P2PMessage {
+        let mut envelope = P2PMessage::new(MessageType::Encrypted, self.local_id.clone(), self.peer_id.clone(), json!({}));
+        let counter = self.sent.fetch_add(1, Ordering::Relaxed);
+        let plaintext = serde_json::to_vec(message).expect("P2P messages always serialize");
+        let ciphertext = self
+            .sealing
+            .encrypt(&nonce(counter), Payload { msg: &plaintext, aad: &associated_data(&envelope) })
+            .expect("encrypting into a Vec can't fail");
+
+        envelope.payload = json!({ "counter": counter, "ciphertext": hex::encode(ciphertext) });
+        envelope
+    }
+
+    /// Unwrap an envelope the peer sent, checking it hasn't been altered or opened before
+    pub fn open(&self, envelope: &P2PMessage) -> Result<P2PMessage, String> {
+        if !matches!(envelope.message_type, MessageType::Encrypted) {
+            return Err("Message isn't encrypted".to_string());
+        }
+        if envelope.sender_id != self.peer_id || envelope.recipient_id != self.local_id {
+            return Err(format!("Envelope from {} to {} isn't for this channel", envelope.sender_id, envelope.recipient_id));
+        }
+
+        let counter = envelope.payload.get("counter").and_then(|c| c.as_u64()).ok_or("Envelope has no valid counter")?;
+        let ciphertext = envelope
+            .payload
+            .get("ciphertext")
+            .and_then(|v| v.as_str())
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or("Envelope has no valid ciphertext")?;
+        // The window only moves for envelopes that authenticate
+        let mut received = self.received.lock().unwrap();
+        received.check(counter)?;
+        let plaintext = self
+            .opening
+            .decrypt(&nonce(counter), Payload { msg: &ciphertext, aad: &associated_data(envelope) })
+            .map_err(|_| "Envelope failed authentication".to_string())?;
+        received.accept(counter);
+        drop(received);
+
+        let message: P2PMessage =
+            serde_json::from_slice(&plaintext).map_err(|e| format!("Invalid message in envelope: {e}"))?;
+        if message.sender_id != self.peer_id {
+            return Err(format!("Envelope from {} carries a message from {}", self.peer_id, message.sender_id));
+        }
+        Ok(message)
+    }
+}
+
+/// Nonce an envelope's counter is sealed under
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// Envelope fields authenticated alongside the ciphertext
+fn associated_data(envelope: &P2PMessage) -> Vec<u8> {
+    format!("{}:{}:{}", envelope.message_id, envelope.sender_id, envelope.recipient_id).into_bytes()
+}
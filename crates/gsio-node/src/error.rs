@@ -0,0 +1,88 @@
+//! Errors the node reports to clients over REST, Socket.IO and gRPC.
+//!
+//! Each error carries a stable [`ErrorCode`], sent as `code` alongside the
+//! human-readable message so clients can branch on it.
+
+use axum::http::StatusCode;
+use thiserror::Error;
+
+use crate::validation::{ErrorCode, ValidationError};
+
+/// Why the node refused a client's request
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GsioNodeError {
+    /// The node is a follower; writes should go to the writable node, if known
+    #[error("Node is a read-only follower{}", .writable_node.as_ref().map(|url| format!("; send writes to {url}")).unwrap_or_default())]
+    ReadOnly { writable_node: Option<String> },
+    /// Proof of authority is on and this node isn't a validator
+    #[error("Only validators can create entries")]
+    NotValidator,
+    /// The channel has a write ACL and this node's key isn't on it
+    #[error("This node's key isn't on the write ACL")]
+    NotWriter,
+    /// The entry data failed the validation policy
+    #[error("{}", .0.message)]
+    Invalid(#[from] ValidationError),
+    /// The request is malformed
+    #[error("Invalid request: {0}")]
+    BadRequest(String),
+    /// The request lacks valid credentials
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The requested resource doesn't exist
+    #[error("{0}")]
+    NotFound(String),
+    /// The request conflicts with the node's current state
+    #[error("{0}")]
+    Conflict(String),
+    /// A peer the request depends on couldn't be reached
+    #[error("{0}")]
+    Unavailable(String),
+    /// The node failed to handle a valid request
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl GsioNodeError {
+    /// Machine-readable reason, stable across releases
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            GsioNodeError::ReadOnly { .. } => ErrorCode::ReadOnly,
+            GsioNodeError::NotValidator => ErrorCode::NotValidator,
+            GsioNodeError::NotWriter => ErrorCode::NotWriter,
+            GsioNodeError::Invalid(e) => e.code,
+            GsioNodeError::BadRequest(_) => ErrorCode::BadRequest,
+            GsioNodeError::Unauthorized(_) => ErrorCode::Unauthorized,
+            GsioNodeError::NotFound(_) => ErrorCode::NotFound,
+            GsioNodeError::Conflict(_) => ErrorCode::Conflict,
+            GsioNodeError::Unavailable(_) => ErrorCode::Unavailable,
+            GsioNodeError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// HTTP status the REST API answers with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            // 307 preserves the method and body when the client follows the redirect
+            GsioNodeError::ReadOnly { writable_node: Some(_) } => StatusCode::TEMPORARY_REDIRECT,
+            GsioNodeError::ReadOnly { writable_node: None }
+            | GsioNodeError::NotValidator
+            | GsioNodeError::NotWriter => StatusCode::FORBIDDEN,
+            GsioNodeError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            GsioNodeError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            GsioNodeError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            GsioNodeError::NotFound(_) => StatusCode::NOT_FOUND,
+            GsioNodeError::Conflict(_) => StatusCode::CONFLICT,
+            GsioNodeError::Unavailable(_) => StatusCode::BAD_GATEWAY,
+            GsioNodeError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Node the client should retry against, if any
+    pub fn redirect(&self) -> Option<&str> {
+        match self {
+            GsioNodeError::ReadOnly { writable_node } => writable_node.as_deref(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Exporting the ledger as NDJSON and importing it back.
+//!
+//! `GET /api/ledger/export` streams the chain a page at a time: a header line
+//! with the heights exported and the public keys needed to check signatures,
+//! then one entry per line, oldest first. If older entries were pruned, the
+//! header also carries a snapshot at the first exported entry, which an
+//! import restores before adding the rest.
+//!
+//! Offloaded entries are exported with their blob reference, so their blobs
+//! have to be reachable from wherever the export is imported. With
+//! `?inline_blobs=true` their data is fetched and exported along with the
+//! reference instead, as when entries are read over the API, and an import
+//! stores it in the importing node's blob store.
+//!
+//! [`import`] loads an export into an empty ledger. Each entry's hash is
+//! checked against its contents and the entry before it, and entries join the
+//! chain the way synced ones do, so signatures and the consensus strategy
+//! still have to accept them.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::QueryRejection, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tracing::info;
+
+use crate::api::ApiError;
+use crate::ledger::{LedgerEntry, Snapshot};
+use crate::offload::BlobRef;
+use crate::p2p::P2PManager;
+
+/// Value of `format` in an export's header line
+pub const EXPORT_FORMAT: &str = "gsio-ledger";
+
+/// Version of the export format written by this node
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Entries read from the ledger at a time while exporting
+pub const EXPORT_PAGE_SIZE: usize = 256;
+
+/// Entries added to the ledger at a time while importing
+pub const IMPORT_BATCH_SIZE: usize = 256;
+
+/// First line of an export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportHeader {
+    /// Always [`EXPORT_FORMAT`]
+    pub format: String,
+    /// Version of the format the export is written in
+    pub version: u32,
+    /// Node the export was taken from
+    pub node_id: String,
+    /// Height of the first exported entry
+    pub from: usize,
+    /// Height of the last exported entry, the tip when the export started
+    pub height: usize,
+    /// Hex-encoded public keys of the nodes the exporting node knew
+    pub node_keys: BTreeMap<String, String>,
+    /// Snapshot at `from`, when the entries before it were pruned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Snapshot>,
+}
+
+/// Build the `/api/ledger/export` route
+pub fn router(p2p: Arc<P2PManager>) -> Router {
+    Router::new().route("/api/ledger/export", get(export_ledger)).with_state(p2p)
+}
+
+/// Options for `GET /api/ledger/export`
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    /// Export the data of offloaded entries along with their references
+    #[serde(default)]
+    pub inline_blobs: bool,
+}
+
+async fn export_ledger(
+    State(p2p): State<Arc<P2PManager>>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let lines = export(p2p, query.inline_blobs).map_err(|e| ApiError::new(StatusCode::CONFLICT, e))?;
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+}
+
+/// The header of an export of the chain as it is now
+fn export_header(p2p: &P2PManager) -> Result<ExportHeader, String> {
+    let ledger = p2p.ledger.clone_ledger();
+    let ledger = ledger.read().unwrap();
+    let height = ledger.height();
+    let from = height - ledger.get_entries().len() + 1;
+    let snapshot = if from > 1 { Some(ledger.create_snapshot(from)?) } else { None };
+
+    Ok(ExportHeader {
+        format: EXPORT_FORMAT.to_string(),
+        version: EXPORT_VERSION,
+        node_id: p2p.node_id().to_string(),
+        from,
+        height,
+        node_keys: ledger.node_keys(),
+        snapshot,
+    })
+}
+
+/// Stream the chain as NDJSON, a page of entries per chunk.
+///
+/// Entries added while the export runs aren't included. The stream fails if
+/// entries it hasn't reached yet are pruned or rolled back in the meantime.
+pub fn export(p2p: Arc<P2PManager>, inline_blobs: bool) -> Result<impl Stream<Item = Result<Bytes, String>>, String> {
+    let header = export_header(&p2p)?;
+    let (from, height) = (header.from, header.height);
+    info!(from, height, inline_blobs, "Exporting ledger");
+
+    let pages = stream::unfold(Some(from), move |next| {
+        let p2p = p2p.clone();
+        async move {
+            let next = next.filter(|next| *next <= height)?;
+            let page = p2p.ledger.sync_page(next, EXPORT_PAGE_SIZE.min(height + 1 - next));
+            if page.from != next || page.entries.is_empty() {
+                let error = format!("Entries from height {next} were pruned or rolled back during the export");
+                return Some((Err(error), None));
+            }
+
+            let entries = if inline_blobs { p2p.rehydrate(page.entries).await } else { page.entries };
+            let mut chunk = Vec::new();
+            for entry in &entries {
+                write_line(&mut chunk, entry);
+            }
+            Some((Ok(Bytes::from(chunk)), Some(next + entries.len())))
+        }
+    });
+
+    let mut first = Vec::new();
+    write_line(&mut first, &header);
+    Ok(stream::once(async move { Ok(Bytes::from(first)) }).chain(pages))
+}
+
+fn write_line(buffer: &mut Vec<u8>, value: &impl Serialize) {
+    serde_json::to_writer(&mut *buffer, value).expect("exports always serialize");
+    buffer.push(b'\n');
+}
+
+/// Load an export into the node's ledger, which has to be empty, returning
+/// the height of the chain once it's in.
+///
+/// Entries are checked as they're read, and added to the chain in batches of
+/// [`IMPORT_BATCH_SIZE`]. The import stops at the first one that fails,
+/// leaving the batches before it on the chain.
+pub async fn import(p2p: &P2PManager, reader: impl AsyncBufRead + Unpin) -> Result<usize, String> {
+    let mut lines = reader.lines();
+    let header = lines.next_line().await.map_err(|e| e.to_string())?.ok_or("Export is empty")?;
+    let header: ExportHeader = serde_json::from_str(&header).map_err(|e| format!("Invalid export header: {e}"))?;
+    if header.format != EXPORT_FORMAT || header.version != EXPORT_VERSION {
+        return Err(format!("Unsupported export format {} version {}", header.format, header.version));
+    }
+    if p2p.ledger.get_last_entry().is_some() {
+        return Err("Only an empty ledger can be imported into".to_string());
+    }
+    info!(node_id = header.node_id, from = header.from, height = header.height, "Importing ledger");
+
+    for (node_id, key) in &header.node_keys {
+        p2p.ledger.add_node_key(node_id.clone(), key)?;
+        p2p.ledger.add_known_node(node_id.clone());
+    }
+    let checkpoint = match &header.snapshot {
+        Some(snapshot) if snapshot.height == header.from => {
+            p2p.ledger.restore_from_snapshot(snapshot)?;
+            Some(snapshot.checkpoint.id.clone())
+        }
+        Some(snapshot) => {
+            return Err(format!("Export starts at height {} but its snapshot is at {}", header.from, snapshot.height));
+        }
+        None if header.from != 1 => return Err(format!("Export starts at height {} without a snapshot", header.from)),
+        None => None,
+    };
+
+    let mut previous_hash = p2p.ledger.chain_tip().hash;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut line_number = 1;
+    while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+        line_number += 1;
+        let entry: LedgerEntry =
+            serde_json::from_str(&line).map_err(|e| format!("Invalid entry on line {line_number}: {e}"))?;
+        if checkpoint.as_ref() == Some(&entry.id) {
+            continue;
+        }
+        if !entry.is_valid() {
+            return Err(format!("Entry {} on line {line_number} doesn't match its hash", entry.id));
+        }
+        if let Err(e) = p2p.ledger.check_chain(&entry) {
+            return Err(format!("Entry {} on line {line_number} can't be imported: {}", entry.id, e.message));
+        }
+        if entry.previous_hash != previous_hash {
+            return Err(format!("Entry {} on line {line_number} doesn't link to the entry before it", entry.id));
+        }
+        previous_hash = entry.hash.clone();
+
+        batch.push(store_inline_blob(p2p, entry).await?);
+        if batch.len() == IMPORT_BATCH_SIZE {
+            add_batch(p2p, std::mem::take(&mut batch))?;
+        }
+    }
+    add_batch(p2p, batch)?;
+
+    let height = p2p.ledger.chain_tip().height;
+    if height != header.height {
+        return Err(format!("Export ends at height {height} instead of {}", header.height));
+    }
+    info!(height, "Imported ledger");
+    Ok(height)
+}
+
+/// Put an exported entry's inline data in the blob store, leaving the reference in `data`
+async fn store_inline_blob(p2p: &P2PManager, mut entry: LedgerEntry) -> Result<LedgerEntry, String> {
+    let Some(reference) = entry.blob.take() else { return Ok(entry) };
+    let data = std::mem::replace(&mut entry.data, reference);
+    if let Some(offloader) = p2p.offloader() {
+        let stored = offloader.offload(&data).await?;
+        if BlobRef::from_data(&entry.data).is_none_or(|reference| reference.hash != stored.hash) {
+            return Err(format!("Data of entry {} doesn't match its blob reference", entry.id));
+        }
+    }
+    Ok(entry)
+}
+
+/// Add a batch of entries to the chain, failing if any of them isn't accepted
+fn add_batch(p2p: &P2PManager, batch: Vec<LedgerEntry>) -> Result<(), String> {
+    for entry in &batch {
+        p2p.ledger.add_pending_entry(entry.clone());
+    }
+    let added = p2p.ledger.process_pending_entries();
+    if let Some(rejected) = batch.get(added.len()) {
+        return Err(format!(
+            "Entry {} was refused; its signatures don't verify or the consensus strategy doesn't approve it",
+            rejected.id
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Fees the node asks transactions to pay.
+//!
+//! The `[fees]` section of the config file sets a base fee for every
+//! transaction and a fee per byte of transaction data, and transaction types
+//! can have a base fee of their own. The rules are served at `GET /api/fees`
+//! so wallets can price a transaction before signing it.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+
+/// The `[fees]` section of the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    /// Fee every transaction pays
+    pub base: u64,
+    /// Fee per byte of transaction data, serialized as JSON
+    pub per_byte: u64,
+    /// Base fees for particular transaction types, such as `Stake`, instead of `base`
+    pub types: BTreeMap<String, u64>,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            base: 1,
+            per_byte: 0,
+            types: BTreeMap::new(),
+        }
+    }
+}
+
+impl FeeConfig {
+    /// The fee for a transaction of `transaction_type` carrying `payload_size` bytes of data
+    pub fn fee(&self, transaction_type: &str, payload_size: usize) -> u64 {
+        let base = self.types.get(transaction_type).copied().unwrap_or(self.base);
+        base.saturating_add(self.per_byte.saturating_mul(payload_size as u64))
+    }
+}
+
+/// Build the `GET /api/fees` route
+pub fn router(fees: Arc<FeeConfig>) -> Router {
+    Router::new().route("/api/fees", get(get_fees)).with_state(fees)
+}
+
+async fn get_fees(State(fees): State<Arc<FeeConfig>>) -> Json<FeeConfig> {
+    Json(fees.as_ref().clone())
+}
@@ -0,0 +1,204 @@
+//! Garbage collection of blobs no ledger entry refers to.
+//!
+//! Offloaded entry data stays in the blob store after the entries that
+//! refer to it are pruned, and snapshots pile up too. A collection round
+//! deletes every blob that no entry in the chain or waiting to join it,
+//! forks included, has referred to for the whole grace period. The grace
+//! period keeps blobs whose entry hasn't reached the ledger yet, and gives
+//! tickets for earlier snapshots time to be used.
+//! Archive segments, and the blobs the entries archived in them refer to,
+//! count as referenced, and so does the last snapshot published, which is
+//! stored under the [`SNAPSHOT_TAG`] tag.
+//!
+//! When the collector starts it indexes the blobs already in the store, so
+//! blobs a persistent store kept from an earlier run are collected as well.
+//! Their grace period starts then, which leaves a restarted node time to
+//! sync its chain before their entries are known again.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use iroh_blobs::net_protocol::Blobs;
+use iroh_blobs::store::Store;
+use iroh_blobs::{Hash, Tag};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::archive::Archive;
+use crate::ledger::{LedgerEntry, SharedLedger};
+use crate::offload::BlobRef;
+use crate::supervisor::{RestartPolicy, Supervisor, Tasks};
+
+/// The `[blob_gc]` section of the config file; blobs are kept forever unless `interval` is set
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlobGcConfig {
+    /// Seconds between collection rounds
+    pub interval: u64,
+    /// Seconds a blob has to go unreferenced before it's deleted
+    pub grace: u64,
+}
+
+impl Default for BlobGcConfig {
+    fn default() -> Self {
+        Self {
+            interval: 0,
+            grace: 3600,
+        }
+    }
+}
+
+impl BlobGcConfig {
+    /// Whether unreferenced blobs are collected
+    pub fn is_enabled(&self) -> bool {
+        self.interval > 0
+    }
+
+    /// Time between collection rounds
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+
+    /// Time a blob has to go unreferenced before it's deleted
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace)
+    }
+}
+
+/// Tag the last snapshot published through `POST /api/ledger/snapshot` is stored under
+pub const SNAPSHOT_TAG: &str = "gsio-snapshot";
+
+/// Hashes of the blobs `entries` refer to
+pub fn referenced_blobs<'a>(entries: impl IntoIterator<Item = &'a LedgerEntry>) -> HashSet<Hash> {
+    entries
+        .into_iter()
+        .filter_map(|entry| BlobRef::from_data(entry.blob.as_ref().unwrap_or(&entry.data)))
+        .filter_map(|reference| reference.hash.parse().ok())
+        .collect()
+}
+
+/// Deletes blobs the ledger no longer refers to
+pub struct BlobGc<S> {
+    blobs: Arc<Blobs<S>>,
+    ledger: SharedLedger,
+    /// Archive whose segments are kept along with the blobs they refer to
+    archive: Option<Arc<Archive>>,
+    grace: Duration,
+    /// Unreferenced blobs in the store, with when they were first seen unreferenced
+    unreferenced: Mutex<HashMap<Hash, Instant>>,
+}
+
+impl<S: Store> BlobGc<S> {
+    /// Create a collector for `blobs`, indexing the blobs already stored that
+    /// `ledger` doesn't refer to
+    pub async fn load(blobs: Arc<Blobs<S>>, ledger: SharedLedger, grace: Duration) -> Result<Self, String> {
+        let gc = Self {
+            blobs,
+            ledger,
+            archive: None,
+            grace,
+            unreferenced: Mutex::new(HashMap::new()),
+        };
+        gc.index(Instant::now()).await?;
+        Ok(gc)
+    }
+
+    /// Keep the blobs `archive` needs as well as the ones the chain refers to
+    pub fn with_archive(mut self, archive: Arc<Archive>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Number of stored blobs no entry refers to
+    pub fn unreferenced(&self) -> usize {
+        self.unreferenced.lock().unwrap().len()
+    }
+
+    /// Delete the blobs that have gone unreferenced for the grace period,
+    /// returning how many were deleted
+    pub async fn collect(&self) -> Result<usize, String> {
+        let now = Instant::now();
+        let expired: HashSet<Hash> = self
+            .index(now)
+            .await?
+            .into_iter()
+            .filter(|(_, since)| now.duration_since(*since) >= self.grace)
+            .map(|(hash, _)| hash)
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        // Tags keep blobs alive, so remove the ones pointing at expired blobs first
+        let client = self.blobs.client();
+        let tags = client.tags().list().await.map_err(|e| e.to_string())?;
+        let tags: Vec<_> = tags.collect().await;
+        for tag in tags {
+            let tag = tag.map_err(|e| e.to_string())?;
+            if expired.contains(&tag.hash) {
+                client.tags().delete(tag.name).await.map_err(|e| e.to_string())?;
+            }
+        }
+        for hash in &expired {
+            client.delete_blob(*hash).await.map_err(|e| format!("Failed to delete blob {hash}: {e}"))?;
+        }
+
+        let mut unreferenced = self.unreferenced.lock().unwrap();
+        unreferenced.retain(|hash, _| !expired.contains(hash));
+        Ok(expired.len())
+    }
+
+    /// Collect every `interval` until the task is aborted, reporting its health to `tasks`
+    pub fn spawn(self: Arc<Self>, interval: Duration, tasks: &Tasks) -> JoinHandle<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        Supervisor::new("blob_gc").with_policy(RestartPolicy::Always).with_tasks(tasks).spawn(move || {
+            let gc = self.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match gc.collect().await {
+                        Ok(0) => {}
+                        Ok(deleted) => info!("Deleted {deleted} unreferenced blobs"),
+                        Err(e) => warn!("Blob garbage collection failed: {e}"),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Bring the index up to date with the store and the ledger, marking
+    /// newly unreferenced blobs as seen at `now`
+    async fn index(&self, now: Instant) -> Result<HashMap<Hash, Instant>, String> {
+        let client = self.blobs.client();
+        let stored = client.list().await.map_err(|e| e.to_string())?;
+        let stored: Vec<_> = stored.collect().await;
+        let mut referenced = self.ledger.with_entries(|entries| referenced_blobs(entries));
+        referenced.extend(referenced_blobs(&self.ledger.get_pending_entries()));
+        if let Some(archive) = &self.archive {
+            referenced.extend(archive.referenced_blobs().iter().filter_map(|hash| hash.parse::<Hash>().ok()));
+        }
+        let tags = client.tags().list().await.map_err(|e| e.to_string())?;
+        for tag in tags.collect::<Vec<_>>().await {
+            let tag = tag.map_err(|e| e.to_string())?;
+            if tag.name == Tag::from(SNAPSHOT_TAG) {
+                referenced.insert(tag.hash);
+            }
+        }
+
+        let mut unreferenced = self.unreferenced.lock().unwrap();
+        let mut current = HashMap::new();
+        for blob in stored {
+            let hash = blob.map_err(|e| e.to_string())?.hash;
+            if !referenced.contains(&hash) {
+                current.insert(hash, unreferenced.get(&hash).copied().unwrap_or(now));
+            }
+        }
+        *unreferenced = current.clone();
+        Ok(current)
+    }
+}
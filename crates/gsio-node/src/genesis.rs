@@ -0,0 +1,93 @@
+//! Genesis.
+//!
+//! A genesis file fixes what a network's chain starts from: its chain ID,
+//! the validators it starts with under proof of authority, balances
+//! allocated to addresses before any transaction, and a timestamp. Its hash
+//! takes the place of the zero hash as the previous hash of the first entry,
+//! so every chain started from the same file shares its ancestry, and nodes
+//! refuse peers whose genesis hash differs from their own.
+//!
+//! Nodes without a genesis file keep the zero hash, as before.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use gsio_client::Address;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::consensus::{ConsensusConfig, Validator};
+
+/// The contents of a genesis file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    /// Name of the network, so chains of different networks never share a genesis
+    pub chain_id: String,
+    /// When the network was started
+    pub timestamp: DateTime<Utc>,
+    /// Validators the chain starts with under proof of authority
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validators: Vec<Validator>,
+    /// Balances addresses start with
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub allocations: BTreeMap<String, u64>,
+}
+
+/// The genesis a node runs, as returned by `GET /api/genesis`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisInfo {
+    /// Previous hash of the first entry
+    pub hash: String,
+    /// The genesis file, or `None` for a node starting from the zero hash
+    pub genesis: Option<Genesis>,
+}
+
+impl Genesis {
+    /// Read and check a genesis file, which is JSON
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read genesis file {}: {e}", path.display()))?;
+        let genesis: Self = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid genesis file {}: {e}", path.display()))?;
+        genesis.normalized().map_err(|e| format!("Invalid genesis file {}: {e}", path.display()))
+    }
+
+    /// Check the genesis, writing allocated addresses in their checksummed
+    /// form so the hash doesn't depend on how they were written
+    pub fn normalized(mut self) -> Result<Self, String> {
+        if self.chain_id.trim().is_empty() {
+            return Err("chain_id can't be empty".to_string());
+        }
+        let mut allocations = BTreeMap::new();
+        for (address, amount) in std::mem::take(&mut self.allocations) {
+            let canonical = Address::parse_any(&address)
+                .map_err(|e| format!("{address} is not an address: {e}"))?
+                .to_string();
+            if allocations.insert(canonical, amount).is_some() {
+                return Err(format!("{address} is allocated more than once"));
+            }
+        }
+        self.allocations = allocations;
+        Ok(self)
+    }
+
+    /// SHA-256 over the genesis serialized as JSON
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("a genesis always serializes");
+        format!("{:x}", Sha256::digest(&json))
+    }
+
+    /// The consensus config with the genesis validators, which mustn't
+    /// conflict with validators configured in `[consensus]`
+    pub fn consensus(&self, config: &ConsensusConfig) -> Result<ConsensusConfig, String> {
+        if self.validators.is_empty() {
+            return Ok(config.clone());
+        }
+        if !config.validators.is_empty() && config.validators != self.validators {
+            return Err("Validators in [consensus] differ from the genesis validators".to_string());
+        }
+        Ok(ConsensusConfig { validators: self.validators.clone(), ..config.clone() })
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value as JsonValue};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+
+use crate::api::IDEMPOTENCY_KEY;
+use crate::auth::Authenticator;
+use crate::error::GsioNodeError;
+use crate::ledger::LedgerEntry;
+use crate::p2p::{NodeMode, P2PManager, MAX_SYNC_PAGE_SIZE};
+use crate::ratelimit::{self, Client, RateLimiter};
+use crate::validation::ErrorCode;
+
+/// Types generated from the workspace's `proto/gsio.proto`
+pub mod proto {
+    tonic::include_proto!("gsio");
+}
+
+use proto::gsio_server::{Gsio, GsioServer};
+use proto::{
+    AddEntryRequest, Entry, GetEntriesRequest, GetLedgerRequest, GetPeersRequest, GetStatusRequest,
+    Ledger, NodeStatus, Peer, Peers, StreamEntriesRequest, SubmitTransactionRequest,
+    SubscribeEntriesRequest,
+};
+
+/// Metadata key carrying the machine-readable reason an entry was rejected
+pub const ERROR_CODE_KEY: &str = "gsio-error-code";
+
+/// Entries read from the ledger at a time while streaming held entries
+pub const STREAM_PAGE_SIZE: usize = 256;
+
+type EntryStream = Pin<Box<dyn Stream<Item = Result<Entry, Status>> + Send>>;
+
+impl From<LedgerEntry> for Entry {
+    fn from(entry: LedgerEntry) -> Self {
+        Self {
+            id: entry.id,
+            timestamp: entry.timestamp.to_rfc3339(),
+            data_json: entry.data.to_string(),
+            previous_hash: entry.previous_hash,
+            hash: entry.hash,
+            creator_node_id: entry.creator_node_id,
+            signatures: entry.signatures,
+            clock: entry.clock,
+            chain_id: entry.chain_id.unwrap_or_default(),
+        }
+    }
+}
+
+/// gRPC service backed by the same P2P manager as the Socket.IO handlers
+pub struct GsioService {
+    p2p: Arc<P2PManager>,
+}
+
+impl GsioService {
+    /// Create a new gRPC service
+    pub fn new(p2p: Arc<P2PManager>) -> Self {
+        Self { p2p }
+    }
+
+    /// Wrap the service in a tonic server
+    pub fn into_server(self) -> GsioServer<Self> {
+        GsioServer::new(self)
+    }
+
+    /// Wrap the service in a tonic server that checks every call with `guard`
+    pub fn into_guarded_server(self, guard: CallGuard) -> InterceptedService<GsioServer<Self>, CallGuard> {
+        GsioServer::with_interceptor(self, guard)
+    }
+
+    /// Heights of the held entries from the first created after `since`, or
+    /// the first held if it's unset, to the tip
+    fn held_range(&self, since: Option<DateTime<Utc>>) -> (usize, usize) {
+        let ledger = self.p2p.ledger.clone_ledger();
+        let ledger = ledger.read().unwrap();
+        let height = ledger.height();
+        let from = match since {
+            Some(since) => ledger.first_height_since(since).unwrap_or(height + 1),
+            None => height - ledger.get_entries().len() + 1,
+        };
+        (from, height)
+    }
+
+    async fn add_entry(&self, data: JsonValue, key: Option<&str>) -> Result<Response<Entry>, Status> {
+        let result = match key {
+            Some(key) => self.p2p.add_entry_data_once(key, data).await,
+            None => self.p2p.add_entry_data(data).await,
+        };
+        match result {
+            Ok(entry) => Ok(Response::new(entry.into())),
+            Err(e) => {
+                let mut status = match e {
+                    GsioNodeError::ReadOnly { .. } | GsioNodeError::Conflict(_) => {
+                        Status::failed_precondition(e.to_string())
+                    }
+                    GsioNodeError::NotValidator | GsioNodeError::NotWriter => Status::permission_denied(e.to_string()),
+                    GsioNodeError::Invalid(_) | GsioNodeError::BadRequest(_) => Status::invalid_argument(e.to_string()),
+                    GsioNodeError::Unauthorized(_) => Status::unauthenticated(e.to_string()),
+                    GsioNodeError::NotFound(_) => Status::not_found(e.to_string()),
+                    GsioNodeError::Unavailable(_) => Status::unavailable(e.to_string()),
+                    GsioNodeError::Internal(_) => Status::internal(e.to_string()),
+                };
+                status.metadata_mut().insert(ERROR_CODE_KEY, MetadataValue::from_static(e.code().as_str()));
+                Err(status)
+            }
+        }
+    }
+}
+
+/// Interceptor holding gRPC calls to the same credentials and rate limit as
+/// the `/api` routes: the credential goes in `authorization` metadata as
+/// `Bearer <key>`, and calls over the limit fail with `RESOURCE_EXHAUSTED`
+#[derive(Debug, Clone, Default)]
+pub struct CallGuard {
+    auth: Option<Arc<Authenticator>>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl CallGuard {
+    /// A guard letting every call through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse calls without a credential `auth` accepts
+    pub fn with_authenticator(mut self, auth: Arc<Authenticator>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Refuse calls over the rate `limiter` allows each client
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+}
+
+impl Interceptor for CallGuard {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let credential = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        // Like the HTTP middleware, the rate limit applies before credentials are checked
+        if let Some(limiter) = &self.limiter {
+            let client = Client {
+                ip: request.remote_addr().map(|addr| addr.ip()),
+                credential: credential.map(str::to_string),
+            };
+            if let Err(wait) = limiter.check(&client) {
+                let mut status = Status::resource_exhausted("Too many requests");
+                status.metadata_mut().insert(ERROR_CODE_KEY, MetadataValue::from_static(ErrorCode::RateLimited.as_str()));
+                status.metadata_mut().insert("retry-after", MetadataValue::from(ratelimit::retry_after_secs(wait)));
+                return Err(status);
+            }
+        }
+        if let Some(auth) = &self.auth {
+            match credential {
+                Some(credential) if auth.is_authorized(credential) => {}
+                Some(_) => return Err(Status::unauthenticated("Invalid credentials")),
+                None => return Err(Status::unauthenticated("Authentication required")),
+            }
+        }
+        Ok(request)
+    }
+}
+
+fn parse_json(field: &str, json: &str) -> Result<JsonValue, Status> {
+    serde_json::from_str(json)
+        .map_err(|e| Status::invalid_argument(format!("Invalid JSON in {field}: {e}")))
+}
+
+/// Stream the held entries from height `from` through `height` a page at a
+/// time, skipping those created at or before `since`.
+///
+/// Each page is read and rehydrated as the stream reaches it, so the chain is
+/// never loaded all at once. The stream fails if entries it hasn't reached
+/// yet are pruned or rolled back in the meantime.
+fn held_entries(
+    p2p: Arc<P2PManager>,
+    from: usize,
+    height: usize,
+    since: Option<DateTime<Utc>>,
+) -> impl Stream<Item = Result<Entry, Status>> + Send {
+    let pages = futures::stream::unfold(Some(from), move |next| {
+        let p2p = p2p.clone();
+        async move {
+            let next = next.filter(|next| *next <= height)?;
+            let page = p2p.ledger.sync_page(next, STREAM_PAGE_SIZE.min(height + 1 - next));
+            if page.from != next || page.entries.is_empty() {
+                let error = format!("Entries from height {next} were pruned or rolled back while streaming");
+                return Some((vec![Err(Status::aborted(error))], None));
+            }
+
+            let following = next + page.entries.len();
+            let page = page.entries.into_iter().filter(|e| since.is_none_or(|since| e.timestamp > since)).collect();
+            let entries = p2p.rehydrate(page).await;
+            Some((entries.into_iter().map(|e| Ok(e.into())).collect::<Vec<_>>(), Some(following)))
+        }
+    });
+    futures::StreamExt::flat_map(pages, futures::stream::iter)
+}
+
+/// Parse an optional RFC 3339 timestamp, where an empty string means none
+fn parse_timestamp(field: &str, timestamp: &str) -> Result<Option<DateTime<Utc>>, Status> {
+    if timestamp.is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| Some(t.with_timezone(&Utc)))
+        .map_err(|e| Status::invalid_argument(format!("Invalid timestamp in {field}: {e}")))
+}
+
+#[tonic::async_trait]
+impl Gsio for GsioService {
+    async fn add_entry(&self, request: Request<AddEntryRequest>) -> Result<Response<Entry>, Status> {
+        let key = request
+            .metadata()
+            .get(IDEMPOTENCY_KEY)
+            .map(|key| key.to_str().map(str::to_string))
+            .transpose()
+            .map_err(|_| Status::invalid_argument("Idempotency key isn't ASCII"))?;
+        let data = parse_json("data_json", &request.into_inner().data_json)?;
+        self.add_entry(data, key.as_deref()).await
+    }
+
+    type GetEntriesStream = EntryStream;
+
+    async fn get_entries(
+        &self,
+        _request: Request<GetEntriesRequest>,
+    ) -> Result<Response<Self::GetEntriesStream>, Status> {
+        let (from, height) = self.held_range(None);
+        Ok(Response::new(Box::pin(held_entries(self.p2p.clone(), from, height, None))))
+    }
+
+    type SubscribeEntriesStream = EntryStream;
+
+    async fn subscribe_entries(
+        &self,
+        _request: Request<SubscribeEntriesRequest>,
+    ) -> Result<Response<Self::SubscribeEntriesStream>, Status> {
+        // Subscribers that fall too far behind skip the entries they missed
+        let stream = BroadcastStream::new(self.p2p.ledger.subscribe())
+            .filter_map(|entry| entry.ok().map(|e| Ok(e.into())));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_status(&self, _request: Request<GetStatusRequest>) -> Result<Response<NodeStatus>, Status> {
+        let (entry_count, tip_hash) = self
+            .p2p
+            .ledger
+            .with_entries(|entries| (entries.len(), entries.last().map(|e| e.hash.clone()).unwrap_or_default()));
+        let mode = match self.p2p.mode() {
+            NodeMode::Writer => "writer",
+            NodeMode::Follower { .. } => "follower",
+        };
+
+        Ok(Response::new(NodeStatus {
+            node_id: self.p2p.node_id().to_string(),
+            mode: mode.to_string(),
+            entry_count: entry_count as u64,
+            tip_hash,
+            known_nodes: self.p2p.ledger.get_known_nodes().len() as u64,
+            connected_peers: self.p2p.clone_connected_nodes().lock().unwrap().len() as u64,
+        }))
+    }
+
+    async fn submit_transaction(
+        &self,
+        request: Request<SubmitTransactionRequest>,
+    ) -> Result<Response<Entry>, Status> {
+        let transaction = parse_json("transaction_json", &request.into_inner().transaction_json)?;
+        if transaction.get("signature").is_none_or(|s| s.is_null()) {
+            return Err(Status::invalid_argument("Transaction is not signed"));
+        }
+
+        self.add_entry(json!({ "type": "transaction", "transaction": transaction }), None).await
+    }
+
+    async fn get_ledger(&self, request: Request<GetLedgerRequest>) -> Result<Response<Ledger>, Status> {
+        let request = request.into_inner();
+        let offset = request.offset as usize;
+        let limit = match request.limit {
+            0 => MAX_SYNC_PAGE_SIZE,
+            limit => (limit as usize).min(MAX_SYNC_PAGE_SIZE),
+        };
+
+        let (page, entry_count) = self
+            .p2p
+            .ledger
+            .with_entries(|entries| (entries.iter().skip(offset).take(limit).cloned().collect(), entries.len()));
+        let entries = self.p2p.rehydrate(page).await;
+
+        Ok(Response::new(Ledger {
+            entries: entries.into_iter().map(Into::into).collect(),
+            entry_count: entry_count as u64,
+        }))
+    }
+
+    type StreamEntriesStream = EntryStream;
+
+    async fn stream_entries(
+        &self,
+        request: Request<StreamEntriesRequest>,
+    ) -> Result<Response<Self::StreamEntriesStream>, Status> {
+        let since = parse_timestamp("since", &request.into_inner().since)?;
+
+        // Subscribe before reading the ledger so no entry falls in between,
+        // then skip the entries added in the meantime, which are streamed as held
+        let before = self.p2p.ledger.chain_tip().height;
+        let live = BroadcastStream::new(self.p2p.ledger.subscribe());
+        let (from, height) = self.held_range(since);
+        let sent: HashSet<String> = if height > before {
+            self.p2p.ledger.sync_page(before + 1, height - before).entries.into_iter().map(|e| e.id).collect()
+        } else {
+            HashSet::new()
+        };
+
+        let live = live.filter_map(move |entry| match entry {
+            Ok(entry) if !sent.contains(&entry.id) => Some(Ok(entry.into())),
+            // Either already sent, or missed by falling too far behind
+            _ => None,
+        });
+        let stream = held_entries(self.p2p.clone(), from, height, since).chain(live);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_peers(&self, _request: Request<GetPeersRequest>) -> Result<Response<Peers>, Status> {
+        let mut node_ids: Vec<String> = self
+            .p2p
+            .ledger
+            .get_known_nodes()
+            .into_iter()
+            .filter(|node_id| node_id != self.p2p.node_id())
+            .collect();
+        node_ids.sort();
+
+        let peers = node_ids
+            .into_iter()
+            .map(|node_id| {
+                let protocol_version = self.p2p.peer_version(&node_id);
+                Peer { node_id, connected: protocol_version.is_some(), protocol_version }
+            })
+            .collect();
+        Ok(Response::new(Peers { peers }))
+    }
+}
@@ -0,0 +1,154 @@
+//! Liveness and readiness probes at `/healthz` and `/readyz`, for running
+//! nodes under Kubernetes and similar orchestrators.
+//!
+//! Both answer with the same [`HealthReport`]. `/healthz` succeeds as long as
+//! the node can answer at all; `/readyz` answers `503 Service Unavailable`
+//! until the ledger is initialized, while the iroh endpoint is closed, once
+//! a supervised background task has failed for good, and once the node
+//! starts shutting down.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use iroh::Endpoint;
+use serde::{Deserialize, Serialize};
+
+use crate::p2p::P2PManager;
+use crate::supervisor::{TaskHealth, Tasks};
+
+/// State of the ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerHealth {
+    /// Whether the ledger is set up, including restoring a checkpoint
+    pub initialized: bool,
+    pub height: usize,
+}
+
+/// State of the iroh endpoint used for blobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrohHealth {
+    pub node_id: String,
+    pub closed: bool,
+    /// Relay the endpoint is connected through, once it has one
+    pub home_relay: Option<String>,
+}
+
+/// Number of connected peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerCount {
+    pub inbound: usize,
+    pub outbound: usize,
+}
+
+/// What the probes report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// Whether the node should be sent traffic
+    pub ready: bool,
+    pub node_id: String,
+    pub ledger: LedgerHealth,
+    /// Absent for nodes running without iroh
+    pub iroh: Option<IrohHealth>,
+    pub peers: PeerCount,
+    /// When the ledger last finished syncing with a peer
+    pub last_sync: Option<DateTime<Utc>>,
+    /// Supervised background tasks by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tasks: BTreeMap<String, TaskHealth>,
+    /// Whether the node is shutting down
+    pub draining: bool,
+}
+
+/// Tracks what the node's readiness depends on
+pub struct Health {
+    p2p: Arc<P2PManager>,
+    endpoint: Option<Endpoint>,
+    tasks: Tasks,
+    ledger_initialized: AtomicBool,
+    draining: AtomicBool,
+}
+
+impl Health {
+    /// Report on the node behind `p2p`, which isn't ready until [`Health::set_ledger_initialized`] is called
+    pub fn new(p2p: Arc<P2PManager>) -> Self {
+        Self {
+            p2p,
+            endpoint: None,
+            tasks: Tasks::new(),
+            ledger_initialized: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// Report on an iroh endpoint too, and only be ready while it is open
+    pub fn with_endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    /// Report on the background tasks supervised with `tasks`, and stop
+    /// being ready once one of them fails for good
+    pub fn with_tasks(mut self, tasks: Tasks) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Mark the ledger as set up
+    pub fn set_ledger_initialized(&self) {
+        self.ledger_initialized.store(true, Ordering::Relaxed);
+    }
+
+    /// Mark the node as shutting down, so it stops being ready
+    pub fn set_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Check every dependency
+    pub fn report(&self) -> HealthReport {
+        let initialized = self.ledger_initialized.load(Ordering::Relaxed);
+        let draining = self.draining.load(Ordering::Relaxed);
+        let iroh = self.endpoint.as_ref().map(|endpoint| IrohHealth {
+            node_id: endpoint.node_id().to_string(),
+            closed: endpoint.is_closed(),
+            home_relay: endpoint.home_relay().get().ok().flatten().map(|url| url.to_string()),
+        });
+
+        HealthReport {
+            ready: initialized
+                && !draining
+                && !iroh.as_ref().is_some_and(|iroh| iroh.closed)
+                && !self.tasks.any_failed(),
+            node_id: self.p2p.node_id().to_string(),
+            ledger: LedgerHealth { initialized, height: self.p2p.ledger.chain_tip().height },
+            iroh,
+            peers: PeerCount {
+                inbound: self.p2p.peer_health().len(),
+                outbound: self.p2p.outbound_peers().len(),
+            },
+            last_sync: self.p2p.last_sync(),
+            tasks: self.tasks.report(),
+            draining,
+        }
+    }
+}
+
+/// Build the `/healthz` and `/readyz` routes, which are left unprotected
+pub fn router(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+async fn healthz(State(health): State<Arc<Health>>) -> Json<HealthReport> {
+    Json(health.report())
+}
+
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report();
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
@@ -0,0 +1,36 @@
+//! Node identity.
+//!
+//! A node is known by its ed25519 public key, hex-encoded. The same key is
+//! the secret key of the node's iroh endpoint, so the node's iroh `NodeId`
+//! and the ID it signs entries, sends P2P messages and appears in known
+//! nodes under are one key, and a peer found over iroh can be matched with
+//! its P2P connection.
+
+use ed25519_dalek::SigningKey;
+use iroh::{NodeId, SecretKey};
+
+/// The ID of the node holding `key`
+pub fn node_id(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+/// The iroh secret key of the node holding `key`, which gives its endpoint
+/// the node's ID
+pub fn iroh_secret_key(key: &SigningKey) -> SecretKey {
+    SecretKey::from_bytes(&key.to_bytes())
+}
+
+/// The node ID of the node at an iroh `NodeId`
+pub fn from_iroh(node_id: &NodeId) -> String {
+    hex::encode(node_id.as_bytes())
+}
+
+/// The iroh `NodeId` of the node with `node_id`, if it is a key
+pub fn to_iroh(node_id: &str) -> Option<NodeId> {
+    node_id.parse().ok()
+}
+
+/// Whether `node_id` is the ID of the node holding the hex-encoded `public_key`
+pub fn is_key_of(node_id: &str, public_key: &str) -> bool {
+    node_id.eq_ignore_ascii_case(public_key)
+}
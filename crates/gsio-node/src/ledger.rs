@@ -1,119 +1,825 @@
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Instant;
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use gsio_types::parse_public_key;
+use rand::rngs::OsRng;
+use tokio::sync::broadcast;
+use tracing::{field, info_span, warn};
 
-/// Represents a single entry in the distributed ledger
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LedgerEntry {
-    /// Unique identifier for the entry
-    pub id: String,
-    /// Timestamp when the entry was created
-    pub timestamp: DateTime<Utc>,
-    /// The actual data stored in the entry
-    pub data: serde_json::Value,
-    /// Hash of the previous entry in the chain
-    pub previous_hash: String,
-    /// Hash of this entry
-    pub hash: String,
-    /// Node ID that created this entry
-    pub creator_node_id: String,
-    /// Signatures from nodes that have validated this entry
-    pub signatures: HashMap<String, String>,
-}
-
-impl LedgerEntry {
-    /// Create a new ledger entry
-    pub fn new(
-        data: serde_json::Value,
-        previous_hash: String,
-        creator_node_id: String,
-    ) -> Self {
-        let timestamp = Utc::now();
-        let id = format!("{}-{}", creator_node_id, timestamp.timestamp_millis());
-
-        let mut entry = Self {
-            id,
-            timestamp,
-            data,
-            previous_hash,
-            hash: String::new(),
-            creator_node_id,
-            signatures: HashMap::new(),
-        };
+use crate::acl::{AclConfig, WriteAcl};
+use crate::consensus::{Consensus, LongestChain, ValidatorSet};
+use crate::genesis::{Genesis, GenesisInfo};
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::offload::BlobRef;
+use crate::rotation::{KeyRotation, KeyRotations, RetiredKey};
+use crate::schema::SchemaRegistry;
+use crate::staking::{StakeInfo, Stakes, StakingConfig};
+use crate::validation::{ErrorCode, PolicySet, ValidationError, ValidationPolicy};
+
+pub use gsio_types::{EntryHeader, LedgerEntry, Query, QueryPage};
+
+/// Previous hash of the first entry in a chain started without a genesis file
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Width in seconds of the timestamp buckets entries are indexed under
+pub const TIMESTAMP_BUCKET_SECS: i64 = 60;
+
+/// The `[limits]` section of the config file: how much a node takes in, so
+/// a hostile peer can't exhaust its memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Largest entry accepted, serialized, in bytes
+    pub max_entry_bytes: usize,
+    /// Most entries held pending at once
+    pub max_pending_entries: usize,
+    /// Most entries a peer may send in one sync page or reorg
+    pub max_sync_entries: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_entry_bytes: 4 * 1024 * 1024,
+            max_pending_entries: 10_000,
+            max_sync_entries: 500,
+        }
+    }
+}
+
+/// How a ledger keeps its entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerMode {
+    /// A hash chain nodes agree on, resolving forks through the consensus strategy
+    #[default]
+    Chain,
+    /// A grow-only set of entries keyed by hash that nodes merge, for apps
+    /// that don't need a total order. Entries link to the genesis rather
+    /// than to each other, so there are no forks, and each node holds them
+    /// in the order it learned them.
+    Crdt,
+}
 
-        // Calculate the hash of this entry
-        entry.hash = entry.calculate_hash();
+/// How much history a ledger keeps before older entries are pruned
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RetentionPolicy {
+    /// Never prune entries
+    #[default]
+    KeepForever,
+    /// Prune entries older than the given number of days
+    KeepDays { days: u32 },
+    /// Keep only the most recent entries
+    KeepLast { entries: usize },
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
 
-        entry
+    /// Parse `forever`, `days:<n>` or `last:<n>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid retention policy: {s}");
+        match s.split_once(':') {
+            None if s == "forever" => Ok(RetentionPolicy::KeepForever),
+            Some(("days", n)) => Ok(RetentionPolicy::KeepDays { days: n.parse().map_err(|_| invalid())? }),
+            Some(("last", n)) => Ok(RetentionPolicy::KeepLast { entries: n.parse().map_err(|_| invalid())? }),
+            _ => Err(invalid()),
+        }
     }
+}
+
+/// Retention metadata so clients know what history is available
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionInfo {
+    /// The policy the ledger is pruned with
+    pub policy: RetentionPolicy,
+    /// Number of entries pruned since the node started
+    pub pruned_entries: usize,
+    /// ID of the oldest entry still held
+    pub oldest_entry_id: Option<String>,
+    /// Timestamp of the oldest entry still held
+    pub oldest_timestamp: Option<DateTime<Utc>>,
+}
+
+/// A competing branch of pending entries that forks off the chain below its tip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fork {
+    /// Hash of the last entry the branch shares with the chain
+    pub fork_point: String,
+    /// Entries on the competing branch, oldest first
+    pub branch: Vec<LedgerEntry>,
+}
+
+/// Result of switching the chain over to a competing branch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reorg {
+    /// Hash of the last entry shared by the old and new chains
+    pub fork_point: String,
+    /// Entries removed from the chain, oldest first
+    pub rolled_back: Vec<LedgerEntry>,
+    /// Entries from the winning branch that replaced them
+    pub applied: Vec<LedgerEntry>,
+}
+
+/// A page of entry headers along with the Merkle root of the whole chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerHeaders {
+    /// Number of entries in the chain
+    pub length: usize,
+    /// Merkle root over all `length` entries
+    pub root: String,
+    /// The requested headers, oldest first
+    pub headers: Vec<EntryHeader>,
+}
 
-    /// Calculate the hash of this entry
+/// An inclusion proof along with the chain it was taken from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryProof {
+    /// Number of entries in the chain
+    pub length: usize,
+    /// Merkle root the proof leads to
+    pub root: String,
+    pub proof: MerkleProof,
+}
+
+/// A page of the chain sent during ledger sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPage {
+    /// Height of the first entry in the page
+    pub from: usize,
+    /// Height of the sender's chain
+    pub height: usize,
+    /// Entries in the page, oldest first
+    pub entries: Vec<LedgerEntry>,
+    /// Hashes of the entries ending each segment of the chain from `from`
+    /// on, when the peer asked for them to split the sync into segments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skeleton: Vec<String>,
+}
+
+/// Summary of a ledger's state for operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerStats {
+    /// The newest entry of the chain
+    pub tip: ChainTip,
+    /// Number of entries held, not counting pruned ones
+    pub entries: usize,
+    /// Number of entries before the oldest one held
+    pub pruned_entries: usize,
+    /// Entries received but not yet added to the chain
+    pub pending_entries: usize,
+    /// Competing branches among the pending entries
+    pub forks: usize,
+    /// Nodes this node knows of
+    pub known_nodes: usize,
+    /// Merkle root over the entries held
+    pub merkle_root: String,
+    /// When the newest entry was created
+    pub last_entry_at: Option<DateTime<Utc>>,
+    /// Name of the consensus strategy
+    pub consensus: String,
+}
+
+/// The newest entry of a chain, exchanged by peers to work out which
+/// entries the other is missing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainTip {
+    /// Hash of the newest entry, or the genesis hash for an empty chain
+    pub hash: String,
+    /// Height of the newest entry
+    pub height: usize,
+}
+
+/// Compact state of the ledger up to a height, from which a new node can
+/// carry on the chain without replaying the entries before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Number of entries in the chain up to and including the checkpoint
+    pub height: usize,
+    /// The entry at `height`, which later entries link to
+    pub checkpoint: LedgerEntry,
+    /// Hex-encoded public keys of the nodes known when the snapshot was taken
+    pub node_keys: BTreeMap<String, String>,
+    /// State the consensus strategy keeps, if any
+    pub consensus: Option<serde_json::Value>,
+    /// Hex-encoded keys on the write ACL at the checkpoint, if the ledger has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<Vec<String>>,
+    /// Number of ACL updates applied up to the checkpoint, which admins' approvals are bound to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl_sequence: Option<u64>,
+    /// Keys retired by rotations up to the checkpoint, by their hex encoding
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rotations: BTreeMap<String, RetiredKey>,
+    /// Balances and stakes by address at the checkpoint
+    #[serde(default, skip_serializing_if = "Stakes::is_empty")]
+    pub stakes: Stakes,
+    /// SHA-256 over the checkpoint hash and the other fields
+    pub hash: String,
+    /// Hex-encoded public key of the node that took the snapshot
+    pub signer: String,
+    /// The signer's signature over `hash`, hex-encoded
+    pub signature: String,
+}
+
+impl Snapshot {
+    /// Calculate the hash anchoring this snapshot
     pub fn calculate_hash(&self) -> String {
         let mut hasher = Sha256::new();
+        hasher.update(self.height.to_string().as_bytes());
+        hasher.update(self.checkpoint.hash.as_bytes());
+        for (node_id, key) in &self.node_keys {
+            hasher.update(node_id.as_bytes());
+            hasher.update(key.as_bytes());
+        }
+        if let Some(state) = &self.consensus {
+            hasher.update(state.to_string().as_bytes());
+        }
+        for key in self.acl.iter().flatten() {
+            hasher.update(key.as_bytes());
+        }
+        if let Some(sequence) = self.acl_sequence {
+            hasher.update(sequence.to_string().as_bytes());
+        }
+        for (key, retired) in &self.rotations {
+            hasher.update(key.as_bytes());
+            hasher.update(retired.new_key.as_bytes());
+            hasher.update(retired.entry_id.as_bytes());
+        }
+        if !self.stakes.is_empty() {
+            hasher.update(serde_json::to_string(&self.stakes).unwrap_or_default().as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
 
-        // Hash the entry fields
-        hasher.update(self.id.as_bytes());
-        hasher.update(self.timestamp.to_rfc3339().as_bytes());
-        hasher.update(self.data.to_string().as_bytes());
-        hasher.update(self.previous_hash.as_bytes());
-        hasher.update(self.creator_node_id.as_bytes());
+    /// Check the snapshot's hash and its signature with `signer`, the key the
+    /// verifying node trusts `self.signer` with, then the checkpoint's hash and
+    /// its creator's signature with the key the snapshot vouches for
+    pub fn verify(&self, signer: &VerifyingKey) -> Result<(), String> {
+        if self.height == 0 {
+            return Err("Snapshot has no entries".to_string());
+        }
+        if self.hash != self.calculate_hash() {
+            return Err("Snapshot hash doesn't match its contents".to_string());
+        }
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|b| Signature::from_slice(&b).ok())
+            .ok_or("Invalid snapshot signature encoding")?;
+        if signer.verify(self.hash.as_bytes(), &signature).is_err() {
+            return Err("Snapshot has an invalid signature".to_string());
+        }
+        if !self.checkpoint.is_valid() {
+            return Err("Snapshot checkpoint has an invalid hash".to_string());
+        }
 
-        // Convert the hash to a hex string
-        format!("{:x}", hasher.finalize())
+        let creator = &self.checkpoint.creator_node_id;
+        let key = self
+            .node_keys
+            .get(creator)
+            .ok_or_else(|| format!("Snapshot has no key for checkpoint creator {creator}"))?;
+        if !self.checkpoint.verify_signature(creator, &parse_node_key(creator, key)?) {
+            return Err("Snapshot checkpoint has an invalid signature".to_string());
+        }
+        Ok(())
+    }
+
+    /// Serialize the snapshot, e.g. to store it as an iroh blob
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("snapshots always serialize")
     }
 
-    /// Add a signature from a node that has validated this entry
-    pub fn add_signature(&mut self, node_id: String, signature: String) {
-        self.signatures.insert(node_id, signature);
+    /// Parse a snapshot serialized with [`Snapshot::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|e| format!("Invalid snapshot: {e}"))
+    }
+}
+
+/// Lookups into the chain, by the height of each entry.
+///
+/// Heights count pruned entries too, so they stay put when retention drops
+/// entries from the front of the chain.
+#[derive(Debug, Default)]
+struct EntryIndex {
+    by_id: HashMap<String, usize>,
+    by_hash: HashMap<String, usize>,
+    by_creator: HashMap<String, BTreeSet<usize>>,
+    /// Entries by [`timestamp_bucket`]; timestamps aren't ordered along the chain, since nodes' clocks differ
+    by_timestamp: BTreeMap<i64, BTreeSet<usize>>,
+}
+
+impl EntryIndex {
+    fn insert(&mut self, entry: &LedgerEntry, height: usize) {
+        self.by_id.insert(entry.id.clone(), height);
+        self.by_hash.insert(entry.hash.clone(), height);
+        self.by_creator.entry(entry.creator_node_id.clone()).or_default().insert(height);
+        self.by_timestamp.entry(timestamp_bucket(entry.timestamp)).or_default().insert(height);
     }
 
-    /// Verify that this entry is valid
-    pub fn is_valid(&self) -> bool {
-        // Check that the hash is correct
-        self.hash == self.calculate_hash()
+    fn remove(&mut self, entry: &LedgerEntry, height: usize) {
+        self.by_id.remove(&entry.id);
+        self.by_hash.remove(&entry.hash);
+        if let Some(heights) = self.by_creator.get_mut(&entry.creator_node_id) {
+            heights.remove(&height);
+            if heights.is_empty() {
+                self.by_creator.remove(&entry.creator_node_id);
+            }
+        }
+        let bucket = timestamp_bucket(entry.timestamp);
+        if let Some(heights) = self.by_timestamp.get_mut(&bucket) {
+            heights.remove(&height);
+            if heights.is_empty() {
+                self.by_timestamp.remove(&bucket);
+            }
+        }
     }
 }
 
+/// The [`TIMESTAMP_BUCKET_SECS`]-wide bucket `timestamp` falls in
+fn timestamp_bucket(timestamp: DateTime<Utc>) -> i64 {
+    timestamp.timestamp().div_euclid(TIMESTAMP_BUCKET_SECS)
+}
+
 /// The distributed ledger
 #[derive(Debug)]
 pub struct Ledger {
     /// The chain of entries in the ledger
     entries: Vec<LedgerEntry>,
+    /// Lookups into `entries` by ID, creator and timestamp
+    index: EntryIndex,
+    /// Merkle tree over `entries`, extended as they're appended
+    merkle: MerkleTree,
     /// The ID of this node
     node_id: String,
     /// Pending entries that have been received but not yet added to the chain
     pending_entries: HashMap<String, LedgerEntry>,
+    /// When each pending entry was first seen pending, to expire those that never join the chain
+    pending_since: HashMap<String, Instant>,
+    /// Highest Lamport clock of any entry seen, which this node's next entry goes past
+    clock: u64,
+    /// Whether entries form a chain or a set
+    mode: LedgerMode,
+    /// How much the ledger takes in
+    limits: Limits,
     /// Set of node IDs that are known to this node
     known_nodes: HashSet<String>,
+    /// How much history to keep
+    retention: RetentionPolicy,
+    /// Number of entries before the oldest one held, removed by retention or skipped by a snapshot
+    pruned_entries: usize,
+    /// Key this node signs its own entries with
+    signing_key: SigningKey,
+    /// Public keys of known nodes, used to verify entry signatures
+    node_keys: HashMap<String, VerifyingKey>,
+    /// Rules entry data has to satisfy
+    validation: Arc<dyn ValidationPolicy>,
+    /// Entry kinds and their schemas, checked after `validation`
+    schemas: Arc<SchemaRegistry>,
+    /// Decides who creates entries, when they join the chain and which fork wins
+    consensus: Box<dyn Consensus>,
+    /// Pending entries this node has proposed or signed, by the hash they link to
+    endorsed: HashMap<String, String>,
+    /// Keys allowed to create entries as of the tip, if writes are restricted
+    acl: Option<WriteAcl>,
+    /// The write ACL as of the entry before the oldest one held, which `acl` is replayed from
+    acl_base: Option<WriteAcl>,
+    /// Keys retired by rotations on the chain as of the tip
+    rotations: KeyRotations,
+    /// The rotations as of the entry before the oldest one held, which `rotations` is replayed from
+    rotations_base: KeyRotations,
+    /// Unbonding period of unstaked amounts
+    staking: StakingConfig,
+    /// Balances and stakes as of the tip
+    stakes: Stakes,
+    /// Balances and stakes as of the entry before the oldest one held, which `stakes` is replayed from
+    stakes_base: Stakes,
+    /// The genesis the chain starts from, if it has one
+    genesis: Option<Genesis>,
+    /// Previous hash of the first entry: the genesis hash, or [`GENESIS_HASH`] without a genesis
+    genesis_hash: String,
+    /// Network the chain belongs to, which every entry on it carries
+    chain_id: Option<String>,
+    /// Keys, besides this node's and the validators', whose snapshots it restores from
+    checkpoint_keys: Vec<VerifyingKey>,
 }
 
 impl Ledger {
-    /// Create a new ledger
+    /// Create a new ledger with a freshly generated key
     pub fn new(node_id: String) -> Self {
+        Self::with_signing_key(node_id, SigningKey::generate(&mut OsRng))
+    }
+
+    /// Create a new ledger that signs entries with `signing_key`
+    pub fn with_signing_key(node_id: String, signing_key: SigningKey) -> Self {
         let mut known_nodes = HashSet::new();
         known_nodes.insert(node_id.clone());
 
+        let mut node_keys = HashMap::new();
+        node_keys.insert(node_id.clone(), signing_key.verifying_key());
+
         Self {
             entries: Vec::new(),
+            index: EntryIndex::default(),
+            merkle: MerkleTree::default(),
             node_id,
             pending_entries: HashMap::new(),
+            pending_since: HashMap::new(),
+            clock: 0,
+            mode: LedgerMode::Chain,
+            limits: Limits::default(),
             known_nodes,
+            retention: RetentionPolicy::KeepForever,
+            pruned_entries: 0,
+            signing_key,
+            node_keys,
+            validation: Arc::new(PolicySet::new()),
+            schemas: Arc::new(SchemaRegistry::default()),
+            consensus: Box::new(LongestChain),
+            endorsed: HashMap::new(),
+            acl: None,
+            acl_base: None,
+            rotations: KeyRotations::new(),
+            rotations_base: KeyRotations::new(),
+            staking: StakingConfig::default(),
+            stakes: Stakes::new(),
+            stakes_base: Stakes::new(),
+            genesis: None,
+            genesis_hash: GENESIS_HASH.to_string(),
+            chain_id: None,
+            checkpoint_keys: Vec::new(),
         }
     }
 
-    /// Add a new entry to the ledger
-    pub fn add_entry(&mut self, data: serde_json::Value) -> Result<LedgerEntry, String> {
-        let previous_hash = match self.entries.last() {
-            Some(entry) => entry.hash.clone(),
-            None => "0".repeat(64), // Genesis block has a hash of all zeros
+    /// Get this node's public key, hex-encoded
+    pub fn public_key(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// Sign a message with this node's key, returning the hex-encoded signature
+    pub fn sign_message(&self, message: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+
+    /// Record the public key a node signs its entries with.
+    ///
+    /// A node's key can't be replaced once known, so a peer can't take over
+    /// another node's identity by announcing a different key.
+    pub fn add_node_key(&mut self, node_id: String, public_key: &str) -> Result<(), String> {
+        let key = parse_node_key(&node_id, public_key)?;
+
+        match self.node_keys.get(&node_id) {
+            Some(existing) if *existing != key => {
+                Err(format!("Node {node_id} already has a different public key"))
+            }
+            _ => {
+                self.node_keys.insert(node_id, key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the public key of a node, hex-encoded
+    pub fn get_node_key(&self, node_id: &str) -> Option<String> {
+        self.node_keys.get(node_id).map(|k| hex::encode(k.to_bytes()))
+    }
+
+    /// Hex-encoded public keys of every known node
+    pub fn node_keys(&self) -> BTreeMap<String, String> {
+        self.node_keys.iter().map(|(id, key)| (id.clone(), hex::encode(key.to_bytes()))).collect()
+    }
+
+    /// Set the rules entry data has to satisfy
+    pub fn set_validation_policy(&mut self, policy: Arc<dyn ValidationPolicy>) {
+        self.validation = policy;
+    }
+
+    /// Check entry data against the validation policy and the schema of its kind
+    ///
+    /// Blob references are refused, as the data they stand for isn't at hand;
+    /// offloaded data is checked itself once it's been matched to its reference.
+    pub fn validate(&self, data: &serde_json::Value) -> Result<(), ValidationError> {
+        if BlobRef::from_data(data).is_some() {
+            return Err(ValidationError::new(ErrorCode::Rejected, "Offloaded entry data can't be checked without its blob"));
+        }
+        self.check_rotations(data)?;
+        self.stakes.check(data, self.height() + 1)?;
+        self.validation.validate(data)?;
+        self.schemas.validate(data)
+    }
+
+    /// Check data this node adds itself, where a blob reference stands for
+    /// data that was checked before it was offloaded
+    fn validate_own(&self, data: &serde_json::Value) -> Result<(), ValidationError> {
+        if BlobRef::from_data(data).is_some() {
+            return Ok(());
+        }
+        self.validate(data)
+    }
+
+    /// Refuse data signed with a retired key and key rotations that don't
+    /// verify or conflict with the ones on the chain
+    fn check_rotations(&self, data: &serde_json::Value) -> Result<(), ValidationError> {
+        if let Some(key) = data.get("public_key").and_then(|k| k.as_str())
+            && let Some(retired) = self.rotations.retired(key)
+        {
+            return Err(ValidationError::new(
+                ErrorCode::KeyRotated,
+                format!("Key {key} was rotated to {}", retired.new_key),
+            ));
+        }
+        self.rotations.check(data).map(|_| ()).map_err(|e| ValidationError::new(ErrorCode::Rejected, e))
+    }
+
+    /// Keys retired by rotations on the chain as of the tip
+    pub fn key_rotations(&self) -> &KeyRotations {
+        &self.rotations
+    }
+
+    /// A statement retiring this node's key in favour of `new_key`, signed
+    /// with the current key; it takes effect once added as an entry
+    pub fn key_rotation(&self, new_key: &VerifyingKey) -> KeyRotation {
+        KeyRotation::new(&self.signing_key, new_key)
+    }
+
+    /// Set the entry kinds and schemas entries are checked against
+    pub fn set_schema_registry(&mut self, schemas: Arc<SchemaRegistry>) {
+        self.schemas = schemas;
+    }
+
+    /// The entry kinds and schemas entries are checked against
+    pub fn schemas(&self) -> Arc<SchemaRegistry> {
+        self.schemas.clone()
+    }
+
+    /// Set the consensus strategy; longest chain by default
+    pub fn set_consensus(&mut self, consensus: Box<dyn Consensus>) {
+        self.consensus = consensus;
+    }
+
+    /// Set whether entries form a chain or a set; only an empty ledger can switch
+    pub fn set_mode(&mut self, mode: LedgerMode) -> Result<(), String> {
+        if mode != self.mode && (self.height() > 0 || !self.pending_entries.is_empty()) {
+            return Err("Only an empty ledger can change its mode".to_string());
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Whether entries form a chain or a set
+    pub fn mode(&self) -> LedgerMode {
+        self.mode
+    }
+
+    /// Set how much the ledger takes in
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// How much the ledger takes in
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Set how long unstaked amounts stay locked; only affects later unstakes
+    pub fn set_staking_config(&mut self, staking: StakingConfig) {
+        self.staking = staking;
+    }
+
+    /// The balance and stake of `address` as of the tip
+    pub fn stake(&self, address: &str) -> Result<StakeInfo, String> {
+        self.stakes.info(address, self.height())
+    }
+
+    /// Start the chain from `genesis`: the first entry links to its hash and
+    /// addresses start with its allocations. Only an empty chain can be given one.
+    pub fn set_genesis(&mut self, genesis: Genesis) -> Result<(), String> {
+        if !self.is_empty_chain() {
+            return Err("Only an empty chain can be given a genesis".to_string());
+        }
+        if self.chain_id.as_ref().is_some_and(|chain_id| *chain_id != genesis.chain_id) {
+            return Err(format!("The genesis is for chain {}, not {}", genesis.chain_id, self.chain_id.as_deref().unwrap_or_default()));
+        }
+        let stakes = Stakes::with_balances(&genesis.allocations)?;
+        self.stakes_base = stakes.clone();
+        self.stakes = stakes;
+        self.genesis_hash = genesis.hash();
+        self.chain_id = Some(genesis.chain_id.clone());
+        self.genesis = Some(genesis);
+        Ok(())
+    }
+
+    /// Put the chain on the network `chain_id`, so its entries carry it and
+    /// entries from other networks are refused. Only an empty chain can be
+    /// moved, and a chain with a genesis stays on the genesis chain ID.
+    pub fn set_chain_id(&mut self, chain_id: Option<String>) -> Result<(), String> {
+        if !self.is_empty_chain() {
+            return Err("Only an empty chain can be given a chain ID".to_string());
+        }
+        if let Some(genesis) = &self.genesis
+            && chain_id.as_ref() != Some(&genesis.chain_id)
+        {
+            return Err(format!("The genesis is for chain {}", genesis.chain_id));
+        }
+        self.chain_id = chain_id;
+        Ok(())
+    }
+
+    /// Restore from snapshots signed with these hex-encoded keys too, not
+    /// only from ones this node or a validator signed
+    pub fn set_checkpoint_keys(&mut self, keys: &[String]) -> Result<(), String> {
+        self.checkpoint_keys = keys
+            .iter()
+            .map(|key| parse_node_key(key, key).map_err(|_| format!("Invalid checkpoint key {key}")))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Network the chain belongs to, if it has a chain ID
+    pub fn chain_id(&self) -> Option<&str> {
+        self.chain_id.as_deref()
+    }
+
+    /// Refuse an entry from another network
+    pub fn check_chain(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        if entry.chain_id != self.chain_id {
+            return Err(ValidationError::new(
+                ErrorCode::WrongChain,
+                format!(
+                    "Entry is on chain {}, this ledger on {}",
+                    entry.chain_id.as_deref().unwrap_or("none"),
+                    self.chain_id.as_deref().unwrap_or("none")
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether no entry was ever added, pruned or left pending
+    fn is_empty_chain(&self) -> bool {
+        self.entries.is_empty() && self.pruned_entries == 0 && self.pending_entries.is_empty()
+    }
+
+    /// The genesis the chain starts from, if it has one
+    pub fn genesis(&self) -> Option<&Genesis> {
+        self.genesis.as_ref()
+    }
+
+    /// Previous hash of the first entry, which peers have to share
+    pub fn genesis_hash(&self) -> &str {
+        &self.genesis_hash
+    }
+
+    /// Whether `sender` spent `nonce` on a transaction on the chain
+    pub fn is_spent(&self, sender: &str, nonce: u64) -> bool {
+        self.stakes.is_spent(sender, nonce)
+    }
+
+    /// Check that an entry from a peer is within the size limit and that
+    /// there is room for it among the pending entries
+    pub fn check_limits(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        let size = serde_json::to_vec(entry).map_or(usize::MAX, |bytes| bytes.len());
+        if size > self.limits.max_entry_bytes {
+            return Err(ValidationError::new(
+                ErrorCode::PayloadTooLarge,
+                format!("Entry of {size} bytes is over the {} byte limit", self.limits.max_entry_bytes),
+            ));
+        }
+        if !self.pending_entries.contains_key(&entry.id) && self.pending_entries.len() >= self.limits.max_pending_entries {
+            return Err(ValidationError::new(
+                ErrorCode::PendingFull,
+                format!("Already holding {} pending entries", self.pending_entries.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The consensus strategy in use
+    pub fn consensus(&self) -> &dyn Consensus {
+        self.consensus.as_ref()
+    }
+
+    /// The current validator set, if the consensus strategy has one
+    pub fn validators(&self) -> Option<&ValidatorSet> {
+        self.consensus.validators()
+    }
+
+    /// Whether the consensus strategy lets this node create entries
+    pub fn can_propose(&self) -> bool {
+        self.consensus.can_propose(&self.node_id)
+    }
+
+    /// Restrict who may create entries to the holders of the keys on `acl`.
+    ///
+    /// Set it before entries are added; the list then changes only through
+    /// ACL updates on the chain.
+    pub fn set_write_acl(&mut self, acl: WriteAcl) {
+        self.acl_base = Some(acl.clone());
+        self.acl = Some(acl);
+    }
+
+    /// The write ACL as of the tip, if writes are restricted
+    pub fn write_acl(&self) -> Option<&WriteAcl> {
+        self.acl.as_ref()
+    }
+
+    /// Whether the write ACL, if any, lets this node create entries
+    pub fn can_write(&self) -> bool {
+        self.acl.as_ref().is_none_or(|acl| acl.allows_key(&self.signing_key.verifying_key()))
+    }
+
+    /// Refuse an entry from a peer whose creator isn't on the write ACL.
+    ///
+    /// Only entries that link to the tip are refused, as an ACL update on the
+    /// way to a later entry may still let it in.
+    pub fn check_writer(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        match &self.acl {
+            Some(acl) if self.links_to_tip(entry) && !acl.allows(entry) => Err(ValidationError::new(
+                ErrorCode::NotWriter,
+                format!("Creator {} isn't on the write ACL", entry.creator_node_id),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether an entry would be added next: in a set every entry is, in a chain the ones linking to the tip
+    fn links_to_tip(&self, entry: &LedgerEntry) -> bool {
+        self.mode == LedgerMode::Crdt || entry.previous_hash == self.tip_hash()
+    }
+
+    /// Whether an entry with a valid hash and creator signature may join the
+    /// chain: the consensus strategy approves it, its creator is on the write
+    /// ACL and the creator's key hasn't been rotated away
+    fn is_approved(&self, entry: &LedgerEntry) -> bool {
+        self.consensus.is_approved(entry)
+            && self.acl.as_ref().is_none_or(|acl| acl.allows(entry))
+            && !self.signs_with_retired_key(&entry.creator_node_id)
+    }
+
+    /// Whether the key a node's signatures are checked with was rotated away
+    fn signs_with_retired_key(&self, node_id: &str) -> bool {
+        self.signer_key(node_id).is_some_and(|key| self.rotations.is_retired(&hex::encode(key.to_bytes())))
+    }
+
+    /// Add a new entry to the ledger, if its data passes the validation policy.
+    ///
+    /// An entry the consensus strategy doesn't approve yet, such as one still
+    /// waiting for validator signatures, stays pending, and this node's later
+    /// entries are built on top of it.
+    pub fn add_entry(&mut self, data: serde_json::Value) -> Result<LedgerEntry, ValidationError> {
+        if !self.can_propose() {
+            return Err(ValidationError::new(ErrorCode::NotValidator, "Only validators can create entries"));
+        }
+        if !self.can_write() {
+            return Err(ValidationError::new(ErrorCode::NotWriter, "This node's key isn't on the write ACL"));
+        }
+        if let Some(retired) = self.rotations.retired(&self.public_key()) {
+            return Err(ValidationError::new(
+                ErrorCode::KeyRotated,
+                format!("This node's key was rotated to {}; restart it with the new key", retired.new_key),
+            ));
+        }
+        self.validate_own(&data)?;
+        let size = data.to_string().len();
+        if size > self.limits.max_entry_bytes {
+            return Err(ValidationError::new(
+                ErrorCode::PayloadTooLarge,
+                format!("Entry data of {size} bytes is over the {} byte limit", self.limits.max_entry_bytes),
+            ));
+        }
+        if let Some(acl) = &self.acl {
+            acl.check(&data).map_err(|e| ValidationError::new(ErrorCode::Rejected, e))?;
+        }
+
+        let mut previous_hash = match self.mode {
+            LedgerMode::Chain => self.tip_hash().to_string(),
+            LedgerMode::Crdt => self.genesis_hash.clone(),
         };
+        while let Some(proposed) = self.endorsed.get(&previous_hash).and_then(|id| self.pending_entries.get(id)) {
+            previous_hash = proposed.hash.clone();
+        }
 
-        let entry = LedgerEntry::new(data, previous_hash, self.node_id.clone());
+        // A peer can send an entry with the highest clock there is
+        self.clock = self.clock.saturating_add(1);
+        let mut entry = LedgerEntry::new(data, previous_hash, self.node_id.clone())
+            .with_clock(self.clock)
+            .with_chain_id(self.chain_id.clone());
+        entry.sign(self.node_id.clone(), &self.signing_key);
 
-        // Add the entry to the chain
-        self.entries.push(entry.clone());
+        if self.links_to_tip(&entry) && self.is_approved(&entry) {
+            // Add the entry to the chain
+            self.append(entry.clone());
+        } else {
+            if self.mode == LedgerMode::Chain {
+                self.endorsed.insert(entry.previous_hash.clone(), entry.id.clone());
+            }
+            self.pending_entries.insert(entry.id.clone(), entry.clone());
+        }
 
         Ok(entry)
     }
@@ -123,120 +829,1435 @@ impl Ledger {
         &self.entries
     }
 
-    /// Get the last entry in the ledger
-    pub fn get_last_entry(&self) -> Option<&LedgerEntry> {
-        self.entries.last()
+    /// Get up to `limit` entries starting at position `offset` in the chain
+    pub fn get_entries_paginated(&self, offset: usize, limit: usize) -> &[LedgerEntry] {
+        let start = offset.min(self.entries.len());
+        let end = start.saturating_add(limit).min(self.entries.len());
+        &self.entries[start..end]
     }
 
-    /// Add a pending entry that has been received from another node
-    pub fn add_pending_entry(&mut self, entry: LedgerEntry) {
-        self.pending_entries.insert(entry.id.clone(), entry);
+    /// Get all entries created strictly after `timestamp`, in chain order
+    pub fn get_entries_since(&self, timestamp: DateTime<Utc>) -> Vec<&LedgerEntry> {
+        let mut heights: Vec<usize> = self
+            .index
+            .by_timestamp
+            .range(timestamp_bucket(timestamp)..)
+            .flat_map(|(_, heights)| heights.iter().copied())
+            .collect();
+        heights.sort_unstable();
+        heights.into_iter().map(|height| self.at_height(height)).filter(|e| e.timestamp > timestamp).collect()
     }
 
-    /// Process pending entries and add them to the chain if they are valid
-    pub fn process_pending_entries(&mut self) -> Vec<LedgerEntry> {
-        let mut added_entries = Vec::new();
+    /// Height of the first entry on the chain created after `timestamp`
+    pub fn first_height_since(&self, timestamp: DateTime<Utc>) -> Option<usize> {
+        self.index
+            .by_timestamp
+            .range(timestamp_bucket(timestamp)..)
+            .flat_map(|(_, heights)| heights.iter().copied())
+            .filter(|&height| self.at_height(height).timestamp > timestamp)
+            .min()
+    }
 
-        // Get the current last entry in the chain
-        let last_entry = match self.entries.last() {
-            Some(entry) => entry.clone(),
-            None => return added_entries,
-        };
+    /// Get an entry by its ID
+    pub fn get_entry_by_id(&self, id: &str) -> Option<&LedgerEntry> {
+        self.index.by_id.get(id).map(|&height| self.at_height(height))
+    }
 
-        // Find pending entries that link to the last entry
-        let mut entries_to_process: Vec<LedgerEntry> = self.pending_entries
-            .values()
-            .filter(|e| e.previous_hash == last_entry.hash)
-            .cloned()
-            .collect();
+    /// Get an entry on the chain by its hash
+    pub fn get_entry_by_hash(&self, hash: &str) -> Option<&LedgerEntry> {
+        self.index.by_hash.get(hash).map(|&height| self.at_height(height))
+    }
 
-        // Sort by timestamp to ensure deterministic ordering
-        entries_to_process.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    /// Get the entries created by `node_id`, in chain order
+    pub fn find_by_creator(&self, node_id: &str) -> Vec<&LedgerEntry> {
+        self.index
+            .by_creator
+            .get(node_id)
+            .map(|heights| heights.iter().map(|&height| self.at_height(height)).collect())
+            .unwrap_or_default()
+    }
 
-        // Process each entry
-        for entry in entries_to_process {
-            if entry.is_valid() {
-                // Add the entry to the chain
-                self.entries.push(entry.clone());
-                // Remove from pending
-                self.pending_entries.remove(&entry.id);
-                // Add to the list of added entries
-                added_entries.push(entry);
-            }
-        }
+    /// Up to `limit` candidates for `query`'s results after height `after`,
+    /// in chain order, along with their heights.
+    ///
+    /// These are the entries matching the query, and offloaded entries whose
+    /// creator and timestamp match, since their filters can only be checked
+    /// once their data has been fetched. Callers page through the candidates
+    /// by passing the height of the last one they got as `after`.
+    pub fn query(&self, query: &Query, after: usize, limit: usize) -> Vec<(usize, &LedgerEntry)> {
+        let from = (after + 1).max(self.pruned_entries + 1);
+        let heights: Box<dyn Iterator<Item = usize>> = match (&query.creator, query.since) {
+            (Some(creator), _) => match self.index.by_creator.get(creator) {
+                Some(heights) => Box::new(heights.range(from..).copied()),
+                None => Box::new(std::iter::empty()),
+            },
+            (None, Some(since)) => match self.first_height_since(since) {
+                Some(first) => Box::new(from.max(first)..=self.height()),
+                None => Box::new(std::iter::empty()),
+            },
+            (None, None) => Box::new(from..=self.height()),
+        };
 
-        added_entries
+        heights
+            .map(|height| (height, self.at_height(height)))
+            .filter(|(_, e)| query.matches_metadata(e) && (e.pending_blob().is_some() || query.matches_data(&e.data)))
+            .take(limit)
+            .collect()
     }
 
-    /// Add a known node to the network
-    pub fn add_known_node(&mut self, node_id: String) {
-        self.known_nodes.insert(node_id);
+    /// The held entry at `height`, which has to be indexed
+    fn at_height(&self, height: usize) -> &LedgerEntry {
+        &self.entries[height - self.pruned_entries - 1]
     }
 
-    /// Get all known nodes in the network
-    pub fn get_known_nodes(&self) -> &HashSet<String> {
-        &self.known_nodes
+    /// Position in `entries` of the held entry with `hash`
+    fn index_of(&self, hash: &str) -> Option<usize> {
+        self.index.by_hash.get(hash).map(|&height| height - self.pruned_entries - 1)
     }
-}
 
-/// Thread-safe wrapper around the ledger
-#[derive(Clone)]
-pub struct SharedLedger {
-    ledger: Arc<Mutex<Ledger>>,
-}
+    /// Get the last entry in the ledger
+    pub fn get_last_entry(&self) -> Option<&LedgerEntry> {
+        self.entries.last()
+    }
 
-impl SharedLedger {
-    /// Create a new shared ledger
-    pub fn new(node_id: String) -> Self {
-        Self {
-            ledger: Arc::new(Mutex::new(Ledger::new(node_id))),
-        }
+    /// The Merkle tree over the entries this ledger holds
+    pub fn merkle_tree(&self) -> &MerkleTree {
+        &self.merkle
     }
 
-    /// Get a clone of the ledger Arc
-    pub fn clone_ledger(&self) -> Arc<Mutex<Ledger>> {
-        self.ledger.clone()
+    /// Get the Merkle root of the entries this ledger holds
+    pub fn merkle_root(&self) -> String {
+        self.merkle_tree().root()
     }
 
-    /// Add a new entry to the ledger
-    pub fn add_entry(&self, data: serde_json::Value) -> Result<LedgerEntry, String> {
-        let mut ledger = self.ledger.lock().unwrap();
-        ledger.add_entry(data)
+    /// Tag that changes whenever the entries this ledger holds do: the
+    /// tip's hash and how many entries before the first held one were pruned
+    pub fn version(&self) -> String {
+        format!("{}-{}", self.tip_hash(), self.pruned_entries)
     }
 
-    /// Get all entries in the ledger
-    pub fn get_entries(&self) -> Vec<LedgerEntry> {
-        let ledger = self.ledger.lock().unwrap();
-        ledger.get_entries().clone()
+    /// Get a proof that an entry is included under the current Merkle root
+    pub fn get_inclusion_proof(&self, id: &str) -> Option<MerkleProof> {
+        let index = self.index.by_id.get(id)? - self.pruned_entries - 1;
+        let entry = &self.entries[index];
+        Some(MerkleProof {
+            entry_id: entry.id.clone(),
+            entry_hash: entry.hash.clone(),
+            index,
+            path: self.merkle_tree().path(index)?,
+        })
     }
 
-    /// Get the last entry in the ledger
-    pub fn get_last_entry(&self) -> Option<LedgerEntry> {
-        let ledger = self.ledger.lock().unwrap();
-        ledger.get_last_entry().cloned()
+    /// Get up to `limit` headers starting at position `offset` in the chain
+    pub fn get_headers(&self, offset: usize, limit: usize) -> LedgerHeaders {
+        LedgerHeaders {
+            length: self.entries.len(),
+            root: self.merkle_root(),
+            headers: self.get_entries_paginated(offset, limit).iter().map(LedgerEntry::header).collect(),
+        }
     }
 
-    /// Add a pending entry that has been received from another node
-    pub fn add_pending_entry(&self, entry: LedgerEntry) {
-        let mut ledger = self.ledger.lock().unwrap();
-        ledger.add_pending_entry(entry);
+    /// Get an inclusion proof for an entry together with the root it leads to
+    pub fn get_entry_proof(&self, id: &str) -> Option<EntryProof> {
+        let proof = self.get_inclusion_proof(id)?;
+        Some(EntryProof {
+            length: self.entries.len(),
+            root: self.merkle_root(),
+            proof,
+        })
     }
 
-    /// Process pending entries and add them to the chain if they are valid
-    pub fn process_pending_entries(&self) -> Vec<LedgerEntry> {
-        let mut ledger = self.ledger.lock().unwrap();
-        ledger.process_pending_entries()
+    /// Get the entries waiting to be added, ordered by Lamport clock then hash
+    pub fn get_pending_entries(&self) -> Vec<&LedgerEntry> {
+        let mut pending: Vec<&LedgerEntry> = self.pending_entries.values().collect();
+        pending.sort_by(|a, b| (a.clock, &a.hash).cmp(&(b.clock, &b.hash)));
+        pending
     }
 
-    /// Add a known node to the network
-    pub fn add_known_node(&self, node_id: String) {
-        let mut ledger = self.ledger.lock().unwrap();
-        ledger.add_known_node(node_id);
+    /// Add a pending entry that has been received from another node.
+    ///
+    /// Valid signatures on an entry that is already pending are merged into
+    /// it. Returns whether the entry or any signatures were new.
+    pub fn add_pending_entry(&mut self, entry: LedgerEntry) -> bool {
+        // Peers resend entries we already have during sync
+        if self.index.by_id.contains_key(&entry.id) {
+            return false;
+        }
+        if let Err(error) = self.check_chain(&entry).and_then(|()| self.check_limits(&entry)) {
+            warn!(entry_id = entry.id, creator = entry.creator_node_id, "Dropping entry: {}", error);
+            return false;
+        }
+        let Some(pending) = self.pending_entries.get(&entry.id) else {
+            self.clock = self.clock.max(entry.clock);
+            self.pending_entries.insert(entry.id.clone(), entry);
+            return true;
+        };
+        if pending.hash != entry.hash {
+            return false;
+        }
+
+        let new_signatures: Vec<(String, String)> = entry
+            .signatures
+            .iter()
+            .filter(|(node_id, _)| {
+                self.signer_key(node_id)
+                    .is_some_and(|key| !pending.verify_signature(node_id, key) && entry.verify_signature(node_id, key))
+            })
+            .map(|(node_id, signature)| (node_id.clone(), signature.clone()))
+            .collect();
+        let pending = self.pending_entries.get_mut(&entry.id).expect("entry is pending");
+        for (node_id, signature) in &new_signatures {
+            pending.add_signature(node_id.clone(), signature.clone());
+        }
+        !new_signatures.is_empty()
     }
 
-    /// Get all known nodes in the network
-    pub fn get_known_nodes(&self) -> HashSet<String> {
-        let ledger = self.ledger.lock().unwrap();
-        ledger.get_known_nodes().clone()
+    /// Sign pending entries if the consensus strategy has this node endorse them, returning the entries signed.
+    ///
+    /// Only entries created by nodes allowed to propose that link to the tip
+    /// or to another pending entry are signed, and at most one per parent, so
+    /// competing entries can't both be approved.
+    pub fn endorse_pending_entries(&mut self) -> Vec<LedgerEntry> {
+        if !self.consensus.endorses(&self.node_id) {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<&LedgerEntry> = self
+            .pending_entries
+            .values()
+            .filter(|e| !e.signatures.contains_key(&self.node_id) && self.consensus.can_propose(&e.creator_node_id))
+            .filter(|e| self.links_to_tip(e) || self.pending_entries.values().any(|p| p.hash == e.previous_hash))
+            .filter(|e| self.check_entry(e) == Some(true))
+            .collect();
+        candidates.sort_by(|a, b| a.hash.cmp(&b.hash));
+        let candidates: Vec<String> = candidates.into_iter().map(|e| e.id.clone()).collect();
+
+        let mut endorsed = Vec::new();
+        for id in candidates {
+            let entry = self.pending_entries.get_mut(&id).expect("entry is pending");
+            // Entries in a set don't compete, so every one can be signed
+            if self.mode == LedgerMode::Crdt {
+                entry.sign(self.node_id.clone(), &self.signing_key);
+                endorsed.push(entry.clone());
+                continue;
+            }
+            if self.endorsed.contains_key(&entry.previous_hash) {
+                continue;
+            }
+            entry.sign(self.node_id.clone(), &self.signing_key);
+            self.endorsed.insert(entry.previous_hash.clone(), id);
+            endorsed.push(entry.clone());
+        }
+        endorsed
+    }
+
+    /// Process pending entries and add them to the chain if they are valid.
+    ///
+    /// When several pending branches of approved entries extend the tip, the
+    /// longest one is added. Entries are appended one at a time, so a change
+    /// to the consensus state, such as a validator update, applies to the
+    /// entries after it.
+    pub fn process_pending_entries(&mut self) -> Vec<LedgerEntry> {
+        let verified = self.drop_invalid_pending_entries();
+        if self.mode == LedgerMode::Crdt {
+            return self.merge_pending_entries(&verified);
+        }
+
+        let mut added = Vec::new();
+        loop {
+            let branch = longest_pending_branch(&self.approved_pending(&verified), self.tip_hash(), started_first);
+            if branch.is_empty() {
+                break;
+            }
+            for entry in branch {
+                // An entry earlier in the branch may have changed what is approved
+                if !self.is_approved(&entry) {
+                    break;
+                }
+                self.pending_entries.remove(&entry.id);
+                self.append(entry.clone());
+                added.push(entry);
+            }
+        }
+
+        added
+    }
+
+    /// Add every verified and approved pending entry to the set, ordered by Lamport clock
+    fn merge_pending_entries(&mut self, verified: &HashSet<String>) -> Vec<LedgerEntry> {
+        let mut ready: Vec<LedgerEntry> = self
+            .pending_entries
+            .values()
+            .filter(|e| verified.contains(&e.id) && self.is_approved(e))
+            .cloned()
+            .collect();
+        ready.sort_by(|a, b| (a.clock, &a.hash).cmp(&(b.clock, &b.hash)));
+
+        for entry in &ready {
+            self.pending_entries.remove(&entry.id);
+            self.append(entry.clone());
+        }
+        ready
+    }
+
+    /// Append an approved entry to the chain
+    fn append(&mut self, entry: LedgerEntry) {
+        self.clock = self.clock.max(entry.clock);
+        self.endorsed.remove(&entry.previous_hash);
+        self.consensus.entry_appended(&entry);
+        if let Some(acl) = &mut self.acl {
+            acl.apply(&entry);
+        }
+        self.rotations.apply(&entry);
+        self.stakes.apply(&entry, self.height() + 1, &self.staking);
+        self.index.insert(&entry, self.height() + 1);
+        self.merkle.push(&entry.hash);
+        self.entries.push(entry);
+    }
+
+    /// Rebuild the Merkle tree after entries were removed from the chain
+    fn rebuild_merkle_tree(&mut self) {
+        self.merkle = MerkleTree::new(self.entries.iter().map(|e| e.hash.as_str()));
+    }
+
+    /// Find pending branches that fork off the chain below its tip
+    pub fn detect_forks(&self) -> Vec<Fork> {
+        self.forks(&self.verified_pending())
+    }
+
+    /// Pending branches off the chain below its tip, made of the `verified` entries
+    fn forks(&self, verified: &HashSet<String>) -> Vec<Fork> {
+        if self.mode == LedgerMode::Crdt {
+            return Vec::new();
+        }
+        // Without the full history a branch off the genesis can't be placed
+        let first = if self.pruned_entries == 0 { 0 } else { 1 };
+        let children = self.approved_pending(verified);
+
+        (first..self.entries.len())
+            .map(|kept| self.hash_before(kept))
+            .filter(|fork_point| children.contains_key(fork_point))
+            .map(|fork_point| {
+                // Competing branches are weighed the way the consensus strategy
+                // breaks ties, so the branch it would pick is never passed over
+                let branch = longest_pending_branch(&children, fork_point, lower_tip);
+                Fork { fork_point: fork_point.to_string(), branch }
+            })
+            .collect()
+    }
+
+    /// Switch to a competing branch if the consensus strategy prefers it to the current chain.
+    ///
+    /// Rolled-back entries go back to pending, so they can win again if their
+    /// branch grows.
+    pub fn resolve_forks(&mut self) -> Option<Reorg> {
+        let verified = self.drop_invalid_pending_entries();
+
+        let fork = self.consensus.choose_fork(&self.entries, self.forks(&verified))?;
+        let kept = self.position_of(&fork.fork_point);
+
+        let rolled_back: Vec<LedgerEntry> = self.entries.drain(kept..).collect();
+        let first_rolled_back = self.height() + 1;
+        for (i, entry) in rolled_back.iter().enumerate() {
+            self.index.remove(entry, first_rolled_back + i);
+        }
+        self.rebuild_merkle_tree();
+        // ACL updates and key rotations on the rolled-back entries no longer apply
+        self.acl = self.acl_at(self.entries.len());
+        self.rotations = self.rotations_at(self.entries.len());
+        self.stakes = self.stakes_at(self.entries.len());
+        for entry in &fork.branch {
+            self.pending_entries.remove(&entry.id);
+            self.append(entry.clone());
+        }
+        for entry in &rolled_back {
+            self.pending_entries.insert(entry.id.clone(), entry.clone());
+        }
+
+        warn!(
+            fork_point = fork.fork_point,
+            rolled_back = rolled_back.len(),
+            applied = fork.branch.len(),
+            "Reorganized chain onto a competing branch"
+        );
+
+        Some(Reorg {
+            fork_point: fork.fork_point,
+            rolled_back,
+            applied: fork.branch,
+        })
+    }
+
+    /// The write ACL after the first `held` entries held, replayed from the base
+    fn acl_at(&self, held: usize) -> Option<WriteAcl> {
+        let mut acl = self.acl_base.clone()?;
+        for entry in &self.entries[..held] {
+            acl.apply(entry);
+        }
+        Some(acl)
+    }
+
+    /// The key rotations after the first `held` entries held, replayed from the base
+    fn rotations_at(&self, held: usize) -> KeyRotations {
+        let mut rotations = self.rotations_base.clone();
+        for entry in &self.entries[..held] {
+            rotations.apply(entry);
+        }
+        rotations
+    }
+
+    /// Balances and stakes after the first `held` entries held, replayed from the base
+    fn stakes_at(&self, held: usize) -> Stakes {
+        let mut stakes = self.stakes_base.clone();
+        for (i, entry) in self.entries[..held].iter().enumerate() {
+            stakes.apply(entry, self.pruned_entries + i + 1, &self.staking);
+        }
+        stakes
+    }
+
+    /// Hash that the next entry on the chain must link to
+    fn tip_hash(&self) -> &str {
+        self.entries.last().map_or(self.genesis_hash.as_str(), |e| e.hash.as_str())
+    }
+
+    /// Hash of the entry before position `index`, or the genesis hash for the first entry
+    fn hash_before(&self, index: usize) -> &str {
+        match index {
+            0 => self.genesis_hash.as_str(),
+            i => self.entries[i - 1].hash.as_str(),
+        }
+    }
+
+    /// Number of chain entries up to and including the entry with `hash`
+    fn position_of(&self, hash: &str) -> usize {
+        self.index_of(hash).map_or(0, |i| i + 1)
+    }
+
+    /// The `verified` pending entries the consensus strategy approves, by the hash they link to
+    fn approved_pending(&self, verified: &HashSet<String>) -> HashMap<&str, Vec<&LedgerEntry>> {
+        let mut children: HashMap<&str, Vec<&LedgerEntry>> = HashMap::new();
+        for entry in self.pending_entries.values().filter(|e| verified.contains(&e.id) && self.is_approved(e)) {
+            children.entry(entry.previous_hash.as_str()).or_default().push(entry);
+        }
+        children
+    }
+
+    /// IDs of the pending entries whose hash and creator's signature check out
+    fn verified_pending(&self) -> HashSet<String> {
+        self.pending_entries
+            .values()
+            .filter(|e| self.check_entry(e) == Some(true))
+            .map(|e| e.id.clone())
+            .collect()
+    }
+
+    /// Check an entry from a peer; `None` means the creator's key isn't known yet
+    fn check_entry(&self, entry: &LedgerEntry) -> Option<bool> {
+        let key = self.signer_key(&entry.creator_node_id)?;
+        Some(entry.is_valid() && entry.verify_signature(&entry.creator_node_id, key))
+    }
+
+    /// Key a node's signatures are checked with: the one the consensus strategy assigns, otherwise the key it announced
+    fn signer_key(&self, node_id: &str) -> Option<&VerifyingKey> {
+        self.consensus.signer_key(node_id).or_else(|| self.node_keys.get(node_id))
+    }
+
+    /// Hashes pending entries link to that are neither on the chain nor
+    /// pending, so the entries can't join the chain until a peer sends them
+    pub fn missing_parents(&self) -> Vec<String> {
+        let pending: HashSet<&str> = self.pending_entries.values().map(|e| e.hash.as_str()).collect();
+        let missing: BTreeSet<String> = self
+            .pending_entries
+            .values()
+            .map(|e| e.previous_hash.as_str())
+            .filter(|hash| *hash != self.genesis_hash && !pending.contains(hash) && self.get_entry_by_hash(hash).is_none())
+            .map(str::to_string)
+            .collect();
+        missing.into_iter().collect()
+    }
+
+    /// Drop pending entries that have waited longer than `max_age` to join
+    /// the chain, returning them.
+    ///
+    /// An entry's age counts from the first call that sees it pending.
+    pub fn expire_pending_entries(&mut self, max_age: std::time::Duration) -> Vec<LedgerEntry> {
+        let now = Instant::now();
+        self.pending_since.retain(|id, _| self.pending_entries.contains_key(id));
+        for id in self.pending_entries.keys() {
+            self.pending_since.entry(id.clone()).or_insert(now);
+        }
+
+        let expired: Vec<String> = self
+            .pending_since
+            .iter()
+            .filter(|(_, since)| now.duration_since(**since) > max_age)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| {
+                self.pending_since.remove(&id);
+                self.pending_entries.remove(&id)
+            })
+            .collect()
+    }
+
+    /// Drop pending entries with a bad hash or creator signature, and those
+    /// linking to the tip from creators not on the write ACL.
+    ///
+    /// Entries from nodes whose key we haven't learned yet stay pending.
+    fn drop_invalid_pending_entries(&mut self) -> HashSet<String> {
+        let mut verified = HashSet::new();
+        let mut invalid = Vec::new();
+        for entry in self.pending_entries.values() {
+            match self.check_entry(entry) {
+                Some(false) => invalid.push(entry.id.clone()),
+                _ if self.check_writer(entry).is_err() => invalid.push(entry.id.clone()),
+                Some(true) => {
+                    verified.insert(entry.id.clone());
+                }
+                None => {}
+            }
+        }
+
+        for id in invalid {
+            if let Some(entry) = self.pending_entries.remove(&id) {
+                warn!(entry_id = entry.id, creator = entry.creator_node_id, "Rejecting entry with invalid hash or signature");
+            }
+        }
+        verified
+    }
+
+    /// Number of entries on the chain since the genesis, including pruned ones
+    pub fn height(&self) -> usize {
+        self.pruned_entries + self.entries.len()
+    }
+
+    /// Take a snapshot of the chain up to and including the entry at `height`.
+    ///
+    /// Heights count every entry since the genesis, including pruned ones.
+    /// Strategies that keep consensus state only know it at the tip, so under
+    /// them only the tip can be snapshotted.
+    pub fn create_snapshot(&self, height: usize) -> Result<Snapshot, String> {
+        let (first, last) = (self.pruned_entries + 1, self.height());
+        if height < first || height > last {
+            return Err(format!("No entry at height {height}; this ledger holds heights {first} to {last}"));
+        }
+        let consensus = self.consensus.snapshot_state();
+        if consensus.is_some() && height != last {
+            return Err(format!("{} consensus can only be snapshotted at the tip", self.consensus.name()));
+        }
+
+        let acl = self.acl_at(height - first + 1);
+        let mut snapshot = Snapshot {
+            height,
+            checkpoint: self.entries[height - first].clone(),
+            node_keys: self.node_keys(),
+            consensus,
+            acl: acl.as_ref().map(WriteAcl::writers),
+            acl_sequence: acl.as_ref().map(WriteAcl::sequence),
+            rotations: self.rotations_at(height - first + 1).to_map(),
+            stakes: self.stakes_at(height - first + 1),
+            hash: String::new(),
+            signer: self.public_key(),
+            signature: String::new(),
+        };
+        snapshot.hash = snapshot.calculate_hash();
+        snapshot.signature = self.sign_message(snapshot.hash.as_bytes());
+        Ok(snapshot)
+    }
+
+    /// Start an empty ledger from a snapshot instead of replaying the chain.
+    ///
+    /// The checkpoint becomes the only entry held, with the entries before it
+    /// counted as pruned, so new entries and pending ones from peers link on
+    /// from there. Only snapshots signed by a key this node trusts are taken;
+    /// see [`Ledger::set_checkpoint_keys`].
+    pub fn restore_from_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        snapshot.verify(&self.snapshot_signer(snapshot)?)?;
+        if !self.entries.is_empty() {
+            return Err("Only an empty ledger can be restored from a snapshot".to_string());
+        }
+        self.check_chain(&snapshot.checkpoint).map_err(|e| e.message)?;
+
+        // Check every key before changing anything
+        let mut node_keys = self.node_keys.clone();
+        for (node_id, key) in &snapshot.node_keys {
+            let key = parse_node_key(node_id, key)?;
+            if node_keys.get(node_id).is_some_and(|existing| *existing != key) {
+                return Err(format!("Node {node_id} already has a different public key"));
+            }
+            node_keys.insert(node_id.clone(), key);
+        }
+        // Admins come from the node's own config, as they aren't changed on the chain
+        let acl = match &snapshot.acl {
+            Some(writers) => {
+                let config = self.acl.as_ref().map(WriteAcl::config).unwrap_or_default();
+                let acl = WriteAcl::new(&AclConfig { writers: writers.clone(), ..config })?;
+                Some(acl.at_sequence(snapshot.acl_sequence.unwrap_or_default()))
+            }
+            None => self.acl.clone(),
+        };
+        if let Some(state) = &snapshot.consensus {
+            self.consensus.restore_state(state)?;
+        }
+
+        self.known_nodes.extend(snapshot.node_keys.keys().cloned());
+        self.node_keys = node_keys;
+        self.entries = vec![snapshot.checkpoint.clone()];
+        self.clock = self.clock.max(snapshot.checkpoint.clock);
+        self.pruned_entries = snapshot.height - 1;
+        self.index = EntryIndex::default();
+        self.index.insert(&snapshot.checkpoint, snapshot.height);
+        self.rebuild_merkle_tree();
+        self.endorsed.clear();
+        // The base already has the checkpoint's update applied, which replaying it again doesn't change
+        self.acl_base = acl.clone();
+        self.acl = acl;
+        self.rotations_base = KeyRotations::from_map(snapshot.rotations.clone());
+        self.rotations = self.rotations_base.clone();
+        self.stakes_base = snapshot.stakes.clone();
+        self.stakes = snapshot.stakes.clone();
+        Ok(())
+    }
+
+    /// The key of a snapshot's signer, if this node trusts it: its own, a
+    /// validator's or one of the checkpoint keys. What the snapshot says
+    /// about keys isn't taken until the signature checks out.
+    fn snapshot_signer(&self, snapshot: &Snapshot) -> Result<VerifyingKey, String> {
+        let key = parse_node_key(&snapshot.signer, &snapshot.signer)?;
+        let validator = self.consensus.validators().is_some_and(|validators| {
+            validators.members().iter().any(|v| v.public_key.eq_ignore_ascii_case(&snapshot.signer))
+        });
+        if key == self.signing_key.verifying_key() || validator || self.checkpoint_keys.contains(&key) {
+            Ok(key)
+        } else {
+            Err(format!("Snapshot is signed by {}, which isn't a validator or checkpoint key", snapshot.signer))
+        }
+    }
+
+    /// Hashes that describe this chain to a peer looking for a common ancestor, newest first.
+    ///
+    /// The ten newest entries are listed, then the step back doubles each
+    /// time, so a chain of any length is described in a few dozen hashes.
+    pub fn sync_locator(&self) -> Vec<String> {
+        // Peers hold a set in their own order, so the only common point is the start
+        if self.mode == LedgerMode::Crdt {
+            return Vec::new();
+        }
+        let mut locator = Vec::new();
+        let mut step = 1;
+        let mut index = self.entries.len();
+        while index > 0 {
+            locator.push(self.entries[index - 1].hash.clone());
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            index = index.saturating_sub(step);
+        }
+        locator
+    }
+
+    /// Height of the newest entry in a peer's `locator` that is also on this chain, or 0 if none is
+    pub fn common_ancestor(&self, locator: &[String]) -> usize {
+        locator
+            .iter()
+            .find_map(|hash| self.index_of(hash))
+            .map_or(0, |index| self.pruned_entries + index + 1)
+    }
+
+    /// Up to `limit` entries starting at `height`, or at the oldest entry held if that is later
+    pub fn sync_page(&self, height: usize, limit: usize) -> SyncPage {
+        let from = height.max(self.pruned_entries + 1);
+        SyncPage {
+            from,
+            height: self.height(),
+            entries: self.get_entries_paginated(from - self.pruned_entries - 1, limit).to_vec(),
+            skeleton: Vec::new(),
+        }
+    }
+
+    /// A sync page along with the hashes of the entries ending each segment
+    /// of `step` entries from its first entry on, the last segment ending at
+    /// the tip, listing at most `max` segments
+    pub fn sync_skeleton_page(&self, height: usize, limit: usize, step: usize, max: usize) -> SyncPage {
+        let mut page = self.sync_page(height, limit);
+        let step = step.max(1);
+        let mut end = page.from.saturating_add(step - 1);
+        while page.from <= page.height && page.skeleton.len() < max {
+            let last = end.min(page.height);
+            page.skeleton.push(self.entries[last - self.pruned_entries - 1].hash.clone());
+            if last == page.height {
+                break;
+            }
+            end = end.saturating_add(step);
+        }
+        page
+    }
+
+    /// Summary of the ledger's state
+    pub fn stats(&self) -> LedgerStats {
+        LedgerStats {
+            tip: self.chain_tip(),
+            entries: self.entries.len(),
+            pruned_entries: self.pruned_entries,
+            pending_entries: self.pending_entries.len(),
+            forks: self.detect_forks().len(),
+            known_nodes: self.known_nodes.len(),
+            merkle_root: self.merkle_root(),
+            last_entry_at: self.get_last_entry().map(|e| e.timestamp),
+            consensus: self.consensus.name().to_string(),
+        }
+    }
+
+    /// The newest entry of this chain
+    pub fn chain_tip(&self) -> ChainTip {
+        ChainTip {
+            hash: self.tip_hash().to_string(),
+            height: self.height(),
+        }
+    }
+
+    /// The first page, of at most `limit` entries, of those a peer whose chain ends at `tip` is missing.
+    ///
+    /// A tip on this chain only needs the entries after it. A peer that went
+    /// its own way gets every entry held so it can compare the branches,
+    /// unless its chain is already at least as long as this one. The rest
+    /// is asked for with [`Ledger::sync_page`] from the height after the page.
+    pub fn missing_entries(&self, tip: &ChainTip, limit: usize) -> SyncPage {
+        // A set is merged whole, as a peer's tip says nothing about which entries it has
+        if self.mode == LedgerMode::Crdt {
+            return self.sync_page(1, limit);
+        }
+        if let Some(after) = self.get_entries_after(&tip.hash) {
+            return self.sync_page(self.height() - after.len() + 1, limit);
+        }
+        if tip.height >= self.height() {
+            return self.sync_page(self.height() + 1, 0);
+        }
+        self.sync_page(1, limit)
+    }
+
+    /// Entries on the chain after the one with `hash`, or `None` if it isn't on the chain
+    pub fn get_entries_after(&self, hash: &str) -> Option<&[LedgerEntry]> {
+        if hash == self.genesis_hash && self.pruned_entries == 0 {
+            return Some(&self.entries);
+        }
+        let index = self.index_of(hash)?;
+        Some(&self.entries[index + 1..])
+    }
+
+    /// Add a known node to the network
+    pub fn add_known_node(&mut self, node_id: String) {
+        self.known_nodes.insert(node_id);
+    }
+
+    /// Forget a known node, returning whether it was known. This node is
+    /// always known; its keys stay, so its entries still verify.
+    pub fn remove_known_node(&mut self, node_id: &str) -> bool {
+        node_id != self.node_id && self.known_nodes.remove(node_id)
+    }
+
+    /// Get all known nodes in the network
+    pub fn get_known_nodes(&self) -> &HashSet<String> {
+        &self.known_nodes
+    }
+
+    /// Set the retention policy for this ledger
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention = policy;
+    }
+
+    /// Get the retention metadata for this ledger
+    pub fn get_retention_info(&self) -> RetentionInfo {
+        let oldest = self.entries.first();
+        RetentionInfo {
+            policy: self.retention.clone(),
+            pruned_entries: self.pruned_entries,
+            oldest_entry_id: oldest.map(|e| e.id.clone()),
+            oldest_timestamp: oldest.map(|e| e.timestamp),
+        }
+    }
+
+    /// Prune entries that fall outside the retention policy, returning how many were removed
+    pub fn apply_retention(&mut self) -> usize {
+        self.apply_retention_at(Utc::now())
+    }
+
+    /// Prune entries that fall outside the retention policy as of `now`
+    pub fn apply_retention_at(&mut self, now: DateTime<Utc>) -> usize {
+        let expired = self.expired_entries_at(now).len();
+        self.prune(expired)
+    }
+
+    /// Entries that fall outside the retention policy as of `now`, oldest first
+    pub fn expired_entries_at(&self, now: DateTime<Utc>) -> &[LedgerEntry] {
+        let expired = match &self.retention {
+            RetentionPolicy::KeepForever => 0,
+            RetentionPolicy::KeepDays { days } => {
+                // A cutoff before the earliest time there is keeps everything
+                match Duration::try_days(i64::from(*days)).and_then(|age| now.checked_sub_signed(age)) {
+                    Some(cutoff) => self.entries.iter().take_while(|e| e.timestamp < cutoff).count(),
+                    None => 0,
+                }
+            }
+            RetentionPolicy::KeepLast { entries } => self.entries.len().saturating_sub(*entries),
+        };
+
+        // Always keep the tip so new entries can still link to the chain
+        &self.entries[..expired.min(self.entries.len().saturating_sub(1))]
+    }
+
+    /// Prune the entries up to and including the one with `hash`, returning
+    /// how many were removed. Nothing is pruned if the entry isn't held or is the tip.
+    pub fn prune_through(&mut self, hash: &str) -> usize {
+        match self.index_of(hash) {
+            Some(index) if index + 1 < self.entries.len() => self.prune(index + 1),
+            _ => 0,
+        }
+    }
+
+    /// Remove the `count` oldest entries
+    fn prune(&mut self, count: usize) -> usize {
+        self.acl_base = self.acl_at(count);
+        self.rotations_base = self.rotations_at(count);
+        self.stakes_base = self.stakes_at(count);
+        for (i, entry) in self.entries.drain(..count).enumerate() {
+            self.index.remove(&entry, self.pruned_entries + 1 + i);
+        }
+        self.pruned_entries += count;
+        self.rebuild_merkle_tree();
+        count
+    }
+}
+
+/// Parse a node's hex-encoded public key
+fn parse_node_key(node_id: &str, public_key: &str) -> Result<VerifyingKey, String> {
+    parse_public_key(public_key).map_err(|_| format!("Invalid public key for node {node_id}"))
+}
+
+/// The part of a pending branch that competing branches are weighed by
+#[derive(Clone, Copy)]
+struct BranchHead<'a> {
+    /// The branch's first entry
+    first: &'a LedgerEntry,
+    /// Hash of its last entry
+    tip: &'a str,
+    len: usize,
+}
+
+/// Longest branch of the pending entries in `children` starting from `from`,
+/// oldest first. Between branches of the same length, `prefer` says whether
+/// the first should win over the second.
+///
+/// The branches are walked without recursion, working out the best branch
+/// under each entry once, so deep or bushy pending sets stay linear.
+fn longest_pending_branch(
+    children: &HashMap<&str, Vec<&LedgerEntry>>,
+    from: &str,
+    prefer: fn(&BranchHead, &BranchHead) -> bool,
+) -> Vec<LedgerEntry> {
+    // The best branch under each hash, once every entry below it is settled
+    let mut best: HashMap<&str, Option<BranchHead>> = HashMap::new();
+    let mut seen = HashSet::from([from]);
+    let mut stack = vec![(from, false)];
+    while let Some((hash, expanded)) = stack.pop() {
+        let below = children.get(hash).map(Vec::as_slice).unwrap_or_default();
+        if !expanded {
+            stack.push((hash, true));
+            let unseen = below.iter().map(|child| child.hash.as_str()).filter(|child| seen.insert(*child));
+            stack.extend(unseen.map(|child| (child, false)));
+            continue;
+        }
+
+        let head = below
+            .iter()
+            .map(|child| {
+                let rest = best.get(child.hash.as_str()).copied().flatten();
+                BranchHead {
+                    first: *child,
+                    tip: rest.map_or(child.hash.as_str(), |rest| rest.tip),
+                    len: 1 + rest.map_or(0, |rest| rest.len),
+                }
+            })
+            .reduce(|a, b| if b.len > a.len || (b.len == a.len && prefer(&b, &a)) { b } else { a });
+        best.insert(hash, head);
+    }
+
+    let mut branch = Vec::new();
+    let mut hash = from;
+    while let Some(Some(head)) = best.get(hash) {
+        branch.push(head.first.clone());
+        hash = head.first.hash.as_str();
+    }
+    branch
+}
+
+/// Whether branch `a` was started before `b` by the Lamport clock, as
+/// creators' timestamps can't be compared
+fn started_first(a: &BranchHead, b: &BranchHead) -> bool {
+    (a.first.clock, a.tip) < (b.first.clock, b.tip)
+}
+
+/// Whether branch `a` ends in a lower tip hash than `b`
+fn lower_tip(a: &BranchHead, b: &BranchHead) -> bool {
+    a.tip < b.tip
+}
+
+/// Thread-safe wrapper around the ledger.
+///
+/// Reads share a lock and only writes take it exclusively, so queries served
+/// to clients and peers don't wait on each other.
+#[derive(Clone)]
+pub struct SharedLedger {
+    ledger: Arc<RwLock<Ledger>>,
+    /// Notifies subscribers of entries appended to the chain
+    appended: broadcast::Sender<LedgerEntry>,
+}
+
+impl SharedLedger {
+    /// Create a new shared ledger
+    pub fn new(node_id: String) -> Self {
+        Self::from_ledger(Ledger::new(node_id))
+    }
+
+    /// Create a new shared ledger that signs entries with `signing_key`
+    pub fn with_signing_key(node_id: String, signing_key: SigningKey) -> Self {
+        Self::from_ledger(Ledger::with_signing_key(node_id, signing_key))
+    }
+
+    fn from_ledger(ledger: Ledger) -> Self {
+        let (appended, _) = broadcast::channel(1024);
+        Self {
+            ledger: Arc::new(RwLock::new(ledger)),
+            appended,
+        }
+    }
+
+    /// Subscribe to entries as they are appended to the chain
+    pub fn subscribe(&self) -> broadcast::Receiver<LedgerEntry> {
+        self.appended.subscribe()
+    }
+
+    /// Get a clone of the ledger Arc
+    pub fn clone_ledger(&self) -> Arc<RwLock<Ledger>> {
+        self.ledger.clone()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Ledger> {
+        self.ledger.read().unwrap()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Ledger> {
+        self.ledger.write().unwrap()
+    }
+
+    /// Add a new entry to the ledger
+    pub fn add_entry(&self, data: serde_json::Value) -> Result<LedgerEntry, ValidationError> {
+        let span = info_span!("ledger.add_entry", entry_id = field::Empty).entered();
+        let mut ledger = self.write();
+        let entry = ledger.add_entry(data)?;
+        span.record("entry_id", &entry.id);
+        // The consensus strategy may not have approved the entry yet
+        if ledger.get_last_entry().is_some_and(|e| e.id == entry.id) {
+            self.appended.send(entry.clone()).ok();
+        }
+        Ok(entry)
+    }
+
+    /// Add several new entries under one write lock, in order, with a result for each
+    pub fn add_entries(&self, data: Vec<serde_json::Value>) -> Vec<Result<LedgerEntry, ValidationError>> {
+        let _span = info_span!("ledger.add_entries", count = data.len()).entered();
+        let mut ledger = self.write();
+        data.into_iter()
+            .map(|data| {
+                let entry = ledger.add_entry(data)?;
+                if ledger.get_last_entry().is_some_and(|e| e.id == entry.id) {
+                    self.appended.send(entry.clone()).ok();
+                }
+                Ok(entry)
+            })
+            .collect()
+    }
+
+    /// Set the consensus strategy; longest chain by default
+    pub fn set_consensus(&self, consensus: Box<dyn Consensus>) {
+        let mut ledger = self.write();
+        ledger.set_consensus(consensus);
+    }
+
+    /// Set whether entries form a chain or a set; only an empty ledger can switch
+    pub fn set_mode(&self, mode: LedgerMode) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.set_mode(mode)
+    }
+
+    /// Whether entries form a chain or a set
+    pub fn mode(&self) -> LedgerMode {
+        let ledger = self.read();
+        ledger.mode()
+    }
+
+    /// Set how much the ledger takes in
+    pub fn set_limits(&self, limits: Limits) {
+        let mut ledger = self.write();
+        ledger.set_limits(limits);
+    }
+
+    /// Set how long unstaked amounts stay locked; only affects later unstakes
+    pub fn set_staking_config(&self, staking: StakingConfig) {
+        let mut ledger = self.write();
+        ledger.set_staking_config(staking);
+    }
+
+    /// The balance and stake of `address` as of the tip
+    pub fn stake(&self, address: &str) -> Result<StakeInfo, String> {
+        let ledger = self.read();
+        ledger.stake(address)
+    }
+
+    /// Start the chain from `genesis`; only an empty chain can be given one
+    pub fn set_genesis(&self, genesis: Genesis) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.set_genesis(genesis)
+    }
+
+    /// The genesis the chain starts from and its hash
+    pub fn genesis(&self) -> GenesisInfo {
+        let ledger = self.read();
+        GenesisInfo { hash: ledger.genesis_hash().to_string(), genesis: ledger.genesis().cloned() }
+    }
+
+    /// Previous hash of the first entry, which peers have to share
+    pub fn genesis_hash(&self) -> String {
+        let ledger = self.read();
+        ledger.genesis_hash().to_string()
+    }
+
+    /// Put the chain on the network `chain_id`; see [`Ledger::set_chain_id`]
+    pub fn set_chain_id(&self, chain_id: Option<String>) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.set_chain_id(chain_id)
+    }
+
+    /// Restore from snapshots signed with these keys too; see [`Ledger::set_checkpoint_keys`]
+    pub fn set_checkpoint_keys(&self, keys: &[String]) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.set_checkpoint_keys(keys)
+    }
+
+    /// Network the chain belongs to, if it has a chain ID
+    pub fn chain_id(&self) -> Option<String> {
+        let ledger = self.read();
+        ledger.chain_id().map(str::to_string)
+    }
+
+    /// Refuse an entry from another network
+    pub fn check_chain(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        let ledger = self.read();
+        ledger.check_chain(entry)
+    }
+
+    /// Whether `sender` spent `nonce` on a transaction on the chain
+    pub fn is_spent(&self, sender: &str, nonce: u64) -> bool {
+        let ledger = self.read();
+        ledger.is_spent(sender, nonce)
+    }
+
+    /// How much the ledger takes in
+    pub fn limits(&self) -> Limits {
+        let ledger = self.read();
+        ledger.limits()
+    }
+
+    /// Check that an entry from a peer is within the size limit and that there is room for it
+    pub fn check_limits(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        let ledger = self.read();
+        ledger.check_limits(entry)
+    }
+
+    /// Name of the consensus strategy in use
+    pub fn consensus_name(&self) -> &'static str {
+        let ledger = self.read();
+        ledger.consensus().name()
+    }
+
+    /// Whether entries circulate between nodes collecting signatures before they're approved
+    pub fn collects_signatures(&self) -> bool {
+        let ledger = self.read();
+        ledger.consensus().collects_signatures()
+    }
+
+    /// The current validator set, if the consensus strategy has one
+    pub fn validators(&self) -> Option<ValidatorSet> {
+        let ledger = self.read();
+        ledger.validators().cloned()
+    }
+
+    /// Whether the consensus strategy lets this node create entries
+    pub fn can_propose(&self) -> bool {
+        let ledger = self.read();
+        ledger.can_propose()
+    }
+
+    /// Restrict who may create entries to the holders of the keys on `acl`
+    pub fn set_write_acl(&self, acl: WriteAcl) {
+        let mut ledger = self.write();
+        ledger.set_write_acl(acl);
+    }
+
+    /// The write ACL as of the tip, if writes are restricted
+    pub fn write_acl(&self) -> Option<WriteAcl> {
+        let ledger = self.read();
+        ledger.write_acl().cloned()
+    }
+
+    /// Keys retired by rotations on the chain as of the tip
+    pub fn key_rotations(&self) -> KeyRotations {
+        let ledger = self.read();
+        ledger.key_rotations().clone()
+    }
+
+    /// A statement retiring this node's key in favour of `new_key`, signed with the current key
+    pub fn key_rotation(&self, new_key: &VerifyingKey) -> KeyRotation {
+        let ledger = self.read();
+        ledger.key_rotation(new_key)
+    }
+
+    /// Whether the write ACL, if any, lets this node create entries
+    pub fn can_write(&self) -> bool {
+        let ledger = self.read();
+        ledger.can_write()
+    }
+
+    /// Refuse an entry from a peer whose creator isn't on the write ACL
+    pub fn check_writer(&self, entry: &LedgerEntry) -> Result<(), ValidationError> {
+        let ledger = self.read();
+        ledger.check_writer(entry)
+    }
+
+    /// Get all entries in the ledger
+    pub fn get_entries(&self) -> Vec<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_entries().clone()
+    }
+
+    /// Run `f` over the entries in the ledger without copying them. Writes
+    /// wait until `f` returns, so it shouldn't block.
+    pub fn with_entries<R>(&self, f: impl FnOnce(&[LedgerEntry]) -> R) -> R {
+        let ledger = self.read();
+        f(ledger.get_entries())
+    }
+
+    /// Number of entries the ledger holds
+    pub fn entry_count(&self) -> usize {
+        let ledger = self.read();
+        ledger.get_entries().len()
+    }
+
+    /// Get up to `limit` entries starting at position `offset` in the chain
+    pub fn get_entries_paginated(&self, offset: usize, limit: usize) -> Vec<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_entries_paginated(offset, limit).to_vec()
+    }
+
+    /// Get all entries created strictly after `timestamp`
+    pub fn get_entries_since(&self, timestamp: DateTime<Utc>) -> Vec<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_entries_since(timestamp).into_iter().cloned().collect()
+    }
+
+    /// Get an entry by its ID
+    pub fn get_entry_by_id(&self, id: &str) -> Option<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_entry_by_id(id).cloned()
+    }
+
+    /// Get an entry on the chain by its hash
+    pub fn get_entry_by_hash(&self, hash: &str) -> Option<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_entry_by_hash(hash).cloned()
+    }
+
+    /// Get the entries created by `node_id`, in chain order
+    pub fn find_by_creator(&self, node_id: &str) -> Vec<LedgerEntry> {
+        let ledger = self.read();
+        ledger.find_by_creator(node_id).into_iter().cloned().collect()
+    }
+
+    /// Up to `limit` candidates for `query`'s results after height `after`; see [`Ledger::query`]
+    pub fn query(&self, query: &Query, after: usize, limit: usize) -> Vec<(usize, LedgerEntry)> {
+        let ledger = self.read();
+        ledger.query(query, after, limit).into_iter().map(|(height, entry)| (height, entry.clone())).collect()
+    }
+
+    /// Get the Merkle root of the entries this ledger holds
+    pub fn merkle_root(&self) -> String {
+        let ledger = self.read();
+        ledger.merkle_root()
+    }
+
+    /// Tag that changes whenever the entries this ledger holds do
+    pub fn version(&self) -> String {
+        let ledger = self.read();
+        ledger.version()
+    }
+
+    /// Get a proof that an entry is included under the current Merkle root
+    pub fn get_inclusion_proof(&self, id: &str) -> Option<MerkleProof> {
+        let ledger = self.read();
+        ledger.get_inclusion_proof(id)
+    }
+
+    /// Get up to `limit` headers starting at position `offset` in the chain
+    pub fn get_headers(&self, offset: usize, limit: usize) -> LedgerHeaders {
+        let ledger = self.read();
+        ledger.get_headers(offset, limit)
+    }
+
+    /// Get an inclusion proof for an entry together with the root it leads to
+    pub fn get_entry_proof(&self, id: &str) -> Option<EntryProof> {
+        let ledger = self.read();
+        ledger.get_entry_proof(id)
+    }
+
+    /// Get this node's public key, hex-encoded
+    pub fn public_key(&self) -> String {
+        let ledger = self.read();
+        ledger.public_key()
+    }
+
+    /// Sign a message with this node's key, returning the hex-encoded signature
+    pub fn sign_message(&self, message: &[u8]) -> String {
+        let ledger = self.read();
+        ledger.sign_message(message)
+    }
+
+    /// Record the public key a node signs its entries with
+    pub fn add_node_key(&self, node_id: String, public_key: &str) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.add_node_key(node_id, public_key)
+    }
+
+    /// Get the public key of a node, hex-encoded
+    pub fn get_node_key(&self, node_id: &str) -> Option<String> {
+        let ledger = self.read();
+        ledger.get_node_key(node_id)
+    }
+
+    /// Get the last entry in the ledger
+    pub fn get_last_entry(&self) -> Option<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_last_entry().cloned()
+    }
+
+    /// Get the entries waiting to be added, ordered by Lamport clock then hash
+    pub fn get_pending_entries(&self) -> Vec<LedgerEntry> {
+        let ledger = self.read();
+        ledger.get_pending_entries().into_iter().cloned().collect()
+    }
+
+    /// Add a pending entry that has been received from another node, returning whether it was new
+    pub fn add_pending_entry(&self, entry: LedgerEntry) -> bool {
+        let _span = info_span!("ledger.add_pending_entry", entry_id = entry.id, creator = entry.creator_node_id).entered();
+        let mut ledger = self.write();
+        ledger.add_pending_entry(entry)
+    }
+
+    /// Sign pending entries as a validator, returning the entries signed
+    pub fn endorse_pending_entries(&self) -> Vec<LedgerEntry> {
+        let mut ledger = self.write();
+        ledger.endorse_pending_entries()
+    }
+
+    /// Process pending entries and add them to the chain if they are valid
+    pub fn process_pending_entries(&self) -> Vec<LedgerEntry> {
+        let span = info_span!("ledger.process_pending_entries", added = field::Empty).entered();
+        let mut ledger = self.write();
+        let added = ledger.process_pending_entries();
+        span.record("added", added.len());
+        for entry in &added {
+            self.appended.send(entry.clone()).ok();
+        }
+        added
+    }
+
+    /// Hashes pending entries link to that are neither on the chain nor pending
+    pub fn missing_parents(&self) -> Vec<String> {
+        let ledger = self.read();
+        ledger.missing_parents()
+    }
+
+    /// Drop pending entries that have waited longer than `max_age` to join the chain, returning them
+    pub fn expire_pending_entries(&self, max_age: std::time::Duration) -> Vec<LedgerEntry> {
+        let mut ledger = self.write();
+        ledger.expire_pending_entries(max_age)
+    }
+
+    /// Find pending branches that fork off the chain below its tip
+    pub fn detect_forks(&self) -> Vec<Fork> {
+        let ledger = self.read();
+        ledger.detect_forks()
+    }
+
+    /// Switch to a competing branch if it makes a better chain than the current one
+    pub fn resolve_forks(&self) -> Option<Reorg> {
+        let mut ledger = self.write();
+        let reorg = ledger.resolve_forks()?;
+        for entry in &reorg.applied {
+            self.appended.send(entry.clone()).ok();
+        }
+        Some(reorg)
+    }
+
+    /// Add a known node to the network
+    pub fn add_known_node(&self, node_id: String) {
+        let mut ledger = self.write();
+        ledger.add_known_node(node_id);
+    }
+
+    /// Forget a known node, returning whether it was known
+    pub fn remove_known_node(&self, node_id: &str) -> bool {
+        let mut ledger = self.write();
+        ledger.remove_known_node(node_id)
+    }
+
+    /// Get all known nodes in the network
+    pub fn get_known_nodes(&self) -> HashSet<String> {
+        let ledger = self.read();
+        ledger.get_known_nodes().clone()
+    }
+
+    /// Set the rules entry data has to satisfy
+    pub fn set_validation_policy(&self, policy: Arc<dyn ValidationPolicy>) {
+        let mut ledger = self.write();
+        ledger.set_validation_policy(policy);
+    }
+
+    /// Check entry data against the validation policy and the schema of its kind
+    pub fn validate(&self, data: &serde_json::Value) -> Result<(), ValidationError> {
+        let ledger = self.read();
+        ledger.validate(data)
+    }
+
+    /// Check data this node adds itself, letting the references it recorded for offloaded data through
+    pub(crate) fn validate_own(&self, data: &serde_json::Value) -> Result<(), ValidationError> {
+        let ledger = self.read();
+        ledger.validate_own(data)
+    }
+
+    /// Set the entry kinds and schemas entries are checked against
+    pub fn set_schema_registry(&self, schemas: Arc<SchemaRegistry>) {
+        let mut ledger = self.write();
+        ledger.set_schema_registry(schemas);
+    }
+
+    /// The entry kinds and schemas entries are checked against
+    pub fn schemas(&self) -> Arc<SchemaRegistry> {
+        let ledger = self.read();
+        ledger.schemas()
+    }
+
+    /// Set the retention policy for this ledger
+    pub fn set_retention_policy(&self, policy: RetentionPolicy) {
+        let mut ledger = self.write();
+        ledger.set_retention_policy(policy);
+    }
+
+    /// Get the retention metadata for this ledger
+    pub fn get_retention_info(&self) -> RetentionInfo {
+        let ledger = self.read();
+        ledger.get_retention_info()
+    }
+
+    /// Prune entries that fall outside the retention policy
+    pub fn apply_retention(&self) -> usize {
+        let mut ledger = self.write();
+        ledger.apply_retention()
+    }
+
+    /// Entries that fall outside the retention policy now, oldest first, with the height of the first
+    pub fn expired_entries(&self) -> (usize, Vec<LedgerEntry>) {
+        let ledger = self.read();
+        let first = ledger.height() - ledger.get_entries().len() + 1;
+        (first, ledger.expired_entries_at(Utc::now()).to_vec())
+    }
+
+    /// Prune the entries up to and including the one with `hash`; see [`Ledger::prune_through`]
+    pub fn prune_through(&self, hash: &str) -> usize {
+        let mut ledger = self.write();
+        ledger.prune_through(hash)
+    }
+
+    /// Take a snapshot of the chain up to and including the entry at `height`
+    pub fn create_snapshot(&self, height: usize) -> Result<Snapshot, String> {
+        let ledger = self.read();
+        ledger.create_snapshot(height)
+    }
+
+    /// Take a snapshot of the whole chain
+    pub fn snapshot_tip(&self) -> Result<Snapshot, String> {
+        let ledger = self.read();
+        ledger.create_snapshot(ledger.height())
+    }
+
+    /// Start an empty ledger from a snapshot instead of replaying the chain
+    pub fn restore_from_snapshot(&self, snapshot: &Snapshot) -> Result<(), String> {
+        let mut ledger = self.write();
+        ledger.restore_from_snapshot(snapshot)
+    }
+
+    /// Hashes that describe this chain to a peer looking for a common ancestor, newest first
+    pub fn sync_locator(&self) -> Vec<String> {
+        let ledger = self.read();
+        ledger.sync_locator()
+    }
+
+    /// Height of the newest entry in a peer's `locator` that is also on this chain, or 0 if none is
+    pub fn common_ancestor(&self, locator: &[String]) -> usize {
+        let ledger = self.read();
+        ledger.common_ancestor(locator)
+    }
+
+    /// Up to `limit` entries starting at `height`, or at the oldest entry held if that is later
+    pub fn sync_page(&self, height: usize, limit: usize) -> SyncPage {
+        let ledger = self.read();
+        ledger.sync_page(height, limit)
+    }
+
+    /// A sync page along with the hashes ending each segment of `step` entries, at most `max` of them
+    pub fn sync_skeleton_page(&self, height: usize, limit: usize, step: usize, max: usize) -> SyncPage {
+        let ledger = self.read();
+        ledger.sync_skeleton_page(height, limit, step, max)
+    }
+
+    /// Summary of the ledger's state
+    pub fn stats(&self) -> LedgerStats {
+        let ledger = self.read();
+        ledger.stats()
+    }
+
+    /// The newest entry of this chain
+    pub fn chain_tip(&self) -> ChainTip {
+        let ledger = self.read();
+        ledger.chain_tip()
+    }
+
+    /// The first page, of at most `limit` entries, of those a peer whose chain ends at `tip` is missing
+    pub fn missing_entries(&self, tip: &ChainTip, limit: usize) -> SyncPage {
+        let ledger = self.read();
+        ledger.missing_entries(tip, limit)
+    }
+
+    /// Entries on the chain after the one with `hash`, or `None` if it isn't on the chain
+    pub fn get_entries_after(&self, hash: &str) -> Option<Vec<LedgerEntry>> {
+        let ledger = self.read();
+        ledger.get_entries_after(hash).map(<[LedgerEntry]>::to_vec)
+    }
+
+    /// Up to `limit` entries on the chain after the one with `hash`, or `None` if it isn't on the chain
+    pub fn get_entries_after_paginated(&self, hash: &str, limit: usize) -> Option<Vec<LedgerEntry>> {
+        let ledger = self.read();
+        ledger.get_entries_after(hash).map(|entries| entries[..limit.min(entries.len())].to_vec())
     }
 }
@@ -1,2 +1,43 @@
+pub mod acl;
+pub mod admin;
+pub mod api;
+pub mod archive;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod bandwidth;
+pub mod channels;
+pub mod codec;
+pub mod config;
+pub mod consensus;
+pub mod contacts;
+pub mod discovery;
+pub mod envelope;
+pub mod error;
+pub mod export;
+pub mod fees;
+pub mod gc;
+pub mod genesis;
+pub mod grpc;
+pub mod health;
+pub mod identity;
 pub mod ledger;
-pub mod p2p;
\ No newline at end of file
+pub mod mempool;
+pub mod merkle;
+pub mod node;
+pub mod offload;
+pub mod p2p;
+pub mod pex;
+pub mod quic;
+pub mod ratelimit;
+pub mod rendezvous;
+pub mod rotation;
+pub mod schema;
+pub mod service;
+pub mod socket;
+pub mod staking;
+pub mod supervisor;
+pub mod sync;
+pub mod telemetry;
+pub mod tls;
+pub mod validation;
@@ -0,0 +1,231 @@
+//! Mempool.
+//!
+//! Entry data from clients waits here for the sequencer, which adds it to the
+//! ledger in order of the fee it pays per byte: transactions
+//! (`{"type": "transaction", "transaction": {...}}`) paying more go first,
+//! and data that isn't a transaction pays nothing. Submissions paying the
+//! same rate keep the order they arrived in.
+//!
+//! The `[mempool]` section of the config file sets a minimum fee, below
+//! which transactions are refused before they're queued. `GET /api/fees/floor`
+//! reports it together with the rate queued transactions pay when the
+//! mempool is full.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio::sync::{oneshot, Notify, Semaphore};
+
+use crate::error::GsioNodeError;
+use crate::ledger::LedgerEntry;
+use crate::validation::{ErrorCode, ValidationError};
+
+/// The `[mempool]` section of the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolConfig {
+    /// Fee transactions have to pay to be queued
+    pub min_fee: u64,
+}
+
+/// What it takes to get a transaction sequenced, as returned by `GET /api/fees/floor`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeFloor {
+    /// Fee below which transactions are refused
+    pub min_fee: u64,
+    /// Lowest fee per byte among queued submissions while the mempool is
+    /// full, or 0 while it has room; paying more goes ahead of them
+    pub fee_per_byte: f64,
+    /// Submissions waiting to be sequenced
+    pub pending: usize,
+    /// Submissions the mempool holds before clients wait for room
+    pub capacity: usize,
+}
+
+/// The fee entry data pays and its size in bytes, serialized as JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeRate {
+    pub fee: u64,
+    pub size: usize,
+}
+
+impl FeeRate {
+    /// The rate `data` pays: the fee of the transaction it records, if any
+    pub fn of(data: &JsonValue) -> Self {
+        Self { fee: transaction_fee(data).unwrap_or(0), size: data.to_string().len().max(1) }
+    }
+
+    /// The fee per byte
+    pub fn per_byte(&self) -> f64 {
+        self.fee as f64 / self.size as f64
+    }
+}
+
+impl Ord for FeeRate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // fee / size against other.fee / other.size, without rounding
+        (self.fee as u128 * other.size as u128).cmp(&(other.fee as u128 * self.size as u128))
+    }
+}
+
+impl PartialOrd for FeeRate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Entry data in the mempool, waiting for the sequencer to add it
+pub struct Submission {
+    pub data: JsonValue,
+    pub added: oneshot::Sender<Result<LedgerEntry, GsioNodeError>>,
+}
+
+/// A submission with its place in line
+struct Queued {
+    rate: FeeRate,
+    /// Arrival order, to break ties between equal rates
+    sequence: u64,
+    submission: Submission,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rate.cmp(&other.rate).then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+#[derive(Default)]
+struct Queue {
+    heap: BinaryHeap<Queued>,
+    next_sequence: u64,
+}
+
+/// Client submissions waiting for the sequencer, highest fee per byte first
+pub struct Mempool {
+    config: MempoolConfig,
+    capacity: usize,
+    queue: Mutex<Queue>,
+    /// Free places in the queue
+    room: Semaphore,
+    /// Wakes the sequencer when something is queued
+    queued: Notify,
+    /// Whether a sequencer takes from the queue
+    open: AtomicBool,
+}
+
+impl Mempool {
+    /// Create an empty mempool holding up to `capacity` submissions
+    pub fn new(config: MempoolConfig, capacity: usize) -> Self {
+        Self {
+            config,
+            capacity,
+            queue: Mutex::new(Queue::default()),
+            room: Semaphore::new(capacity),
+            queued: Notify::new(),
+            open: AtomicBool::new(false),
+        }
+    }
+
+    /// The config the mempool was created with
+    pub fn config(&self) -> MempoolConfig {
+        self.config
+    }
+
+    /// Mark the mempool as served by a sequencer, so submissions are queued
+    pub fn open(&self) {
+        self.open.store(true, AtomicOrdering::SeqCst);
+    }
+
+    /// Whether a sequencer takes from the mempool
+    pub fn is_open(&self) -> bool {
+        self.open.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Refuse transactions paying less than the minimum fee. Other data passes.
+    pub fn check_fee(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        match transaction_fee(data) {
+            Some(fee) if fee < self.config.min_fee => Err(ValidationError::new(
+                ErrorCode::FeeTooLow,
+                format!("Transaction pays a fee of {fee}, the node's minimum is {}", self.config.min_fee),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Queue entry data paying `rate`, waiting for room if the mempool is
+    /// full, and get a receiver for the sequencer's result
+    pub async fn push(&self, data: JsonValue, rate: FeeRate) -> oneshot::Receiver<Result<LedgerEntry, GsioNodeError>> {
+        self.room.acquire().await.expect("the mempool's semaphore is never closed").forget();
+        let (added, result) = oneshot::channel();
+        let mut queue = self.queue.lock().unwrap();
+        let sequence = queue.next_sequence;
+        queue.next_sequence += 1;
+        queue.heap.push(Queued { rate, sequence, submission: Submission { data, added } });
+        drop(queue);
+        self.queued.notify_one();
+        result
+    }
+
+    /// Take up to `max` submissions, highest fee per byte first, waiting for
+    /// one if the mempool is empty
+    pub async fn next_batch(&self, max: usize) -> Vec<Submission> {
+        loop {
+            let batch: Vec<Submission> = {
+                let mut queue = self.queue.lock().unwrap();
+                (0..max).map_while(|_| queue.heap.pop()).map(|queued| queued.submission).collect()
+            };
+            if !batch.is_empty() {
+                self.room.add_permits(batch.len());
+                return batch;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    /// Number of submissions waiting
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().heap.len()
+    }
+
+    /// Whether no submissions are waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The minimum fee and, while the mempool is full, the rate to beat
+    pub fn fee_floor(&self) -> FeeFloor {
+        let queue = self.queue.lock().unwrap();
+        let full = queue.heap.len() >= self.capacity;
+        let fee_per_byte = match queue.heap.iter().map(|queued| queued.rate).min() {
+            Some(rate) if full => rate.per_byte(),
+            _ => 0.0,
+        };
+        FeeFloor { min_fee: self.config.min_fee, fee_per_byte, pending: queue.heap.len(), capacity: self.capacity }
+    }
+}
+
+/// The fee of the transaction entry data records, if it records one
+fn transaction_fee(data: &JsonValue) -> Option<u64> {
+    if data.get("type").and_then(JsonValue::as_str) != Some("transaction") {
+        return None;
+    }
+    Some(data.get("transaction")?.get("fee")?.as_u64().unwrap_or(0))
+}
@@ -0,0 +1,8 @@
+//! Merkle tree over ledger entry hashes, so a node can prove an entry is in
+//! its chain without sending the whole chain.
+//!
+//! The tree is shared with light clients, which check the proofs nodes serve
+//! against it; the ledger keeps one over the entries it holds, extended as
+//! entries are appended.
+
+pub use gsio_types::{merkle_root, verify_proof, MerkleProof, MerkleTree, ProofStep, Side, EMPTY_ROOT};
@@ -0,0 +1,1167 @@
+//! Running a node, on its own or embedded in another application.
+//!
+//! A [`NodeBuilder`] starts from a [`NodeConfig`], loaded the way the
+//! `gsio-node` binary loads it or built in code, and can override its
+//! storage, transports and channels. [`NodeBuilder::start`] binds the iroh
+//! endpoint and the HTTP listener, sets up the ledger and channels, and
+//! starts serving and syncing in the background, returning a [`Node`] that
+//! hands out the ledger and P2P manager. [`Node::shutdown`] says goodbye to
+//! peers, drains the servers and stops the background tasks. The binary is
+//! a thin wrapper that starts a node and shuts it down on a signal.
+
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    http::StatusCode,
+    middleware,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use ed25519_dalek::SigningKey;
+use iroh::{protocol::Router as IrohRouter, Endpoint, RelayMap, RelayMode, RelayUrl};
+use iroh_blobs::{
+    net_protocol::Blobs,
+    rpc::client::blobs::MemClient,
+    store::Store,
+    ticket::BlobTicket,
+    Hash, ALPN,
+};
+use serde_json::{json, Value as JsonValue};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    SocketIo,
+};
+use rand::rngs::OsRng;
+use std::{io, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tonic::transport::ServerTlsConfig;
+use tracing::{debug, error, info, warn};
+use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+
+use crate::api::{self, ApiError, SnapshotQuery};
+use crate::archive::{self, Archive};
+use crate::acl::WriteAcl;
+use crate::admin::{self, Admin, RuntimeConfig};
+use crate::audit::AuditLog;
+use crate::auth::{self, Authenticator};
+use crate::bandwidth::Bandwidth;
+use crate::channels::{self, Channels};
+use crate::health::{self, Health};
+use crate::identity;
+use crate::schema::SchemaRegistry;
+use crate::config::NodeConfig;
+use crate::export;
+use crate::fees;
+use crate::gc::{BlobGc, SNAPSHOT_TAG};
+use crate::discovery::{Discovery, PeerRecord, DISCOVERY_ALPN};
+use crate::grpc::{CallGuard, GsioService};
+use crate::ledger::{ChainTip, LedgerEntry, SharedLedger, Snapshot};
+use crate::offload::{IrohBlobStore, Offloader};
+use crate::p2p::{P2PManager, MAX_SYNC_PAGE_SIZE};
+use crate::pex::{self, PeerExchangeConfig};
+use crate::quic::QuicTransport;
+use crate::ratelimit::{self, RateLimiter};
+use crate::socket;
+use crate::supervisor::{RestartPolicy, Supervisor, Tasks};
+use crate::sync::SyncCoordinator;
+
+/// Error a node fails to start or stop with
+pub type NodeError = Box<dyn std::error::Error + Send + Sync>;
+
+/// ========== Socket.io namespace helpers ==========
+fn register_p2p_namespace(io: &SocketIo, p2p: Arc<P2PManager>) {
+    let p2p_clone = p2p.clone();
+    io.ns(p2p.namespace(), move |s, d| on_p2p_connect(s, d, p2p_clone.clone()));
+}
+
+fn register_peer_namespace<S>(io: &SocketIo, p2p: Arc<P2PManager>, blobs: Arc<Blobs<S>>)
+where
+    S: Store + Send + Sync + 'static,
+{
+    let p2p_clone = p2p.clone();
+    let blobs_arc = blobs.clone();
+    io.ns("/peers", async move |s, d| {
+        let blobs_client = blobs_arc.client();
+        on_peer_message(s, d, p2p_clone.clone(), &blobs_client.clone()).await
+    });
+}
+
+/// ========== Periodic tasks ==========
+/// Name of a supervised task, set apart by the channel it works on
+fn task_name(task: &str, channel: Option<&str>) -> String {
+    match channel {
+        Some(channel) => format!("{task}:{channel}"),
+        None => task.to_string(),
+    }
+}
+
+/// Supervise a task that loops for as long as the node runs
+fn looping(name: impl Into<String>, tasks: &Tasks) -> Supervisor {
+    Supervisor::new(name).with_policy(RestartPolicy::Always).with_tasks(tasks)
+}
+
+fn spawn_advertisement_task(io: SocketIo, node_id: String, interval: Duration, tasks: &Tasks) {
+    looping("advertisement", tasks).spawn(move || {
+        let advertisement = json!({ "type": "advertise", "peer_id": node_id });
+        let io = io.clone();
+        async move {
+            loop {
+                if let Some(nsp) = io.of("/peers") {
+                    nsp.emit("advertise", &advertisement).await.ok();
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }
+    });
+}
+
+/// How often peers exchange heartbeats
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a peer may stay silent before its connection is considered dead
+const PEER_TIMEOUT: Duration = Duration::from_secs(45);
+
+fn spawn_peer_health_task(p2p: Arc<P2PManager>, tasks: &Tasks) {
+    looping(task_name("peer_health", p2p.channel()), tasks).spawn(move || {
+        let p2p = p2p.clone();
+        async move {
+            loop {
+                tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                p2p.send_heartbeat();
+                let dropped = p2p.check_peer_health(PEER_TIMEOUT);
+                if !dropped.is_empty() {
+                    info!("Dropped {} unresponsive peers", dropped.len());
+                }
+            }
+        }
+    });
+}
+
+/// How long an entry may stay pending before it is dropped
+const PENDING_ENTRY_TIMEOUT: Duration = Duration::from_secs(600);
+
+fn spawn_pending_entries_task(p2p: Arc<P2PManager>, interval: Duration, tasks: &Tasks) {
+    looping(task_name("pending_entries", p2p.channel()), tasks).spawn(move || {
+        let p2p = p2p.clone();
+        async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let added = p2p.retry_pending_entries(PENDING_ENTRY_TIMEOUT).await;
+                if !added.is_empty() {
+                    info!("Added {} pending entries on retry", added.len());
+                }
+            }
+        }
+    });
+}
+
+fn spawn_peer_connections(p2p: Arc<P2PManager>, bootstrap_peers: Vec<String>) {
+    tokio::spawn(async move {
+        for url in bootstrap_peers {
+            match p2p.discover_peers(&url, HEARTBEAT_INTERVAL, PEER_TIMEOUT).await {
+                Ok(dialed) => info!(bootstrap_url = url, "Dialing {} peers", dialed.len()),
+                Err(e) => warn!(bootstrap_url = url, "Peer discovery failed: {e}"),
+            }
+        }
+    });
+}
+
+/// How long the initial sync waits for peers to connect before syncing from those that did
+const INITIAL_SYNC_WAIT: Duration = Duration::from_secs(10);
+
+/// Sync the ledger from up to `peers` peers at once as soon as that many
+/// have connected, then leave newly connected peers to be synced with one by one
+fn spawn_initial_sync(p2p: Arc<P2PManager>, peers: usize, tasks: &Tasks) {
+    Supervisor::new(task_name("initial_sync", p2p.channel())).with_tasks(tasks).spawn(move || {
+        let p2p = p2p.clone();
+        async move {
+            let deadline = tokio::time::Instant::now() + INITIAL_SYNC_WAIT;
+            while p2p.connected_peer_ids().len() < peers && tokio::time::Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            let sources = pex::sample(p2p.connected_peer_ids(), peers);
+            if !sources.is_empty() {
+                match SyncCoordinator::new(&p2p).sync(&sources).await {
+                    Ok(report) => info!(
+                        peers = report.peers,
+                        segments = report.segments,
+                        retries = report.retries,
+                        "Initial sync added {} entries",
+                        report.added
+                    ),
+                    Err(e) => warn!("Initial sync failed, syncing with each peer instead: {e}"),
+                }
+            }
+            p2p.finish_initial_sync().await;
+        }
+    });
+}
+
+fn spawn_retention_task(
+    ledger: SharedLedger,
+    archive: Option<Arc<Archive>>,
+    interval: Duration,
+    channel: Option<&str>,
+    tasks: &Tasks,
+) {
+    looping(task_name("retention", channel), tasks).spawn(move || {
+        let (ledger, archive) = (ledger.clone(), archive.clone());
+        async move {
+            loop {
+                let pruned = match &archive {
+                    Some(archive) => archive.apply_retention(&ledger).await.unwrap_or_else(|e| {
+                        warn!("Failed to archive entries before pruning: {e}");
+                        0
+                    }),
+                    None => ledger.apply_retention(),
+                };
+                if pruned > 0 {
+                    info!("Pruned {pruned} entries under the retention policy");
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }
+    });
+}
+
+/// Dial the known nodes `p2p` has contacts for but no session with, and ask
+/// a few peers for more
+fn spawn_peer_exchange_task(p2p: Arc<P2PManager>, config: &PeerExchangeConfig, tasks: &Tasks) {
+    let (interval, fanout) = (config.interval(), config.fanout);
+    looping(task_name("peer_exchange", p2p.channel()), tasks).spawn(move || {
+        let p2p = p2p.clone();
+        async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                for contact in p2p.unconnected_contacts() {
+                    if let Err(e) = p2p.dial_contact(&contact, HEARTBEAT_INTERVAL, PEER_TIMEOUT).await {
+                        debug!(peer_id = contact.node_id, "Not dialing known node: {e}");
+                    }
+                }
+                p2p.exchange_peers(fanout).await;
+            }
+        }
+    });
+}
+
+/// How often nodes nobody has heard from or of are looked for
+const NODE_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Forget the nodes `p2p` knows of that neither it nor its peers heard from within `expiry`
+fn spawn_node_expiry_task(p2p: Arc<P2PManager>, expiry: Duration, tasks: &Tasks) {
+    looping(task_name("node_expiry", p2p.channel()), tasks).spawn(move || {
+        let p2p = p2p.clone();
+        async move {
+            loop {
+                tokio::time::sleep(NODE_EXPIRY_INTERVAL).await;
+                let expired = p2p.expire_nodes(expiry);
+                if !expired.is_empty() {
+                    info!(nodes = ?expired, "Forgot {} nodes not heard of in {expiry:?}", expired.len());
+                }
+            }
+        }
+    });
+}
+
+/// Set up a manager for each channel in `config`, its ledger configured like the main one
+fn build_channels(
+    config: &NodeConfig,
+    p2p: &P2PManager,
+    signing_key: &SigningKey,
+    endpoint: &Endpoint,
+) -> Result<Channels, String> {
+    if let Some(name) = config.acl.keys().find(|name| !config.channels.contains(name)) {
+        return Err(format!("ACL configured for {name}, which isn't a channel"));
+    }
+    if let Some(name) = config.channel_modes.keys().find(|name| !config.channels.contains(name)) {
+        return Err(format!("Mode configured for {name}, which isn't a channel"));
+    }
+    let mut channels = Channels::new();
+    for name in &config.channels {
+        let ledger = SharedLedger::with_signing_key(p2p.node_id().to_string(), signing_key.clone());
+        if let Some(mode) = config.channel_modes.get(name) {
+            ledger.set_mode(*mode)?;
+        }
+        ledger.set_consensus(config.consensus.build()?);
+        ledger.set_chain_id(config.chain_id.clone())?;
+        ledger.set_retention_policy(config.retention.clone());
+        ledger.set_limits(config.limits);
+        ledger.set_staking_config(config.staking);
+        ledger.set_validation_policy(Arc::new(config.validation.policy()));
+        ledger.set_schema_registry(p2p.ledger.schemas());
+        if let Some(acl) = config.acl.get(name) {
+            ledger.set_write_acl(WriteAcl::new(acl).map_err(|e| format!("ACL of channel {name}: {e}"))?);
+        }
+        let mut channel = P2PManager::new(p2p.node_id().to_string(), ledger)
+            .with_mode(p2p.mode().clone())
+            .with_encryption(config.p2p_encryption)
+            .with_key_ids(true)
+            .with_endpoint(endpoint.clone())
+            .with_codec(config.p2p_codec)
+            .with_audit_log(p2p.audit())
+            .with_mempool_config(config.mempool)
+            .with_stale_after(config.peer_stale_after())
+            .with_channel(name.clone());
+        if let Some(url) = &config.public_url {
+            channel = channel.with_public_url(url.clone());
+        }
+        if config.peer_exchange.is_enabled() {
+            channel = channel.with_peer_exchange(config.peer_exchange.sample_size);
+        }
+        if config.sync_peers > 0 {
+            channel = channel.with_initial_sync();
+        }
+        // Channels share the node's link, so they share its buckets too
+        if let Some(bandwidth) = p2p.bandwidth() {
+            channel = channel.with_bandwidth(bandwidth);
+        }
+        channels.insert(Arc::new(channel))?;
+    }
+    Ok(channels)
+}
+
+/// Record the contacts of the peers discovery learns about and dial them as
+/// they're learned, over QUIC if they can be reached that way and their URL otherwise
+fn spawn_discovered_peer_connections(p2p: Arc<P2PManager>, learned: UnboundedReceiver<PeerRecord>, tasks: &Tasks) {
+    // A restarted task picks up the peers learned while it was down
+    let learned = Arc::new(tokio::sync::Mutex::new(learned));
+    Supervisor::new("discovered_peers").with_tasks(tasks).spawn(move || {
+        let (p2p, learned) = (p2p.clone(), learned.clone());
+        async move {
+            let mut learned = learned.lock().await;
+            while let Some(peer) = learned.recv().await {
+                if peer.node_id == p2p.node_id() {
+                    continue;
+                }
+                p2p.contacts().set_url(&peer.node_id, peer.url.clone());
+                p2p.contacts().set_addr(&peer.node_id, peer.addr.clone());
+                let Some(contact) = p2p.contacts().get(&peer.node_id) else {
+                    continue;
+                };
+                let p2p = p2p.clone();
+                tokio::spawn(async move {
+                    match p2p.dial_contact(&contact, HEARTBEAT_INTERVAL, PEER_TIMEOUT).await {
+                        Ok(()) => info!(peer_id = peer.node_id, peer_url = peer.url, "Dialing discovered peer"),
+                        Err(e) => warn!(peer_id = peer.node_id, "Failed to dial discovered peer: {e}"),
+                    }
+                });
+            }
+        }
+    });
+}
+
+/// Download the snapshot behind a blob ticket and start the empty ledger from it
+async fn restore_checkpoint<S>(ledger: &SharedLedger, blobs: &Blobs<S>, ticket: &str) -> Result<(), NodeError>
+where
+    S: Store + Send + Sync + 'static,
+{
+    let ticket = BlobTicket::from_str(ticket)?;
+    let client = blobs.client();
+    client.download(ticket.hash(), ticket.node_addr().clone()).await?.finish().await?;
+    let snapshot = Snapshot::from_bytes(&client.read_to_bytes(ticket.hash()).await?)?;
+    ledger.restore_from_snapshot(&snapshot)?;
+    info!(height = snapshot.height, hash = snapshot.hash, "Restored ledger from checkpoint");
+    Ok(())
+}
+
+/// `POST /api/ledger/snapshot`: store a snapshot as a blob and return a ticket other nodes can start from
+fn snapshot_routes<S>(p2p: Arc<P2PManager>, blobs: Arc<Blobs<S>>, endpoint: Endpoint) -> Router
+where
+    S: Store + Send + Sync + 'static,
+{
+    let publish = async move |query: Result<Query<SnapshotQuery>, QueryRejection>| {
+        let Query(query) = query?;
+        let snapshot = api::take_snapshot(&p2p, &query)?;
+        // Publishing moves the tag, so earlier snapshots are left to garbage collection
+        let added = blobs.client().add_bytes_named(snapshot.to_bytes(), SNAPSHOT_TAG).await.map_err(internal_error)?;
+        let addr = endpoint.node_addr().await.map_err(internal_error)?;
+        let ticket = BlobTicket::new(addr, added.hash, added.format).map_err(internal_error)?;
+        info!(height = snapshot.height, blob_hash = %added.hash, "Published ledger snapshot");
+        Ok::<_, ApiError>(Json(json!({
+            "height": snapshot.height,
+            "hash": snapshot.hash,
+            "ticket": ticket.to_string(),
+        })))
+    };
+    Router::new().route("/api/ledger/snapshot", post(publish))
+}
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Serve gRPC on `addr`, checking calls with `guard`, until `stop` is set
+fn spawn_grpc_server(
+    addr: SocketAddr,
+    p2p: Arc<P2PManager>,
+    tls: Option<ServerTlsConfig>,
+    guard: CallGuard,
+    mut stop: watch::Receiver<bool>,
+) -> Result<(), tonic::transport::Error> {
+    let mut builder = tonic::transport::Server::builder();
+    if let Some(tls) = tls {
+        builder = builder.tls_config(tls)?;
+    }
+    let service = GsioService::new(p2p).into_guarded_server(guard);
+    let server = builder.add_service(service).serve_with_shutdown(addr, async move {
+        stop.wait_for(|stop| *stop).await.ok();
+    });
+    tokio::spawn(async move {
+        info!("gRPC server listening on {addr}");
+        if let Err(e) = server.await {
+            error!("gRPC server stopped: {e}");
+        }
+    });
+    Ok(())
+}
+
+/// ========== Socket connection handlers ==========
+async fn on_p2p_connect(socket: SocketRef, Data(data): Data<JsonValue>, p2p: Arc<P2PManager>) {
+    info!(ns = socket.ns(), ?socket.id, "P2P node connected");
+    p2p.handle_connection(socket, data);
+}
+
+/// ========== Peer-to-peer message router ==========
+async fn on_peer_message(
+    socket: SocketRef,
+    Data(data): Data<JsonValue>,
+    p2p: Arc<P2PManager>,
+    blobs_client: &MemClient,
+) {
+    let Some(msg_type) = data.get("type").and_then(|t| t.as_str()) else {
+        info!("Ignoring peer message without a type");
+        return;
+    };
+    let handled = match msg_type {
+        "peer_discovered" => handle_peer_discovered(socket, p2p, &data).await,
+        "advertise" => handle_advertise(socket, p2p, &data).await,
+        "sync_request" => handle_sync_request(socket, p2p, &data).await,
+        "sync_response" => handle_sync_response(socket, p2p, &data).await,
+        "fetch_blob" => handle_fetch_blob(socket, p2p, &data, blobs_client).await,
+        "entry_announce" => handle_entry_announce(socket, p2p, &data).await,
+        "blob_available" => handle_blob_available(socket, p2p, &data).await,
+        _ => {
+            info!("Unknown peer message type: {msg_type}");
+            Ok(())
+        }
+    };
+    // A message the node can't handle is dropped, and the connection carries on
+    if let Err(e) = handled {
+        warn!(msg_type, "Failed to handle peer message: {e}");
+    }
+}
+
+/// Who a peer message came from, for logs and the audit log. The `peer_id`
+/// a message claims isn't authenticated, so the socket stands in for it.
+fn peer_sender(socket: &SocketRef) -> String {
+    format!("/peers:{}", socket.id)
+}
+
+/// A string field of a peer message, which the message must have
+fn peer_field<'a>(data: &'a JsonValue, name: &str) -> Result<&'a str, String> {
+    data.get(name).and_then(|v| v.as_str()).ok_or_else(|| format!("Message has no {name}"))
+}
+
+/// ---- Individual peer-message helpers ----
+async fn handle_peer_discovered(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    let peer_id = peer_field(data, "peer_id")?;
+    info!(peer_id = peer_id, "Peer discovered, initiating peering");
+    p2p.ledger.add_known_node(peer_id.to_owned());
+    socket
+        .emit("advertise", &json!({ "type": "advertise", "peer_id": p2p.node_id() }))
+        .map_err(|e| format!("Failed to advertise to peer: {e}"))
+}
+
+async fn handle_advertise(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    let peer_id = peer_field(data, "peer_id")?;
+    info!(peer_id = peer_id, "Received peer advertisement, establishing connection");
+    p2p.ledger.add_known_node(peer_id.to_owned());
+    socket
+        .emit("peer_ack", &json!({ "type": "ack", "peer_id": p2p.node_id() }))
+        .map_err(|e| format!("Failed to acknowledge peer: {e}"))?;
+    info!(peer_id = peer_id, "Sent acknowledgment to peer, connection established");
+    request_peer_sync(&socket, &p2p, None)
+}
+
+/// Ask a peer for the entries after our tip, or for the page of its chain
+/// starting at the height `from` it said the rest of a sync picks up at
+fn request_peer_sync(socket: &SocketRef, p2p: &P2PManager, from: Option<usize>) -> Result<(), String> {
+    socket
+        .emit(
+            "peer_sync_request",
+            &json!({ "type": "sync_request", "peer_id": p2p.node_id(), "tip": p2p.ledger.chain_tip(), "from": from }),
+        )
+        .map_err(|e| format!("Failed to request sync: {e}"))
+}
+
+async fn handle_sync_request(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    // Peers that don't send their tip get the whole chain, a page at a time
+    let tip = data.get("tip").and_then(|t| serde_json::from_value::<ChainTip>(t.clone()).ok());
+    let from = data.get("from").and_then(|f| f.as_u64());
+    let page = match (from, &tip) {
+        (Some(from), _) => p2p.ledger.sync_page(from as usize, MAX_SYNC_PAGE_SIZE),
+        (None, Some(tip)) => p2p.ledger.missing_entries(tip, MAX_SYNC_PAGE_SIZE),
+        (None, None) => p2p.ledger.sync_page(1, MAX_SYNC_PAGE_SIZE),
+    };
+    // Where the peer picks up if the page doesn't reach our tip; `from` comes from the peer, so it may be anything
+    let next = page.from.saturating_add(page.entries.len());
+    let next = (!page.entries.is_empty() && next <= page.height).then_some(next);
+    let own_tip = p2p.ledger.chain_tip();
+    socket
+        .emit(
+            "peer_sync_response",
+            &json!({
+                "type": "sync_response",
+                "peer_id": p2p.node_id(),
+                "tip": own_tip,
+                "entries": page.entries,
+                "next": next
+            }),
+        )
+        .map_err(|e| format!("Failed to send sync response: {e}"))?;
+
+    // A peer with a longer chain has entries we are missing in turn
+    if from.is_none() && tip.is_some_and(|tip| tip.height > own_tip.height) {
+        request_peer_sync(&socket, &p2p, None)?;
+    }
+    Ok(())
+}
+
+async fn handle_sync_response(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    if let Some(peer_id) = data.get("peer_id").and_then(|id| id.as_str()) {
+        info!(peer_id = peer_id, "Received sync response from peer, peering active");
+    }
+
+    let entries = data.get("entries").ok_or("Sync response has no entries")?;
+    let entries = serde_json::from_value::<Vec<LedgerEntry>>(entries.clone())
+        .map_err(|e| format!("Invalid entries in sync response: {e}"))?;
+    if entries.is_empty() {
+        p2p.record_sync();
+        info!("Ledger already in sync with peer");
+        return Ok(());
+    }
+    // Entries are checked like those synced over /p2p
+    let sender = peer_sender(&socket);
+    p2p.check_entry_count(&sender, entries.len()).map_err(|e| e.to_string())?;
+    let received = entries.len();
+    let (_, rejection) = p2p.add_peer_entries(&sender, entries);
+    let added = p2p.apply_pending_entries();
+    p2p.record_sync();
+    info!("Added {} of {} entries from peer sync", added.len(), received);
+    // Entries after a rejected one build on it, so there is no point in asking for the rest
+    if rejection.is_some() {
+        return Err("Peer sent entries that were rejected".to_string());
+    }
+    match data.get("next").and_then(|n| n.as_u64()) {
+        Some(next) => request_peer_sync(&socket, &p2p, Some(next as usize)),
+        None => Ok(()),
+    }
+}
+
+async fn handle_fetch_blob(
+    socket: SocketRef,
+    p2p: Arc<P2PManager>,
+    data: &JsonValue,
+    _blobs_client: &MemClient,
+) -> Result<(), String> {
+    let hash_str = peer_field(data, "blob_hash")?.to_owned();
+    let ack = match Hash::from_str(&hash_str) {
+        Ok(_hash) => json!({
+            "type": "blob_fetch_ack",
+            "peer_id": p2p.node_id(),
+            "blob_hash": hash_str,
+            "status": "success"
+        }),
+        Err(e) => json!({
+            "type": "blob_fetch_ack",
+            "peer_id": p2p.node_id(),
+            "blob_hash": hash_str,
+            "status": "error",
+            "error": format!("Invalid hash: {e}")
+        }),
+    };
+    socket.emit("blob_fetch_ack", &ack).map_err(|e| format!("Failed to acknowledge blob fetch: {e}"))
+}
+
+async fn handle_entry_announce(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    let entry = data.get("entry").ok_or("Entry announcement has no entry")?;
+    let entry = serde_json::from_value::<LedgerEntry>(entry.clone()).map_err(|e| format!("Invalid announced entry: {e}"))?;
+    let (_, rejection) = p2p.add_peer_entries(&peer_sender(&socket), vec![entry.clone()]);
+    p2p.apply_pending_entries();
+    if rejection.is_some() {
+        return Err(format!("Announced entry {} was rejected", entry.id));
+    }
+
+    let hash_str = format!("entry-{}-hash", entry.id);
+    if Hash::from_str(&hash_str).is_ok() {
+        socket
+            .emit(
+                "blob_available",
+                &json!({
+                    "type": "blob_available",
+                    "peer_id": p2p.node_id(),
+                    "entry_id": entry.id,
+                    "blob_hash": hash_str
+                }),
+            )
+            .map_err(|e| format!("Failed to announce blob: {e}"))?;
+    }
+    Ok(())
+}
+
+async fn handle_blob_available(socket: SocketRef, p2p: Arc<P2PManager>, data: &JsonValue) -> Result<(), String> {
+    let blob_hash = peer_field(data, "blob_hash")?;
+    let entry_id = peer_field(data, "entry_id")?;
+    socket
+        .emit(
+            "fetch_blob",
+            &json!({
+                "type": "fetch_blob",
+                "peer_id": p2p.node_id(),
+                "blob_hash": blob_hash,
+                "entry_id": entry_id
+            }),
+        )
+        .map_err(|e| format!("Failed to fetch blob: {e}"))
+}
+
+/// ========== Embedding API ==========
+/// Configures a node, then starts it
+pub struct NodeBuilder {
+    config: NodeConfig,
+    signing_key: Option<SigningKey>,
+    grpc: bool,
+}
+
+impl NodeBuilder {
+    /// Start from `config`, loaded with [`NodeConfig::load`] or built in code
+    pub fn new(config: NodeConfig) -> Self {
+        Self { config, signing_key: None, grpc: true }
+    }
+
+    /// Run as `key` rather than the configured node key or a new one
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Keep blobs in a store under `path` rather than in memory
+    pub fn with_blob_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.blob_path = Some(path.into());
+        self
+    }
+
+    /// Serve the HTTP API and Socket.IO on `addr`, where port 0 picks a free port
+    pub fn with_listen_address(mut self, addr: SocketAddr) -> Self {
+        self.config.listen_address = addr;
+        self
+    }
+
+    /// Serve gRPC on `addr`
+    pub fn with_grpc_address(mut self, addr: SocketAddr) -> Self {
+        self.config.grpc_address = addr;
+        self.grpc = true;
+        self
+    }
+
+    /// Don't serve gRPC
+    pub fn without_grpc(mut self) -> Self {
+        self.grpc = false;
+        self
+    }
+
+    /// Reach other nodes' iroh endpoints through the relay at `url`
+    pub fn with_relay(mut self, url: impl Into<String>) -> Self {
+        self.config.relay_address = Some(url.into());
+        self
+    }
+
+    /// Dial the peers of the node at `url` once started
+    pub fn with_bootstrap_peer(mut self, url: impl Into<String>) -> Self {
+        self.config.bootstrap_peers.push(url.into());
+        self
+    }
+
+    /// Host channel `name` alongside the main ledger
+    pub fn with_channel(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        if !self.config.channels.contains(&name) {
+            self.config.channels.push(name);
+        }
+        self
+    }
+
+    /// Bind the node's iroh endpoint and listener and start it, serving and syncing in the background
+    pub async fn start(self) -> Result<Node, NodeError> {
+        let NodeBuilder { config, signing_key, grpc } = self;
+        // The node is known by its key, which also keys its iroh endpoint
+        let signing_key = match signing_key {
+            Some(key) => key,
+            None => config.signing_key()?.unwrap_or_else(|| SigningKey::generate(&mut OsRng)),
+        };
+        let relay_address = config
+            .relay_address
+            .as_deref()
+            .ok_or("A relay address must be set with RELAY_ADDRESS, --relay or relay_address")?;
+        let relays = RelayMap::from(RelayUrl::from_str(relay_address)?);
+
+        // --- IROH SETUP --------------------------------------------------------
+        let endpoint = Endpoint::builder()
+            .secret_key(identity::iroh_secret_key(&signing_key))
+            .discovery_n0()
+            .relay_conn_protocol(iroh_relay::http::Protocol::Websocket)
+            .discovery_local_network()
+            .relay_mode(RelayMode::Custom(relays)).bind().await?;
+
+        match &config.blob_path {
+            Some(path) => {
+                info!("Storing blobs in {}", path.display());
+                let blobs = Blobs::persistent(path).await?.build(&endpoint);
+                start_node(config, signing_key, endpoint, Arc::new(blobs), grpc).await
+            }
+            None => {
+                let blobs = Blobs::memory().build(&endpoint);
+                start_node(config, signing_key, endpoint, Arc::new(blobs), grpc).await
+            }
+        }
+    }
+}
+
+/// A running node, until it's [shut down](Node::shutdown)
+pub struct Node {
+    p2p: Arc<P2PManager>,
+    channels: Arc<Channels>,
+    health: Arc<Health>,
+    admin: Arc<Admin>,
+    tasks: Tasks,
+    local_addr: SocketAddr,
+    router: IrohRouter,
+    stop: watch::Sender<bool>,
+    server: JoinHandle<io::Result<()>>,
+    sequencers: Vec<JoinHandle<()>>,
+}
+
+impl Node {
+    /// Configure a node starting from `config`
+    pub fn builder(config: NodeConfig) -> NodeBuilder {
+        NodeBuilder::new(config)
+    }
+
+    /// The node's ID, derived from its key
+    pub fn node_id(&self) -> &str {
+        self.p2p.node_id()
+    }
+
+    /// The main ledger
+    pub fn ledger(&self) -> &SharedLedger {
+        &self.p2p.ledger
+    }
+
+    /// The manager syncing the main ledger with peers
+    pub fn p2p(&self) -> &Arc<P2PManager> {
+        &self.p2p
+    }
+
+    /// The node's channels
+    pub fn channels(&self) -> &Arc<Channels> {
+        &self.channels
+    }
+
+    /// What the health probes report
+    pub fn health(&self) -> &Arc<Health> {
+        &self.health
+    }
+
+    /// The node's background tasks, for adding supervised tasks of your own
+    pub fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    /// Address the HTTP API and Socket.IO are served on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Resolves once a shutdown has been requested through the admin API
+    pub async fn shutdown_requested(&self) {
+        self.admin.shutdown_requested().await;
+    }
+
+    /// Say goodbye to peers, drain the servers, stop the background tasks and
+    /// stop the iroh protocols, which flushes the blob store to disk
+    pub async fn shutdown(self) -> Result<(), NodeError> {
+        self.stop.send_replace(true);
+        let served = self.server.await;
+        self.tasks.abort_all();
+        for sequencer in &self.sequencers {
+            sequencer.abort();
+        }
+        self.router.shutdown().await?;
+        served??;
+        info!("Node stopped");
+        Ok(())
+    }
+}
+async fn start_node<S>(
+    mut config: NodeConfig,
+    signing_key: SigningKey,
+    endpoint: Endpoint,
+    blobs: Arc<Blobs<S>>,
+    grpc: bool,
+) -> Result<Node, NodeError>
+where
+    S: Store + Send + Sync + 'static,
+{
+    let node_id = identity::from_iroh(&endpoint.node_id());
+    info!(name = ?config.node_name, "Starting node with ID: {node_id}");
+
+    // --- DISCOVERY ---------------------------------------------------------
+    let discovery_peers = config.discovery.peer_ids()?;
+    if config.public_url.is_none() {
+        warn!("No public URL set, so other nodes can't discover this one");
+    }
+    let (discovery, learned_peers) = Discovery::new(
+        endpoint.clone(),
+        &config.discovery.topic,
+        config.public_url.clone(),
+        config.discovery.interval(),
+    );
+    let router = IrohRouter::builder(endpoint.clone()).accept(ALPN, blobs.clone());
+    let router = if config.discovery.is_enabled() {
+        router.accept(DISCOVERY_ALPN, discovery.clone())
+    } else {
+        router
+    };
+
+    // --- NODE & LEDGER -----------------------------------------------------
+    // Channels' ledgers sign with the same key
+    let ledger = SharedLedger::with_signing_key(node_id.clone(), signing_key.clone());
+    info!(public_key = ledger.public_key(), "Node key");
+    // Validators and the chain ID in the genesis apply to channels as well
+    let genesis = config.load_genesis()?;
+    if let Some(genesis) = &genesis {
+        config.consensus = genesis.consensus(&config.consensus)?;
+        config.chain_id.get_or_insert_with(|| genesis.chain_id.clone());
+    }
+    let consensus = config.consensus.build()?;
+    if let Some(validators) = consensus.validators() {
+        if let Some(key) = validators.key(&node_id)
+            && hex::encode(key.to_bytes()) != ledger.public_key()
+        {
+            return Err(format!("Validator key for {node_id} doesn't match the node key").into());
+        }
+        info!(validators = ?validators.node_ids(), quorum = validators.quorum(), "Validators");
+    }
+    info!(strategy = consensus.name(), "Consensus");
+    ledger.set_consensus(consensus);
+    ledger.set_chain_id(config.chain_id.clone())?;
+    if let Some(chain_id) = &config.chain_id {
+        info!(chain_id, "Chain");
+    }
+    if let Some(genesis) = genesis {
+        info!(chain_id = genesis.chain_id, hash = genesis.hash(), "Genesis");
+        ledger.set_genesis(genesis)?;
+    }
+    ledger.set_checkpoint_keys(&config.checkpoint_keys)?;
+    if let Some(ticket) = &config.checkpoint {
+        restore_checkpoint(&ledger, &blobs, ticket).await?;
+    }
+    ledger.set_retention_policy(config.retention.clone());
+    ledger.set_limits(config.limits);
+    info!(unbonding_period = config.staking.unbonding_period, "Staking");
+    ledger.set_staking_config(config.staking);
+    let validation = config.validation.policy();
+    info!(rules = validation.len(), "Entry validation");
+    ledger.set_validation_policy(Arc::new(validation));
+    let schemas = SchemaRegistry::from_config(&config.schemas)?;
+    info!(
+        kinds = schemas.kinds().len(),
+        require_kind = schemas.requires_kind(),
+        allow_unknown_kinds = schemas.allows_unknown_kinds(),
+        "Entry schemas"
+    );
+    ledger.set_schema_registry(Arc::new(schemas));
+    let mode = config.node_mode();
+    info!(?mode, "Node mode");
+    info!(encryption = config.p2p_encryption, "P2P encryption");
+    info!(codec = %config.p2p_codec, "P2P codec");
+    // Peers' references are rehydrated even when this node doesn't offload
+    let blob_store = Arc::new(IrohBlobStore::new(blobs.clone(), endpoint.node_id()));
+    let offloader = Offloader::from_config(blob_store.clone(), &config.offload);
+    info!(threshold_bytes = offloader.threshold(), "Entry data offloading");
+    let archive = config.archive.enabled.then(|| Arc::new(Archive::new(blob_store)));
+    info!(enabled = config.archive.enabled, "Archiving pruned entries");
+    let audit = Arc::new(AuditLog::from_config(&config.audit)?);
+    info!(path = ?config.audit.path, "Audit log");
+    let mut p2p = P2PManager::new(node_id.clone(), ledger)
+        .with_mode(mode)
+        .with_encryption(config.p2p_encryption)
+        .with_key_ids(true)
+        .with_endpoint(endpoint.clone())
+        .with_codec(config.p2p_codec)
+        .with_audit_log(audit)
+        .with_mempool_config(config.mempool)
+        .with_stale_after(config.peer_stale_after())
+        .with_offloader(Arc::new(offloader));
+    if config.sync_peers > 0 {
+        p2p = p2p.with_initial_sync();
+    }
+    if config.bandwidth.is_enabled() {
+        p2p = p2p.with_bandwidth(Arc::new(Bandwidth::new(config.bandwidth)));
+    }
+    info!(
+        upload = config.bandwidth.upload,
+        download = config.bandwidth.download,
+        peer_upload = config.bandwidth.peer_upload,
+        peer_download = config.bandwidth.peer_download,
+        enabled = config.bandwidth.is_enabled(),
+        "Sync bandwidth limits"
+    );
+    if let Some(url) = &config.public_url {
+        p2p = p2p.with_public_url(url.clone());
+    }
+    if config.peer_exchange.is_enabled() {
+        p2p = p2p.with_peer_exchange(config.peer_exchange.sample_size);
+    }
+    let p2p = Arc::new(match &archive {
+        Some(archive) => p2p.with_archive(archive.clone()),
+        None => p2p,
+    });
+    // Background tasks report how they fare here, for the health probes
+    let tasks = Tasks::new();
+    let health = Arc::new(Health::new(p2p.clone()).with_endpoint(endpoint.clone()).with_tasks(tasks.clone()));
+    if let Some(path) = &config.import {
+        let file = tokio::fs::File::open(path).await.map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        export::import(&p2p, tokio::io::BufReader::new(file)).await?;
+    }
+    // Only ready once the checkpoint and the import, if any, are in the ledger
+    health.set_ledger_initialized();
+    let channels = Arc::new(build_channels(&config, &p2p, &signing_key, &endpoint)?);
+    info!(channels = ?config.channels, "Channels");
+    // Peers reach the main ledger and each channel over their own ALPN
+    let router = [p2p.clone()]
+        .into_iter()
+        .chain(channels.iter().map(|(_, channel)| channel.clone()))
+        .map(|p2p| QuicTransport::new((*p2p).clone()))
+        .fold(router, |router, transport| router.accept(transport.alpn(), transport));
+    let router = router.spawn();
+    // Set when the node shuts down, stopping the servers
+    let (stop, stopped) = watch::channel(false);
+    let authenticator = if config.auth.is_enabled() {
+        Some(Arc::new(Authenticator::new(&config.auth)?))
+    } else {
+        None
+    };
+    info!(enabled = authenticator.is_some(), "Client authentication");
+    let limiter = if config.rate_limit.is_enabled() {
+        Some(Arc::new(RateLimiter::new(&config.rate_limit)?))
+    } else {
+        None
+    };
+    info!(
+        requests_per_second = config.rate_limit.requests_per_second,
+        burst = config.rate_limit.burst,
+        enabled = limiter.is_some(),
+        "Rate limiting"
+    );
+
+    // --- SOCKET.IO ---------------------------------------------------------
+    let (layer, io) = SocketIo::new_layer();
+    match &authenticator {
+        Some(auth) => socket::register_authenticated_root_namespace(&io, p2p.clone(), auth.clone(), limiter.clone()),
+        None => socket::register_root_namespace(&io, p2p.clone(), limiter.clone()),
+    }
+    register_p2p_namespace(&io, p2p.clone());
+    register_peer_namespace(&io, p2p.clone(), blobs.clone());
+
+    let advertisement_interval = config.advertisement_interval();
+    spawn_advertisement_task(io.clone(), node_id.clone(), advertisement_interval, &tasks);
+    spawn_retention_task(p2p.ledger.clone(), archive.clone(), config.retention_interval(), None, &tasks);
+    if config.blob_gc.is_enabled() {
+        // Blobs a persistent store kept from earlier runs are indexed here
+        let gc = BlobGc::load(blobs.clone(), p2p.ledger.clone(), config.blob_gc.grace()).await?;
+        let gc = match &archive {
+            Some(archive) => gc.with_archive(archive.clone()),
+            None => gc,
+        };
+        info!(unreferenced = gc.unreferenced(), "Indexed stored blobs");
+        Arc::new(gc).spawn(config.blob_gc.interval(), &tasks);
+    }
+    info!(
+        interval = config.blob_gc.interval,
+        grace = config.blob_gc.grace,
+        enabled = config.blob_gc.is_enabled(),
+        "Blob garbage collection"
+    );
+    spawn_peer_health_task(p2p.clone(), &tasks);
+    spawn_pending_entries_task(p2p.clone(), config.pending_retry_interval(), &tasks);
+    let mut sequencers = vec![p2p.start_sequencer()];
+    spawn_peer_connections(p2p.clone(), config.bootstrap_peers.clone());
+    if config.sync_peers > 0 {
+        spawn_initial_sync(p2p.clone(), config.sync_peers, &tasks);
+    }
+    if config.peer_exchange.is_enabled() {
+        spawn_peer_exchange_task(p2p.clone(), &config.peer_exchange, &tasks);
+    }
+    if let Some(expiry) = config.peer_expiry() {
+        spawn_node_expiry_task(p2p.clone(), expiry, &tasks);
+    }
+    for (_, channel) in channels.iter() {
+        register_p2p_namespace(&io, channel.clone());
+        spawn_retention_task(channel.ledger.clone(), None, config.retention_interval(), channel.channel(), &tasks);
+        spawn_peer_health_task(channel.clone(), &tasks);
+        spawn_pending_entries_task(channel.clone(), config.pending_retry_interval(), &tasks);
+        sequencers.push(channel.start_sequencer());
+        spawn_peer_connections(channel.clone(), config.bootstrap_peers.clone());
+        if config.sync_peers > 0 {
+            spawn_initial_sync(channel.clone(), config.sync_peers, &tasks);
+        }
+        if config.peer_exchange.is_enabled() {
+            spawn_peer_exchange_task(channel.clone(), &config.peer_exchange, &tasks);
+        }
+        if let Some(expiry) = config.peer_expiry() {
+            spawn_node_expiry_task(channel.clone(), expiry, &tasks);
+        }
+    }
+    info!(
+        interval = config.peer_exchange.interval,
+        fanout = config.peer_exchange.fanout,
+        enabled = config.peer_exchange.is_enabled(),
+        "Peer exchange"
+    );
+    info!(stale_after = config.peer_stale_after, expiry = config.peer_expiry, "Node liveness");
+    if let Some(url) = &config.rendezvous.url {
+        p2p.connect_rendezvous(url.clone(), config.rendezvous.token.clone(), HEARTBEAT_INTERVAL);
+    }
+    info!(
+        url = ?config.rendezvous.url,
+        token = config.rendezvous.token.is_some(),
+        enabled = config.rendezvous.is_enabled(),
+        "Relay rendezvous"
+    );
+    let snapshots = snapshot_routes(p2p.clone(), blobs.clone(), endpoint.clone());
+    info!(
+        enabled = config.discovery.is_enabled(),
+        topic = config.discovery.topic,
+        "Peer discovery"
+    );
+    // Peers joined through the admin API are dialed even without discovery
+    spawn_discovered_peer_connections(p2p.clone(), learned_peers, &tasks);
+    if config.discovery.is_enabled() {
+        discovery.clone().spawn(discovery_peers, config.discovery.interval(), &tasks);
+    }
+
+    // --- GRPC SERVER -------------------------------------------------------
+    info!(
+        enabled = config.tls.is_enabled(),
+        client_certificates = config.tls.requires_client_certificates(),
+        "TLS"
+    );
+    let grpc_tls = config.tls.is_enabled().then(|| config.tls.grpc_config()).transpose()?;
+    if grpc {
+        // gRPC clients are held to the same credentials and rate limit as HTTP ones
+        let mut guard = CallGuard::new();
+        if let Some(auth) = &authenticator {
+            guard = guard.with_authenticator(auth.clone());
+        }
+        if let Some(limiter) = &limiter {
+            guard = guard.with_rate_limiter(limiter.clone());
+        }
+        spawn_grpc_server(config.grpc_address, p2p.clone(), grpc_tls, guard, stopped.clone())?;
+    }
+
+    // --- HTTP SERVER -------------------------------------------------------
+    info!(
+        base = config.fees.base,
+        per_byte = config.fees.per_byte,
+        types = config.fees.types.len(),
+        min_fee = config.mempool.min_fee,
+        "Transaction fees"
+    );
+    let api = api::router(p2p.clone())
+        .merge(export::router(p2p.clone()))
+        .merge(snapshots)
+        .merge(fees::router(Arc::new(config.fees.clone())))
+        .merge(channels::router(channels.clone()));
+    let api = match archive {
+        Some(archive) => api.merge(archive::router(archive)),
+        None => api,
+    };
+    let api = match authenticator {
+        Some(auth) => api
+            .route_layer(middleware::from_fn_with_state(auth.clone(), auth::require_auth))
+            .merge(auth::router(auth)),
+        None => api,
+    };
+    let mut admin = Admin::new(
+        p2p.clone(),
+        RuntimeConfig { retention: config.retention.clone(), validation: config.validation.clone() },
+    )
+    .with_discovery(discovery);
+    if let Some(path) = &config.node_key {
+        admin = admin.with_key_file(path);
+    }
+    let admin = Arc::new(admin);
+    info!(enabled = config.admin.is_enabled(), "Admin API");
+    let api = if config.admin.is_enabled() {
+        api.merge(admin::router(admin.clone(), &config.admin)?)
+    } else {
+        api
+    };
+    let api = match limiter {
+        Some(limiter) => api.layer(middleware::from_fn_with_state(limiter, ratelimit::limit_rate)),
+        None => api,
+    };
+    let app = Router::new()
+        .route("/", get(|| async { "GSIO-Net Distributed Ledger Node" }))
+        .merge(health::router(health.clone()))
+        .merge(api)
+        .layer(layer)
+        // Every request runs in a span, exported along with the P2P and ledger spans
+        .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO)));
+
+    let tls = config.tls.is_enabled().then(|| config.tls.server_config()).transpose()?;
+    let listener = TcpListener::bind(config.listen_address).await?;
+    let local_addr = listener.local_addr()?;
+    info!("Server listening on {local_addr}");
+
+    let drain = drain_node(stopped, p2p.clone(), channels.clone(), io, health.clone());
+    // Clients' addresses are needed to limit their request rate
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = match tls {
+        Some(tls) => {
+            let listener = listener.into_std()?;
+            let handle = axum_server::Handle::new();
+            let draining = handle.clone();
+            tokio::spawn(async move {
+                drain.await;
+                draining.graceful_shutdown(None);
+            });
+            let server = axum_server::from_tcp_rustls(listener, RustlsConfig::from_config(tls)).handle(handle).serve(app);
+            tokio::spawn(server)
+        }
+        None => tokio::spawn(async move { axum::serve(listener, app).with_graceful_shutdown(drain).await }),
+    };
+
+    Ok(Node { p2p, channels, health, admin, tasks, local_addr, router, stop, server, sequencers })
+}
+
+/// Once `stop` is set, say goodbye to peers and close every socket so the HTTP server can drain
+async fn drain_node(
+    mut stop: watch::Receiver<bool>,
+    p2p: Arc<P2PManager>,
+    channels: Arc<Channels>,
+    io: SocketIo,
+    health: Arc<Health>,
+) {
+    stop.wait_for(|stop| *stop).await.ok();
+    info!("Shutting down");
+    health.set_draining();
+
+    p2p.leave().await;
+    channels.leave().await;
+    // Socket.IO connections are long-lived, so the server only drains once they are closed
+    io.close().await;
+}
@@ -0,0 +1,290 @@
+//! Keeping large entry data out of the ledger.
+//!
+//! Entry data longer than the configured threshold is stored as a blob and
+//! the entry records a reference to it instead, so peers only exchange the
+//! 32-byte hash on every sync. References look like
+//! `{"$blob": "<hex BLAKE3 hash>", "size": <bytes>, "provider": "<iroh node ID>"}`;
+//! the entry hash and signatures cover the reference, not the data.
+//!
+//! Entries are rehydrated when they're read: the data is fetched from the
+//! local store, or downloaded from the node that provided it, and put back
+//! in `data` with the reference moved to `blob`. Entries whose blob can't be
+//! found are returned with the reference in place.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use iroh::{NodeAddr, NodeId};
+use iroh_blobs::net_protocol::Blobs;
+use iroh_blobs::Hash;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tracing::warn;
+
+use crate::ledger::LedgerEntry;
+
+/// Key marking entry data as a blob reference
+pub const BLOB_KEY: &str = "$blob";
+
+/// The `[offload]` section of the config file; entry data stays inline unless `threshold_bytes` is set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OffloadConfig {
+    /// Entry data longer than this many bytes, serialized as JSON, is stored as a blob
+    pub threshold_bytes: usize,
+}
+
+impl OffloadConfig {
+    /// Whether this node offloads the data of entries it creates
+    pub fn is_enabled(&self) -> bool {
+        self.threshold_bytes > 0
+    }
+}
+
+/// Reference to entry data stored as a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobRef {
+    /// Hex-encoded BLAKE3 hash of the serialized data
+    pub hash: String,
+    /// Length of the serialized data in bytes
+    pub size: u64,
+    /// Iroh node ID of a node that has the blob
+    pub provider: Option<String>,
+}
+
+impl BlobRef {
+    /// Read a reference from entry data, if the data is one
+    pub fn from_data(data: &JsonValue) -> Option<Self> {
+        let object = data.as_object()?;
+        let hash = object.get(BLOB_KEY)?.as_str()?;
+        let size = object.get("size")?.as_u64()?;
+        let provider = match object.get("provider") {
+            None => None,
+            Some(provider) => Some(provider.as_str()?.to_string()),
+        };
+        // Data that merely happens to have a `$blob` key isn't a reference
+        let known_keys = 2 + usize::from(provider.is_some());
+        if object.len() != known_keys || !is_blob_hash(hash) {
+            return None;
+        }
+
+        Some(Self { hash: hash.to_string(), size, provider })
+    }
+
+    /// Whether `data`, serialized the way it's offloaded, is the blob this refers to
+    pub fn matches(&self, data: &JsonValue) -> bool {
+        let bytes = data.to_string().into_bytes();
+        bytes.len() as u64 == self.size && blob_hash(&bytes) == self.hash
+    }
+
+    /// The entry data standing in for the blob
+    pub fn to_data(&self) -> JsonValue {
+        match &self.provider {
+            Some(provider) => json!({ BLOB_KEY: self.hash, "size": self.size, "provider": provider }),
+            None => json!({ BLOB_KEY: self.hash, "size": self.size }),
+        }
+    }
+}
+
+/// Whether `hash` is a BLAKE3 hash in lowercase hex, as blobs are addressed
+pub fn is_blob_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hex-encoded BLAKE3 hash blobs are addressed by
+pub fn blob_hash(bytes: &[u8]) -> String {
+    Hash::new(bytes).to_hex()
+}
+
+/// Where offloaded entry data is kept
+pub trait BlobStore: Send + Sync {
+    /// Store `bytes`, returning their hash
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<String, String>>;
+
+    /// The bytes with `hash`, fetched from `provider` if they aren't stored
+    /// locally, or `None` if they can't be found
+    fn get(&self, hash: String, provider: Option<String>) -> BoxFuture<'_, Result<Option<Vec<u8>>, String>>;
+
+    /// Iroh node ID other nodes can download this store's blobs from
+    fn provider(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Blob store that keeps blobs in memory and can't share them, for tests
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of blobs stored
+    pub fn len(&self) -> usize {
+        self.blobs.lock().unwrap().len()
+    }
+
+    /// Whether no blobs are stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<String, String>> {
+        let hash = blob_hash(&bytes);
+        self.blobs.lock().unwrap().insert(hash.clone(), bytes);
+        Box::pin(async move { Ok(hash) })
+    }
+
+    fn get(&self, hash: String, _provider: Option<String>) -> BoxFuture<'_, Result<Option<Vec<u8>>, String>> {
+        let bytes = self.blobs.lock().unwrap().get(&hash).cloned();
+        Box::pin(async move { Ok(bytes) })
+    }
+}
+
+/// Blob store backed by the node's iroh blobs, downloading blobs it doesn't have from their provider
+pub struct IrohBlobStore<S> {
+    blobs: Arc<Blobs<S>>,
+    node_id: NodeId,
+}
+
+impl<S> IrohBlobStore<S> {
+    /// Create a store on `blobs`, which other nodes reach as `node_id`
+    pub fn new(blobs: Arc<Blobs<S>>, node_id: NodeId) -> Self {
+        Self { blobs, node_id }
+    }
+}
+
+impl<S> fmt::Debug for IrohBlobStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IrohBlobStore").field("node_id", &self.node_id).finish()
+    }
+}
+
+impl<S: iroh_blobs::store::Store> BlobStore for IrohBlobStore<S> {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<String, String>> {
+        Box::pin(async move {
+            let added = self.blobs.client().add_bytes(bytes).await.map_err(|e| e.to_string())?;
+            Ok(added.hash.to_hex())
+        })
+    }
+
+    fn get(&self, hex: String, provider: Option<String>) -> BoxFuture<'_, Result<Option<Vec<u8>>, String>> {
+        Box::pin(async move {
+            let hash = Hash::from_str(&hex).map_err(|e| e.to_string())?;
+            let client = self.blobs.client();
+            if !client.has(hash).await.map_err(|e| e.to_string())? {
+                let Some(provider) = &provider else { return Ok(None) };
+                let provider = NodeId::from_str(provider).map_err(|e| e.to_string())?;
+                if provider == self.node_id {
+                    return Ok(None);
+                }
+                let download = client.download(hash, NodeAddr::new(provider)).await.map_err(|e| e.to_string())?;
+                download.finish().await.map_err(|e| format!("Failed to download blob {hex}: {e}"))?;
+            }
+            let bytes = client.read_to_bytes(hash).await.map_err(|e| e.to_string())?;
+            Ok(Some(bytes.to_vec()))
+        })
+    }
+
+    fn provider(&self) -> Option<String> {
+        Some(self.node_id.to_string())
+    }
+}
+
+/// Moves large entry data into a blob store and back
+pub struct Offloader {
+    store: Arc<dyn BlobStore>,
+    /// Data longer than this is offloaded; nothing is if unset
+    threshold: Option<usize>,
+}
+
+impl Offloader {
+    /// Create an offloader storing data longer than `threshold` bytes in `store`.
+    ///
+    /// Without a threshold no data is offloaded, but references in entries
+    /// from peers are still rehydrated.
+    pub fn new(store: Arc<dyn BlobStore>, threshold: Option<usize>) -> Self {
+        Self { store, threshold }
+    }
+
+    /// Create an offloader for the `[offload]` config section
+    pub fn from_config(store: Arc<dyn BlobStore>, config: &OffloadConfig) -> Self {
+        Self::new(store, config.is_enabled().then_some(config.threshold_bytes))
+    }
+
+    /// Bytes above which data is offloaded, if any is
+    pub fn threshold(&self) -> Option<usize> {
+        self.threshold
+    }
+
+    /// Whether `data` is large enough to be offloaded
+    pub fn should_offload(&self, data: &JsonValue) -> bool {
+        self.threshold.is_some_and(|threshold| data.to_string().len() > threshold)
+    }
+
+    /// Store `data` as a blob, returning the reference to record in its place
+    pub async fn offload(&self, data: &JsonValue) -> Result<BlobRef, String> {
+        let bytes = data.to_string().into_bytes();
+        let size = bytes.len() as u64;
+        let hash = self.store.put(bytes).await?;
+        Ok(BlobRef { hash, size, provider: self.store.provider() })
+    }
+
+    /// The bytes of the blob with `hash`, asking `provider` for it if it isn't stored here
+    pub async fn fetch(&self, hash: &str, provider: Option<String>) -> Result<Option<Vec<u8>>, String> {
+        let Some(bytes) = self.store.get(hash.to_string(), provider).await? else { return Ok(None) };
+        if blob_hash(&bytes) != hash {
+            return Err(format!("Blob {hash} doesn't match its hash"));
+        }
+        Ok(Some(bytes))
+    }
+
+    /// The data a reference points to, or `None` if the blob can't be found
+    pub async fn load(&self, reference: &BlobRef) -> Result<Option<JsonValue>, String> {
+        let Some(bytes) = self.fetch(&reference.hash, reference.provider.clone()).await? else { return Ok(None) };
+        if bytes.len() as u64 != reference.size {
+            return Err(format!("Blob {} doesn't match its reference", reference.hash));
+        }
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| format!("Blob {} isn't JSON: {e}", reference.hash))
+    }
+
+    /// Put an entry's offloaded data back in place, keeping the reference in `blob`
+    pub async fn rehydrate(&self, mut entry: LedgerEntry) -> LedgerEntry {
+        if entry.blob.is_some() {
+            return entry;
+        }
+        let Some(reference) = BlobRef::from_data(&entry.data) else { return entry };
+        match self.load(&reference).await {
+            Ok(Some(data)) => entry.blob = Some(std::mem::replace(&mut entry.data, data)),
+            Ok(None) => warn!(entry_id = entry.id, hash = reference.hash, "Blob for entry not found"),
+            Err(e) => warn!(entry_id = entry.id, "Failed to rehydrate entry: {e}"),
+        }
+        entry
+    }
+
+    /// Rehydrate each of `entries`
+    pub async fn rehydrate_all(&self, entries: Vec<LedgerEntry>) -> Vec<LedgerEntry> {
+        let mut rehydrated = Vec::with_capacity(entries.len());
+        for entry in entries {
+            rehydrated.push(self.rehydrate(entry).await);
+        }
+        rehydrated
+    }
+}
+
+impl fmt::Debug for Offloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Offloader").field("threshold", &self.threshold).finish()
+    }
+}
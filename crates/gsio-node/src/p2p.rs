@@ -1,316 +1,3365 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use rust_socketio::{asynchronous::{Client as PeerClient, ClientBuilder}, Payload};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
 use socketioxide::extract::{Data, SocketRef};
-use tracing::info;
-use uuid::Uuid;
-use iroh::{protocol::Router, Endpoint};
-use iroh_blobs::{store::{Store, mem}, net_protocol::Blobs};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, info_span, warn};
+use iroh::endpoint::{Connection, RecvStream, SendStream};
+use iroh::{protocol::Router, Endpoint, NodeAddr};
+use iroh_blobs::{store::mem, net_protocol::Blobs};
 
-use crate::ledger::{LedgerEntry, SharedLedger};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use gsio_types::parse_public_key;
+use sha2::{Digest, Sha256};
 
-/// Types of messages that can be sent between nodes
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum MessageType {
-    /// Announce a new node joining the network
-    NodeAnnounce,
-    /// Request the list of known nodes
-    NodeListRequest,
-    /// Response with the list of known nodes
-    NodeListResponse,
-    /// Announce a new ledger entry
-    EntryAnnounce,
-    /// Request a specific ledger entry
-    EntryRequest,
-    /// Response with a requested ledger entry
-    EntryResponse,
-    /// Request all ledger entries
-    LedgerSyncRequest,
-    /// Response with all ledger entries
-    LedgerSyncResponse,
-}
-
-/// A message sent between nodes in the p2p network
+use crate::archive::Archive;
+use crate::audit::{AuditEvent, AuditEventType, AuditLog};
+use crate::auth::random_hex;
+use crate::bandwidth::{Bandwidth, Direction};
+use crate::codec::{Codec, Frame};
+use crate::contacts::{Contacts, Liveness, NodeContact};
+use crate::envelope::{EphemeralKey, SecureChannel};
+use crate::error::GsioNodeError;
+use crate::identity;
+use crate::ledger::{LedgerEntry, Reorg, SharedLedger, SyncPage, GENESIS_HASH};
+use crate::mempool::{FeeFloor, FeeRate, Mempool, MempoolConfig};
+use crate::offload::{BlobRef, Offloader};
+use crate::pex::{self, PeerAddress};
+use crate::quic;
+use crate::rendezvous::{self, RelayFrame, RelayPayload};
+use crate::validation::{ErrorCode, ValidationError};
+
+pub use gsio_types::{
+    EntryRejection, MessageType, P2PMessage, ProtocolVersions, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+/// Why a request to a peer got no usable reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The node isn't connected to the peer, directly or through the relay
+    NotConnected,
+    /// The peer didn't reply in time
+    TimedOut,
+    /// The peer doesn't have what was asked for
+    NotFound,
+    /// The peer's reply couldn't be read
+    InvalidReply(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::NotConnected => write!(f, "Not connected to the peer"),
+            RequestError::TimedOut => write!(f, "The peer didn't reply in time"),
+            RequestError::NotFound => write!(f, "The peer doesn't have it"),
+            RequestError::InvalidReply(e) => write!(f, "Unreadable reply: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Role a node plays in the network
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NodeMode {
+    /// Accepts writes from clients and proposes entries to peers
+    #[default]
+    Writer,
+    /// Read-only replica that syncs and serves the ledger but never proposes entries
+    Follower {
+        /// URL of a writable node that clients should send writes to
+        writable_node: Option<String>,
+    },
+}
+
+impl NodeMode {
+    /// Whether this node rejects writes from clients
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, NodeMode::Follower { .. })
+    }
+}
+
+impl FromStr for NodeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "writer" => Ok(NodeMode::Writer),
+            "follower" | "replica" => Ok(NodeMode::Follower { writable_node: None }),
+            other => Err(format!("Unknown node mode: {other}")),
+        }
+    }
+}
+
+/// Liveness of a connected peer
+#[derive(Debug, Clone, Copy)]
+pub struct PeerHealth {
+    /// When the peer connected
+    pub connected_at: Instant,
+    /// When we last received a message from the peer
+    pub last_seen: Instant,
+}
+
+/// Connection a peer's messages go over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// A `/p2p` Socket.IO connection, whichever side opened it
+    Socketio,
+    /// A QUIC connection between the nodes' iroh endpoints
+    Iroh,
+    /// A session through a gsio-relay
+    Relay,
+}
+
+/// A connected peer, as served by `GET /api/peers/{id}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct P2PMessage {
-    /// Type of message
-    pub message_type: MessageType,
-    /// Unique ID for this message
-    pub message_id: String,
-    /// ID of the node that sent this message
-    pub sender_id: String,
-    /// ID of the node that should receive this message (empty for broadcast)
-    pub recipient_id: String,
-    /// The actual message payload
-    pub payload: JsonValue,
-}
-
-impl P2PMessage {
-    /// Create a new p2p message
-    pub fn new(
-        message_type: MessageType,
-        sender_id: String,
-        recipient_id: String,
-        payload: JsonValue,
+pub struct PeerInfo {
+    pub node_id: String,
+    /// Connection messages to the peer go over
+    pub transport: Transport,
+    /// Round trip of the last request the peer answered, in milliseconds
+    pub rtt_ms: Option<u64>,
+    /// Bytes received from the peer, over every connection to it
+    pub bytes_in: u64,
+    /// Bytes sent to the peer, over every connection to it
+    pub bytes_out: u64,
+    /// Seconds since the peer was last heard from
+    pub last_seen_secs: Option<u64>,
+    /// Height of the peer's chain when it last sent a ledger sync page
+    pub sync_height: Option<usize>,
+}
+
+/// What has been counted of the traffic with a peer
+#[derive(Debug, Clone, Default)]
+struct PeerTraffic {
+    bytes_in: u64,
+    bytes_out: u64,
+    last_seen: Option<Instant>,
+    rtt: Option<Duration>,
+    sync_height: Option<usize>,
+    /// When each request to the peer not answered yet was sent, by message ID
+    requests: HashMap<String, Instant>,
+}
+
+/// Traffic counted per peer, shared by every connection to it
+#[derive(Clone, Default)]
+struct Traffic(Arc<Mutex<HashMap<String, PeerTraffic>>>);
+
+impl Traffic {
+    fn update(&self, node_id: &str, update: impl FnOnce(&mut PeerTraffic)) {
+        update(self.0.lock().unwrap().entry(node_id.to_string()).or_default());
+    }
+
+    fn get(&self, node_id: &str) -> Option<PeerTraffic> {
+        self.0.lock().unwrap().get(node_id).cloned()
+    }
+}
+
+/// Announcements handled recently, so an entry that comes back from several
+/// peers is only validated and passed on once
+#[derive(Clone, Default)]
+struct SeenAnnouncements(Arc<Mutex<(HashSet<String>, VecDeque<String>)>>);
+
+impl SeenAnnouncements {
+    /// Remember an announced entry, returning whether it is new. The same
+    /// entry with more signatures counts as new, so endorsements spread.
+    fn insert(&self, entry: &LedgerEntry) -> bool {
+        let mut signers: Vec<&str> = entry.signatures.keys().map(String::as_str).collect();
+        signers.sort_unstable();
+        let key = format!("{}:{}", entry.hash, signers.join(","));
+
+        let (seen, order) = &mut *self.0.lock().unwrap();
+        if !seen.insert(key.clone()) {
+            return false;
+        }
+        order.push_back(key);
+        if order.len() > SEEN_ANNOUNCEMENTS
+            && let Some(oldest) = order.pop_front()
+        {
+            seen.remove(&oldest);
+        }
+        true
+    }
+}
+
+/// Outcome of a request made under an idempotency key, `None` while the
+/// entry is still being added
+type KeyedResult = watch::Receiver<Option<Result<LedgerEntry, GsioNodeError>>>;
+
+/// Entries added recently under clients' idempotency keys, with the data
+/// each was requested with, so a retried request gets back the entry the
+/// first one added
+#[derive(Clone, Default)]
+struct IdempotencyKeys(Arc<Mutex<(HashMap<String, (JsonValue, KeyedResult)>, VecDeque<String>)>>);
+
+/// Where a request stands against the idempotency keys
+enum KeyClaim {
+    /// The key is new; the request adds the entry and reports through this
+    New(watch::Sender<Option<Result<LedgerEntry, GsioNodeError>>>),
+    /// An earlier request with the key is running or done
+    Seen(KeyedResult),
+}
+
+impl IdempotencyKeys {
+    /// Claim `key` for a request with `data`, or find the earlier request
+    /// that claimed it
+    fn claim(&self, key: &str, data: &JsonValue) -> Result<KeyClaim, GsioNodeError> {
+        let (requests, order) = &mut *self.0.lock().unwrap();
+        if let Some((claimed, result)) = requests.get(key) {
+            if claimed != data {
+                return Err(GsioNodeError::Conflict(format!("Idempotency key {key} was used for different data")));
+            }
+            return Ok(KeyClaim::Seen(result.clone()));
+        }
+        let (tx, rx) = watch::channel(None);
+        requests.insert(key.to_string(), (data.clone(), rx));
+        order.push_back(key.to_string());
+        if order.len() > IDEMPOTENCY_KEYS
+            && let Some(oldest) = order.pop_front()
+        {
+            requests.remove(&oldest);
+        }
+        Ok(KeyClaim::New(tx))
+    }
+
+    /// Release `key` after its request failed, so it can be retried
+    fn release(&self, key: &str) {
+        let (requests, order) = &mut *self.0.lock().unwrap();
+        requests.remove(key);
+        order.retain(|k| k != key);
+    }
+}
+
+/// Exponential backoff between reconnection attempts
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    /// Create a backoff that starts at `min` and doubles up to `max`
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, next: min }
+    }
+
+    /// Get the delay before the next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    /// Start again from the minimum delay after a successful connection
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+/// How long a connecting peer has to answer the handshake challenge
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a peer to answer a request, for callers without a deadline of their own
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Submissions the mempool holds before clients wait for room
+pub const MEMPOOL_CAPACITY: usize = 1024;
+
+/// Most submissions the sequencer adds to the ledger under one write lock
+pub const MAX_SEQUENCER_BATCH: usize = 64;
+
+/// Announcements remembered to drop ones that come back, oldest forgotten first
+pub const SEEN_ANNOUNCEMENTS: usize = 10_000;
+
+/// Idempotency keys remembered with the entries they added, oldest forgotten first
+pub const IDEMPOTENCY_KEYS: usize = 10_000;
+/// Longest idempotency key a client may send
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// How long a node may go unheard from and of before it counts as stale,
+/// unless set with [`P2PManager::with_stale_after`]
+pub const STALE_AFTER: Duration = Duration::from_secs(300);
+
+/// Entries a node asks for per ledger sync page, unless set with [`P2PManager::with_sync_page_size`]
+pub const SYNC_PAGE_SIZE: usize = 100;
+/// Most entries a node sends in one ledger sync page, however many the peer asks for
+pub const MAX_SYNC_PAGE_SIZE: usize = 500;
+/// Most segment hashes a node sends in one ledger sync skeleton
+pub const MAX_SYNC_SKELETON: usize = 1000;
+
+/// Handshake data fields of the connecting node a handshake transcript covers
+const TRANSCRIPT_AUTH_FIELDS: [&str; 10] = [
+    "public_key", "challenge", "ephemeral_key", "encryption", "consensus", "genesis", "chain_id", "protocol", "codecs",
+    "url",
+];
+/// `AuthChallenge` fields of the accepting node a handshake transcript covers
+const TRANSCRIPT_PROOF_FIELDS: [&str; 9] = [
+    "public_key", "challenge", "ephemeral_key", "encryption", "consensus", "genesis", "protocol", "protocol_version",
+    "codec",
+];
+
+/// Hash of what a peer handshake settled, hex-encoded, which both sides sign.
+///
+/// `auth` is the handshake data `initiator_id` connected with and `proof` the
+/// `AuthChallenge` payload `responder_id` answered with, so both challenges,
+/// both nodes' identity and ephemeral keys, what the connecting node offered
+/// and what the accepting node picked are covered, and neither side can be
+/// talked into a weaker connection than the other agreed to.
+pub fn handshake_transcript(initiator_id: &str, auth: &JsonValue, responder_id: &str, proof: &JsonValue) -> String {
+    let mut transcript = Sha256::new();
+    transcript.update(format!("gsio-p2p-transcript:{initiator_id}:{responder_id}"));
+    for (fields, data) in [(&TRANSCRIPT_AUTH_FIELDS[..], auth), (&TRANSCRIPT_PROOF_FIELDS[..], proof)] {
+        for field in fields {
+            let value = data.get(field).map(JsonValue::to_string).unwrap_or_default();
+            transcript.update(format!("\n{field}={value}"));
+        }
+    }
+    hex::encode(transcript.finalize())
+}
+
+/// Bytes a node signs to prove it holds its key during the peer handshake.
+///
+/// Both node IDs are covered, so a signature can't be replayed to a
+/// different node or reflected back to the one that issued the challenge.
+fn handshake_payload(transcript: &str, signer_id: &str, verifier_id: &str) -> Vec<u8> {
+    format!("gsio-p2p-handshake:{transcript}:{signer_id}:{verifier_id}").into_bytes()
+}
+
+/// Sign a [handshake transcript](handshake_transcript) with `verifier_id` as
+/// `signer_id`, returning the hex-encoded signature
+pub fn sign_challenge(key: &SigningKey, transcript: &str, signer_id: &str, verifier_id: &str) -> String {
+    hex::encode(key.sign(&handshake_payload(transcript, signer_id, verifier_id)).to_bytes())
+}
+
+/// Check a handshake signature made with the hex-encoded `public_key`
+fn verify_challenge(
+    public_key: &str,
+    signature: &str,
+    transcript: &str,
+    signer_id: &str,
+    verifier_id: &str,
+) -> Result<(), String> {
+    verify_signature(public_key, signature, &handshake_payload(transcript, signer_id, verifier_id))
+        .map_err(|e| e.unwrap_or_else(|| format!("Signature doesn't match the key claimed by {signer_id}")))
+}
+
+/// Bytes a node signs to vouch for the key and URL it announces itself with
+fn announce_payload(node_id: &str, public_key: &str, url: Option<&str>) -> Vec<u8> {
+    format!("gsio-p2p-announce:{node_id}:{public_key}:{}", url.unwrap_or_default()).into_bytes()
+}
+
+/// Sign the `NodeAnnounce` of `node_id`, returning the hex-encoded signature
+pub fn sign_announcement(key: &SigningKey, node_id: &str, url: Option<&str>) -> String {
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    hex::encode(key.sign(&announce_payload(node_id, &public_key, url)).to_bytes())
+}
+
+/// Check that a node's announce is signed by the key it announces
+fn verify_announcement(public_key: &str, signature: &str, node_id: &str, url: Option<&str>) -> Result<(), String> {
+    verify_signature(public_key, signature, &announce_payload(node_id, public_key, url))
+        .map_err(|e| e.unwrap_or_else(|| format!("Announce isn't signed by the key {node_id} claims")))
+}
+
+/// Check a hex-encoded signature over `payload` made with the hex-encoded
+/// `public_key`; a signature that is well formed but doesn't match is `Err(None)`
+fn verify_signature(public_key: &str, signature: &str, payload: &[u8]) -> Result<(), Option<String>> {
+    let key = parse_public_key(public_key).map_err(|_| "Invalid public key".to_string())?;
+    let signature = hex::decode(signature)
+        .ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+        .ok_or("Invalid signature encoding".to_string())?;
+    key.verify(payload, &signature).map_err(|_| None)
+}
+
+/// A connecting peer that hasn't answered the handshake challenge yet
+#[derive(Clone)]
+struct PendingHandshake {
+    node_id: String,
+    public_key: String,
+    /// Hash of the handshake the peer has to sign
+    transcript: String,
+    /// Protocol version agreed with the peer
+    version: u32,
+    /// Channel messages will be sealed with once the handshake is done, if encrypted
+    channel: Option<SecureChannel>,
+    /// Encoding agreed with the peer
+    codec: Codec,
+    /// URL the peer says it can be dialed at
+    url: Option<String>,
+}
+
+/// The node a connection has proven to belong to, the protocol version and
+/// encoding agreed with it, and the channel to it if encrypted
+#[derive(Clone)]
+struct PeerSession {
+    node_id: String,
+    version: u32,
+    codec: Codec,
+    /// Network both sides are on
+    chain_id: Option<String>,
+    channel: Option<SecureChannel>,
+    traffic: Traffic,
+}
+
+impl PeerSession {
+    /// A message for the peer in the agreed version and marked with the
+    /// network's chain ID, sealed if the connection is encrypted.
+    ///
+    /// Requests are timed until the peer answers them.
+    fn seal(&self, message: &P2PMessage) -> P2PMessage {
+        if matches!(
+            message.message_type,
+            MessageType::NodeListRequest | MessageType::EntryRequest | MessageType::LedgerSyncRequest
+        ) {
+            self.traffic.update(&self.node_id, |traffic| {
+                // Requests that went unanswered don't pile up
+                traffic.requests.retain(|_, sent| sent.elapsed() < REQUEST_TIMEOUT);
+                traffic.requests.insert(message.message_id.clone(), Instant::now());
+            });
+        }
+        let message = P2PMessage { version: self.version, chain_id: self.chain_id.clone(), ..message.clone() };
+        match &self.channel {
+            Some(channel) => P2PMessage { version: self.version, chain_id: self.chain_id.clone(), ..channel.seal(&message) },
+            None => message,
+        }
+    }
+
+    /// Seal a message and encode it with the agreed codec
+    fn encode(&self, message: &P2PMessage) -> Result<Frame, String> {
+        self.codec.encode(&self.seal(message))
+    }
+
+    /// Check a message came from the peer in a version it agreed to and on
+    /// the same network, opening it if the connection is encrypted
+    fn open(&self, message: P2PMessage) -> Result<P2PMessage, String> {
+        if message.version > self.version {
+            return Err(format!(
+                "Message is written in protocol version {}, the connection speaks version {}",
+                message.version, self.version
+            ));
+        }
+        let message = match &self.channel {
+            Some(channel) => channel.open(&message)?,
+            None if message.sender_id != self.node_id => {
+                return Err(format!("Message sent under {}'s ID", message.sender_id));
+            }
+            None => message,
+        };
+        if message.chain_id != self.chain_id {
+            return Err(format!("Message is for chain {}", message.chain_id.as_deref().unwrap_or("none")));
+        }
+        if let Some(request_id) = &message.in_reply_to {
+            self.traffic.update(&self.node_id, |traffic| {
+                if let Some(sent) = traffic.requests.remove(request_id) {
+                    traffic.rtt = Some(sent.elapsed());
+                }
+            });
+        }
+        Ok(message)
+    }
+
+    /// Count bytes sent to the peer
+    fn sent(&self, bytes: usize) {
+        self.traffic.update(&self.node_id, |traffic| traffic.bytes_out += bytes as u64);
+    }
+
+    /// Count bytes received from the peer
+    fn received(&self, bytes: usize) {
+        self.traffic.update(&self.node_id, |traffic| {
+            traffic.bytes_in += bytes as u64;
+            traffic.last_seen = Some(Instant::now());
+        });
+    }
+}
+
+/// The reason a peer gave in a `HandshakeRejected` message
+fn rejection_reason(message: &P2PMessage) -> &str {
+    message.payload.get("reason").and_then(|r| r.as_str()).unwrap_or("no reason given")
+}
+
+/// Send a message to an inbound peer, sealed if its connection is encrypted
+fn emit_to_peer(socket: &SocketRef, message: &P2PMessage) -> bool {
+    let session = socket.extensions.get::<PeerSession>();
+    let encoded = match &session {
+        Some(session) => session.encode(message),
+        None => Codec::Json.encode(message),
+    };
+    let Some(frame) = frame_or_log(encoded, message) else {
+        return false;
+    };
+    let size = frame.size();
+    let sent = socket.emit("p2p_message", &frame).is_ok();
+    if sent && let Some(session) = session {
+        session.sent(size);
+    }
+    sent
+}
+
+/// Serialize a message payload, logging a value that can't be rather than
+/// taking the node down with it
+fn to_payload(value: impl Serialize) -> Option<JsonValue> {
+    serde_json::to_value(value).inspect_err(|e| error!("Failed to serialize message payload: {e}")).ok()
+}
+
+/// The frame a message was encoded to, logging a message that couldn't be
+fn frame_or_log(encoded: Result<Frame, String>, message: &P2PMessage) -> Option<Frame> {
+    encoded.inspect_err(|e| error!(message_id = message.message_id, "Failed to encode message: {e}")).ok()
+}
+
+/// Send a frame over a connection this node opened
+async fn emit_frame(client: &PeerClient, frame: Frame) -> Result<(), rust_socketio::Error> {
+    match frame {
+        Frame::Json(value) => client.emit("p2p_message", value).await,
+        Frame::Binary(bytes) => client.emit("p2p_message", Payload::Binary(bytes.into())).await,
+    }
+}
+
+/// A connection this node opened, and the session with the peer once the handshake is done
+#[derive(Clone)]
+struct OutboundPeer {
+    client: PeerClient,
+    session: Arc<Mutex<Option<PeerSession>>>,
+}
+
+impl OutboundPeer {
+    /// Send a message to the peer, sealed if the connection is encrypted
+    async fn send(&self, message: &P2PMessage) -> Result<(), String> {
+        let session = self.session.lock().unwrap().clone();
+        let encoded = match &session {
+            Some(session) => session.encode(message),
+            None => Codec::Json.encode(message),
+        };
+        let frame = encoded.map_err(|e| format!("Failed to encode message {}: {e}", message.message_id))?;
+        let size = frame.size();
+        emit_frame(&self.client, frame).await.map_err(|e| e.to_string())?;
+        if let Some(session) = session {
+            session.sent(size);
+        }
+        Ok(())
+    }
+}
+
+/// A request sent to a peer, waiting for its reply
+struct PendingRequest {
+    /// Node the reply has to come from
+    peer_id: String,
+    reply: oneshot::Sender<P2PMessage>,
+}
+
+/// Forgets a pending request once its caller stops waiting, whether or not a reply came
+struct PendingRequestGuard<'a> {
+    pending: &'a Mutex<HashMap<String, PendingRequest>>,
+    request_id: String,
+}
+
+impl Drop for PendingRequestGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.request_id);
+    }
+}
+
+/// The handshake data a node opened a connection with, and the ephemeral key behind the one it sent
+#[derive(Clone)]
+struct OpeningHandshake {
+    auth: JsonValue,
+    ephemeral: EphemeralKey,
+}
+
+/// An open outbound connection, when the peer was last heard from, and its announced node ID
+type OutboundConnection = (OutboundPeer, Arc<Mutex<Instant>>, Arc<Mutex<Option<String>>>);
+
+/// Where a session with a node reached through the relay stands
+#[derive(Clone)]
+enum RelaySession {
+    /// This node opened the session and waits for the peer's proof and challenge
+    Dialing { opening: OpeningHandshake, started: Instant },
+    /// The peer opened the session and has to answer our challenge
+    Accepting { pending: PendingHandshake, started: Instant },
+    /// The peer proved its identity
+    Connected(PeerSession),
+}
+
+/// This node's connection to a gsio-relay and the sessions it carries
+#[derive(Default)]
+struct RelayLink {
+    /// Sends frames to the relay while connected
+    sender: Option<mpsc::UnboundedSender<RelayFrame>>,
+    /// Other nodes registered with the relay
+    nodes: HashSet<String>,
+    /// Sessions with nodes reached through the relay, by node ID
+    sessions: HashMap<String, RelaySession>,
+}
+
+impl RelayLink {
+    /// Have the relay deliver `payload` to node `to`, returning false if the relay isn't connected
+    fn forward(&self, to: &str, payload: RelayPayload) -> bool {
+        let frame = RelayFrame::Forward { to: to.to_string(), payload: payload.to_msgpack() };
+        self.sender.as_ref().is_some_and(|sender| sender.send(frame).is_ok())
+    }
+
+    /// Send a message to a node with an open session, sealed if the session is encrypted
+    fn send(&self, to: &str, message: &P2PMessage) -> bool {
+        match self.sessions.get(to) {
+            Some(RelaySession::Connected(session)) => {
+                let Some(message) = to_payload(session.seal(message)) else {
+                    return false;
+                };
+                let size = message.to_string().len();
+                let sent = self.forward(to, RelayPayload::Message { message });
+                if sent {
+                    session.sent(size);
+                }
+                sent
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A QUIC connection to a peer over the iroh endpoint, and the session with it
+#[derive(Clone)]
+struct QuicPeer {
+    /// Queues messages for the task writing to the connection's stream
+    sender: mpsc::UnboundedSender<P2PMessage>,
+    session: PeerSession,
+    connection: Connection,
+    /// Node that dialed the connection
+    dialer: String,
+}
+
+impl QuicPeer {
+    /// Send a message to the peer in the agreed version
+    fn send(&self, message: &P2PMessage) -> bool {
+        self.sender.send(self.session.seal(message)).is_ok()
+    }
+}
+
+/// Fetch the peer URLs a node lists under `/api/nodes`, those of its
+/// connections followed by those in its contacts
+async fn fetch_peer_urls(node_url: &str) -> Result<Vec<String>, String> {
+    let url = format!("{}/api/nodes", node_url.trim_end_matches('/'));
+    let response: JsonValue = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to fetch peers from {node_url}: {e}"))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid peer list from {node_url}: {e}"))?;
+
+    let peers = response.get("peers").and_then(|p| p.as_array()).into_iter().flatten().filter_map(|p| p.as_str());
+    let contacts = response
+        .get("contacts")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.get("url").and_then(|u| u.as_str()));
+    let mut urls: Vec<String> = Vec::new();
+    for url in peers.chain(contacts) {
+        if !urls.iter().any(|known| known == url) {
+            urls.push(url.to_string());
+        }
+    }
+    Ok(urls)
+}
+
+/// Manages p2p communication between nodes
+pub struct P2PManager {
+    /// The ID of this node
+    node_id: String,
+    /// The shared ledger
+    pub ledger: SharedLedger,
+    /// Channel the ledger belongs to, or `None` for the node's main ledger
+    channel: Option<String>,
+    /// Connected sockets by node ID
+    connected_nodes: Arc<Mutex<HashMap<String, SocketRef>>>,
+    /// Liveness of inbound peers by node ID
+    peer_health: Arc<Mutex<HashMap<String, PeerHealth>>>,
+    /// Connections this node opened to other nodes, by URL
+    outbound_peers: Arc<Mutex<HashMap<String, OutboundPeer>>>,
+    /// Tasks keeping outbound connections open, by URL
+    dialed_peers: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    /// Iroh endpoint for peer discovery and communication
+    endpoint: Option<Arc<Endpoint>>,
+    /// Iroh blobs for data storage and synchronization
+    blobs: Option<Arc<Blobs<mem::Store>>>,
+    /// Iroh router for handling connections
+    router: Option<Arc<Router>>,
+    /// Whether this node accepts writes or only replicates
+    mode: NodeMode,
+    /// Whether this node insists on encrypting messages to its peers
+    encryption: bool,
+    /// Whether peers have to be known by their public key
+    key_ids: bool,
+    /// Protocol versions this node speaks with its peers
+    protocol: ProtocolVersions,
+    /// Encoding this node prefers for messages on direct connections
+    codec: Codec,
+    /// Log of peer connections and entries accepted or rejected
+    audit: Arc<AuditLog>,
+    /// Entries to ask for per ledger sync page
+    sync_page_size: usize,
+    /// Height of the next page to ask each peer for, while a sync with it is unfinished
+    sync_progress: Arc<Mutex<HashMap<String, usize>>>,
+    /// Whether newly connected peers are left for a coordinated sync instead of synced with one by one
+    initial_sync: Arc<Mutex<bool>>,
+    /// Requests waiting for a reply, by the request's message ID
+    pending_requests: Arc<Mutex<HashMap<String, PendingRequest>>>,
+    /// Node IDs refused as peers
+    banned_peers: Arc<Mutex<HashSet<String>>>,
+    /// When this node last finished syncing its ledger with a peer
+    last_sync: Arc<Mutex<Option<DateTime<Utc>>>>,
+    /// Stores large entry data as blobs and rehydrates it
+    offloader: Option<Arc<Offloader>>,
+    /// Entries pruned from the chain, fetched on demand
+    archive: Option<Arc<Archive>>,
+    /// Limits on how fast sync pages are sent to and received from peers
+    bandwidth: Option<Arc<Bandwidth>>,
+    /// Connection to a gsio-relay, for peers this node can't reach directly
+    relay: Arc<Mutex<RelayLink>>,
+    /// Task keeping the relay connection open
+    relay_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    /// QUIC connections to peers over the iroh endpoint, by node ID
+    quic_peers: Arc<Mutex<HashMap<String, QuicPeer>>>,
+    /// Bytes, round trips and sync heights counted per peer
+    traffic: Traffic,
+    /// Entry announcements handled recently
+    seen_announcements: SeenAnnouncements,
+    /// Client idempotency keys and the entries added under them
+    idempotency_keys: IdempotencyKeys,
+    /// Client submissions waiting for the sequencer, once it's started
+    mempool: Arc<Mempool>,
+    /// Takes every outgoing message in place of the node's connections
+    outbox: Option<mpsc::UnboundedSender<P2PMessage>>,
+    /// URL other nodes reach this node at, if it can be dialed
+    public_url: Option<String>,
+    /// Addresses sent in each node list reply, if this node takes part in peer exchange
+    peer_exchange: Option<usize>,
+    /// Where known nodes can be reached
+    contacts: Contacts,
+    /// How long a node may go unheard from and of before it counts as stale
+    stale_after: Duration,
+}
+
+/// The iroh endpoint, blob store and router a node serves blobs with
+type IrohComponents = (Arc<Endpoint>, Arc<Blobs<mem::Store>>, Arc<Router>);
+
+impl P2PManager {
+    /// Create a new p2p manager
+    pub fn new(node_id: String, ledger: SharedLedger) -> Self {
+        Self::with_components(node_id, ledger, None)
+    }
+
+    /// Create a new p2p manager with iroh components
+    pub fn new_with_iroh(
+        node_id: String,
+        ledger: SharedLedger,
+        endpoint: Arc<Endpoint>,
+        blobs: Arc<Blobs<mem::Store>>,
+        router: Arc<Router>,
     ) -> Self {
+        Self::with_components(node_id, ledger, Some((endpoint, blobs, router)))
+    }
+
+    /// Create a p2p manager with the iroh endpoint, blob store and router, if the node has them
+    fn with_components(node_id: String, ledger: SharedLedger, iroh: Option<IrohComponents>) -> Self {
+        let (endpoint, blobs, router) = match iroh {
+            Some((endpoint, blobs, router)) => (Some(endpoint), Some(blobs), Some(router)),
+            None => (None, None, None),
+        };
         Self {
-            message_type,
-            message_id: Uuid::new_v4().to_string(),
-            sender_id,
-            recipient_id,
-            payload,
+            node_id,
+            ledger,
+            channel: None,
+            connected_nodes: Arc::new(Mutex::new(HashMap::new())),
+            peer_health: Arc::new(Mutex::new(HashMap::new())),
+            outbound_peers: Arc::new(Mutex::new(HashMap::new())),
+            dialed_peers: Arc::new(Mutex::new(HashMap::new())),
+            endpoint,
+            blobs,
+            router,
+            mode: NodeMode::Writer,
+            encryption: false,
+            key_ids: false,
+            protocol: ProtocolVersions::default(),
+            codec: Codec::default(),
+            audit: Arc::new(AuditLog::in_memory()),
+            sync_page_size: SYNC_PAGE_SIZE,
+            sync_progress: Arc::new(Mutex::new(HashMap::new())),
+            initial_sync: Arc::new(Mutex::new(false)),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            banned_peers: Arc::new(Mutex::new(HashSet::new())),
+            last_sync: Arc::new(Mutex::new(None)),
+            offloader: None,
+            archive: None,
+            bandwidth: None,
+            relay: Arc::new(Mutex::new(RelayLink::default())),
+            relay_task: Arc::new(Mutex::new(None)),
+            quic_peers: Arc::new(Mutex::new(HashMap::new())),
+            traffic: Traffic::default(),
+            seen_announcements: SeenAnnouncements::default(),
+            idempotency_keys: IdempotencyKeys::default(),
+            mempool: Arc::new(Mempool::new(MempoolConfig::default(), MEMPOOL_CAPACITY)),
+            outbox: None,
+            public_url: None,
+            peer_exchange: None,
+            contacts: Contacts::new(),
+            stale_after: STALE_AFTER,
+        }
+    }
+
+    /// Set whether this node accepts writes or only replicates
+    pub fn with_mode(mut self, mode: NodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set whether messages to peers have to be encrypted.
+    ///
+    /// Connections are encrypted when either side asks for it; a node that
+    /// requires encryption refuses peers that won't seal their messages.
+    pub fn with_encryption(mut self, encryption: bool) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Only accept peers whose node ID is their public key, as it is for
+    /// nodes whose ID is their iroh `NodeId` (see [`crate::identity`])
+    pub fn with_key_ids(mut self, key_ids: bool) -> Self {
+        self.key_ids = key_ids;
+        self
+    }
+
+    /// Carry messages over QUIC on `endpoint` to peers that can be reached
+    /// that way, before falling back to Socket.IO or the relay. Only nodes
+    /// known by their keys are reached over QUIC, see [`Self::with_key_ids`].
+    pub fn with_endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = Some(Arc::new(endpoint));
+        self
+    }
+
+    /// Set the protocol versions this node speaks, by default every version
+    /// from [`MIN_PROTOCOL_VERSION`] to [`PROTOCOL_VERSION`]. Peers speak the
+    /// newest version both sides know, and peers with none in common are refused.
+    pub fn with_protocol_versions(mut self, protocol: ProtocolVersions) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the encoding this node offers for messages on direct connections,
+    /// JSON by default. Peers that don't read it are sent JSON.
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Record operational events in `audit` instead of an in-memory log
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Set how many entries to ask a peer for per ledger sync page
+    pub fn with_sync_page_size(mut self, size: usize) -> Self {
+        self.sync_page_size = size.clamp(1, MAX_SYNC_PAGE_SIZE);
+        self
+    }
+
+    /// Hold off the sync newly connected peers start with until
+    /// [`finish_initial_sync`](Self::finish_initial_sync), so a
+    /// [`SyncCoordinator`](crate::sync::SyncCoordinator) can sync from several at once first
+    pub fn with_initial_sync(self) -> Self {
+        *self.initial_sync.lock().unwrap() = true;
+        self
+    }
+
+    /// Set where large entry data is offloaded to and rehydrated from
+    pub fn with_offloader(mut self, offloader: Arc<Offloader>) -> Self {
+        self.offloader = Some(offloader);
+        self
+    }
+
+    /// Set where entries are archived before retention prunes them
+    pub fn with_archive(mut self, archive: Arc<Archive>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Keep sync pages sent to and received from peers within the limits of `bandwidth`
+    pub fn with_bandwidth(mut self, bandwidth: Arc<Bandwidth>) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Set the minimum fee client transactions have to pay to be queued
+    pub fn with_mempool_config(mut self, config: MempoolConfig) -> Self {
+        self.mempool = Arc::new(Mempool::new(config, MEMPOOL_CAPACITY));
+        self
+    }
+
+    /// Set the URL other nodes can dial this node at, which peer exchange passes on
+    pub fn with_public_url(mut self, url: impl Into<String>) -> Self {
+        self.public_url = Some(url.into());
+        self
+    }
+
+    /// Take part in peer exchange, answering node list requests with up to
+    /// `sample_size` addresses and adding the addresses peers answer with to the contacts
+    pub fn with_peer_exchange(mut self, sample_size: usize) -> Self {
+        self.peer_exchange = Some(sample_size);
+        self
+    }
+
+    /// Count nodes neither heard from nor of within `stale_after` as stale
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Hand every message the node sends to `outbox` instead of its
+    /// connections, so a simulated network can deliver them in any order,
+    /// or not at all, with [`P2PManager::handle_message`]
+    pub fn with_outbox(mut self, outbox: mpsc::UnboundedSender<P2PMessage>) -> Self {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Make this the manager of `channel`'s ledger, which peers sync under its own namespace
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// Channel this manager's ledger belongs to, or `None` for the main ledger
+    pub fn channel(&self) -> Option<&str> {
+        self.channel.as_deref()
+    }
+
+    /// Socket.IO namespace peers connect to for this ledger: `/p2p`, or
+    /// `/p2p/<channel>` for a channel
+    pub fn namespace(&self) -> String {
+        match &self.channel {
+            Some(channel) => format!("/p2p/{channel}"),
+            None => "/p2p".to_string(),
         }
     }
-}
 
-/// Manages p2p communication between nodes
-pub struct P2PManager {
-    /// The ID of this node
-    node_id: String,
-    /// The shared ledger
-    pub ledger: SharedLedger,
-    /// Connected sockets by node ID
-    connected_nodes: Arc<Mutex<HashMap<String, SocketRef>>>,
-    /// Iroh endpoint for peer discovery and communication
-    endpoint: Option<Arc<Endpoint>>,
-    /// Iroh blobs for data storage and synchronization
-    blobs: Option<Arc<Blobs<mem::Store>>>,
-    /// Iroh router for handling connections
-    router: Option<Arc<Router>>,
-}
+    /// Get the node ID
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Entries this node asks a peer for per ledger sync page
+    pub fn sync_page_size(&self) -> usize {
+        self.sync_page_size
+    }
+
+    /// The log operational events are recorded in
+    pub fn audit(&self) -> Arc<AuditLog> {
+        self.audit.clone()
+    }
+
+    /// The limits sync pages are kept within, if any
+    pub fn bandwidth(&self) -> Option<Arc<Bandwidth>> {
+        self.bandwidth.clone()
+    }
+
+    /// Get the mode this node runs in
+    pub fn mode(&self) -> &NodeMode {
+        &self.mode
+    }
+
+    /// Add an entry submitted by a client and announce it to peers.
+    ///
+    /// Followers never propose entries, so writes are rejected with the
+    /// writable node (if known) that the client should retry against. Nodes the
+    /// consensus strategy doesn't allow to propose, like non-validators under
+    /// proof of authority, reject writes too. Data that fails the
+    /// ledger's validation policy is rejected as well.
+    pub fn add_local_entry(&self, data: JsonValue) -> Result<LedgerEntry, GsioNodeError> {
+        self.check_writable()?;
+        self.local_entry_added(self.ledger.add_entry(data))
+    }
+
+    /// Audit the outcome of adding a client's entry, announcing it if it was added
+    fn local_entry_added(&self, result: Result<LedgerEntry, ValidationError>) -> Result<LedgerEntry, GsioNodeError> {
+        let entry = result.inspect_err(|error| self.audit_rejection(None, None, error))?;
+        self.audit.record(AuditEvent::new(AuditEventType::EntryAccepted).with_entry(&entry.id));
+        self.broadcast_entry(entry.clone());
+        Ok(entry)
+    }
+
+    /// Start the task that adds client submissions from the mempool to the ledger.
+    ///
+    /// Submissions are checked as they arrive, then queued; the sequencer
+    /// takes up to [`MAX_SEQUENCER_BATCH`] at a time, highest fee per byte
+    /// first, and adds them under one write lock before announcing them, so
+    /// concurrent clients don't take turns on the lock. Until it's started,
+    /// submissions are added directly.
+    pub fn start_sequencer(&self) -> JoinHandle<()> {
+        self.mempool.open();
+        let p2p = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let batch = p2p.mempool.next_batch(MAX_SEQUENCER_BATCH).await;
+                let (data, added): (Vec<_>, Vec<_>) = batch.into_iter().map(|s| (s.data, s.added)).unzip();
+                debug!(count = data.len(), "Sequencing entries from the mempool");
+                for (result, added) in p2p.ledger.add_entries(data).into_iter().zip(added) {
+                    added.send(p2p.local_entry_added(result)).ok();
+                }
+            }
+        })
+    }
+
+    /// Number of submissions waiting in the mempool
+    pub fn mempool_len(&self) -> usize {
+        self.mempool.len()
+    }
+
+    /// The minimum fee and the fee per byte that gets a transaction
+    /// sequenced ahead of the ones waiting
+    pub fn fee_floor(&self) -> FeeFloor {
+        self.mempool.fee_floor()
+    }
+
+    /// Queue a client's entry data in the mempool and wait for the sequencer
+    /// to add it, or add it directly if the sequencer isn't running. `rate`
+    /// is what the data as submitted pays, which offloaded data no longer shows.
+    async fn submit_local_entry(&self, data: JsonValue, rate: FeeRate) -> Result<LedgerEntry, GsioNodeError> {
+        if !self.mempool.is_open() {
+            return self.add_local_entry(data);
+        }
+        self.check_writable()?;
+        // Data that will be refused doesn't take a place in the queue
+        self.ledger.validate_own(&data).inspect_err(|error| self.audit_rejection(None, None, error))?;
+
+        // The sequencer answers every submission it takes, unless the node stops first
+        let result = self.mempool.push(data, rate).await;
+        result.await.unwrap_or_else(|_| Err(GsioNodeError::Unavailable("The node is shutting down".to_string())))
+    }
+
+    /// Record that an entry, from a client if `peer_id` is unset, failed validation
+    fn audit_rejection(&self, peer_id: Option<&str>, entry_id: Option<&str>, error: &ValidationError) {
+        let mut event = AuditEvent::new(AuditEventType::EntryRejected)
+            .with_details(json!({ "code": error.code, "error": error.message }));
+        event.peer_id = peer_id.map(str::to_string);
+        event.entry_id = entry_id.map(str::to_string);
+        self.audit.record(event);
+    }
+
+    /// Add an entry submitted by a client, offloading its data to a blob if it's large.
+    ///
+    /// The data is checked against the validation policy before it's
+    /// offloaded, and the entry is returned with its data rehydrated.
+    /// Clients can't submit blob references themselves, as those would skip
+    /// validation. If the blob can't be stored the data is kept inline.
+    pub async fn add_entry_data(&self, data: JsonValue) -> Result<LedgerEntry, GsioNodeError> {
+        if BlobRef::from_data(&data).is_some() {
+            let error = ValidationError::new(ErrorCode::Rejected, "Entry data can't be a blob reference");
+            self.audit_rejection(None, None, &error);
+            return Err(GsioNodeError::Invalid(error));
+        }
+        self.mempool.check_fee(&data).inspect_err(|error| self.audit_rejection(None, None, error))?;
+        let rate = FeeRate::of(&data);
+        let Some(offloader) = self.offloader.as_ref().filter(|o| o.should_offload(&data)) else {
+            return self.submit_local_entry(data, rate).await;
+        };
+        // Refuse the write before storing a blob nothing will reference
+        self.check_writable()?;
+        self.ledger.validate(&data).inspect_err(|error| self.audit_rejection(None, None, error))?;
+
+        let reference = match offloader.offload(&data).await {
+            Ok(reference) => reference,
+            Err(e) => {
+                warn!("Failed to offload entry data, keeping it inline: {e}");
+                return self.submit_local_entry(data, rate).await;
+            }
+        };
+        let mut entry = self.submit_local_entry(reference.to_data(), rate).await?;
+        entry.blob = Some(std::mem::replace(&mut entry.data, data));
+        Ok(entry)
+    }
+
+    /// Add an entry submitted by a client under an idempotency key. A
+    /// request retried with the same key and data gets back the entry the
+    /// first one added, waiting for it if that request is still running,
+    /// instead of adding it again. Reusing a key for other data is a conflict.
+    ///
+    /// The entry is added on its own task, so it's recorded under the key
+    /// even if the client gives up on the request.
+    pub async fn add_entry_data_once(&self, key: &str, data: JsonValue) -> Result<LedgerEntry, GsioNodeError> {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(GsioNodeError::BadRequest(format!(
+                "idempotency key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} bytes"
+            )));
+        }
+        let mut result = match self.idempotency_keys.claim(key, &data)? {
+            KeyClaim::Seen(result) => {
+                debug!("Request with idempotency key {key} was already made");
+                result
+            }
+            KeyClaim::New(tx) => {
+                let rx = tx.subscribe();
+                let p2p = self.clone();
+                let key = key.to_string();
+                tokio::spawn(async move {
+                    let result = p2p.add_entry_data(data).await;
+                    if result.is_err() {
+                        p2p.idempotency_keys.release(&key);
+                    }
+                    tx.send_replace(Some(result));
+                });
+                rx
+            }
+        };
+        let outcome = result
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| GsioNodeError::Internal("Adding the entry failed".to_string()))?;
+        outcome.clone().expect("waited for the outcome")
+    }
+
+    /// Put offloaded data back into `entries`; entries are returned as they
+    /// are if this node doesn't offload
+    pub async fn rehydrate(&self, entries: Vec<LedgerEntry>) -> Vec<LedgerEntry> {
+        match &self.offloader {
+            Some(offloader) => offloader.rehydrate_all(entries).await,
+            None => entries,
+        }
+    }
+
+    /// Put offloaded data back into `entry`
+    pub async fn rehydrate_entry(&self, entry: LedgerEntry) -> LedgerEntry {
+        match &self.offloader {
+            Some(offloader) => offloader.rehydrate(entry).await,
+            None => entry,
+        }
+    }
+
+    /// Where entries are archived before they're pruned, if anywhere
+    pub fn archive(&self) -> Option<&Arc<Archive>> {
+        self.archive.as_ref()
+    }
+
+    /// Get an entry by its ID from the chain, or from the archive if it's been pruned
+    pub async fn find_entry(&self, id: &str) -> Option<LedgerEntry> {
+        if let Some(entry) = self.ledger.get_entry_by_id(id) {
+            return Some(entry);
+        }
+        match self.archive.as_ref()?.get_entry(id).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!(entry_id = id, "Failed to read archived entry: {e}");
+                None
+            }
+        }
+    }
+
+    /// Where large entry data is offloaded to, if anywhere
+    pub fn offloader(&self) -> Option<&Arc<Offloader>> {
+        self.offloader.as_ref()
+    }
+
+    /// The bytes of an offloaded entry's data, if this node has or can find the blob
+    pub async fn blob(&self, hash: &str) -> Result<Option<Vec<u8>>, String> {
+        match &self.offloader {
+            Some(offloader) => offloader.fetch(hash, None).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Whether this node accepts entries from clients
+    fn check_writable(&self) -> Result<(), GsioNodeError> {
+        if let NodeMode::Follower { writable_node } = &self.mode {
+            return Err(GsioNodeError::ReadOnly { writable_node: writable_node.clone() });
+        }
+        if !self.ledger.can_propose() {
+            return Err(GsioNodeError::NotValidator);
+        }
+        if !self.ledger.can_write() {
+            return Err(GsioNodeError::NotWriter);
+        }
+        Ok(())
+    }
+
+    /// Get a clone of the connected nodes Arc
+    pub fn clone_connected_nodes(&self) -> Arc<Mutex<HashMap<String, SocketRef>>> {
+        self.connected_nodes.clone()
+    }
+
+    /// Handle a new connection from another node.
+    ///
+    /// The node is only added to the connected nodes once it has signed our
+    /// challenge with the key it claims. Banned nodes, and nodes that don't
+    /// start the handshake, claim a different key than the one already known
+    /// for their ID, run a different consensus strategy, start from a
+    /// different genesis, are on another network, or don't answer within
+    /// [`HANDSHAKE_TIMEOUT`], are disconnected.
+    pub fn handle_connection(&self, socket: SocketRef, data: JsonValue) {
+        // Extract the node ID from the connection data
+        let node_id = match data.get("node_id") {
+            Some(id) => id.as_str().unwrap_or("unknown").to_string(),
+            None => "unknown".to_string(),
+        };
+
+        // A node can end up dialing its own URL through peer discovery
+        if node_id == self.node_id {
+            info!(ns = socket.ns(), ?socket.id, "Refusing connection from this node");
+            self.announce_self(&socket, node_id);
+            socket.disconnect().ok();
+            return;
+        }
+
+        if self.is_banned(&node_id) {
+            warn!(ns = socket.ns(), ?socket.id, node_id = node_id, "Refusing banned peer");
+            socket.disconnect().ok();
+            return;
+        }
+
+        // The peer has to prove it holds the key it claims before it is trusted
+        let (pending, proof) = match self.challenge_peer(&node_id, &data) {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                warn!(ns = socket.ns(), ?socket.id, node_id = node_id, "Refusing peer: {}", e);
+                if let Some(rejection) = to_payload(self.handshake_rejection(&node_id, &e)) {
+                    socket.emit("p2p_message", &rejection).ok();
+                }
+                socket.disconnect().ok();
+                return;
+            }
+        };
+
+        info!(ns = socket.ns(), ?socket.id, node_id = node_id, "P2P node connected, verifying its identity");
+        socket.extensions.insert(pending);
+        if let Some(proof) = to_payload(proof) {
+            socket.emit("p2p_message", &proof).ok();
+        }
+
+        // Drop peers that never answer
+        let pending = socket.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(HANDSHAKE_TIMEOUT).await;
+            if pending.extensions.get::<PendingHandshake>().is_some() {
+                warn!(?pending.id, "Peer didn't answer the handshake, disconnecting");
+                pending.disconnect().ok();
+            }
+        });
+
+        self.setup_socket_handlers(socket);
+    }
+
+    /// Check the handshake data a connecting peer sent, returning the
+    /// handshake to finish once it answers and our proof and challenge for it
+    fn challenge_peer(&self, node_id: &str, data: &JsonValue) -> Result<(PendingHandshake, P2PMessage), String> {
+        let (Some(public_key), Some(_)) = (
+            data.get("public_key").and_then(|k| k.as_str()),
+            data.get("challenge").and_then(|c| c.as_str()),
+        ) else {
+            return Err("Peer didn't start a handshake".to_string());
+        };
+        self.check_peer_key(node_id, public_key)?;
+        self.check_peer_consensus(data.get("consensus"))?;
+        self.check_peer_genesis(data.get("genesis"))?;
+        self.check_peer_chain(data.get("chain_id").and_then(|c| c.as_str()))?;
+        let version = self.negotiate_version(data.get("protocol"))?;
+        let codec = self.codec.negotiate(data.get("codecs"));
+
+        let encrypted = self.encryption || data.get("encryption").and_then(|e| e.as_bool()).unwrap_or(false);
+        let peer_ephemeral = data.get("ephemeral_key").and_then(|k| k.as_str());
+        if encrypted && peer_ephemeral.is_none() {
+            return Err("Peer didn't send an ephemeral key to encrypt with".to_string());
+        }
+
+        // Prove our own key over everything settled so far, and challenge the peer to prove its one
+        let ephemeral = EphemeralKey::generate();
+        let mut payload = json!({
+            "public_key": self.ledger.public_key(),
+            "challenge": random_hex(),
+            "ephemeral_key": ephemeral.public_key(),
+            "encryption": encrypted,
+            "consensus": self.ledger.consensus_name(),
+            "genesis": self.ledger.genesis_hash(),
+            "protocol": self.protocol,
+            "protocol_version": version,
+            "codec": codec,
+        });
+        let transcript = handshake_transcript(node_id, data, &self.node_id, &payload);
+        payload["signature"] = self.ledger.sign_message(&handshake_payload(&transcript, &self.node_id, node_id)).into();
+        let channel = match peer_ephemeral {
+            Some(peer_ephemeral) if encrypted => {
+                Some(SecureChannel::new(&ephemeral.agree(peer_ephemeral)?, &transcript, &self.node_id, node_id))
+            }
+            _ => None,
+        };
+        let proof = P2PMessage::new(MessageType::AuthChallenge, self.node_id.clone(), node_id.to_string(), payload)
+            .with_chain_id(self.ledger.chain_id());
+        let pending = PendingHandshake {
+            node_id: node_id.to_string(),
+            public_key: public_key.to_string(),
+            transcript,
+            version,
+            channel,
+            codec,
+            url: data.get("url").and_then(|u| u.as_str()).map(str::to_string),
+        };
+        Ok((pending, proof))
+    }
+
+    /// The protocol version to speak with a peer that sent `protocol` in its
+    /// handshake data. Peers that send none predate negotiation and speak version 1.
+    fn negotiate_version(&self, protocol: Option<&JsonValue>) -> Result<u32, String> {
+        let theirs = match protocol {
+            Some(protocol) => serde_json::from_value::<ProtocolVersions>(protocol.clone())
+                .map_err(|_| "Peer sent unreadable protocol versions".to_string())?,
+            None => ProtocolVersions::LEGACY,
+        };
+        self.protocol.negotiate(&theirs).ok_or_else(|| {
+            format!(
+                "Peer speaks protocol versions {}-{}, this node speaks {}-{}",
+                theirs.min, theirs.max, self.protocol.min, self.protocol.max
+            )
+        })
+    }
+
+    /// Tell a peer why its handshake was refused, and which protocol versions this node speaks
+    fn handshake_rejection(&self, recipient_id: &str, reason: &str) -> P2PMessage {
+        P2PMessage::new(
+            MessageType::HandshakeRejected,
+            self.node_id.clone(),
+            recipient_id.to_string(),
+            json!({ "reason": reason, "protocol": self.protocol }),
+        )
+        .with_chain_id(self.ledger.chain_id())
+    }
+
+    /// Refuse a key for a node that is already known by a different one, or
+    /// that isn't the node's ID when IDs have to be keys
+    fn check_peer_key(&self, node_id: &str, public_key: &str) -> Result<(), String> {
+        if self.key_ids && !identity::is_key_of(node_id, public_key) {
+            return Err(format!("Node ID {node_id} isn't its public key"));
+        }
+        match self.ledger.get_node_key(node_id) {
+            Some(known) if !known.eq_ignore_ascii_case(public_key) => {
+                Err(format!("Node {node_id} is known by a different public key"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Finish the handshake with a connecting peer once it answers our challenge
+    fn complete_handshake(&self, socket: SocketRef, message: P2PMessage) {
+        let Some(pending) = socket.extensions.remove::<PendingHandshake>() else {
+            return;
+        };
+
+        let session = match self.verify_handshake(&pending, &message) {
+            Ok(session) => session,
+            Err(e) => {
+                warn!(peer_id = pending.node_id, "Peer failed the handshake, disconnecting: {}", e);
+                socket.disconnect().ok();
+                return;
+            }
+        };
+
+        socket.extensions.insert(session);
+        if let Some(url) = pending.url {
+            self.contacts.set_url(&pending.node_id, url);
+        }
+        self.register_peer(socket, pending.node_id, &pending.public_key);
+    }
+
+    /// Check a connecting peer's answer to our challenge, returning the session with it
+    fn verify_handshake(&self, pending: &PendingHandshake, message: &P2PMessage) -> Result<PeerSession, String> {
+        if message.sender_id != pending.node_id {
+            return Err(format!("Answer came from {} instead", message.sender_id));
+        }
+        let signature = message.payload.get("signature").and_then(|s| s.as_str()).unwrap_or_default();
+        verify_challenge(&pending.public_key, signature, &pending.transcript, &pending.node_id, &self.node_id)?;
+        Ok(self.peer_session(&pending.node_id, pending.version, pending.codec, pending.channel.clone()))
+    }
+
+    /// Session with a node that proved its identity, with a channel to it if encrypted
+    fn peer_session(&self, node_id: &str, version: u32, codec: Codec, channel: Option<SecureChannel>) -> PeerSession {
+        PeerSession {
+            node_id: node_id.to_string(),
+            version,
+            codec,
+            chain_id: self.ledger.chain_id(),
+            channel,
+            traffic: self.traffic.clone(),
+        }
+    }
+
+    /// Start peering with a node that proved its identity
+    fn register_peer(&self, socket: SocketRef, node_id: String, public_key: &str) {
+        // Add the node to the connected nodes
+        {
+            let mut connected_nodes = self.connected_nodes.lock().unwrap();
+            connected_nodes.insert(node_id.clone(), socket.clone());
+            info!(peer_id = node_id, "Successfully peered with node");
+        }
+
+        // Forget the node when its socket goes away
+        let p2p_manager = self.clone();
+        let disconnected_id = node_id.clone();
+        socket.on_disconnect(move |socket: SocketRef| {
+            p2p_manager.remove_peer_socket(&disconnected_id, socket);
+        });
+
+        self.track_peer(&node_id, public_key);
+        self.audit_connection(AuditEventType::PeerConnected, &node_id, json!({ "via": "inbound" }));
+
+        // Introduce ourselves so the new node knows the handshake is done
+        self.announce_self(&socket, node_id);
+    }
+
+    /// Track the health and key of a node that proved its identity, and tell the other peers about it
+    fn track_peer(&self, node_id: &str, public_key: &str) {
+        let now = Instant::now();
+        self.peer_health.lock().unwrap().insert(node_id.to_string(), PeerHealth { connected_at: now, last_seen: now });
+
+        // Add the node to the known nodes in the ledger
+        self.ledger.add_known_node(node_id.to_string());
+
+        // Record the key the node signs its entries with
+        if let Err(e) = self.ledger.add_node_key(node_id.to_string(), public_key) {
+            warn!(peer_id = node_id, "Ignoring public key: {}", e);
+        }
+        self.upgrade_to_quic(node_id);
+
+        // Tell all other nodes of the node, with where to dial it if known. Its key
+        // isn't passed on, as nodes only take a key from the node it belongs to
+        let url = self.contacts.get(node_id).and_then(|contact| contact.url);
+        self.broadcast_message(P2PMessage::new(
+            MessageType::NodeAnnounce,
+            self.node_id.clone(),
+            "".to_string(),
+            json!({ "node_id": node_id, "url": url }),
+        ));
+    }
+
+    /// Send our node ID and public key to a newly connected node
+    fn announce_self(&self, socket: &SocketRef, recipient_id: String) {
+        emit_to_peer(socket, &self.announcement(recipient_id));
+    }
+
+    /// Our node ID and public key, for a newly connected node
+    fn announcement(&self, recipient_id: String) -> P2PMessage {
+        let public_key = self.ledger.public_key();
+        let url = self.public_url.as_deref();
+        let signature = self.ledger.sign_message(&announce_payload(&self.node_id, &public_key, url));
+        P2PMessage::new(
+            MessageType::NodeAnnounce,
+            self.node_id.clone(),
+            recipient_id,
+            json!({ "node_id": self.node_id, "public_key": public_key, "url": url, "signature": signature }),
+        )
+    }
+
+    /// Set up event handlers for a socket
+    fn setup_socket_handlers(&self, socket: SocketRef) {
+        let p2p_manager = self.clone();
+
+        // Handle p2p messages
+        socket.on("p2p_message", move |socket: SocketRef, Data(frame): Data<Frame>| {
+            let p2p_manager = p2p_manager.clone();
+            async move {
+                // Parse the message, in whichever encoding it came
+                let size = frame.size();
+                let message = match frame.decode() {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        info!("Error parsing p2p message: {}", e);
+                        return;
+                    }
+                };
+                debug!(?message, "Received p2p message");
+
+                // Only the handshake is accepted until the peer has proven who it is,
+                // and afterwards it can't speak for other nodes
+                let Some(session) = socket.extensions.get::<PeerSession>() else {
+                    if matches!(message.message_type, MessageType::AuthResponse) {
+                        p2p_manager.complete_handshake(socket, message);
+                    } else if matches!(message.message_type, MessageType::HandshakeRejected) {
+                        warn!(?socket.id, "Peer refused the handshake: {}", rejection_reason(&message));
+                        socket.extensions.remove::<PendingHandshake>();
+                        socket.disconnect().ok();
+                    } else {
+                        info!(?socket.id, "Ignoring message from unverified peer");
+                    }
+                    return;
+                };
+                session.received(size);
+                let message = match session.open(message) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(peer_id = session.node_id, "Ignoring message: {}", e);
+                        return;
+                    }
+                };
+
+                // Handle the message and answer on the same socket
+                p2p_manager.record_peer_activity(&message.sender_id);
+                if let Some(reply) = p2p_manager.handle_message(message) {
+                    emit_to_peer(&socket, &reply);
+                }
+            }
+        });
+    }
+
+    /// Handle a p2p message, returning the reply to send back to the sender if any
+    pub fn handle_message(&self, message: P2PMessage) -> Option<P2PMessage> {
+        let _span = info_span!(
+            "p2p.message",
+            message_id = message.message_id,
+            message_type = ?message.message_type,
+            peer_id = message.sender_id,
+        )
+        .entered();
+        let awaited = self.resolve_request(&message);
+        match message.message_type {
+            MessageType::NodeAnnounce => self.handle_node_announce(message),
+            MessageType::NodeListRequest => return Some(self.handle_node_list_request(message)),
+            MessageType::NodeListResponse => self.handle_node_list_response(message),
+            MessageType::EntryAnnounce => return self.handle_entry_announce(message),
+            MessageType::EntryRequest => return self.handle_entry_request(message),
+            MessageType::LedgerSyncRequest => return self.handle_ledger_sync_request(message),
+            // Pages asked for by a sync coordinator are added by the coordinator
+            MessageType::LedgerSyncResponse if awaited => {}
+            MessageType::LedgerSyncResponse => return self.handle_ledger_sync_response(message),
+            MessageType::ChainReorg => return self.handle_chain_reorg(message),
+            // Activity is recorded for every message, so there is nothing more to do
+            MessageType::Heartbeat => {}
+            MessageType::NodeLeave => self.handle_node_leave(message),
+            MessageType::EntryRejected => self.handle_entry_rejected(message),
+            MessageType::EntryResponse => return self.handle_entry_response(message),
+            _ => info!("Unhandled message type: {:?}", message.message_type),
+        }
+        None
+    }
+
+    /// Handle a node announce message
+    fn handle_node_announce(&self, message: P2PMessage) {
+        // Extract the node ID from the message
+        let node_id = match message.payload.get("node_id") {
+            Some(id) => id.as_str().unwrap_or("unknown").to_string(),
+            None => "unknown".to_string(),
+        };
+
+        // Add the node to the known nodes in the ledger
+        if node_id != self.node_id {
+            self.contacts.heard_of(&node_id);
+        }
+        self.ledger.add_known_node(node_id.clone());
+
+        // A key is only taken from the node it belongs to, signed with that key
+        let url = message.payload.get("url").and_then(|u| u.as_str());
+        if let Some(public_key) = message.payload.get("public_key").and_then(|k| k.as_str()) {
+            let signature = message.payload.get("signature").and_then(|s| s.as_str()).unwrap_or_default();
+            let accepted = if node_id != message.sender_id {
+                Err(format!("Announce for {node_id} was sent by {}", message.sender_id))
+            } else {
+                verify_announcement(public_key, signature, &node_id, url)
+            };
+            if let Err(e) = accepted.and_then(|()| self.ledger.add_node_key(node_id.clone(), public_key)) {
+                warn!(peer_id = node_id, "Ignoring public key: {}", e);
+            }
+        }
+        if let Some(url) = url
+            && node_id != self.node_id
+        {
+            self.contacts.set_url(&node_id, url.to_string());
+        }
+    }
+
+    /// Handle a node list request message
+    fn handle_node_list_request(&self, message: P2PMessage) -> P2PMessage {
+        // Stale nodes aren't passed on, so nodes that went away drop out of node lists in time
+        let known_nodes: Vec<String> = self
+            .node_liveness()
+            .into_iter()
+            .filter(|(_, liveness)| *liveness == Liveness::Live)
+            .map(|(node_id, _)| node_id)
+            .collect();
+        let mut payload = json!({ "nodes": known_nodes });
+        if let Some(sample_size) = self.peer_exchange
+            && let Some(peers) = to_payload(pex::sample(self.peer_addresses(), sample_size))
+        {
+            payload["peers"] = peers;
+        }
+
+        // Build the response
+        P2PMessage::new(MessageType::NodeListResponse, self.node_id.clone(), message.sender_id.clone(), payload)
+            .reply_to(&message)
+    }
+
+    /// Handle a node list response by remembering the nodes the peer knows about
+    fn handle_node_list_response(&self, message: P2PMessage) {
+        let nodes: Vec<String> = match message.payload.get("nodes").map(|n| serde_json::from_value(n.clone())) {
+            Some(Ok(nodes)) => nodes,
+            _ => {
+                info!("Error parsing node list response");
+                return;
+            }
+        };
+
+        for node_id in nodes.into_iter().filter(|id| *id != self.node_id) {
+            self.contacts.heard_of(&node_id);
+            self.ledger.add_known_node(node_id);
+        }
+
+        // Nodes that don't take part in peer exchange send no addresses
+        if self.peer_exchange.is_some()
+            && let Some(peers) = message.payload.get("peers")
+        {
+            match serde_json::from_value::<Vec<PeerAddress>>(peers.clone()) {
+                Ok(peers) => {
+                    let learned = peers
+                        .into_iter()
+                        .filter(|peer| peer.node_id != self.node_id)
+                        .filter(|peer| self.contacts.set_url(&peer.node_id, peer.url.clone()))
+                        .count();
+                    if learned > 0 {
+                        debug!(peer_id = message.sender_id, "Learned {learned} peer addresses");
+                    }
+                }
+                Err(e) => info!(peer_id = message.sender_id, "Error parsing peer addresses: {e}"),
+            }
+        }
+    }
+
+    /// Handle a node leave message by dropping the peer without waiting for it to time out
+    fn handle_node_leave(&self, message: P2PMessage) {
+        info!(peer_id = message.sender_id, "Peer is leaving the network");
+        self.remove_peer(&message.sender_id);
+    }
+
+    /// Handle a node's report of entries it refused
+    fn handle_entry_rejected(&self, message: P2PMessage) {
+        let rejections: Vec<EntryRejection> =
+            serde_json::from_value(message.payload.get("rejections").cloned().unwrap_or_default()).unwrap_or_default();
+        for rejection in rejections {
+            warn!(
+                peer_id = message.sender_id,
+                entry_id = rejection.entry_id,
+                code = rejection.error.code.as_str(),
+                "Peer rejected entry: {}",
+                rejection.error.message
+            );
+        }
+    }
+
+    /// Queue entries from a peer that pass the validation policy and the write ACL.
+    ///
+    /// Returns the entries that were new or carried new signatures, and an
+    /// `EntryRejected` reply listing the entries that don't pass.
+    pub(crate) fn add_peer_entries(&self, sender_id: &str, entries: Vec<LedgerEntry>) -> (Vec<LedgerEntry>, Option<P2PMessage>) {
+        let mut learned = Vec::new();
+        let mut rejections = Vec::new();
+        let mut unfetched = Vec::new();
+        for mut entry in entries {
+            // Only the reference is covered by the entry hash, so rehydrated
+            // data from a peer is only trusted once it matches the reference
+            let sent = entry.blob.take().map(|reference| std::mem::replace(&mut entry.data, reference));
+            let offloaded = match BlobRef::from_data(&entry.data) {
+                Some(reference) => match sent.filter(|data| reference.matches(data)) {
+                    Some(data) => Some(data),
+                    None => {
+                        unfetched.push(entry);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let checked = self
+                .ledger
+                .check_chain(&entry)
+                .and_then(|()| self.ledger.check_limits(&entry))
+                .and_then(|()| self.ledger.validate(offloaded.as_ref().unwrap_or(&entry.data)))
+                .and_then(|()| self.ledger.check_writer(&entry));
+            match checked {
+                Ok(()) => {
+                    if self.ledger.add_pending_entry(entry.clone()) {
+                        learned.push(entry);
+                    }
+                }
+                Err(error) => rejections.push(self.reject_peer_entry(sender_id, entry.id, error)),
+            }
+        }
+        if !unfetched.is_empty() {
+            self.fetch_offloaded_entries(sender_id, unfetched);
+        }
+
+        (learned, self.rejection_reply(sender_id, rejections))
+    }
+
+    /// Log and audit a peer's entry being rejected
+    fn reject_peer_entry(&self, sender_id: &str, entry_id: String, error: ValidationError) -> EntryRejection {
+        warn!(peer_id = sender_id, entry_id, "Rejecting entry: {}", error);
+        self.audit_rejection(Some(sender_id), Some(&entry_id), &error);
+        EntryRejection { entry_id, error }
+    }
+
+    /// The message telling a peer which of its entries were rejected, if any were
+    fn rejection_reply(&self, sender_id: &str, rejections: Vec<EntryRejection>) -> Option<P2PMessage> {
+        if rejections.is_empty() {
+            return None;
+        }
+        Some(P2PMessage::new(
+            MessageType::EntryRejected,
+            self.node_id.clone(),
+            sender_id.to_string(),
+            json!({ "rejections": rejections }),
+        ))
+    }
+
+    /// Fetch the offloaded data of a peer's entries that came with only their
+    /// blob reference, and add the entries once their data matches the
+    /// reference, checking it like any other peer entry's. Entries whose blob
+    /// can't be fetched are rejected, as their data can't be checked.
+    fn fetch_offloaded_entries(&self, sender_id: &str, entries: Vec<LedgerEntry>) {
+        let (p2p, sender_id) = (self.clone(), sender_id.to_string());
+        tokio::spawn(async move {
+            let mut fetched = Vec::new();
+            let mut rejections = Vec::new();
+            for mut entry in entries {
+                let Some(reference) = BlobRef::from_data(&entry.data) else { continue };
+                let loaded = match &p2p.offloader {
+                    Some(offloader) => offloader.load(&reference).await,
+                    None => Ok(None),
+                };
+                let error = match loaded {
+                    Ok(Some(data)) if reference.matches(&data) => {
+                        entry.blob = Some(std::mem::replace(&mut entry.data, data));
+                        fetched.push(entry);
+                        continue;
+                    }
+                    Ok(Some(_)) => format!("Blob {} isn't in the form entry data is offloaded in", reference.hash),
+                    Ok(None) => format!("Blob {} of the entry's offloaded data can't be found", reference.hash),
+                    Err(e) => e,
+                };
+                let error = ValidationError::new(ErrorCode::Rejected, error);
+                rejections.push(p2p.reject_peer_entry(&sender_id, entry.id, error));
+            }
+
+            let (learned, reply) = p2p.add_peer_entries(&sender_id, fetched);
+            if p2p.ledger.collects_signatures() {
+                for entry in learned.into_iter().chain(p2p.ledger.endorse_pending_entries()) {
+                    p2p.broadcast_entry(entry);
+                }
+            }
+            p2p.apply_pending_entries();
+            if let Some(reply) = reply {
+                p2p.send_message(sender_id.clone(), reply);
+            }
+            if let Some(reply) = p2p.rejection_reply(&sender_id, rejections) {
+                p2p.send_message(sender_id, reply);
+            }
+        });
+    }
+
+    /// Handle an entry announce message
+    fn handle_entry_announce(&self, message: P2PMessage) -> Option<P2PMessage> {
+        // Extract the entry from the message
+        let entry: LedgerEntry = match serde_json::from_value(message.payload.clone()) {
+            Ok(entry) => entry,
+            Err(e) => {
+                info!("Error parsing entry announce: {}", e);
+                return None;
+            }
+        };
+
+        // Peers pass entries on, so the same announcement arrives from each of them
+        if !self.seen_announcements.insert(&entry) {
+            debug!(peer_id = message.sender_id, entry_id = entry.id, "Ignoring announcement already handled");
+            return None;
+        }
+
+        // Add the entry to the pending entries
+        let (learned, reply) = self.add_peer_entries(&message.sender_id, vec![entry]);
+
+        // Pass entries on while they collect signatures, and add ours
+        if self.ledger.collects_signatures() {
+            for entry in learned.into_iter().chain(self.ledger.endorse_pending_entries()) {
+                self.broadcast_entry(entry);
+            }
+        }
+
+        // Process pending entries and announce any that were added
+        self.apply_pending_entries();
+        reply
+    }
+
+    /// Handle a chain reorg message by considering the winning branch ourselves
+    fn handle_chain_reorg(&self, message: P2PMessage) -> Option<P2PMessage> {
+        let reorg: Reorg = match serde_json::from_value(message.payload) {
+            Ok(reorg) => reorg,
+            Err(e) => {
+                info!("Error parsing chain reorg: {}", e);
+                return None;
+            }
+        };
+
+        if self.check_entry_count(&message.sender_id, reorg.applied.len()).is_err() {
+            return None;
+        }
+        info!(peer_id = message.sender_id, fork_point = reorg.fork_point, "Peer reorganized its chain");
+        let (_, reply) = self.add_peer_entries(&message.sender_id, reorg.applied);
+        self.apply_pending_entries();
+        reply
+    }
+
+    /// Add pending entries to the chain, switching branches if a peer's fork wins.
+    ///
+    /// New entries are announced to peers, and a reorg is broadcast as a
+    /// `ChainReorg` message. Returns the entries that were added.
+    pub fn apply_pending_entries(&self) -> Vec<LedgerEntry> {
+        let mut added = self.ledger.process_pending_entries();
+
+        if let Some(reorg) = self.ledger.resolve_forks() {
+            added.extend(reorg.applied.iter().cloned());
+            if let Some(payload) = to_payload(&reorg) {
+                self.broadcast_message(P2PMessage::new(
+                    MessageType::ChainReorg,
+                    self.node_id.clone(),
+                    "".to_string(),
+                    payload,
+                ));
+            }
+        }
+
+        for entry in &added {
+            let event = AuditEvent::new(AuditEventType::EntryAccepted).with_entry(&entry.id);
+            self.audit.record(event.with_peer(&entry.creator_node_id));
+            self.broadcast_entry(entry.clone());
+        }
+
+        added
+    }
+
+    /// Retry adding pending entries to the chain, fetching the parents they are missing from peers.
+    ///
+    /// Entries pending for longer than `max_age` are dropped first. Each
+    /// missing parent is requested from the connected peers in turn until
+    /// one has it, and fetching repeats while parents turn up, so a gap of
+    /// several entries closes in one run. Returns the entries added.
+    pub async fn retry_pending_entries(&self, max_age: Duration) -> Vec<LedgerEntry> {
+        for entry in self.ledger.expire_pending_entries(max_age) {
+            warn!(entry_id = entry.id, creator = entry.creator_node_id, "Dropping entry that stayed pending for too long");
+            let event = AuditEvent::new(AuditEventType::EntryRejected)
+                .with_entry(&entry.id)
+                .with_details(json!({ "error": "Expired while pending" }));
+            self.audit.record(event.with_peer(&entry.creator_node_id));
+        }
+
+        let mut added = self.apply_pending_entries();
+        let peers = self.connected_peer_ids();
+        let mut asked = HashSet::new();
+        loop {
+            let missing: Vec<String> = self.ledger.missing_parents().into_iter().filter(|h| asked.insert(h.clone())).collect();
+            if missing.is_empty() || peers.is_empty() {
+                break;
+            }
+            for hash in missing {
+                for peer_id in &peers {
+                    match self.request_entry_by_hash_await(peer_id.clone(), hash.clone(), REQUEST_TIMEOUT).await {
+                        Ok(entry) => {
+                            debug!(%peer_id, entry_id = entry.id, "Fetched missing parent entry");
+                            self.add_peer_entries(peer_id, vec![entry]);
+                            break;
+                        }
+                        Err(e) => debug!(%peer_id, %hash, "Peer couldn't send missing parent entry: {e}"),
+                    }
+                }
+            }
+            added.extend(self.apply_pending_entries());
+        }
+        added
+    }
+
+    /// Get the IDs of the nodes this node has a session with over any transport, sorted
+    pub fn connected_peer_ids(&self) -> Vec<String> {
+        let mut peers: HashSet<String> = self.peer_health.lock().unwrap().keys().cloned().collect();
+        peers.extend(self.outbound_peer_ids().into_values().flatten());
+        peers.extend(self.quic_peers());
+        peers.extend(self.relayed_peers());
+        let mut peers: Vec<String> = peers.into_iter().collect();
+        peers.sort();
+        peers
+    }
+
+    /// Handle an entry request message, answering with the entry, or `null` if we don't have it.
+    /// The entry is looked up by the `entry_id` in the request, or by its `hash`.
+    fn handle_entry_request(&self, message: P2PMessage) -> Option<P2PMessage> {
+        // Find the entry in the ledger
+        let entry = match message.payload.get("hash").and_then(|h| h.as_str()) {
+            Some(hash) => self.ledger.get_entry_by_hash(hash),
+            None => {
+                let entry_id = message.payload.get("entry_id").and_then(|id| id.as_str()).unwrap_or("");
+                self.ledger.get_entry_by_id(entry_id)
+            }
+        };
+
+        // Build the response
+        let payload = to_payload(entry)?;
+        Some(
+            P2PMessage::new(MessageType::EntryResponse, self.node_id.clone(), message.sender_id.clone(), payload)
+                .reply_to(&message),
+        )
+    }
+
+    /// Handle an entry response by queueing the entry, if the peer had it, and
+    /// adding pending entries to the chain. A request awaiting the reply gets
+    /// it as well.
+    fn handle_entry_response(&self, message: P2PMessage) -> Option<P2PMessage> {
+        if message.payload.is_null() {
+            return None;
+        }
+        let entry: LedgerEntry = match serde_json::from_value(message.payload.clone()) {
+            Ok(entry) => entry,
+            Err(e) => {
+                info!("Error parsing entry response: {}", e);
+                return None;
+            }
+        };
+
+        let (_, reply) = self.add_peer_entries(&message.sender_id, vec![entry]);
+        self.apply_pending_entries();
+        reply
+    }
+
+    /// Handle a ledger sync request by sending one page of our chain.
+    ///
+    /// The page starts at the height in `from`, or after the newest entry of
+    /// the peer's `locator` that is also on our chain, or at our oldest entry
+    /// if the peer gave neither. It holds at most `limit` entries, capped at
+    /// [`MAX_SYNC_PAGE_SIZE`]. A peer that sends a `skeleton` segment length
+    /// also gets the hashes ending each segment from the page on. Under an
+    /// upload limit, the page is sent once its bytes fit the limit.
+    fn handle_ledger_sync_request(&self, message: P2PMessage) -> Option<P2PMessage> {
+        let payload = &message.payload;
+        let limit = payload
+            .get("limit")
+            .and_then(|l| l.as_u64())
+            .map_or(SYNC_PAGE_SIZE, |l| l as usize)
+            .clamp(1, MAX_SYNC_PAGE_SIZE);
+        let from = match (payload.get("from").and_then(|f| f.as_u64()), payload.get("locator")) {
+            (Some(from), _) => from as usize,
+            (None, Some(locator)) => {
+                let locator: Vec<String> = serde_json::from_value(locator.clone()).unwrap_or_default();
+                self.ledger.common_ancestor(&locator) + 1
+            }
+            (None, None) => 1,
+        };
+
+        let page = match payload.get("skeleton").and_then(|s| s.as_u64()) {
+            Some(step) => self.ledger.sync_skeleton_page(from, limit, step as usize, MAX_SYNC_SKELETON),
+            None => self.ledger.sync_page(from, limit),
+        };
+        let payload = to_payload(page)?;
+        let wait = self.reserve_bandwidth(&message.sender_id, Direction::Upload, &payload);
+        let reply =
+            P2PMessage::new(MessageType::LedgerSyncResponse, self.node_id.clone(), message.sender_id.clone(), payload)
+                .reply_to(&message);
+        if wait.is_zero() {
+            return Some(reply);
+        }
+        debug!(peer_id = message.sender_id, "Holding a sync page back for {:?} to stay within the upload limit", wait);
+        self.send_later(wait, reply);
+        None
+    }
+
+    /// Handle a page of a peer's chain, asking for the next one until we have caught up.
+    ///
+    /// Pages are only requested once the previous one has been added, so a
+    /// long chain never has to be held in memory at once. If the sync is cut
+    /// off, the next one with the same peer picks up at the page it stopped at.
+    /// Under a download limit, the next page is asked for once the bytes of
+    /// this one fit the limit.
+    fn handle_ledger_sync_response(&self, message: P2PMessage) -> Option<P2PMessage> {
+        let wait = self.reserve_bandwidth(&message.sender_id, Direction::Download, &message.payload);
+        let page: SyncPage = match serde_json::from_value(message.payload) {
+            Ok(page) => page,
+            Err(e) => {
+                info!("Error parsing ledger sync response: {}", e);
+                return None;
+            }
+        };
+
+        self.traffic.update(&message.sender_id, |traffic| traffic.sync_height = Some(page.height));
+        if self.check_entry_count(&message.sender_id, page.entries.len()).is_err() {
+            self.sync_progress.lock().unwrap().remove(&message.sender_id);
+            return None;
+        }
+        let received = page.entries.len();
+        let (_, rejection) = self.add_peer_entries(&message.sender_id, page.entries);
+        let added = self.apply_pending_entries();
+        info!(peer_id = message.sender_id, from = page.from, "Added {} of {} entries from ledger sync", added.len(), received);
+
+        // `from` comes from the peer, so it may be anything
+        let next = page.from.saturating_add(received);
+        // Entries after a rejected one build on it, so there is no point in going further
+        if rejection.is_some() || received == 0 || next > page.height {
+            self.sync_progress.lock().unwrap().remove(&message.sender_id);
+            self.record_sync();
+            info!(peer_id = message.sender_id, height = page.height, "Ledger sync finished");
+            return rejection;
+        }
+
+        self.sync_progress.lock().unwrap().insert(message.sender_id.clone(), next);
+        let request = self.ledger_sync_request(message.sender_id);
+        if wait.is_zero() {
+            return Some(request);
+        }
+        self.send_later(wait, request);
+        None
+    }
+
+    /// Reserve the bytes of a sync page in `direction` with `peer_id`,
+    /// returning how long to hold off to stay within the bandwidth limits
+    fn reserve_bandwidth(&self, peer_id: &str, direction: Direction, payload: &JsonValue) -> Duration {
+        match &self.bandwidth {
+            Some(bandwidth) if bandwidth.is_limited(direction) => {
+                let bytes = serde_json::to_vec(payload).map_or(0, |bytes| bytes.len());
+                bandwidth.reserve(peer_id, direction, bytes)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Wait out the bandwidth limits after a sync page of `payload` in `direction` with `peer_id`
+    async fn throttle(&self, peer_id: &str, direction: Direction, payload: &JsonValue) {
+        let wait = self.reserve_bandwidth(peer_id, direction, payload);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Send `message` to its recipient once `delay` has passed
+    fn send_later(&self, delay: Duration, message: P2PMessage) {
+        let p2p = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            p2p.send_to_peer(&message.recipient_id, &message).await;
+        });
+    }
+
+    /// Refuse a message from a peer carrying more entries than the ledger's limits allow at once
+    pub(crate) fn check_entry_count(&self, sender_id: &str, count: usize) -> Result<(), ValidationError> {
+        let max = self.ledger.limits().max_sync_entries;
+        if count <= max {
+            return Ok(());
+        }
+        let error = ValidationError::new(ErrorCode::TooManyEntries, format!("{count} entries in one message is over the limit of {max}"));
+        warn!(peer_id = sender_id, "Refusing entries: {}", error);
+        self.audit_rejection(Some(sender_id), None, &error);
+        Err(error)
+    }
+
+    /// Add a segment of a peer's chain a sync coordinator fetched, returning how many entries were added
+    pub(crate) fn add_synced_entries(&self, sender_id: &str, entries: Vec<LedgerEntry>) -> Result<usize, String> {
+        self.check_entry_count(sender_id, entries.len()).map_err(|e| e.to_string())?;
+        let received = entries.len();
+        let (_, rejection) = self.add_peer_entries(sender_id, entries);
+        let added = self.apply_pending_entries();
+        debug!(peer_id = sender_id, "Added {} of {} entries from a synced segment", added.len(), received);
+        match rejection {
+            Some(rejection) => {
+                self.send_message(sender_id.to_string(), rejection);
+                Err(format!("{sender_id} sent entries that were rejected"))
+            }
+            None => Ok(added.len()),
+        }
+    }
+
+    /// Let newly connected peers be synced with again, and sync with every
+    /// connected peer to pick up what a coordinated sync left out
+    pub async fn finish_initial_sync(&self) {
+        *self.initial_sync.lock().unwrap() = false;
+        for peer_id in self.connected_peer_ids() {
+            let request = self.ledger_sync_request(peer_id.clone());
+            self.send_to_peer(&peer_id, &request).await;
+        }
+    }
+
+    /// Record that the ledger has just been synced with a peer
+    pub fn record_sync(&self) {
+        *self.last_sync.lock().unwrap() = Some(Utc::now());
+    }
+
+    /// When the ledger was last synced with a peer, if it has been since the node started
+    pub fn last_sync(&self) -> Option<DateTime<Utc>> {
+        *self.last_sync.lock().unwrap()
+    }
+
+    /// Record that a message was received from a connected node
+    pub fn record_peer_activity(&self, node_id: &str) {
+        if let Some(health) = self.peer_health.lock().unwrap().get_mut(node_id) {
+            health.last_seen = Instant::now();
+        }
+        self.contacts.seen(node_id);
+    }
+
+    /// Get the liveness of every inbound peer
+    pub fn peer_health(&self) -> HashMap<String, PeerHealth> {
+        self.peer_health.lock().unwrap().clone()
+    }
+
+    /// The protocol version agreed with a peer, over whichever connection to it is open
+    pub fn peer_version(&self, node_id: &str) -> Option<u32> {
+        self.session_with(node_id).map(|session| session.version)
+    }
+
+    /// The encoding agreed with a peer, over whichever connection to it is open.
+    /// Sessions through the relay always carry JSON messages.
+    pub fn peer_codec(&self, node_id: &str) -> Option<Codec> {
+        self.session_with(node_id).map(|session| session.codec)
+    }
+
+    /// The session with a peer, over whichever connection to it is open
+    fn session_with(&self, node_id: &str) -> Option<PeerSession> {
+        self.connection_to(node_id).map(|(_, session)| session)
+    }
+
+    /// The connection messages to a peer go over, and the session on it
+    fn connection_to(&self, node_id: &str) -> Option<(Transport, PeerSession)> {
+        let quic = self.quic_peers.lock().unwrap().get(node_id).map(|peer| peer.session.clone());
+        let inbound = || self.connected_nodes.lock().unwrap().get(node_id).and_then(|socket| socket.extensions.get::<PeerSession>());
+        let outbound = || {
+            let outbound = self.outbound_peers.lock().unwrap();
+            outbound.values().find_map(|peer| peer.session.lock().unwrap().clone().filter(|s| s.node_id == node_id))
+        };
+        let relayed = || match self.relay.lock().unwrap().sessions.get(node_id) {
+            Some(RelaySession::Connected(session)) => Some(session.clone()),
+            _ => None,
+        };
+        quic.map(|session| (Transport::Iroh, session))
+            .or_else(|| inbound().or_else(outbound).map(|session| (Transport::Socketio, session)))
+            .or_else(|| relayed().map(|session| (Transport::Relay, session)))
+    }
+
+    /// How a connected peer is reached, the traffic with it and how far its chain reached at the last sync
+    pub fn peer_info(&self, node_id: &str) -> Option<PeerInfo> {
+        let (transport, _) = self.connection_to(node_id)?;
+        let traffic = self.traffic.get(node_id).unwrap_or_default();
+        Some(PeerInfo {
+            node_id: node_id.to_string(),
+            transport,
+            rtt_ms: traffic.rtt.map(|rtt| rtt.as_millis() as u64),
+            bytes_in: traffic.bytes_in,
+            bytes_out: traffic.bytes_out,
+            last_seen_secs: traffic.last_seen.map(|seen| seen.elapsed().as_secs()),
+            sync_height: traffic.sync_height,
+        })
+    }
+
+    /// Drop a peer from the connected nodes
+    pub fn remove_peer(&self, node_id: &str) -> Option<SocketRef> {
+        self.peer_health.lock().unwrap().remove(node_id);
+        let socket = self.connected_nodes.lock().unwrap().remove(node_id);
+        let relayed = self.relay.lock().unwrap().sessions.remove(node_id).is_some();
+        let quic = self.quic_peers.lock().unwrap().remove(node_id);
+        if let Some(peer) = &quic {
+            peer.connection.close(0u32.into(), b"disconnected");
+        }
+        if socket.is_some() || relayed || quic.is_some() {
+            info!(peer_id = node_id, "Removed peer");
+            let via = match (&socket, &quic) {
+                (Some(_), _) => "inbound",
+                (None, Some(_)) => "quic",
+                (None, None) => "relay",
+            };
+            self.audit_connection(AuditEventType::PeerDisconnected, node_id, json!({ "via": via }));
+        }
+        socket
+    }
+
+    /// Record a peer connecting or disconnecting, and over which connection
+    fn audit_connection(&self, event: AuditEventType, node_id: &str, details: JsonValue) {
+        self.audit.record(AuditEvent::new(event).with_peer(node_id).with_details(details));
+    }
+
+    /// Close the connections to and from a peer, returning whether there were any.
+    ///
+    /// URLs this node dials are dialed again after the usual backoff, unless
+    /// the peer is banned.
+    pub async fn disconnect_peer(&self, node_id: &str) -> bool {
+        let relayed = self.relay.lock().unwrap().sessions.contains_key(node_id);
+        let quic = self.quic_peers.lock().unwrap().contains_key(node_id);
+        let inbound = self.remove_peer(node_id).map(|socket| socket.disconnect().ok()).is_some() || relayed || quic;
+
+        let urls: Vec<String> = self
+            .outbound_peer_ids()
+            .into_iter()
+            .filter(|(_, peer_id)| peer_id.as_deref() == Some(node_id))
+            .map(|(url, _)| url)
+            .collect();
+        let banned = self.is_banned(node_id);
+        for url in &urls {
+            if banned && let Some(task) = self.dialed_peers.lock().unwrap().remove(url) {
+                // The connection task won't get to record the disconnect
+                task.abort();
+                self.audit_connection(AuditEventType::PeerDisconnected, node_id, json!({ "via": "outbound", "url": url }));
+            }
+            let peer = self.outbound_peers.lock().unwrap().remove(url);
+            if let Some(peer) = peer {
+                peer.client.disconnect().await.ok();
+            }
+        }
+
+        inbound || !urls.is_empty()
+    }
+
+    /// Disconnect a peer and refuse it from now on, whichever side opens the connection
+    pub async fn ban_peer(&self, node_id: &str) {
+        self.banned_peers.lock().unwrap().insert(node_id.to_string());
+        warn!(peer_id = node_id, "Banned peer");
+        self.disconnect_peer(node_id).await;
+    }
+
+    /// Accept a banned peer again, returning false if it wasn't banned
+    pub fn unban_peer(&self, node_id: &str) -> bool {
+        let removed = self.banned_peers.lock().unwrap().remove(node_id);
+        if removed {
+            info!(peer_id = node_id, "Lifted ban on peer");
+        }
+        removed
+    }
+
+    /// Whether connections from and to a node are refused
+    pub fn is_banned(&self, node_id: &str) -> bool {
+        self.banned_peers.lock().unwrap().contains(node_id)
+    }
+
+    /// Get the IDs of the banned nodes
+    pub fn banned_peers(&self) -> Vec<String> {
+        self.banned_peers.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Drop a peer when its socket disconnects, unless it has already reconnected on a new one
+    fn remove_peer_socket(&self, node_id: &str, socket: SocketRef) {
+        let current = self.connected_nodes.lock().unwrap().get(node_id).map(|s| s.id);
+        if current == Some(socket.id) {
+            self.remove_peer(node_id);
+        }
+    }
+
+    /// Disconnect peers we haven't heard from within `timeout`, returning their IDs
+    pub fn check_peer_health(&self, timeout: Duration) -> Vec<String> {
+        let stale: Vec<String> = self
+            .peer_health
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, health)| health.last_seen.elapsed() > timeout)
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+
+        for node_id in &stale {
+            warn!(peer_id = node_id, "Peer missed its heartbeats, disconnecting");
+            if let Some(socket) = self.remove_peer(node_id) {
+                socket.disconnect().ok();
+            }
+        }
+
+        stale
+    }
+
+    /// Send a heartbeat to every connected node
+    pub fn send_heartbeat(&self) {
+        self.broadcast_message(P2PMessage::new(
+            MessageType::Heartbeat,
+            self.node_id.clone(),
+            "".to_string(),
+            json!({}),
+        ));
+    }
+
+    /// Say goodbye to every peer and close outbound connections.
+    ///
+    /// Inbound peers are sent a `NodeLeave` message but stay connected until
+    /// the server closes their sockets.
+    pub async fn leave(&self) {
+        let goodbye = P2PMessage::new(MessageType::NodeLeave, self.node_id.clone(), "".to_string(), json!({}));
+
+        for socket in self.connected_nodes.lock().unwrap().values() {
+            emit_to_peer(socket, &goodbye);
+        }
+
+        // Dropping the senders finishes the QUIC streams once the goodbyes are written
+        let quic: Vec<QuicPeer> = self.quic_peers.lock().unwrap().drain().map(|(_, peer)| peer).collect();
+        for peer in quic {
+            peer.send(&goodbye);
+        }
+
+        // The goodbyes are flushed before the relay connection closes
+        if let Some(task) = self.relay_task.lock().unwrap().take() {
+            task.abort();
+        }
+        let relay = std::mem::take(&mut *self.relay.lock().unwrap());
+        for node_id in relay.sessions.keys() {
+            relay.send(node_id, &goodbye);
+        }
+
+        // Stop redialing before closing, or the connections would come straight back
+        for (_, task) in self.dialed_peers.lock().unwrap().drain() {
+            task.abort();
+        }
+        let outbound: Vec<OutboundPeer> = self.outbound_peers.lock().unwrap().drain().map(|(_, peer)| peer).collect();
+        for peer in outbound {
+            peer.send(&goodbye).await.ok();
+            peer.client.disconnect().await.ok();
+        }
+    }
+
+    /// Get the URLs of the nodes this node currently has an outbound connection to
+    pub fn outbound_peers(&self) -> Vec<String> {
+        self.outbound_peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Get the node ID behind each outbound connection, by URL, once the peer has proven it
+    pub fn outbound_peer_ids(&self) -> HashMap<String, Option<String>> {
+        self.outbound_peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, peer)| (url.clone(), peer.session.lock().unwrap().as_ref().map(|s| s.node_id.clone())))
+            .collect()
+    }
+
+    /// Addresses this node can vouch for: its own, if it has a public URL,
+    /// and those of the peers it dialed that proved who they are
+    pub fn peer_addresses(&self) -> Vec<PeerAddress> {
+        let own = self.public_url.iter().map(|url| PeerAddress { node_id: self.node_id.clone(), url: url.clone() });
+        let dialed = self
+            .outbound_peer_ids()
+            .into_iter()
+            .filter_map(|(url, node_id)| Some(PeerAddress { node_id: node_id?, url }));
+        own.chain(dialed).collect()
+    }
+
+    /// Where the nodes this node has heard of can be reached
+    pub fn contacts(&self) -> &Contacts {
+        &self.contacts
+    }
+
+    /// Liveness of each known node, this one included. Nodes known without a
+    /// contact yet, like those restored from a snapshot, count as just heard of.
+    pub fn node_liveness(&self) -> BTreeMap<String, Liveness> {
+        let now = Utc::now();
+        self.ledger
+            .get_known_nodes()
+            .into_iter()
+            .map(|node_id| {
+                let liveness = match self.contacts.get(&node_id) {
+                    Some(contact) if node_id != self.node_id => contact.liveness(self.stale_after, now),
+                    _ => Liveness::Live,
+                };
+                (node_id, liveness)
+            })
+            .collect()
+    }
+
+    /// Forget the nodes neither heard from nor of within `expiry`, other
+    /// than connected peers, returning their IDs
+    pub fn expire_nodes(&self, expiry: Duration) -> Vec<String> {
+        // Known nodes without a contact start their expiry now
+        for node_id in self.ledger.get_known_nodes() {
+            if node_id != self.node_id && self.contacts.get(&node_id).is_none() {
+                self.contacts.heard_of(&node_id);
+            }
+        }
+        let connected: HashSet<String> = self.connected_peer_ids().into_iter().collect();
+        let expired = self.contacts.expire(expiry, |node_id| connected.contains(node_id));
+        for node_id in &expired {
+            self.ledger.remove_known_node(node_id);
+        }
+        expired
+    }
+
+    /// Contacts of the nodes this node could dial but has no session with
+    pub fn unconnected_contacts(&self) -> Vec<NodeContact> {
+        let connected: HashSet<String> = self.connected_peer_ids().into_iter().collect();
+        self.contacts
+            .all()
+            .into_iter()
+            .filter(|contact| contact.is_dialable() && contact.node_id != self.node_id)
+            .filter(|contact| !connected.contains(&contact.node_id) && !self.is_banned(&contact.node_id))
+            .collect()
+    }
+
+    /// Dial a known node over QUIC, at the iroh address in its contact or by
+    /// its node ID, and at its URL if that fails.
+    ///
+    /// Fails if QUIC fails and the contact has no URL to fall back on.
+    pub async fn dial_contact(
+        &self,
+        contact: &NodeContact,
+        heartbeat_interval: Duration,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        // Nodes known by their keys can be looked up by iroh without an address
+        let addr = contact.addr.clone().or_else(|| identity::to_iroh(&contact.node_id).map(NodeAddr::new));
+        let quic = match addr {
+            Some(addr) => self.dial_quic(addr).await,
+            None => Err(format!("Node {} has no iroh address", contact.node_id)),
+        };
+        match (quic, &contact.url) {
+            (Ok(()), _) => Ok(()),
+            (Err(_), Some(url)) => {
+                self.dial_peer(url.clone(), heartbeat_interval, timeout);
+                Ok(())
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    /// Ask up to `fanout` connected peers, picked at random, for the nodes
+    /// they know and where to reach them, returning how many were asked
+    pub async fn exchange_peers(&self, fanout: usize) -> usize {
+        if self.peer_exchange.is_none() {
+            return 0;
+        }
+        let mut asked = 0;
+        for peer_id in pex::sample(self.connected_peer_ids(), fanout) {
+            let request = P2PMessage::new(MessageType::NodeListRequest, self.node_id.clone(), peer_id.clone(), json!({}));
+            if self.send_to_peer(&peer_id, &request).await {
+                asked += 1;
+            }
+        }
+        asked
+    }
+
+    /// Get the URLs this node is dialing, whether or not they are currently connected
+    pub fn dialed_peers(&self) -> Vec<String> {
+        let mut dialed = self.dialed_peers.lock().unwrap();
+        dialed.retain(|_, task| !task.is_finished());
+        dialed.keys().cloned().collect()
+    }
+
+    /// Start keeping a connection open to the node at `url`.
+    ///
+    /// Returns false if the URL is already being dialed.
+    pub fn dial_peer(&self, url: String, heartbeat_interval: Duration, timeout: Duration) -> bool {
+        let mut dialed = self.dialed_peers.lock().unwrap();
+        if dialed.get(&url).is_some_and(|task| !task.is_finished()) {
+            return false;
+        }
+
+        let task = tokio::spawn(self.clone().maintain_peer_connection(url.clone(), heartbeat_interval, timeout));
+        dialed.insert(url, task);
+        true
+    }
+
+    /// Dial every URL in `urls` that isn't already being dialed, returning how many were new
+    pub fn dial_peers(&self, urls: &[String], heartbeat_interval: Duration, timeout: Duration) -> usize {
+        urls.iter()
+            .filter(|url| self.dial_peer((*url).clone(), heartbeat_interval, timeout))
+            .count()
+    }
+
+    /// Dial a bootstrap node and the peers it lists under `/api/nodes`.
+    ///
+    /// The bootstrap node is dialed even if its peer list can't be fetched.
+    /// Returns the URLs that were newly dialed.
+    pub async fn discover_peers(
+        &self,
+        bootstrap_url: &str,
+        heartbeat_interval: Duration,
+        timeout: Duration,
+    ) -> Result<Vec<String>, String> {
+        let mut urls = vec![bootstrap_url.to_string()];
+        let listed = fetch_peer_urls(bootstrap_url).await;
+        if let Ok(peers) = &listed {
+            urls.extend(peers.iter().cloned());
+        }
+
+        let dialed = urls
+            .into_iter()
+            .filter(|url| self.dial_peer(url.clone(), heartbeat_interval, timeout))
+            .collect();
+        listed.map(|_| dialed)
+    }
+
+    /// Keep a connection open to the node at `url`, reconnecting with exponential backoff.
+    ///
+    /// Once the peer introduces itself, its node list and ledger are requested
+    /// so a new node catches up straight away. The connection is treated as
+    /// dead once nothing, not even a heartbeat, has been received from the
+    /// peer for `timeout`. Runs until the task is aborted, or returns if `url`
+    /// turns out to be this node.
+    pub async fn maintain_peer_connection(self, url: String, heartbeat_interval: Duration, timeout: Duration) {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+
+        loop {
+            match self.connect_to_peer(&url).await {
+                Ok((peer, last_seen, peer_id)) => {
+                    info!(peer_url = url, "Connected to peer");
+                    backoff.reset();
+                    self.outbound_peers.lock().unwrap().insert(url.clone(), peer.clone());
+
+                    // Heartbeat until the peer goes quiet or the socket fails
+                    loop {
+                        tokio::time::sleep(heartbeat_interval).await;
+                        let heartbeat = P2PMessage::new(MessageType::Heartbeat, self.node_id.clone(), "".to_string(), json!({}));
+                        let sent = peer.send(&heartbeat).await;
+                        if sent.is_err() || last_seen.lock().unwrap().elapsed() > timeout {
+                            break;
+                        }
+                    }
+
+                    self.outbound_peers.lock().unwrap().remove(&url);
+                    peer.client.disconnect().await.ok();
+                    if let Some(session) = peer.session.lock().unwrap().as_ref() {
+                        let via = json!({ "via": "outbound", "url": url });
+                        self.audit_connection(AuditEventType::PeerDisconnected, &session.node_id, via);
+                    }
+                    if peer_id.lock().unwrap().as_deref() == Some(self.node_id.as_str()) {
+                        info!(peer_url = url, "Peer is this node, no longer dialing it");
+                        return;
+                    }
+                    warn!(peer_url = url, "Lost connection to peer");
+                }
+                Err(e) => warn!(peer_url = url, "Failed to connect to peer: {}", e),
+            }
+
+            let delay = backoff.next_delay();
+            info!(peer_url = url, ?delay, "Reconnecting to peer");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Open a connection to the [namespace](Self::namespace) of the node at `url`.
+    ///
+    /// The peer has to prove it holds the key it claims for its node ID before
+    /// any of its messages are handled; otherwise the connection is closed.
+    /// Messages are sealed if either side asked for encryption. Also returns when a message was last received and, once the peer has
+    /// announced itself, the peer's node ID.
+    async fn connect_to_peer(&self, url: &str) -> Result<OutboundConnection, rust_socketio::Error> {
+        let last_seen = Arc::new(Mutex::new(Instant::now()));
+        let peer_id = Arc::new(Mutex::new(None));
+        let p2p_manager = self.clone();
+        let seen = last_seen.clone();
+        let announced_id = peer_id.clone();
+        let opening = Arc::new(self.open_handshake());
+        let peer_url = Arc::new(url.to_string());
+        // Node ID the peer has proven, once it has answered our challenge
+        let session: Arc<Mutex<Option<PeerSession>>> = Arc::new(Mutex::new(None));
+        let verified = session.clone();
+
+        let client = ClientBuilder::new(url)
+            .namespace(self.namespace())
+            .auth(opening.auth.clone())
+            // Reconnection is handled by maintain_peer_connection
+            .reconnect(false)
+            .on("p2p_message", move |payload: Payload, client: PeerClient| {
+                *seen.lock().unwrap() = Instant::now();
+                let p2p_manager = p2p_manager.clone();
+                let announced_id = announced_id.clone();
+                let opening = opening.clone();
+                let verified = verified.clone();
+                let peer_url = peer_url.clone();
+                async move {
+                    let frame = match payload {
+                        Payload::Text(values) => values.into_iter().next().map(Frame::Json),
+                        Payload::Binary(bytes) => Some(Frame::Binary(bytes.to_vec())),
+                        _ => None,
+                    };
+                    let size = frame.as_ref().map_or(0, Frame::size);
+                    let Some(Ok(message)) = frame.map(Frame::decode) else {
+                        return;
+                    };
+
+                    if matches!(message.message_type, MessageType::AuthChallenge) {
+                        if verified.lock().unwrap().is_some() {
+                            return;
+                        }
+                        match p2p_manager.answer_challenge(&opening, &message) {
+                            Ok((response, session)) => {
+                                let via = json!({ "via": "outbound", "url": peer_url.as_str() });
+                                p2p_manager.audit_connection(AuditEventType::PeerConnected, &session.node_id, via);
+                                p2p_manager.contacts.set_url(&session.node_id, peer_url.to_string());
+                                *verified.lock().unwrap() = Some(session);
+                                if let Some(response) = to_payload(response) {
+                                    client.emit("p2p_message", response).await.ok();
+                                }
+                            }
+                            Err(e) => {
+                                warn!(peer_id = message.sender_id, "Peer failed the handshake, disconnecting: {}", e);
+                                let rejection = p2p_manager.handshake_rejection(&message.sender_id, &e);
+                                if let Some(rejection) = to_payload(rejection) {
+                                    client.emit("p2p_message", rejection).await.ok();
+                                }
+                                client.disconnect().await.ok();
+                            }
+                        }
+                        return;
+                    }
+                    if matches!(message.message_type, MessageType::HandshakeRejected) {
+                        if verified.lock().unwrap().is_none() {
+                            warn!(peer_id = message.sender_id, "Peer refused the handshake: {}", rejection_reason(&message));
+                            client.disconnect().await.ok();
+                        }
+                        return;
+                    }
+
+                    // A node refusing a connection from itself says so without a handshake
+                    if matches!(message.message_type, MessageType::NodeAnnounce)
+                        && message.sender_id == p2p_manager.node_id
+                        && message.recipient_id == p2p_manager.node_id
+                    {
+                        *announced_id.lock().unwrap() = Some(message.sender_id);
+                        return;
+                    }
+                    let Some(session) = verified.lock().unwrap().clone() else {
+                        info!(sender_id = message.sender_id, "Ignoring message from unverified peer");
+                        return;
+                    };
+                    session.received(size);
+                    let message = match session.open(message) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            warn!(peer_id = session.node_id, "Ignoring message: {}", e);
+                            return;
+                        }
+                    };
+
+                    // The peer introduces itself once the handshake is done
+                    let greeted = matches!(message.message_type, MessageType::NodeAnnounce)
+                        && message.recipient_id == p2p_manager.node_id;
+                    let peer_id = message.sender_id.clone();
+                    p2p_manager.contacts.seen(&peer_id);
+                    if greeted {
+                        *announced_id.lock().unwrap() = Some(peer_id.clone());
+                        p2p_manager.upgrade_to_quic(&peer_id);
+                    }
+
+                    let mut replies: Vec<P2PMessage> = p2p_manager.handle_message(message).into_iter().collect();
+                    if greeted {
+                        replies.extend(p2p_manager.catch_up_requests(peer_id));
+                    }
+                    for reply in replies {
+                        let Some(frame) = frame_or_log(session.encode(&reply), &reply) else {
+                            continue;
+                        };
+                        let size = frame.size();
+                        if emit_frame(&client, frame).await.is_ok() {
+                            session.sent(size);
+                        }
+                    }
+                }
+                .boxed()
+            })
+            .connect()
+            .await?;
+
+        Ok((OutboundPeer { client, session }, last_seen, peer_id))
+    }
+
+    /// The handshake data this node opens a connection with, and the
+    /// ephemeral key it offers to encrypt the connection with
+    fn open_handshake(&self) -> OpeningHandshake {
+        let ephemeral = EphemeralKey::generate();
+        let auth = json!({
+            "node_id": self.node_id,
+            "public_key": self.ledger.public_key(),
+            "challenge": random_hex(),
+            "ephemeral_key": ephemeral.public_key(),
+            "encryption": self.encryption,
+            "consensus": self.ledger.consensus_name(),
+            "genesis": self.ledger.genesis_hash(),
+            "chain_id": self.ledger.chain_id(),
+            "protocol": self.protocol,
+            "codecs": self.codec.offered(),
+            "url": self.public_url,
+        });
+        OpeningHandshake { auth, ephemeral }
+    }
+
+    /// Requests to catch up on a newly connected peer's ledger and the nodes
+    /// it knows about, leaving the ledger to an initial sync still to come
+    fn catch_up_requests(&self, peer_id: String) -> Vec<P2PMessage> {
+        let mut requests =
+            vec![P2PMessage::new(MessageType::NodeListRequest, self.node_id.clone(), peer_id.clone(), json!({}))];
+        if !*self.initial_sync.lock().unwrap() {
+            requests.push(self.ledger_sync_request(peer_id));
+        }
+        requests
+    }
+
+    /// Check the proof a node we connected to sent of its key, and answer its challenge.
+    ///
+    /// Also returns the session with the node, encrypted if either side asked for it.
+    fn answer_challenge(
+        &self,
+        opening: &OpeningHandshake,
+        message: &P2PMessage,
+    ) -> Result<(P2PMessage, PeerSession), String> {
+        let field = |name: &str| {
+            message.payload.get(name).and_then(|v| v.as_str()).ok_or(format!("Handshake is missing {name}"))
+        };
+        let (public_key, signature) = (field("public_key")?, field("signature")?);
+        if message.recipient_id != self.node_id {
+            return Err(format!("Handshake was meant for {}", message.recipient_id));
+        }
+        if self.is_banned(&message.sender_id) {
+            return Err(format!("Node {} is banned", message.sender_id));
+        }
+
+        self.check_peer_key(&message.sender_id, public_key)?;
+        let transcript = handshake_transcript(&self.node_id, &opening.auth, &message.sender_id, &message.payload);
+        verify_challenge(public_key, signature, &transcript, &message.sender_id, &self.node_id)?;
+        let encrypted = message.payload.get("encryption").and_then(|e| e.as_bool()).unwrap_or(false);
+        if self.encryption && !encrypted {
+            return Err("Peer won't encrypt the connection".to_string());
+        }
+        self.check_peer_consensus(message.payload.get("consensus"))?;
+        self.check_peer_genesis(message.payload.get("genesis"))?;
+        self.check_peer_chain(message.chain_id.as_deref())?;
+        // Nodes from before negotiation don't say, and speak version 1
+        let version = match message.payload.get("protocol_version") {
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or("Handshake has an invalid protocol_version")?,
+            None => ProtocolVersions::LEGACY.max,
+        };
+        if !self.protocol.contains(version) {
+            return Err(format!(
+                "Peer picked protocol version {version}, this node speaks {}-{}",
+                self.protocol.min, self.protocol.max
+            ));
+        }
+        // Nodes from before codecs don't say, and send JSON
+        let codec = match message.payload.get("codec") {
+            Some(codec) => serde_json::from_value::<Codec>(codec.clone())
+                .ok()
+                .filter(|codec| self.codec.offered().contains(codec))
+                .ok_or("Peer picked a codec this node didn't offer")?,
+            None => Codec::Json,
+        };
+        let channel = if encrypted {
+            let shared_secret = opening.ephemeral.agree(field("ephemeral_key")?)?;
+            Some(SecureChannel::new(&shared_secret, &transcript, &self.node_id, &message.sender_id))
+        } else {
+            None
+        };
+        self.ledger.add_node_key(message.sender_id.clone(), public_key)?;
+
+        let response = P2PMessage::new(
+            MessageType::AuthResponse,
+            self.node_id.clone(),
+            message.sender_id.clone(),
+            json!({ "signature": self.ledger.sign_message(&handshake_payload(&transcript, &self.node_id, &message.sender_id)) }),
+        )
+        .with_chain_id(self.ledger.chain_id());
+        Ok((response, self.peer_session(&message.sender_id, version, codec, channel)))
+    }
+
+    /// Check that a peer runs the same consensus strategy, if it said which one it runs
+    fn check_peer_consensus(&self, consensus: Option<&JsonValue>) -> Result<(), String> {
+        let ours = self.ledger.consensus_name();
+        match consensus.and_then(|c| c.as_str()) {
+            Some(theirs) if theirs != ours => Err(format!("Peer runs {theirs} consensus, this node runs {ours}")),
+            _ => Ok(()),
+        }
+    }
 
-impl P2PManager {
-    /// Create a new p2p manager
-    pub fn new(node_id: String, ledger: SharedLedger) -> Self {
-        Self {
-            node_id,
-            ledger,
-            connected_nodes: Arc::new(Mutex::new(HashMap::new())),
-            endpoint: None,
-            blobs: None,
-            router: None,
+    /// Refuse a peer whose chain starts from another genesis. Peers that
+    /// don't say predate genesis files and start from the zero hash.
+    fn check_peer_genesis(&self, genesis: Option<&JsonValue>) -> Result<(), String> {
+        let ours = self.ledger.genesis_hash();
+        let theirs = genesis.and_then(|g| g.as_str()).unwrap_or(GENESIS_HASH);
+        if theirs != ours {
+            return Err(format!("Peer's chain starts from genesis {theirs}, this node's from {ours}"));
         }
+        Ok(())
     }
 
-    /// Create a new p2p manager with iroh components
-    pub fn new_with_iroh(
-        node_id: String,
-        ledger: SharedLedger,
-        endpoint: Arc<Endpoint>,
-        blobs: Arc<Blobs<mem::Store>>,
-        router: Arc<Router>,
-    ) -> Self {
-        Self {
-            node_id,
-            ledger,
-            connected_nodes: Arc::new(Mutex::new(HashMap::new())),
-            endpoint: Some(endpoint),
-            blobs: Some(blobs),
-            router: Some(router),
+    /// Refuse a peer on another network. Peers without a chain ID only
+    /// peer with nodes without one.
+    fn check_peer_chain(&self, chain_id: Option<&str>) -> Result<(), String> {
+        let ours = self.ledger.chain_id();
+        if chain_id != ours.as_deref() {
+            let name = |chain_id: Option<&str>| chain_id.unwrap_or("none").to_string();
+            return Err(format!("Peer is on chain {}, this node on {}", name(chain_id), name(ours.as_deref())));
         }
+        Ok(())
     }
 
-    /// Get the node ID
-    pub fn node_id(&self) -> &str {
-        &self.node_id
+    /// Start keeping a connection open to the gsio-relay at `url`, for peers
+    /// this node can't reach directly or that can't reach it. `token` is the
+    /// node's access token for the relay channel, if the relay requires one.
+    ///
+    /// Every `retry_interval` the node opens sessions with registered nodes
+    /// it has none with, so sessions that timed out come back. Returns false
+    /// if a relay connection is already kept open.
+    pub fn connect_rendezvous(&self, url: String, token: Option<String>, retry_interval: Duration) -> bool {
+        let mut task = self.relay_task.lock().unwrap();
+        if task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return false;
+        }
+        *task = Some(tokio::spawn(self.clone().maintain_rendezvous(url, token, retry_interval)));
+        true
     }
 
-    /// Get a clone of the connected nodes Arc
-    pub fn clone_connected_nodes(&self) -> Arc<Mutex<HashMap<String, SocketRef>>> {
-        self.connected_nodes.clone()
+    /// Get the IDs of the nodes this node has a session with through the relay, sorted
+    pub fn relayed_peers(&self) -> Vec<String> {
+        let relay = self.relay.lock().unwrap();
+        let mut peers: Vec<String> = relay
+            .sessions
+            .iter()
+            .filter(|(_, session)| matches!(session, RelaySession::Connected(_)))
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        peers.sort();
+        peers
     }
 
-    /// Handle a new connection from another node
-    pub fn handle_connection(&self, socket: SocketRef, data: JsonValue) {
-        // Extract the node ID from the connection data
-        let node_id = match data.get("node_id") {
-            Some(id) => id.as_str().unwrap_or("unknown").to_string(),
-            None => "unknown".to_string(),
-        };
+    /// Keep a connection open to the relay at `url`, reconnecting with exponential backoff.
+    ///
+    /// Sessions through the relay end when the connection does. Runs until
+    /// the task is aborted.
+    pub async fn maintain_rendezvous(self, url: String, token: Option<String>, retry_interval: Duration) {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
 
-        info!(ns = socket.ns(), ?socket.id, node_id = node_id, "P2P node connected, establishing peering");
+        loop {
+            match rendezvous::connect(&url, token.as_deref()).await {
+                Ok((sender, mut frames)) => {
+                    info!(relay_url = url, "Connected to relay");
+                    backoff.reset();
+                    sender.send(RelayFrame::Register { node_id: self.node_id.clone() }).ok();
+                    self.relay.lock().unwrap().sender = Some(sender);
 
-        // Add the node to the connected nodes
-        {
-            let mut connected_nodes = self.connected_nodes.lock().unwrap();
-            connected_nodes.insert(node_id.clone(), socket.clone());
-            info!(peer_id = node_id, "Successfully peered with node");
+                    let mut retry = tokio::time::interval(retry_interval);
+                    loop {
+                        tokio::select! {
+                            frame = frames.recv() => match frame {
+                                Some(frame) => self.handle_relay_frame(frame),
+                                None => break,
+                            },
+                            _ = retry.tick() => self.dial_relayed_peers(),
+                        }
+                    }
+
+                    let relay = std::mem::take(&mut *self.relay.lock().unwrap());
+                    for node_id in relay.sessions.keys() {
+                        self.drop_relayed_peer(node_id);
+                    }
+                    warn!(relay_url = url, "Lost connection to relay");
+                }
+                Err(e) => warn!(relay_url = url, "{}", e),
+            }
+
+            let delay = backoff.next_delay();
+            info!(relay_url = url, ?delay, "Reconnecting to relay");
+            tokio::time::sleep(delay).await;
         }
+    }
 
-        // Add the node to the known nodes in the ledger
-        self.ledger.add_known_node(node_id.clone());
+    /// Handle a frame from the relay
+    fn handle_relay_frame(&self, frame: RelayFrame) {
+        match frame {
+            RelayFrame::Registered { nodes, .. } => {
+                info!(nodes = nodes.len(), "Registered with relay");
+                self.relay.lock().unwrap().nodes = nodes.into_iter().collect();
+                self.dial_relayed_peers();
+            }
+            RelayFrame::NodeJoined { node_id } => {
+                self.relay.lock().unwrap().nodes.insert(node_id);
+                self.dial_relayed_peers();
+            }
+            RelayFrame::NodeLeft { node_id } => {
+                let mut relay = self.relay.lock().unwrap();
+                relay.nodes.remove(&node_id);
+                let session = relay.sessions.remove(&node_id);
+                drop(relay);
+                if session.is_some() {
+                    info!(peer_id = node_id, "Relayed peer left the relay");
+                    self.drop_relayed_peer(&node_id);
+                }
+            }
+            RelayFrame::Deliver { from, payload, .. } => match RelayPayload::from_msgpack(payload) {
+                Ok(RelayPayload::Connect { auth }) => self.accept_relayed_peer(from, auth),
+                Ok(RelayPayload::Message { message }) => {
+                    let size = message.to_string().len();
+                    match serde_json::from_value(message) {
+                        Ok(message) => self.handle_relayed_message(from, message, size),
+                        Err(e) => info!(sender_id = from, "Error parsing relayed message: {}", e),
+                    }
+                }
+                Err(e) => info!(sender_id = from, "Error parsing relayed payload: {}", e),
+            },
+            RelayFrame::Error { message } => warn!("Relay refused a frame: {}", message),
+            // Only nodes send these
+            RelayFrame::Register { .. } | RelayFrame::Forward { .. } | RelayFrame::Ack { .. } => {}
+        }
+    }
 
-        // Send a node announce message to all other nodes
-        self.broadcast_message(P2PMessage::new(
-            MessageType::NodeAnnounce,
-            self.node_id.clone(),
-            "".to_string(),
-            json!({ "node_id": node_id }),
-        ));
+    /// Open sessions with the registered nodes this node has none with.
+    ///
+    /// Only the node whose ID sorts first opens a session, so two nodes never
+    /// open one with each other at once. Nodes connected directly or over
+    /// QUIC are left alone, and handshakes that stalled are started over.
+    fn dial_relayed_peers(&self) {
+        let direct: HashSet<String> = self
+            .connected_nodes
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .chain(self.outbound_peer_ids().into_values().flatten())
+            .chain(self.quic_peers())
+            .collect();
 
-        // Set up event handlers for this socket
-        self.setup_socket_handlers(socket);
+        let mut relay = self.relay.lock().unwrap();
+        relay.sessions.retain(|_, session| match session {
+            RelaySession::Dialing { started, .. } | RelaySession::Accepting { started, .. } => {
+                started.elapsed() < HANDSHAKE_TIMEOUT
+            }
+            RelaySession::Connected(_) => true,
+        });
+        let dial: Vec<String> = relay
+            .nodes
+            .iter()
+            .filter(|node_id| **node_id > self.node_id && !relay.sessions.contains_key(*node_id))
+            .filter(|node_id| !direct.contains(*node_id) && !self.is_banned(node_id))
+            .cloned()
+            .collect();
+        for node_id in dial {
+            let mut opening = self.open_handshake();
+            // Relay frames are msgpack already, so relayed sessions stick to JSON messages
+            opening.auth.as_object_mut().unwrap().remove("codecs");
+            if relay.forward(&node_id, RelayPayload::Connect { auth: opening.auth.clone() }) {
+                relay.sessions.insert(node_id, RelaySession::Dialing { opening, started: Instant::now() });
+            }
+        }
     }
 
-    /// Set up event handlers for a socket
-    fn setup_socket_handlers(&self, socket: SocketRef) {
-        let p2p_manager = self.clone();
+    /// Challenge a node that opened a session through the relay
+    fn accept_relayed_peer(&self, node_id: String, auth: JsonValue) {
+        if auth.get("node_id").and_then(|id| id.as_str()) != Some(node_id.as_str()) {
+            warn!(peer_id = node_id, "Refusing relayed peer that claims another node ID");
+            return;
+        }
+        if self.is_banned(&node_id) {
+            warn!(peer_id = node_id, "Refusing banned peer");
+            return;
+        }
 
-        // Handle p2p messages
-        socket.on("p2p_message", move |socket: SocketRef, Data(data): Data<JsonValue>| {
-            let p2p_manager = p2p_manager.clone();
-            async move {
-                info!(?data, "Received p2p message");
+        let (pending, proof) = match self.challenge_peer(&node_id, &auth) {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                warn!(peer_id = node_id, "Refusing relayed peer: {}", e);
+                if let Some(rejection) = to_payload(self.handshake_rejection(&node_id, &e)) {
+                    self.relay.lock().unwrap().forward(&node_id, RelayPayload::Message { message: rejection });
+                }
+                return;
+            }
+        };
+        info!(peer_id = node_id, "Relayed peer connected, verifying its identity");
+        let Some(proof) = to_payload(proof) else {
+            return;
+        };
+        let mut relay = self.relay.lock().unwrap();
+        if relay.forward(&node_id, RelayPayload::Message { message: proof }) {
+            relay.sessions.insert(node_id, RelaySession::Accepting { pending, started: Instant::now() });
+        }
+    }
 
-                // Parse the message
-                let message: P2PMessage = match serde_json::from_value(data) {
-                    Ok(msg) => msg,
+    /// Handle a P2P message of `size` bytes that came through the relay, answering through it
+    fn handle_relayed_message(&self, from: String, message: P2PMessage, size: usize) {
+        let session = self.relay.lock().unwrap().sessions.get(&from).cloned();
+        match session {
+            Some(RelaySession::Dialing { opening, .. }) if matches!(message.message_type, MessageType::AuthChallenge) => {
+                match self.answer_challenge(&opening, &message) {
+                    Ok((response, session)) => {
+                        let mut relay = self.relay.lock().unwrap();
+                        relay.sessions.insert(from.clone(), RelaySession::Connected(session));
+                        if let Some(response) = to_payload(response) {
+                            relay.forward(&from, RelayPayload::Message { message: response });
+                        }
+                        drop(relay);
+                        self.audit_connection(AuditEventType::PeerConnected, &from, json!({ "via": "relay" }));
+                        let now = Instant::now();
+                        self.peer_health.lock().unwrap().insert(from.clone(), PeerHealth { connected_at: now, last_seen: now });
+                        self.upgrade_to_quic(&from);
+                    }
                     Err(e) => {
-                        info!("Error parsing p2p message: {}", e);
+                        warn!(peer_id = from, "Relayed peer failed the handshake: {}", e);
+                        let mut relay = self.relay.lock().unwrap();
+                        relay.sessions.remove(&from);
+                        if let Some(rejection) = to_payload(self.handshake_rejection(&from, &e)) {
+                            relay.forward(&from, RelayPayload::Message { message: rejection });
+                        }
+                    }
+                }
+            }
+            Some(RelaySession::Dialing { .. } | RelaySession::Accepting { .. })
+                if matches!(message.message_type, MessageType::HandshakeRejected) =>
+            {
+                warn!(peer_id = from, "Relayed peer refused the handshake: {}", rejection_reason(&message));
+                self.relay.lock().unwrap().sessions.remove(&from);
+            }
+            Some(RelaySession::Accepting { pending, .. }) if matches!(message.message_type, MessageType::AuthResponse) => {
+                match self.verify_handshake(&pending, &message) {
+                    Ok(session) => {
+                        self.relay.lock().unwrap().sessions.insert(from.clone(), RelaySession::Connected(session));
+                        info!(peer_id = from, "Successfully peered with node through the relay");
+                        self.track_peer(&from, &pending.public_key);
+                        self.audit_connection(AuditEventType::PeerConnected, &from, json!({ "via": "relay" }));
+                        // Introduce ourselves so the new node knows the handshake is done
+                        self.relay.lock().unwrap().send(&from, &self.announcement(from.clone()));
+                    }
+                    Err(e) => {
+                        warn!(peer_id = from, "Relayed peer failed the handshake: {}", e);
+                        self.relay.lock().unwrap().sessions.remove(&from);
+                    }
+                }
+            }
+            Some(RelaySession::Connected(session)) => {
+                session.received(size);
+                let message = match session.open(message) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(peer_id = from, "Ignoring message: {}", e);
                         return;
                     }
                 };
 
-                // Handle the message
-                p2p_manager.handle_message(socket, message);
+                self.record_peer_activity(&from);
+                let greeted = matches!(message.message_type, MessageType::NodeAnnounce) && message.recipient_id == self.node_id;
+                let mut replies: Vec<P2PMessage> = self.handle_message(message).into_iter().collect();
+                if greeted {
+                    replies.extend(self.catch_up_requests(from.clone()));
+                }
+                let relay = self.relay.lock().unwrap();
+                for reply in replies {
+                    relay.send(&from, &reply);
+                }
             }
-        });
+            _ => info!(sender_id = from, "Ignoring relayed message from unverified peer"),
+        }
     }
 
-    /// Handle a p2p message
-    fn handle_message(&self, socket: SocketRef, message: P2PMessage) {
-        match message.message_type {
-            MessageType::NodeAnnounce => self.handle_node_announce(message),
-            MessageType::NodeListRequest => self.handle_node_list_request(socket, message),
-            MessageType::EntryAnnounce => self.handle_entry_announce(message),
-            MessageType::EntryRequest => self.handle_entry_request(socket, message),
-            MessageType::LedgerSyncRequest => self.handle_ledger_sync_request(socket, message),
-            _ => info!("Unhandled message type: {:?}", message.message_type),
+    /// Stop tracking the health of a node whose relay session ended, unless it's connected directly
+    fn drop_relayed_peer(&self, node_id: &str) {
+        if !self.connected_nodes.lock().unwrap().contains_key(node_id) {
+            self.peer_health.lock().unwrap().remove(node_id);
         }
     }
 
-    /// Handle a node announce message
-    fn handle_node_announce(&self, message: P2PMessage) {
-        // Extract the node ID from the message
-        let node_id = match message.payload.get("node_id") {
-            Some(id) => id.as_str().unwrap_or("unknown").to_string(),
-            None => "unknown".to_string(),
+    /// Get the IDs of the nodes this node has a QUIC connection with
+    pub fn quic_peers(&self) -> Vec<String> {
+        self.quic_peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Open a QUIC connection to the node at `addr` over the iroh endpoint
+    /// and peer with it, unless there is one already
+    pub async fn dial_quic(&self, addr: impl Into<NodeAddr>) -> Result<(), String> {
+        let addr = addr.into();
+        let Some(endpoint) = self.endpoint.clone() else {
+            return Err("No iroh endpoint to dial from".to_string());
         };
+        if !self.key_ids {
+            return Err("Only nodes known by their keys are reached over QUIC".to_string());
+        }
+        let node_id = identity::from_iroh(&addr.node_id);
+        if node_id == self.node_id {
+            return Err("Refusing to dial this node".to_string());
+        }
+        if self.is_banned(&node_id) {
+            return Err(format!("Node {node_id} is banned"));
+        }
+        if self.quic_peers.lock().unwrap().contains_key(&node_id) {
+            return Ok(());
+        }
 
-        // Add the node to the known nodes in the ledger
-        self.ledger.add_known_node(node_id);
+        let handshake = async {
+            let connection = endpoint.connect(addr, &quic::alpn(self.channel())).await.map_err(|e| e.to_string())?;
+            let (mut send, mut recv) = connection.open_bi().await.map_err(|e| e.to_string())?;
+            quic::write_message(&mut send, &self.quic_hello(&node_id, None)).await?;
+            let (reply, _) = quic::read_message(&mut recv).await?;
+            if matches!(reply.message_type, MessageType::HandshakeRejected) {
+                return Err(format!("Peer refused the handshake: {}", rejection_reason(&reply)));
+            }
+            self.check_quic_hello(&node_id, &reply)?;
+            let version = reply
+                .payload
+                .get("protocol_version")
+                .and_then(|v| v.as_u64())
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|v| self.protocol.contains(*v))
+                .ok_or("Peer picked a protocol version this node doesn't speak")?;
+            Ok::<_, String>((connection, send, recv, version))
+        };
+        let (connection, send, recv, version) =
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await.map_err(|_| "Handshake timed out".to_string())??;
+        self.start_quic_session(node_id, connection, send, recv, version, self.node_id.clone());
+        Ok(())
     }
 
-    /// Handle a node list request message
-    fn handle_node_list_request(&self, socket: SocketRef, message: P2PMessage) {
-        // Get the list of known nodes
-        let known_nodes = self.ledger.get_known_nodes();
+    /// Peer with a node that opened a QUIC connection to this one
+    pub(crate) async fn accept_quic(&self, connection: Connection) -> Result<(), String> {
+        let node_id = identity::from_iroh(&connection.remote_node_id().map_err(|e| e.to_string())?);
+        if !self.key_ids {
+            return Err("Only nodes known by their keys are accepted over QUIC".to_string());
+        }
+        if self.is_banned(&node_id) {
+            return Err(format!("Node {node_id} is banned"));
+        }
 
-        // Send the response
-        let response = P2PMessage::new(
-            MessageType::NodeListResponse,
-            self.node_id.clone(),
-            message.sender_id,
-            json!({ "nodes": known_nodes }),
-        );
+        let handshake = async {
+            let (mut send, mut recv) = connection.accept_bi().await.map_err(|e| e.to_string())?;
+            let (hello, _) = quic::read_message(&mut recv).await?;
+            let version = self
+                .check_quic_hello(&node_id, &hello)
+                .and_then(|()| self.negotiate_version(hello.payload.get("protocol")));
+            match version {
+                Ok(version) => {
+                    quic::write_message(&mut send, &self.quic_hello(&node_id, Some(version))).await?;
+                    Ok::<_, String>((send, recv, version))
+                }
+                Err(e) => {
+                    // Wait for the peer to read the rejection before the connection closes
+                    quic::write_message(&mut send, &self.handshake_rejection(&node_id, &e)).await.ok();
+                    send.finish().ok();
+                    send.stopped().await.ok();
+                    Err(e)
+                }
+            }
+        };
+        let (send, recv, version) =
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await.map_err(|_| "Handshake timed out".to_string())??;
+        self.start_quic_session(node_id.clone(), connection, send, recv, version, node_id);
+        Ok(())
+    }
 
-        socket.emit("p2p_message", &serde_json::to_value(response).unwrap()).ok();
+    /// What a node opens a QUIC connection with, or, with the protocol version
+    /// it picked, answers one with
+    fn quic_hello(&self, recipient_id: &str, version: Option<u32>) -> P2PMessage {
+        let public_key = self.ledger.public_key();
+        let mut payload = json!({
+            "node_id": self.node_id,
+            "signature": self.ledger.sign_message(&announce_payload(&self.node_id, &public_key, None)),
+            "public_key": public_key,
+            "consensus": self.ledger.consensus_name(),
+            "genesis": self.ledger.genesis_hash(),
+            "protocol": self.protocol,
+        });
+        if let Some(version) = version {
+            payload["protocol_version"] = json!(version);
+        }
+        P2PMessage::new(MessageType::NodeAnnounce, self.node_id.clone(), recipient_id.to_string(), payload)
+            .with_chain_id(self.ledger.chain_id())
     }
 
-    /// Handle an entry announce message
-    fn handle_entry_announce(&self, message: P2PMessage) {
-        // Extract the entry from the message
-        let entry: LedgerEntry = match serde_json::from_value(message.payload.clone()) {
-            Ok(entry) => entry,
-            Err(e) => {
-                info!("Error parsing entry announce: {}", e);
-                return;
+    /// Check the introduction a node sent over a QUIC connection whose other end is `node_id`
+    fn check_quic_hello(&self, node_id: &str, hello: &P2PMessage) -> Result<(), String> {
+        if !matches!(hello.message_type, MessageType::NodeAnnounce) || hello.sender_id != node_id {
+            return Err(format!("Expected {node_id} to introduce itself"));
+        }
+        if hello.recipient_id != self.node_id {
+            return Err(format!("Handshake was meant for {}", hello.recipient_id));
+        }
+        // The connection proves the peer holds the key its ID names
+        self.check_peer_key(node_id, node_id)?;
+        self.check_peer_consensus(hello.payload.get("consensus"))?;
+        self.check_peer_genesis(hello.payload.get("genesis"))?;
+        self.check_peer_chain(hello.chain_id.as_deref())
+    }
+
+    /// Start peering over a QUIC connection whose handshake is done.
+    ///
+    /// Should two nodes dial each other at once, both keep the connection
+    /// dialed by the node whose ID sorts first.
+    fn start_quic_session(
+        &self,
+        node_id: String,
+        connection: Connection,
+        mut send: SendStream,
+        mut recv: RecvStream,
+        version: u32,
+        dialer: String,
+    ) {
+        // QUIC encrypts the connection already, and messages travel as JSON like over the relay
+        let session = self.peer_session(&node_id, version, Codec::Json, None);
+        let (sender, mut outgoing) = mpsc::unbounded_channel::<P2PMessage>();
+        let peer = QuicPeer { sender, session: session.clone(), connection: connection.clone(), dialer };
+        {
+            let mut quic_peers = self.quic_peers.lock().unwrap();
+            if let Some(existing) = quic_peers.get(&node_id) {
+                if existing.connection.close_reason().is_none() && existing.dialer <= peer.dialer {
+                    debug!(peer_id = node_id, "Already connected over QUIC, closing the new connection");
+                    connection.close(0u32.into(), b"duplicate connection");
+                    return;
+                }
+                existing.connection.close(0u32.into(), b"duplicate connection");
             }
-        };
+            quic_peers.insert(node_id.clone(), peer.clone());
+        }
+        info!(peer_id = node_id, "Successfully peered with node over QUIC");
+        self.track_peer(&node_id, &node_id);
+        self.audit_connection(AuditEventType::PeerConnected, &node_id, json!({ "via": "quic" }));
+        for request in self.catch_up_requests(node_id.clone()) {
+            peer.send(&request);
+        }
 
-        // Add the entry to the pending entries
-        self.ledger.add_pending_entry(entry);
+        // Messages are written in the order they were sent
+        let writer_session = session.clone();
+        tokio::spawn(async move {
+            while let Some(message) = outgoing.recv().await {
+                match quic::write_message(&mut send, &message).await {
+                    Ok(size) => writer_session.sent(size),
+                    Err(e) => {
+                        debug!("Writing to QUIC peer failed: {e}");
+                        break;
+                    }
+                }
+            }
+            send.finish().ok();
+        });
 
-        // Process pending entries
-        let added_entries = self.ledger.process_pending_entries();
+        let p2p_manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = match quic::read_message(&mut recv).await {
+                    Ok((message, size)) => {
+                        session.received(size);
+                        message
+                    }
+                    Err(e) => {
+                        debug!(peer_id = node_id, "QUIC stream ended: {e}");
+                        break;
+                    }
+                };
+                let message = match session.open(message) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(peer_id = node_id, "Ignoring message: {}", e);
+                        continue;
+                    }
+                };
 
-        // Announce any new entries that were added
-        for entry in added_entries {
-            self.broadcast_entry(entry);
-        }
+                p2p_manager.record_peer_activity(&node_id);
+                if let Some(reply) = p2p_manager.handle_message(message) {
+                    p2p_manager.send_message(node_id.clone(), reply);
+                }
+            }
+            p2p_manager.drop_quic_peer(&node_id, &connection);
+        });
     }
 
-    /// Handle an entry request message
-    fn handle_entry_request(&self, socket: SocketRef, message: P2PMessage) {
-        // Extract the entry ID from the message
-        let entry_id = match message.payload.get("entry_id") {
-            Some(id) => id.as_str().unwrap_or("").to_string(),
-            None => "".to_string(),
+    /// Forget a QUIC connection that closed, unless the peer has reconnected over a new one
+    fn drop_quic_peer(&self, node_id: &str, connection: &Connection) {
+        let removed = {
+            let mut quic_peers = self.quic_peers.lock().unwrap();
+            let current = quic_peers.get(node_id).is_some_and(|peer| peer.connection.stable_id() == connection.stable_id());
+            current && quic_peers.remove(node_id).is_some()
         };
-
-        // Find the entry in the ledger
-        let entries = self.ledger.get_entries();
-        let entry = entries.iter().find(|e| e.id == entry_id);
-
-        // Send the response
-        if let Some(entry) = entry {
-            let response = P2PMessage::new(
-                MessageType::EntryResponse,
-                self.node_id.clone(),
-                message.sender_id,
-                serde_json::to_value(entry).unwrap(),
-            );
-
-            socket.emit("p2p_message", &serde_json::to_value(response).unwrap()).ok();
+        if removed {
+            info!(peer_id = node_id, "QUIC connection to peer closed");
+            self.audit_connection(AuditEventType::PeerDisconnected, node_id, json!({ "via": "quic" }));
+            if self.session_with(node_id).is_none() {
+                self.peer_health.lock().unwrap().remove(node_id);
+            }
         }
     }
 
-    /// Handle a ledger sync request message
-    fn handle_ledger_sync_request(&self, socket: SocketRef, message: P2PMessage) {
-        // Get all entries in the ledger
-        let entries = self.ledger.get_entries();
-
-        // Send the response
-        let response = P2PMessage::new(
-            MessageType::LedgerSyncResponse,
-            self.node_id.clone(),
-            message.sender_id,
-            serde_json::to_value(entries).unwrap(),
-        );
-
-        socket.emit("p2p_message", &serde_json::to_value(response).unwrap()).ok();
+    /// Move a peer this node reached another way to QUIC, if it can be
+    /// reached over the iroh endpoint. Like relay sessions, only the node
+    /// whose ID sorts first dials.
+    fn upgrade_to_quic(&self, node_id: &str) {
+        if self.endpoint.is_none() || !self.key_ids || node_id <= self.node_id.as_str() {
+            return;
+        }
+        if self.quic_peers.lock().unwrap().contains_key(node_id) {
+            return;
+        }
+        // Contacts can tell iroh which relay and addresses to try
+        let addr = self.contacts.get(node_id).and_then(|contact| contact.addr);
+        let Some(addr) = addr.or_else(|| identity::to_iroh(node_id).map(NodeAddr::new)) else {
+            return;
+        };
+        let p2p_manager = self.clone();
+        let node_id = node_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = p2p_manager.dial_quic(addr).await {
+                debug!(peer_id = node_id, "Staying off QUIC: {e}");
+            }
+        });
     }
 
     /// Broadcast a message to all connected nodes
     pub fn broadcast_message(&self, message: P2PMessage) {
+        if let Some(outbox) = &self.outbox {
+            outbox.send(message).ok();
+            return;
+        }
+        // Peers reached over QUIC aren't sent the message again over their other connections
+        let quic_peers = self.quic_peers.lock().unwrap();
+        for peer in quic_peers.values() {
+            peer.send(&message);
+        }
+
         let connected_nodes = self.connected_nodes.lock().unwrap();
+        for (_, socket) in connected_nodes.iter().filter(|(node_id, _)| !quic_peers.contains_key(*node_id)) {
+            emit_to_peer(socket, &message);
+        }
+
+        let relay = self.relay.lock().unwrap();
+        for node_id in relay.sessions.keys().filter(|node_id| !quic_peers.contains_key(*node_id)) {
+            relay.send(node_id, &message);
+        }
 
-        for (_, socket) in connected_nodes.iter() {
-            socket.emit("p2p_message", &serde_json::to_value(message.clone()).unwrap()).ok();
+        // Outbound connections can only emit asynchronously
+        let outbound_peers = self.outbound_peers.lock().unwrap();
+        let outbound = outbound_peers.values().filter(|peer| {
+            !peer.session.lock().unwrap().as_ref().is_some_and(|session| quic_peers.contains_key(&session.node_id))
+        });
+        for peer in outbound {
+            let peer = peer.clone();
+            let message = message.clone();
+            tokio::spawn(async move {
+                peer.send(&message).await.ok();
+            });
         }
     }
 
     /// Broadcast a new ledger entry to all connected nodes
     pub fn broadcast_entry(&self, entry: LedgerEntry) {
-        let message = P2PMessage::new(
-            MessageType::EntryAnnounce,
-            self.node_id.clone(),
-            "".to_string(),
-            serde_json::to_value(entry).unwrap(),
-        );
-
-        self.broadcast_message(message);
+        let _span = info_span!("p2p.broadcast_entry", entry_id = entry.id).entered();
+        let Some(payload) = to_payload(entry) else {
+            return;
+        };
+        self.broadcast_message(P2PMessage::new(MessageType::EntryAnnounce, self.node_id.clone(), "".to_string(), payload));
     }
 
     /// Send a message to a specific node
     pub fn send_message(&self, recipient_id: String, message: P2PMessage) -> bool {
+        if let Some(outbox) = &self.outbox {
+            return outbox.send(P2PMessage { recipient_id, ..message }).is_ok();
+        }
+        if let Some(peer) = self.quic_peers.lock().unwrap().get(&recipient_id)
+            && peer.send(&message)
+        {
+            return true;
+        }
         let connected_nodes = self.connected_nodes.lock().unwrap();
 
         if let Some(socket) = connected_nodes.get(&recipient_id) {
-            socket.emit("p2p_message", &serde_json::to_value(message).unwrap()).is_ok()
+            emit_to_peer(socket, &message)
         } else {
-            false
+            self.relay.lock().unwrap().send(&recipient_id, &message)
         }
     }
 
@@ -338,17 +3387,168 @@ impl P2PManager {
         self.send_message(recipient_id, message)
     }
 
-    /// Request all ledger entries from a specific node
-    pub fn request_ledger_sync(&self, recipient_id: String) -> bool {
+    /// Ask a node for the nodes it knows, waiting up to `timeout` for its answer
+    pub async fn request_node_list_await(&self, recipient_id: String, timeout: Duration) -> Result<Vec<String>, RequestError> {
         let message = P2PMessage::new(
-            MessageType::LedgerSyncRequest,
+            MessageType::NodeListRequest,
             self.node_id.clone(),
             recipient_id.clone(),
             json!({}),
         );
 
+        let reply = self.request(recipient_id, message, timeout).await?;
+        let nodes = reply.payload.get("nodes").cloned().unwrap_or_default();
+        serde_json::from_value(nodes).map_err(|e| RequestError::InvalidReply(e.to_string()))
+    }
+
+    /// Ask a node for up to `limit` entries of its chain from height `from`,
+    /// or from where its chain parts from ours if `from` is `None`, along with
+    /// the hashes ending each `skeleton` entries if given, waiting up to `timeout` for its answer.
+    /// Under a download limit, the page is only returned once its bytes fit the limit.
+    pub async fn request_sync_page_await(
+        &self,
+        recipient_id: String,
+        from: Option<usize>,
+        limit: usize,
+        skeleton: Option<usize>,
+        timeout: Duration,
+    ) -> Result<SyncPage, RequestError> {
+        let mut payload = match from {
+            Some(from) => json!({ "from": from, "limit": limit }),
+            None => json!({ "locator": self.ledger.sync_locator(), "limit": limit }),
+        };
+        if let Some(step) = skeleton {
+            payload["skeleton"] = json!(step);
+        }
+        let message = P2PMessage::new(MessageType::LedgerSyncRequest, self.node_id.clone(), recipient_id.clone(), payload);
+
+        let reply = self.request(recipient_id.clone(), message, timeout).await?;
+        self.throttle(&recipient_id, Direction::Download, &reply.payload).await;
+        serde_json::from_value(reply.payload).map_err(|e| RequestError::InvalidReply(e.to_string()))
+    }
+
+    /// Ask a node for a ledger entry, waiting up to `timeout` for its answer
+    pub async fn request_entry_await(
+        &self,
+        recipient_id: String,
+        entry_id: String,
+        timeout: Duration,
+    ) -> Result<LedgerEntry, RequestError> {
+        let message = P2PMessage::new(
+            MessageType::EntryRequest,
+            self.node_id.clone(),
+            recipient_id.clone(),
+            json!({ "entry_id": entry_id }),
+        );
+
+        let reply = self.request(recipient_id, message, timeout).await?;
+        if reply.payload.is_null() {
+            return Err(RequestError::NotFound);
+        }
+        let entry: LedgerEntry = serde_json::from_value(reply.payload).map_err(|e| RequestError::InvalidReply(e.to_string()))?;
+        if entry.id != entry_id {
+            return Err(RequestError::InvalidReply(format!("Asked for entry {entry_id}, got {}", entry.id)));
+        }
+        Ok(entry)
+    }
+
+    /// Ask a node for the entry with `hash`, waiting up to `timeout` for its answer
+    pub async fn request_entry_by_hash_await(
+        &self,
+        recipient_id: String,
+        hash: String,
+        timeout: Duration,
+    ) -> Result<LedgerEntry, RequestError> {
+        let message = P2PMessage::new(
+            MessageType::EntryRequest,
+            self.node_id.clone(),
+            recipient_id.clone(),
+            json!({ "hash": hash }),
+        );
+
+        let reply = self.request(recipient_id, message, timeout).await?;
+        if reply.payload.is_null() {
+            return Err(RequestError::NotFound);
+        }
+        let entry: LedgerEntry = serde_json::from_value(reply.payload).map_err(|e| RequestError::InvalidReply(e.to_string()))?;
+        if entry.hash != hash {
+            return Err(RequestError::InvalidReply(format!("Asked for entry {hash}, got {}", entry.hash)));
+        }
+        Ok(entry)
+    }
+
+    /// Send a request to a node and wait up to `timeout` for the message that
+    /// names it in `in_reply_to`. Only a reply from `recipient_id` counts.
+    pub async fn request(&self, recipient_id: String, message: P2PMessage, timeout: Duration) -> Result<P2PMessage, RequestError> {
+        let (reply, replied) = oneshot::channel();
+        let request_id = message.message_id.clone();
+        let pending = PendingRequest { peer_id: recipient_id.clone(), reply };
+        self.pending_requests.lock().unwrap().insert(request_id.clone(), pending);
+        let _guard = PendingRequestGuard { pending: &self.pending_requests, request_id };
+
+        if !self.send_to_peer(&recipient_id, &message).await {
+            return Err(RequestError::NotConnected);
+        }
+        match tokio::time::timeout(timeout, replied).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) | Err(_) => Err(RequestError::TimedOut),
+        }
+    }
+
+    /// Hand a reply to the request waiting for it, returning whether there was one
+    fn resolve_request(&self, message: &P2PMessage) -> bool {
+        let Some(request_id) = &message.in_reply_to else {
+            return false;
+        };
+        let mut pending = self.pending_requests.lock().unwrap();
+        if pending.get(request_id).is_some_and(|request| request.peer_id == message.sender_id)
+            && let Some(request) = pending.remove(request_id)
+        {
+            return request.reply.send(message.clone()).is_ok();
+        }
+        false
+    }
+
+    /// Send a message to a node over whichever connection reaches it: QUIC,
+    /// one it opened to us, one we opened to it, or the relay
+    async fn send_to_peer(&self, recipient_id: &str, message: &P2PMessage) -> bool {
+        if let Some(outbox) = &self.outbox {
+            return outbox.send(P2PMessage { recipient_id: recipient_id.to_string(), ..message.clone() }).is_ok();
+        }
+        if let Some(peer) = self.quic_peers.lock().unwrap().get(recipient_id)
+            && peer.send(message)
+        {
+            return true;
+        }
+        if let Some(socket) = self.connected_nodes.lock().unwrap().get(recipient_id) {
+            return emit_to_peer(socket, message);
+        }
+        let outbound = self.outbound_peers.lock().unwrap().values().find(|peer| {
+            peer.session.lock().unwrap().as_ref().is_some_and(|session| session.node_id == recipient_id)
+        }).cloned();
+        match outbound {
+            Some(peer) => peer.send(message).await.is_ok(),
+            None => self.relay.lock().unwrap().send(recipient_id, message),
+        }
+    }
+
+    /// Start syncing the ledger with a specific node
+    pub fn request_ledger_sync(&self, recipient_id: String) -> bool {
+        let message = self.ledger_sync_request(recipient_id.clone());
         self.send_message(recipient_id, message)
     }
+
+    /// Ask a node for the next page of its chain.
+    ///
+    /// An unfinished sync with the node resumes at the page it stopped at;
+    /// otherwise the node is sent our locator to find where our chains part.
+    fn ledger_sync_request(&self, recipient_id: String) -> P2PMessage {
+        let payload = match self.sync_progress.lock().unwrap().get(&recipient_id) {
+            Some(next) => json!({ "from": next, "limit": self.sync_page_size }),
+            None => json!({ "locator": self.ledger.sync_locator(), "limit": self.sync_page_size }),
+        };
+        P2PMessage::new(MessageType::LedgerSyncRequest, self.node_id.clone(), recipient_id, payload)
+    }
 }
 
 impl Clone for P2PManager {
@@ -356,10 +3556,41 @@ impl Clone for P2PManager {
         Self {
             node_id: self.node_id.clone(),
             ledger: self.ledger.clone(),
+            channel: self.channel.clone(),
             connected_nodes: self.connected_nodes.clone(),
+            peer_health: self.peer_health.clone(),
+            outbound_peers: self.outbound_peers.clone(),
+            dialed_peers: self.dialed_peers.clone(),
             endpoint: self.endpoint.clone(),
             blobs: self.blobs.clone(),
             router: self.router.clone(),
+            mode: self.mode.clone(),
+            encryption: self.encryption,
+            key_ids: self.key_ids,
+            protocol: self.protocol,
+            codec: self.codec,
+            audit: self.audit.clone(),
+            sync_page_size: self.sync_page_size,
+            sync_progress: self.sync_progress.clone(),
+            initial_sync: self.initial_sync.clone(),
+            pending_requests: self.pending_requests.clone(),
+            banned_peers: self.banned_peers.clone(),
+            last_sync: self.last_sync.clone(),
+            offloader: self.offloader.clone(),
+            archive: self.archive.clone(),
+            bandwidth: self.bandwidth.clone(),
+            relay: self.relay.clone(),
+            relay_task: self.relay_task.clone(),
+            quic_peers: self.quic_peers.clone(),
+            traffic: self.traffic.clone(),
+            seen_announcements: self.seen_announcements.clone(),
+            idempotency_keys: self.idempotency_keys.clone(),
+            mempool: self.mempool.clone(),
+            outbox: self.outbox.clone(),
+            public_url: self.public_url.clone(),
+            peer_exchange: self.peer_exchange,
+            contacts: self.contacts.clone(),
+            stale_after: self.stale_after,
         }
     }
 }
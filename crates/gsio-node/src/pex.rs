@@ -0,0 +1,66 @@
+//! Peer exchange.
+//!
+//! Every round a node asks a few of its peers which nodes they know, with
+//! the `NodeListRequest` it also sends a peer it has just connected to.
+//! Nodes that take part answer with a random sample of the addresses they
+//! can vouch for next to the node IDs: their own public URL and the URLs of
+//! the peers they dial. The asking node adds the addresses to its
+//! [contacts](crate::contacts) and dials the nodes it isn't connected to
+//! yet, passing their addresses on once it dials them itself, so a
+//! node that reaches one member of the mesh soon reaches the rest, with or
+//! without iroh discovery. Nodes that don't take part ignore the addresses.
+
+use std::time::Duration;
+
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+
+/// The `[peer_exchange]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeerExchangeConfig {
+    /// Whether to exchange addresses with peers at all
+    pub enabled: bool,
+    /// Seconds between exchange rounds
+    pub interval: u64,
+    /// Peers asked each round
+    pub fanout: usize,
+    /// Addresses sent in each answer at most
+    pub sample_size: usize,
+}
+
+impl Default for PeerExchangeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 60,
+            fanout: 3,
+            sample_size: 16,
+        }
+    }
+}
+
+impl PeerExchangeConfig {
+    /// Whether this node takes part in peer exchange
+    pub fn is_enabled(&self) -> bool {
+        self.enabled && self.interval > 0
+    }
+
+    /// Time between exchange rounds
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+/// Where a node can be dialed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerAddress {
+    pub node_id: String,
+    /// Base URL the node serves its `/p2p` namespace at
+    pub url: String,
+}
+
+/// Up to `n` of `items`, picked at random
+pub fn sample<T>(items: impl IntoIterator<Item = T>, n: usize) -> Vec<T> {
+    items.into_iter().choose_multiple(&mut rand::thread_rng(), n)
+}
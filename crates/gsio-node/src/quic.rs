@@ -0,0 +1,97 @@
+//! P2P messages over iroh QUIC connections.
+//!
+//! Nodes whose IDs are their keys (see [`crate::identity`]) can reach each
+//! other over their iroh endpoints, which punch holes through NATs where
+//! Socket.IO would need a public URL. Each ledger speaks its own ALPN, so
+//! channels' messages don't mix with the main ledger's. A connection carries
+//! one bidirectional stream of length-prefixed JSON messages; the node that
+//! dials it sends a `NodeAnnounce` with its protocol versions and consensus,
+//! and the node it dials answers with one naming the version picked, or
+//! with a `HandshakeRejected`. QUIC already proves each side holds the key
+//! its node ID names, so there is no challenge to sign.
+
+use std::fmt;
+
+use futures::future::BoxFuture;
+use iroh::endpoint::{Connection, RecvStream, SendStream};
+use iroh::protocol::ProtocolHandler;
+use tracing::debug;
+
+use crate::p2p::{P2PManager, P2PMessage};
+
+/// ALPN of the P2P protocol for the node's main ledger
+pub const P2P_ALPN: &[u8] = b"gsio/p2p/0";
+
+/// Largest message a peer may send, so a peer can't make the node buffer without bound
+pub const MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+
+/// ALPN of the P2P protocol for `channel`'s ledger, or the main ledger's
+pub fn alpn(channel: Option<&str>) -> Vec<u8> {
+    match channel {
+        Some(channel) => [P2P_ALPN, b"/", channel.as_bytes()].concat(),
+        None => P2P_ALPN.to_vec(),
+    }
+}
+
+/// Write a message to a stream, prefixed with its length, returning the bytes written
+pub async fn write_message(send: &mut SendStream, message: &P2PMessage) -> Result<usize, String> {
+    let bytes = serde_json::to_vec(message).map_err(|e| e.to_string())?;
+    let len = (bytes.len() as u32).to_be_bytes();
+    send.write_all(&len).await.map_err(|e| e.to_string())?;
+    send.write_all(&bytes).await.map_err(|e| e.to_string())?;
+    Ok(len.len() + bytes.len())
+}
+
+/// Read the next message from a stream along with the bytes it took,
+/// failing once the stream ends
+pub async fn read_message(recv: &mut RecvStream) -> Result<(P2PMessage, usize), String> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await.map_err(|e| e.to_string())?;
+    let size = u32::from_be_bytes(len) as usize;
+    if size > MAX_FRAME_BYTES {
+        return Err(format!("Message of {size} bytes is over the {MAX_FRAME_BYTES} byte limit"));
+    }
+    let mut bytes = vec![0u8; size];
+    recv.read_exact(&mut bytes).await.map_err(|e| e.to_string())?;
+    let message = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    Ok((message, len.len() + size))
+}
+
+/// Accepts P2P connections to a ledger's manager on the iroh router
+#[derive(Clone)]
+pub struct QuicTransport {
+    p2p: P2PManager,
+}
+
+impl QuicTransport {
+    pub fn new(p2p: P2PManager) -> Self {
+        Self { p2p }
+    }
+
+    /// The ALPN to register the transport under
+    pub fn alpn(&self) -> Vec<u8> {
+        alpn(self.p2p.channel())
+    }
+}
+
+impl fmt::Debug for QuicTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuicTransport")
+            .field("node_id", &self.p2p.node_id())
+            .field("channel", &self.p2p.channel())
+            .finish()
+    }
+}
+
+impl ProtocolHandler for QuicTransport {
+    fn accept(&self, connection: Connection) -> BoxFuture<'static, anyhow::Result<()>> {
+        let p2p = self.p2p.clone();
+        Box::pin(async move {
+            let peer = connection.remote_node_id()?;
+            if let Err(e) = p2p.accept_quic(connection).await {
+                debug!(%peer, "Refused QUIC peer: {e}");
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,191 @@
+//! Token-bucket rate limiting for the client-facing `/api` routes and the
+//! `add_ledger_entry` Socket.IO event.
+//!
+//! Every client IP address has a bucket, and so does every bearer credential
+//! a client presents. A request takes a token from each bucket it falls under
+//! and is refused if any of them is empty, so spreading requests over several
+//! addresses doesn't get around the limit on a key, and making up keys doesn't
+//! get around the limit on an address.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use socketioxide::extract::SocketRef;
+
+use crate::api::ApiError;
+use crate::validation::ErrorCode;
+
+/// Number of buckets above which those that have filled up again are dropped
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// The `[rate_limit]` section of the config file; limiting is off unless `requests_per_second` is set
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Requests each IP address and each credential may make per second on average
+    pub requests_per_second: f64,
+    /// Requests that may be made at once before the average rate applies
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 0.0,
+            burst: 20,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Whether requests are limited
+    pub fn is_enabled(&self) -> bool {
+        self.requests_per_second > 0.0
+    }
+}
+
+/// Who a request came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Client {
+    pub ip: Option<IpAddr>,
+    /// Bearer credential the client presented, if any
+    pub credential: Option<String>,
+}
+
+impl Client {
+    /// Identify the sender of an HTTP request; the address is only known when
+    /// the server is run with `into_make_service_with_connect_info`
+    pub fn from_request(request: &Request) -> Self {
+        Self {
+            ip: request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip()),
+            credential: request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(str::to_string),
+        }
+    }
+
+    /// Identify a Socket.IO client from its connection request and the
+    /// `token` in its connect auth data
+    pub fn from_socket(socket: &SocketRef, auth: &JsonValue) -> Self {
+        Self {
+            ip: socket.req_parts().extensions.get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip()),
+            credential: auth.get("token").and_then(JsonValue::as_str).map(str::to_string),
+        }
+    }
+
+    /// Names of the buckets the client's requests take tokens from; clients
+    /// whose address isn't known share one
+    fn buckets(&self) -> impl Iterator<Item = String> + '_ {
+        let ip = match self.ip {
+            Some(ip) => format!("ip:{ip}"),
+            None => "ip:unknown".to_string(),
+        };
+        let credential = self.credential.as_ref().map(|key| format!("key:{key}"));
+        std::iter::once(ip).chain(credential)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Hands out tokens to clients at the configured rate
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter for the rate in `config`
+    pub fn new(config: &RateLimitConfig) -> Result<Self, String> {
+        if !config.requests_per_second.is_finite() || config.requests_per_second <= 0.0 {
+            return Err(format!("Invalid requests_per_second {}", config.requests_per_second));
+        }
+        if config.burst == 0 {
+            return Err("Rate limit burst must be at least 1".to_string());
+        }
+
+        Ok(Self {
+            rate: config.requests_per_second,
+            burst: f64::from(config.burst),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Take a token for a request from `client`, or say how long until one is available
+    pub fn check(&self, client: &Client) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| self.refill(*bucket, now).tokens < self.burst);
+        }
+
+        let keys: Vec<String> = client.buckets().collect();
+        let refilled: Vec<Bucket> = keys
+            .iter()
+            .map(|key| {
+                let bucket = buckets.get(key).copied().unwrap_or(Bucket { tokens: self.burst, updated: now });
+                self.refill(bucket, now)
+            })
+            .collect();
+
+        // Nothing is taken unless every bucket has a token to give
+        let wait = refilled
+            .iter()
+            .filter(|bucket| bucket.tokens < 1.0)
+            .map(|bucket| Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+            .max();
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+        for (key, bucket) in keys.into_iter().zip(refilled) {
+            buckets.insert(key, Bucket { tokens: bucket.tokens - 1.0, ..bucket });
+        }
+        Ok(())
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.rate).min(self.burst),
+            updated: now,
+        }
+    }
+}
+
+/// Middleware answering requests over the limit with `429 Too Many Requests`
+/// and a `Retry-After` header
+pub async fn limit_rate(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    match limiter.check(&Client::from_request(&request)) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let mut response = ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests")
+                .with_code(ErrorCode::RateLimited)
+                .into_response();
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs(wait)));
+            response
+        }
+    }
+}
+
+/// Whole seconds to wait before retrying, rounded up
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
@@ -0,0 +1,312 @@
+//! Reaching peers through a gsio-relay rendezvous.
+//!
+//! A node behind NAT can't accept connections from its peers, and may not
+//! be able to dial them either. Such a node keeps a WebSocket open to the
+//! relay instead and registers its node ID there. Registered nodes open
+//! sessions with each other through the relay and exchange the same P2P
+//! messages as over `/p2p` connections. The handshake runs end to end, so the
+//! relay can't pass itself off as a node, and can't read encrypted messages.
+//!
+//! A relay that is exposed publicly only lets nodes join a channel with an
+//! access token, issued to the node's ID by whoever holds the channel's
+//! secret. The node presents it as the `token` query parameter.
+//!
+//! Frames go to the relay as msgpack in binary WebSocket messages. The relay
+//! refuses messages over [`MAX_FRAME_BYTES`], so a payload too big for one
+//! frame, like a large sync response, is split into fragments that the
+//! receiving node puts back together.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use futures::{SinkExt, StreamExt};
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+use url::Url;
+
+/// The `[rendezvous]` section of the config file; nodes only connect directly unless `url` is set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendezvousConfig {
+    /// WebSocket URL of the gsio-relay channel to register with, like `wss://relay.example.com/channels/mesh`
+    pub url: Option<String>,
+    /// Access token for the channel, issued to this node's ID
+    pub token: Option<String>,
+}
+
+impl RendezvousConfig {
+    /// Whether the node reaches peers through a relay
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some()
+    }
+}
+
+/// Largest message the relay accepts, in bytes
+pub const MAX_FRAME_BYTES: usize = 1 << 20;
+
+/// Size of each fragment of a payload too big for one frame, small enough
+/// for the relay to keep fragments in Durable Object storage
+const FRAGMENT_BYTES: usize = 96 * 1024;
+
+/// Most fragments a payload is split into, capping payloads at 60 MiB
+const MAX_FRAGMENTS: usize = 640;
+
+/// How long a partly received payload waits for its remaining fragments
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A frame exchanged with the relay, matching gsio-relay's
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelayFrame {
+    /// Claim a node ID on the connection
+    Register { node_id: String },
+    /// The node is registered; `nodes` are the other registered nodes
+    Registered { node_id: String, nodes: Vec<String> },
+    /// Another node registered
+    NodeJoined { node_id: String },
+    /// A registered node went away
+    NodeLeft { node_id: String },
+    /// Have `payload` delivered to node `to`
+    Forward { to: String, payload: Value },
+    /// A payload forwarded by node `from`, numbered for acknowledging
+    Deliver {
+        from: String,
+        #[serde(default)]
+        seq: u64,
+        payload: Value,
+    },
+    /// Tell the relay the deliveries up to and including `seq` arrived
+    Ack { seq: u64 },
+    /// Why the relay refused the last frame
+    Error { message: String },
+}
+
+/// What nodes forward to each other through the relay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RelayPayload {
+    /// Open a session, with the handshake data a `/p2p` connection authenticates with
+    Connect { auth: JsonValue },
+    /// A P2P message, sealed if the session is encrypted
+    Message { message: JsonValue },
+}
+
+impl RelayPayload {
+    /// The payload as msgpack, keyed like its JSON
+    pub fn to_msgpack(&self) -> Value {
+        let json = serde_json::to_value(self).expect("payloads serialize");
+        rmpv::ext::to_value(json).expect("JSON converts to msgpack")
+    }
+
+    /// Read a payload forwarded by another node
+    pub fn from_msgpack(value: Value) -> Result<Self, String> {
+        let json = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        serde_json::from_value(json).map_err(|e| e.to_string())
+    }
+}
+
+/// Encode a frame as a msgpack map keyed like its JSON
+pub fn encode_frame(frame: &RelayFrame) -> Vec<u8> {
+    let fields = match frame {
+        RelayFrame::Register { node_id } => vec![("type", "register".into()), ("node_id", node_id.as_str().into())],
+        RelayFrame::Registered { node_id, nodes } => vec![
+            ("type", "registered".into()),
+            ("node_id", node_id.as_str().into()),
+            ("nodes", Value::Array(nodes.iter().map(|node| node.as_str().into()).collect())),
+        ],
+        RelayFrame::NodeJoined { node_id } => vec![("type", "node_joined".into()), ("node_id", node_id.as_str().into())],
+        RelayFrame::NodeLeft { node_id } => vec![("type", "node_left".into()), ("node_id", node_id.as_str().into())],
+        RelayFrame::Forward { to, payload } => {
+            vec![("type", "forward".into()), ("to", to.as_str().into()), ("payload", payload.clone())]
+        }
+        RelayFrame::Deliver { from, seq, payload } => vec![
+            ("type", "deliver".into()),
+            ("from", from.as_str().into()),
+            ("seq", (*seq).into()),
+            ("payload", payload.clone()),
+        ],
+        RelayFrame::Ack { seq } => vec![("type", "ack".into()), ("seq", (*seq).into())],
+        RelayFrame::Error { message } => vec![("type", "error".into()), ("message", message.as_str().into())],
+    };
+    let map = Value::Map(fields.into_iter().map(|(key, value)| (key.into(), value)).collect());
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &map).expect("writing to a Vec can't fail");
+    bytes
+}
+
+/// Decode a frame from a binary message
+pub fn decode_frame(bytes: &[u8]) -> Result<RelayFrame, String> {
+    let value = rmpv::decode::read_value(&mut &bytes[..]).map_err(|e| format!("Invalid msgpack: {e}"))?;
+    rmpv::ext::from_value(value).map_err(|e| format!("Invalid frame: {e}"))
+}
+
+/// Encode a frame as the binary messages to send, splitting a forwarded
+/// payload into fragments if the frame would be too big.
+///
+/// Returns no messages for a payload too big even to split.
+pub fn encode_frames(frame: RelayFrame, fragment_id: u64) -> Vec<Vec<u8>> {
+    let bytes = encode_frame(&frame);
+    let RelayFrame::Forward { to, payload } = frame else {
+        return vec![bytes];
+    };
+    if bytes.len() <= MAX_FRAME_BYTES {
+        return vec![bytes];
+    }
+
+    let mut data = Vec::new();
+    rmpv::encode::write_value(&mut data, &payload).expect("writing to a Vec can't fail");
+    let count = data.len().div_ceil(FRAGMENT_BYTES);
+    if count > MAX_FRAGMENTS {
+        warn!(peer_id = to, bytes = data.len(), "Payload is too big to send through the relay");
+        return Vec::new();
+    }
+    data.chunks(FRAGMENT_BYTES)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let fragment = Value::Map(vec![
+                ("kind".into(), "fragment".into()),
+                ("id".into(), fragment_id.into()),
+                ("index".into(), (index as u64).into()),
+                ("count".into(), (count as u64).into()),
+                ("data".into(), Value::Binary(chunk.to_vec())),
+            ]);
+            encode_frame(&RelayFrame::Forward { to: to.clone(), payload: fragment })
+        })
+        .collect()
+}
+
+/// A payload still arriving in fragments
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    started: Instant,
+}
+
+/// Puts fragmented payloads back together
+#[derive(Default)]
+pub struct Reassembly {
+    /// Payloads still arriving, by sender and fragment ID
+    partial: HashMap<(String, u64), Partial>,
+}
+
+impl Reassembly {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a frame from the relay, returning it once its payload is whole.
+    ///
+    /// Fragments of payloads that don't complete within a minute are dropped.
+    pub fn receive(&mut self, frame: RelayFrame) -> Option<RelayFrame> {
+        let RelayFrame::Deliver { from, seq, payload } = frame else {
+            return Some(frame);
+        };
+        if payload["kind"].as_str() != Some("fragment") {
+            return Some(RelayFrame::Deliver { from, seq, payload });
+        }
+
+        self.partial.retain(|_, partial| partial.started.elapsed() < FRAGMENT_TIMEOUT);
+        let (Some(id), Some(index), Some(count), Some(data)) = (
+            payload["id"].as_u64(),
+            payload["index"].as_u64().map(|index| index as usize),
+            payload["count"].as_u64().map(|count| count as usize),
+            payload["data"].as_slice(),
+        ) else {
+            debug!(sender_id = from, "Ignoring malformed fragment");
+            return None;
+        };
+        if count > MAX_FRAGMENTS || index >= count {
+            debug!(sender_id = from, index, count, "Ignoring fragment out of range");
+            return None;
+        }
+
+        let key = (from, id);
+        let partial = self.partial.entry(key.clone()).or_insert_with(|| Partial {
+            chunks: vec![None; count],
+            started: Instant::now(),
+        });
+        if partial.chunks.len() != count {
+            debug!(sender_id = key.0, "Ignoring fragment with a different count");
+            return None;
+        }
+        partial.chunks[index] = Some(data.to_vec());
+        if partial.chunks.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let partial = self.partial.remove(&key)?;
+        let data: Vec<u8> = partial.chunks.into_iter().flatten().flatten().collect();
+        match rmpv::decode::read_value(&mut data.as_slice()) {
+            Ok(payload) => Some(RelayFrame::Deliver { from: key.0, seq, payload }),
+            Err(e) => {
+                debug!(sender_id = key.0, "Ignoring reassembled payload: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Open a WebSocket to the relay at `url`, presenting `token` if there is one.
+///
+/// Frames sent on the returned sender go to the relay, and frames from the
+/// relay arrive on the receiver, which closes when the connection does.
+/// Deliveries are acknowledged as they arrive, so the relay stops keeping them.
+pub async fn connect(
+    url: &str,
+    token: Option<&str>,
+) -> Result<(mpsc::UnboundedSender<RelayFrame>, mpsc::UnboundedReceiver<RelayFrame>), String> {
+    let mut request = Url::parse(url).map_err(|e| format!("Invalid relay URL {url}: {e}"))?;
+    if let Some(token) = token {
+        request.query_pairs_mut().append_pair("token", token);
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request.as_str())
+        .await
+        .map_err(|e| format!("Failed to connect to relay {url}: {e}"))?;
+    let (mut sink, mut stream) = socket.split();
+    let (outgoing, mut to_send) = mpsc::unbounded_channel::<RelayFrame>();
+    let (received, incoming) = mpsc::unbounded_channel();
+    let acks = outgoing.clone();
+
+    tokio::spawn(async move {
+        let mut fragment_id: u64 = rand::random();
+        'send: while let Some(frame) = to_send.recv().await {
+            fragment_id = fragment_id.wrapping_add(1);
+            for bytes in encode_frames(frame, fragment_id) {
+                if sink.send(Message::binary(bytes)).await.is_err() {
+                    break 'send;
+                }
+            }
+        }
+        sink.close().await.ok();
+    });
+    tokio::spawn(async move {
+        let mut reassembly = Reassembly::new();
+        while let Some(Ok(message)) = stream.next().await {
+            let frame = match message {
+                Message::Binary(bytes) => decode_frame(&bytes),
+                Message::Text(text) => serde_json::from_str::<RelayFrame>(&text).map_err(|e| e.to_string()),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if let Ok(RelayFrame::Deliver { seq, .. }) = &frame
+                && *seq > 0
+            {
+                acks.send(RelayFrame::Ack { seq: *seq }).ok();
+            }
+            match frame.map(|frame| reassembly.receive(frame)) {
+                Ok(Some(frame)) => {
+                    if received.send(frame).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => debug!("Ignoring invalid frame from relay: {e}"),
+            }
+        }
+    });
+
+    Ok((outgoing, incoming))
+}
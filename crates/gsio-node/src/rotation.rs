@@ -0,0 +1,114 @@
+//! Key rotation.
+//!
+//! The holder of a key retires it by having a [`KeyRotation`] statement,
+//! signed with the old key and naming the new one, recorded on the chain.
+//! Once it is there the ledger refuses the old key for entries and entry
+//! data signed after it, and write ACLs let the new key write where the old
+//! one could. The statement stays on the chain, so the link from the old
+//! key to the new one can be checked by anyone replaying it.
+//!
+//! A node's key is also its ID, so a node that rotates its key rejoins the
+//! network under a new ID once restarted with the new key.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{info, warn};
+
+use crate::ledger::LedgerEntry;
+
+pub use gsio_types::{KeyRotation, KEY_ROTATION_KEY};
+
+/// A key retired by a rotation on the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetiredKey {
+    /// Hex-encoded key that replaced it
+    pub new_key: String,
+    /// ID of the entry recording the rotation
+    pub entry_id: String,
+}
+
+/// Keys retired by rotations on the chain, by their lowercase hex encoding
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyRotations {
+    retired: BTreeMap<String, RetiredKey>,
+}
+
+impl KeyRotations {
+    /// Create a set with no rotations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `key` was rotated away
+    pub fn is_retired(&self, key: &str) -> bool {
+        self.retired.contains_key(&key.to_lowercase())
+    }
+
+    /// The rotation that retired `key`, if any
+    pub fn retired(&self, key: &str) -> Option<&RetiredKey> {
+        self.retired.get(&key.to_lowercase())
+    }
+
+    /// The key that `key` was last rotated to, following rotations of the
+    /// keys it was rotated to, or `key` itself if it wasn't rotated
+    pub fn current(&self, key: &str) -> String {
+        let mut key = key.to_lowercase();
+        // Rotating to a retired key is refused, so this can't loop
+        while let Some(retired) = self.retired.get(&key) {
+            key = retired.new_key.to_lowercase();
+        }
+        key
+    }
+
+    /// Every retired key with what replaced it
+    pub fn all(&self) -> &BTreeMap<String, RetiredKey> {
+        &self.retired
+    }
+
+    /// The rotation in entry data, if any, checked against the rotations
+    /// so far: it has to be signed by the old key, and neither key may have
+    /// been retired already
+    pub fn check(&self, data: &JsonValue) -> Result<Option<KeyRotation>, String> {
+        let Some(rotation) = KeyRotation::from_data(data) else {
+            return Ok(None);
+        };
+        let rotation = rotation?;
+        rotation.verify()?;
+        if let Some(retired) = self.retired(&rotation.old_key) {
+            return Err(format!("Key {} was already rotated to {}", rotation.old_key, retired.new_key));
+        }
+        if self.is_retired(&rotation.new_key) {
+            return Err(format!("Key {} was retired and can't be rotated to", rotation.new_key));
+        }
+        Ok(Some(rotation))
+    }
+
+    /// Apply the rotation an entry on the chain records, if any.
+    ///
+    /// Rotations that fail [`KeyRotations::check`] are ignored.
+    pub fn apply(&mut self, entry: &LedgerEntry) {
+        match self.check(&entry.data) {
+            Ok(None) => {}
+            Ok(Some(rotation)) => {
+                info!(entry_id = entry.id, old_key = rotation.old_key, new_key = rotation.new_key, "Key rotated");
+                self.retired.insert(
+                    rotation.old_key.to_lowercase(),
+                    RetiredKey { new_key: rotation.new_key.to_lowercase(), entry_id: entry.id.clone() },
+                );
+            }
+            Err(e) => warn!(entry_id = entry.id, "Ignoring key rotation: {}", e),
+        }
+    }
+
+    /// The retired keys and what replaced them, as carried in snapshots
+    pub fn to_map(&self) -> BTreeMap<String, RetiredKey> {
+        self.retired.clone()
+    }
+
+    /// Restore the rotations a snapshot carries
+    pub fn from_map(retired: BTreeMap<String, RetiredKey>) -> Self {
+        Self { retired: retired.into_iter().map(|(key, retired)| (key.to_lowercase(), retired)).collect() }
+    }
+}
@@ -0,0 +1,386 @@
+//! Entry kinds and the JSON Schemas their payloads have to match.
+//!
+//! Typed entries carry their data as `{"kind": "<kind>", "payload": ...}`.
+//! Kinds are registered in the `[schemas]` section of the config file or
+//! through `PUT /api/schemas/{kind}`, optionally with a schema; a node then
+//! rejects entries of that kind whose payload doesn't match it, whether they
+//! come from a client or from a peer. Registrations aren't replicated, so each
+//! node only enforces the kinds registered with it.
+//!
+//! Schemas support a subset of JSON Schema: `type`, `properties`, `required`,
+//! `additionalProperties`, `items`, `enum`, `const`, `minimum`, `maximum`,
+//! `exclusiveMinimum`, `exclusiveMaximum`, `minLength`, `maxLength`,
+//! `minItems` and `maxItems`, along with annotations such as `title`. Schemas
+//! using any other keyword are refused rather than partially enforced.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::validation::{ErrorCode, ValidationError, ValidationPolicy};
+
+/// Longest kind name accepted
+const MAX_KIND_LENGTH: usize = 64;
+
+const TYPES: [&str; 7] = ["null", "boolean", "object", "array", "number", "string", "integer"];
+
+/// Keywords that describe a schema without constraining values
+const ANNOTATIONS: [&str; 7] = ["$schema", "$id", "$comment", "title", "description", "default", "examples"];
+
+/// A kind in the `[schemas]` section; the schema is given inline or as a file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KindConfig {
+    pub schema: Option<JsonValue>,
+    /// JSON file holding the schema
+    pub schema_file: Option<PathBuf>,
+}
+
+/// The `[schemas]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemaConfig {
+    /// Whether entries without a kind are rejected
+    pub require_kind: bool,
+    /// Whether entries of kinds that aren't registered are accepted
+    pub allow_unknown_kinds: bool,
+    /// Kinds registered on startup
+    pub kinds: BTreeMap<String, KindConfig>,
+}
+
+impl Default for SchemaConfig {
+    fn default() -> Self {
+        Self {
+            require_kind: false,
+            allow_unknown_kinds: true,
+            kinds: BTreeMap::new(),
+        }
+    }
+}
+
+/// A JSON Schema that has been checked to only use supported keywords
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonSchema(JsonValue);
+
+impl JsonSchema {
+    /// Check that `schema` only uses supported keywords
+    pub fn parse(schema: JsonValue) -> Result<Self, String> {
+        check_schema(&schema, "schema")?;
+        Ok(Self(schema))
+    }
+
+    /// The schema as JSON
+    pub fn as_json(&self) -> &JsonValue {
+        &self.0
+    }
+
+    /// Check `value` against the schema, naming the offending part `path` in errors
+    pub fn validate(&self, value: &JsonValue, path: &str) -> Result<(), String> {
+        validate_value(&self.0, value, path)
+    }
+}
+
+fn check_schema(schema: &JsonValue, path: &str) -> Result<(), String> {
+    let object = match schema {
+        JsonValue::Bool(_) => return Ok(()),
+        JsonValue::Object(object) => object,
+        _ => return Err(format!("{path} must be an object or a boolean")),
+    };
+
+    for (keyword, value) in object {
+        let valid = match keyword.as_str() {
+            "type" => match value {
+                JsonValue::String(name) => TYPES.contains(&name.as_str()),
+                JsonValue::Array(names) => names.iter().all(|name| name.as_str().is_some_and(|name| TYPES.contains(&name))),
+                _ => false,
+            },
+            "properties" => match value.as_object() {
+                Some(properties) => {
+                    for (name, property) in properties {
+                        check_schema(property, &format!("{path}.properties.{name}"))?;
+                    }
+                    true
+                }
+                None => false,
+            },
+            "additionalProperties" | "items" => {
+                check_schema(value, &format!("{path}.{keyword}"))?;
+                true
+            }
+            "required" => value.as_array().is_some_and(|names| names.iter().all(JsonValue::is_string)),
+            "enum" => value.is_array(),
+            "const" => true,
+            "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" => value.is_number(),
+            "minLength" | "maxLength" | "minItems" | "maxItems" => value.is_u64(),
+            annotation if ANNOTATIONS.contains(&annotation) => true,
+            _ => return Err(format!("Unsupported keyword {keyword} in {path}")),
+        };
+        if !valid {
+            return Err(format!("Invalid {keyword} in {path}"));
+        }
+    }
+    Ok(())
+}
+
+fn matches_type(name: &str, value: &JsonValue) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => false,
+    }
+}
+
+/// Check a value against a schema that has passed [`check_schema`]
+fn validate_value(schema: &JsonValue, value: &JsonValue, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        JsonValue::Bool(true) => return Ok(()),
+        JsonValue::Object(schema) => schema,
+        _ => return Err(format!("{path} isn't allowed")),
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            JsonValue::Array(names) => names.iter().filter_map(JsonValue::as_str).collect(),
+            name => name.as_str().into_iter().collect(),
+        };
+        if !names.iter().any(|name| matches_type(name, value)) {
+            return Err(format!("{path} must be of type {}", names.join(" or ")));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(JsonValue::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!("{path} must be one of {}", JsonValue::Array(allowed.clone())));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        return Err(format!("{path} must be {expected}"));
+    }
+
+    if let Some(number) = value.as_f64() {
+        let bound = |keyword: &str| schema.get(keyword).and_then(JsonValue::as_f64);
+        let checks = [
+            ("minimum", bound("minimum").is_some_and(|min| number < min), "at least"),
+            ("maximum", bound("maximum").is_some_and(|max| number > max), "at most"),
+            ("exclusiveMinimum", bound("exclusiveMinimum").is_some_and(|min| number <= min), "greater than"),
+            ("exclusiveMaximum", bound("exclusiveMaximum").is_some_and(|max| number >= max), "less than"),
+        ];
+        if let Some((keyword, _, relation)) = checks.iter().find(|(_, failed, _)| *failed) {
+            return Err(format!("{path} must be {relation} {}", schema[*keyword]));
+        }
+    }
+
+    let size = |keyword: &str| schema.get(keyword).and_then(JsonValue::as_u64).map(|n| n as usize);
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count();
+        if size("minLength").is_some_and(|min| length < min) {
+            return Err(format!("{path} must be at least {} characters long", schema["minLength"]));
+        }
+        if size("maxLength").is_some_and(|max| length > max) {
+            return Err(format!("{path} must be at most {} characters long", schema["maxLength"]));
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if size("minItems").is_some_and(|min| items.len() < min) {
+            return Err(format!("{path} must have at least {} items", schema["minItems"]));
+        }
+        if size("maxItems").is_some_and(|max| items.len() > max) {
+            return Err(format!("{path} must have at most {} items", schema["maxItems"]));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                validate_value(item_schema, item, &format!("{path}[{index}]"))?;
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(JsonValue::as_object);
+        for name in schema.get("required").and_then(JsonValue::as_array).into_iter().flatten() {
+            if let Some(name) = name.as_str()
+                && !object.contains_key(name)
+            {
+                return Err(format!("Missing field {path}.{name}"));
+            }
+        }
+        for (name, field) in object {
+            let field_path = format!("{path}.{name}");
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => validate_value(property, field, &field_path)?,
+                None => {
+                    if let Some(additional) = schema.get("additionalProperties") {
+                        validate_value(additional, field, &field_path)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Why a kind couldn't be registered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The kind name or its schema isn't valid
+    Invalid(String),
+    /// The kind is already registered with a different schema
+    Conflict(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Invalid(message) => write!(f, "{message}"),
+            SchemaError::Conflict(kind) => write!(f, "Kind {kind} is already registered with a different schema"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// The entry kinds a node knows, checked against every entry it adds.
+///
+/// A kind's schema can't be changed once registered, so entries that were
+/// accepted under it stay valid.
+#[derive(Debug)]
+pub struct SchemaRegistry {
+    require_kind: bool,
+    allow_unknown_kinds: bool,
+    kinds: RwLock<BTreeMap<String, Option<JsonSchema>>>,
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self::new(false, true)
+    }
+}
+
+impl SchemaRegistry {
+    /// Create an empty registry
+    pub fn new(require_kind: bool, allow_unknown_kinds: bool) -> Self {
+        Self {
+            require_kind,
+            allow_unknown_kinds,
+            kinds: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Create a registry holding the kinds in `config`, reading schema files as needed
+    pub fn from_config(config: &SchemaConfig) -> Result<Self, String> {
+        let registry = Self::new(config.require_kind, config.allow_unknown_kinds);
+        for (kind, kind_config) in &config.kinds {
+            let schema = match (&kind_config.schema, &kind_config.schema_file) {
+                (Some(_), Some(_)) => return Err(format!("Kind {kind} has both schema and schema_file")),
+                (Some(schema), None) => Some(schema.clone()),
+                (None, Some(path)) => {
+                    let contents = std::fs::read_to_string(path)
+                        .map_err(|e| format!("Failed to read schema file {}: {e}", path.display()))?;
+                    let schema = serde_json::from_str(&contents)
+                        .map_err(|e| format!("Invalid schema file {}: {e}", path.display()))?;
+                    Some(schema)
+                }
+                (None, None) => None,
+            };
+            registry.register(kind, schema).map_err(|e| e.to_string())?;
+        }
+        Ok(registry)
+    }
+
+    /// Register `kind`, with a schema its payloads must match if given.
+    ///
+    /// Returns whether the kind is new; registering a kind again with the
+    /// same schema does nothing.
+    pub fn register(&self, kind: &str, schema: Option<JsonValue>) -> Result<bool, SchemaError> {
+        check_kind(kind).map_err(SchemaError::Invalid)?;
+        let schema = schema
+            .map(JsonSchema::parse)
+            .transpose()
+            .map_err(|e| SchemaError::Invalid(format!("Invalid schema for kind {kind}: {e}")))?;
+
+        let mut kinds = self.kinds.write().unwrap();
+        match kinds.get(kind) {
+            Some(existing) if *existing == schema => Ok(false),
+            Some(_) => Err(SchemaError::Conflict(kind.to_string())),
+            None => {
+                kinds.insert(kind.to_string(), schema);
+                Ok(true)
+            }
+        }
+    }
+
+    /// The schema registered for `kind`; `Some(None)` if it has none
+    pub fn get(&self, kind: &str) -> Option<Option<JsonValue>> {
+        let kinds = self.kinds.read().unwrap();
+        kinds.get(kind).map(|schema| schema.as_ref().map(|schema| schema.as_json().clone()))
+    }
+
+    /// Every registered kind along with its schema, if any
+    pub fn kinds(&self) -> BTreeMap<String, Option<JsonValue>> {
+        let kinds = self.kinds.read().unwrap();
+        kinds
+            .iter()
+            .map(|(kind, schema)| (kind.clone(), schema.as_ref().map(|schema| schema.as_json().clone())))
+            .collect()
+    }
+
+    /// Whether entries without a kind are rejected
+    pub fn requires_kind(&self) -> bool {
+        self.require_kind
+    }
+
+    /// Whether entries of kinds that aren't registered are accepted
+    pub fn allows_unknown_kinds(&self) -> bool {
+        self.allow_unknown_kinds
+    }
+}
+
+/// Kinds are short names made of letters, digits, `_`, `-` and `.`
+fn check_kind(kind: &str) -> Result<(), String> {
+    let valid_chars = kind.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if kind.is_empty() || kind.len() > MAX_KIND_LENGTH || !valid_chars {
+        return Err(format!("Invalid kind {kind:?}"));
+    }
+    Ok(())
+}
+
+impl ValidationPolicy for SchemaRegistry {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        let kind = match data.get("kind") {
+            Some(JsonValue::String(kind)) => kind,
+            Some(_) => return Err(ValidationError::new(ErrorCode::InvalidFieldType, "Field kind must be of type string")),
+            None if self.require_kind => {
+                return Err(ValidationError::new(ErrorCode::MissingField, "Missing field kind"));
+            }
+            None => return Ok(()),
+        };
+
+        let kinds = self.kinds.read().unwrap();
+        match kinds.get(kind.as_str()) {
+            Some(Some(schema)) => schema
+                .validate(data.get("payload").unwrap_or(&JsonValue::Null), "payload")
+                .map_err(|e| ValidationError::new(ErrorCode::SchemaMismatch, format!("Entry of kind {kind} doesn't match its schema: {e}"))),
+            Some(None) => Ok(()),
+            None if self.allow_unknown_kinds => Ok(()),
+            None => Err(ValidationError::new(ErrorCode::UnknownKind, format!("Unknown entry kind {kind}"))),
+        }
+    }
+}
+
+/// A registered kind as returned by `GET /api/schemas/{kind}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindSchema {
+    pub kind: String,
+    pub schema: Option<JsonValue>,
+}
@@ -0,0 +1,222 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Future that resolves when the service manager asks the node to stop
+pub type Shutdown = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Wait for Ctrl-C, or SIGTERM on Unix, when running outside a service manager
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            // Without a handler the node can only be killed, so never resolve
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Send a state string (e.g. `READY=1`) to the service manager.
+///
+/// Returns `Ok(false)` when the node is not running under a manager that
+/// expects notifications.
+pub fn notify(state: &str) -> io::Result<bool> {
+    #[cfg(windows)]
+    if windows::notify(state) {
+        return Ok(true);
+    }
+
+    match std::env::var("NOTIFY_SOCKET") {
+        Ok(socket_path) if !socket_path.is_empty() => {
+            send_notify(&socket_path, state)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Tell the service manager that the node has finished starting up
+pub fn notify_ready() -> io::Result<bool> {
+    notify("READY=1\nSTATUS=Accepting connections")
+}
+
+/// Tell the service manager that the node is shutting down
+pub fn notify_stopping() -> io::Result<bool> {
+    notify("STOPPING=1")
+}
+
+/// Send a keep-alive ping to the service manager watchdog
+pub fn notify_watchdog() -> io::Result<bool> {
+    notify("WATCHDOG=1")
+}
+
+/// Get the interval at which watchdog pings should be sent, if the watchdog is enabled
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    // The watchdog may be meant for another process in our tree
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok()? != std::process::id()
+    {
+        return None;
+    }
+
+    // Ping at half the timeout so a single late tick doesn't trip the watchdog
+    Some(Duration::from_micros(usec / 2)).filter(|d| !d.is_zero())
+}
+
+#[cfg(unix)]
+fn send_notify(socket_path: &str, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+
+    // A leading '@' denotes a socket in the Linux abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket_path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(state.as_bytes(), socket_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notify(_socket_path: &str, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Windows service control manager integration
+#[cfg(windows)]
+pub mod windows {
+    use super::Shutdown;
+    use std::ffi::OsString;
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Duration;
+    use tracing::error;
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+        service_dispatcher,
+    };
+
+    /// Name the service is registered under with the service control manager
+    pub const SERVICE_NAME: &str = "gsio-node";
+
+    /// Entry point that runs the node until the shutdown future resolves
+    pub type NodeMain = fn(Shutdown) -> Result<(), crate::node::NodeError>;
+
+    static NODE_MAIN: OnceLock<NodeMain> = OnceLock::new();
+    static STATUS_HANDLE: OnceLock<ServiceStatusHandle> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Hand control to the service dispatcher, running `node_main` as the service body
+    pub fn run(node_main: NodeMain) -> windows_service::Result<()> {
+        NODE_MAIN.get_or_init(|| node_main);
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+    }
+
+    /// Translate sd_notify-style states into service status updates
+    pub(super) fn notify(state: &str) -> bool {
+        let Some(handle) = STATUS_HANDLE.get() else {
+            return false;
+        };
+
+        let status = if state.contains("READY=1") {
+            status(ServiceState::Running)
+        } else if state.contains("STOPPING=1") {
+            status(ServiceState::StopPending)
+        } else {
+            // The service control manager has no watchdog equivalent
+            return true;
+        };
+
+        handle.set_service_status(status).is_ok()
+    }
+
+    fn status(state: ServiceState) -> ServiceStatus {
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::from_secs(30),
+            process_id: None,
+        }
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            error!("Windows service failed: {e}");
+        }
+    }
+
+    fn run_service() -> windows_service::Result<()> {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let stop_tx = Mutex::new(Some(stop_tx));
+
+        let handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(tx) = stop_tx.lock().unwrap().take() {
+                    tx.send(()).ok();
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+        handle.set_service_status(status(ServiceState::StartPending))?;
+        let handle = *STATUS_HANDLE.get_or_init(|| handle);
+
+        let shutdown: Shutdown = Box::pin(async move {
+            stop_rx.await.ok();
+        });
+        let exit_code = match NODE_MAIN.get() {
+            Some(node_main) => match node_main(shutdown) {
+                Ok(()) => 0,
+                Err(e) => {
+                    error!("Node exited with error: {e}");
+                    1
+                }
+            },
+            None => 1,
+        };
+
+        handle.set_service_status(ServiceStatus {
+            exit_code: ServiceExitCode::Win32(exit_code),
+            ..status(ServiceState::Stopped)
+        })
+    }
+}
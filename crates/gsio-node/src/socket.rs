@@ -0,0 +1,295 @@
+//! Handlers for the root `/` Socket.IO namespace used by clients
+
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use gsio_types::{Filter, LedgerEntry};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Map, Value as JsonValue};
+use socketioxide::{
+    extract::{AckSender, Data, SocketRef, TryData},
+    handler::ConnectHandler,
+    SocketIo,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::auth::Authenticator;
+use crate::error::GsioNodeError;
+use crate::p2p::P2PManager;
+use crate::ratelimit::{retry_after_secs, Client, RateLimiter};
+use crate::validation::ErrorCode;
+
+/// Register the client-facing handlers on the root namespace, holding
+/// `add_ledger_entry` to `limiter` if one is given
+pub fn register_root_namespace(io: &SocketIo, p2p: Arc<P2PManager>, limiter: Option<Arc<RateLimiter>>) {
+    let p2p_clone = p2p.clone();
+    io.ns("/", move |s, d| on_connect(s, d, p2p_clone.clone(), limiter.clone()));
+}
+
+/// Register the client-facing handlers on the root namespace, refusing
+/// connections whose auth data doesn't carry a valid token
+pub fn register_authenticated_root_namespace(
+    io: &SocketIo,
+    p2p: Arc<P2PManager>,
+    auth: Arc<Authenticator>,
+    limiter: Option<Arc<RateLimiter>>,
+) {
+    let p2p_clone = p2p.clone();
+    let handler = move |s, d| on_connect(s, d, p2p_clone.clone(), limiter.clone());
+    io.ns(
+        "/",
+        handler.with(move |socket: SocketRef, Data(data): Data<JsonValue>| {
+            let result = auth.check_socket_auth(&data);
+            if let Err(e) = &result {
+                warn!(?socket.id, "Refused Socket.IO client: {e}");
+            }
+            async move { result }
+        }),
+    );
+}
+
+async fn on_connect(
+    socket: SocketRef,
+    Data(data): Data<JsonValue>,
+    p2p: Arc<P2PManager>,
+    limiter: Option<Arc<RateLimiter>>,
+) {
+    info!(ns = socket.ns(), ?socket.id, "Socket.IO client connected");
+    let client = Client::from_socket(&socket, &data);
+    register_basic_handlers(&socket);
+    register_ledger_handlers(&socket, p2p, limiter.map(|limiter| (limiter, client))).await;
+    // Clients treat "auth" as the signal that the handlers are ready
+    socket.emit("auth", &data).ok();
+}
+
+fn register_basic_handlers(socket: &SocketRef) {
+    socket.on("message", |socket: SocketRef, Data(d): Data<JsonValue>| async move {
+        socket.emit("message-back", &d).ok();
+    });
+    socket.on("ping", |socket: SocketRef, Data(d): Data<JsonValue>| async move {
+        socket.emit("pong", &d).ok();
+    });
+    socket.on(
+        "message-with-ack",
+        |Data(d): Data<JsonValue>, ack: AckSender| async move {
+            ack.send(&d).ok();
+        },
+    );
+}
+
+async fn register_ledger_handlers(socket: &SocketRef, p2p: Arc<P2PManager>, limit: Option<(Arc<RateLimiter>, Client)>) {
+    let add_clone = p2p.clone();
+    socket.on(
+        "add_ledger_entry",
+        move |socket: SocketRef, Data(d): Data<JsonValue>| {
+            let p2p = add_clone.clone();
+            let limit = limit.clone();
+            async move {
+                if let Some((limiter, client)) = limit
+                    && let Err(wait) = limiter.check(&client)
+                {
+                    let error = json!({
+                        "error": "Too many requests",
+                        "code": ErrorCode::RateLimited,
+                        "retry_after": retry_after_secs(wait)
+                    });
+                    socket.emit("error", &error).ok();
+                    return;
+                }
+                handle_add_entry(socket, p2p, d).await
+            }
+        },
+    );
+
+    let get_clone = p2p.clone();
+    socket.on("get_ledger", move |socket: SocketRef| {
+        let p2p = get_clone.clone();
+        async move {
+            let entries = p2p.rehydrate(p2p.ledger.get_entries()).await;
+            socket.emit("ledger_entries", &json!(entries)).ok();
+        }
+    });
+
+    let page_clone = p2p.clone();
+    socket.on(
+        "get_ledger_page",
+        move |socket: SocketRef, Data(d): Data<JsonValue>| {
+            let p2p = page_clone.clone();
+            async move {
+                let Some(page) = parse_request::<PageRequest>(&socket, d) else { return };
+                let entries = p2p.rehydrate(p2p.ledger.get_entries_paginated(page.offset, page.limit)).await;
+                socket.emit("ledger_entries", &json!(entries)).ok();
+            }
+        },
+    );
+
+    let since_clone = p2p.clone();
+    socket.on(
+        "get_entries_since",
+        move |socket: SocketRef, Data(d): Data<JsonValue>| {
+            let p2p = since_clone.clone();
+            async move {
+                let Some(request) = parse_request::<SinceRequest>(&socket, d) else { return };
+                let entries = p2p.rehydrate(p2p.ledger.get_entries_since(request.since)).await;
+                socket.emit("ledger_entries", &json!(entries)).ok();
+            }
+        },
+    );
+
+    let entry_clone = p2p.clone();
+    socket.on(
+        "get_entry",
+        move |socket: SocketRef, Data(d): Data<JsonValue>| {
+            let p2p = entry_clone.clone();
+            async move {
+                let Some(request) = parse_request::<EntryRequest>(&socket, d) else { return };
+                // A missing entry is answered with null rather than an error
+                let entry = match p2p.find_entry(&request.id).await {
+                    Some(entry) => Some(p2p.rehydrate_entry(entry).await),
+                    None => None,
+                };
+                socket.emit("ledger_entry", &json!(entry)).ok();
+            }
+        },
+    );
+
+    let nodes_clone = p2p.clone();
+    socket.on("get_known_nodes", move |socket: SocketRef| {
+        let p2p = nodes_clone.clone();
+        async move {
+            let nodes = p2p.ledger.get_known_nodes();
+            socket.emit("known_nodes", &json!({ "nodes": nodes })).ok();
+        }
+    });
+
+    let subscribe_clone = p2p.clone();
+    socket.on(
+        "subscribe_entries",
+        move |socket: SocketRef, TryData(d): TryData<JsonValue>| {
+            let p2p = subscribe_clone.clone();
+            // Older clients subscribe without a payload
+            let d = d.unwrap_or_default();
+            async move {
+                let Some(filters) = parse_subscription_filter(&socket, d) else { return };
+                handle_subscribe_entries(socket, p2p, filters)
+            }
+        },
+    );
+
+    let retention_clone = p2p.clone();
+    socket.on("get_retention", move |socket: SocketRef| {
+        let p2p = retention_clone.clone();
+        async move {
+            let retention = p2p.ledger.get_retention_info();
+            socket.emit("retention", &json!(retention)).ok();
+        }
+    });
+}
+
+/// Filters of the socket's entry subscription, replaced when it subscribes again
+#[derive(Clone)]
+struct EntrySubscription(Arc<RwLock<Vec<Filter>>>);
+
+/// Read a subscription payload like `{ "kind": "sensor" }`, which only lets
+/// through entries whose data has each of these fields with the given value.
+/// `null` or `{}` lets every entry through.
+fn parse_subscription_filter(socket: &SocketRef, data: JsonValue) -> Option<Vec<Filter>> {
+    let fields = match data {
+        JsonValue::Null => Map::new(),
+        JsonValue::Object(fields) => fields,
+        _ => {
+            emit_error(socket, &GsioNodeError::BadRequest("subscription filter must be an object".to_string()));
+            return None;
+        }
+    };
+    Some(fields.into_iter().map(|(field, value)| Filter::eq(format!("$[\"{field}\"]"), value)).collect())
+}
+
+/// Push entries matching `filters` to the socket as they are appended to
+/// the chain, until it disconnects
+fn handle_subscribe_entries(socket: SocketRef, p2p: Arc<P2PManager>, filters: Vec<Filter>) {
+    if let Some(EntrySubscription(current)) = socket.extensions.get::<EntrySubscription>() {
+        *current.write().unwrap() = filters;
+    } else {
+        let current = Arc::new(RwLock::new(filters));
+        socket.extensions.insert(EntrySubscription(current.clone()));
+
+        // Subscribe before acknowledging so no entry falls in between
+        let mut entries = p2p.ledger.subscribe();
+        let socket = socket.clone();
+        tokio::spawn(async move {
+            loop {
+                match entries.recv().await {
+                    Ok(entry) => {
+                        let Some(entry) = matching_entry(&p2p, &current, entry).await else { continue };
+                        if socket.emit("entry_appended", &json!(entry)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(?socket.id, "Subscriber fell behind, skipped {skipped} entries");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+    socket.emit("entries_subscribed", &json!({})).ok();
+}
+
+/// The entry if its data passes every filter. An offloaded entry is checked,
+/// and sent, with its data fetched back from the blob store.
+async fn matching_entry(p2p: &P2PManager, filters: &RwLock<Vec<Filter>>, entry: LedgerEntry) -> Option<LedgerEntry> {
+    if filters.read().unwrap().is_empty() {
+        return Some(entry);
+    }
+    let entry = match entry.pending_blob() {
+        Some(_) => p2p.rehydrate_entry(entry).await,
+        None => entry,
+    };
+    filters.read().unwrap().iter().all(|filter| filter.matches(&entry.data)).then_some(entry)
+}
+
+#[derive(Deserialize)]
+struct PageRequest {
+    #[serde(default)]
+    offset: usize,
+    limit: usize,
+}
+
+#[derive(Deserialize)]
+struct SinceRequest {
+    since: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct EntryRequest {
+    id: String,
+}
+
+/// Decode an event payload, answering with an "error" event if it is malformed
+fn parse_request<T: DeserializeOwned>(socket: &SocketRef, data: JsonValue) -> Option<T> {
+    match serde_json::from_value(data) {
+        Ok(request) => Some(request),
+        Err(e) => {
+            emit_error(socket, &GsioNodeError::BadRequest(e.to_string()));
+            None
+        }
+    }
+}
+
+async fn handle_add_entry(socket: SocketRef, p2p: Arc<P2PManager>, data: JsonValue) {
+    match p2p.add_entry_data(data).await {
+        Ok(entry) => {
+            socket.emit("ledger_entry_added", &json!(entry)).ok();
+        }
+        Err(e) => emit_error(&socket, &e),
+    }
+}
+
+/// Answer with an "error" event carrying the message, its code and, for a
+/// read-only node, where to send writes instead
+fn emit_error(socket: &SocketRef, error: &GsioNodeError) {
+    let payload = json!({ "error": error.to_string(), "code": error.code(), "redirect": error.redirect() });
+    socket.emit("error", &payload).ok();
+}
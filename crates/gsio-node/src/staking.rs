@@ -0,0 +1,255 @@
+//! Staking.
+//!
+//! A `Stake` transaction locks part of the sender's balance as stake, and an
+//! `Unstake` transaction starts releasing it: the amount is unbonding for
+//! the `[staking]` section's `unbonding_period`, counted in entries, then
+//! returns to the balance. Both name the sender as recipient.
+//!
+//! The ledger keeps every address's balance and stake by replaying the
+//! transactions on the chain, counting those signed by the sender's key
+//! (given as the entry data's `public_key`) once per sender and nonce, the
+//! way wallets do. A transaction reusing a nonce its sender already spent is
+//! refused as a double spend, and so are staking transactions the sender
+//! can't cover; transfers are otherwise counted but not checked.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ed25519_dalek::{Signature, Verifier};
+use gsio_client::Address;
+use gsio_types::parse_public_key;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{info, warn};
+
+use crate::ledger::LedgerEntry;
+use crate::validation::{ErrorCode, ValidationError};
+
+pub use gsio_types::{StakeInfo, Transaction, TransactionType, Unbonding};
+
+/// The `[staking]` section of the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StakingConfig {
+    /// Number of entries unstaked amounts stay locked for
+    pub unbonding_period: usize,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self { unbonding_period: 100 }
+    }
+}
+
+/// What the ledger knows of an address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakeAccount {
+    pub balance: u64,
+    pub staked: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbonding: Vec<Unbonding>,
+    /// Nonces of the transactions counted for the address as sender
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub nonces: BTreeSet<u64>,
+}
+
+impl StakeAccount {
+    /// Return unbonding amounts released by `height` to the balance
+    fn settle(&mut self, height: usize) {
+        let (released, unbonding) = self.unbonding.drain(..).partition(|u: &Unbonding| u.release_height <= height);
+        self.unbonding = unbonding;
+        for unbonding in released {
+            self.balance = self.balance.saturating_add(unbonding.amount);
+        }
+    }
+}
+
+/// Balances and stakes by canonical address, as of an entry on the chain
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Stakes {
+    accounts: BTreeMap<String, StakeAccount>,
+}
+
+impl Stakes {
+    /// Create a set with no accounts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set where addresses start with `balances`, as allocated by a genesis
+    pub fn with_balances(balances: &BTreeMap<String, u64>) -> Result<Self, String> {
+        let mut accounts = BTreeMap::new();
+        for (address, balance) in balances {
+            let account = StakeAccount { balance: *balance, ..StakeAccount::default() };
+            accounts.insert(canonical_address(address)?, account);
+        }
+        Ok(Self { accounts })
+    }
+
+    /// Whether no address has an allocation or a counted transaction
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// The balance and stake of `address` at `height`
+    pub fn info(&self, address: &str, height: usize) -> Result<StakeInfo, String> {
+        let address = canonical_address(address)?;
+        let mut account = self.accounts.get(&address).cloned().unwrap_or_default();
+        account.settle(height);
+        Ok(StakeInfo { address, balance: account.balance, staked: account.staked, unbonding: account.unbonding })
+    }
+
+    /// Whether `sender` already spent `nonce` on a transaction that counted
+    pub fn is_spent(&self, sender: &str, nonce: u64) -> bool {
+        canonical_address(sender)
+            .ok()
+            .and_then(|sender| self.accounts.get(&sender))
+            .is_some_and(|account| account.nonces.contains(&nonce))
+    }
+
+    /// Check a transaction in entry data that would be added at `height`:
+    /// its nonce mustn't have been spent by the sender, and a staking
+    /// transaction has to be signed by the sender, name the sender as
+    /// recipient and be covered by the sender's balance or stake. Other
+    /// data passes.
+    pub fn check(&self, data: &JsonValue, height: usize) -> Result<(), ValidationError> {
+        let Some(transaction) = parse_transaction(data) else {
+            return Ok(());
+        };
+        let transaction = transaction.map_err(|e| ValidationError::new(ErrorCode::Rejected, e))?;
+        if self.is_spent(&transaction.sender, transaction.nonce) {
+            return Err(double_spend(&transaction));
+        }
+        if matches!(transaction.transaction_type, TransactionType::Transfer) {
+            return Ok(());
+        }
+        let sender = verify_sender(&transaction, data)?;
+        let mut account = self.accounts.get(&sender).cloned().unwrap_or_default();
+        account.settle(height);
+        check_staking(&transaction, &sender, &account)
+    }
+
+    /// Count the transaction an entry on the chain at `height` records, if any.
+    ///
+    /// Transactions that aren't signed by the sender or reuse a nonce are
+    /// ignored, and so are staking transactions that fail [`Stakes::check`].
+    pub fn apply(&mut self, entry: &LedgerEntry, height: usize, config: &StakingConfig) {
+        let Some(Ok(transaction)) = parse_transaction(&entry.data) else {
+            return;
+        };
+        let Ok(sender) = verify_sender(&transaction, &entry.data) else {
+            return;
+        };
+        let account = self.accounts.entry(sender.clone()).or_default();
+        account.settle(height);
+        if account.nonces.contains(&transaction.nonce) {
+            return;
+        }
+        let amount = transaction.amount;
+        match transaction.transaction_type {
+            TransactionType::Transfer => {
+                let Ok(recipient) = canonical_address(&transaction.recipient) else {
+                    return;
+                };
+                account.nonces.insert(transaction.nonce);
+                account.balance = account.balance.saturating_sub(amount.saturating_add(transaction.fee));
+                let recipient = self.accounts.entry(recipient).or_default();
+                recipient.balance = recipient.balance.saturating_add(amount);
+            }
+            TransactionType::Stake | TransactionType::Unstake => {
+                if let Err(e) = check_staking(&transaction, &sender, account) {
+                    warn!(entry_id = entry.id, "Ignoring staking transaction: {}", e.message);
+                    return;
+                }
+                account.nonces.insert(transaction.nonce);
+                account.balance -= transaction.fee;
+                if matches!(transaction.transaction_type, TransactionType::Stake) {
+                    account.balance -= amount;
+                    account.staked += amount;
+                    info!(entry_id = entry.id, address = sender, amount, "Staked");
+                } else {
+                    account.staked -= amount;
+                    let release_height = height.saturating_add(config.unbonding_period);
+                    account.unbonding.push(Unbonding { amount, release_height });
+                    info!(entry_id = entry.id, address = sender, amount, release_height, "Unstaked");
+                }
+            }
+        }
+    }
+}
+
+/// The transaction in entry data of `"type": "transaction"`, if any
+fn parse_transaction(data: &JsonValue) -> Option<Result<Transaction, String>> {
+    if data.get("type").and_then(JsonValue::as_str) != Some("transaction") {
+        return None;
+    }
+    let transaction = data.get("transaction")?;
+    Some(serde_json::from_value(transaction.clone()).map_err(|e| format!("Invalid transaction: {e}")))
+}
+
+/// Check that the transaction is signed by the key in the data's
+/// `public_key` and that the key is the sender's, returning the sender's
+/// canonical address
+fn verify_sender(transaction: &Transaction, data: &JsonValue) -> Result<String, ValidationError> {
+    let (Some(public_key), Some(signature)) =
+        (data.get("public_key").and_then(JsonValue::as_str), transaction.signature.as_deref())
+    else {
+        return Err(ValidationError::new(
+            ErrorCode::MissingSignature,
+            "Staking transactions must be signed and carry the sender's public_key",
+        ));
+    };
+
+    let invalid = |message: &str| ValidationError::new(ErrorCode::InvalidSignature, message);
+    let key = parse_public_key(public_key).map_err(|_| invalid("Invalid public key"))?;
+    let sender = canonical_address(&transaction.sender).map_err(|e| ValidationError::new(ErrorCode::InvalidAddress, e))?;
+    if Address::from_public_key(&key.to_bytes()).to_string() != sender {
+        return Err(invalid("The public key isn't the sender's"));
+    }
+    let signature = hex::decode(signature)
+        .ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+        .ok_or_else(|| invalid("Invalid signature encoding"))?;
+    key.verify(&transaction.signing_bytes(), &signature)
+        .map_err(|_| invalid("Signature doesn't match the transaction"))?;
+    Ok(sender)
+}
+
+/// Check a staking transaction against the sender's account
+fn check_staking(transaction: &Transaction, sender: &str, account: &StakeAccount) -> Result<(), ValidationError> {
+    let rejected = |message: String| ValidationError::new(ErrorCode::Rejected, message);
+    if canonical_address(&transaction.recipient).ok().as_deref() != Some(sender) {
+        return Err(rejected("Staking transactions must name the sender as recipient".to_string()));
+    }
+    if transaction.amount == 0 {
+        return Err(rejected("Staking transactions must move more than 0".to_string()));
+    }
+    if account.nonces.contains(&transaction.nonce) {
+        return Err(double_spend(transaction));
+    }
+
+    let insufficient = |message: String| ValidationError::new(ErrorCode::InsufficientFunds, message);
+    let cost = match transaction.transaction_type {
+        TransactionType::Stake => transaction.amount.saturating_add(transaction.fee),
+        _ => transaction.fee,
+    };
+    if cost > account.balance {
+        return Err(insufficient(format!("{sender} has a balance of {}, {cost} is needed", account.balance)));
+    }
+    if matches!(transaction.transaction_type, TransactionType::Unstake) && transaction.amount > account.staked {
+        return Err(insufficient(format!("{sender} has {} staked, {} can't be unstaked", account.staked, transaction.amount)));
+    }
+    Ok(())
+}
+
+fn double_spend(transaction: &Transaction) -> ValidationError {
+    ValidationError::new(
+        ErrorCode::DoubleSpend,
+        format!("Nonce {} of {} was already spent", transaction.nonce, transaction.sender),
+    )
+}
+
+/// An address in its checksummed form, whichever way it's written
+fn canonical_address(address: &str) -> Result<String, String> {
+    Address::parse_any(address).map(|address| address.to_string()).map_err(|e| format!("{address} is not an address: {e}"))
+}
@@ -0,0 +1,226 @@
+//! Supervision of background tasks.
+//!
+//! The node keeps peers healthy, retries pending entries, prunes the ledger
+//! and discovers peers in tasks that run for as long as it does. A bug that
+//! panics in one of them would otherwise stop that work silently while the
+//! rest of the node carries on. A [`Supervisor`] runs a named task and,
+//! depending on its [`RestartPolicy`], starts it again when it panics or
+//! returns, waiting longer after each restart that follows quickly on the
+//! last. An aborted task stays stopped.
+//!
+//! Supervisors given a [`Tasks`] registry report each task's state and
+//! restarts there, which `/healthz` and `/readyz` include.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+/// When a supervised task is started again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// After it panics, and after it returns, for loops that should never end
+    Always,
+    /// After it panics only, so work that completes stays done
+    #[default]
+    OnPanic,
+    /// Never; a panic leaves the task failed
+    Never,
+}
+
+/// What a supervised task is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Waiting out the delay before it's started again
+    Restarting,
+    /// Returned, and its policy doesn't start it again
+    Finished,
+    /// Panicked, and its policy doesn't start it again
+    Failed,
+}
+
+/// How a supervised task has fared, as reported by [`Tasks::report`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskHealth {
+    pub state: TaskState,
+    /// Times the task was started again
+    pub restarts: u64,
+    /// Message of the last panic, if it ever panicked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_panic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_panic_at: Option<DateTime<Utc>>,
+}
+
+/// Health of supervised tasks by name; clones share the registry
+#[derive(Debug, Clone, Default)]
+pub struct Tasks {
+    tasks: Arc<Mutex<BTreeMap<String, TaskHealth>>>,
+    /// Supervisors reporting here, so they can be stopped together
+    supervisors: Arc<Mutex<Vec<AbortHandle>>>,
+}
+
+impl Tasks {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Health of every task reported so far
+    pub fn report(&self) -> BTreeMap<String, TaskHealth> {
+        self.tasks.lock().unwrap().clone()
+    }
+
+    /// Whether any task stopped for good after a panic
+    pub fn any_failed(&self) -> bool {
+        self.tasks.lock().unwrap().values().any(|task| task.state == TaskState::Failed)
+    }
+
+    /// Stop every task reporting here, along with its supervisor
+    pub fn abort_all(&self) {
+        for supervisor in self.supervisors.lock().unwrap().drain(..) {
+            supervisor.abort();
+        }
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut TaskHealth)) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = tasks.entry(name.to_string()).or_insert(TaskHealth {
+            state: TaskState::Running,
+            restarts: 0,
+            last_panic: None,
+            last_panic_at: None,
+        });
+        update(task);
+    }
+}
+
+/// Runs a background task, restarting it according to its policy
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    name: String,
+    policy: RestartPolicy,
+    min_delay: Duration,
+    max_delay: Duration,
+    tasks: Tasks,
+}
+
+impl Supervisor {
+    /// Supervise the task called `name`, restarting it after a panic a
+    /// second after its first crash and at most a minute after later ones
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            policy: RestartPolicy::default(),
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            tasks: Tasks::new(),
+        }
+    }
+
+    /// Restart the task under `policy`
+    pub fn with_policy(mut self, policy: RestartPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Wait `min` before the first restart, doubling for each restart up to `max`
+    pub fn with_restart_delay(mut self, min: Duration, max: Duration) -> Self {
+        self.min_delay = min;
+        self.max_delay = max.max(min);
+        self
+    }
+
+    /// Report the task's health to `tasks`
+    pub fn with_tasks(mut self, tasks: &Tasks) -> Self {
+        self.tasks = tasks.clone();
+        self
+    }
+
+    /// Start the task `start` makes, and a new one each time its policy says to.
+    ///
+    /// Aborting the returned handle, or calling [`Tasks::abort_all`] on the
+    /// registry it reports to, stops the supervisor along with the task it's running.
+    pub fn spawn<F, Fut>(self, start: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let tasks = self.tasks.clone();
+        let handle = tokio::spawn(async move {
+            let name = self.name.as_str();
+            let mut delay = self.min_delay;
+            loop {
+                self.tasks.update(name, |task| task.state = TaskState::Running);
+                let started = Instant::now();
+                let task = tokio::spawn(start());
+                let _abort = AbortOnDrop(task.abort_handle());
+                match task.await {
+                    Ok(()) if self.policy == RestartPolicy::Always => {
+                        warn!(task = name, restart_in = ?delay, "Background task returned");
+                    }
+                    Ok(()) => {
+                        info!(task = name, "Background task finished");
+                        self.tasks.update(name, |task| task.state = TaskState::Finished);
+                        return;
+                    }
+                    Err(error) if error.is_panic() => {
+                        let panic = error.into_panic();
+                        let message = panic_message(&*panic).to_string();
+                        error!(task = name, policy = ?self.policy, "Background task panicked: {message}");
+                        self.tasks.update(name, |task| {
+                            task.last_panic = Some(message);
+                            task.last_panic_at = Some(Utc::now());
+                        });
+                        if self.policy == RestartPolicy::Never {
+                            self.tasks.update(name, |task| task.state = TaskState::Failed);
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+
+                // A task that ran a good while before stopping starts over from the shortest delay
+                if started.elapsed() >= self.max_delay {
+                    delay = self.min_delay;
+                }
+                self.tasks.update(name, |task| {
+                    task.state = TaskState::Restarting;
+                    task.restarts += 1;
+                });
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(self.max_delay);
+            }
+        });
+        tasks.supervisors.lock().unwrap().push(handle.abort_handle());
+        handle
+    }
+}
+
+/// Aborts the supervised task when the supervisor itself is aborted
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The message a panic was raised with
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message")
+}
@@ -0,0 +1,231 @@
+//! Syncing the ledger from several peers at once.
+//!
+//! A newly connected peer is synced with one page at a time, each page asked
+//! for once the last was added, which makes bootstrapping a long chain from
+//! a single peer slow. A [`SyncCoordinator`] instead asks every peer where
+//! its chain parts from ours and how long it is. The peer with the longest
+//! chain also sends a skeleton: the hashes of the entries ending each
+//! segment of the heights this node is missing. The segments are then asked
+//! of all the peers whose chains reach them, a few at a time from each. A
+//! segment only counts if its entries hash to their hashes, link to each
+//! other and end at the skeleton's hash, so a peer on another branch can't
+//! slip in its own entries; a segment that fails that, or that a peer
+//! doesn't send in time, is asked of the next peer. Segments are added in
+//! height order as they arrive, so only the segments in flight are held in
+//! memory. A skeleton covers at most [`MAX_SYNC_SKELETON`] segments, so a
+//! longer chain is synced in several rounds.
+
+use std::time::Duration;
+
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::ledger::{LedgerEntry, LedgerMode, SyncPage};
+use crate::p2p::{P2PManager, MAX_SYNC_SKELETON, REQUEST_TIMEOUT};
+
+/// Segments asked of each peer at a time
+const SEGMENTS_PER_PEER: usize = 2;
+
+/// Heights `from` to `to` of a chain, both included, fetched in one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Segment {
+    /// Number of entries in the segment
+    fn len(&self) -> usize {
+        self.to + 1 - self.from
+    }
+}
+
+/// Split heights `from` to `to` into segments of `size` entries, the last one shorter if need be
+pub fn split(from: usize, to: usize, size: usize) -> Vec<Segment> {
+    let size = size.max(1);
+    (from..=to).step_by(size).map(|start| Segment { from: start, to: to.min(start + size - 1) }).collect()
+}
+
+/// Check that `entries` hash to their hashes and, if `linked`, link to each
+/// other, and that the last of them is the entry with hash `last`
+pub fn verify_segment(entries: &[LedgerEntry], linked: bool, last: &str) -> Result<(), String> {
+    for entry in entries {
+        // The hash covers the blob reference an offloaded entry was stored with, not its data
+        let mut covered = entry.clone();
+        if let Some(reference) = covered.blob.take() {
+            covered.data = reference;
+        }
+        if covered.hash != covered.calculate_hash() {
+            return Err(format!("Entry {} doesn't match its hash", entry.id));
+        }
+    }
+    if linked && let Some(pair) = entries.windows(2).find(|pair| pair[1].previous_hash != pair[0].hash) {
+        return Err(format!("Entry {} doesn't link to entry {}", pair[1].id, pair[0].id));
+    }
+    match entries.last() {
+        Some(entry) if entry.hash == last => Ok(()),
+        Some(entry) => Err(format!("Segment ends at {} instead of {last}", entry.hash)),
+        None => Err("Segment is empty".to_string()),
+    }
+}
+
+/// How a coordinated sync went
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Peers that had segments to give
+    pub peers: usize,
+    /// Segments fetched
+    pub segments: usize,
+    /// Entries added to the ledger
+    pub added: usize,
+    /// Times a segment was asked of another peer after one failed to send it
+    pub retries: usize,
+}
+
+/// A peer's answer to where its chain parts from ours
+struct Probe {
+    peer_id: String,
+    page: SyncPage,
+}
+
+/// Syncs a node's ledger from several of its peers at once
+pub struct SyncCoordinator<'a> {
+    p2p: &'a P2PManager,
+    segment_size: usize,
+    timeout: Duration,
+}
+
+impl<'a> SyncCoordinator<'a> {
+    /// Sync `p2p`'s ledger in segments of its sync page size, waiting
+    /// [`REQUEST_TIMEOUT`] for each before asking the next peer
+    pub fn new(p2p: &'a P2PManager) -> Self {
+        Self { p2p, segment_size: p2p.sync_page_size(), timeout: REQUEST_TIMEOUT }
+    }
+
+    /// Fetch segments of `size` entries
+    pub fn with_segment_size(mut self, size: usize) -> Self {
+        self.segment_size = size.max(1);
+        self
+    }
+
+    /// Wait `timeout` for a peer to send a segment before asking the next one
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sync the ledger from `peers` until it reaches the longest chain among them.
+    ///
+    /// Fails if no peer answers, if the peer with the longest chain sends no
+    /// skeleton, or if no peer sends a segment that checks out.
+    pub async fn sync(&self, peers: &[String]) -> Result<SyncReport, String> {
+        let mut report = SyncReport::default();
+        loop {
+            let probes = self.probe(peers).await;
+            let Some(reference) = probes.iter().max_by_key(|probe| probe.page.height) else {
+                return Err("No peer answered".to_string());
+            };
+            let (from, height) = (reference.page.from, reference.page.height);
+            if from > height {
+                break;
+            }
+            if reference.page.skeleton.is_empty() {
+                return Err(format!("{} sent no skeleton", reference.peer_id));
+            }
+
+            // Heights of sets differ from node to node, so only the reference's segments fit its skeleton
+            let sources: Vec<&Probe> = match self.p2p.ledger.mode() {
+                LedgerMode::Chain => probes.iter().filter(|probe| probe.page.from <= from).collect(),
+                LedgerMode::Crdt => vec![reference],
+            };
+            let segments: Vec<Segment> =
+                split(from, height, self.segment_size).into_iter().take(reference.page.skeleton.len()).collect();
+            report.peers = report.peers.max(sources.len());
+            info!(
+                reference = reference.peer_id,
+                peers = sources.len(),
+                from,
+                height,
+                "Syncing {} segments",
+                segments.len()
+            );
+
+            let mut fetched = stream::iter(segments.iter().zip(&reference.page.skeleton).enumerate())
+                .map(|(index, (segment, last))| self.fetch(*segment, last, &sources, index))
+                .buffered(sources.len() * SEGMENTS_PER_PEER);
+            let mut added = 0;
+            while let Some(result) = fetched.next().await {
+                let (peer_id, entries, retries) = result?;
+                report.segments += 1;
+                report.retries += retries;
+                added += self.p2p.add_synced_entries(&peer_id, entries)?;
+            }
+            report.added += added;
+
+            // A round that adds nothing would only be repeated
+            let reached = segments.last().is_some_and(|segment| segment.to >= height);
+            if reached || added == 0 {
+                break;
+            }
+        }
+        self.p2p.record_sync();
+        Ok(report)
+    }
+
+    /// Ask each peer where its chain parts from ours, keeping the answers that come in time
+    async fn probe(&self, peers: &[String]) -> Vec<Probe> {
+        let probes = peers.iter().map(|peer_id| async move {
+            let page = self
+                .p2p
+                .request_sync_page_await(peer_id.clone(), None, 1, Some(self.segment_size), self.timeout)
+                .await;
+            match page {
+                Ok(page) => Some(Probe { peer_id: peer_id.clone(), page }),
+                Err(e) => {
+                    debug!(peer_id, "Leaving peer out of the sync: {e}");
+                    None
+                }
+            }
+        });
+        futures::future::join_all(probes).await.into_iter().flatten().collect()
+    }
+
+    /// Fetch `segment` from the first of `sources`, starting at the one
+    /// `index` picks, whose chain reaches it and who sends it ending at `last`,
+    /// returning who sent it, its entries and how many peers failed first
+    async fn fetch(
+        &self,
+        segment: Segment,
+        last: &str,
+        sources: &[&Probe],
+        index: usize,
+    ) -> Result<(String, Vec<LedgerEntry>, usize), String> {
+        let linked = self.p2p.ledger.mode() == LedgerMode::Chain;
+        let candidates: Vec<&&Probe> = sources.iter().filter(|probe| probe.page.height >= segment.to).collect();
+        let start = index % candidates.len().max(1);
+        let mut retries = 0;
+        for probe in candidates.iter().cycle().skip(start).take(candidates.len()) {
+            let peer_id = &probe.peer_id;
+            let result = self
+                .p2p
+                .request_sync_page_await(peer_id.clone(), Some(segment.from), segment.len(), None, self.timeout)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|page| {
+                    if page.from != segment.from {
+                        return Err(format!("Page starts at {} instead", page.from));
+                    }
+                    verify_segment(&page.entries, linked, last).map(|()| page.entries)
+                });
+            match result {
+                Ok(entries) => return Ok((peer_id.clone(), entries, retries)),
+                Err(e) => {
+                    warn!(peer_id, from = segment.from, to = segment.to, "Peer failed to send segment: {e}");
+                    retries += 1;
+                }
+            }
+        }
+        Err(format!("No peer sent heights {} to {}", segment.from, segment.to))
+    }
+}
@@ -0,0 +1,270 @@
+//! Tracing setup and OpenTelemetry export.
+//!
+//! The node logs to stdout as it always has. With `[telemetry]
+//! otlp_endpoint` set, spans are also exported to an OpenTelemetry
+//! collector over OTLP/HTTP, encoded as JSON. P2P message handling, ledger
+//! writes and HTTP requests each run in a span carrying the IDs they concern
+//! (`message_id`, `peer_id`, `entry_id`), so an entry can be followed through
+//! the mesh by searching the collector for its ID across every node's spans.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{warn, Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+/// Finished spans waiting to be exported; spans finishing while the queue is full are dropped
+pub const MAX_QUEUED_SPANS: usize = 4096;
+
+/// Most spans sent to the collector in one request
+pub const EXPORT_BATCH_SIZE: usize = 512;
+
+/// How long a finished span waits for a batch to fill before it's exported anyway
+pub const EXPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The `[telemetry]` section of the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Base URL of an OTLP/HTTP collector, like `http://localhost:4318`; spans aren't exported if unset
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` the node's spans are exported under
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "gsio-node".to_string(),
+        }
+    }
+}
+
+/// Install the global subscriber: logs to stdout, and spans to the collector
+/// in `config` if one is set, exported under `node_id`. Must be called within
+/// a Tokio runtime when spans are exported.
+pub fn init(config: &TelemetryConfig, node_id: &str) -> Result<(), String> {
+    let otlp = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| OtlpLayer::new(endpoint, &config.service_name, node_id).with_filter(LevelFilter::INFO));
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(LevelFilter::INFO))
+        .with(otlp)
+        .try_init()
+        .map_err(|e| format!("Failed to set up tracing: {e}"))
+}
+
+/// A span attribute or event field, as OTLP encodes it
+type Attribute = (String, JsonValue);
+
+/// Collects fields into OTLP attributes
+struct AttributeVisitor<'a>(&'a mut Vec<Attribute>);
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: JsonValue) {
+        self.0.retain(|(key, _)| key != field.name());
+        self.0.push((field.name().to_string(), value));
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, json!({ "stringValue": value }));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, json!({ "boolValue": value }));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, json!({ "doubleValue": value }));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, json!({ "stringValue": format!("{value:?}") }));
+    }
+}
+
+/// Something logged while a span was open
+struct SpanEvent {
+    time: SystemTime,
+    level: Level,
+    fields: Vec<Attribute>,
+}
+
+/// A span being recorded, kept in the span's extensions until it closes
+struct SpanData {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    attributes: Vec<Attribute>,
+    events: Vec<SpanEvent>,
+}
+
+/// A tracing layer exporting finished spans to an OTLP/HTTP collector
+pub struct OtlpLayer {
+    spans: mpsc::Sender<JsonValue>,
+}
+
+impl OtlpLayer {
+    /// Export spans to the collector at `endpoint` as `service_name`, from
+    /// the node `node_id`. Spans are sent by a task spawned on the current
+    /// Tokio runtime.
+    pub fn new(endpoint: &str, service_name: &str, node_id: &str) -> Self {
+        let (spans, queue) = mpsc::channel(MAX_QUEUED_SPANS);
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        let resource = json!({
+            "attributes": [
+                { "key": "service.name", "value": { "stringValue": service_name } },
+                { "key": "service.instance.id", "value": { "stringValue": node_id } },
+            ]
+        });
+        tokio::spawn(export_spans(url, resource, queue));
+        Self { spans }
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanData>().map(|data| (data.trace_id, data.span_id)));
+        let (trace_id, parent_span_id) = match parent {
+            Some((trace_id, parent_id)) => (trace_id, Some(parent_id)),
+            None => (random_bytes(), None),
+        };
+
+        let mut attributes = Vec::new();
+        attrs.record(&mut AttributeVisitor(&mut attributes));
+        span.extensions_mut().insert(SpanData {
+            trace_id,
+            span_id: random_bytes(),
+            parent_span_id,
+            start: SystemTime::now(),
+            attributes,
+            events: Vec::new(),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            values.record(&mut AttributeVisitor(&mut data.attributes));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.event_span(event)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            let mut fields = Vec::new();
+            event.record(&mut AttributeVisitor(&mut fields));
+            data.events.push(SpanEvent { time: SystemTime::now(), level: *event.metadata().level(), fields });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else { return };
+        let metadata = span.metadata();
+        let mut attributes = data.attributes;
+        attributes.push(("code.namespace".to_string(), json!({ "stringValue": metadata.target() })));
+
+        let mut otlp_span = json!({
+            "traceId": hex::encode(data.trace_id),
+            "spanId": hex::encode(data.span_id),
+            "name": metadata.name(),
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(data.start),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": otlp_attributes(attributes),
+            "events": data.events.into_iter().map(otlp_event).collect::<Vec<_>>(),
+        });
+        if let Some(parent_span_id) = data.parent_span_id {
+            otlp_span["parentSpanId"] = json!(hex::encode(parent_span_id));
+        }
+        // Dropping spans is better than blocking the code being traced
+        self.spans.try_send(otlp_span).ok();
+    }
+}
+
+/// Send queued spans to the collector in batches until the layer is dropped
+async fn export_spans(url: String, resource: JsonValue, mut queue: mpsc::Receiver<JsonValue>) {
+    let http = reqwest::Client::new();
+    while let Some(span) = queue.recv().await {
+        let mut batch = vec![span];
+        let deadline = tokio::time::Instant::now() + EXPORT_INTERVAL;
+        while batch.len() < EXPORT_BATCH_SIZE {
+            match tokio::time::timeout_at(deadline, queue.recv()).await {
+                Ok(Some(span)) => batch.push(span),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let body = json!({
+            "resourceSpans": [{
+                "resource": resource,
+                "scopeSpans": [{ "scope": { "name": "gsio-node" }, "spans": batch }],
+            }]
+        });
+        match http.post(&url).json(&body).send().await {
+            Ok(response) if !response.status().is_success() => {
+                warn!(status = %response.status(), "Collector refused {} spans", batch.len());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to export {} spans: {e}", batch.len()),
+        }
+    }
+}
+
+/// An event as an OTLP span event, named by its message
+fn otlp_event(event: SpanEvent) -> JsonValue {
+    let mut fields = event.fields;
+    let name = match fields.iter().position(|(key, _)| key == "message") {
+        Some(i) => fields.remove(i).1["stringValue"].as_str().unwrap_or_default().to_string(),
+        None => "event".to_string(),
+    };
+    fields.push(("level".to_string(), json!({ "stringValue": event.level.as_str() })));
+    json!({ "timeUnixNano": unix_nanos(event.time), "name": name, "attributes": otlp_attributes(fields) })
+}
+
+fn otlp_attributes(attributes: Vec<Attribute>) -> Vec<JsonValue> {
+    attributes.into_iter().map(|(key, value)| json!({ "key": key, "value": value })).collect()
+}
+
+/// Nanoseconds since the Unix epoch, as a string since OTLP encodes 64-bit integers that way in JSON
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
@@ -0,0 +1,102 @@
+//! HTTPS for the node's HTTP, Socket.IO and gRPC servers.
+//!
+//! Certificates and keys are read from PEM files named in the `[tls]`
+//! section of the config file. With `client_ca_path` set the node also
+//! requires clients to present a certificate signed by one of those CAs.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use serde::Deserialize;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// The `[tls]` section of the config file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain to serve, leaf first; the node serves plain HTTP if unset
+    pub cert_path: Option<PathBuf>,
+    /// PEM file with the certificate's private key
+    pub key_path: Option<PathBuf>,
+    /// PEM file with the CAs client certificates must be signed by; clients aren't asked for one if unset
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Whether the node serves HTTPS
+    pub fn is_enabled(&self) -> bool {
+        self.cert_path.is_some() || self.key_path.is_some()
+    }
+
+    /// Whether clients must present a certificate
+    pub fn requires_client_certificates(&self) -> bool {
+        self.client_ca_path.is_some()
+    }
+
+    /// The rustls configuration the HTTP and Socket.IO server is served with
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, String> {
+        let (cert_path, key_path) = self.paths()?;
+        let certs = read_certificates(cert_path)?;
+        let key = PrivateKeyDer::from_pem_file(key_path)
+            .map_err(|e| format!("Failed to read private key from {}: {e}", key_path.display()))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Invalid TLS configuration: {e}"))?;
+        let builder = match &self.client_ca_path {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certificates(path)? {
+                    roots.add(cert).map_err(|e| format!("Invalid CA certificate in {}: {e}", path.display()))?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .map_err(|e| format!("Invalid client CAs in {}: {e}", path.display()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid certificate or key in {}: {e}", cert_path.display()))?;
+        // Socket.IO upgrades to WebSockets, which need HTTP/1.1
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// The TLS configuration the gRPC server is served with
+    pub fn grpc_config(&self) -> Result<ServerTlsConfig, String> {
+        let (cert_path, key_path) = self.paths()?;
+        let config = ServerTlsConfig::new().identity(Identity::from_pem(read(cert_path)?, read(key_path)?));
+        Ok(match &self.client_ca_path {
+            Some(path) => config.client_ca_root(Certificate::from_pem(read(path)?)),
+            None => config,
+        })
+    }
+
+    fn paths(&self) -> Result<(&Path, &Path), String> {
+        match (&self.cert_path, &self.key_path) {
+            (Some(cert), Some(key)) => Ok((cert, key)),
+            _ => Err("TLS needs both cert_path and key_path".to_string()),
+        }
+    }
+}
+
+/// Every certificate in a PEM file
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificates from {}: {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+}
@@ -0,0 +1,270 @@
+//! Rules entry data has to satisfy before a node accepts it, whether it comes
+//! from a client or from a peer.
+//!
+//! Operators pick rules in the `[validation]` section of the config file;
+//! anything else can implement [`ValidationPolicy`] and be installed with
+//! `SharedLedger::set_validation_policy`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use gsio_client::Address;
+use gsio_types::parse_public_key;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+pub use gsio_types::{ErrorCode, ValidationError};
+
+/// A rule entry data has to satisfy
+pub trait ValidationPolicy: fmt::Debug + Send + Sync {
+    /// Check the data of an entry about to be added to the ledger
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError>;
+}
+
+/// Runs several policies in order, stopping at the first rejection.
+///
+/// An empty set accepts everything and is what a ledger starts with.
+#[derive(Debug, Clone, Default)]
+pub struct PolicySet {
+    policies: Vec<Arc<dyn ValidationPolicy>>,
+}
+
+impl PolicySet {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a policy to the set
+    pub fn with(mut self, policy: impl ValidationPolicy + 'static) -> Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Number of policies in the set
+    pub fn len(&self) -> usize {
+        self.policies.len()
+    }
+
+    /// Whether the set accepts everything
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+}
+
+impl ValidationPolicy for PolicySet {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        self.policies.iter().try_for_each(|policy| policy.validate(data))
+    }
+}
+
+/// Rejects data whose serialized JSON is longer than the given number of bytes
+#[derive(Debug, Clone)]
+pub struct MaxPayloadSize(pub usize);
+
+impl ValidationPolicy for MaxPayloadSize {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        let size = data.to_string().len();
+        if size > self.0 {
+            return Err(ValidationError::new(
+                ErrorCode::PayloadTooLarge,
+                format!("Entry data is {size} bytes, the limit is {}", self.0),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Requires fields to be present; nested fields are written `a.b.c`
+#[derive(Debug, Clone)]
+pub struct RequiredFields(pub Vec<String>);
+
+impl ValidationPolicy for RequiredFields {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        match self.0.iter().find(|path| field(data, path).is_none()) {
+            Some(path) => Err(ValidationError::new(ErrorCode::MissingField, format!("Missing field {path}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// JSON type a field is expected to have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Object,
+    Array,
+}
+
+impl FieldType {
+    /// The type as written in the config file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Integer => "integer",
+            FieldType::Boolean => "boolean",
+            FieldType::Object => "object",
+            FieldType::Array => "array",
+        }
+    }
+
+    fn matches(&self, value: &JsonValue) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Object => value.is_object(),
+            FieldType::Array => value.is_array(),
+        }
+    }
+}
+
+/// Requires the data to be an object whose fields, when present, have the given types
+#[derive(Debug, Clone, Default)]
+pub struct Schema(pub BTreeMap<String, FieldType>);
+
+impl ValidationPolicy for Schema {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        if !data.is_object() {
+            return Err(ValidationError::new(ErrorCode::InvalidFieldType, "Entry data must be an object"));
+        }
+        for (path, expected) in &self.0 {
+            if let Some(value) = field(data, path)
+                && !expected.matches(value)
+            {
+                return Err(ValidationError::new(
+                    ErrorCode::InvalidFieldType,
+                    format!("Field {path} must be of type {}", expected.as_str()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Requires the data to be signed with the ed25519 key it names.
+///
+/// The data carries a hex-encoded `public_key` and a hex-encoded `signature`
+/// over the data without its `signature` field; see [`sign_data`].
+#[derive(Debug, Clone)]
+pub struct RequireSignature;
+
+impl ValidationPolicy for RequireSignature {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        let (Some(public_key), Some(signature)) = (
+            data.get("public_key").and_then(JsonValue::as_str),
+            data.get("signature").and_then(JsonValue::as_str),
+        ) else {
+            return Err(ValidationError::new(
+                ErrorCode::MissingSignature,
+                "Entry data must carry public_key and signature",
+            ));
+        };
+
+        let invalid = |message: &str| ValidationError::new(ErrorCode::InvalidSignature, message);
+        let key = parse_public_key(public_key).map_err(|_| invalid("Invalid public key"))?;
+        let signature = hex::decode(signature)
+            .ok()
+            .and_then(|b| Signature::from_slice(&b).ok())
+            .ok_or_else(|| invalid("Invalid signature encoding"))?;
+
+        key.verify(&signing_payload(data), &signature)
+            .map_err(|_| invalid("Signature doesn't match the entry data"))
+    }
+}
+
+/// Requires transactions to name a valid sender and recipient address.
+///
+/// Applies to data of `"type": "transaction"`; `transaction.sender` and
+/// `transaction.recipient` must be checksummed addresses, or addresses
+/// written the old `gsio_<hex>` way, which the ledger already holds.
+#[derive(Debug, Clone)]
+pub struct TransactionAddresses;
+
+impl ValidationPolicy for TransactionAddresses {
+    fn validate(&self, data: &JsonValue) -> Result<(), ValidationError> {
+        if data.get("type").and_then(JsonValue::as_str) != Some("transaction") {
+            return Ok(());
+        }
+        for path in ["transaction.sender", "transaction.recipient"] {
+            let address = field(data, path).and_then(JsonValue::as_str).unwrap_or_default();
+            Address::parse_any(address)
+                .map_err(|e| ValidationError::new(ErrorCode::InvalidAddress, format!("Field {path} is not an address: {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Add `public_key` and `signature` fields to an object so it passes [`RequireSignature`]
+pub fn sign_data(mut data: JsonValue, key: &SigningKey) -> JsonValue {
+    if let Some(object) = data.as_object_mut() {
+        object.insert("public_key".to_string(), hex::encode(key.verifying_key().to_bytes()).into());
+        object.remove("signature");
+    }
+    let signature = key.sign(&signing_payload(&data));
+    if let Some(object) = data.as_object_mut() {
+        object.insert("signature".to_string(), hex::encode(signature.to_bytes()).into());
+    }
+    data
+}
+
+/// Bytes a data signature covers: the JSON without its `signature` field
+fn signing_payload(data: &JsonValue) -> Vec<u8> {
+    let mut data = data.clone();
+    if let Some(object) = data.as_object_mut() {
+        object.remove("signature");
+    }
+    data.to_string().into_bytes()
+}
+
+/// Look up a field by a dotted path such as `transaction.amount`
+fn field<'a>(data: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    path.split('.').try_fold(data, |value, key| value.get(key))
+}
+
+/// The `[validation]` section of the config file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// Largest serialized entry data accepted, in bytes
+    pub max_payload_bytes: Option<usize>,
+    /// Fields every entry must have
+    pub required_fields: Vec<String>,
+    /// Types fields must have when present
+    pub field_types: BTreeMap<String, FieldType>,
+    /// Whether entry data must be signed, see [`RequireSignature`]
+    pub require_signature: bool,
+    /// Whether transactions must name valid addresses, see [`TransactionAddresses`]
+    pub check_addresses: bool,
+}
+
+impl ValidationConfig {
+    /// Build the policy set these settings describe
+    pub fn policy(&self) -> PolicySet {
+        let mut policy = PolicySet::new();
+        if let Some(limit) = self.max_payload_bytes {
+            policy = policy.with(MaxPayloadSize(limit));
+        }
+        if !self.required_fields.is_empty() {
+            policy = policy.with(RequiredFields(self.required_fields.clone()));
+        }
+        if !self.field_types.is_empty() {
+            policy = policy.with(Schema(self.field_types.clone()));
+        }
+        if self.require_signature {
+            policy = policy.with(RequireSignature);
+        }
+        if self.check_addresses {
+            policy = policy.with(TransactionAddresses);
+        }
+        policy
+    }
+}
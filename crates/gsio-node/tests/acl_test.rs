@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use gsio_node::acl::{AclConfig, AclUpdate, WriteAcl};
+use gsio_node::ledger::{Ledger, LedgerEntry, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use gsio_node::validation::ErrorCode;
+use rand::rngs::OsRng;
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server;
+
+fn key_hex(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+/// Key of the admin approving ACL updates in these tests
+fn admin_key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+fn write_acl(writers: &[&SigningKey]) -> WriteAcl {
+    let writers = writers.iter().map(|key| key_hex(key)).collect();
+    WriteAcl::new(&AclConfig { writers, admins: vec![key_hex(&admin_key())], threshold: 1 }).unwrap()
+}
+
+/// Entry data for `update`, approved by the admin against `acl`
+fn approved(update: AclUpdate, acl: &WriteAcl) -> JsonValue {
+    update.approve(acl, &admin_key()).to_entry_data()
+}
+
+/// A channel ledger signing with `key`, writable by `writers`
+fn new_ledger(node_id: &str, key: &SigningKey, writers: &[&SigningKey]) -> Ledger {
+    let mut ledger = Ledger::with_signing_key(node_id.to_string(), key.clone());
+    ledger.set_write_acl(write_acl(writers));
+    ledger
+}
+
+fn new_channel(node_id: &str, key: &SigningKey, writers: &[&SigningKey]) -> Arc<P2PManager> {
+    let ledger = SharedLedger::with_signing_key(node_id.to_string(), key.clone());
+    ledger.set_write_acl(write_acl(writers));
+    Arc::new(P2PManager::new(node_id.to_string(), ledger).with_channel("payments"))
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_write_acl_updates() {
+    let (a, b, admin) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng), admin_key());
+    assert!(WriteAcl::new(&AclConfig::default()).is_err());
+    assert!(WriteAcl::new(&AclConfig { writers: vec!["not hex".to_string()], ..AclConfig::default() }).is_err());
+
+    let acl = write_acl(&[&a]);
+    assert_eq!(acl.writers(), vec![key_hex(&a)]);
+    assert!(acl.allows_key(&a.verifying_key()));
+    assert!(!acl.allows_key(&b.verifying_key()));
+
+    // Writers can't change the list, only admins
+    let add_b = AclUpdate { add: vec![key_hex(&b).to_uppercase()], ..AclUpdate::default() };
+    assert!(acl.updated(&add_b).unwrap_err().contains("approved by 0"));
+    assert!(acl.updated(&add_b.clone().approve(&acl, &a)).unwrap_err().contains("approved by 0"));
+    let added = acl.updated(&add_b.clone().approve(&acl, &admin)).unwrap();
+    assert!(added.allows_key(&b.verifying_key()));
+    assert_eq!(added.sequence(), 1);
+
+    // An approval only fits the list it was made for
+    assert!(added.updated(&add_b.approve(&acl, &admin)).is_err());
+    let remove_a = AclUpdate { remove: vec![key_hex(&a)], ..AclUpdate::default() };
+    let replaced = added.updated(&remove_a.clone().approve(&added, &admin)).unwrap();
+    assert_eq!(replaced.writers(), vec![key_hex(&b)]);
+
+    // An update may not leave the channel without writers
+    let emptied = remove_a.approve(&acl, &admin);
+    assert!(acl.updated(&emptied).unwrap_err().contains("every writer"));
+    assert!(acl.check(&emptied.to_entry_data()).is_err());
+    assert!(acl.check(&json!({ "acl_update": { "grant": [] } })).is_err());
+    assert!(acl.check(&json!({ "amount": 5 })).is_ok());
+}
+
+#[test]
+fn test_acl_updates_need_enough_admins() {
+    let writer = SigningKey::generate(&mut OsRng);
+    let admins = [0; 3].map(|_| SigningKey::generate(&mut OsRng));
+    let config =
+        AclConfig { writers: vec![key_hex(&writer)], admins: admins.iter().map(key_hex).collect(), threshold: 2 };
+    let acl = WriteAcl::new(&config).unwrap();
+    assert!(WriteAcl::new(&AclConfig { threshold: 4, ..config.clone() }).is_err());
+    assert!(WriteAcl::new(&AclConfig { threshold: 0, ..config.clone() }).is_err());
+
+    let update = AclUpdate { add: vec![key_hex(&SigningKey::generate(&mut OsRng))], ..AclUpdate::default() };
+    let once = update.clone().approve(&acl, &admins[0]);
+    assert!(acl.updated(&once).unwrap_err().contains("1 of the 2"));
+
+    // An admin listed twice still counts once
+    let mut twice = once.clone();
+    let signature = once.approvals[&key_hex(&admins[0])].clone();
+    twice.approvals.insert(key_hex(&admins[0]).to_uppercase(), signature);
+    assert!(acl.updated(&twice).is_err());
+    assert!(acl.updated(&once.approve(&acl, &admins[2])).is_ok());
+
+    // Without admins the list stays as configured
+    let fixed = WriteAcl::new(&AclConfig { writers: vec![key_hex(&writer)], ..AclConfig::default() }).unwrap();
+    assert!(fixed.updated(&update.approve(&fixed, &admins[0])).unwrap_err().contains("no admins"));
+}
+
+#[test]
+fn test_only_writers_add_entries() {
+    let (a, b) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng));
+    let mut writer = new_ledger("test-node-1", &a, &[&a]);
+    let mut reader = new_ledger("test-node-2", &b, &[&a]);
+    assert!(writer.can_write());
+    assert!(!reader.can_write());
+
+    let error = reader.add_entry(json!({ "amount": 5 })).unwrap_err();
+    assert_eq!(error.code, ErrorCode::NotWriter);
+    assert!(reader.get_entries().is_empty());
+
+    // Updates that would empty the list are refused up front
+    let emptied = AclUpdate { remove: vec![key_hex(&a)], ..AclUpdate::default() };
+    let acl = writer.write_acl().unwrap().clone();
+    assert_eq!(writer.add_entry(approved(emptied, &acl)).unwrap_err().code, ErrorCode::Rejected);
+
+    // A peer entry from a non-writer is refused and never joins the chain
+    writer.add_entry(json!({ "amount": 1 })).unwrap();
+    let mut entry = LedgerEntry::new(json!({ "amount": 2 }), writer.get_entries()[0].hash.clone(), "test-node-2".to_string());
+    entry.sign("test-node-2".to_string(), &b);
+    writer.add_node_key("test-node-2".to_string(), &key_hex(&b)).unwrap();
+    assert_eq!(writer.check_writer(&entry).unwrap_err().code, ErrorCode::NotWriter);
+    writer.add_pending_entry(entry.clone());
+    writer.process_pending_entries();
+    assert_eq!(writer.get_entries().len(), 1);
+    assert!(writer.get_entry_by_id(&entry.id).is_none());
+}
+
+#[test]
+fn test_governance_entries_change_writers() {
+    let (a, b) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng));
+    let mut writer = new_ledger("test-node-1", &a, &[&a]);
+    writer.add_node_key("test-node-2".to_string(), &key_hex(&b)).unwrap();
+    writer.add_entry(json!({ "amount": 1 })).unwrap();
+
+    // Writers can't add updates the admins haven't approved
+    let add_b = AclUpdate { add: vec![key_hex(&b)], ..AclUpdate::default() };
+    assert_eq!(writer.add_entry(add_b.to_entry_data()).unwrap_err().code, ErrorCode::Rejected);
+    let acl = writer.write_acl().unwrap().clone();
+    writer.add_entry(approved(add_b, &acl)).unwrap();
+    assert_eq!(writer.write_acl().unwrap().writers().len(), 2);
+
+    // The new writer's entries now join the chain
+    let mut entry = LedgerEntry::new(json!({ "amount": 2 }), writer.get_entries()[1].hash.clone(), "test-node-2".to_string());
+    entry.sign("test-node-2".to_string(), &b);
+    assert!(writer.check_writer(&entry).is_ok());
+    writer.add_pending_entry(entry.clone());
+    writer.process_pending_entries();
+    assert_eq!(writer.get_entries().last().unwrap().id, entry.id);
+
+    // Snapshots carry the list as of their height
+    let snapshot = writer.create_snapshot(1).unwrap();
+    assert_eq!((snapshot.acl, snapshot.acl_sequence), (Some(vec![key_hex(&a)]), Some(0)));
+    let snapshot = writer.create_snapshot(3).unwrap();
+    assert_eq!(snapshot.acl.as_ref().unwrap().len(), 2);
+    assert_eq!(snapshot.acl_sequence, Some(1));
+    let mut restored = Ledger::with_signing_key("test-node-2".to_string(), b.clone());
+    restored.set_checkpoint_keys(&[writer.public_key()]).unwrap();
+    restored.restore_from_snapshot(&snapshot).unwrap();
+    assert!(restored.can_write());
+
+    // Removing a writer stops them writing
+    let acl = writer.write_acl().unwrap().clone();
+    writer.add_entry(approved(AclUpdate { remove: vec![key_hex(&a)], ..AclUpdate::default() }, &acl)).unwrap();
+    assert!(!writer.can_write());
+    assert_eq!(writer.add_entry(json!({ "amount": 3 })).unwrap_err().code, ErrorCode::NotWriter);
+}
+
+#[tokio::test]
+async fn test_acl_is_enforced_across_peers() {
+    let (a, b) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng));
+    let writer = new_channel("test-node-1", &a, &[&a]);
+    let url = start_server(writer.clone()).await;
+    let client = reqwest::Client::new();
+
+    let response = client.post(format!("{url}/api/ledger")).json(&json!({ "amount": 1 })).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let reader = new_channel("test-node-2", &b, &[&a]);
+    let reader_url = start_server(reader.clone()).await;
+    reader.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || reader.ledger.entry_count() == 1).await;
+
+    // The reader can't write until a governance entry adds its key
+    let response = client.post(format!("{reader_url}/api/ledger")).json(&json!({ "amount": 2 })).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["code"], "not_writer");
+
+    let acl = writer.ledger.write_acl().unwrap();
+    writer.add_local_entry(approved(AclUpdate { add: vec![key_hex(&b)], ..AclUpdate::default() }, &acl)).unwrap();
+    wait_for(Duration::from_secs(5), || reader.ledger.can_write()).await;
+
+    // Its entries then reach the writer
+    let entry = reader.add_local_entry(json!({ "amount": 2 })).unwrap();
+    wait_for(Duration::from_secs(5), || writer.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    assert_eq!(writer.ledger.chain_tip(), reader.ledger.chain_tip());
+
+    reader.leave().await;
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::admin::{self, Admin, AdminConfig, PeerList, RuntimeConfig};
+use gsio_node::api;
+use gsio_node::ledger::{LedgerStats, RetentionPolicy, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server_with;
+
+const ADMIN_KEY: &str = "test-admin-key";
+
+fn admin_config() -> AdminConfig {
+    AdminConfig { api_keys: vec![ADMIN_KEY.to_string()] }
+}
+
+async fn start_server(p2p: Arc<P2PManager>, admin: Arc<Admin>) -> String {
+    let app = api::router(p2p.clone()).merge(admin::router(admin, &admin_config()).unwrap());
+    start_server_with(p2p, app).await
+}
+
+fn node(node_id: &str) -> (Arc<P2PManager>, Arc<Admin>) {
+    let p2p = Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())));
+    let admin = Arc::new(Admin::new(p2p.clone(), RuntimeConfig::default()));
+    (p2p, admin)
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_admin_requires_key() {
+    let (p2p, admin) = node("test-node-1");
+    let url = start_server(p2p, admin).await;
+    let http = reqwest::Client::new();
+
+    let response = http.get(format!("{url}/admin/ledger/stats")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = http.get(format!("{url}/admin/ledger/stats")).bearer_auth("client-key").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = http.get(format!("{url}/admin/ledger/stats")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // The audit log is served with the admin routes
+    let response = http.get(format!("{url}/admin/audit")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = http.get(format!("{url}/admin/audit")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // The client API stays open
+    let response = http.get(format!("{url}/api/ledger")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    assert!(!AdminConfig::default().is_enabled());
+}
+
+#[tokio::test]
+async fn test_ledger_stats_and_config() {
+    let (p2p, admin) = node("test-node-1");
+    for i in 1..=3 {
+        p2p.add_local_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    let url = start_server(p2p.clone(), admin).await;
+    let http = reqwest::Client::new();
+
+    let stats: LedgerStats = http
+        .get(format!("{url}/admin/ledger/stats"))
+        .bearer_auth(ADMIN_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!((stats.tip.height, stats.entries, stats.pending_entries), (3, 3, 0));
+    assert_eq!(stats.tip.hash, p2p.ledger.get_last_entry().unwrap().hash);
+    assert_eq!(stats.merkle_root, p2p.ledger.merkle_root());
+    assert_eq!(stats.consensus, "longest_chain");
+
+    let config: JsonValue = http
+        .get(format!("{url}/admin/config"))
+        .bearer_auth(ADMIN_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(config["node_id"], "test-node-1");
+    assert_eq!(config["retention"], json!({ "type": "keep_forever" }));
+
+    // New settings take effect straight away
+    let update = json!({
+        "retention": { "type": "keep_last", "entries": 2 },
+        "validation": { "required_fields": ["message"] },
+    });
+    let response = http.patch(format!("{url}/admin/config")).bearer_auth(ADMIN_KEY).json(&update).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let config: JsonValue = response.json().await.unwrap();
+    assert_eq!(config["validation"]["required_fields"], json!(["message"]));
+    assert_eq!(p2p.ledger.get_retention_info().policy, RetentionPolicy::KeepLast { entries: 2 });
+    assert_eq!(p2p.ledger.apply_retention(), 1);
+    assert!(p2p.add_local_entry(json!({ "text": "no message" })).is_err());
+
+    // Settings that can't change at runtime are refused
+    let response = http
+        .patch(format!("{url}/admin/config"))
+        .bearer_auth(ADMIN_KEY)
+        .json(&json!({ "mode": "follower" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_peer_management() {
+    let (source, admin) = node("test-node-1");
+    let url = start_server(source.clone(), admin.clone()).await;
+    let peer = Arc::new(P2PManager::new("test-node-2".to_string(), SharedLedger::new("test-node-2".to_string())));
+    peer.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || source.peer_health().contains_key("test-node-2")).await;
+
+    let http = reqwest::Client::new();
+    let peers: PeerList = http
+        .get(format!("{url}/admin/peers"))
+        .bearer_auth(ADMIN_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(peers.inbound.len(), 1);
+    assert_eq!(peers.inbound[0].node_id, "test-node-2");
+    assert!(peers.banned.is_empty());
+
+    // A banned peer is dropped and can't come back
+    let response = http.post(format!("{url}/admin/peers/test-node-2/ban")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(source.peer_health().is_empty());
+    assert_eq!(admin.peers().banned, vec!["test-node-2".to_string()]);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(source.peer_health().is_empty());
+
+    // Once the ban is lifted it reconnects
+    let response = http.delete(format!("{url}/admin/peers/test-node-2/ban")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    wait_for(Duration::from_secs(10), || source.peer_health().contains_key("test-node-2")).await;
+
+    let response = http
+        .post(format!("{url}/admin/peers/test-node-2/disconnect"))
+        .bearer_auth(ADMIN_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(!source.peer_health().contains_key("test-node-2"));
+
+    let response = http.post(format!("{url}/admin/peers/test-node-9/disconnect")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let response = http.delete(format!("{url}/admin/peers/test-node-9/ban")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    peer.leave().await;
+}
+
+#[tokio::test]
+async fn test_shutdown_request() {
+    let (p2p, admin) = node("test-node-1");
+    let url = start_server(p2p, admin.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{url}/admin/shutdown"))
+        .bearer_auth(ADMIN_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+    tokio::time::timeout(Duration::from_secs(1), admin.shutdown_requested()).await.unwrap();
+}
@@ -0,0 +1,647 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use gsio_client::{ErrorCode, Filter, GsioClient, GsioClientError, LightClient, Query};
+use gsio_node::api::{self, HEADERS_PAGE_SIZE, MAX_HEADERS_PAGE_SIZE};
+use gsio_node::ledger::{RetentionPolicy, SharedLedger};
+use gsio_node::p2p::{NodeMode, P2PManager};
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+
+mod common;
+
+use common::start_server;
+
+#[tokio::test]
+async fn test_client_round_trip() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id.clone())));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    // Add entries through the HTTP API
+    let data = json!({ "message": "Test entry 1" });
+    let entry = client.add_ledger_entry(data.clone()).await.unwrap();
+    assert_eq!(entry.data, data);
+    assert_eq!(entry.creator_node_id, node_id);
+    client.add_ledger_entry(json!({ "message": "Test entry 2" })).await.unwrap();
+
+    // Read them back
+    let entries = client.get_ledger().await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, entry.id);
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    // Known nodes include this node
+    let nodes = client.get_known_nodes().await.unwrap();
+    assert_eq!(nodes, vec![node_id]);
+}
+
+#[tokio::test]
+async fn test_invalid_json_is_rejected() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let url = start_server(p2p.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{url}/api/ledger"))
+        .header("content-type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+    assert_eq!(body["code"], "bad_request");
+    assert!(p2p.ledger.get_entries().is_empty());
+
+    // Every error names a code, not just rejected entries
+    let response = reqwest::get(format!("{url}/api/ledger/missing")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "not_found");
+}
+
+#[tokio::test]
+async fn test_ledger_etag() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let url = start_server(p2p.clone()).await;
+    let http = reqwest::Client::new();
+    p2p.ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    let response = http.get(format!("{url}/api/ledger")).send().await.unwrap();
+    let etag = response.headers()[reqwest::header::ETAG].to_str().unwrap().to_string();
+
+    // An unchanged ledger isn't sent again
+    let response = http.get(format!("{url}/api/ledger")).header("if-none-match", &etag).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[reqwest::header::ETAG], etag.as_str());
+
+    // A new entry changes the tag
+    p2p.ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+    let response = http.get(format!("{url}/api/ledger")).header("if-none-match", &etag).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_ne!(response.headers()[reqwest::header::ETAG], etag.as_str());
+
+    // The client answers polls of an unchanged ledger from its cache
+    let client = GsioClient::new(&url).unwrap();
+    assert_eq!(client.get_ledger().await.unwrap().len(), 2);
+    assert_eq!(client.get_ledger().await.unwrap().len(), 2);
+    assert_eq!(client.get_ledger_paginated(1, 1).await.unwrap().len(), 1);
+    p2p.ledger.add_entry(json!({ "message": "Test entry 3" })).unwrap();
+    assert_eq!(client.get_ledger().await.unwrap().len(), 3);
+    assert_eq!(client.get_ledger_paginated(1, 1).await.unwrap()[0].data, json!({ "message": "Test entry 2" }));
+}
+
+#[tokio::test]
+async fn test_follower_redirects_writes() {
+    // A writable node and a follower pointing at it
+    let writer_id = "test-node-1".to_string();
+    let writer = Arc::new(P2PManager::new(writer_id.clone(), SharedLedger::new(writer_id)));
+    let writer_url = start_server(writer.clone()).await;
+
+    let follower_id = "test-node-2".to_string();
+    let mode = NodeMode::Follower { writable_node: Some(writer_url) };
+    let follower = Arc::new(P2PManager::new(follower_id.clone(), SharedLedger::new(follower_id)).with_mode(mode));
+    let client = GsioClient::new(&start_server(follower.clone()).await).unwrap();
+
+    // The client follows the redirect and the write lands on the writer
+    client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(writer.ledger.get_entries().len(), 1);
+    assert!(follower.ledger.get_entries().is_empty());
+}
+
+#[tokio::test]
+async fn test_follower_without_writer_forbids_writes() {
+    let node_id = "test-node-1".to_string();
+    let mode = NodeMode::Follower { writable_node: None };
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)).with_mode(mode));
+    let client = GsioClient::new(&start_server(p2p).await).unwrap();
+
+    let err = client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::ReadOnly));
+}
+
+#[tokio::test]
+async fn test_client_range_queries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    for i in 0..5 {
+        client.add_ledger_entry(json!({ "message": format!("Test entry {i}") })).await.unwrap();
+    }
+    let entries = p2p.ledger.get_entries();
+
+    // Pagination
+    let page = client.get_ledger_paginated(1, 2).await.unwrap();
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].id, entries[1].id);
+    assert_eq!(page[1].id, entries[2].id);
+    assert!(client.get_ledger_paginated(5, 2).await.unwrap().is_empty());
+
+    // Pages following an entry, which has to be on the chain
+    let page = client.get_ledger_after(&entries[1].hash, 2).await.unwrap().unwrap();
+    assert_eq!(page.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&entries[2].id, &entries[3].id]);
+    assert!(client.get_ledger_after(&entries[4].hash, 2).await.unwrap().unwrap().is_empty());
+    assert!(client.get_ledger_after(&"0".repeat(64), 2).await.unwrap().is_none());
+    let response = reqwest::get(format!("{}/api/ledger?after={}&creator=test-node-1", client.node_url(), entries[1].hash))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Entries after a timestamp
+    let newer = client.get_entries_since(entries[2].timestamp).await.unwrap();
+    assert_eq!(newer.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&entries[3].id, &entries[4].id]);
+
+    // Single entry lookup
+    let entry = client.get_entry_by_id(&entries[0].id).await.unwrap().unwrap();
+    assert_eq!(entry.hash, entries[0].hash);
+    assert!(client.get_entry_by_id("missing").await.unwrap().is_none());
+
+    // Entries by creator
+    let created = client.find_by_creator("test-node-1").await.unwrap();
+    assert_eq!(created.len(), 5);
+    assert_eq!(created[4].id, entries[4].id);
+    assert!(client.find_by_creator("test-node-2").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_query_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    for i in 0..10 {
+        let kind = if i % 2 == 0 { "transfer" } else { "note" };
+        client.add_ledger_entry(json!({ "kind": kind, "amount": i * 10, "tags": [format!("tag-{i}")] })).await.unwrap();
+    }
+    let entries = p2p.ledger.get_entries();
+
+    // Filters on data fields all have to hold
+    let query = Query::new().filter(Filter::eq("$.kind", "transfer")).filter(Filter::gte("$.amount", 40));
+    let page = client.query_entries(query).await.unwrap();
+    assert_eq!(page.entries.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&entries[4].id, &entries[6].id, &entries[8].id]);
+    assert_eq!(page.next_offset, None);
+    let page = client.query_entries(Query::new().filter(Filter::contains("$.tags", "tag-3"))).await.unwrap();
+    assert_eq!(page.entries[0].id, entries[3].id);
+
+    // Pages follow on from `next_offset`
+    let query = Query::new().filter(Filter::eq("kind", "note")).limit(2);
+    let first = client.query_entries(query.clone()).await.unwrap();
+    assert_eq!(first.entries.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&entries[1].id, &entries[3].id]);
+    assert_eq!(first.next_offset, Some(2));
+    let second = client.query_entries(query.clone().offset(4)).await.unwrap();
+    assert_eq!(second.entries[0].id, entries[9].id);
+    assert_eq!(second.next_offset, None);
+
+    // Creator and time range
+    let page = client.query_entries(Query::new().creator("test-node-1").since(entries[7].timestamp)).await.unwrap();
+    assert!(page.entries.iter().all(|e| e.timestamp > entries[7].timestamp));
+    assert!(client.query_entries(Query::new().creator("test-node-2")).await.unwrap().entries.is_empty());
+    let page = client.query_entries(Query::new().until(entries[0].timestamp)).await.unwrap();
+    assert!(page.entries.iter().any(|e| e.id == entries[0].id));
+
+    // Malformed queries are refused
+    let err = client.query_entries(Query::new().filter(Filter::eq("$.items[x]", 1))).await.unwrap_err();
+    assert!(matches!(
+        err,
+        GsioClientError::ServerError { code: Some(ErrorCode::BadRequest), message } if message.contains("Invalid path")
+    ));
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/ledger/query", client.node_url()))
+        .json(&json!({ "filters": [], "order": "desc" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_headers_and_proofs() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    for i in 0..5 {
+        client.add_ledger_entry(json!({ "message": format!("Test entry {i}") })).await.unwrap();
+    }
+    let entries = p2p.ledger.get_entries();
+    let root = p2p.ledger.merkle_root();
+
+    // Headers carry everything but the data
+    let page = client.get_headers(1, 2).await.unwrap();
+    assert_eq!(page.length, 5);
+    assert_eq!(page.root, root);
+    assert_eq!(page.headers.len(), 2);
+    assert_eq!(page.headers[0].hash, entries[1].hash);
+    assert_eq!(page.headers[1].previous_hash, entries[1].hash);
+
+    // Proofs verify against the root they were served with
+    let proof = client.get_entry_proof(&entries[3].id).await.unwrap().unwrap();
+    assert_eq!(proof.length, 5);
+    assert_eq!(proof.root, root);
+    assert!(gsio_client::verify_proof(&proof.proof, &root));
+    assert!(client.get_entry_proof("missing").await.unwrap().is_none());
+
+    // Entries fetched in full match their hash
+    let entry = client.get_entry_by_id(&entries[3].id).await.unwrap().unwrap();
+    assert_eq!(entry.calculate_hash(), entries[3].hash);
+
+    // Pages are capped however many headers are asked for
+    for i in 5..MAX_HEADERS_PAGE_SIZE + 1 {
+        p2p.ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    assert_eq!(client.get_headers(0, usize::MAX).await.unwrap().headers.len(), MAX_HEADERS_PAGE_SIZE);
+    let response = reqwest::get(format!("{}/api/ledger/headers", client.node_url())).await.unwrap();
+    let page: JsonValue = response.json().await.unwrap();
+    assert_eq!(page["headers"].as_array().unwrap().len(), HEADERS_PAGE_SIZE);
+}
+
+#[tokio::test]
+async fn test_light_client() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let url = start_server(p2p.clone()).await;
+    let mut light = LightClient::new(&url).unwrap();
+
+    for i in 0..3 {
+        p2p.ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    assert_eq!(light.sync_headers().await.unwrap(), 3);
+    assert_eq!(light.root(), p2p.ledger.merkle_root());
+    assert_eq!(light.sync_headers().await.unwrap(), 0);
+
+    let entries = p2p.ledger.get_entries();
+    let entry = light.get_verified_entry(&entries[1].id).await.unwrap().unwrap();
+    assert_eq!(entry.data, entries[1].data);
+    assert!(light.get_verified_entry("missing").await.unwrap().is_none());
+
+    // Entries added since the last sync are picked up when verifying them
+    let entry = p2p.ledger.add_entry(json!({ "message": "Test entry 3" })).unwrap();
+    assert!(light.get_verified_entry(&entry.id).await.unwrap().is_some());
+    assert_eq!(light.headers().len(), 4);
+    assert_eq!(light.root(), p2p.ledger.merkle_root());
+
+    // After pruning, the client drops the stale headers and starts over
+    p2p.ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 2 });
+    p2p.ledger.apply_retention();
+    p2p.ledger.add_entry(json!({ "message": "Test entry 4" })).unwrap();
+    assert!(light.get_verified_entry(&entries[2].id).await.unwrap().is_some());
+    assert_eq!(light.headers().len(), 3);
+    assert_eq!(light.root(), p2p.ledger.merkle_root());
+}
+
+#[tokio::test]
+async fn test_light_client_rejects_forged_entries() {
+    let node_id = "test-node-1".to_string();
+    let ledger = SharedLedger::new(node_id);
+    let entry = ledger.add_entry(json!({ "message": "Test entry" })).unwrap();
+    ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    // A node that serves genuine headers and proofs but tampered entry data
+    let headers = ledger.get_headers(0, usize::MAX);
+    let proof = ledger.get_entry_proof(&entry.id).unwrap();
+    let mut forged = entry.clone();
+    forged.data = json!({ "message": "Forged entry" });
+    let app = Router::new()
+        .route("/api/ledger/headers", get(move || {
+            let headers = headers.clone();
+            async move { Json(headers) }
+        }))
+        .route("/api/ledger/{id}/proof", get(move || {
+            let proof = proof.clone();
+            async move { Json(proof) }
+        }))
+        .route("/api/ledger/{id}", get(move || {
+            let forged = forged.clone();
+            async move { Json(forged) }
+        }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let mut light = LightClient::new(&url).unwrap();
+    light.sync_headers().await.unwrap();
+    let err = light.get_verified_entry(&entry.id).await.unwrap_err();
+    assert!(matches!(err, GsioClientError::VerificationError(_)));
+}
+
+#[tokio::test]
+async fn test_light_client_rejects_forged_headers() {
+    let node_id = "test-node-1".to_string();
+    let ledger = SharedLedger::new(node_id);
+    for i in 0..3 {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    // Headers that don't add up to the root the node reports
+    let mut headers = ledger.get_headers(0, usize::MAX);
+    headers.headers.swap(0, 1);
+    let app = Router::new().route("/api/ledger/headers", get(move || {
+        let headers = headers.clone();
+        async move { Json(headers) }
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let mut light = LightClient::new(&url).unwrap();
+    let err = light.sync_headers().await.unwrap_err();
+    assert!(matches!(err, GsioClientError::VerificationError(_)));
+    assert!(light.headers().is_empty());
+}
+
+/// Serve `/api/ledger` and `/api/nodes`, failing the first `failures` requests with `status`
+async fn start_flaky_server(failures: usize, status: StatusCode) -> (String, Arc<AtomicUsize>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let handler = move || {
+        let counter = counter.clone();
+        async move {
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                Err(status)
+            } else {
+                Ok(Json(json!({ "nodes": ["test-node-1"], "peers": [] })))
+            }
+        }
+    };
+    let app = Router::new()
+        .route("/api/nodes", get(handler.clone()))
+        .route("/api/ledger", axum::routing::post(handler));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (url, requests)
+}
+
+fn quick_backoff(url: &str) -> gsio_client::GsioClientBuilder {
+    GsioClient::builder(url).backoff(Duration::from_millis(1), Duration::from_millis(10))
+}
+
+#[tokio::test]
+async fn test_client_retries_server_errors() {
+    let (url, requests) = start_flaky_server(2, StatusCode::SERVICE_UNAVAILABLE).await;
+    let client = quick_backoff(&url).max_retries(2).build().unwrap();
+    assert_eq!(client.get_known_nodes().await.unwrap(), vec!["test-node-1".to_string()]);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    // Without enough retries the last error is returned
+    let (url, requests) = start_flaky_server(2, StatusCode::SERVICE_UNAVAILABLE).await;
+    let client = quick_backoff(&url).max_retries(1).build().unwrap();
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::ServerError { .. })));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // Client errors aren't retried
+    let (url, requests) = start_flaky_server(1, StatusCode::BAD_REQUEST).await;
+    let client = quick_backoff(&url).build().unwrap();
+    assert!(client.get_known_nodes().await.is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_client_retries_keyed_writes() {
+    // Writes carry an idempotency key, so one that failed is retried on the
+    // same node; the flaky server doesn't answer with an entry, so only the
+    // attempts count here
+    let (url, requests) = start_flaky_server(1, StatusCode::INTERNAL_SERVER_ERROR).await;
+    let client = quick_backoff(&url).build().unwrap();
+    assert!(client.add_ledger_entry(json!({ "message": "Test entry" })).await.is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_idempotency_keys() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    // A retried request gets the entry the first one added
+    let data = json!({ "message": "Test entry" });
+    let entry = client.add_ledger_entry_with_key(data.clone(), "key-1").await.unwrap();
+    let retried = client.add_ledger_entry_with_key(data.clone(), "key-1").await.unwrap();
+    assert_eq!(retried.id, entry.id);
+    assert_eq!(p2p.ledger.get_entries().len(), 1);
+
+    // Requests racing with the same key add one entry between them
+    let (first, second) = tokio::join!(
+        p2p.add_entry_data_once("key-2", json!({ "message": "Concurrent" })),
+        p2p.add_entry_data_once("key-2", json!({ "message": "Concurrent" })),
+    );
+    assert_eq!(first.unwrap().id, second.unwrap().id);
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    // A key can't be reused for other data
+    let err = client.add_ledger_entry_with_key(json!({ "message": "Other" }), "key-1").await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::Conflict));
+
+    // Without a key every request adds an entry
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/ledger", client.node_url()))
+        .json(&data)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    assert_eq!(p2p.ledger.get_entries().len(), 3);
+}
+
+#[tokio::test]
+async fn test_client_retries_connection_errors() {
+    // Reserve a port, then start the node on it after the client's first attempt
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let client = GsioClient::builder(&format!("http://{addr}"))
+        .backoff(Duration::from_millis(200), Duration::from_millis(200))
+        .jitter(false)
+        .max_retries(5)
+        .build()
+        .unwrap();
+    let server = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+        let listener = TcpListener::bind(addr).await.unwrap();
+        axum::serve(listener, api::router(p2p)).await.unwrap();
+    });
+
+    let entry = client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(entry.data, json!({ "message": "Test entry" }));
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_offline_queue() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let path = std::env::temp_dir().join(format!("gsio-queue-{}.jsonl", uuid::Uuid::new_v4()));
+    let build = || GsioClient::builder(&format!("http://{addr}")).max_retries(0).offline_queue(&path).build().unwrap();
+
+    // Entries written while the node is down are queued in order
+    let client = build();
+    let error = client.add_ledger_entry(json!({ "message": "Test entry 1" })).await.unwrap_err();
+    assert!(matches!(&error, GsioClientError::Queued(key) if *key == client.queued_entries()[0].key));
+    client.add_ledger_entry(json!({ "message": "Test entry 2" })).await.unwrap_err();
+    assert!(client.flush_queue().await.unwrap_err().is_unreachable());
+    assert_eq!(client.queued_entries().len(), 2);
+
+    // A client started later picks the queue up and sends it once the node is back
+    let client = Arc::new(build());
+    assert_eq!(client.queued_entries().len(), 2);
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let router = api::router(p2p.clone());
+    let server = tokio::spawn(async move {
+        axum::serve(TcpListener::bind(addr).await.unwrap(), router).await.unwrap();
+    });
+    let flusher = client.spawn_queue_flusher(Duration::from_millis(50));
+    for _ in 0..100 {
+        if client.queued_entries().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(client.queued_entries().is_empty());
+    let entries = p2p.ledger.get_entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].data, json!({ "message": "Test entry 1" }));
+
+    // Flushing again can't add them twice, and with the queue empty entries go straight to the node
+    assert_eq!(client.flush_queue().await.unwrap(), 0);
+    client.add_ledger_entry(json!({ "message": "Test entry 3" })).await.unwrap();
+    assert_eq!(p2p.ledger.get_entries().len(), 3);
+
+    flusher.abort();
+    server.abort();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_client_circuit_breaker() {
+    let (url, requests) = start_flaky_server(usize::MAX, StatusCode::SERVICE_UNAVAILABLE).await;
+    let client = quick_backoff(&url)
+        .max_retries(0)
+        .circuit_breaker(2, Duration::from_millis(200))
+        .build()
+        .unwrap();
+
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::ServerError { .. })));
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::ServerError { .. })));
+
+    // The breaker is open, so requests fail without reaching the node
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::CircuitOpen(_))));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // Once the timeout passes a trial request goes through
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::ServerError { .. })));
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert!(matches!(client.get_known_nodes().await, Err(GsioClientError::CircuitOpen(_))));
+}
+
+/// URL of a port nothing listens on
+fn unreachable_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[tokio::test]
+async fn test_multi_node_failover() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let healthy = start_server(p2p.clone()).await;
+    let (erroring, erroring_requests) = start_flaky_server(usize::MAX, StatusCode::SERVICE_UNAVAILABLE).await;
+    let down = unreachable_url();
+
+    let client = GsioClient::builder(&down)
+        .max_retries(1)
+        .backoff(Duration::from_millis(1), Duration::from_millis(10))
+        .build()
+        .unwrap();
+    assert!(client.get_known_nodes().await.is_err());
+
+    let client = gsio_client::GsioClientBuilder::new_multi(&[down.clone(), erroring.clone(), healthy.clone()])
+        .max_retries(1)
+        .backoff(Duration::from_millis(1), Duration::from_millis(10))
+        .build()
+        .unwrap();
+    assert_eq!(client.node_url(), down);
+
+    // The request skips past the unreachable and the erroring node
+    assert_eq!(client.get_known_nodes().await.unwrap(), vec!["test-node-1".to_string()]);
+    assert_eq!(erroring_requests.load(Ordering::SeqCst), 2);
+    assert_eq!(client.node_url(), healthy);
+
+    let health = client.node_health();
+    assert_eq!(health.iter().map(|h| h.url.as_str()).collect::<Vec<_>>(), vec![down.as_str(), erroring.as_str(), healthy.as_str()]);
+    assert!(!health[0].is_healthy());
+    assert!(health[0].last_error.is_some());
+    assert_eq!(health[1].consecutive_failures, 1);
+    assert!(health[2].is_healthy());
+    assert!(health[2].last_success.is_some());
+
+    // Later requests stick with the node that answered
+    client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(erroring_requests.load(Ordering::SeqCst), 2);
+    assert_eq!(p2p.ledger.get_entries().len(), 1);
+}
+
+#[tokio::test]
+async fn test_multi_node_writes_fail_over_only_when_unsent() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let healthy = start_server(p2p.clone()).await;
+
+    // An unreachable node never saw the write, so it goes to the next node
+    let client = GsioClient::new_multi(&[unreachable_url(), healthy.clone()]).unwrap();
+    client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(p2p.ledger.get_entries().len(), 1);
+
+    // A node that failed the write may have applied it, so it isn't resent
+    let (erroring, _) = start_flaky_server(usize::MAX, StatusCode::INTERNAL_SERVER_ERROR).await;
+    let client = GsioClient::new_multi(&[erroring, healthy]).unwrap();
+    assert!(matches!(
+        client.add_ledger_entry(json!({ "message": "Test entry 2" })).await,
+        Err(GsioClientError::ServerError { .. })
+    ));
+    assert_eq!(p2p.ledger.get_entries().len(), 1);
+
+    let no_nodes: &[&str] = &[];
+    assert!(GsioClient::new_multi(no_nodes).is_err());
+}
+
+#[tokio::test]
+async fn test_multi_node_skips_open_circuits() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let healthy = start_server(p2p).await;
+    let (erroring, erroring_requests) = start_flaky_server(usize::MAX, StatusCode::SERVICE_UNAVAILABLE).await;
+
+    let client = gsio_client::GsioClientBuilder::new_multi(&[erroring, healthy])
+        .max_retries(0)
+        .circuit_breaker(1, Duration::from_secs(60))
+        .build()
+        .unwrap();
+    client.get_known_nodes().await.unwrap();
+    assert!(client.node_health()[0].circuit_open);
+
+    // The failing node stays out of rotation until its breaker resets
+    client.get_known_nodes().await.unwrap();
+    client.get_known_nodes().await.unwrap();
+    assert_eq!(erroring_requests.load(Ordering::SeqCst), 1);
+}
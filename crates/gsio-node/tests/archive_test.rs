@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use gsio_node::api;
+use gsio_node::archive::{self, Archive, ArchiveSegment};
+use gsio_node::ledger::{LedgerEntry, RetentionPolicy, SharedLedger};
+use gsio_node::offload::{BlobRef, BlobStore, MemoryBlobStore, Offloader};
+use gsio_node::p2p::P2PManager;
+use serde_json::json;
+
+mod common;
+
+use common::start_server_with;
+
+const THRESHOLD: usize = 64;
+
+fn new_node(node_id: &str, store: Arc<MemoryBlobStore>) -> (Arc<P2PManager>, Arc<Archive>) {
+    let archive = Arc::new(Archive::new(store.clone()));
+    let offloader = Offloader::new(store, Some(THRESHOLD));
+    let p2p = P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+        .with_offloader(Arc::new(offloader))
+        .with_archive(archive.clone());
+    (Arc::new(p2p), archive)
+}
+
+async fn start_server(p2p: Arc<P2PManager>, archive: Arc<Archive>) -> String {
+    let app = api::router(p2p.clone()).merge(archive::router(archive));
+    start_server_with(p2p, app).await
+}
+
+#[tokio::test]
+async fn test_entries_are_archived_before_pruning() {
+    let store = Arc::new(MemoryBlobStore::new());
+    let (p2p, archive) = new_node("test-node-1", store.clone());
+    let mut entries = Vec::new();
+    for i in 0..10 {
+        let data = if i == 2 { json!({ "index": i, "message": "x".repeat(THRESHOLD * 2) }) } else { json!({ "index": i }) };
+        entries.push(p2p.add_entry_data(data).await.unwrap());
+    }
+
+    // Nothing is archived while the policy keeps everything
+    assert_eq!(archive.apply_retention(&p2p.ledger).await.unwrap(), 0);
+    assert!(archive.segments().is_empty());
+
+    p2p.ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 4 });
+    assert_eq!(archive.apply_retention(&p2p.ledger).await.unwrap(), 6);
+    assert_eq!(p2p.ledger.get_entries().len(), 4);
+    let segments = archive.segments();
+    assert_eq!(segments.len(), 1);
+    assert_eq!((segments[0].from, segments[0].to), (1, 6));
+    assert_eq!(segments[0].last_hash, entries[5].hash);
+
+    // The segment holds the pruned entries as they were on the chain
+    let bytes = store.get(segments[0].blob.clone(), None).await.unwrap().unwrap();
+    let archived: Vec<LedgerEntry> = serde_json::from_slice(&bytes).unwrap();
+    let ids = |entries: &[LedgerEntry]| entries.iter().map(|e| e.hash.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&archived), ids(&entries[..6]));
+
+    // Archived entries are still found, and pruned entries aren't archived twice
+    assert!(archive.contains(&entries[0].id));
+    assert!(!archive.contains(&entries[6].id));
+    assert_eq!(p2p.find_entry(&entries[3].id).await.unwrap().data, json!({ "index": 3 }));
+    assert_eq!(p2p.find_entry(&entries[8].id).await.unwrap().data, json!({ "index": 8 }));
+    assert!(p2p.find_entry("unknown").await.is_none());
+    assert_eq!(archive.apply_retention(&p2p.ledger).await.unwrap(), 0);
+
+    // The segment and the offloaded data of its entries are kept by blob GC
+    let offloaded = BlobRef::from_data(entries[2].blob.as_ref().unwrap()).unwrap();
+    assert_eq!(segments[0].data_blobs, vec![offloaded.hash.clone()]);
+    let referenced = archive.referenced_blobs();
+    assert!(referenced.contains(&segments[0].blob));
+    assert!(referenced.contains(&offloaded.hash));
+
+    // Later rounds add segments after the first
+    for i in 10..13 {
+        entries.push(p2p.add_entry_data(json!({ "index": i })).await.unwrap());
+    }
+    assert_eq!(archive.apply_retention(&p2p.ledger).await.unwrap(), 3);
+    let segments = archive.segments();
+    assert_eq!((segments[1].from, segments[1].to), (7, 9));
+    assert_eq!(p2p.find_entry(&entries[7].id).await.unwrap().hash, entries[7].hash);
+    assert_eq!(p2p.find_entry(&entries[1].id).await.unwrap().hash, entries[1].hash);
+}
+
+#[tokio::test]
+async fn test_archived_entries_are_served_by_the_api() {
+    let (p2p, archive) = new_node("test-node-1", Arc::new(MemoryBlobStore::new()));
+    let mut entries = Vec::new();
+    for i in 0..5 {
+        entries.push(p2p.add_entry_data(json!({ "index": i })).await.unwrap());
+    }
+    p2p.ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 2 });
+    archive.apply_retention(&p2p.ledger).await.unwrap();
+    let url = start_server(p2p.clone(), archive).await;
+
+    let response = reqwest::get(format!("{url}/api/ledger/{}", entries[0].id)).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.json::<LedgerEntry>().await.unwrap().hash, entries[0].hash);
+    let response = reqwest::get(format!("{url}/api/ledger/unknown")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let segments: Vec<ArchiveSegment> = reqwest::get(format!("{url}/api/ledger/archive")).await.unwrap().json().await.unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!((segments[0].from, segments[0].to), (1, 3));
+}
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use gsio_node::admin::{Admin, ConfigUpdate, RuntimeConfig};
+use gsio_node::api;
+use gsio_node::audit::{self, AuditEvent, AuditEventType, AuditFilter, AuditLog, AuditQuery, MEMORY_EVENTS};
+use gsio_node::ledger::{RetentionPolicy, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use gsio_node::validation::ValidationConfig;
+use serde_json::json;
+use uuid::Uuid;
+
+mod common;
+
+use common::start_server_with;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+async fn start_server(p2p: Arc<P2PManager>) -> String {
+    let app = api::router(p2p.clone()).merge(audit::router(p2p.audit()));
+    start_server_with(p2p, app).await
+}
+
+fn temp_log_path() -> PathBuf {
+    std::env::temp_dir().join(format!("gsio-audit-{}.jsonl", Uuid::new_v4()))
+}
+
+/// Every event in `audit` of type `event`
+fn events_of(audit: &AuditLog, event: AuditEventType) -> Vec<AuditEvent> {
+    let filter = AuditFilter { events: vec![event], ..AuditFilter::default() };
+    audit.query(&filter, usize::MAX).unwrap()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_audit_log_persists_across_restarts() {
+    let path = temp_log_path();
+    let audit = AuditLog::open(&path).unwrap();
+    audit.record(AuditEvent::new(AuditEventType::PeerConnected).with_peer("test-node-2"));
+    audit.record(AuditEvent::new(AuditEventType::EntryAccepted).with_entry("entry-1"));
+    drop(audit);
+
+    // A line cut short by a crash is skipped, and numbering carries on
+    std::fs::write(&path, std::fs::read_to_string(&path).unwrap() + "{\"seq\": 3, \"times\n").unwrap();
+    let audit = AuditLog::open(&path).unwrap();
+    audit.record(AuditEvent::new(AuditEventType::PeerDisconnected).with_peer("test-node-2"));
+
+    let events = audit.query(&AuditFilter::default(), usize::MAX).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(events[0].peer_id.as_deref(), Some("test-node-2"));
+    assert_eq!(events[1].entry_id.as_deref(), Some("entry-1"));
+    assert_eq!(events[2].event, AuditEventType::PeerDisconnected);
+}
+
+#[test]
+fn test_audit_log_rotates() {
+    let path = temp_log_path();
+    let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+    let audit = AuditLog::open(&path).unwrap().with_rotation(400, 2);
+    for i in 0..20 {
+        audit.record(AuditEvent::new(AuditEventType::EntryAccepted).with_entry(format!("entry-{i}")));
+    }
+
+    // The two newest full files are kept next to the current one, and queries read across them
+    assert!(rotated(1).exists() && rotated(2).exists() && !rotated(3).exists());
+    assert!(std::fs::metadata(&path).unwrap().len() < 400);
+    let events = audit.query(&AuditFilter::default(), usize::MAX).unwrap();
+    let seqs: Vec<u64> = events.iter().map(|e| e.seq).collect();
+    assert_eq!(*seqs.last().unwrap(), 20);
+    assert!(seqs.len() < 20);
+    assert!(seqs.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    let latest = audit.query(&AuditFilter::default(), 3).unwrap();
+    assert_eq!(latest.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![18, 19, 20]);
+    drop(audit);
+
+    // Numbering carries on from the rotated files when the current one is empty
+    std::fs::write(&path, "").unwrap();
+    let audit = AuditLog::open(&path).unwrap();
+    audit.record(AuditEvent::new(AuditEventType::PeerConnected));
+    let last = audit.query(&AuditFilter::default(), 1).unwrap();
+    for file in [path.clone(), rotated(1), rotated(2)] {
+        std::fs::remove_file(file).unwrap();
+    }
+    assert!(last[0].seq > 1);
+}
+
+#[test]
+fn test_audit_queries() {
+    let audit = AuditLog::in_memory();
+    let start = Utc::now();
+    for i in 0..5 {
+        audit.record(AuditEvent::new(AuditEventType::EntryAccepted).with_entry(format!("entry-{i}")));
+    }
+    audit.record(AuditEvent::new(AuditEventType::PeerConnected).with_peer("test-node-2"));
+
+    // The most recent events are returned, oldest first
+    let latest = audit.query(&AuditFilter::default(), 2).unwrap();
+    assert_eq!(latest.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![5, 6]);
+    assert_eq!(events_of(&audit, AuditEventType::EntryAccepted).len(), 5);
+
+    let query = AuditQuery { event: Some("peer_connected, entry_rejected".to_string()), ..AuditQuery::default() };
+    let filter = AuditFilter::try_from(&query).unwrap();
+    assert_eq!(audit.query(&filter, usize::MAX).unwrap().len(), 1);
+    let filter = AuditFilter { until: Some(start), ..AuditFilter::default() };
+    assert!(audit.query(&filter, usize::MAX).unwrap().is_empty());
+    let filter = AuditFilter { since: Some(start), ..AuditFilter::default() };
+    assert_eq!(audit.query(&filter, usize::MAX).unwrap().len(), 6);
+
+    let query = AuditQuery { event: Some("entry_created".to_string()), ..AuditQuery::default() };
+    assert!(AuditFilter::try_from(&query).is_err());
+}
+
+#[test]
+fn test_in_memory_log_keeps_recent_events() {
+    let audit = AuditLog::in_memory();
+    for _ in 0..MEMORY_EVENTS + 5 {
+        audit.record(AuditEvent::new(AuditEventType::EntryAccepted));
+    }
+    let events = audit.query(&AuditFilter::default(), usize::MAX).unwrap();
+    assert_eq!(events.len(), MEMORY_EVENTS);
+    assert_eq!(events[0].seq, 6);
+}
+
+#[tokio::test]
+async fn test_entries_are_audited() {
+    let p2p = Arc::new(new_node("test-node-1"));
+    let validation = ValidationConfig { required_fields: vec!["message".to_string()], ..ValidationConfig::default() };
+    p2p.ledger.set_validation_policy(Arc::new(validation.policy()));
+
+    let entry = p2p.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert!(p2p.add_local_entry(json!({ "text": "no message" })).is_err());
+    assert!(p2p.add_entry_data(json!({ "$blob": "0".repeat(64), "size": 1 })).await.is_err());
+
+    let accepted = events_of(&p2p.audit(), AuditEventType::EntryAccepted);
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(accepted[0].entry_id.as_deref(), Some(entry.id.as_str()));
+    let rejected = events_of(&p2p.audit(), AuditEventType::EntryRejected);
+    assert_eq!(rejected.len(), 2);
+    assert_eq!(rejected[0].details["code"], "missing_field");
+    assert!(rejected[0].peer_id.is_none());
+}
+
+#[tokio::test]
+async fn test_peers_and_their_entries_are_audited() {
+    let server = Arc::new(new_node("test-node-1"));
+    let url = start_server(server.clone()).await;
+    let client = Arc::new(new_node("test-node-2"));
+    assert!(client.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5)));
+
+    wait_for(Duration::from_secs(5), || !events_of(&server.audit(), AuditEventType::PeerConnected).is_empty()).await;
+    wait_for(Duration::from_secs(5), || !events_of(&client.audit(), AuditEventType::PeerConnected).is_empty()).await;
+    let connected = events_of(&server.audit(), AuditEventType::PeerConnected);
+    assert_eq!(connected[0].peer_id.as_deref(), Some("test-node-2"));
+    assert_eq!(connected[0].details["via"], "inbound");
+    let connected = events_of(&client.audit(), AuditEventType::PeerConnected);
+    assert_eq!(connected[0].peer_id.as_deref(), Some("test-node-1"));
+    assert_eq!(connected[0].details["url"], url.as_str());
+
+    // The client records the entry it learned from its peer
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    let accepted = events_of(&client.audit(), AuditEventType::EntryAccepted);
+    assert!(accepted.iter().any(|e| e.entry_id.as_deref() == Some(entry.id.as_str()) && e.peer_id.as_deref() == Some("test-node-1")));
+
+    assert!(server.disconnect_peer("test-node-2").await);
+    let disconnected = events_of(&server.audit(), AuditEventType::PeerDisconnected);
+    assert_eq!(disconnected[0].peer_id.as_deref(), Some("test-node-2"));
+
+    // The audit log is served over HTTP
+    let http = reqwest::Client::new();
+    let response = http.get(format!("{url}/admin/audit?event=peer_connected,peer_disconnected")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let events: Vec<AuditEvent> = response.json().await.unwrap();
+    assert!(events.len() >= 2);
+    assert!(events.iter().all(|e| matches!(e.event, AuditEventType::PeerConnected | AuditEventType::PeerDisconnected)));
+    let since = urlencode(&Utc::now().to_rfc3339());
+    let response = http.get(format!("{url}/admin/audit?since={since}")).send().await.unwrap();
+    let events: Vec<AuditEvent> = response.json().await.unwrap();
+    assert!(events.is_empty());
+    let events: Vec<AuditEvent> = http.get(format!("{url}/admin/audit?limit=1")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(events.len(), 1);
+    let response = http.get(format!("{url}/admin/audit?event=reorg")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_admin_actions_are_audited() {
+    let p2p = Arc::new(new_node("test-node-1"));
+    let admin = Admin::new(p2p.clone(), RuntimeConfig::default());
+    let update = ConfigUpdate { retention: Some(RetentionPolicy::KeepLast { entries: 2 }), ..ConfigUpdate::default() };
+    admin.update_config(update);
+    admin.request_shutdown();
+
+    let actions = events_of(&p2p.audit(), AuditEventType::AdminAction);
+    assert_eq!(actions.len(), 2);
+    assert_eq!(actions[0].details["action"], "set_retention");
+    assert_eq!(actions[0].details["retention"], json!({ "type": "keep_last", "entries": 2 }));
+    assert_eq!(actions[1].details["action"], "shutdown");
+}
+
+/// Percent-encode the characters of an RFC 3339 time that can't go in a query string as they are
+fn urlencode(value: &str) -> String {
+    value.replace('+', "%2B").replace(':', "%3A")
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use axum::{middleware, Router};
+use ed25519_dalek::{Signer, SigningKey};
+use gsio_client::{GsioClient, GsioClientError, GsioSocketClient};
+use gsio_node::api;
+use gsio_node::auth::{self, AuthConfig, Authenticator, TokenRequest};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::socket;
+use rand::rngs::OsRng;
+use serde_json::json;
+use socketioxide::SocketIo;
+use tokio::net::TcpListener;
+
+const API_KEY: &str = "test-api-key";
+
+fn auth_config(authorized: &SigningKey) -> AuthConfig {
+    AuthConfig {
+        api_keys: vec![API_KEY.to_string()],
+        authorized_keys: vec![hex::encode(authorized.verifying_key().to_bytes())],
+    }
+}
+
+fn sign(key: &SigningKey, challenge: &str) -> TokenRequest {
+    TokenRequest {
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+        challenge: challenge.to_string(),
+        signature: hex::encode(key.sign(challenge.as_bytes()).to_bytes()),
+    }
+}
+
+/// Serve the API and Socket.IO namespace the way a node with `[auth]` set does
+async fn start_server(p2p: Arc<P2PManager>, auth: Arc<Authenticator>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let (layer, io) = SocketIo::builder().build_layer();
+    socket::register_authenticated_root_namespace(&io, p2p.clone(), auth.clone(), None);
+    let app: Router = api::router(p2p)
+        .route_layer(middleware::from_fn_with_state(auth.clone(), auth::require_auth))
+        .merge(auth::router(auth))
+        .layer(layer);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    url
+}
+
+#[test]
+fn test_authenticator() {
+    let key = SigningKey::generate(&mut OsRng);
+    let auth = Authenticator::new(&auth_config(&key)).unwrap();
+    assert!(auth.is_authorized(API_KEY));
+    assert!(!auth.is_authorized("wrong-key"));
+
+    // A signed challenge is exchanged for a token once
+    let challenge = auth.issue_challenge().challenge;
+    let token = auth.issue_token(&sign(&key, &challenge)).unwrap().token;
+    assert!(auth.is_authorized(&token));
+    assert!(auth.issue_token(&sign(&key, &challenge)).is_err());
+
+    // Keys that aren't listed, and signatures over something else, are refused
+    let stranger = SigningKey::generate(&mut OsRng);
+    let challenge = auth.issue_challenge().challenge;
+    assert!(auth.issue_token(&sign(&stranger, &challenge)).is_err());
+    let challenge = auth.issue_challenge().challenge;
+    let forged = TokenRequest {
+        challenge: challenge.clone(),
+        ..sign(&key, "another challenge")
+    };
+    assert!(auth.issue_token(&forged).is_err());
+    assert!(auth.issue_token(&sign(&key, "made-up challenge")).is_err());
+
+    assert!(auth.check_socket_auth(&json!({ "token": token })).is_ok());
+    assert!(auth.check_socket_auth(&json!({ "token": "wrong-key" })).is_err());
+    assert!(auth.check_socket_auth(&json!({})).is_err());
+
+    assert!(!AuthConfig::default().is_enabled());
+    let invalid = AuthConfig {
+        authorized_keys: vec!["not a key".to_string()],
+        ..AuthConfig::default()
+    };
+    assert!(Authenticator::new(&invalid).is_err());
+}
+
+#[test]
+fn test_challenges_are_capped() {
+    let key = SigningKey::generate(&mut OsRng);
+    let auth = Authenticator::new(&auth_config(&key)).unwrap();
+    let first = auth.issue_challenge().challenge;
+    let second = auth.issue_challenge().challenge;
+    for _ in 2..auth::MAX_CHALLENGES {
+        auth.issue_challenge();
+    }
+
+    // Past the cap, the oldest challenge makes room for the new one
+    auth.issue_challenge();
+    assert!(auth.issue_token(&sign(&key, &first)).is_err());
+    assert!(auth.issue_token(&sign(&key, &second)).is_ok());
+}
+
+#[tokio::test]
+async fn test_api_requires_auth() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let key = SigningKey::generate(&mut OsRng);
+    let url = start_server(p2p, Arc::new(Authenticator::new(&auth_config(&key)).unwrap())).await;
+
+    let response = reqwest::get(format!("{url}/api/ledger")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let err = GsioClient::new(&url).unwrap().get_ledger().await.unwrap_err();
+    assert!(matches!(err, GsioClientError::AuthError(_)));
+    let err = GsioClient::new(&url).unwrap().with_api_key("wrong-key").get_ledger().await.unwrap_err();
+    assert!(matches!(err, GsioClientError::AuthError(_)));
+
+    let client = GsioClient::new(&url).unwrap().with_api_key(API_KEY);
+    let entry = client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(client.get_ledger().await.unwrap().len(), 1);
+
+    // A keypair logs in once and reuses the session
+    let client = GsioClient::new(&url).unwrap().with_keypair(key);
+    assert_eq!(client.get_entry_by_id(&entry.id).await.unwrap().unwrap().id, entry.id);
+    assert_eq!(client.get_ledger().await.unwrap().len(), 1);
+
+    let stranger = GsioClient::new(&url).unwrap().with_keypair(SigningKey::generate(&mut OsRng));
+    let err = stranger.get_ledger().await.unwrap_err();
+    assert!(matches!(err, GsioClientError::AuthError(_)));
+}
+
+#[tokio::test]
+async fn test_socket_requires_auth() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let key = SigningKey::generate(&mut OsRng);
+    let url = start_server(p2p.clone(), Arc::new(Authenticator::new(&auth_config(&key)).unwrap())).await;
+
+    let err = GsioSocketClient::connect(&url).await.err().unwrap();
+    assert!(matches!(err, GsioClientError::AuthError(_)));
+    let err = GsioSocketClient::connect_with_token(&url, "wrong-key").await.err().unwrap();
+    assert!(matches!(err, GsioClientError::AuthError(_)));
+
+    let client = GsioSocketClient::connect_with_token(&url, API_KEY).await.unwrap();
+    client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(client.get_ledger().await.unwrap().len(), 1);
+    client.disconnect().await.unwrap();
+
+    // Subscriptions log in with the client's keypair first
+    let client = GsioClient::new(&url).unwrap().with_keypair(key);
+    client.subscribe_entries().await.unwrap().close().await.unwrap();
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use gsio_node::admin::{self, Admin, AdminConfig, RuntimeConfig};
+use gsio_node::backup::{self, Backup, BACKUP_FORMAT};
+use gsio_node::config::{self, NodeConfig};
+use gsio_node::export;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::offload::{MemoryBlobStore, Offloader};
+use gsio_node::p2p::P2PManager;
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+
+mod common;
+
+use common::start_server_with;
+
+const ADMIN_KEY: &str = "test-admin-key";
+const THRESHOLD: usize = 64;
+
+fn new_node(node_id: &str, key_file: &Path) -> Arc<P2PManager> {
+    let key = config::load_or_create_key(key_file).unwrap();
+    let offloader = Offloader::new(Arc::new(MemoryBlobStore::new()), Some(THRESHOLD));
+    Arc::new(
+        P2PManager::new(node_id.to_string(), SharedLedger::with_signing_key(node_id.to_string(), key))
+            .with_offloader(Arc::new(offloader)),
+    )
+}
+
+async fn start_admin(p2p: Arc<P2PManager>, admin: Arc<Admin>) -> String {
+    let config = AdminConfig { api_keys: vec![ADMIN_KEY.to_string()] };
+    let app = gsio_node::api::router(p2p.clone()).merge(admin::router(admin, &config).unwrap());
+    start_server_with(p2p, app).await
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gsio-node-{}-{name}", Uuid::new_v4()))
+}
+
+fn large_data(i: usize) -> JsonValue {
+    json!({ "index": i, "message": "x".repeat(THRESHOLD * 2) })
+}
+
+#[tokio::test]
+async fn test_backup_and_restore() {
+    let key_file = temp_path("node.key");
+    let source = new_node("test-node-1", &key_file);
+    for i in 0..20 {
+        let data = if i % 5 == 0 { large_data(i) } else { json!({ "index": i }) };
+        source.add_entry_data(data).await.unwrap();
+    }
+    source.contacts().set_url("test-node-2", "http://peer.example:3000".to_string());
+    let admin = Arc::new(Admin::new(source.clone(), RuntimeConfig::default()).with_key_file(&key_file));
+    let url = start_admin(source.clone(), admin).await;
+
+    // Only admins can take a backup
+    let response = reqwest::get(format!("{url}/admin/backup")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let out = temp_path("backup.tar.zst");
+    assert!(backup::fetch(&url, "client-key", &out).await.is_err());
+    assert!(!out.exists());
+
+    let manifest = backup::fetch(&url, ADMIN_KEY, &out).await.unwrap();
+    assert_eq!(manifest.format, BACKUP_FORMAT);
+    assert_eq!((manifest.node_id.as_str(), manifest.height), ("test-node-1", 20));
+    assert!(manifest.has_key);
+    let backup = Backup::from_archive(std::fs::File::open(&out).unwrap()).unwrap();
+    assert_eq!(backup.peers, vec!["http://peer.example:3000".to_string()]);
+
+    // Restoring needs somewhere to put the ledger and key
+    let mut config = NodeConfig::default();
+    assert!(backup::restore(&out, &config, false).unwrap_err().contains("import"));
+    config.import = Some(temp_path("ledger.ndjson"));
+    assert!(backup::restore(&out, &config, false).unwrap_err().contains("node_key"));
+
+    // Existing files are only replaced when forced
+    config.node_key = Some(temp_path("restored.key"));
+    std::fs::write(config.import.as_ref().unwrap(), "").unwrap();
+    assert!(backup::restore(&out, &config, false).unwrap_err().contains("--force"));
+    backup::restore(&out, &config, true).unwrap();
+
+    // The restored node has the same key and loads the same chain, blobs included
+    let target = new_node("test-node-1", config.node_key.as_ref().unwrap());
+    assert_eq!(target.ledger.public_key(), source.ledger.public_key());
+    let ledger = tokio::fs::read(config.import.as_ref().unwrap()).await.unwrap();
+    assert_eq!(export::import(&target, ledger.as_slice()).await.unwrap(), 20);
+    assert_eq!(target.ledger.merkle_root(), source.ledger.merkle_root());
+    let first = target.ledger.get_entries()[0].clone();
+    assert_eq!(target.rehydrate_entry(first).await.data, large_data(0));
+
+    for path in [key_file, out, config.import.unwrap(), config.node_key.unwrap()] {
+        std::fs::remove_file(path).ok();
+    }
+}
+
+#[test]
+fn test_backup_rejects_other_archives() {
+    assert!(Backup::from_archive(&b"not an archive"[..]).is_err());
+    let empty = zstd::encode_all(&[0u8; 1024][..], 0).unwrap();
+    assert!(Backup::from_archive(empty.as_slice()).unwrap_err().contains("manifest"));
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::bandwidth::{Bandwidth, BandwidthConfig, Direction};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{MessageType, P2PManager, P2PMessage};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+fn shared_ledger_with_entries(node_id: &str, count: usize) -> SharedLedger {
+    let ledger = SharedLedger::new(node_id.to_string());
+    for i in 1..=count {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    ledger
+}
+
+fn sync_request(sender_id: &str) -> P2PMessage {
+    P2PMessage::new(
+        MessageType::LedgerSyncRequest,
+        sender_id.to_string(),
+        "test-node-1".to_string(),
+        json!({ "from": 1, "limit": 100 }),
+    )
+}
+
+/// Whether `wait` is `expected`, less the little time that passed while reserving
+fn about(wait: Duration, expected: Duration) -> bool {
+    wait <= expected && wait + Duration::from_millis(50) >= expected
+}
+
+#[test]
+fn test_bandwidth_buckets() {
+    assert!(!BandwidthConfig::default().is_enabled());
+    let bandwidth = Bandwidth::new(BandwidthConfig { upload: 2000, peer_upload: 1000, ..BandwidthConfig::default() });
+    assert!(bandwidth.is_limited(Direction::Upload));
+    assert!(!bandwidth.is_limited(Direction::Download));
+
+    // A bucket holds a second's worth of bytes and may go into debt
+    assert_eq!(bandwidth.reserve("test-node-2", Direction::Upload, 600), Duration::ZERO);
+    assert!(about(bandwidth.reserve("test-node-2", Direction::Upload, 900), Duration::from_millis(500)));
+
+    // Another peer has a bucket of its own, but shares the overall one
+    assert!(about(bandwidth.reserve("test-node-3", Direction::Upload, 1000), Duration::from_millis(250)));
+
+    // Unlimited traffic never waits
+    assert_eq!(bandwidth.reserve("test-node-2", Direction::Download, 1_000_000), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_sync_pages_are_held_back() {
+    let ledger = shared_ledger_with_entries("test-node-1", 20);
+    let page = serde_json::to_vec(&ledger.sync_page(1, 100)).unwrap().len() as u64;
+
+    // Room for two pages at once, then one every half second
+    let (sender, mut outbox) = mpsc::unbounded_channel();
+    let bandwidth = Bandwidth::new(BandwidthConfig { peer_upload: page * 2, ..BandwidthConfig::default() });
+    let node = P2PManager::new("test-node-1".to_string(), ledger)
+        .with_bandwidth(Arc::new(bandwidth))
+        .with_outbox(sender);
+    assert!(node.handle_message(sync_request("test-node-2")).is_some());
+    assert!(node.handle_message(sync_request("test-node-2")).is_some());
+
+    // The next page goes out once the bucket has room for it again
+    let request = sync_request("test-node-2");
+    assert!(node.handle_message(request.clone()).is_none());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(outbox.try_recv().is_err());
+    let reply = tokio::time::timeout(Duration::from_secs(2), outbox.recv()).await.unwrap().unwrap();
+    assert!(matches!(reply.message_type, MessageType::LedgerSyncResponse));
+    assert_eq!(reply.recipient_id, "test-node-2");
+    assert_eq!(reply.in_reply_to, Some(request.message_id));
+    assert_eq!(reply.payload["entries"].as_array().unwrap().len(), 20);
+
+    // Other peers aren't held back by it
+    assert!(node.handle_message(sync_request("test-node-3")).is_some());
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use gsio_node::genesis::Genesis;
+use gsio_node::ledger::{Ledger, SharedLedger};
+use gsio_node::p2p::{EntryRejection, MessageType, P2PManager, P2PMessage};
+use gsio_node::validation::ErrorCode;
+use serde_json::json;
+
+mod common;
+
+use common::start_server;
+
+fn ledger_on(node_id: &str, chain_id: Option<&str>) -> Ledger {
+    let mut ledger = Ledger::new(node_id.to_string());
+    ledger.set_chain_id(chain_id.map(str::to_string)).unwrap();
+    ledger
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_entries_carry_chain_id() {
+    let mut testnet = ledger_on("test-node-1", Some("gsio-testnet"));
+    let entry = testnet.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert_eq!(entry.chain_id.as_deref(), Some("gsio-testnet"));
+    assert!(entry.is_valid());
+
+    // Another network's ledger drops the entry, however it arrives
+    let mut mainnet = ledger_on("test-node-2", Some("gsio-mainnet"));
+    mainnet.add_node_key("test-node-1".to_string(), &testnet.public_key()).unwrap();
+    assert_eq!(mainnet.check_chain(&entry).unwrap_err().code, ErrorCode::WrongChain);
+    assert!(!mainnet.add_pending_entry(entry.clone()));
+    let mut unnamed = ledger_on("test-node-3", None);
+    unnamed.add_node_key("test-node-1".to_string(), &testnet.public_key()).unwrap();
+    assert!(!unnamed.add_pending_entry(entry.clone()));
+
+    // The same network takes it
+    let mut peer = ledger_on("test-node-4", Some("gsio-testnet"));
+    peer.add_node_key("test-node-1".to_string(), &testnet.public_key()).unwrap();
+    assert!(peer.add_pending_entry(entry.clone()));
+    assert_eq!(peer.process_pending_entries().len(), 1);
+
+    // Snapshots of another network can't be restored
+    let snapshot = testnet.create_snapshot(1).unwrap();
+    mainnet.set_checkpoint_keys(&[testnet.public_key()]).unwrap();
+    assert!(mainnet.restore_from_snapshot(&snapshot).is_err());
+    let mut restored = ledger_on("test-node-5", Some("gsio-testnet"));
+    restored.set_checkpoint_keys(&[testnet.public_key()]).unwrap();
+    assert!(restored.restore_from_snapshot(&snapshot).is_ok());
+}
+
+#[test]
+fn test_chain_id_is_fixed_once_entries_exist() {
+    let mut ledger = ledger_on("test-node-1", Some("gsio-testnet"));
+    ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert!(ledger.set_chain_id(Some("gsio-mainnet".to_string())).is_err());
+    assert_eq!(ledger.chain_id(), Some("gsio-testnet"));
+
+    // A genesis puts the chain on its network, and the two have to agree
+    let genesis = Genesis {
+        chain_id: "gsio-mainnet".to_string(),
+        timestamp: Utc::now(),
+        validators: Vec::new(),
+        allocations: Default::default(),
+    };
+    let mut ledger = Ledger::new("test-node-2".to_string());
+    ledger.set_genesis(genesis.clone()).unwrap();
+    assert_eq!(ledger.chain_id(), Some("gsio-mainnet"));
+    assert!(ledger.set_chain_id(Some("gsio-testnet".to_string())).is_err());
+    assert!(ledger.set_chain_id(None).is_err());
+    assert!(ledger_on("test-node-3", Some("gsio-testnet")).set_genesis(genesis).is_err());
+}
+
+#[test]
+fn test_entries_from_another_network_are_rejected() {
+    let mut testnet = ledger_on("test-node-1", Some("gsio-testnet"));
+    let entry = testnet.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    let ledger = SharedLedger::new("test-node-2".to_string());
+    ledger.set_chain_id(Some("gsio-mainnet".to_string())).unwrap();
+    ledger.add_node_key("test-node-1".to_string(), &testnet.public_key()).unwrap();
+    let receiver = P2PManager::new("test-node-2".to_string(), ledger);
+    let announce =
+        P2PMessage::new(MessageType::EntryAnnounce, "test-node-1".to_string(), "".to_string(), serde_json::to_value(&entry).unwrap());
+    let reply = receiver.handle_message(announce).unwrap();
+    assert!(matches!(reply.message_type, MessageType::EntryRejected));
+    let rejections: Vec<EntryRejection> = serde_json::from_value(reply.payload["rejections"].clone()).unwrap();
+    assert_eq!(rejections[0].error.code, ErrorCode::WrongChain);
+    assert!(receiver.ledger.get_entry_by_id(&entry.id).is_none());
+}
+
+#[tokio::test]
+async fn test_networks_stay_apart() {
+    let nodes: Vec<Arc<P2PManager>> = [
+        ("test-node-1", Some("gsio-testnet")),
+        ("test-node-2", Some("gsio-testnet")),
+        ("test-node-3", Some("gsio-mainnet")),
+        ("test-node-4", None),
+    ]
+    .into_iter()
+    .map(|(id, chain_id)| {
+        let ledger = SharedLedger::new(id.to_string());
+        ledger.set_chain_id(chain_id.map(str::to_string)).unwrap();
+        Arc::new(P2PManager::new(id.to_string(), ledger))
+    })
+    .collect();
+
+    let url = start_server(nodes[0].clone()).await;
+    nodes[1].dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || nodes[0].peer_health().len() == 1).await;
+    let entry = nodes[0].add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || nodes[1].ledger.get_entry_by_id(&entry.id).is_some()).await;
+    assert_eq!(nodes[1].ledger.get_entry_by_id(&entry.id).unwrap().chain_id.as_deref(), Some("gsio-testnet"));
+
+    // Nodes on another network, or on none, aren't let in
+    for node in &nodes[2..] {
+        node.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(nodes[0].peer_health().len(), 1);
+    for node in &nodes[2..] {
+        assert!(node.peer_health().is_empty());
+        assert!(node.ledger.get_entry_by_id(&entry.id).is_none());
+    }
+
+    for node in &nodes[1..] {
+        node.leave().await;
+    }
+}
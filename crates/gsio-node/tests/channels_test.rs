@@ -0,0 +1,144 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::Router;
+use gsio_node::api;
+use gsio_node::channels::{self, ChannelInfo, Channels};
+use gsio_node::ledger::{LedgerEntry, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use serde_json::{json, Value as JsonValue};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    SocketIo,
+};
+use tokio::net::TcpListener;
+
+fn new_node(node_id: &str) -> Arc<P2PManager> {
+    Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())))
+}
+
+fn new_channel(node_id: &str, name: &str) -> Arc<P2PManager> {
+    Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())).with_channel(name))
+}
+
+fn new_channels(node_id: &str, names: &[&str]) -> Arc<Channels> {
+    let mut channels = Channels::new();
+    for name in names {
+        channels.insert(new_channel(node_id, name)).unwrap();
+    }
+    Arc::new(channels)
+}
+
+async fn start_server(p2p: Arc<P2PManager>, channels: Arc<Channels>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (layer, io) = SocketIo::builder().build_layer();
+    for (_, manager) in channels.iter().chain([("", &p2p)]) {
+        let manager = manager.clone();
+        io.ns(manager.namespace(), move |socket: SocketRef, Data(data): Data<JsonValue>| {
+            manager.handle_connection(socket, data);
+        });
+    }
+    let app: Router = api::router(p2p).merge(channels::router(channels)).layer(layer);
+
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    format!("http://{addr}")
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_channel_names() {
+    for name in ["payments", "chat-2", "app_1"] {
+        assert!(channels::validate_channel_name(name).is_ok(), "{name} should be accepted");
+    }
+    for name in ["", "Payments", "a/b", "a b", &"x".repeat(channels::MAX_CHANNEL_NAME_LEN + 1)] {
+        assert!(channels::validate_channel_name(name).is_err(), "{name:?} should be rejected");
+    }
+
+    let mut channels = Channels::new();
+    channels.insert(new_channel("test-node-1", "payments")).unwrap();
+    assert!(channels.insert(new_channel("test-node-1", "payments")).unwrap_err().contains("twice"));
+    assert!(channels.insert(new_channel("test-node-1", "../admin")).is_err());
+    assert!(channels.insert(new_node("test-node-1")).is_err());
+    assert_eq!(channels.len(), 1);
+    assert_eq!(new_channel("test-node-1", "payments").namespace(), "/p2p/payments");
+    assert_eq!(new_node("test-node-1").namespace(), "/p2p");
+}
+
+#[tokio::test]
+async fn test_channels_keep_their_entries_apart() {
+    let p2p = new_node("test-node-1");
+    let channels = new_channels("test-node-1", &["payments", "chat"]);
+    let url = start_server(p2p.clone(), channels.clone()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{url}/api/channels/payments/ledger"))
+        .json(&json!({ "amount": 5 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let entry: LedgerEntry = response.json().await.unwrap();
+    client.post(format!("{url}/api/ledger")).json(&json!({ "main": true })).send().await.unwrap();
+
+    // Each ledger only holds its own entries
+    let payments: Vec<LedgerEntry> = client.get(format!("{url}/api/channels/payments/ledger")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(payments.len(), 1);
+    assert_eq!(payments[0].data, json!({ "amount": 5 }));
+    let chat: Vec<LedgerEntry> = client.get(format!("{url}/api/channels/chat/ledger")).send().await.unwrap().json().await.unwrap();
+    assert!(chat.is_empty());
+    let main: Vec<LedgerEntry> = client.get(format!("{url}/api/ledger")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(main.len(), 1);
+    assert_eq!(main[0].data, json!({ "main": true }));
+
+    let response = client.get(format!("{url}/api/channels/payments/ledger/{}", entry.id)).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let response = client.get(format!("{url}/api/ledger/{}", entry.id)).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let response = client.get(format!("{url}/api/channels/unknown/ledger")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let listed: Vec<ChannelInfo> = client.get(format!("{url}/api/channels")).send().await.unwrap().json().await.unwrap();
+    let summary: Vec<(String, usize)> = listed.into_iter().map(|c| (c.name, c.height)).collect();
+    assert_eq!(summary, vec![("chat".to_string(), 0), ("payments".to_string(), 1)]);
+}
+
+#[tokio::test]
+async fn test_channels_sync_separately() {
+    let source = new_node("test-node-1");
+    let source_channels = new_channels("test-node-1", &["payments", "chat"]);
+    source.ledger.add_entry(json!({ "main": true })).unwrap();
+    let payments = source_channels.get("payments").unwrap();
+    for i in 0..3 {
+        payments.ledger.add_entry(json!({ "amount": i })).unwrap();
+    }
+    source_channels.get("chat").unwrap().ledger.add_entry(json!({ "text": "hello" })).unwrap();
+    let url = start_server(source.clone(), source_channels.clone()).await;
+
+    // A node hosting only the payments channel syncs just that ledger
+    let node = new_channel("test-node-2", "payments");
+    node.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || node.ledger.entry_count() == 3).await;
+    assert_eq!(node.ledger.chain_tip(), payments.ledger.chain_tip());
+    assert!(node.ledger.get_entries().iter().all(|entry| entry.data.get("amount").is_some()));
+
+    // Entries added later reach it over the channel's connection
+    payments.add_local_entry(json!({ "amount": 3 })).unwrap();
+    wait_for(Duration::from_secs(5), || node.ledger.entry_count() == 4).await;
+    assert!(payments.ledger.get_known_nodes().contains("test-node-2"));
+    assert!(!source.ledger.get_known_nodes().contains("test-node-2"));
+
+    node.leave().await;
+}
@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use futures::FutureExt;
+use gsio_node::codec::{Codec, Frame};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{handshake_transcript, sign_challenge, MessageType, P2PManager, P2PMessage};
+use rand::rngs::OsRng;
+use rust_socketio::{
+    asynchronous::{Client as PeerClient, ClientBuilder},
+    Payload,
+};
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+/// Connect to a node's `/p2p` namespace as `node_id` offering `codecs`, if
+/// any, answering its challenge and recording every message the node sends
+/// along with whether it came as a binary attachment
+async fn connect_peer(url: &str, node_id: &str, codecs: Option<JsonValue>) -> (PeerClient, Arc<Mutex<Vec<(P2PMessage, bool)>>>) {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut auth = json!({
+        "node_id": node_id,
+        "public_key": hex::encode(key.verifying_key().to_bytes()),
+        "challenge": "test-challenge",
+    });
+    if let Some(codecs) = codecs {
+        auth["codecs"] = codecs;
+    }
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (peer_id, messages) = (node_id.to_string(), received.clone());
+    let client = ClientBuilder::new(url)
+        .namespace("/p2p")
+        .auth(auth.clone())
+        .on("p2p_message", move |payload: Payload, client: PeerClient| {
+            let (key, peer_id, messages, auth) = (key.clone(), peer_id.clone(), messages.clone(), auth.clone());
+            async move {
+                let (frame, binary) = match payload {
+                    Payload::Text(values) => (Frame::Json(values[0].clone()), false),
+                    Payload::Binary(bytes) => (Frame::Binary(bytes.to_vec()), true),
+                    _ => return,
+                };
+                let message = frame.decode().unwrap();
+                messages.lock().unwrap().push((message.clone(), binary));
+                if matches!(message.message_type, MessageType::AuthChallenge) {
+                    let transcript = handshake_transcript(&peer_id, &auth, &message.sender_id, &message.payload);
+                    let signature = sign_challenge(&key, &transcript, &peer_id, &message.sender_id);
+                    let response = P2PMessage::new(
+                        MessageType::AuthResponse,
+                        peer_id.clone(),
+                        message.sender_id.clone(),
+                        json!({ "signature": signature }),
+                    );
+                    client.emit("p2p_message", serde_json::to_value(response).unwrap()).await.unwrap();
+                }
+            }
+            .boxed()
+        })
+        .connect()
+        .await
+        .unwrap();
+    (client, received)
+}
+
+/// The first message of `message_type` in `messages`, and whether it came as binary
+fn find(messages: &Mutex<Vec<(P2PMessage, bool)>>, message_type: &str) -> Option<(P2PMessage, bool)> {
+    let messages = messages.lock().unwrap();
+    messages.iter().find(|(m, _)| serde_json::to_value(&m.message_type).unwrap() == message_type).cloned()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_messages_survive_both_codecs() {
+    let message = P2PMessage::new(
+        MessageType::EntryAnnounce,
+        "test-node-1".to_string(),
+        "test-node-2".to_string(),
+        json!({ "id": "entry-1", "data": { "amount": 30, "fee": 31, "memo": null, "tags": ["a", "b"], "rate": 0.5 } }),
+    );
+
+    for codec in [Codec::Json, Codec::Msgpack] {
+        let decoded = codec.encode(&message).unwrap().decode().unwrap();
+        assert_eq!(serde_json::to_value(decoded).unwrap(), serde_json::to_value(&message).unwrap());
+    }
+    // Engine.IO's record separator (30) never goes out in a binary frame
+    let Ok(Frame::Binary(bytes)) = Codec::Msgpack.encode(&message) else { panic!("msgpack frames are binary") };
+    assert!(!bytes.contains(&0x1e));
+    assert!(matches!(Codec::Json.encode(&message), Ok(Frame::Json(_))));
+    assert!(Frame::Binary(vec![0xc1]).decode().is_err());
+    assert!(Frame::Binary(vec![0x1f]).decode().is_err());
+}
+
+#[test]
+fn test_codec_negotiation() {
+    let both = json!(["msgpack", "json"]);
+    assert_eq!(Codec::Msgpack.negotiate(Some(&both)), Codec::Msgpack);
+    assert_eq!(Codec::Json.negotiate(Some(&both)), Codec::Json);
+    assert_eq!(Codec::Msgpack.negotiate(Some(&json!(["json", "msgpack"]))), Codec::Json);
+
+    // Peers from before codecs, and codecs this node doesn't know, fall back to JSON
+    assert_eq!(Codec::Msgpack.negotiate(None), Codec::Json);
+    assert_eq!(Codec::Msgpack.negotiate(Some(&json!(["cbor"]))), Codec::Json);
+    assert_eq!(Codec::Msgpack.negotiate(Some(&json!("msgpack"))), Codec::Json);
+    assert_eq!("MsgPack".parse::<Codec>(), Ok(Codec::Msgpack));
+    assert!("cbor".parse::<Codec>().is_err());
+}
+
+#[tokio::test]
+async fn test_peers_exchange_msgpack() {
+    let server = Arc::new(new_node("test-node-1").with_codec(Codec::Msgpack));
+    let url = start_server(server.clone()).await;
+
+    let client = Arc::new(new_node("test-node-2").with_codec(Codec::Msgpack));
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || server.peer_codec("test-node-2").is_some()).await;
+    wait_for(Duration::from_secs(5), || client.peer_codec("test-node-1").is_some()).await;
+    assert_eq!(server.peer_codec("test-node-2"), Some(Codec::Msgpack));
+    assert_eq!(client.peer_codec("test-node-1"), Some(Codec::Msgpack));
+
+    // Entries flow both ways, whatever bytes they encode to
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1", "count": 30 })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    let entry = client.add_local_entry(json!({ "message": "Test entry 2" })).unwrap();
+    wait_for(Duration::from_secs(5), || server.ledger.get_entry_by_id(&entry.id).is_some()).await;
+}
+
+#[tokio::test]
+async fn test_json_only_node_is_sent_json() {
+    let server = Arc::new(new_node("test-node-1").with_codec(Codec::Msgpack));
+    let url = start_server(server.clone()).await;
+
+    // Nodes offer JSON alone unless told otherwise
+    let client = Arc::new(new_node("test-node-2"));
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || server.peer_codec("test-node-2").is_some()).await;
+    wait_for(Duration::from_secs(5), || client.peer_codec("test-node-1").is_some()).await;
+    assert_eq!(server.peer_codec("test-node-2"), Some(Codec::Json));
+    assert_eq!(client.peer_codec("test-node-1"), Some(Codec::Json));
+
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+}
+
+#[tokio::test]
+async fn test_codec_applies_once_the_handshake_is_done() {
+    let node = Arc::new(new_node("test-node-1").with_codec(Codec::Msgpack));
+    let url = start_server(node.clone()).await;
+
+    // The challenge is JSON so any node can read it; afterwards messages are binary
+    let (_client, received) = connect_peer(&url, "test-node-2", Some(json!(["msgpack", "json"]))).await;
+    wait_for(Duration::from_secs(5), || find(&received, "NodeAnnounce").is_some()).await;
+    let (challenge, binary) = find(&received, "AuthChallenge").unwrap();
+    assert_eq!(challenge.payload["codec"], "msgpack");
+    assert!(!binary);
+    let (announce, binary) = find(&received, "NodeAnnounce").unwrap();
+    assert_eq!(announce.sender_id, "test-node-1");
+    assert!(binary);
+
+    // A node from before codecs offers none and only ever gets JSON
+    let (_client, received) = connect_peer(&url, "test-node-3", None).await;
+    wait_for(Duration::from_secs(5), || find(&received, "NodeAnnounce").is_some()).await;
+    assert_eq!(find(&received, "AuthChallenge").unwrap().0.payload["codec"], "json");
+    assert!(received.lock().unwrap().iter().all(|(_, binary)| !binary));
+    assert_eq!(node.peer_codec("test-node-3"), Some(Codec::Json));
+}
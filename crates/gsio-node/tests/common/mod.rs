@@ -0,0 +1,48 @@
+//! Servers shared by the integration tests: a node's REST API, with its P2P
+//! namespace on the same port, as the node serves them.
+
+// Each test binary only uses some of these
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use axum::Router;
+use gsio_node::api;
+use gsio_node::p2p::P2PManager;
+use serde_json::Value as JsonValue;
+use socketioxide::{
+    extract::{Data, SocketRef},
+    SocketIo,
+};
+use tokio::net::TcpListener;
+
+/// Serve the REST API of `p2p` and its P2P namespace on a local port, returning the URL
+pub async fn start_server(p2p: Arc<P2PManager>) -> String {
+    start_server_with(p2p.clone(), api::router(p2p)).await
+}
+
+/// Serve `app` and the P2P namespace of `p2p` on a local port, returning the URL
+pub async fn start_server_with(p2p: Arc<P2PManager>, app: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    serve(listener, p2p, app);
+    url
+}
+
+/// Serve the REST API of `p2p` and its P2P namespace on `listener`, bound
+/// beforehand so the URL can go into the node's config
+pub async fn start_server_on(p2p: Arc<P2PManager>, listener: TcpListener) {
+    serve(listener, p2p.clone(), api::router(p2p));
+}
+
+fn serve(listener: TcpListener, p2p: Arc<P2PManager>, app: Router) {
+    let (layer, io) = SocketIo::builder().build_layer();
+    io.ns(p2p.namespace(), move |socket: SocketRef, Data(data): Data<JsonValue>| {
+        p2p.handle_connection(socket, data);
+    });
+    let app = app.layer(layer);
+
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+use gsio_node::codec::Codec;
+use gsio_node::config::{load_or_create_key, Cli, NodeConfig};
+use gsio_node::consensus::ConsensusStrategy;
+use gsio_node::ledger::{LedgerMode, Limits, RetentionPolicy};
+use gsio_node::p2p::NodeMode;
+use uuid::Uuid;
+
+fn write_config(contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("gsio-node-{}.toml", Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_defaults() {
+    let config = NodeConfig::default();
+    assert_eq!(config.listen_address, "0.0.0.0:3000".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.grpc_address, "0.0.0.0:50051".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.advertisement_interval(), Duration::from_secs(30));
+    assert_eq!(config.pending_retry_interval(), Duration::from_secs(30));
+    assert_eq!(config.retention_interval(), Duration::from_secs(60));
+    assert!(config.peer_exchange.is_enabled());
+    assert_eq!(config.peer_stale_after(), Duration::from_secs(300));
+    assert_eq!(config.peer_expiry(), Some(Duration::from_secs(86_400)));
+    assert_eq!(config.sync_peers, 4);
+    assert!(!config.bandwidth.is_enabled());
+    assert_eq!(config.node_mode(), NodeMode::Writer);
+    assert!(config.blob_path.is_none());
+    assert!(config.bootstrap_peers.is_empty());
+    assert_eq!(config.p2p_codec, Codec::Json);
+}
+
+#[test]
+fn test_config_file() {
+    let path = write_config(
+        r#"
+        listen_address = "127.0.0.1:4000"
+        node_name = "node-a"
+        blob_path = "/var/lib/gsio/blobs"
+        bootstrap_peers = ["http://node-b:3000"]
+        advertisement_interval = 10
+        mode = "follower"
+        writable_node = "http://writer:3000"
+        retention = "last:100"
+        channels = ["payments"]
+
+        [acl.payments]
+        writers = ["0000000000000000000000000000000000000000000000000000000000000000"]
+
+        [channel_modes]
+        payments = "crdt"
+
+        [limits]
+        max_pending_entries = 500
+
+        [tls]
+        cert_path = "/etc/gsio/cert.pem"
+        key_path = "/etc/gsio/key.pem"
+
+        [rate_limit]
+        requests_per_second = 5.0
+        burst = 10
+
+        [consensus]
+        strategy = "proof_of_authority"
+        quorum = 1
+        validators = [{ node_id = "node-a", public_key = "0000000000000000000000000000000000000000000000000000000000000000" }]
+        "#,
+    );
+    let config = NodeConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.listen_address, "127.0.0.1:4000".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.node_name.as_deref(), Some("node-a"));
+    assert_eq!(config.blob_path, Some(PathBuf::from("/var/lib/gsio/blobs")));
+    assert_eq!(config.bootstrap_peers, vec!["http://node-b:3000".to_string()]);
+    assert_eq!(config.advertisement_interval(), Duration::from_secs(10));
+    assert_eq!(config.retention, RetentionPolicy::KeepLast { entries: 100 });
+    assert_eq!(config.acl["payments"].writers.len(), 1);
+    assert_eq!(config.channel_modes["payments"], LedgerMode::Crdt);
+    assert_eq!(config.limits.max_pending_entries, 500);
+    assert_eq!(config.limits.max_sync_entries, Limits::default().max_sync_entries);
+    assert!(config.tls.is_enabled());
+    assert_eq!(config.tls.key_path, Some(PathBuf::from("/etc/gsio/key.pem")));
+    assert!(!config.tls.requires_client_certificates());
+    assert_eq!(
+        config.node_mode(),
+        NodeMode::Follower { writable_node: Some("http://writer:3000".to_string()) }
+    );
+
+    assert_eq!(config.consensus.strategy(), ConsensusStrategy::ProofOfAuthority);
+    assert_eq!(config.consensus.quorum, Some(1));
+    assert!(config.rate_limit.is_enabled());
+    assert_eq!(config.rate_limit.burst, 10);
+
+    // Settings left out keep their defaults
+    assert_eq!(config.grpc_address, NodeConfig::default().grpc_address);
+}
+
+#[test]
+fn test_node_key_file() {
+    let path = std::env::temp_dir().join(format!("gsio-node-{}.key", Uuid::new_v4()));
+    let config = NodeConfig { node_key: Some(path.clone()), ..NodeConfig::default() };
+    assert!(NodeConfig::default().signing_key().unwrap().is_none());
+
+    // The key is generated once and reused afterwards
+    let key = config.signing_key().unwrap().unwrap();
+    assert_eq!(load_or_create_key(&path).unwrap().to_bytes(), key.to_bytes());
+
+    std::fs::write(&path, "not a key").unwrap();
+    assert!(load_or_create_key(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_invalid_config_file() {
+    for contents in [
+        "mode = \"leader\"",
+        "retention = \"weeks:2\"",
+        "listen_port = 3000",
+        "[acl.payments]\nreaders = []",
+        "[channel_modes]\npayments = \"dag\"",
+        "[limits]\nmax_peers = 10",
+        "[consensus]\nstrategy = \"proof_of_work\"",
+    ] {
+        let path = write_config(contents);
+        let result = NodeConfig::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err(), "{contents} should be rejected");
+    }
+    assert!(NodeConfig::from_file(&PathBuf::from("/nonexistent/gsio.toml")).is_err());
+}
+
+#[test]
+fn test_env_overrides_file() {
+    let path = write_config("node_name = \"from-file\"\nadvertisement_interval = 10\n");
+    let mut config = NodeConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    config
+        .apply_env(env(&[
+            ("NODE_NAME", "from-env"),
+            ("PEERS", "http://node-a:3000, http://node-b:3000,"),
+            ("NODE_MODE", "follower"),
+            ("LEDGER_RETENTION", "days:7"),
+            ("LEDGER_ARCHIVE", "true"),
+            ("LEDGER_CHANNELS", "payments, chat"),
+            ("API_KEYS", "key-a,key-b"),
+            ("P2P_ENCRYPTION", "true"),
+            ("P2P_CODEC", "msgpack"),
+            ("CONSENSUS", "poa"),
+            ("CHECKPOINT", "blobticket"),
+            ("CHECKPOINT_KEYS", "aa11, bb22"),
+            ("IMPORT_FILE", "/backups/ledger.ndjson"),
+            ("RATE_LIMIT", "2.5"),
+            ("ADMIN_API_KEYS", "admin-key"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("TLS_CLIENT_CA_PATH", "/etc/gsio/clients.pem"),
+            ("RETENTION_INTERVAL", "300"),
+            ("PEER_EXCHANGE_INTERVAL", "0"),
+            ("PEER_EXPIRY", "0"),
+            ("SYNC_PEERS", "0"),
+            ("BANDWIDTH_DOWNLOAD", "1000000"),
+            ("BANDWIDTH_PEER_UPLOAD", "250000"),
+        ]))
+        .unwrap();
+    assert_eq!(config.node_name.as_deref(), Some("from-env"));
+    assert_eq!(config.bootstrap_peers, vec!["http://node-a:3000".to_string(), "http://node-b:3000".to_string()]);
+    assert_eq!(config.node_mode(), NodeMode::Follower { writable_node: None });
+    assert_eq!(config.retention, RetentionPolicy::KeepDays { days: 7 });
+    assert!(config.archive.enabled);
+    assert_eq!(config.channels, vec!["payments".to_string(), "chat".to_string()]);
+    assert_eq!(config.advertisement_interval(), Duration::from_secs(10));
+    assert_eq!(config.auth.api_keys, vec!["key-a".to_string(), "key-b".to_string()]);
+    assert!(config.auth.is_enabled());
+    assert!(config.p2p_encryption);
+    assert_eq!(config.p2p_codec, Codec::Msgpack);
+    assert_eq!(config.consensus.strategy, Some(ConsensusStrategy::ProofOfAuthority));
+    assert_eq!(config.checkpoint.as_deref(), Some("blobticket"));
+    assert_eq!(config.checkpoint_keys, vec!["aa11".to_string(), "bb22".to_string()]);
+    assert_eq!(config.import, Some(PathBuf::from("/backups/ledger.ndjson")));
+    assert_eq!(config.rate_limit.requests_per_second, 2.5);
+    assert!(config.admin.is_enabled());
+    assert_eq!(config.telemetry.otlp_endpoint.as_deref(), Some("http://collector:4318"));
+    assert_eq!(config.telemetry.service_name, "gsio-node");
+    assert!(config.tls.requires_client_certificates());
+    assert!(!config.tls.is_enabled());
+    assert_eq!(config.retention_interval(), Duration::from_secs(300));
+    assert!(!config.peer_exchange.is_enabled());
+    assert_eq!(config.peer_expiry(), None);
+    assert_eq!(config.sync_peers, 0);
+    assert_eq!(config.bandwidth.download, 1_000_000);
+    assert_eq!(config.bandwidth.peer_upload, 250_000);
+    assert_eq!(config.bandwidth.upload, 0);
+
+    assert!(config.apply_env(env(&[("GRPC_ADDRESS", "not an address")])).is_err());
+}
+
+#[test]
+fn test_cli_overrides_env() {
+    let mut config = NodeConfig::default();
+    config
+        .apply_env(env(&[("LISTEN_ADDRESS", "127.0.0.1:4000"), ("PEERS", "http://node-a:3000")]))
+        .unwrap();
+
+    let cli = Cli::try_parse_from([
+        "gsio-node",
+        "--listen",
+        "127.0.0.1:5000",
+        "--peer",
+        "http://node-b:3000",
+        "--peer",
+        "http://node-c:3000",
+        "--mode",
+        "follower",
+        "--retention",
+        "forever",
+        "--consensus",
+        "longest-chain",
+        "--rate-limit",
+        "50",
+        "--upload-limit",
+        "500000",
+        "--tls-cert",
+        "cert.pem",
+        "--tls-key",
+        "key.pem",
+        "--checkpoint-key",
+        "cc33",
+    ])
+    .unwrap();
+    config.apply_cli(&cli);
+
+    assert_eq!(config.listen_address, "127.0.0.1:5000".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.bootstrap_peers, vec!["http://node-b:3000".to_string(), "http://node-c:3000".to_string()]);
+    assert_eq!(config.node_mode(), NodeMode::Follower { writable_node: None });
+    assert_eq!(config.consensus.strategy(), ConsensusStrategy::LongestChain);
+    assert_eq!(config.rate_limit.requests_per_second, 50.0);
+    assert_eq!(config.bandwidth.upload, 500_000);
+    assert_eq!(config.tls.cert_path, Some(PathBuf::from("cert.pem")));
+    assert_eq!(config.checkpoint_keys, vec!["cc33".to_string()]);
+
+    // Flags that weren't given leave the setting alone
+    config.apply_cli(&Cli::try_parse_from(["gsio-node"]).unwrap());
+    assert_eq!(config.listen_address, "127.0.0.1:5000".parse::<SocketAddr>().unwrap());
+
+    assert!(Cli::try_parse_from(["gsio-node", "--mode", "leader"]).is_err());
+    assert!(Cli::try_parse_from(["gsio-node", "--consensus", "proof_of_work"]).is_err());
+}
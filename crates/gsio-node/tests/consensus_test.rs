@@ -0,0 +1,238 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use gsio_node::consensus::{
+    Consensus, ConsensusConfig, ConsensusStrategy, LongestChain, ProofOfAuthority, Validator, ValidatorSet,
+    ValidatorUpdate,
+};
+use gsio_node::error::GsioNodeError;
+use gsio_node::ledger::{Ledger, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use gsio_node::validation::ErrorCode;
+use rand::rngs::OsRng;
+use serde_json::json;
+
+mod common;
+
+use common::start_server;
+
+fn validator(node_id: &str, key: &SigningKey) -> Validator {
+    Validator {
+        node_id: node_id.to_string(),
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+    }
+}
+
+/// Ledgers for `node_ids`, all requiring signatures from the first `validators` of them
+fn poa_ledgers(node_ids: &[&str], validators: usize, quorum: Option<usize>) -> Vec<Ledger> {
+    let keys: Vec<SigningKey> = node_ids.iter().map(|_| SigningKey::generate(&mut OsRng)).collect();
+    let config = ConsensusConfig {
+        validators: node_ids.iter().zip(&keys).take(validators).map(|(id, key)| validator(id, key)).collect(),
+        quorum,
+        ..ConsensusConfig::default()
+    };
+
+    node_ids
+        .iter()
+        .zip(keys)
+        .map(|(id, key)| {
+            let mut ledger = Ledger::with_signing_key(id.to_string(), key);
+            ledger.set_consensus(config.build().unwrap());
+            ledger
+        })
+        .collect()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_strategy_selection() {
+    let key = SigningKey::generate(&mut OsRng);
+    let validators = vec![validator("test-node-1", &key)];
+
+    // Listing validators picks proof of authority unless a strategy is given
+    let default = ConsensusConfig::default();
+    assert_eq!(default.strategy(), ConsensusStrategy::LongestChain);
+    assert_eq!(default.build().unwrap().name(), "longest_chain");
+    let poa = ConsensusConfig { validators: validators.clone(), ..ConsensusConfig::default() };
+    assert_eq!(poa.strategy(), ConsensusStrategy::ProofOfAuthority);
+    assert_eq!(poa.build().unwrap().name(), "proof_of_authority");
+
+    // Settings that don't fit the strategy are rejected
+    let config = ConsensusConfig { strategy: Some(ConsensusStrategy::LongestChain), ..poa.clone() };
+    assert!(config.build().is_err());
+    let config = ConsensusConfig { strategy: Some(ConsensusStrategy::ProofOfAuthority), ..ConsensusConfig::default() };
+    assert!(config.build().is_err());
+
+    assert_eq!("poa".parse::<ConsensusStrategy>().unwrap(), ConsensusStrategy::ProofOfAuthority);
+    assert_eq!("longest-chain".parse::<ConsensusStrategy>().unwrap(), ConsensusStrategy::LongestChain);
+    assert!("proof_of_work".parse::<ConsensusStrategy>().is_err());
+
+    // Only proof of authority restricts who proposes and keeps competing branches out
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let entry = ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert!(LongestChain.can_propose("test-node-9") && LongestChain.is_approved(&entry));
+    let authority = ProofOfAuthority::new(ValidatorSet::new(&poa).unwrap());
+    assert!(authority.can_propose("test-node-1") && !authority.can_propose("test-node-9"));
+    assert!(!authority.is_approved(&entry));
+    assert!(authority.collects_signatures() && !LongestChain.collects_signatures());
+}
+
+#[test]
+fn test_validator_set() {
+    let keys: Vec<SigningKey> = (0..4).map(|_| SigningKey::generate(&mut OsRng)).collect();
+    let validators: Vec<Validator> =
+        keys.iter().enumerate().map(|(i, key)| validator(&format!("test-node-{i}"), key)).collect();
+
+    // A majority is needed unless the quorum is set
+    let config = ConsensusConfig { validators: validators.clone(), ..ConsensusConfig::default() };
+    let set = ValidatorSet::new(&config).unwrap();
+    assert_eq!(set.quorum(), 3);
+    assert!(set.contains("test-node-0"));
+    assert!(!set.contains("test-node-9"));
+    assert_eq!(ValidatorSet::new(&ConsensusConfig { quorum: Some(2), ..config.clone() }).unwrap().quorum(), 2);
+    assert_eq!(ValidatorSet::new(&ConsensusConfig { quorum: Some(9), ..config.clone() }).unwrap().quorum(), 4);
+
+    assert!(ValidatorSet::new(&ConsensusConfig { quorum: Some(0), ..config.clone() }).is_err());
+    let invalid = ConsensusConfig {
+        validators: vec![Validator { node_id: "test-node-0".to_string(), public_key: "not a key".to_string() }],
+        ..ConsensusConfig::default()
+    };
+    assert!(ValidatorSet::new(&invalid).is_err());
+}
+
+#[test]
+fn test_entry_needs_quorum() {
+    let [mut a, mut b, mut c, mut observer]: [Ledger; 4] =
+        poa_ledgers(&["test-node-1", "test-node-2", "test-node-3", "test-node-4"], 3, None).try_into().unwrap();
+
+    // The entry waits for a second validator
+    let entry = a.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert!(a.get_entries().is_empty());
+
+    // Other nodes don't add it with only its creator's signature
+    observer.add_pending_entry(entry.clone());
+    assert!(observer.process_pending_entries().is_empty());
+
+    // Once another validator signs it, everyone adds it
+    assert!(b.add_pending_entry(entry.clone()));
+    let signed = b.endorse_pending_entries();
+    assert_eq!(signed.len(), 1);
+    assert_eq!(b.process_pending_entries().len(), 1);
+    for ledger in [&mut a, &mut c, &mut observer] {
+        assert!(ledger.add_pending_entry(signed[0].clone()));
+        assert_eq!(ledger.process_pending_entries().len(), 1);
+        assert_eq!(ledger.get_last_entry().unwrap().id, entry.id);
+    }
+
+    // Non-validators can't create entries
+    let err = observer.add_entry(json!({ "message": "Test entry 2" })).unwrap_err();
+    assert_eq!(err.code, ErrorCode::NotValidator);
+
+    // Approved entries are final
+    assert!(a.resolve_forks().is_none());
+}
+
+#[test]
+fn test_validator_signs_once_per_parent() {
+    let [mut a, mut b, mut c]: [Ledger; 3] =
+        poa_ledgers(&["test-node-1", "test-node-2", "test-node-3"], 3, None).try_into().unwrap();
+
+    // Two validators propose competing entries on the same parent
+    let first = a.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let second = b.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+    assert_eq!(first.previous_hash, second.previous_hash);
+
+    // The third only backs one of them
+    c.add_pending_entry(first.clone());
+    c.add_pending_entry(second.clone());
+    let signed = c.endorse_pending_entries();
+    assert_eq!(signed.len(), 1);
+    assert!(c.endorse_pending_entries().is_empty());
+    assert_eq!(c.process_pending_entries().len(), 1);
+
+    // A validator's later proposals build on the one it is waiting for
+    let next = a.add_entry(json!({ "message": "Test entry 3" })).unwrap();
+    assert_eq!(next.previous_hash, first.hash);
+}
+
+#[test]
+fn test_validator_update() {
+    let [mut a, mut b]: [Ledger; 2] = poa_ledgers(&["test-node-1", "test-node-2"], 1, None).try_into().unwrap();
+
+    // With a single validator its entries are added straight away
+    let newcomer = SigningKey::generate(&mut OsRng);
+    let update = ValidatorUpdate { add: vec![validator("test-node-2", &newcomer)], remove: vec![] };
+    let entry = a.add_entry(update.to_entry_data()).unwrap();
+    assert_eq!(a.get_entries().len(), 1);
+    assert!(a.validators().unwrap().contains("test-node-2"));
+    assert_eq!(a.validators().unwrap().quorum(), 2);
+
+    // Other nodes apply the update when they add the entry
+    b.add_pending_entry(entry);
+    assert_eq!(b.process_pending_entries().len(), 1);
+    assert!(b.validators().unwrap().contains("test-node-2"));
+
+    // An update that would leave no validators is ignored
+    let update = ValidatorUpdate { add: vec![], remove: vec!["test-node-1".to_string(), "test-node-2".to_string()] };
+    let mut solo = poa_ledgers(&["test-node-1"], 1, None).remove(0);
+    solo.add_entry(update.to_entry_data()).unwrap();
+    assert!(solo.validators().unwrap().contains("test-node-1"));
+}
+
+#[tokio::test]
+async fn test_poa_network() {
+    let ids = ["test-node-1", "test-node-2", "test-node-3"];
+    let keys: Vec<SigningKey> = ids.iter().map(|_| SigningKey::generate(&mut OsRng)).collect();
+    let config = ConsensusConfig {
+        validators: vec![validator(ids[0], &keys[0]), validator(ids[1], &keys[1])],
+        quorum: Some(2),
+        ..ConsensusConfig::default()
+    };
+    let nodes: Vec<Arc<P2PManager>> = ids
+        .iter()
+        .zip(keys)
+        .map(|(id, key)| {
+            let ledger = SharedLedger::with_signing_key(id.to_string(), key);
+            ledger.set_consensus(config.build().unwrap());
+            Arc::new(P2PManager::new(id.to_string(), ledger))
+        })
+        .collect();
+
+    // The second validator and an observer both connect to the first validator
+    let url = start_server(nodes[0].clone()).await;
+    for node in &nodes[1..] {
+        node.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    }
+    wait_for(Duration::from_secs(5), || nodes[0].peer_health().len() == 2).await;
+
+    // An entry from the second validator is signed by the first and reaches every node
+    let entry = nodes[1].add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    for node in &nodes {
+        wait_for(Duration::from_secs(5), || node.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    }
+    let added = nodes[2].ledger.get_entry_by_id(&entry.id).unwrap();
+    assert!(added.signatures.contains_key("test-node-1") && added.signatures.contains_key("test-node-2"));
+
+    // The observer only relays
+    let err = nodes[2].add_local_entry(json!({ "message": "Test entry 2" })).unwrap_err();
+    assert_eq!(err, GsioNodeError::NotValidator);
+
+    // A node running a different strategy isn't let in
+    let outsider = Arc::new(P2PManager::new("test-node-4".to_string(), SharedLedger::new("test-node-4".to_string())));
+    outsider.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(nodes[0].peer_health().len(), 2);
+    assert!(outsider.peer_health().is_empty());
+
+    for node in nodes[1..].iter().chain([&outsider]) {
+        node.leave().await;
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use gsio_node::contacts::{Contacts, Liveness};
+use gsio_node::identity;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{MessageType, P2PManager, P2PMessage};
+use iroh::NodeAddr;
+use rand::rngs::OsRng;
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+
+mod common;
+
+use common::start_server_on;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_contact_updates() {
+    let contacts = Contacts::new();
+    assert!(contacts.set_url("test-node-2", "http://node-2:3000".to_string()));
+    assert!(!contacts.set_url("test-node-3", String::new()));
+    assert!(contacts.get("test-node-3").is_none());
+
+    // Hearing of a node again only counts if it moved
+    assert!(!contacts.set_url("test-node-2", "http://node-2:3000".to_string()));
+    assert!(contacts.set_url("test-node-2", "http://node-2:4000".to_string()));
+
+    let key = SigningKey::generate(&mut OsRng);
+    let addr = NodeAddr::new(identity::to_iroh(&hex::encode(key.verifying_key().as_bytes())).unwrap());
+    assert!(contacts.set_addr("test-node-2", addr.clone()));
+    assert!(!contacts.set_addr("test-node-2", addr.clone()));
+
+    // Being heard from makes a node known, but not dialable
+    contacts.seen("test-node-4");
+    let all = contacts.all();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].url.as_deref(), Some("http://node-2:4000"));
+    assert_eq!(all[0].addr, Some(addr));
+    assert!(all[0].is_dialable());
+    assert!(all[1].last_seen.is_some());
+    assert!(!all[1].is_dialable());
+}
+
+#[tokio::test]
+async fn test_handshake_carries_url() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let node = Arc::new(new_node("test-node-1"));
+    start_server_on(node.clone(), listener).await;
+
+    let dialing = new_node("test-node-2").with_public_url("http://node-2:3000");
+    dialing.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+
+    // The accepting node learns where to dial back, and the dialing node where it dialed
+    wait_for(Duration::from_secs(5), || {
+        node.contacts().get("test-node-2").and_then(|contact| contact.url).as_deref() == Some("http://node-2:3000")
+    })
+    .await;
+    wait_for(Duration::from_secs(5), || {
+        dialing.contacts().get("test-node-1").and_then(|contact| contact.url) == Some(url.clone())
+    })
+    .await;
+    assert!(node.contacts().get("test-node-2").unwrap().last_seen.is_some());
+
+    // Contacts are listed with the known nodes
+    let nodes: JsonValue = reqwest::get(format!("{url}/api/nodes")).await.unwrap().json().await.unwrap();
+    assert_eq!(nodes["contacts"][0]["node_id"], "test-node-2");
+    assert_eq!(nodes["contacts"][0]["url"], "http://node-2:3000");
+    assert_eq!(nodes["liveness"]["test-node-2"], "live");
+
+    dialing.leave().await;
+}
+
+#[tokio::test]
+async fn test_quiet_nodes_go_stale_and_expire() {
+    let node = new_node("test-node-1").with_stale_after(Duration::from_millis(100));
+    node.handle_message(P2PMessage::new(
+        MessageType::NodeListResponse,
+        "test-node-4".to_string(),
+        "test-node-1".to_string(),
+        json!({ "nodes": ["test-node-2"] }),
+    ));
+    // Nodes known without a contact, as after a restore, count as just heard of
+    node.ledger.add_known_node("test-node-3".to_string());
+    assert_eq!(node.node_liveness()["test-node-2"], Liveness::Live);
+    assert_eq!(node.node_liveness()["test-node-3"], Liveness::Live);
+    assert!(node.expire_nodes(Duration::from_millis(500)).is_empty());
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let liveness = node.node_liveness();
+    assert_eq!(liveness["test-node-1"], Liveness::Live);
+    assert_eq!(liveness["test-node-2"], Liveness::Stale);
+    assert_eq!(liveness["test-node-3"], Liveness::Stale);
+
+    // Stale nodes aren't passed on
+    let request =
+        P2PMessage::new(MessageType::NodeListRequest, "test-node-4".to_string(), "test-node-1".to_string(), json!({}));
+    let reply = node.handle_message(request).unwrap();
+    assert_eq!(reply.payload["nodes"], json!(["test-node-1"]));
+
+    // Being passed on again keeps a node around
+    node.contacts().heard_of("test-node-3");
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    assert_eq!(node.expire_nodes(Duration::from_millis(500)), vec!["test-node-2".to_string()]);
+    let known = node.ledger.get_known_nodes();
+    assert!(!known.contains("test-node-2"));
+    assert!(known.contains("test-node-3"));
+    assert!(known.contains("test-node-1"));
+    assert!(node.contacts().get("test-node-2").is_none());
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, VecDeque};
+use ed25519_dalek::SigningKey;
+use gsio_node::ledger::{Ledger, LedgerEntry, SharedLedger};
+use gsio_node::p2p::{P2PManager, P2PMessage};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use proptest::test_runner::{Config as ProptestConfig, RngSeed};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+const NODES: usize = 3;
+
+/// Cases run from a fixed seed, so a failure shows up on every run
+fn config() -> ProptestConfig {
+    ProptestConfig {
+        cases: 64,
+        rng_seed: RngSeed::Fixed(0x6773_696f),
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    }
+}
+
+fn node_id(node: usize) -> String {
+    format!("test-node-{}", node + 1)
+}
+
+/// Nodes sign with fixed keys, so every case knows every key up front
+fn signing_key(node: usize) -> SigningKey {
+    SigningKey::from_bytes(&[node as u8 + 1; 32])
+}
+
+fn public_key(node: usize) -> String {
+    hex::encode(signing_key(node).verifying_key().to_bytes())
+}
+
+/// Which side of the partition each node is on, a bit per node; 0 is a healed network
+fn reachable(partition: u8, from: usize, to: usize) -> bool {
+    (partition >> from) & 1 == (partition >> to) & 1
+}
+
+fn chain_hashes(entries: &[LedgerEntry]) -> Vec<String> {
+    entries.iter().map(|e| e.hash.clone()).collect()
+}
+
+#[derive(Debug, Clone)]
+enum LedgerOp {
+    /// A node adds an entry to its own chain
+    Create(usize),
+    /// Some of what `from` holds reaches `to`, in the order picked
+    Deliver { from: usize, to: usize, picks: Vec<Index>, apply_each: bool },
+    Partition(u8),
+    Heal,
+}
+
+fn ledger_op() -> impl Strategy<Value = LedgerOp> {
+    prop_oneof![
+        3 => (0..NODES).prop_map(LedgerOp::Create),
+        4 => (0..NODES, 0..NODES, prop::collection::vec(any::<Index>(), 1..6), any::<bool>())
+            .prop_map(|(from, to, picks, apply_each)| LedgerOp::Deliver { from, to, picks, apply_each }),
+        1 => (1..(1u8 << NODES) - 1).prop_map(LedgerOp::Partition),
+        1 => Just(LedgerOp::Heal),
+    ]
+}
+
+fn ledger(node: usize) -> Ledger {
+    let mut ledger = Ledger::with_signing_key(node_id(node), signing_key(node));
+    for other in (0..NODES).filter(|other| *other != node) {
+        ledger.add_node_key(node_id(other), &public_key(other)).unwrap();
+    }
+    ledger
+}
+
+/// Everything a ledger knows of: its chain, then what is pending
+fn holdings(ledger: &Ledger) -> Vec<LedgerEntry> {
+    let pending = ledger.get_pending_entries().into_iter().cloned();
+    ledger.get_entries().iter().cloned().chain(pending).collect()
+}
+
+/// Add entries the way a node handles them from a peer
+fn receive(ledger: &mut Ledger, entries: impl IntoIterator<Item = LedgerEntry>, apply_each: bool) {
+    for entry in entries {
+        ledger.add_pending_entry(entry);
+        if apply_each {
+            ledger.process_pending_entries();
+            ledger.resolve_forks();
+        }
+    }
+    ledger.process_pending_entries();
+    ledger.resolve_forks();
+}
+
+/// Run `ops`, then heal the network and gossip until nothing changes
+fn run_ledgers(ops: &[LedgerOp]) -> Vec<Ledger> {
+    let mut ledgers: Vec<Ledger> = (0..NODES).map(ledger).collect();
+    let mut partition = 0;
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            LedgerOp::Create(node) => {
+                ledgers[*node].add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+            }
+            LedgerOp::Deliver { from, to, picks, apply_each } => {
+                let held = holdings(&ledgers[*from]);
+                if from == to || held.is_empty() || !reachable(partition, *from, *to) {
+                    continue;
+                }
+                let entries: Vec<LedgerEntry> = picks.iter().map(|pick| pick.get(&held).clone()).collect();
+                receive(&mut ledgers[*to], entries, *apply_each);
+            }
+            LedgerOp::Partition(sides) => partition = *sides,
+            LedgerOp::Heal => partition = 0,
+        }
+    }
+
+    for _ in 0..NODES * 2 {
+        let before: Vec<_> = ledgers.iter().map(|l| (chain_hashes(l.get_entries()), holdings(l).len())).collect();
+        for from in 0..NODES {
+            for to in (0..NODES).filter(|to| *to != from) {
+                let held = holdings(&ledgers[from]);
+                receive(&mut ledgers[to], held, false);
+            }
+        }
+        let after: Vec<_> = ledgers.iter().map(|l| (chain_hashes(l.get_entries()), holdings(l).len())).collect();
+        if before == after {
+            break;
+        }
+    }
+    ledgers
+}
+
+proptest! {
+    #![proptest_config(config())]
+
+    /// However entries were created, delivered and partitioned, replicas
+    /// that have seen the same entries hold the same chain
+    #[test]
+    fn test_ledgers_converge(ops in prop::collection::vec(ledger_op(), 1..40)) {
+        let ledgers = run_ledgers(&ops);
+        let chain = chain_hashes(ledgers[0].get_entries());
+        for ledger in &ledgers[1..] {
+            prop_assert_eq!(&chain_hashes(ledger.get_entries()), &chain);
+        }
+
+        // And it is the chain a node given every entry at once settles on
+        let mut all: BTreeMap<String, LedgerEntry> = BTreeMap::new();
+        for ledger in &ledgers {
+            all.extend(holdings(ledger).into_iter().map(|e| (e.id.clone(), e)));
+        }
+        let mut reference = Ledger::with_signing_key("test-node-0".to_string(), SigningKey::from_bytes(&[0; 32]));
+        for node in 0..NODES {
+            reference.add_node_key(node_id(node), &public_key(node)).unwrap();
+        }
+        receive(&mut reference, all.into_values(), false);
+        prop_assert_eq!(chain_hashes(reference.get_entries()), chain);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NetworkOp {
+    /// A node's client adds an entry, which it announces
+    Create(usize),
+    /// One message in flight arrives, unless the network is partitioned between its ends
+    Deliver(Index),
+    /// One message in flight is lost
+    Drop(Index),
+    Partition(u8),
+    Heal,
+}
+
+fn network_op() -> impl Strategy<Value = NetworkOp> {
+    prop_oneof![
+        3 => (0..NODES).prop_map(NetworkOp::Create),
+        6 => any::<Index>().prop_map(NetworkOp::Deliver),
+        1 => any::<Index>().prop_map(NetworkOp::Drop),
+        1 => (1..(1u8 << NODES) - 1).prop_map(NetworkOp::Partition),
+        1 => Just(NetworkOp::Heal),
+    ]
+}
+
+/// Nodes whose messages go to a queue the test delivers from
+struct Network {
+    nodes: Vec<P2PManager>,
+    outbox: mpsc::UnboundedReceiver<P2PMessage>,
+    in_flight: VecDeque<P2PMessage>,
+    partition: u8,
+}
+
+impl Network {
+    fn new() -> Self {
+        let (sender, outbox) = mpsc::unbounded_channel();
+        let nodes = (0..NODES)
+            .map(|node| {
+                let ledger = SharedLedger::with_signing_key(node_id(node), signing_key(node));
+                for other in (0..NODES).filter(|other| *other != node) {
+                    ledger.add_node_key(node_id(other), &public_key(other)).unwrap();
+                }
+                P2PManager::new(node_id(node), ledger).with_outbox(sender.clone())
+            })
+            .collect();
+        Self { nodes, outbox, in_flight: VecDeque::new(), partition: 0 }
+    }
+
+    fn node_index(&self, node_id: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.node_id() == node_id)
+    }
+
+    /// Move what nodes sent into the queue, a copy per recipient of a broadcast
+    fn collect(&mut self) {
+        while let Ok(message) = self.outbox.try_recv() {
+            if !message.recipient_id.is_empty() {
+                self.in_flight.push_back(message);
+                continue;
+            }
+            for node in self.nodes.iter().filter(|node| node.node_id() != message.sender_id) {
+                self.in_flight.push_back(P2PMessage { recipient_id: node.node_id().to_string(), ..message.clone() });
+            }
+        }
+    }
+
+    /// Deliver a message, queueing the reply to its sender
+    fn deliver(&mut self, message: P2PMessage) {
+        let (Some(from), Some(to)) = (self.node_index(&message.sender_id), self.node_index(&message.recipient_id)) else {
+            return;
+        };
+        if !reachable(self.partition, from, to) {
+            return;
+        }
+        let sender_id = message.sender_id.clone();
+        if let Some(reply) = self.nodes[to].handle_message(message) {
+            let recipient_id = if reply.recipient_id.is_empty() { sender_id } else { reply.recipient_id.clone() };
+            self.in_flight.push_back(P2PMessage { recipient_id, ..reply });
+        }
+        self.collect();
+    }
+
+    /// Deliver everything in flight, and what that sends in turn
+    fn drain(&mut self) {
+        let mut budget = 10_000;
+        while let Some(message) = self.in_flight.pop_front() {
+            budget -= 1;
+            assert!(budget > 0, "the network never went quiet");
+            self.deliver(message);
+        }
+    }
+
+    fn chains(&self) -> Vec<Vec<String>> {
+        self.nodes.iter().map(|node| chain_hashes(&node.ledger.get_entries())).collect()
+    }
+}
+
+/// Run `ops`, then heal the network and have every node sync with every other
+fn run_network(ops: &[NetworkOp]) -> Network {
+    let mut network = Network::new();
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            NetworkOp::Create(node) => {
+                network.nodes[*node].add_local_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+                network.collect();
+            }
+            NetworkOp::Deliver(pick) if !network.in_flight.is_empty() => {
+                let message = network.in_flight.remove(pick.index(network.in_flight.len())).unwrap();
+                network.deliver(message);
+            }
+            NetworkOp::Drop(pick) if !network.in_flight.is_empty() => {
+                network.in_flight.remove(pick.index(network.in_flight.len()));
+            }
+            NetworkOp::Partition(sides) => network.partition = *sides,
+            NetworkOp::Heal => network.partition = 0,
+            _ => {}
+        }
+    }
+
+    network.partition = 0;
+    network.drain();
+    for _ in 0..NODES {
+        let before = network.chains();
+        for node in 0..NODES {
+            for peer in (0..NODES).filter(|peer| *peer != node) {
+                network.nodes[node].request_ledger_sync(node_id(peer));
+                network.collect();
+                network.drain();
+            }
+        }
+        if network.chains() == before {
+            break;
+        }
+    }
+    network
+}
+
+proptest! {
+    #![proptest_config(config())]
+
+    /// Nodes exchanging messages that arrive out of order, or not at all,
+    /// across partitions end on the same chain once they sync
+    #[test]
+    fn test_nodes_converge(ops in prop::collection::vec(network_op(), 1..60)) {
+        let network = run_network(&ops);
+        let chains = network.chains();
+        for chain in &chains[1..] {
+            prop_assert_eq!(chain, &chains[0]);
+        }
+    }
+}
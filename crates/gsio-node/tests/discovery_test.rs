@@ -0,0 +1,111 @@
+use std::time::Duration;
+use gsio_node::config::NodeConfig;
+use gsio_node::discovery::{Discovery, PeerRecord, DISCOVERY_ALPN, DISCOVERY_TOPIC};
+use gsio_node::identity;
+use iroh::protocol::Router;
+use iroh::{Endpoint, NodeAddr, RelayMode};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+struct TestNode {
+    discovery: Discovery,
+    learned: UnboundedReceiver<PeerRecord>,
+    addr: NodeAddr,
+    _router: Router,
+}
+
+impl TestNode {
+    /// The ID the node announces itself under
+    fn id(&self) -> String {
+        identity::from_iroh(&self.addr.node_id)
+    }
+}
+
+/// Start a node gossiping on `topic`, announcing a URL if it has a name
+async fn start_node(name: Option<&str>, topic: &str) -> TestNode {
+    let endpoint = Endpoint::builder().relay_mode(RelayMode::Disabled).bind().await.unwrap();
+    let url = name.map(|name| format!("http://{name}.example:3000"));
+    let (discovery, learned) = Discovery::new(endpoint.clone(), topic, url, Duration::from_secs(30));
+    let router = Router::builder(endpoint.clone()).accept(DISCOVERY_ALPN, discovery.clone()).spawn();
+    let addr = endpoint.node_addr().await.unwrap();
+    TestNode { discovery, learned, addr, _router: router }
+}
+
+fn node_ids(node: &TestNode) -> Vec<String> {
+    node.discovery.peers().into_iter().map(|peer| peer.node_id).collect()
+}
+
+/// IDs of `nodes`, ordered the way `Discovery::peers` orders them
+fn sorted_ids(nodes: &[&TestNode]) -> Vec<String> {
+    let mut ids: Vec<String> = nodes.iter().map(|node| node.id()).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_discovery_config() {
+    let config: NodeConfig = toml::from_str(
+        r#"
+        public_url = "http://node-a:3000"
+
+        [discovery]
+        topic = "staging"
+        interval = 10
+        "#,
+    )
+    .unwrap();
+    assert!(config.discovery.is_enabled());
+    assert_eq!(config.discovery.topic, "staging");
+    assert_eq!(config.discovery.interval(), Duration::from_secs(10));
+    assert_eq!(NodeConfig::default().discovery.topic, DISCOVERY_TOPIC);
+
+    let mut config = NodeConfig::default();
+    config
+        .apply_env(|name| match name {
+            "PUBLIC_URL" => Some("http://node-b:3000".to_string()),
+            "DISCOVERY_PEERS" => Some("not-a-node-id".to_string()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(config.public_url.as_deref(), Some("http://node-b:3000"));
+    assert!(config.discovery.peer_ids().is_err());
+}
+
+#[tokio::test]
+async fn test_nodes_learn_peers_through_gossip() {
+    let mut a = start_node(Some("node-a"), DISCOVERY_TOPIC).await;
+    // Nodes without a public URL pass on what they learn
+    let mut hub = start_node(None, DISCOVERY_TOPIC).await;
+    let mut c = start_node(Some("node-c"), DISCOVERY_TOPIC).await;
+
+    assert_eq!(a.discovery.exchange(hub.addr.clone()).await.unwrap(), 0);
+    let learned = hub.learned.recv().await.unwrap();
+    assert_eq!((learned.node_id.clone(), learned.url.as_str()), (a.id(), "http://node-a.example:3000"));
+    assert_eq!(learned.addr.node_id, a.addr.node_id);
+
+    // node-c hears about node-a from the hub, and the hub about node-c
+    assert_eq!(c.discovery.exchange(hub.addr.clone()).await.unwrap(), 1);
+    assert_eq!(c.learned.recv().await.unwrap().node_id, a.id());
+    assert_eq!(hub.learned.recv().await.unwrap().node_id, c.id());
+    assert_eq!(node_ids(&hub), sorted_ids(&[&a, &c]));
+
+    // node-a learns about node-c on its next round, and nobody learns about themselves
+    assert_eq!(a.discovery.exchange(hub.addr.clone()).await.unwrap(), 1);
+    assert_eq!(a.learned.recv().await.unwrap().node_id, c.id());
+    assert_eq!(node_ids(&a), vec![c.id()]);
+
+    // node-c can reach node-a directly with the address it was gossiped
+    let address = c.discovery.peers()[0].addr.clone();
+    assert_eq!(c.discovery.exchange(address).await.unwrap(), 0);
+    assert_eq!(node_ids(&a), vec![c.id()]);
+    assert!(a.learned.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_other_topics_are_ignored() {
+    let hub = start_node(None, DISCOVERY_TOPIC).await;
+    let mut other = start_node(Some("node-x"), "other-network").await;
+
+    assert!(other.discovery.exchange(hub.addr.clone()).await.is_err());
+    assert!(hub.discovery.peers().is_empty());
+    assert!(other.learned.try_recv().is_err());
+}
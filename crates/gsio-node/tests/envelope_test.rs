@@ -0,0 +1,126 @@
+use gsio_node::envelope::{EphemeralKey, SecureChannel, REPLAY_WINDOW};
+use gsio_node::p2p::{handshake_transcript, MessageType, P2PMessage};
+use serde_json::json;
+
+/// Channels between two nodes, as each of them derives it from a handshake
+fn channel_pair() -> (SecureChannel, SecureChannel) {
+    let (a, b) = (EphemeralKey::generate(), EphemeralKey::generate());
+    let transcript = handshake_transcript(
+        "test-node-1",
+        &json!({ "ephemeral_key": a.public_key() }),
+        "test-node-2",
+        &json!({ "ephemeral_key": b.public_key() }),
+    );
+    (
+        SecureChannel::new(&a.agree(&b.public_key()).unwrap(), &transcript, "test-node-1", "test-node-2"),
+        SecureChannel::new(&b.agree(&a.public_key()).unwrap(), &transcript, "test-node-2", "test-node-1"),
+    )
+}
+
+fn entry_announce() -> P2PMessage {
+    P2PMessage::new(
+        MessageType::EntryAnnounce,
+        "test-node-1".to_string(),
+        "".to_string(),
+        json!({ "message": "Secret entry" }),
+    )
+}
+
+#[test]
+fn test_envelope_round_trip() {
+    let (to_b, from_a) = channel_pair();
+    assert_eq!(to_b.peer_id(), "test-node-2");
+
+    let message = entry_announce();
+    let envelope = to_b.seal(&message);
+    assert!(matches!(envelope.message_type, MessageType::Encrypted));
+    assert_eq!(envelope.sender_id, "test-node-1");
+    assert_eq!(envelope.recipient_id, "test-node-2");
+    assert!(!envelope.payload.to_string().contains("Secret entry"));
+
+    // Both sides derive the same key
+    let opened = from_a.open(&envelope).unwrap();
+    assert_eq!(opened.message_id, message.message_id);
+    assert_eq!(opened.payload, message.payload);
+
+    // Sealing the same message twice uses the next counter
+    let again = to_b.seal(&message);
+    assert_eq!((envelope.payload["counter"].as_u64(), again.payload["counter"].as_u64()), (Some(0), Some(1)));
+    assert_ne!(again.payload["ciphertext"], envelope.payload["ciphertext"]);
+}
+
+#[test]
+fn test_envelope_rejects_tampering() {
+    let (to_b, from_a) = channel_pair();
+    let envelope = to_b.seal(&entry_announce());
+
+    let mut altered = envelope.clone();
+    let mut ciphertext = hex::decode(altered.payload["ciphertext"].as_str().unwrap()).unwrap();
+    ciphertext[0] ^= 1;
+    altered.payload["ciphertext"] = json!(hex::encode(ciphertext));
+    assert!(from_a.open(&altered).is_err());
+
+    // The envelope's ID is authenticated too
+    let mut altered = envelope.clone();
+    altered.message_id = "another-id".to_string();
+    assert!(from_a.open(&altered).is_err());
+
+    // And so is its counter
+    let mut altered = envelope.clone();
+    altered.payload["counter"] = json!(7);
+    assert!(from_a.open(&altered).is_err());
+
+    // Plaintext isn't accepted in place of an envelope
+    assert!(from_a.open(&entry_announce()).is_err());
+
+    // None of which moved the window along
+    assert!(from_a.open(&envelope).is_ok());
+}
+
+#[test]
+fn test_envelope_only_opens_for_recipient() {
+    let (to_b, _) = channel_pair();
+    let envelope = to_b.seal(&entry_announce());
+
+    // A channel from another handshake between the same nodes can't open it
+    let (_, from_a) = channel_pair();
+    assert!(from_a.open(&envelope).is_err());
+
+    // Nor can it be passed off as addressed to someone else
+    let mut redirected = envelope.clone();
+    redirected.recipient_id = "test-node-3".to_string();
+    assert!(from_a.open(&redirected).is_err());
+
+    // Each direction has its own key, so an envelope can't be reflected to its sender
+    let (to_b, from_a) = channel_pair();
+    let mut reflected = to_b.seal(&entry_announce());
+    reflected.sender_id = "test-node-2".to_string();
+    reflected.recipient_id = "test-node-1".to_string();
+    assert!(to_b.open(&reflected).is_err());
+    assert!(from_a.open(&to_b.seal(&entry_announce())).is_ok());
+
+    assert!(EphemeralKey::generate().agree("not a key").is_err());
+    assert!(EphemeralKey::generate().agree(&hex::encode([0u8; 32])).is_err());
+}
+
+#[test]
+fn test_envelope_rejects_replays() {
+    let (to_b, from_a) = channel_pair();
+    let envelopes: Vec<P2PMessage> = (0..REPLAY_WINDOW + 2).map(|_| to_b.seal(&entry_announce())).collect();
+
+    // Envelopes open once each, in whatever order they arrive
+    assert!(from_a.open(&envelopes[1]).is_ok());
+    assert!(from_a.open(&envelopes[0]).is_ok());
+    assert!(from_a.open(&envelopes[1]).unwrap_err().contains("received already"));
+    assert!(from_a.open(&envelopes[0]).is_err());
+
+    // Envelopes that fall out of the window can't be opened late
+    assert!(from_a.open(&envelopes[REPLAY_WINDOW as usize + 1]).is_ok());
+    assert!(from_a.open(&envelopes[2]).is_ok());
+    assert!(from_a.open(&envelopes[1]).unwrap_err().contains("too old"));
+
+    // Clones of a channel share its counters
+    let clone = from_a.clone();
+    assert!(clone.open(&envelopes[2]).is_err());
+    assert_eq!(to_b.clone().seal(&entry_announce()).payload["counter"], json!(REPLAY_WINDOW + 2));
+}
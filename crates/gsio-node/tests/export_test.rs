@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use gsio_node::export::{self, ExportHeader, EXPORT_FORMAT};
+use gsio_node::ledger::{LedgerEntry, RetentionPolicy, SharedLedger};
+use gsio_node::offload::{BlobRef, MemoryBlobStore, Offloader};
+use gsio_node::p2p::P2PManager;
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server_with;
+
+const THRESHOLD: usize = 64;
+
+fn new_node(node_id: &str) -> Arc<P2PManager> {
+    let offloader = Offloader::new(Arc::new(MemoryBlobStore::new()), Some(THRESHOLD));
+    Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())).with_offloader(Arc::new(offloader)))
+}
+
+async fn start_server(p2p: Arc<P2PManager>) -> String {
+    start_server_with(p2p.clone(), export::router(p2p)).await
+}
+
+async fn fetch_export(url: &str, query: &str) -> String {
+    let response = reqwest::get(format!("{url}/api/ledger/export{query}")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    response.text().await.unwrap()
+}
+
+fn large_data(i: usize) -> JsonValue {
+    json!({ "index": i, "message": "x".repeat(THRESHOLD * 2) })
+}
+
+#[tokio::test]
+async fn test_export_and_import_round_trip() {
+    let source = new_node("test-node-1");
+    for i in 0..600 {
+        let data = if i % 100 == 0 { large_data(i) } else { json!({ "index": i }) };
+        source.add_entry_data(data).await.unwrap();
+    }
+    let url = start_server(source.clone()).await;
+
+    // A header line, then every entry in order
+    let ndjson = fetch_export(&url, "?inline_blobs=true").await;
+    let lines: Vec<&str> = ndjson.lines().collect();
+    assert_eq!(lines.len(), 601);
+    let header: ExportHeader = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(header.format, EXPORT_FORMAT);
+    assert_eq!((header.from, header.height), (1, 600));
+    assert!(header.snapshot.is_none());
+    let offloaded: LedgerEntry = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(offloaded.data, large_data(0));
+    assert!(offloaded.blob.is_some());
+
+    // The import rebuilds the same chain, with the offloaded data in the new node's store
+    let target = new_node("test-node-2");
+    let height = export::import(&target, ndjson.as_bytes()).await.unwrap();
+    assert_eq!(height, 600);
+    assert_eq!(target.ledger.merkle_root(), source.ledger.merkle_root());
+    let stored = target.ledger.get_entry_by_id(&offloaded.id).unwrap();
+    assert!(BlobRef::from_data(&stored.data).is_some());
+    assert_eq!(target.rehydrate_entry(stored).await.data, large_data(0));
+
+    // Without inline blobs the references are exported as they are
+    let ndjson = fetch_export(&url, "").await;
+    let first: LedgerEntry = serde_json::from_str(ndjson.lines().nth(1).unwrap()).unwrap();
+    assert!(BlobRef::from_data(&first.data).is_some());
+    assert!(first.blob.is_none());
+
+    // Only an empty ledger can be imported into
+    let err = export::import(&target, ndjson.as_bytes()).await.unwrap_err();
+    assert!(err.contains("empty ledger"));
+}
+
+#[tokio::test]
+async fn test_pruned_chain_imports_from_its_snapshot() {
+    let source = new_node("test-node-1");
+    for i in 0..10 {
+        source.add_entry_data(json!({ "index": i })).await.unwrap();
+    }
+    source.ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 4 });
+    source.ledger.apply_retention();
+    let url = start_server(source.clone()).await;
+
+    let ndjson = fetch_export(&url, "").await;
+    let header: ExportHeader = serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+    assert_eq!((header.from, header.height), (7, 10));
+    assert_eq!(header.snapshot.as_ref().unwrap().height, 7);
+
+    // Only a node that trusts the exporter takes its snapshot
+    let target = new_node("test-node-2");
+    assert!(export::import(&target, ndjson.as_bytes()).await.is_err());
+    target.ledger.set_checkpoint_keys(&[source.ledger.public_key()]).unwrap();
+    assert_eq!(export::import(&target, ndjson.as_bytes()).await.unwrap(), 10);
+    assert_eq!(target.ledger.chain_tip(), source.ledger.chain_tip());
+    assert_eq!(target.ledger.get_entries().len(), 4);
+}
+
+#[tokio::test]
+async fn test_import_rejects_tampered_exports() {
+    let source = new_node("test-node-1");
+    for i in 0..5 {
+        source.add_entry_data(json!({ "index": i })).await.unwrap();
+    }
+    let url = start_server(source).await;
+    let ndjson = fetch_export(&url, "").await;
+    let lines: Vec<String> = ndjson.lines().map(str::to_string).collect();
+
+    // Changed data no longer matches the entry hash
+    let mut tampered = lines.clone();
+    let mut entry: LedgerEntry = serde_json::from_str(&tampered[3]).unwrap();
+    entry.data = json!({ "index": 100 });
+    tampered[3] = serde_json::to_string(&entry).unwrap();
+    let target = new_node("test-node-2");
+    let err = export::import(&target, tampered.join("\n").as_bytes()).await.unwrap_err();
+    assert!(err.contains("doesn't match its hash"), "{err}");
+    // Entries are added in batches, so the ones before it weren't added either
+    assert_eq!(target.ledger.chain_tip().height, 0);
+
+    // A missing entry breaks the chain
+    let mut gapped = lines.clone();
+    gapped.remove(2);
+    let err = export::import(&new_node("test-node-3"), gapped.join("\n").as_bytes()).await.unwrap_err();
+    assert!(err.contains("doesn't link"), "{err}");
+
+    // A truncated export is noticed
+    let err = export::import(&new_node("test-node-4"), lines[..4].join("\n").as_bytes()).await.unwrap_err();
+    assert!(err.contains("instead of 5"), "{err}");
+
+    // Anything but an export is refused up front
+    let err = export::import(&new_node("test-node-5"), &b"{\"id\": \"not a header\"}\n"[..]).await.unwrap_err();
+    assert!(err.contains("Invalid export header"), "{err}");
+}
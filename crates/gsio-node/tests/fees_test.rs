@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use clap::Parser;
+use gsio_client::GsioClient;
+use gsio_node::config::{Cli, NodeConfig};
+use gsio_node::fees::{self, FeeConfig};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+async fn start_server(fees: FeeConfig) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, fees::router(Arc::new(fees))).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+#[test]
+fn test_fee_rules() {
+    let fees = FeeConfig {
+        base: 1,
+        per_byte: 2,
+        types: [("Stake".to_string(), 10)].into(),
+    };
+    assert_eq!(fees.fee("Transfer", 0), 1);
+    assert_eq!(fees.fee("Transfer", 5), 11);
+    assert_eq!(fees.fee("Stake", 5), 20);
+    assert_eq!(fees.fee("Transfer", usize::MAX), u64::MAX);
+}
+
+#[test]
+fn test_fees_config() {
+    let path = std::env::temp_dir().join(format!("gsio-node-{}.toml", Uuid::new_v4()));
+    std::fs::write(&path, "[fees]\nper_byte = 2\ntypes = { Stake = 10 }\n").unwrap();
+    let mut config = NodeConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config.fees.base, 1);
+    assert_eq!(config.fees.per_byte, 2);
+    assert_eq!(config.fees.types.get("Stake"), Some(&10));
+
+    let vars: HashMap<&str, &str> = [("FEE_BASE", "3"), ("FEE_PER_BYTE", "4")].into();
+    config.apply_env(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+    assert_eq!((config.fees.base, config.fees.per_byte), (3, 4));
+
+    config.apply_cli(&Cli::try_parse_from(["gsio-node", "--fee-base", "5"]).unwrap());
+    assert_eq!((config.fees.base, config.fees.per_byte), (5, 4));
+
+    assert!(config.apply_env(|name| (name == "FEE_BASE").then(|| "-1".to_string())).is_err());
+}
+
+#[tokio::test]
+async fn test_clients_fetch_fee_rules() {
+    let fees = FeeConfig {
+        base: 2,
+        per_byte: 1,
+        types: [("Stake".to_string(), 10)].into(),
+    };
+    let url = start_server(fees).await;
+
+    let rules = GsioClient::new(&url).unwrap().get_fee_rules().await.unwrap();
+    assert_eq!((rules.base, rules.per_byte), (2, 1));
+    assert_eq!(rules.fee("Stake", 3), 13);
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::config::NodeConfig;
+use gsio_node::gc::{referenced_blobs, BlobGc, BlobGcConfig, SNAPSHOT_TAG};
+use gsio_node::ledger::{LedgerEntry, SharedLedger};
+use gsio_node::offload::{BlobRef, BlobStore, IrohBlobStore, Offloader};
+use gsio_node::p2p::P2PManager;
+use iroh::{Endpoint, RelayMode};
+use iroh_blobs::net_protocol::Blobs;
+use iroh_blobs::store::Store;
+use iroh_blobs::Hash;
+use serde_json::json;
+
+async fn bind_endpoint() -> Endpoint {
+    Endpoint::builder().relay_mode(RelayMode::Disabled).bind().await.unwrap()
+}
+
+fn large_data(message: &str) -> serde_json::Value {
+    json!({ "message": message.repeat(64) })
+}
+
+#[test]
+fn test_blob_gc_config() {
+    let config: NodeConfig = toml::from_str(
+        r#"
+        blob_path = "/var/lib/gsio/blobs"
+
+        [blob_gc]
+        interval = 600
+        "#,
+    )
+    .unwrap();
+    assert!(config.blob_gc.is_enabled());
+    assert_eq!(config.blob_gc.interval(), Duration::from_secs(600));
+    assert_eq!(config.blob_gc.grace(), BlobGcConfig::default().grace());
+    assert!(!NodeConfig::default().blob_gc.is_enabled());
+
+    let mut config = NodeConfig::default();
+    config
+        .apply_env(|name| match name {
+            "BLOB_GC_INTERVAL" => Some("60".to_string()),
+            "BLOB_GC_GRACE" => Some("0".to_string()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(config.blob_gc, BlobGcConfig { interval: 60, grace: 0 });
+}
+
+#[tokio::test]
+async fn test_unreferenced_blobs_are_collected() {
+    let endpoint = bind_endpoint().await;
+    let blobs = Arc::new(Blobs::memory().build(&endpoint));
+    let store = Arc::new(IrohBlobStore::new(blobs.clone(), endpoint.node_id()));
+    let node_id = "test-node-1".to_string();
+    let p2p = P2PManager::new(node_id.clone(), SharedLedger::new(node_id))
+        .with_offloader(Arc::new(Offloader::new(store.clone(), Some(16))));
+
+    let entry = p2p.add_entry_data(large_data("kept")).await.unwrap();
+    let orphan = store.put(large_data("orphan").to_string().into_bytes()).await.unwrap();
+    let referenced = referenced_blobs(&p2p.ledger.get_entries());
+    let kept: Hash = BlobRef::from_data(entry.blob.as_ref().unwrap()).unwrap().hash.parse().unwrap();
+    assert_eq!(referenced, [kept].into());
+
+    // Nothing is deleted within the grace period
+    let patient = BlobGc::load(blobs.clone(), p2p.ledger.clone(), Duration::from_secs(3600)).await.unwrap();
+    assert_eq!(patient.unreferenced(), 1);
+    assert_eq!(patient.collect().await.unwrap(), 0);
+
+    let gc = BlobGc::load(blobs.clone(), p2p.ledger.clone(), Duration::ZERO).await.unwrap();
+    assert_eq!(gc.collect().await.unwrap(), 1);
+    assert_eq!(gc.unreferenced(), 0);
+    let client = blobs.client();
+    assert!(client.has(kept).await.unwrap());
+    assert!(!client.has(orphan.parse().unwrap()).await.unwrap());
+    assert_eq!(p2p.rehydrate(p2p.ledger.get_entries()).await[0].data, large_data("kept"));
+}
+
+#[tokio::test]
+async fn test_pending_and_snapshot_blobs_are_kept() {
+    let endpoint = bind_endpoint().await;
+    let blobs = Arc::new(Blobs::memory().build(&endpoint));
+    let store = IrohBlobStore::new(blobs.clone(), endpoint.node_id());
+    let node_id = "test-node-1".to_string();
+    let ledger = SharedLedger::new(node_id);
+
+    // An entry from a peer still waiting for its parent
+    let data = large_data("pending").to_string().into_bytes();
+    let reference = BlobRef { hash: store.put(data.clone()).await.unwrap(), size: data.len() as u64, provider: None };
+    let pending = LedgerEntry::new(reference.to_data(), "unknown-parent".to_string(), "test-node-2".to_string());
+    assert!(ledger.add_pending_entry(pending));
+
+    // The last snapshot published, and an older one whose tag has moved on
+    let client = blobs.client();
+    let older = client.add_bytes_named(b"older snapshot".to_vec(), SNAPSHOT_TAG).await.unwrap();
+    let latest = client.add_bytes_named(b"latest snapshot".to_vec(), SNAPSHOT_TAG).await.unwrap();
+
+    let gc = BlobGc::load(blobs.clone(), ledger, Duration::ZERO).await.unwrap();
+    assert_eq!(gc.collect().await.unwrap(), 1);
+    assert!(client.has(reference.hash.parse().unwrap()).await.unwrap());
+    assert!(client.has(latest.hash).await.unwrap());
+    assert!(!client.has(older.hash).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_persistent_blobs_survive_restart() {
+    let path = std::env::temp_dir().join(format!("gsio-blobs-{}", uuid::Uuid::new_v4()));
+    let data = large_data("persisted").to_string().into_bytes();
+
+    let endpoint = bind_endpoint().await;
+    let blobs = Arc::new(Blobs::persistent(&path).await.unwrap().build(&endpoint));
+    let hash = IrohBlobStore::new(blobs.clone(), endpoint.node_id()).put(data.clone()).await.unwrap();
+    // Stopping the store releases the database for the next run
+    blobs.store().shutdown().await;
+    drop(blobs);
+    endpoint.close().await;
+
+    let endpoint = bind_endpoint().await;
+    let blobs = Arc::new(Blobs::persistent(&path).await.unwrap().build(&endpoint));
+    let store = IrohBlobStore::new(blobs.clone(), endpoint.node_id());
+    assert_eq!(store.get(hash.clone(), None).await.unwrap(), Some(data));
+
+    // The collector picks up blobs kept from the earlier run
+    let node_id = "test-node-1".to_string();
+    let gc = BlobGc::load(blobs, SharedLedger::new(node_id), Duration::ZERO).await.unwrap();
+    assert_eq!(gc.unreferenced(), 1);
+    assert_eq!(gc.collect().await.unwrap(), 1);
+    assert_eq!(store.get(hash, None).await.unwrap(), None);
+    std::fs::remove_dir_all(&path).ok();
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{TimeZone, Utc};
+use ed25519_dalek::SigningKey;
+use gsio_client::Address;
+use gsio_node::consensus::{ConsensusConfig, Validator};
+use gsio_node::genesis::{Genesis, GenesisInfo};
+use gsio_node::ledger::{Ledger, SharedLedger, GENESIS_HASH};
+use gsio_node::p2p::P2PManager;
+use rand::rngs::OsRng;
+use serde_json::json;
+use uuid::Uuid;
+
+mod common;
+
+use common::start_server;
+
+fn address(key: &SigningKey) -> Address {
+    Address::from_public_key(&key.verifying_key().to_bytes())
+}
+
+fn genesis(chain_id: &str, allocations: &[(String, u64)]) -> Genesis {
+    Genesis {
+        chain_id: chain_id.to_string(),
+        timestamp: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+        validators: Vec::new(),
+        allocations: allocations.iter().cloned().collect(),
+    }
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_genesis_file() {
+    let key = SigningKey::generate(&mut OsRng);
+    let checksummed = address(&key).to_string();
+    let legacy = format!("gsio_{}", hex::encode(address(&key).as_bytes()));
+
+    // Addresses are written the same way whichever way the file has them
+    let path = std::env::temp_dir().join(format!("gsio-genesis-{}.json", Uuid::new_v4()));
+    let file = json!({
+        "chain_id": "gsio-test",
+        "timestamp": "2025-01-01T00:00:00Z",
+        "allocations": { legacy: 1_000 },
+    });
+    std::fs::write(&path, file.to_string()).unwrap();
+    let loaded = Genesis::from_file(&path).unwrap();
+    assert_eq!(loaded, genesis("gsio-test", &[(checksummed.clone(), 1_000)]));
+    assert_eq!(loaded.hash(), genesis("gsio-test", &[(checksummed.clone(), 1_000)]).hash());
+    assert_ne!(loaded.hash(), genesis("gsio-other", &[(checksummed.clone(), 1_000)]).hash());
+
+    let mut duplicated = genesis("gsio-test", &[(checksummed.clone(), 1), (legacy, 2)]);
+    assert!(duplicated.clone().normalized().is_err());
+    duplicated.allocations = BTreeMap::from([("not-an-address".to_string(), 1)]);
+    assert!(duplicated.normalized().is_err());
+    assert!(genesis(" ", &[]).normalized().is_err());
+
+    std::fs::write(&path, json!({ "chain_id": "gsio-test", "timestamp": "2025-01-01T00:00:00Z", "extra": 1 }).to_string())
+        .unwrap();
+    assert!(Genesis::from_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_chain_starts_from_genesis() {
+    let key = SigningKey::generate(&mut OsRng);
+    let me = address(&key).to_string();
+    let genesis = genesis("gsio-test", &[(me.clone(), 1_000)]);
+
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    assert_eq!(ledger.genesis_hash(), GENESIS_HASH);
+    ledger.set_genesis(genesis.clone()).unwrap();
+    assert_eq!(ledger.genesis_hash(), genesis.hash());
+    assert_eq!(ledger.stake(&me).unwrap().balance, 1_000);
+
+    let entry = ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert_eq!(entry.previous_hash, genesis.hash());
+
+    // The chain has begun, so the genesis can't change
+    assert!(ledger.set_genesis(genesis).is_err());
+}
+
+#[test]
+fn test_genesis_validators() {
+    let key = SigningKey::generate(&mut OsRng);
+    let validator = Validator {
+        node_id: "test-node-1".to_string(),
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+    };
+    let genesis = Genesis { validators: vec![validator.clone()], ..genesis("gsio-test", &[]) };
+
+    let config = genesis.consensus(&ConsensusConfig::default()).unwrap();
+    assert_eq!(config.validators, vec![validator.clone()]);
+    assert_eq!(genesis.consensus(&config).unwrap(), config);
+
+    let other = Validator { node_id: "test-node-2".to_string(), ..validator };
+    let config = ConsensusConfig { validators: vec![other], ..ConsensusConfig::default() };
+    assert!(genesis.consensus(&config).is_err());
+}
+
+#[tokio::test]
+async fn test_peers_share_genesis() {
+    let nodes: Vec<Arc<P2PManager>> = ["test-node-1", "test-node-2", "test-node-3"]
+        .iter()
+        .zip(["gsio-test", "gsio-test", "gsio-other"])
+        .map(|(id, chain_id)| {
+            let ledger = SharedLedger::new(id.to_string());
+            ledger.set_genesis(genesis(chain_id, &[])).unwrap();
+            Arc::new(P2PManager::new(id.to_string(), ledger))
+        })
+        .collect();
+
+    let url = start_server(nodes[0].clone()).await;
+    nodes[1].dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || nodes[0].peer_health().len() == 1).await;
+    let entry = nodes[0].add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || nodes[1].ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    // A node started from another genesis isn't let in, and neither is one without
+    let outsider = Arc::new(P2PManager::new("test-node-4".to_string(), SharedLedger::new("test-node-4".to_string())));
+    for node in [&nodes[2], &outsider] {
+        node.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(nodes[0].peer_health().len(), 1);
+    assert!(nodes[2].peer_health().is_empty() && outsider.peer_health().is_empty());
+    assert!(nodes[2].ledger.get_entry_by_id(&entry.id).is_none());
+
+    for node in nodes[1..].iter().chain([&outsider]) {
+        node.leave().await;
+    }
+}
+
+#[tokio::test]
+async fn test_genesis_api() {
+    let genesis = genesis("gsio-test", &[]);
+    let ledger = SharedLedger::new("test-node-1".to_string());
+    ledger.set_genesis(genesis.clone()).unwrap();
+    let url = start_server(Arc::new(P2PManager::new("test-node-1".to_string(), ledger))).await;
+
+    let info: GenesisInfo = reqwest::get(format!("{url}/api/genesis")).await.unwrap().json().await.unwrap();
+    assert_eq!(info, GenesisInfo { hash: genesis.hash(), genesis: Some(genesis) });
+
+    // Nodes without a genesis report the zero hash
+    let url = start_server(Arc::new(P2PManager::new("test-node-2".to_string(), SharedLedger::new("test-node-2".to_string())))).await;
+    let info: GenesisInfo = reqwest::get(format!("{url}/api/genesis")).await.unwrap().json().await.unwrap();
+    assert_eq!(info, GenesisInfo { hash: GENESIS_HASH.to_string(), genesis: None });
+}
@@ -0,0 +1,284 @@
+use std::sync::Arc;
+use gsio_node::grpc::proto::gsio_client::GsioClient;
+use gsio_node::grpc::proto::{
+    AddEntryRequest, GetEntriesRequest, GetLedgerRequest, GetPeersRequest, GetStatusRequest,
+    StreamEntriesRequest, SubmitTransactionRequest, SubscribeEntriesRequest,
+};
+use gsio_node::auth::{AuthConfig, Authenticator};
+use gsio_node::grpc::{CallGuard, GsioService, ERROR_CODE_KEY, STREAM_PAGE_SIZE};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{NodeMode, P2PManager, MAX_SYNC_PAGE_SIZE};
+use gsio_node::ratelimit::{RateLimitConfig, RateLimiter};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::StreamExt;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+async fn start_server(p2p: Arc<P2PManager>) -> GsioClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(GsioService::new(p2p).into_server())
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    GsioClient::connect(format!("http://{addr}")).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_grpc_add_and_get_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id.clone())));
+    let mut client = start_server(p2p.clone()).await;
+
+    // Add entries over gRPC
+    let data = json!({ "message": "Test entry 1" });
+    let entry = client
+        .add_entry(AddEntryRequest { data_json: data.to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(entry.creator_node_id, node_id);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&entry.data_json).unwrap(), data);
+
+    client
+        .add_entry(AddEntryRequest { data_json: json!({ "message": "Test entry 2" }).to_string() })
+        .await
+        .unwrap();
+
+    // Stream them back
+    let entries: Vec<_> = client
+        .get_entries(GetEntriesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .collect::<Result<_, _>>()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, entry.id);
+    assert_eq!(entries[1].previous_hash, entries[0].hash);
+
+    // Status reflects the ledger
+    let status = client.get_status(GetStatusRequest {}).await.unwrap().into_inner();
+    assert_eq!(status.node_id, node_id);
+    assert_eq!(status.mode, "writer");
+    assert_eq!(status.entry_count, 2);
+    assert_eq!(status.tip_hash, entries[1].hash);
+
+    // Malformed JSON is rejected
+    let err = client
+        .add_entry(AddEntryRequest { data_json: "{not json".to_string() })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_grpc_subscribe_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let mut client = start_server(p2p.clone()).await;
+
+    let mut stream = client
+        .subscribe_entries(SubscribeEntriesRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+
+    // Entries added through any interface are pushed to subscribers
+    let added = p2p.add_local_entry(json!({ "message": "Live entry" })).unwrap();
+    let received = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.id, added.id);
+}
+
+#[tokio::test]
+async fn test_grpc_get_ledger_and_peers() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    p2p.ledger.add_known_node("test-node-2".to_string());
+    let mut client = start_server(p2p.clone()).await;
+
+    for i in 0..3 {
+        p2p.ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    let entries = p2p.ledger.get_entries();
+
+    let ledger = client.get_ledger(GetLedgerRequest { offset: 1, limit: 1 }).await.unwrap().into_inner();
+    assert_eq!(ledger.entry_count, 3);
+    assert_eq!(ledger.entries.len(), 1);
+    assert_eq!(ledger.entries[0].id, entries[1].id);
+
+    // A limit of 0 returns the rest of the ledger, up to a page
+    let ledger = client.get_ledger(GetLedgerRequest { offset: 1, limit: 0 }).await.unwrap().into_inner();
+    assert_eq!(ledger.entries.len(), 2);
+
+    // Known nodes other than this one, none of them connected
+    let peers = client.get_peers(GetPeersRequest {}).await.unwrap().into_inner().peers;
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].node_id, "test-node-2");
+    assert!(!peers[0].connected);
+    assert_eq!(peers[0].protocol_version, None);
+}
+
+#[tokio::test]
+async fn test_grpc_pages_large_ledgers() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let mut client = start_server(p2p.clone()).await;
+    for i in 0..MAX_SYNC_PAGE_SIZE + 10 {
+        p2p.ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    // Neither a limit of 0 nor a larger one returns more than a page
+    for limit in [0, u64::MAX] {
+        let ledger = client.get_ledger(GetLedgerRequest { offset: 0, limit }).await.unwrap().into_inner();
+        assert_eq!(ledger.entries.len(), MAX_SYNC_PAGE_SIZE);
+        assert_eq!(ledger.entry_count as usize, MAX_SYNC_PAGE_SIZE + 10);
+    }
+
+    // Streams read the ledger a page at a time, in order
+    assert!(MAX_SYNC_PAGE_SIZE + 10 > STREAM_PAGE_SIZE);
+    let entries: Vec<_> = client
+        .get_entries(GetEntriesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .collect::<Result<_, _>>()
+        .await
+        .unwrap();
+    let held = p2p.ledger.get_entries();
+    assert_eq!(entries.iter().map(|e| &e.id).collect::<Vec<_>>(), held.iter().map(|e| &e.id).collect::<Vec<_>>());
+
+    let since = held[STREAM_PAGE_SIZE + 5].timestamp;
+    let expected: Vec<&String> = held.iter().filter(|e| e.timestamp > since).map(|e| &e.id).collect();
+    let request = StreamEntriesRequest { since: since.to_rfc3339() };
+    let stream = client.stream_entries(request).await.unwrap().into_inner();
+    let streamed: Vec<_> = stream.take(expected.len()).collect::<Result<_, _>>().await.unwrap();
+    assert_eq!(streamed.iter().map(|e| &e.id).collect::<Vec<_>>(), expected);
+}
+
+#[tokio::test]
+async fn test_grpc_stream_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let mut client = start_server(p2p.clone()).await;
+
+    let first = p2p.ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let second = p2p.ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    // Held entries after `since` come first, then new ones
+    let mut stream = client
+        .stream_entries(StreamEntriesRequest { since: first.timestamp.to_rfc3339() })
+        .await
+        .unwrap()
+        .into_inner();
+    let live = p2p.add_local_entry(json!({ "message": "Live entry" })).unwrap();
+
+    for expected in [&second, &live] {
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.id, expected.id);
+    }
+
+    let err = client
+        .stream_entries(StreamEntriesRequest { since: "yesterday".to_string() })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_grpc_submit_transaction() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let mut client = start_server(p2p.clone()).await;
+
+    // Unsigned transactions are rejected
+    let unsigned = json!({ "id": "tx-1", "amount": 10, "signature": null });
+    let err = client
+        .submit_transaction(SubmitTransactionRequest { transaction_json: unsigned.to_string() })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    // Signed transactions are recorded as ledger entries
+    let signed = json!({ "id": "tx-1", "amount": 10, "signature": "abcd" });
+    client
+        .submit_transaction(SubmitTransactionRequest { transaction_json: signed.to_string() })
+        .await
+        .unwrap();
+
+    let entry = p2p.ledger.get_last_entry().unwrap();
+    assert_eq!(entry.data["type"], "transaction");
+    assert_eq!(entry.data["transaction"], signed);
+}
+
+#[tokio::test]
+async fn test_grpc_follower_rejects_writes() {
+    let node_id = "test-node-1".to_string();
+    let mode = NodeMode::Follower { writable_node: None };
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)).with_mode(mode));
+    let mut client = start_server(p2p).await;
+
+    let err = client
+        .add_entry(AddEntryRequest { data_json: json!({}).to_string() })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::FailedPrecondition);
+
+    let status = client.get_status(GetStatusRequest {}).await.unwrap().into_inner();
+    assert_eq!(status.mode, "follower");
+}
+
+#[tokio::test]
+async fn test_grpc_guard() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let auth = Authenticator::new(&AuthConfig { api_keys: vec!["test-api-key".to_string()], ..AuthConfig::default() });
+    let limiter = RateLimiter::new(&RateLimitConfig { requests_per_second: 0.1, burst: 2 });
+    let guard = CallGuard::new()
+        .with_authenticator(Arc::new(auth.unwrap()))
+        .with_rate_limiter(Arc::new(limiter.unwrap()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(GsioService::new(p2p).into_guarded_server(guard))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    let mut client = GsioClient::connect(format!("http://{addr}")).await.unwrap();
+    let add = |key: Option<&str>| {
+        let mut request = Request::new(AddEntryRequest { data_json: json!({ "message": "Test entry" }).to_string() });
+        if let Some(key) = key {
+            request.metadata_mut().insert("authorization", format!("Bearer {key}").parse().unwrap());
+        }
+        request
+    };
+
+    // Calls need a credential, like requests to the HTTP API
+    assert_eq!(client.add_entry(add(None)).await.unwrap_err().code(), Code::Unauthenticated);
+    client.add_entry(add(Some("test-api-key"))).await.unwrap();
+
+    // And share its rate limit
+    let err = client.add_entry(add(Some("test-api-key"))).await.unwrap_err();
+    assert_eq!(err.code(), Code::ResourceExhausted);
+    assert_eq!(err.metadata().get(ERROR_CODE_KEY).unwrap(), "rate_limited");
+    assert!(err.metadata().get("retry-after").is_some());
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::api;
+use gsio_node::health::{self, Health, HealthReport};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::supervisor::{RestartPolicy, Supervisor, TaskState, Tasks};
+use iroh::{Endpoint, RelayMode};
+use serde_json::json;
+
+mod common;
+
+use common::start_server_with;
+
+fn new_node(node_id: &str) -> Arc<P2PManager> {
+    Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())))
+}
+
+async fn start_server(p2p: Arc<P2PManager>, health: Arc<Health>) -> String {
+    let app = health::router(health).merge(api::router(p2p.clone()));
+    start_server_with(p2p, app).await
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+async fn probe(url: &str, path: &str) -> (reqwest::StatusCode, HealthReport) {
+    let response = reqwest::get(format!("{url}{path}")).await.unwrap();
+    (response.status(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_ready_once_ledger_initialized() {
+    let p2p = new_node("test-node-1");
+    p2p.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let health = Arc::new(Health::new(p2p.clone()));
+    let url = start_server(p2p, health.clone()).await;
+
+    // Alive but not ready while the ledger is being set up
+    let (status, report) = probe(&url, "/healthz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert!(!report.ready);
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!report.ledger.initialized);
+
+    health.set_ledger_initialized();
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(report.node_id, "test-node-1");
+    assert_eq!(report.ledger.height, 1);
+    assert!(report.iroh.is_none());
+    assert!(report.last_sync.is_none());
+
+    // A node that is shutting down stops taking traffic but stays alive
+    health.set_draining();
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(report.draining);
+    let (status, _) = probe(&url, "/healthz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_peers_and_last_sync_reported() {
+    let server = new_node("test-node-1");
+    server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let server_url = start_server(server.clone(), Arc::new(Health::new(server.clone()))).await;
+
+    let client = new_node("test-node-2");
+    let health = Arc::new(Health::new(client.clone()));
+    health.set_ledger_initialized();
+    let client_url = start_server(client.clone(), health.clone()).await;
+    client.dial_peer(server_url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || client.last_sync().is_some()).await;
+
+    let (status, report) = probe(&client_url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(report.peers.outbound, 1);
+    assert_eq!(report.ledger.height, 1);
+    assert_eq!(report.last_sync, client.last_sync());
+
+    client.leave().await;
+}
+
+#[tokio::test]
+async fn test_not_ready_with_closed_endpoint() {
+    let p2p = new_node("test-node-1");
+    let endpoint = Endpoint::builder().relay_mode(RelayMode::Disabled).bind().await.unwrap();
+    let health = Arc::new(Health::new(p2p.clone()).with_endpoint(endpoint.clone()));
+    health.set_ledger_initialized();
+    let url = start_server(p2p, health).await;
+
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let iroh = report.iroh.unwrap();
+    assert_eq!(iroh.node_id, endpoint.node_id().to_string());
+    assert!(!iroh.closed);
+
+    endpoint.close().await;
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(report.iroh.unwrap().closed);
+}
+
+#[tokio::test]
+async fn test_not_ready_with_failed_task() {
+    let p2p = new_node("test-node-1");
+    let tasks = Tasks::new();
+    let health = Arc::new(Health::new(p2p.clone()).with_tasks(tasks.clone()));
+    health.set_ledger_initialized();
+    let url = start_server(p2p, health).await;
+
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert!(report.tasks.is_empty());
+
+    let task = Supervisor::new("test").with_policy(RestartPolicy::Never).with_tasks(&tasks);
+    task.spawn(|| async { panic!("Test panic") }).await.unwrap();
+    let (status, report) = probe(&url, "/readyz").await;
+    assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(report.tasks["test"].state, TaskState::Failed);
+    assert_eq!(report.tasks["test"].last_panic.as_deref(), Some("Test panic"));
+
+    // Liveness doesn't depend on background tasks
+    let (status, _) = probe(&url, "/healthz").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+}
@@ -0,0 +1,27 @@
+use ed25519_dalek::SigningKey;
+use gsio_node::identity;
+use gsio_node::ledger::SharedLedger;
+use iroh::{Endpoint, RelayMode};
+use rand::rngs::OsRng;
+
+#[tokio::test]
+async fn test_node_id_is_iroh_node_id() {
+    let key = SigningKey::generate(&mut OsRng);
+    let node_id = identity::node_id(&key);
+
+    let endpoint = Endpoint::builder()
+        .secret_key(identity::iroh_secret_key(&key))
+        .relay_mode(RelayMode::Disabled)
+        .bind()
+        .await
+        .unwrap();
+    assert_eq!(identity::from_iroh(&endpoint.node_id()), node_id);
+    // The ID parses back to the endpoint's iroh ID
+    assert_eq!(node_id.parse::<iroh::NodeId>().unwrap(), endpoint.node_id());
+
+    // Entries are signed with the key the ID names
+    let ledger = SharedLedger::with_signing_key(node_id.clone(), key);
+    assert!(identity::is_key_of(&node_id, &ledger.public_key()));
+    assert!(!identity::is_key_of("test-node-1", &ledger.public_key()));
+    endpoint.close().await;
+}
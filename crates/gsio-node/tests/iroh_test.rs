@@ -83,7 +83,7 @@ async fn test_blob_announcement() {
     let addr = router_clone.endpoint().node_addr().await.unwrap();
 
     // Verify we can get the node address (using debug format since Display is not implemented)
-    assert!(format!("{:?}", addr).len() > 0);
+    assert!(!format!("{:?}", addr).is_empty());
 
     // Verify the hash is valid
     assert!(!res.hash.to_string().is_empty());
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_client::GsioClient;
+use gsio_node::admin::{self, Admin, AdminConfig, RuntimeConfig};
+use gsio_node::discovery::{parse_node_addr, Discovery, PeerRecord, DISCOVERY_ALPN, DISCOVERY_TOPIC};
+use gsio_node::identity;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use iroh::protocol::Router;
+use iroh::{Endpoint, NodeAddr, RelayMode};
+use iroh_blobs::ticket::BlobTicket;
+use iroh_blobs::{BlobFormat, Hash};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+struct TestNode {
+    discovery: Discovery,
+    learned: UnboundedReceiver<PeerRecord>,
+    addr: NodeAddr,
+    _router: Router,
+}
+
+impl TestNode {
+    /// The ID the node announces itself under
+    fn id(&self) -> String {
+        identity::from_iroh(&self.addr.node_id)
+    }
+}
+
+/// Start a node gossiping on the default topic, announcing a URL if it has a name
+async fn start_node(name: Option<&str>) -> TestNode {
+    let endpoint = Endpoint::builder().relay_mode(RelayMode::Disabled).bind().await.unwrap();
+    let url = name.map(|name| format!("http://{name}.example:3000"));
+    let (discovery, learned) = Discovery::new(endpoint.clone(), DISCOVERY_TOPIC, url, Duration::from_secs(30));
+    let router = Router::builder(endpoint.clone()).accept(DISCOVERY_ALPN, discovery.clone()).spawn();
+    let addr = endpoint.node_addr().await.unwrap();
+    TestNode { discovery, learned, addr, _router: router }
+}
+
+const ADMIN_KEY: &str = "test-admin-key";
+
+/// Serve the admin routes, which joining is one of
+async fn start_server(discovery: Discovery) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let p2p = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())));
+    let admin = Arc::new(Admin::new(p2p, RuntimeConfig::default()).with_discovery(discovery));
+    let app = admin::router(admin, &AdminConfig { api_keys: vec![ADMIN_KEY.to_string()] }).unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_tickets_and_node_ids_are_parsed() {
+    let node = start_node(None).await;
+    let node_id = node.addr.node_id.to_string();
+    assert_eq!(parse_node_addr(&node_id).unwrap(), NodeAddr::new(node.addr.node_id));
+    assert_eq!(parse_node_addr(&format!(" {node_id}\n")).unwrap().node_id, node.addr.node_id);
+
+    // Tickets carry the direct addresses as well
+    let ticket = BlobTicket::new(node.addr.clone(), Hash::new(b"snapshot"), BlobFormat::Raw).unwrap();
+    assert_eq!(parse_node_addr(&ticket.to_string()).unwrap(), node.addr);
+
+    assert!(parse_node_addr("not-a-ticket").is_err());
+    assert!(parse_node_addr("").is_err());
+}
+
+#[tokio::test]
+async fn test_join_peer_over_api() {
+    let mut a = start_node(Some("node-a")).await;
+    let b = start_node(Some("node-b")).await;
+    let client = GsioClient::new(&start_server(a.discovery.clone()).await).unwrap().with_api_key(ADMIN_KEY);
+
+    let ticket = BlobTicket::new(b.addr.clone(), Hash::new(b"snapshot"), BlobFormat::Raw).unwrap();
+    let joined = client.join_peer(&ticket.to_string()).await.unwrap();
+    assert_eq!((joined.peer.node_id, joined.peer.url.as_str()), (b.id(), "http://node-b.example:3000"));
+    assert_eq!(joined.learned, 1);
+    assert_eq!(a.learned.recv().await.unwrap().node_id, b.id());
+
+    // Joining a known node again still has it dialed
+    let joined = client.join_peer(&b.addr.node_id.to_string()).await.unwrap();
+    assert_eq!(joined.learned, 0);
+    assert_eq!(a.learned.recv().await.unwrap().node_id, b.id());
+
+    // node-b learned about node-a through the exchange
+    let peers = b.discovery.peers();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].node_id, a.id());
+}
+
+#[tokio::test]
+async fn test_join_peer_errors() {
+    let a = start_node(Some("node-a")).await;
+    let anonymous = start_node(None).await;
+    let url = start_server(a.discovery.clone()).await;
+    let http = reqwest::Client::new();
+    let join = |ticket: String| {
+        let request = http.post(format!("{url}/admin/peers/join")).json(&json!({ "ticket": ticket }));
+        request.bearer_auth(ADMIN_KEY).send()
+    };
+
+    // Only operators can join nodes
+    let response = http.post(format!("{url}/admin/peers/join")).json(&json!({ "ticket": a.id() })).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = join("not-a-ticket".to_string()).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // A node without a public URL can't be dialed
+    let ticket = BlobTicket::new(anonymous.addr.clone(), Hash::new(b"snapshot"), BlobFormat::Raw).unwrap();
+    let response = join(ticket.to_string()).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Nor can one there's no route to
+    let unreachable = start_node(Some("node-c")).await;
+    let response = join(unreachable.addr.node_id.to_string()).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_GATEWAY);
+
+    let error = GsioClient::new(&url).unwrap().with_api_key(ADMIN_KEY).join_peer("not-a-ticket").await.unwrap_err();
+    assert!(error.to_string().contains("neither a blob ticket nor an iroh node ID"));
+    assert!(a.discovery.peers().is_empty());
+}
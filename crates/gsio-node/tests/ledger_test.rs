@@ -1,4 +1,8 @@
-use gsio_node::ledger::{LedgerEntry, Ledger, SharedLedger};
+use chrono::{Duration, Utc};
+use ed25519_dalek::SigningKey;
+use gsio_node::ledger::{LedgerEntry, LedgerMode, Ledger, Limits, RetentionPolicy, SharedLedger, GENESIS_HASH};
+use gsio_node::merkle::{self, EMPTY_ROOT};
+use rand::rngs::OsRng;
 use serde_json::json;
 
 #[test]
@@ -99,6 +103,36 @@ fn test_shared_ledger() {
     assert_eq!(last_entry.data, data2);
 }
 
+#[test]
+fn test_shared_ledger_concurrent_reads() {
+    let shared_ledger = SharedLedger::new("test-node-1".to_string());
+    let first = shared_ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    // Readers run alongside a writer without seeing a half-added entry
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..200 {
+                    let (count, linked) = shared_ledger.with_entries(|entries| {
+                        (entries.len(), entries.windows(2).all(|pair| pair[1].previous_hash == pair[0].hash))
+                    });
+                    assert!(count >= 1);
+                    assert!(linked);
+                    assert_eq!(shared_ledger.get_entry_by_id(&first.id).unwrap().hash, first.hash);
+                }
+            });
+        }
+        scope.spawn(|| {
+            for i in 2..=50 {
+                shared_ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+            }
+        });
+    });
+
+    assert_eq!(shared_ledger.entry_count(), 50);
+    assert_eq!(shared_ledger.with_entries(|entries| entries[49].data.clone()), json!({ "message": "Test entry 50" }));
+}
+
 #[test]
 fn test_pending_entries() {
     // Create a new shared ledger
@@ -109,9 +143,12 @@ fn test_pending_entries() {
     let data1 = json!({ "message": "Test entry 1" });
     let entry1 = shared_ledger.add_entry(data1.clone()).unwrap();
 
-    // Create a pending entry that links to the first entry
+    // Create a pending entry from another node that links to the first entry
+    let node2_key = SigningKey::generate(&mut OsRng);
+    shared_ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
     let data2 = json!({ "message": "Test entry 2" });
-    let entry2 = LedgerEntry::new(data2.clone(), entry1.hash.clone(), "test-node-2".to_string());
+    let mut entry2 = LedgerEntry::new(data2.clone(), entry1.hash.clone(), "test-node-2".to_string());
+    entry2.sign("test-node-2".to_string(), &node2_key);
 
     // Add the pending entry
     shared_ledger.add_pending_entry(entry2.clone());
@@ -147,4 +184,520 @@ fn test_known_nodes() {
     assert!(known_nodes.contains(&node_id));
     assert!(known_nodes.contains(&"test-node-2".to_string()));
     assert!(known_nodes.contains(&"test-node-3".to_string()));
+
+    // Forgetting a node leaves the others, and this node is never forgotten
+    assert!(shared_ledger.remove_known_node("test-node-2"));
+    assert!(!shared_ledger.remove_known_node("test-node-2"));
+    assert!(!shared_ledger.remove_known_node(&node_id));
+    assert_eq!(shared_ledger.get_known_nodes().len(), 2);
+}
+
+#[test]
+fn test_retention_policy_parsing() {
+    assert_eq!("forever".parse::<RetentionPolicy>().unwrap(), RetentionPolicy::KeepForever);
+    assert_eq!("days:30".parse::<RetentionPolicy>().unwrap(), RetentionPolicy::KeepDays { days: 30 });
+    assert_eq!("last:100".parse::<RetentionPolicy>().unwrap(), RetentionPolicy::KeepLast { entries: 100 });
+    assert!("last:many".parse::<RetentionPolicy>().is_err());
+    assert!("weeks:2".parse::<RetentionPolicy>().is_err());
+}
+
+#[test]
+fn test_retention_keep_last() {
+    // Create a new ledger that keeps the last two entries
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 2 });
+
+    let hashes: Vec<String> =
+        (0..5).map(|i| ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap().hash).collect();
+
+    // Prune and verify only the newest entries remain
+    assert_eq!(ledger.apply_retention(), 3);
+    let entries = ledger.get_entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].data["message"], "Test entry 3");
+    assert_eq!(entries[1].previous_hash, entries[0].hash);
+
+    // Retention metadata reflects the pruned history
+    let info = ledger.get_retention_info();
+    assert_eq!(info.pruned_entries, 3);
+    assert_eq!(info.oldest_entry_id.as_deref(), Some(entries[0].id.as_str()));
+
+    // Only retained entries are found by hash
+    assert!(ledger.get_entry_by_hash(&hashes[2]).is_none());
+    assert!(ledger.get_entries_after(&hashes[2]).is_none());
+    assert_eq!(ledger.get_entry_by_hash(&hashes[3]).unwrap().id, entries[0].id);
+    assert_eq!(ledger.get_entries_after(&hashes[3]).unwrap().len(), 1);
+
+    // New entries still link to the retained tip
+    let entry = ledger.add_entry(json!({ "message": "Test entry 5" })).unwrap();
+    assert_eq!(entry.previous_hash, ledger.get_entries()[1].hash);
+}
+
+#[test]
+fn test_retention_keep_days() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    ledger.set_retention_policy(RetentionPolicy::KeepDays { days: 1 });
+
+    ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    // Nothing is old enough to prune yet
+    assert_eq!(ledger.apply_retention(), 0);
+
+    // Two days later everything has expired, but the tip is always kept
+    assert_eq!(ledger.apply_retention_at(Utc::now() + Duration::days(2)), 1);
+    assert_eq!(ledger.get_entries().len(), 1);
+    assert_eq!(ledger.get_entries()[0].data["message"], "Test entry 2");
+
+    // More days than time goes back retains everything
+    ledger.add_entry(json!({ "message": "Test entry 3" })).unwrap();
+    ledger.set_retention_policy(RetentionPolicy::KeepDays { days: u32::MAX });
+    assert_eq!(ledger.apply_retention_at(Utc::now() + Duration::days(2)), 0);
+    assert_eq!(ledger.get_entries().len(), 2);
+}
+
+#[test]
+fn test_retention_keep_forever() {
+    let shared_ledger = SharedLedger::new("test-node-1".to_string());
+
+    shared_ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    shared_ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    assert_eq!(shared_ledger.apply_retention(), 0);
+    assert_eq!(shared_ledger.get_entries().len(), 2);
+    assert_eq!(shared_ledger.get_retention_info().policy, RetentionPolicy::KeepForever);
+}
+
+#[test]
+fn test_indexed_lookups() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let first = ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    // An entry from another node
+    let node2_key = SigningKey::generate(&mut OsRng);
+    ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+    let mut second = LedgerEntry::new(json!({ "message": "Test entry 2" }), first.hash.clone(), "test-node-2".to_string());
+    second.sign("test-node-2".to_string(), &node2_key);
+    assert!(ledger.add_pending_entry(second.clone()));
+    assert_eq!(ledger.process_pending_entries().len(), 1);
+
+    let third = ledger.add_entry(json!({ "message": "Test entry 3" })).unwrap();
+
+    // Entries are found by ID and grouped by creator in chain order
+    assert_eq!(ledger.get_entry_by_id(&second.id).unwrap().hash, second.hash);
+    let own: Vec<_> = ledger.find_by_creator("test-node-1").iter().map(|e| e.id.clone()).collect();
+    assert_eq!(own, vec![first.id.clone(), third.id.clone()]);
+    assert_eq!(ledger.find_by_creator("test-node-2")[0].id, second.id);
+    assert!(ledger.find_by_creator("test-node-3").is_empty());
+
+    // A chain entry isn't taken as pending again
+    assert!(!ledger.add_pending_entry(second.clone()));
+
+    // Pruned entries drop out of the index, and the ones held stay findable
+    ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 1 });
+    assert_eq!(ledger.apply_retention(), 2);
+    assert!(ledger.get_entry_by_id(&first.id).is_none());
+    assert!(ledger.find_by_creator("test-node-2").is_empty());
+    assert_eq!(ledger.get_entry_by_id(&third.id).unwrap().hash, third.hash);
+    assert_eq!(ledger.find_by_creator("test-node-1").len(), 1);
+    assert_eq!(ledger.get_entries_since(first.timestamp - Duration::minutes(5)).len(), 1);
+    assert_eq!(ledger.get_inclusion_proof(&third.id).unwrap().index, 0);
+
+    // Entries added after pruning are indexed at their place in the chain
+    let fourth = ledger.add_entry(json!({ "message": "Test entry 4" })).unwrap();
+    assert_eq!(ledger.get_entry_by_id(&fourth.id).unwrap().hash, fourth.hash);
+    assert_eq!(ledger.find_by_creator("test-node-1").len(), 2);
+}
+
+#[test]
+fn test_ledger_range_queries() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    for i in 0..5 {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    // Pages are clamped to the end of the chain
+    let page = ledger.get_entries_paginated(1, 2);
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].data["message"], "Test entry 1");
+    assert_eq!(ledger.get_entries_paginated(4, 10).len(), 1);
+    assert!(ledger.get_entries_paginated(10, 10).is_empty());
+
+    // Entries strictly after a timestamp
+    let since = ledger.get_entries()[2].timestamp;
+    let newer = ledger.get_entries_since(since);
+    assert_eq!(newer.len(), 2);
+    assert_eq!(newer[0].data["message"], "Test entry 3");
+
+    // Lookup by ID
+    let id = ledger.get_entries()[3].id.clone();
+    assert_eq!(ledger.get_entry_by_id(&id).unwrap().data["message"], "Test entry 3");
+    assert!(ledger.get_entry_by_id("missing").is_none());
+}
+
+#[test]
+fn test_shared_ledger_range_queries() {
+    let shared_ledger = SharedLedger::new("test-node-1".to_string());
+    let first = shared_ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let second = shared_ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    assert_eq!(shared_ledger.get_entries_paginated(0, 1)[0].id, first.id);
+    assert_eq!(shared_ledger.get_entries_since(first.timestamp)[0].id, second.id);
+    assert_eq!(shared_ledger.get_entry_by_id(&second.id).unwrap().hash, second.hash);
+}
+
+#[test]
+fn test_entries_are_signed_by_creator() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let entry = ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    // The creator's signature verifies against its public key
+    let public_key = hex::decode(ledger.public_key()).unwrap();
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&public_key.try_into().unwrap()).unwrap();
+    assert!(entry.verify_signature("test-node-1", &key));
+
+    // Tampering with the entry breaks the signature
+    let mut tampered = entry.clone();
+    tampered.hash = "0".repeat(64);
+    assert!(!tampered.verify_signature("test-node-1", &key));
+}
+
+#[test]
+fn test_pending_entries_require_valid_signature() {
+    let shared_ledger = SharedLedger::new("test-node-1".to_string());
+    let entry1 = shared_ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+
+    let node2_key = SigningKey::generate(&mut OsRng);
+    let node2_public_key = hex::encode(node2_key.verifying_key().to_bytes());
+
+    // Entries from a node whose key is unknown wait in pending
+    let mut entry2 = LedgerEntry::new(json!({ "message": "Test entry 2" }), entry1.hash.clone(), "test-node-2".to_string());
+    entry2.sign("test-node-2".to_string(), &node2_key);
+    shared_ledger.add_pending_entry(entry2.clone());
+    assert!(shared_ledger.process_pending_entries().is_empty());
+
+    // Once the key is known the entry is accepted
+    shared_ledger.add_node_key("test-node-2".to_string(), &node2_public_key).unwrap();
+    assert_eq!(shared_ledger.process_pending_entries().len(), 1);
+
+    // Unsigned entries and entries signed with the wrong key are rejected
+    let unsigned = LedgerEntry::new(json!({ "message": "Unsigned" }), entry2.hash.clone(), "test-node-2".to_string());
+    let mut forged = LedgerEntry::new(json!({ "message": "Forged" }), entry2.hash.clone(), "test-node-2".to_string());
+    forged.sign("test-node-2".to_string(), &SigningKey::generate(&mut OsRng));
+    shared_ledger.add_pending_entry(unsigned);
+    shared_ledger.add_pending_entry(forged);
+    assert!(shared_ledger.process_pending_entries().is_empty());
+    assert_eq!(shared_ledger.get_entries().len(), 2);
+
+    // A node's key can't be replaced by a different one
+    let other_key = hex::encode(SigningKey::generate(&mut OsRng).verifying_key().to_bytes());
+    assert!(shared_ledger.add_node_key("test-node-2".to_string(), &other_key).is_err());
+    assert!(shared_ledger.add_node_key("test-node-3".to_string(), "not hex").is_err());
+}
+
+fn signed_entry(message: &str, previous_hash: &str, node_id: &str, key: &SigningKey) -> LedgerEntry {
+    let mut entry = LedgerEntry::new(json!({ "message": message }), previous_hash.to_string(), node_id.to_string());
+    entry.sign(node_id.to_string(), key);
+    entry
+}
+
+#[test]
+fn test_pending_entries_sync_empty_chain() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let node2_key = SigningKey::generate(&mut OsRng);
+    ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+
+    // A peer's whole chain arrives out of order
+    let entry1 = signed_entry("Test entry 1", GENESIS_HASH, "test-node-2", &node2_key);
+    let entry2 = signed_entry("Test entry 2", &entry1.hash, "test-node-2", &node2_key);
+    let entry3 = signed_entry("Test entry 3", &entry2.hash, "test-node-2", &node2_key);
+    for entry in [&entry3, &entry1, &entry2] {
+        ledger.add_pending_entry(entry.clone());
+    }
+
+    let added = ledger.process_pending_entries();
+    assert_eq!(added.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&entry1.id, &entry2.id, &entry3.id]);
+    assert_eq!(ledger.get_entries().len(), 3);
+
+    // Entries we already have are ignored
+    ledger.add_pending_entry(entry2);
+    assert!(ledger.detect_forks().is_empty());
+}
+
+#[test]
+fn test_fork_resolution_prefers_longest_chain() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let node2_key = SigningKey::generate(&mut OsRng);
+    ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+
+    let shared = ledger.add_entry(json!({ "message": "Shared" })).unwrap();
+    let local = ledger.add_entry(json!({ "message": "Local" })).unwrap();
+
+    // A peer appended two entries after the shared one at the same time
+    let remote1 = signed_entry("Remote 1", &shared.hash, "test-node-2", &node2_key);
+    let remote2 = signed_entry("Remote 2", &remote1.hash, "test-node-2", &node2_key);
+    ledger.add_pending_entry(remote2.clone());
+    ledger.add_pending_entry(remote1.clone());
+
+    // Neither links to the tip, so they are detected as a fork
+    assert!(ledger.process_pending_entries().is_empty());
+    let forks = ledger.detect_forks();
+    assert_eq!(forks.len(), 1);
+    assert_eq!(forks[0].fork_point, shared.hash);
+    assert_eq!(forks[0].branch.len(), 2);
+
+    // The longer branch wins
+    let reorg = ledger.resolve_forks().unwrap();
+    assert_eq!(reorg.fork_point, shared.hash);
+    assert_eq!(reorg.rolled_back.len(), 1);
+    assert_eq!(reorg.rolled_back[0].id, local.id);
+    assert_eq!(reorg.applied.iter().map(|e| &e.id).collect::<Vec<_>>(), vec![&remote1.id, &remote2.id]);
+
+    let entries = ledger.get_entries();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].previous_hash, shared.hash);
+    assert_eq!(entries[2].id, remote2.id);
+
+    // The rolled-back entry is now the shorter fork, so nothing changes
+    assert_eq!(ledger.detect_forks().len(), 1);
+    assert!(ledger.resolve_forks().is_none());
+}
+
+#[test]
+fn test_deep_pending_branches() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let node2_key = SigningKey::generate(&mut OsRng);
+    ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+
+    let shared = ledger.add_entry(json!({ "message": "Shared" })).unwrap();
+    ledger.add_entry(json!({ "message": "Local" })).unwrap();
+
+    // A long branch and a short one fork off the shared entry, arriving newest first
+    let mut long = vec![signed_entry("Long 0", &shared.hash, "test-node-2", &node2_key)];
+    for i in 1..2000 {
+        let previous = long[i - 1].hash.clone();
+        long.push(signed_entry(&format!("Long {i}"), &previous, "test-node-2", &node2_key));
+    }
+    let short = signed_entry("Short", &shared.hash, "test-node-2", &node2_key);
+    for entry in long.iter().rev().chain([&short]) {
+        assert!(ledger.add_pending_entry(entry.clone()));
+    }
+
+    let forks = ledger.detect_forks();
+    assert_eq!(forks.len(), 1);
+    assert_eq!(forks[0].fork_point, shared.hash);
+    assert_eq!(forks[0].branch.iter().map(|e| &e.id).collect::<Vec<_>>(), long.iter().map(|e| &e.id).collect::<Vec<_>>());
+
+    let reorg = ledger.resolve_forks().unwrap();
+    assert_eq!(reorg.applied.len(), 2000);
+    assert_eq!(ledger.get_last_entry().unwrap().id, long[1999].id);
+    assert_eq!(ledger.merkle_root(), merkle::merkle_root(ledger.get_entries().iter().map(|e| e.hash.as_str())));
+}
+
+#[test]
+fn test_fork_resolution_tie_break() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let node2_key = SigningKey::generate(&mut OsRng);
+    ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+
+    let shared = ledger.add_entry(json!({ "message": "Shared" })).unwrap();
+    let local = ledger.add_entry(json!({ "message": "Local" })).unwrap();
+    let remote = signed_entry("Remote", &shared.hash, "test-node-2", &node2_key);
+    ledger.add_pending_entry(remote.clone());
+
+    // Equal-length branches settle on the lowest tip hash, whichever node we are
+    let reorg = ledger.resolve_forks();
+    assert_eq!(reorg.is_some(), remote.hash < local.hash);
+    let expected_tip = std::cmp::min(&local.hash, &remote.hash);
+    assert_eq!(&ledger.get_last_entry().unwrap().hash, expected_tip);
+    assert!(ledger.resolve_forks().is_none());
+}
+
+#[test]
+fn test_inclusion_proofs() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    assert_eq!(ledger.merkle_root(), EMPTY_ROOT);
+
+    // Cover trees with odd levels as well as full ones
+    for count in 1..=9 {
+        ledger.add_entry(json!({ "message": format!("Test entry {count}") })).unwrap();
+        let root = ledger.merkle_root();
+
+        for (index, entry) in ledger.get_entries().iter().enumerate() {
+            let proof = ledger.get_inclusion_proof(&entry.id).unwrap();
+            assert_eq!(proof.index, index);
+            assert_eq!(proof.entry_hash, entry.hash);
+            assert!(merkle::verify_proof(&proof, &root));
+
+            // The client verifies proofs the same way
+            let client_proof: gsio_client::MerkleProof = serde_json::from_value(serde_json::to_value(&proof).unwrap()).unwrap();
+            assert!(gsio_client::verify_proof(&client_proof, &root));
+        }
+    }
+
+    assert!(ledger.get_inclusion_proof("missing").is_none());
+
+    // The tree follows the chain when old entries are pruned
+    let third = ledger.get_entries()[2].hash.clone();
+    assert_eq!(ledger.prune_through(&third), 3);
+    let root = ledger.merkle_root();
+    assert_eq!(root, merkle::merkle_root(ledger.get_entries().iter().map(|e| e.hash.as_str())));
+    let entry = ledger.get_entries()[0].clone();
+    assert!(merkle::verify_proof(&ledger.get_inclusion_proof(&entry.id).unwrap(), &root));
+}
+
+#[test]
+fn test_inclusion_proof_rejects_tampering() {
+    let ledger = SharedLedger::new("test-node-1".to_string());
+    for i in 0..5 {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    let entries = ledger.get_entries();
+    let root = ledger.merkle_root();
+    let proof = ledger.get_inclusion_proof(&entries[2].id).unwrap();
+    assert!(merkle::verify_proof(&proof, &root));
+
+    // A different entry hash doesn't verify
+    let mut forged = proof.clone();
+    forged.entry_hash = entries[3].hash.clone();
+    assert!(!merkle::verify_proof(&forged, &root));
+
+    // Nor does a modified path
+    let mut forged = proof.clone();
+    forged.path[0].hash = "0".repeat(64);
+    assert!(!merkle::verify_proof(&forged, &root));
+    let mut forged = proof.clone();
+    forged.path.pop();
+    assert!(!merkle::verify_proof(&forged, &root));
+
+    // Roots change as the chain grows, so old proofs only verify against the old root
+    ledger.add_entry(json!({ "message": "Test entry 5" })).unwrap();
+    assert!(!merkle::verify_proof(&proof, &ledger.merkle_root()));
+}
+
+#[test]
+fn test_missing_parents_and_expiry() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    let first = ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert_eq!(ledger.get_entry_by_hash(&first.hash).unwrap().id, first.id);
+
+    // A pending chain whose oldest entry links to one we don't have
+    let second = LedgerEntry::new(json!({ "message": "Test entry 2" }), "f".repeat(64), "test-node-2".to_string());
+    let third = LedgerEntry::new(json!({ "message": "Test entry 3" }), second.hash.clone(), "test-node-2".to_string());
+    let linked = LedgerEntry::new(json!({ "message": "Test entry 4" }), first.hash.clone(), "test-node-2".to_string());
+    for entry in [second.clone(), third, linked] {
+        assert!(ledger.add_pending_entry(entry));
+    }
+    assert_eq!(ledger.missing_parents(), vec!["f".repeat(64)]);
+
+    // Entries only expire once they've been pending for longer than the limit
+    assert!(ledger.expire_pending_entries(std::time::Duration::from_secs(600)).is_empty());
+    assert_eq!(ledger.expire_pending_entries(std::time::Duration::ZERO).len(), 3);
+    assert_eq!(ledger.stats().pending_entries, 0);
+    assert!(ledger.missing_parents().is_empty());
+}
+
+/// An entry signed by `node_id` whose creator's clock is `skew` off, carrying Lamport clock `clock`
+fn skewed_entry(message: &str, previous_hash: &str, node_id: &str, key: &SigningKey, clock: u64, skew: Duration) -> LedgerEntry {
+    let mut entry = LedgerEntry::new(json!({ "message": message }), previous_hash.to_string(), node_id.to_string());
+    entry.timestamp += skew;
+    let mut entry = entry.with_clock(clock);
+    entry.sign(node_id.to_string(), key);
+    entry
+}
+
+#[test]
+fn test_pending_entries_ordered_by_lamport_clock() {
+    let node2_key = SigningKey::generate(&mut OsRng);
+    let node3_key = SigningKey::generate(&mut OsRng);
+
+    // Whichever entry has the higher hash, the one first by the clock wins,
+    // though its creator's clock runs an hour fast
+    for (first_clock, second_clock) in [(1, 5), (5, 1)] {
+        let mut ledger = Ledger::new("test-node-1".to_string());
+        ledger.add_node_key("test-node-2".to_string(), &hex::encode(node2_key.verifying_key().to_bytes())).unwrap();
+        ledger.add_node_key("test-node-3".to_string(), &hex::encode(node3_key.verifying_key().to_bytes())).unwrap();
+
+        let ahead = skewed_entry("Ahead", GENESIS_HASH, "test-node-2", &node2_key, first_clock, Duration::hours(1));
+        let behind = skewed_entry("Behind", GENESIS_HASH, "test-node-3", &node3_key, second_clock, Duration::hours(-1));
+        ledger.add_pending_entry(behind.clone());
+        ledger.add_pending_entry(ahead.clone());
+
+        let added = ledger.process_pending_entries();
+        let expected = if first_clock < second_clock { &ahead } else { &behind };
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].id, expected.id);
+
+        // This node's next entry comes after every entry it has seen, whatever its timestamp
+        let own = ledger.add_entry(json!({ "message": "Own entry" })).unwrap();
+        assert_eq!(own.clock, 6);
+        assert!(own.timestamp < ahead.timestamp);
+    }
+}
+
+#[test]
+fn test_crdt_ledgers_merge_concurrent_entries() {
+    let mut a = Ledger::new("test-node-1".to_string());
+    let mut b = Ledger::new("test-node-2".to_string());
+    a.set_mode(LedgerMode::Crdt).unwrap();
+    b.set_mode(LedgerMode::Crdt).unwrap();
+    a.add_node_key("test-node-2".to_string(), &b.public_key()).unwrap();
+    b.add_node_key("test-node-1".to_string(), &a.public_key()).unwrap();
+
+    // Both nodes write at once; the entries don't link to each other
+    let a1 = a.add_entry(json!({ "message": "From A 1" })).unwrap();
+    let a2 = a.add_entry(json!({ "message": "From A 2" })).unwrap();
+    let b1 = b.add_entry(json!({ "message": "From B 1" })).unwrap();
+    assert_eq!(a2.previous_hash, GENESIS_HASH);
+    assert!(a.set_mode(LedgerMode::Chain).is_err());
+
+    // Merging in either direction, in any order, leaves the same set and no forks
+    for entry in b.missing_entries(&a.chain_tip()) {
+        a.add_pending_entry(entry);
+    }
+    for entry in a.missing_entries(&b.chain_tip()).into_iter().rev() {
+        b.add_pending_entry(entry);
+    }
+    assert_eq!(a.process_pending_entries().len(), 1);
+    assert_eq!(b.process_pending_entries().len(), 2);
+    let ids = |ledger: &Ledger| {
+        let mut ids: Vec<String> = ledger.get_entries().iter().map(|e| e.id.clone()).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(&a), ids(&b));
+    assert!(ids(&a).contains(&a1.id) && ids(&a).contains(&b1.id));
+    assert!(a.detect_forks().is_empty());
+    assert!(a.resolve_forks().is_none());
+    assert!(a.sync_locator().is_empty());
+
+    // Merging again changes nothing
+    for entry in b.get_entries().clone() {
+        assert!(!a.add_pending_entry(entry));
+    }
+    assert!(a.process_pending_entries().is_empty());
+}
+
+#[test]
+fn test_limits() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    ledger.set_limits(Limits { max_entry_bytes: 512, max_pending_entries: 2, ..Limits::default() });
+
+    // Too much data is refused from clients and peers alike
+    let error = ledger.add_entry(json!({ "message": "x".repeat(600) })).unwrap_err();
+    assert_eq!(error.code, gsio_node::validation::ErrorCode::PayloadTooLarge);
+    let large = LedgerEntry::new(json!({ "message": "x".repeat(600) }), GENESIS_HASH.to_string(), "test-node-2".to_string());
+    assert_eq!(ledger.check_limits(&large).unwrap_err().code, gsio_node::validation::ErrorCode::PayloadTooLarge);
+    assert!(!ledger.add_pending_entry(large));
+
+    // Once the pending entries are full, only ones already held get through
+    let entries: Vec<LedgerEntry> = (0..3)
+        .map(|i| LedgerEntry::new(json!({ "message": format!("Test entry {i}") }), "f".repeat(64), "test-node-2".to_string()))
+        .collect();
+    assert!(ledger.add_pending_entry(entries[0].clone()));
+    assert!(ledger.add_pending_entry(entries[1].clone()));
+    assert_eq!(ledger.check_limits(&entries[2]).unwrap_err().code, gsio_node::validation::ErrorCode::PendingFull);
+    assert!(!ledger.add_pending_entry(entries[2].clone()));
+    assert!(ledger.check_limits(&entries[0]).is_ok());
+    assert_eq!(ledger.stats().pending_entries, 2);
 }
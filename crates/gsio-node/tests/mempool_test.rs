@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use gsio_client::GsioClient;
+use gsio_node::error::GsioNodeError;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::mempool::{FeeRate, Mempool, MempoolConfig};
+use gsio_node::p2p::{NodeMode, P2PManager};
+use gsio_node::validation::{ErrorCode, RequiredFields};
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+#[tokio::test]
+async fn test_sequencer_adds_concurrent_submissions() {
+    let p2p = Arc::new(new_node("test-node-1"));
+    p2p.start_sequencer();
+
+    let submissions = (0..200).map(|i| {
+        let p2p = p2p.clone();
+        tokio::spawn(async move { p2p.add_entry_data(json!({ "message": format!("Test entry {i}") })).await })
+    });
+    let mut ids = Vec::new();
+    for submission in submissions {
+        ids.push(submission.await.unwrap().unwrap().id);
+    }
+
+    // Every entry joined the chain once, linked in the order it was sequenced
+    let entries = p2p.ledger.get_entries();
+    assert_eq!(entries.len(), 200);
+    for pair in entries.windows(2) {
+        assert_eq!(pair[1].previous_hash, pair[0].hash);
+    }
+    ids.sort();
+    let mut chained: Vec<String> = entries.into_iter().map(|e| e.id).collect();
+    chained.sort();
+    assert_eq!(ids, chained);
+    assert_eq!(p2p.mempool_len(), 0);
+}
+
+#[tokio::test]
+async fn test_mempool_refuses_before_queueing() {
+    let p2p = new_node("test-node-1");
+    p2p.ledger.set_validation_policy(Arc::new(RequiredFields(vec!["message".to_string()])));
+    p2p.start_sequencer();
+
+    let error = p2p.add_entry_data(json!({ "note": "no message" })).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::MissingField);
+    assert!(p2p.ledger.get_entries().is_empty());
+
+    let follower = new_node("test-node-2").with_mode(NodeMode::Follower { writable_node: None });
+    follower.start_sequencer();
+    let error = follower.add_entry_data(json!({ "message": "Test entry" })).await.unwrap_err();
+    assert_eq!(error, GsioNodeError::ReadOnly { writable_node: None });
+}
+
+/// Entry data recording a transaction paying `fee`, padded with a memo of `memo` bytes
+fn transaction(fee: u64, memo: usize) -> JsonValue {
+    json!({
+        "type": "transaction",
+        "transaction": {
+            "id": "tx-1",
+            "transaction_type": "Transfer",
+            "amount": 1,
+            "fee": fee,
+            "sender": "sender",
+            "recipient": "recipient",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "status": "Pending",
+            "signature": null,
+            "data": { "memo": "x".repeat(memo) },
+        },
+    })
+}
+
+#[tokio::test]
+async fn test_mempool_orders_by_fee_per_byte() {
+    let mempool = Mempool::new(MempoolConfig::default(), 16);
+    let submissions = [
+        json!({ "message": "Test entry 1" }),
+        transaction(10, 1_000),
+        transaction(10, 0),
+        json!({ "message": "Test entry 2" }),
+        transaction(50, 1_000),
+    ];
+    for data in submissions {
+        let rate = FeeRate::of(&data);
+        let _ = mempool.push(data, rate).await;
+    }
+    assert_eq!(mempool.len(), 5);
+
+    // Highest fee per byte first, then in the order they came
+    let batch = mempool.next_batch(3).await;
+    let fees: Vec<_> = batch.iter().map(|s| s.data["transaction"]["fee"].as_u64()).collect();
+    assert_eq!(fees, vec![Some(10), Some(50), Some(10)]);
+    assert_eq!(batch[0].data, transaction(10, 0));
+    let batch = mempool.next_batch(3).await;
+    assert_eq!(batch[0].data["message"], "Test entry 1");
+    assert_eq!(batch[1].data["message"], "Test entry 2");
+    assert!(mempool.is_empty());
+}
+
+#[tokio::test]
+async fn test_fee_floor() {
+    let mempool = Mempool::new(MempoolConfig { min_fee: 2 }, 2);
+    assert!(mempool.check_fee(&json!({ "message": "Test entry" })).is_ok());
+    assert_eq!(mempool.check_fee(&transaction(1, 0)).unwrap_err().code, ErrorCode::FeeTooLow);
+    assert!(mempool.check_fee(&transaction(2, 0)).is_ok());
+
+    // The floor rises to the lowest rate waiting once the mempool is full
+    let floor = mempool.fee_floor();
+    assert_eq!((floor.min_fee, floor.fee_per_byte, floor.pending, floor.capacity), (2, 0.0, 0, 2));
+    for data in [transaction(100, 0), transaction(2, 0)] {
+        let rate = FeeRate::of(&data);
+        let _ = mempool.push(data, rate).await;
+    }
+    let floor = mempool.fee_floor();
+    assert_eq!(floor.fee_per_byte, FeeRate::of(&transaction(2, 0)).per_byte());
+    assert_eq!(floor.pending, 2);
+}
+
+#[tokio::test]
+async fn test_node_refuses_transactions_below_min_fee() {
+    let p2p = Arc::new(new_node("test-node-1").with_mempool_config(MempoolConfig { min_fee: 5 }));
+    p2p.start_sequencer();
+
+    let error = p2p.add_entry_data(transaction(4, 0)).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::FeeTooLow);
+    p2p.add_entry_data(transaction(5, 0)).await.unwrap();
+    p2p.add_entry_data(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = gsio_node::api::router(p2p);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    let floor = GsioClient::new(&url).unwrap().get_fee_floor().await.unwrap();
+    assert_eq!((floor.min_fee, floor.pending), (5, 0));
+}
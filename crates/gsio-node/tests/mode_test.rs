@@ -0,0 +1,39 @@
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{NodeMode, P2PManager};
+use serde_json::json;
+
+#[test]
+fn test_node_mode_parsing() {
+    assert_eq!("writer".parse::<NodeMode>().unwrap(), NodeMode::Writer);
+    assert_eq!(
+        "Follower".parse::<NodeMode>().unwrap(),
+        NodeMode::Follower { writable_node: None }
+    );
+    assert!("leader".parse::<NodeMode>().is_err());
+}
+
+#[test]
+fn test_writer_accepts_local_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = P2PManager::new(node_id.clone(), SharedLedger::new(node_id));
+
+    let entry = p2p.add_local_entry(json!({ "message": "Test entry" })).unwrap();
+
+    assert_eq!(p2p.ledger.get_entries().len(), 1);
+    assert_eq!(p2p.ledger.get_last_entry().unwrap().id, entry.id);
+}
+
+#[test]
+fn test_follower_rejects_local_entries() {
+    let node_id = "test-node-1".to_string();
+    let mode = NodeMode::Follower {
+        writable_node: Some("http://writer:3000".to_string()),
+    };
+    let p2p = P2PManager::new(node_id.clone(), SharedLedger::new(node_id)).with_mode(mode);
+
+    // Writes are rejected with a pointer to the writable node
+    let err = p2p.add_local_entry(json!({ "message": "Test entry" })).unwrap_err();
+    assert!(err.to_string().contains("http://writer:3000"));
+    assert!(p2p.ledger.get_entries().is_empty());
+}
+
@@ -0,0 +1,9 @@
+use gsio_node::config::NodeConfig;
+use gsio_node::node::NodeBuilder;
+
+#[tokio::test]
+async fn test_node_needs_relay() {
+    let started = NodeBuilder::new(NodeConfig::default()).without_grpc().start().await;
+    let error = started.err().expect("node started without a relay address");
+    assert!(error.to_string().contains("relay address"));
+}
@@ -0,0 +1,236 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use gsio_client::{Filter, GsioClient, Query};
+use gsio_node::error::GsioNodeError;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::offload::{blob_hash, BlobRef, BlobStore, MemoryBlobStore, OffloadConfig, Offloader};
+use gsio_node::ledger::LedgerEntry;
+use gsio_node::p2p::{EntryRejection, MessageType, P2PManager, P2PMessage};
+use gsio_node::validation::{ErrorCode, RequiredFields};
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc;
+
+mod common;
+
+use common::start_server;
+
+const THRESHOLD: usize = 64;
+
+fn offloading_node(node_id: &str, store: Arc<MemoryBlobStore>) -> Arc<P2PManager> {
+    let ledger = SharedLedger::new(node_id.to_string());
+    let offloader = Offloader::new(store, Some(THRESHOLD));
+    Arc::new(P2PManager::new(node_id.to_string(), ledger).with_offloader(Arc::new(offloader)))
+}
+
+fn large_data() -> JsonValue {
+    json!({ "message": "x".repeat(THRESHOLD * 4) })
+}
+
+/// A memory store that counts the blobs read from it
+#[derive(Default)]
+struct CountingStore {
+    store: MemoryBlobStore,
+    reads: AtomicUsize,
+}
+
+impl BlobStore for CountingStore {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<String, String>> {
+        self.store.put(bytes)
+    }
+
+    fn get(&self, hash: String, provider: Option<String>) -> BoxFuture<'_, Result<Option<Vec<u8>>, String>> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.store.get(hash, provider)
+    }
+}
+
+#[test]
+fn test_blob_references() {
+    let hash = blob_hash(b"{}");
+    let reference = BlobRef { hash: hash.clone(), size: 2, provider: None };
+    assert_eq!(reference.to_data(), json!({ "$blob": hash, "size": 2 }));
+    assert_eq!(BlobRef::from_data(&reference.to_data()), Some(reference));
+
+    let provided = json!({ "$blob": hash, "size": 2, "provider": "node" });
+    assert_eq!(BlobRef::from_data(&provided).unwrap().provider.as_deref(), Some("node"));
+
+    // Data that only looks a bit like a reference is left alone
+    assert!(BlobRef::from_data(&json!({ "$blob": hash })).is_none());
+    assert!(BlobRef::from_data(&json!({ "$blob": hash, "size": 2, "message": "hello" })).is_none());
+    assert!(BlobRef::from_data(&json!({ "$blob": "not a hash", "size": 2 })).is_none());
+    assert!(BlobRef::from_data(&json!({ "$blob": hash.to_uppercase(), "size": 2 })).is_none());
+
+    let config: OffloadConfig = toml::from_str("threshold_bytes = 1024").unwrap();
+    assert!(config.is_enabled());
+    assert!(!OffloadConfig::default().is_enabled());
+}
+
+#[tokio::test]
+async fn test_large_entries_are_offloaded() {
+    let store = Arc::new(MemoryBlobStore::new());
+    let p2p = offloading_node("test-node-1", store.clone());
+
+    let small = p2p.add_entry_data(json!({ "message": "hello" })).await.unwrap();
+    assert!(small.blob.is_none());
+    assert!(store.is_empty());
+
+    let entry = p2p.add_entry_data(large_data()).await.unwrap();
+    assert_eq!(entry.data, large_data());
+    assert_eq!(store.len(), 1);
+
+    // The chain only holds the reference, and the hash covers it
+    let stored = p2p.ledger.get_entry_by_id(&entry.id).unwrap();
+    let reference = BlobRef::from_data(&stored.data).unwrap();
+    assert_eq!(reference.hash, blob_hash(large_data().to_string().as_bytes()));
+    assert_eq!(reference.size, large_data().to_string().len() as u64);
+    assert_eq!(Some(&stored.data), entry.blob.as_ref());
+    assert_eq!(stored.calculate_hash(), stored.hash);
+    assert_eq!(entry.calculate_hash(), entry.hash);
+
+    let rehydrated = p2p.rehydrate_entry(stored.clone()).await;
+    assert_eq!(rehydrated.data, large_data());
+    assert_eq!(rehydrated.hash, stored.hash);
+
+    // A node without the blob hands out the reference
+    let other = offloading_node("test-node-2", Arc::new(MemoryBlobStore::new()));
+    assert_eq!(other.rehydrate_entry(stored.clone()).await.data, stored.data);
+
+    // Clients can't slip references past validation
+    let error = p2p.add_entry_data(stored.data.clone()).await.unwrap_err();
+    assert!(matches!(&error, GsioNodeError::Invalid(e) if e.code == ErrorCode::Rejected));
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+}
+
+#[tokio::test]
+async fn test_offloaded_entries_over_api() {
+    let p2p = offloading_node("test-node-1", Arc::new(MemoryBlobStore::new()));
+    let url = start_server(p2p.clone()).await;
+    let client = GsioClient::new(&url).unwrap();
+
+    let entry = client.add_ledger_entry(large_data()).await.unwrap();
+    assert_eq!(entry.data, large_data());
+    assert!(entry.is_valid());
+
+    let entries = client.get_ledger().await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].data, large_data());
+    assert!(entries[0].is_valid());
+    let fetched = client.get_entry_by_id(&entry.id).await.unwrap().unwrap();
+    assert_eq!(fetched.blob, entry.blob);
+
+    // The blob is served as the exact bytes its hash covers
+    let hash = BlobRef::from_data(entry.blob.as_ref().unwrap()).unwrap().hash;
+    assert_eq!(client.get_blob(&hash).await.unwrap(), Some(large_data()));
+    let http = reqwest::Client::new();
+    let bytes = http.get(format!("{url}/api/blobs/{hash}")).send().await.unwrap().bytes().await.unwrap();
+    assert_eq!(blob_hash(&bytes), hash);
+
+    // Queries filter on the offloaded data, not the reference
+    client.add_ledger_entry(json!({ "message": "small" })).await.unwrap();
+    let page = client.query_entries(Query::new().filter(Filter::eq("$.message", large_data()["message"].clone()))).await.unwrap();
+    assert_eq!(page.entries.len(), 1);
+    assert_eq!(page.entries[0].id, entry.id);
+    assert!(client.query_entries(Query::new().filter(Filter::exists("$.$blob"))).await.unwrap().entries.is_empty());
+
+    assert_eq!(client.get_blob(&blob_hash(b"missing")).await.unwrap(), None);
+    let response = http.get(format!("{url}/api/blobs/not-a-hash")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_queries_only_fetch_the_blobs_they_need() {
+    let store = Arc::new(CountingStore::default());
+    let offloader = Offloader::new(store.clone(), Some(THRESHOLD));
+    let p2p = Arc::new(
+        P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string()))
+            .with_offloader(Arc::new(offloader)),
+    );
+    for i in 0..40 {
+        p2p.add_entry_data(json!({ "index": i, "message": "x".repeat(THRESHOLD * 4) })).await.unwrap();
+    }
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+    store.reads.store(0, Ordering::SeqCst);
+
+    // Without filters only the entries on the page are fetched
+    let page = client.query_entries(Query::new().offset(10).limit(5)).await.unwrap();
+    let indexes: Vec<u64> = page.entries.iter().map(|e| e.data["index"].as_u64().unwrap()).collect();
+    assert_eq!(indexes, (10..15).collect::<Vec<_>>());
+    assert_eq!(page.next_offset, Some(15));
+    assert_eq!(store.reads.swap(0, Ordering::SeqCst), 5);
+
+    // Filters need the data, but only up to the first match past the page
+    let page = client.query_entries(Query::new().filter(Filter::gte("$.index", 5)).limit(5)).await.unwrap();
+    assert_eq!(page.entries.len(), 5);
+    assert_eq!(page.entries[0].data["index"], 5);
+    assert_eq!(store.reads.swap(0, Ordering::SeqCst), 11);
+}
+
+#[tokio::test]
+async fn test_unresolved_references_reach_the_client() {
+    // A node that doesn't offload still takes references from peers
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let reference = BlobRef { hash: blob_hash(b"elsewhere"), size: 9, provider: None };
+    p2p.add_local_entry(reference.to_data()).unwrap();
+    let url = start_server(p2p).await;
+
+    let entries = GsioClient::new(&url).unwrap().get_ledger().await.unwrap();
+    assert_eq!(entries[0].data, reference.to_data());
+    assert_eq!(entries[0].pending_blob(), Some(reference.hash.as_str()));
+    assert!(entries[0].is_valid());
+}
+
+#[tokio::test]
+async fn test_offloaded_peer_entries_are_validated() {
+    // The receiver reaches the sender's blobs, as if it downloaded them from the provider
+    let store = Arc::new(MemoryBlobStore::new());
+    let sender = offloading_node("test-node-1", store.clone());
+    let accepted = sender.add_entry_data(large_data()).await.unwrap();
+    let refused = sender.add_entry_data(json!({ "note": "x".repeat(THRESHOLD * 4) })).await.unwrap();
+    let missing = BlobRef { hash: blob_hash(b"missing"), size: 7, provider: None };
+    let unfetchable = sender.add_local_entry(missing.to_data()).unwrap();
+
+    let receiver_ledger = SharedLedger::new("test-node-2".to_string());
+    receiver_ledger.add_node_key("test-node-1".to_string(), &sender.ledger.public_key()).unwrap();
+    receiver_ledger.set_validation_policy(Arc::new(RequiredFields(vec!["message".to_string()])));
+    let (outbox, mut sent) = mpsc::unbounded_channel();
+    let receiver = P2PManager::new("test-node-2".to_string(), receiver_ledger)
+        .with_offloader(Arc::new(Offloader::new(store, Some(THRESHOLD))))
+        .with_outbox(outbox);
+
+    // Data a peer sends along with its reference is checked in its place
+    let sync = P2PMessage::new(
+        MessageType::LedgerSyncResponse,
+        "test-node-1".to_string(),
+        "test-node-2".to_string(),
+        json!({ "from": 1, "height": 2, "entries": [accepted, refused] }),
+    );
+    let reply = receiver.handle_message(sync).unwrap();
+    let rejections: Vec<EntryRejection> = serde_json::from_value(reply.payload["rejections"].clone()).unwrap();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].entry_id, refused.id);
+    assert_eq!(rejections[0].error.code, ErrorCode::MissingField);
+    assert_eq!(receiver.ledger.get_entries().len(), 1);
+
+    // A bare reference is checked once its blob is fetched, and refused if it can't be
+    let announce = |entry: LedgerEntry| {
+        let data = serde_json::to_value(entry).unwrap();
+        P2PMessage::new(MessageType::EntryAnnounce, "test-node-1".to_string(), "".to_string(), data)
+    };
+    let wrapped = sender.ledger.get_entry_by_id(&refused.id).unwrap();
+    assert!(BlobRef::from_data(&wrapped.data).is_some());
+    assert!(receiver.handle_message(announce(wrapped)).is_none());
+    assert!(receiver.handle_message(announce(unfetchable.clone())).is_none());
+    let mut rejected = Vec::new();
+    while rejected.len() < 2 {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), sent.recv()).await.unwrap().unwrap();
+        if matches!(message.message_type, MessageType::EntryRejected) {
+            let rejections: Vec<EntryRejection> = serde_json::from_value(message.payload["rejections"].clone()).unwrap();
+            rejected.extend(rejections.into_iter().map(|rejection| (rejection.entry_id, rejection.error.code)));
+        }
+    }
+    assert!(rejected.contains(&(refused.id.clone(), ErrorCode::MissingField)));
+    assert!(rejected.contains(&(unfetchable.id.clone(), ErrorCode::Rejected)));
+    assert_eq!(receiver.ledger.get_entries().len(), 1);
+}
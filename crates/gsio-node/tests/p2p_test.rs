@@ -1,9 +1,10 @@
-use gsio_node::p2p::{P2PMessage, MessageType};
+use ed25519_dalek::SigningKey;
+use gsio_node::p2p::{sign_announcement, P2PManager, P2PMessage, MessageType};
+use rand::rngs::OsRng;
 use gsio_node::ledger::{LedgerEntry, SharedLedger};
 use serde_json::{json, Value as JsonValue};
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
-use uuid::Uuid;
 use tracing::info;
 
 // Mock SocketRef for testing
@@ -389,4 +390,31 @@ fn test_request_ledger_sync() {
     assert_eq!(sent_message["sender_id"], node_id);
     assert_eq!(sent_message["recipient_id"], "test-node-2");
     assert_eq!(sent_message["message_type"], "LedgerSyncRequest");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_node_announce_keys() {
+    let p2p_manager = P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string()));
+    let key = SigningKey::generate(&mut OsRng);
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    let announce = |sender_id: &str, payload: JsonValue| {
+        P2PMessage::new(MessageType::NodeAnnounce, sender_id.to_string(), "test-node-1".to_string(), payload)
+    };
+
+    // An unsigned announce, or one relayed by another node, doesn't name a key
+    p2p_manager.handle_message(announce("test-node-2", json!({ "node_id": "test-node-2", "public_key": public_key })));
+    let signature = sign_announcement(&key, "test-node-2", None);
+    let relayed = json!({ "node_id": "test-node-2", "public_key": public_key, "signature": signature });
+    p2p_manager.handle_message(announce("test-node-3", relayed.clone()));
+    let forged = sign_announcement(&SigningKey::generate(&mut OsRng), "test-node-2", None);
+    p2p_manager.handle_message(announce(
+        "test-node-2",
+        json!({ "node_id": "test-node-2", "public_key": public_key, "signature": forged }),
+    ));
+    assert_eq!(p2p_manager.ledger.get_node_key("test-node-2"), None);
+    assert!(p2p_manager.ledger.get_known_nodes().contains("test-node-2"));
+
+    // The node itself, signing with the key, does
+    p2p_manager.handle_message(announce("test-node-2", relayed));
+    assert_eq!(p2p_manager.ledger.get_node_key("test-node-2"), Some(public_key));
+}
@@ -0,0 +1,391 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use futures::FutureExt;
+use gsio_node::envelope::EphemeralKey;
+use gsio_node::identity;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{
+    handshake_transcript, sign_challenge, Backoff, MessageType, P2PManager, P2PMessage, PeerInfo, Transport,
+};
+use rand::rngs::OsRng;
+use rust_socketio::{
+    asynchronous::{Client as PeerClient, ClientBuilder},
+    Payload,
+};
+use serde_json::json;
+
+mod common;
+
+use common::start_server;
+
+fn new_node(node_id: &str) -> Arc<P2PManager> {
+    Arc::new(P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string())))
+}
+
+/// Connect to a node's `/p2p` namespace as `node_id`, answering its challenge with `key`
+async fn connect_peer(url: &str, node_id: &str, key: SigningKey) -> PeerClient {
+    let peer_id = node_id.to_string();
+    let auth = json!({
+        "node_id": node_id,
+        "public_key": hex::encode(key.verifying_key().to_bytes()),
+        "challenge": "test-challenge",
+        "ephemeral_key": EphemeralKey::generate().public_key(),
+    });
+    ClientBuilder::new(url)
+        .namespace("/p2p")
+        .auth(auth.clone())
+        .on("p2p_message", move |payload: Payload, client: PeerClient| {
+            let (key, peer_id, auth) = (key.clone(), peer_id.clone(), auth.clone());
+            async move {
+                let Payload::Text(values) = payload else { return };
+                let Ok(message) = serde_json::from_value::<P2PMessage>(values[0].clone()) else { return };
+                if matches!(message.message_type, MessageType::AuthChallenge) {
+                    let transcript = handshake_transcript(&peer_id, &auth, &message.sender_id, &message.payload);
+                    let signature = sign_challenge(&key, &transcript, &peer_id, &message.sender_id);
+                    let response = P2PMessage::new(
+                        MessageType::AuthResponse,
+                        peer_id.clone(),
+                        message.sender_id.clone(),
+                        json!({ "signature": signature }),
+                    );
+                    client.emit("p2p_message", serde_json::to_value(response).unwrap()).await.unwrap();
+                }
+            }
+            .boxed()
+        })
+        .connect()
+        .await
+        .unwrap()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_backoff() {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+
+    // Delays double up to the maximum
+    assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+    assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+    assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+    assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+
+    // A successful connection starts over
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_peer_removed_on_disconnect() {
+    let node = new_node("test-node-1");
+    let url = start_server(node.clone()).await;
+
+    let client = connect_peer(&url, "test-node-2", SigningKey::generate(&mut OsRng)).await;
+
+    wait_for(Duration::from_secs(5), || node.peer_health().contains_key("test-node-2")).await;
+    assert!(node.clone_connected_nodes().lock().unwrap().contains_key("test-node-2"));
+
+    client.disconnect().await.unwrap();
+    wait_for(Duration::from_secs(5), || !node.clone_connected_nodes().lock().unwrap().contains_key("test-node-2")).await;
+    assert!(node.peer_health().is_empty());
+}
+
+#[tokio::test]
+async fn test_outbound_peer_reconnects() {
+    let server = new_node("test-node-1");
+    let url = start_server(server.clone()).await;
+
+    // The server heartbeats so the outbound side can tell the connection is alive
+    let heartbeat_server = server.clone();
+    tokio::spawn(async move {
+        loop {
+            heartbeat_server.send_heartbeat();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+
+    let client = new_node("test-node-2");
+    let connection = tokio::spawn((*client).clone().maintain_peer_connection(
+        url.clone(),
+        Duration::from_millis(100),
+        Duration::from_millis(500),
+    ));
+
+    // The peers register each other and exchange keys
+    wait_for(Duration::from_secs(5), || server.peer_health().contains_key("test-node-2")).await;
+    assert_eq!(client.outbound_peers(), vec![url]);
+    wait_for(Duration::from_secs(5), || client.ledger.get_node_key("test-node-1").is_some()).await;
+    assert_eq!(server.ledger.get_node_key("test-node-2"), Some(client.ledger.public_key()));
+
+    // Entries flow over the outbound connection
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    // Heartbeats keep the peer healthy
+    assert!(server.check_peer_health(Duration::from_secs(5)).is_empty());
+
+    // Dropping the peer makes it go quiet, so the client reconnects after backing off
+    let first_connected = server.peer_health()["test-node-2"].connected_at;
+    assert_eq!(server.check_peer_health(Duration::ZERO), vec!["test-node-2".to_string()]);
+    wait_for(Duration::from_secs(10), || {
+        server.peer_health().get("test-node-2").is_some_and(|h| h.connected_at > first_connected)
+    })
+    .await;
+
+    connection.abort();
+}
+
+#[tokio::test]
+async fn test_dialed_peer_syncs_ledger() {
+    let server = new_node("test-node-1");
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    server.ledger.add_known_node("test-node-3".to_string());
+    let url = start_server(server.clone()).await;
+
+    let client = new_node("test-node-2");
+    assert!(client.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5)));
+    // Dialing the same URL again is a no-op
+    assert!(!client.dial_peer(url.clone(), Duration::from_millis(100), Duration::from_secs(5)));
+    assert_eq!(client.dialed_peers(), vec![url]);
+
+    // The handshake pulls the existing ledger and node list
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    wait_for(Duration::from_secs(5), || client.ledger.get_known_nodes().iter().any(|n| n == "test-node-3")).await;
+}
+
+#[tokio::test]
+async fn test_peer_info() {
+    let server = new_node("test-node-1");
+    server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let server_url = start_server(server.clone()).await;
+    let client = new_node("test-node-2");
+    let client_url = start_server(client.clone()).await;
+    assert!(client.dial_peer(server_url, Duration::from_millis(100), Duration::from_secs(5)));
+
+    // The sync the handshake starts is timed and records how long the peer's chain is
+    wait_for(Duration::from_secs(5), || {
+        client.peer_info("test-node-1").is_some_and(|info| info.sync_height == Some(1) && info.rtt_ms.is_some())
+    })
+    .await;
+    let info: PeerInfo = reqwest::get(format!("{client_url}/api/peers/test-node-1")).await.unwrap().json().await.unwrap();
+    assert_eq!(info.transport, Transport::Socketio);
+    assert!(info.bytes_in > 0 && info.bytes_out > 0);
+    assert!(info.last_seen_secs.is_some());
+
+    // The dialed node counts the same traffic the other way round
+    let info = server.peer_info("test-node-2").unwrap();
+    assert_eq!(info.transport, Transport::Socketio);
+    assert!(info.bytes_in > 0 && info.bytes_out > 0);
+
+    let response = reqwest::get(format!("{client_url}/api/peers/test-node-3")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_discover_peers() {
+    let first = new_node("test-node-1");
+    let first_url = start_server(first.clone()).await;
+
+    // The bootstrap node is connected to the first node
+    let bootstrap = new_node("test-node-2");
+    let bootstrap_url = start_server(bootstrap.clone()).await;
+    bootstrap.dial_peer(first_url.clone(), Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || !bootstrap.outbound_peers().is_empty()).await;
+
+    // A new node dials the bootstrap node and the peers it lists
+    let node = new_node("test-node-3");
+    let mut dialed = node
+        .discover_peers(&bootstrap_url, Duration::from_millis(100), Duration::from_secs(5))
+        .await
+        .unwrap();
+    dialed.sort();
+    let mut expected = vec![first_url, bootstrap_url];
+    expected.sort();
+    assert_eq!(dialed, expected);
+
+    wait_for(Duration::from_secs(5), || first.peer_health().contains_key("test-node-3")).await;
+    wait_for(Duration::from_secs(5), || bootstrap.peer_health().contains_key("test-node-3")).await;
+}
+
+#[tokio::test]
+async fn test_node_stops_dialing_itself() {
+    let node = new_node("test-node-1");
+    let url = start_server(node.clone()).await;
+
+    assert!(node.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || node.dialed_peers().is_empty()).await;
+    assert!(node.peer_health().is_empty());
+    assert!(node.outbound_peers().is_empty());
+}
+
+#[tokio::test]
+async fn test_node_leave_drops_peer() {
+    let node = new_node("test-node-1");
+    let url = start_server(node.clone()).await;
+
+    let client = connect_peer(&url, "test-node-2", SigningKey::generate(&mut OsRng)).await;
+    wait_for(Duration::from_secs(5), || node.peer_health().contains_key("test-node-2")).await;
+
+    // The peer is dropped on its goodbye, without waiting for a heartbeat timeout
+    let goodbye = P2PMessage::new(MessageType::NodeLeave, "test-node-2".to_string(), "".to_string(), json!({}));
+    assert!(node.handle_message(goodbye).is_none());
+    assert!(node.peer_health().is_empty());
+    assert!(!node.clone_connected_nodes().lock().unwrap().contains_key("test-node-2"));
+
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_leave_closes_outbound_connections() {
+    let server = new_node("test-node-1");
+    let url = start_server(server.clone()).await;
+
+    let client = new_node("test-node-2");
+    client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || server.peer_health().contains_key("test-node-2")).await;
+
+    client.leave().await;
+    assert!(client.outbound_peers().is_empty());
+    assert!(client.dialed_peers().is_empty());
+    wait_for(Duration::from_secs(5), || server.peer_health().is_empty()).await;
+
+    // The connection isn't redialed
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(server.peer_health().is_empty());
+}
+
+#[tokio::test]
+async fn test_peer_must_prove_its_key() {
+    let node = new_node("test-node-1");
+    let url = start_server(node.clone()).await;
+
+    // A peer that doesn't take part in the handshake is never trusted
+    let silent = ClientBuilder::new(url.clone())
+        .namespace("/p2p")
+        .auth(json!({ "node_id": "test-node-2" }))
+        .on("p2p_message", |_, _| async {}.boxed())
+        .connect()
+        .await
+        .unwrap();
+
+    // Nor is one claiming the ID of a node known by a different key
+    let known = SigningKey::generate(&mut OsRng);
+    node.ledger
+        .add_node_key("test-node-3".to_string(), &hex::encode(known.verifying_key().to_bytes()))
+        .unwrap();
+    let impostor = connect_peer(&url, "test-node-3", SigningKey::generate(&mut OsRng)).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(node.peer_health().is_empty());
+
+    // The genuine key gets through
+    let genuine = connect_peer(&url, "test-node-3", known).await;
+    wait_for(Duration::from_secs(5), || node.peer_health().contains_key("test-node-3")).await;
+    assert!(!node.peer_health().contains_key("test-node-2"));
+
+    for client in [silent, impostor, genuine] {
+        client.disconnect().await.ok();
+    }
+}
+
+#[tokio::test]
+async fn test_key_ids_refuse_named_peers() {
+    let node_id = "test-node-1".to_string();
+    let node = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)).with_key_ids(true));
+    let url = start_server(node.clone()).await;
+
+    // A peer known by a name rather than its key is refused
+    let named = connect_peer(&url, "test-node-2", SigningKey::generate(&mut OsRng)).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(node.peer_health().is_empty());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let node_id = identity::node_id(&key);
+    let keyed = connect_peer(&url, &node_id, key).await;
+    wait_for(Duration::from_secs(5), || node.peer_health().contains_key(&node_id)).await;
+
+    named.disconnect().await.ok();
+    keyed.disconnect().await.ok();
+}
+
+#[tokio::test]
+async fn test_peer_cannot_speak_for_another_node() {
+    let node = new_node("test-node-1");
+    let url = start_server(node.clone()).await;
+
+    let first = connect_peer(&url, "test-node-2", SigningKey::generate(&mut OsRng)).await;
+    let second = connect_peer(&url, "test-node-3", SigningKey::generate(&mut OsRng)).await;
+    wait_for(Duration::from_secs(5), || node.peer_health().len() == 2).await;
+
+    // A goodbye sent in another node's name is ignored
+    let goodbye = P2PMessage::new(MessageType::NodeLeave, "test-node-2".to_string(), "".to_string(), json!({}));
+    second.emit("p2p_message", serde_json::to_value(goodbye).unwrap()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(node.peer_health().contains_key("test-node-2"));
+
+    first.disconnect().await.unwrap();
+    second.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_outbound_peer_refuses_impostor() {
+    // The client already knows test-node-1 by another key
+    let impostor = new_node("test-node-1");
+    let url = start_server(impostor.clone()).await;
+    impostor.add_local_entry(json!({ "message": "Forged entry" })).unwrap();
+
+    let client = new_node("test-node-2");
+    client.ledger.add_node_key("test-node-1".to_string(), &SharedLedger::new("test-node-1".to_string()).public_key()).unwrap();
+    client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(client.ledger.get_entries().is_empty());
+    assert!(impostor.peer_health().is_empty());
+    client.leave().await;
+}
+
+#[tokio::test]
+async fn test_encrypted_peers_sync_ledger() {
+    // The server requires encryption, so the connection is sealed even though the client doesn't ask
+    let server = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())).with_encryption(true));
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let url = start_server(server.clone()).await;
+
+    let client = new_node("test-node-2");
+    client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    // New entries flow both ways
+    let entry = server.add_local_entry(json!({ "message": "Test entry 2" })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    let entry = client.add_local_entry(json!({ "message": "Test entry 3" })).unwrap();
+    wait_for(Duration::from_secs(5), || server.ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    client.leave().await;
+    wait_for(Duration::from_secs(5), || server.peer_health().is_empty()).await;
+}
+
+#[tokio::test]
+async fn test_encrypted_peer_ignores_plaintext() {
+    let node = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())).with_encryption(true));
+    let url = start_server(node.clone()).await;
+
+    // The peer proves its key but then speaks in the clear
+    let client = connect_peer(&url, "test-node-2", SigningKey::generate(&mut OsRng)).await;
+    wait_for(Duration::from_secs(5), || node.peer_health().contains_key("test-node-2")).await;
+
+    let goodbye = P2PMessage::new(MessageType::NodeLeave, "test-node-2".to_string(), "".to_string(), json!({}));
+    client.emit("p2p_message", serde_json::to_value(goodbye).unwrap()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(node.peer_health().contains_key("test-node-2"));
+
+    client.disconnect().await.unwrap();
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{MessageType, P2PManager, P2PMessage};
+use gsio_node::pex::{PeerAddress, PeerExchangeConfig};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+mod common;
+
+use common::start_server_on;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+fn address(node_id: &str, url: &str) -> PeerAddress {
+    PeerAddress { node_id: node_id.to_string(), url: url.to_string() }
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_defaults() {
+    let config = PeerExchangeConfig::default();
+    assert!(config.is_enabled());
+    assert_eq!(config.interval(), Duration::from_secs(60));
+    assert!(!PeerExchangeConfig { interval: 0, ..config }.is_enabled());
+}
+
+#[test]
+fn test_node_list_carries_addresses() {
+    let node = new_node("test-node-2").with_public_url("http://node-2:3000").with_peer_exchange(16);
+    assert_eq!(node.peer_addresses(), vec![address("test-node-2", "http://node-2:3000")]);
+
+    let request =
+        P2PMessage::new(MessageType::NodeListRequest, "test-node-1".to_string(), "test-node-2".to_string(), json!({}));
+    let reply = node.handle_message(request.clone()).unwrap();
+    assert!(matches!(reply.message_type, MessageType::NodeListResponse));
+    assert_eq!(reply.payload["peers"], json!([{ "node_id": "test-node-2", "url": "http://node-2:3000" }]));
+
+    // The asking node learns the address, and would dial it
+    let asking = new_node("test-node-1").with_peer_exchange(16);
+    assert!(asking.handle_message(reply.clone()).is_none());
+    let contact = asking.contacts().get("test-node-2").unwrap();
+    assert_eq!(contact.url.as_deref(), Some("http://node-2:3000"));
+    assert_eq!(asking.unconnected_contacts(), vec![contact]);
+
+    // Nodes that don't take part send no addresses and ignore those sent to them
+    let reply = new_node("test-node-3").with_public_url("http://node-3:3000").handle_message(request).unwrap();
+    assert!(reply.payload.get("peers").is_none());
+    let ignoring = new_node("test-node-4");
+    let reply = node.handle_message(P2PMessage::new(
+        MessageType::NodeListRequest,
+        "test-node-4".to_string(),
+        "test-node-2".to_string(),
+        json!({}),
+    ));
+    ignoring.handle_message(reply.unwrap());
+    assert!(ignoring.contacts().all().is_empty());
+}
+
+#[tokio::test]
+async fn test_mesh_is_reached_transitively() {
+    let mut nodes = Vec::new();
+    let mut urls = Vec::new();
+    for id in ["test-node-1", "test-node-2", "test-node-3"] {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let node = Arc::new(new_node(id).with_public_url(url.clone()).with_peer_exchange(16));
+        start_server_on(node.clone(), listener).await;
+        nodes.push(node);
+        urls.push(url);
+    }
+
+    // Node 3 only knows node 2, which only knows node 1
+    nodes[1].dial_peer(urls[0].clone(), Duration::from_millis(100), Duration::from_secs(5));
+    nodes[2].dial_peer(urls[1].clone(), Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || {
+        nodes[1].outbound_peer_ids().values().any(|id| id.as_deref() == Some("test-node-1"))
+            && nodes[2].outbound_peer_ids().values().any(|id| id.as_deref() == Some("test-node-2"))
+    })
+    .await;
+
+    // A round of peer exchange teaches node 3 where node 1 is
+    assert_eq!(nodes[2].exchange_peers(3).await, 1);
+    let unconnected_urls = |node: &P2PManager| -> Vec<String> {
+        node.unconnected_contacts().into_iter().filter_map(|contact| contact.url).collect()
+    };
+    wait_for(Duration::from_secs(5), || unconnected_urls(&nodes[2]).contains(&urls[0])).await;
+    for contact in nodes[2].unconnected_contacts() {
+        nodes[2].dial_contact(&contact, Duration::from_millis(100), Duration::from_secs(5)).await.unwrap();
+    }
+    wait_for(Duration::from_secs(5), || nodes[0].peer_health().contains_key("test-node-3")).await;
+
+    // Once connected, node 1 isn't dialed again
+    wait_for(Duration::from_secs(5), || !unconnected_urls(&nodes[2]).contains(&urls[0])).await;
+
+    for node in &nodes {
+        node.leave().await;
+    }
+}
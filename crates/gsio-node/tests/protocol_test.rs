@@ -0,0 +1,166 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use futures::FutureExt;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{
+    handshake_transcript, sign_challenge, MessageType, P2PManager, P2PMessage, ProtocolVersions, PROTOCOL_VERSION,
+};
+use rand::rngs::OsRng;
+use rust_socketio::{
+    asynchronous::{Client as PeerClient, ClientBuilder},
+    Payload,
+};
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+/// Connect to a node's `/p2p` namespace as `node_id` with the given `protocol`
+/// handshake field, if any, answering its challenge and recording every
+/// message the node sends
+async fn connect_peer(url: &str, node_id: &str, protocol: Option<JsonValue>) -> (PeerClient, Arc<Mutex<Vec<P2PMessage>>>) {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut auth = json!({
+        "node_id": node_id,
+        "public_key": hex::encode(key.verifying_key().to_bytes()),
+        "challenge": "test-challenge",
+    });
+    if let Some(protocol) = protocol {
+        auth["protocol"] = protocol;
+    }
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (peer_id, messages) = (node_id.to_string(), received.clone());
+    let client = ClientBuilder::new(url)
+        .namespace("/p2p")
+        .auth(auth.clone())
+        .on("p2p_message", move |payload: Payload, client: PeerClient| {
+            let (key, peer_id, messages, auth) = (key.clone(), peer_id.clone(), messages.clone(), auth.clone());
+            async move {
+                let Payload::Text(values) = payload else { return };
+                let Ok(message) = serde_json::from_value::<P2PMessage>(values[0].clone()) else { return };
+                messages.lock().unwrap().push(message.clone());
+                if matches!(message.message_type, MessageType::AuthChallenge) {
+                    let transcript = handshake_transcript(&peer_id, &auth, &message.sender_id, &message.payload);
+                    let signature = sign_challenge(&key, &transcript, &peer_id, &message.sender_id);
+                    let response = P2PMessage::new(
+                        MessageType::AuthResponse,
+                        peer_id.clone(),
+                        message.sender_id.clone(),
+                        json!({ "signature": signature }),
+                    );
+                    client.emit("p2p_message", serde_json::to_value(response).unwrap()).await.unwrap();
+                }
+            }
+            .boxed()
+        })
+        .connect()
+        .await
+        .unwrap();
+    (client, received)
+}
+
+/// The first message of `message_type` in `messages`
+fn find(messages: &Mutex<Vec<P2PMessage>>, message_type: &str) -> Option<P2PMessage> {
+    let messages = messages.lock().unwrap();
+    messages.iter().find(|m| serde_json::to_value(&m.message_type).unwrap() == message_type).cloned()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_peers_speak_the_newest_common_version() {
+    let server = Arc::new(new_node("test-node-1"));
+    let url = start_server(server.clone()).await;
+
+    let client = Arc::new(new_node("test-node-2"));
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || server.peer_version("test-node-2").is_some()).await;
+    wait_for(Duration::from_secs(5), || client.peer_version("test-node-1").is_some()).await;
+    assert_eq!(server.peer_version("test-node-2"), Some(PROTOCOL_VERSION));
+    assert_eq!(client.peer_version("test-node-1"), Some(PROTOCOL_VERSION));
+}
+
+#[tokio::test]
+async fn test_peers_downgrade_to_an_older_version() {
+    // The server only speaks version 1, so the client speaks it too
+    let server = Arc::new(new_node("test-node-1").with_protocol_versions(ProtocolVersions::LEGACY));
+    let url = start_server(server.clone()).await;
+
+    let client = Arc::new(new_node("test-node-2"));
+    client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+    wait_for(Duration::from_secs(5), || client.peer_version("test-node-1").is_some()).await;
+    wait_for(Duration::from_secs(5), || server.peer_version("test-node-2").is_some()).await;
+    assert_eq!(client.peer_version("test-node-1"), Some(1));
+    assert_eq!(server.peer_version("test-node-2"), Some(1));
+
+    // Entries still flow
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+}
+
+#[tokio::test]
+async fn test_peer_without_versions_speaks_version_1() {
+    let node = Arc::new(new_node("test-node-1"));
+    let url = start_server(node.clone()).await;
+
+    // A node from before negotiation sends no protocol field
+    let (_client, received) = connect_peer(&url, "test-node-2", None).await;
+    wait_for(Duration::from_secs(5), || node.peer_version("test-node-2").is_some()).await;
+    assert_eq!(node.peer_version("test-node-2"), Some(1));
+
+    let challenge = find(&received, "AuthChallenge").unwrap();
+    assert_eq!(challenge.payload["protocol_version"], 1);
+    assert_eq!(challenge.payload["protocol"], json!({ "min": 1, "max": PROTOCOL_VERSION }));
+    wait_for(Duration::from_secs(5), || find(&received, "NodeAnnounce").is_some()).await;
+    assert_eq!(find(&received, "NodeAnnounce").unwrap().version, 1);
+}
+
+#[tokio::test]
+async fn test_peer_without_a_common_version_is_refused() {
+    let node = Arc::new(new_node("test-node-1"));
+    let url = start_server(node.clone()).await;
+
+    let newer = json!({ "min": PROTOCOL_VERSION + 1, "max": PROTOCOL_VERSION + 2 });
+    let (_client, received) = connect_peer(&url, "test-node-2", Some(newer)).await;
+    wait_for(Duration::from_secs(5), || find(&received, "HandshakeRejected").is_some()).await;
+
+    // The node says why and which versions it speaks, instead of just hanging up
+    let rejection = find(&received, "HandshakeRejected").unwrap();
+    assert!(rejection.payload["reason"].as_str().unwrap().contains("protocol versions"));
+    assert_eq!(rejection.payload["protocol"], json!({ "min": 1, "max": PROTOCOL_VERSION }));
+    assert!(find(&received, "AuthChallenge").is_none());
+    assert!(node.peer_version("test-node-2").is_none());
+
+    // Unreadable versions are refused too
+    let (_client, received) = connect_peer(&url, "test-node-3", Some(json!("latest"))).await;
+    wait_for(Duration::from_secs(5), || find(&received, "HandshakeRejected").is_some()).await;
+}
+
+#[tokio::test]
+async fn test_dialer_refuses_a_version_it_does_not_speak() {
+    let server = Arc::new(new_node("test-node-1").with_protocol_versions(ProtocolVersions::LEGACY));
+    let url = start_server(server.clone()).await;
+
+    let only_newest = ProtocolVersions { min: PROTOCOL_VERSION, max: PROTOCOL_VERSION };
+    let client = Arc::new(new_node("test-node-2").with_protocol_versions(only_newest));
+    client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(server.peer_version("test-node-2").is_none());
+    assert!(client.peer_version("test-node-1").is_none());
+    assert!(client.outbound_peer_ids().values().all(Option::is_none));
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use gsio_node::identity;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{P2PManager, Transport};
+use gsio_node::quic::{self, QuicTransport, P2P_ALPN};
+use iroh::protocol::Router;
+use iroh::{Endpoint, NodeAddr, RelayMode};
+use rand::rngs::OsRng;
+use serde_json::json;
+
+struct TestNode {
+    p2p: Arc<P2PManager>,
+    addr: NodeAddr,
+    _router: Router,
+}
+
+/// Start a node whose ID is the key of its iroh endpoint, accepting P2P connections over QUIC
+async fn start_node(key_ids: bool) -> TestNode {
+    let key = SigningKey::generate(&mut OsRng);
+    let node_id = identity::node_id(&key);
+    let endpoint = Endpoint::builder()
+        .secret_key(identity::iroh_secret_key(&key))
+        .relay_mode(RelayMode::Disabled)
+        .bind()
+        .await
+        .unwrap();
+    let p2p = P2PManager::new(node_id.clone(), SharedLedger::with_signing_key(node_id, key))
+        .with_key_ids(key_ids)
+        .with_endpoint(endpoint.clone());
+    let transport = QuicTransport::new(p2p.clone());
+    let router = Router::builder(endpoint.clone()).accept(transport.alpn(), transport).spawn();
+    let addr = endpoint.node_addr().await.unwrap();
+    TestNode { p2p: Arc::new(p2p), addr, _router: router }
+}
+
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_alpn_per_channel() {
+    assert_eq!(quic::alpn(None), P2P_ALPN);
+    assert_eq!(quic::alpn(Some("payments")), b"gsio/p2p/0/payments");
+}
+
+#[tokio::test]
+async fn test_quic_peers_sync_and_broadcast() {
+    let a = start_node(true).await;
+    let b = start_node(true).await;
+    let synced = a.p2p.add_local_entry(json!({ "message": "Before connecting" })).unwrap();
+
+    a.p2p.dial_quic(b.addr.clone()).await.unwrap();
+    wait_for(Duration::from_secs(5), || b.p2p.quic_peers() == vec![a.p2p.node_id().to_string()]).await;
+    assert_eq!(a.p2p.quic_peers(), vec![b.p2p.node_id().to_string()]);
+    assert!(b.p2p.peer_version(a.p2p.node_id()).is_some());
+    assert_eq!(b.p2p.peer_info(a.p2p.node_id()).unwrap().transport, Transport::Iroh);
+
+    // The new peer catches up on the chain, then hears about new entries
+    wait_for(Duration::from_secs(5), || b.p2p.ledger.get_entries().iter().any(|e| e.id == synced.id)).await;
+    let announced = a.p2p.add_local_entry(json!({ "message": "Over QUIC" })).unwrap();
+    wait_for(Duration::from_secs(5), || b.p2p.ledger.get_entries().iter().any(|e| e.id == announced.id)).await;
+
+    // Requests are answered over the same connection
+    let nodes = b.p2p.request_node_list_await(a.p2p.node_id().to_string(), Duration::from_secs(5)).await.unwrap();
+    assert!(nodes.contains(&b.p2p.node_id().to_string()));
+
+    // Dialing again keeps the open connection
+    a.p2p.dial_quic(b.addr.clone()).await.unwrap();
+    assert_eq!(a.p2p.quic_peers().len(), 1);
+
+    assert!(a.p2p.disconnect_peer(b.p2p.node_id()).await);
+    wait_for(Duration::from_secs(5), || b.p2p.quic_peers().is_empty()).await;
+}
+
+#[tokio::test]
+async fn test_quic_needs_key_ids() {
+    let a = start_node(true).await;
+    let b = start_node(false).await;
+
+    // A node that doesn't insist on key IDs neither dials nor accepts over QUIC
+    assert!(b.p2p.dial_quic(a.addr.clone()).await.is_err());
+    assert!(a.p2p.dial_quic(b.addr.clone()).await.is_err());
+    assert!(a.p2p.quic_peers().is_empty());
+    assert!(b.p2p.quic_peers().is_empty());
+}
+
+#[tokio::test]
+async fn test_quic_refuses_banned_peer() {
+    let a = start_node(true).await;
+    let b = start_node(true).await;
+
+    b.p2p.ban_peer(a.p2p.node_id()).await;
+    assert!(a.p2p.dial_quic(b.addr.clone()).await.is_err());
+    assert!(b.p2p.quic_peers().is_empty());
+}
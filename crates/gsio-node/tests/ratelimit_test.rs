@@ -0,0 +1,111 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use axum::{middleware, Router};
+use gsio_client::{ErrorCode, GsioClientError, GsioSocketClient};
+use gsio_node::api;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::ratelimit::{self, Client, RateLimitConfig, RateLimiter};
+use gsio_node::socket;
+use serde_json::{json, Value as JsonValue};
+use socketioxide::SocketIo;
+use tokio::net::TcpListener;
+
+/// Two requests at once and practically no refill, so the third is always refused
+fn config() -> RateLimitConfig {
+    RateLimitConfig {
+        requests_per_second: 0.01,
+        burst: 2,
+    }
+}
+
+fn client(ip: u8, credential: Option<&str>) -> Client {
+    Client {
+        ip: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, ip))),
+        credential: credential.map(str::to_string),
+    }
+}
+
+/// Serve the API and Socket.IO namespace the way a node with `[rate_limit]` set does
+async fn start_server(p2p: Arc<P2PManager>, limiter: Arc<RateLimiter>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let (layer, io) = SocketIo::builder().build_layer();
+    socket::register_root_namespace(&io, p2p.clone(), Some(limiter.clone()));
+    let app: Router = api::router(p2p)
+        .layer(middleware::from_fn_with_state(limiter, ratelimit::limit_rate))
+        .layer(layer);
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+    });
+
+    url
+}
+
+#[test]
+fn test_rate_limiter() {
+    let limiter = RateLimiter::new(&config()).unwrap();
+
+    // A client gets its burst and then has to wait
+    assert!(limiter.check(&client(1, Some("key-1"))).is_ok());
+    assert!(limiter.check(&client(1, Some("key-1"))).is_ok());
+    let wait = limiter.check(&client(1, Some("key-1"))).unwrap_err();
+    assert!(wait.as_secs() > 1);
+    assert!(ratelimit::retry_after_secs(wait) >= wait.as_secs());
+
+    // The key is spent from any address, and the address with any key
+    assert!(limiter.check(&client(2, Some("key-1"))).is_err());
+    assert!(limiter.check(&client(1, Some("key-2"))).is_err());
+    assert!(limiter.check(&client(1, None)).is_err());
+
+    // Refused requests don't use up the other buckets
+    assert!(limiter.check(&client(2, None)).is_ok());
+    assert!(limiter.check(&client(2, Some("key-2"))).is_ok());
+    assert!(limiter.check(&client(2, None)).is_err());
+
+    assert!(!RateLimitConfig::default().is_enabled());
+    assert!(RateLimiter::new(&RateLimitConfig::default()).is_err());
+    assert!(RateLimiter::new(&RateLimitConfig { burst: 0, ..config() }).is_err());
+}
+
+#[tokio::test]
+async fn test_api_requests_are_limited() {
+    let p2p = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())));
+    let url = start_server(p2p, Arc::new(RateLimiter::new(&config()).unwrap())).await;
+    let http = reqwest::Client::new();
+
+    for _ in 0..2 {
+        let response = http.get(format!("{url}/api/ledger")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    let response = http.get(format!("{url}/api/ledger")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 1);
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["code"], "rate_limited");
+
+    // Another key from the same address doesn't help
+    let response = http.get(format!("{url}/api/ledger")).bearer_auth("another-key").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_socket_entries_are_limited() {
+    let p2p = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())));
+    let url = start_server(p2p.clone(), Arc::new(RateLimiter::new(&config()).unwrap())).await;
+    let client = GsioSocketClient::connect(&url).await.unwrap();
+
+    client.add_ledger_entry(json!({ "message": "Test entry 1" })).await.unwrap();
+    client.add_ledger_entry(json!({ "message": "Test entry 2" })).await.unwrap();
+    let result = client.add_ledger_entry(json!({ "message": "Test entry 3" })).await;
+    assert!(matches!(result, Err(GsioClientError::ServerError { code: Some(ErrorCode::RateLimited), .. })));
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    // Reads aren't limited on the socket
+    assert_eq!(client.get_ledger().await.unwrap().len(), 2);
+
+    client.disconnect().await.unwrap();
+}
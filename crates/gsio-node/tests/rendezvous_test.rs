@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use gsio_client::relay_token;
+use gsio_node::config::NodeConfig;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::rendezvous::{decode_frame, encode_frame, encode_frames, Reassembly, RelayFrame, MAX_FRAME_BYTES};
+use rmpv::Value;
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(200);
+const SECRET: &str = "correct horse battery staple";
+const EXPIRES: u64 = 4_000_000_000;
+
+/// What went through the relay
+#[derive(Default)]
+struct RelayLog {
+    /// Payloads forwarded, as text
+    forwarded: Vec<String>,
+    /// Deliveries the nodes acknowledged
+    acked: Vec<u64>,
+}
+
+type Log = Arc<Mutex<RelayLog>>;
+
+/// The node ID a token for the `mesh` channel was issued to, if it's genuine
+fn token_holder(request: &Request) -> Option<String> {
+    let query = request.uri().query()?;
+    let token = url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "token")?.1;
+    let node_id = token.split('.').next()?;
+    (token == relay_token(SECRET, "mesh", node_id, EXPIRES)).then(|| node_id.to_string())
+}
+
+/// Start a relay that checks tokens and routes frames the way gsio-relay does, returning its URL
+async fn start_relay() -> (String, Log) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let nodes: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<RelayFrame>>>> = Arc::default();
+    let relay_log = Log::default();
+    let log = relay_log.clone();
+    let next_seq = Arc::new(AtomicU64::new(1));
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let nodes = nodes.clone();
+            let log = log.clone();
+            let next_seq = next_seq.clone();
+            tokio::spawn(async move {
+                let mut admitted = None;
+                // tungstenite decides the callback's error type
+                #[allow(clippy::result_large_err)]
+                let check_token = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+                    admitted = token_holder(request);
+                    match admitted {
+                        Some(_) => Ok(response),
+                        None => {
+                            let mut refused = ErrorResponse::new(Some("Invalid token".to_string()));
+                            *refused.status_mut() = StatusCode::UNAUTHORIZED;
+                            Err(refused)
+                        }
+                    }
+                };
+                let Ok(socket) = tokio_tungstenite::accept_hdr_async(stream, check_token).await else {
+                    return;
+                };
+                let (mut sink, mut stream) = socket.split();
+                let (sender, mut outgoing) = mpsc::unbounded_channel::<RelayFrame>();
+                tokio::spawn(async move {
+                    while let Some(frame) = outgoing.recv().await {
+                        if sink.send(Message::binary(encode_frame(&frame))).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                let mut registered = None;
+                while let Some(Ok(Message::Binary(bytes))) = stream.next().await {
+                    if bytes.len() > MAX_FRAME_BYTES {
+                        sender.send(RelayFrame::Error { message: "Frame is over the limit".to_string() }).ok();
+                        continue;
+                    }
+                    match decode_frame(&bytes).unwrap() {
+                        RelayFrame::Register { node_id } if admitted.as_ref() == Some(&node_id) => {
+                            let mut nodes = nodes.lock().unwrap();
+                            for other in nodes.values() {
+                                other.send(RelayFrame::NodeJoined { node_id: node_id.clone() }).ok();
+                            }
+                            let others = nodes.keys().cloned().collect();
+                            nodes.insert(node_id.clone(), sender.clone());
+                            sender.send(RelayFrame::Registered { node_id: node_id.clone(), nodes: others }).ok();
+                            registered = Some(node_id);
+                        }
+                        RelayFrame::Forward { to, payload } => {
+                            log.lock().unwrap().forwarded.push(payload.to_string());
+                            let from = registered.clone().unwrap();
+                            let seq = next_seq.fetch_add(1, Ordering::SeqCst);
+                            if let Some(target) = nodes.lock().unwrap().get(&to) {
+                                target.send(RelayFrame::Deliver { from, seq, payload }).ok();
+                            }
+                        }
+                        RelayFrame::Ack { seq } => log.lock().unwrap().acked.push(seq),
+                        _ => {}
+                    }
+                }
+
+                if let Some(node_id) = registered {
+                    let mut nodes = nodes.lock().unwrap();
+                    nodes.remove(&node_id);
+                    for other in nodes.values() {
+                        other.send(RelayFrame::NodeLeft { node_id: node_id.clone() }).ok();
+                    }
+                }
+            });
+        }
+    });
+    (format!("ws://{addr}/channels/mesh"), relay_log)
+}
+
+fn token(node_id: &str) -> Option<String> {
+    Some(relay_token(SECRET, "mesh", node_id, EXPIRES))
+}
+
+fn node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_rendezvous_config() {
+    let config: NodeConfig = toml::from_str(
+        r#"
+        [rendezvous]
+        url = "wss://relay.example.com/channels/mesh"
+        token = "node-a.1700000000.d75d6c0f"
+        "#,
+    )
+    .unwrap();
+    assert!(config.rendezvous.is_enabled());
+    assert!(!NodeConfig::default().rendezvous.is_enabled());
+
+    let mut config = NodeConfig::default();
+    config
+        .apply_env(|name| match name {
+            "RENDEZVOUS_URL" => Some("ws://localhost:3001/channels/mesh".to_string()),
+            "RENDEZVOUS_TOKEN" => Some("node-a.1700000000.d75d6c0f".to_string()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(config.rendezvous.url.as_deref(), Some("ws://localhost:3001/channels/mesh"));
+    assert_eq!(config.rendezvous.token.as_deref(), Some("node-a.1700000000.d75d6c0f"));
+}
+
+#[tokio::test]
+async fn test_nodes_peer_through_the_relay() {
+    let (url, log) = start_relay().await;
+    let a = node("node-a");
+    let b = node("node-b");
+    // node-b has history for node-a to catch up on
+    let existing = b.add_local_entry(json!({ "message": "before" })).unwrap();
+
+    assert!(b.connect_rendezvous(url.clone(), token("node-b"), RETRY_INTERVAL));
+    assert!(!b.connect_rendezvous(url.clone(), token("node-b"), RETRY_INTERVAL));
+    a.connect_rendezvous(url, token("node-a"), RETRY_INTERVAL);
+    wait_for(Duration::from_secs(5), || a.relayed_peers() == ["node-b"] && b.relayed_peers() == ["node-a"]).await;
+    wait_for(Duration::from_secs(5), || a.ledger.get_entry_by_id(&existing.id).is_some()).await;
+    assert!(b.peer_health().contains_key("node-a"));
+    // Deliveries are acknowledged so the relay can stop keeping them
+    wait_for(Duration::from_secs(5), || log.lock().unwrap().acked.contains(&1)).await;
+
+    // New entries are announced through the relay
+    let entry = a.add_local_entry(json!({ "message": "after" })).unwrap();
+    wait_for(Duration::from_secs(5), || b.ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    // A node that leaves is dropped by its relayed peers straight away
+    a.leave().await;
+    wait_for(Duration::from_secs(5), || b.relayed_peers().is_empty()).await;
+    assert!(!b.peer_health().contains_key("node-a"));
+}
+
+#[tokio::test]
+async fn test_relay_only_sees_sealed_messages() {
+    let (url, log) = start_relay().await;
+    let a = node("node-a").with_encryption(true);
+    let b = node("node-b");
+    a.connect_rendezvous(url.clone(), token("node-a"), RETRY_INTERVAL);
+    b.connect_rendezvous(url, token("node-b"), RETRY_INTERVAL);
+    wait_for(Duration::from_secs(5), || a.relayed_peers() == ["node-b"] && b.relayed_peers() == ["node-a"]).await;
+
+    let entry = b.add_local_entry(json!({ "message": "for node-a only" })).unwrap();
+    wait_for(Duration::from_secs(5), || a.ledger.get_entry_by_id(&entry.id).is_some()).await;
+    assert!(log.lock().unwrap().forwarded.iter().all(|payload| !payload.contains("for node-a only")));
+}
+
+#[tokio::test]
+async fn test_banned_nodes_get_no_session() {
+    let (url, _) = start_relay().await;
+    let a = node("node-a");
+    let b = node("node-b");
+    b.ban_peer("node-a").await;
+    a.connect_rendezvous(url.clone(), token("node-a"), RETRY_INTERVAL);
+    b.connect_rendezvous(url, token("node-b"), RETRY_INTERVAL);
+
+    tokio::time::sleep(RETRY_INTERVAL * 3).await;
+    assert!(a.relayed_peers().is_empty());
+    assert!(b.relayed_peers().is_empty());
+    assert!(b.peer_health().is_empty());
+}
+
+#[tokio::test]
+async fn test_nodes_need_a_token_of_their_own() {
+    let (url, _) = start_relay().await;
+    let a = node("node-a");
+    let b = node("node-b");
+    let c = node("node-c");
+    a.connect_rendezvous(url.clone(), token("node-a"), RETRY_INTERVAL);
+    // node-b presents no token, and node-c one issued to node-a
+    b.connect_rendezvous(url.clone(), None, RETRY_INTERVAL);
+    c.connect_rendezvous(url, token("node-a"), RETRY_INTERVAL);
+
+    tokio::time::sleep(RETRY_INTERVAL * 3).await;
+    assert!(a.relayed_peers().is_empty());
+    assert!(b.relayed_peers().is_empty());
+    assert!(c.relayed_peers().is_empty());
+}
+
+#[test]
+fn test_large_payloads_are_fragmented() {
+    let payload = Value::Map(vec![("data".into(), "x".repeat(MAX_FRAME_BYTES + 1000).into())]);
+    let small = encode_frames(RelayFrame::Forward { to: "node-b".to_string(), payload: "hello".into() }, 1);
+    assert_eq!(small.len(), 1);
+
+    let messages = encode_frames(RelayFrame::Forward { to: "node-b".to_string(), payload: payload.clone() }, 7);
+    assert_eq!(messages.len(), 11);
+    assert!(messages.iter().all(|bytes| bytes.len() <= MAX_FRAME_BYTES));
+
+    // Fragments are put back together whatever order they arrive in
+    let mut reassembly = Reassembly::new();
+    let mut delivered = Vec::new();
+    for bytes in messages.iter().rev() {
+        let RelayFrame::Forward { payload, .. } = decode_frame(bytes).unwrap() else {
+            panic!("expected a forward frame");
+        };
+        delivered.extend(reassembly.receive(RelayFrame::Deliver { from: "node-a".to_string(), seq: 1, payload }));
+    }
+    assert_eq!(delivered, vec![RelayFrame::Deliver { from: "node-a".to_string(), seq: 1, payload }]);
+
+    // Frames that aren't fragments pass straight through
+    let frame = RelayFrame::NodeLeft { node_id: "node-a".to_string() };
+    assert_eq!(reassembly.receive(frame.clone()), Some(frame));
+}
+
+#[tokio::test]
+async fn test_large_entries_sync_through_the_relay() {
+    let (url, _) = start_relay().await;
+    let a = node("node-a");
+    let b = node("node-b");
+    let large = b.add_local_entry(json!({ "message": "x".repeat(2 * MAX_FRAME_BYTES) })).unwrap();
+
+    a.connect_rendezvous(url.clone(), token("node-a"), RETRY_INTERVAL);
+    b.connect_rendezvous(url, token("node-b"), RETRY_INTERVAL);
+    wait_for(Duration::from_secs(10), || a.ledger.get_entry_by_id(&large.id).is_some()).await;
+}
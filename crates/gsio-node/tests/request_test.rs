@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ed25519_dalek::SigningKey;
+use futures::FutureExt;
+use gsio_node::codec::Frame;
+use gsio_node::ledger::{LedgerEntry, SharedLedger};
+use gsio_node::p2p::{handshake_transcript, sign_challenge, MessageType, P2PManager, P2PMessage, RequestError};
+use rand::rngs::OsRng;
+use rust_socketio::{
+    asynchronous::{Client as PeerClient, ClientBuilder},
+    Payload,
+};
+use serde_json::json;
+
+mod common;
+
+use common::start_server;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+/// Connect to a node's `/p2p` namespace as `node_id`, answering its
+/// challenge but nothing else, and recording every message the node sends
+async fn connect_silent_peer(url: &str, node_id: &str) -> (PeerClient, Arc<Mutex<Vec<P2PMessage>>>) {
+    let key = SigningKey::generate(&mut OsRng);
+    let auth = json!({
+        "node_id": node_id,
+        "public_key": hex::encode(key.verifying_key().to_bytes()),
+        "challenge": "test-challenge",
+        "codecs": ["json"],
+    });
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (peer_id, messages) = (node_id.to_string(), received.clone());
+    let client = ClientBuilder::new(url)
+        .namespace("/p2p")
+        .auth(auth.clone())
+        .on("p2p_message", move |payload: Payload, client: PeerClient| {
+            let (key, peer_id, messages, auth) = (key.clone(), peer_id.clone(), messages.clone(), auth.clone());
+            async move {
+                let Payload::Text(values) = payload else { return };
+                let message = Frame::Json(values[0].clone()).decode().unwrap();
+                messages.lock().unwrap().push(message.clone());
+                if matches!(message.message_type, MessageType::AuthChallenge) {
+                    let transcript = handshake_transcript(&peer_id, &auth, &message.sender_id, &message.payload);
+                    let signature = sign_challenge(&key, &transcript, &peer_id, &message.sender_id);
+                    let response = P2PMessage::new(
+                        MessageType::AuthResponse,
+                        peer_id.clone(),
+                        message.sender_id.clone(),
+                        json!({ "signature": signature }),
+                    );
+                    client.emit("p2p_message", serde_json::to_value(response).unwrap()).await.unwrap();
+                }
+            }
+            .boxed()
+        })
+        .connect()
+        .await
+        .unwrap();
+    (client, received)
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_requests_await_their_reply() {
+    let server = Arc::new(new_node("test-node-1"));
+    let entry = server.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let url = start_server(server.clone()).await;
+
+    let client = Arc::new(new_node("test-node-2"));
+    client.ledger.add_known_node("test-node-3".to_string());
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || server.peer_version("test-node-2").is_some()).await;
+    wait_for(Duration::from_secs(5), || client.peer_version("test-node-1").is_some()).await;
+
+    // Over the connection the client opened
+    let timeout = Duration::from_secs(5);
+    let fetched = client.request_entry_await("test-node-1".to_string(), entry.id.clone(), timeout).await.unwrap();
+    assert_eq!(fetched.id, entry.id);
+    assert_eq!(fetched.data, entry.data);
+    let missing = client.request_entry_await("test-node-1".to_string(), "no-such-entry".to_string(), timeout).await;
+    assert_eq!(missing.unwrap_err(), RequestError::NotFound);
+
+    // And over the one the server accepted
+    let nodes = server.request_node_list_await("test-node-2".to_string(), timeout).await.unwrap();
+    assert!(nodes.contains(&"test-node-3".to_string()));
+
+    // Concurrent requests each get their own reply
+    let (a, b) = tokio::join!(
+        client.request_entry_await("test-node-1".to_string(), entry.id.clone(), timeout),
+        client.request_entry_await("test-node-1".to_string(), "no-such-entry".to_string(), timeout),
+    );
+    assert_eq!(a.unwrap().id, entry.id);
+    assert_eq!(b.unwrap_err(), RequestError::NotFound);
+}
+
+#[tokio::test]
+async fn test_requests_time_out() {
+    let server = Arc::new(new_node("test-node-1"));
+    let url = start_server(server.clone()).await;
+
+    let result = server.request_entry_await("test-node-2".to_string(), "entry-1".to_string(), Duration::from_secs(1)).await;
+    assert_eq!(result.unwrap_err(), RequestError::NotConnected);
+
+    // A peer that never answers
+    let (_client, received) = connect_silent_peer(&url, "test-node-2").await;
+    wait_for(Duration::from_secs(5), || server.peer_version("test-node-2").is_some()).await;
+    let result = server.request_entry_await("test-node-2".to_string(), "entry-1".to_string(), Duration::from_millis(300)).await;
+    assert_eq!(result.unwrap_err(), RequestError::TimedOut);
+    let request = received.lock().unwrap().iter().find(|m| matches!(m.message_type, MessageType::EntryRequest)).cloned();
+    assert_eq!(request.unwrap().payload["entry_id"], "entry-1");
+
+    // A late reply, or one from a node the request didn't go to, is ignored
+    let request = P2PMessage::new(MessageType::EntryRequest, "test-node-1".to_string(), "test-node-2".to_string(), json!({}));
+    let reply = P2PMessage::new(MessageType::EntryResponse, "test-node-3".to_string(), "test-node-1".to_string(), json!(null))
+        .reply_to(&request);
+    assert!(server.handle_message(reply).is_none());
+}
+
+#[tokio::test]
+async fn test_retry_fetches_missing_parents() {
+    let server = Arc::new(new_node("test-node-1"));
+    let url = start_server(server.clone()).await;
+    let client = Arc::new(new_node("test-node-2"));
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || client.peer_version("test-node-1").is_some()).await;
+
+    // Entries added straight to the server's ledger aren't announced
+    let first = server.ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let second = server.ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+    let timeout = Duration::from_secs(5);
+    let fetched = client.request_entry_by_hash_await("test-node-1".to_string(), first.hash.clone(), timeout).await.unwrap();
+    assert_eq!(fetched.id, first.id);
+
+    // The client hears of the second entry but not its parent
+    client.ledger.add_pending_entry(second.clone());
+    assert_eq!(client.ledger.missing_parents(), vec![first.hash.clone()]);
+    let added = client.retry_pending_entries(Duration::from_secs(600)).await;
+    let ids: Vec<_> = added.iter().map(|e| e.id.clone()).collect();
+    assert_eq!(ids, vec![first.id.clone(), second.id.clone()]);
+    assert!(client.ledger.missing_parents().is_empty());
+
+    // Entries whose parent no peer has are dropped once they expire
+    let orphan = LedgerEntry::new(json!({ "message": "Test entry 3" }), "f".repeat(64), "test-node-3".to_string());
+    client.ledger.add_pending_entry(orphan);
+    assert!(client.retry_pending_entries(Duration::from_secs(600)).await.is_empty());
+    assert_eq!(client.ledger.stats().pending_entries, 1);
+    client.retry_pending_entries(Duration::ZERO).await;
+    assert_eq!(client.ledger.stats().pending_entries, 0);
+}
+
+#[tokio::test]
+async fn test_responses_are_handled_without_waiting() {
+    let server = Arc::new(new_node("test-node-1"));
+    server.ledger.add_known_node("test-node-3".to_string());
+    let url = start_server(server.clone()).await;
+    let client = Arc::new(new_node("test-node-2"));
+    assert!(client.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5)));
+    wait_for(Duration::from_secs(5), || client.peer_version("test-node-1").is_some()).await;
+
+    // An entry the server doesn't announce reaches the client's chain through its response
+    let entry = server.ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert!(client.request_entry("test-node-1".to_string(), entry.id.clone()));
+    wait_for(Duration::from_secs(5), || client.ledger.get_entry_by_id(&entry.id).is_some()).await;
+
+    assert!(client.request_node_list("test-node-1".to_string()));
+    wait_for(Duration::from_secs(5), || client.ledger.get_known_nodes().contains("test-node-3")).await;
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+use ed25519_dalek::SigningKey;
+use gsio_node::acl::{AclConfig, WriteAcl};
+use gsio_node::admin::{self, Admin, AdminConfig, RuntimeConfig};
+use gsio_node::config;
+use gsio_node::ledger::{Ledger, LedgerEntry, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use gsio_node::rotation::KeyRotation;
+use gsio_node::validation::{sign_data, ErrorCode};
+use rand::rngs::OsRng;
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const ADMIN_KEY: &str = "test-admin-key";
+
+fn key_hex(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+/// An entry by `node_id` linking to the tip of `ledger`
+fn peer_entry(ledger: &Ledger, node_id: &str, key: &SigningKey, data: JsonValue) -> LedgerEntry {
+    let mut entry = LedgerEntry::new(data, ledger.get_last_entry().unwrap().hash.clone(), node_id.to_string());
+    entry.sign(node_id.to_string(), key);
+    entry
+}
+
+#[test]
+fn test_rotation_retires_key() {
+    let (old, new) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng));
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    ledger.add_node_key("test-node-2".to_string(), &key_hex(&old)).unwrap();
+    ledger.add_entry(sign_data(json!({ "message": "before" }), &old)).unwrap();
+
+    // Only a rotation signed by the old key is taken
+    let rotation = KeyRotation::new(&old, &new.verifying_key());
+    let forged = KeyRotation { signature: KeyRotation::new(&new, &old.verifying_key()).signature, ..rotation.clone() };
+    assert_eq!(ledger.add_entry(forged.to_entry_data()).unwrap_err().code, ErrorCode::Rejected);
+    let entry = ledger.add_entry(rotation.to_entry_data()).unwrap();
+    let retired = ledger.key_rotations().retired(&key_hex(&old)).unwrap().clone();
+    assert_eq!(retired.new_key, key_hex(&new));
+    assert_eq!(retired.entry_id, entry.id);
+    assert_eq!(ledger.key_rotations().current(&key_hex(&old)), key_hex(&new));
+
+    // Data signed with the old key is refused from then on, with the new key it's taken
+    let error = ledger.add_entry(sign_data(json!({ "message": "after" }), &old)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::KeyRotated);
+    ledger.add_entry(sign_data(json!({ "message": "after" }), &new)).unwrap();
+
+    // The old key can't be rotated again, nor can a key be rotated back to it
+    let again = KeyRotation::new(&old, &SigningKey::generate(&mut OsRng).verifying_key());
+    assert_eq!(ledger.add_entry(again.to_entry_data()).unwrap_err().code, ErrorCode::Rejected);
+    let back = KeyRotation::new(&new, &old.verifying_key());
+    assert_eq!(ledger.add_entry(back.to_entry_data()).unwrap_err().code, ErrorCode::Rejected);
+
+    // Entries created with the old key no longer join the chain
+    let height = ledger.height();
+    let late = peer_entry(&ledger, "test-node-2", &old, json!({ "message": "late" }));
+    ledger.add_pending_entry(late.clone());
+    ledger.process_pending_entries();
+    assert_eq!(ledger.height(), height);
+    assert!(ledger.get_entry_by_id(&late.id).is_none());
+
+    // The earlier entries, and the rotation itself, stay valid
+    assert!(ledger.get_entries().iter().all(|entry| entry.is_valid()));
+    assert!(ledger.get_entry_by_id(&entry.id).is_some());
+}
+
+#[test]
+fn test_node_with_rotated_key_stops_writing() {
+    let old = SigningKey::generate(&mut OsRng);
+    let new = SigningKey::generate(&mut OsRng);
+    let mut ledger = Ledger::with_signing_key("test-node-1".to_string(), old.clone());
+    ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    ledger.add_entry(ledger.key_rotation(&new.verifying_key()).to_entry_data()).unwrap();
+
+    let error = ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap_err();
+    assert_eq!(error.code, ErrorCode::KeyRotated);
+    assert!(error.message.contains(&key_hex(&new)));
+
+    // Snapshots carry the rotations, so a restored ledger refuses the old key too
+    let snapshot = ledger.create_snapshot(ledger.height()).unwrap();
+    assert_eq!(snapshot.rotations.len(), 1);
+    let mut restored = Ledger::new("test-node-2".to_string());
+    restored.set_checkpoint_keys(&[ledger.public_key()]).unwrap();
+    restored.restore_from_snapshot(&snapshot).unwrap();
+    assert!(restored.key_rotations().is_retired(&key_hex(&old)));
+    let error = restored.add_entry(sign_data(json!({ "message": "Test entry 2" }), &old)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::KeyRotated);
+
+    // Snapshots without rotations hash as before
+    let mut unrotated = Ledger::new("test-node-3".to_string());
+    unrotated.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let snapshot = unrotated.create_snapshot(1).unwrap();
+    assert!(snapshot.rotations.is_empty());
+    assert!(!serde_json::to_value(&snapshot).unwrap().as_object().unwrap().contains_key("rotations"));
+}
+
+#[test]
+fn test_rotation_moves_acl_writer() {
+    let (old, new) = (SigningKey::generate(&mut OsRng), SigningKey::generate(&mut OsRng));
+    let mut ledger = Ledger::with_signing_key("test-node-1".to_string(), old.clone());
+    ledger.set_write_acl(WriteAcl::new(&AclConfig { writers: vec![key_hex(&old)], ..AclConfig::default() }).unwrap());
+    ledger.add_node_key("test-node-2".to_string(), &key_hex(&new)).unwrap();
+    ledger.add_entry(json!({ "amount": 1 })).unwrap();
+    ledger.add_entry(KeyRotation::new(&old, &new.verifying_key()).to_entry_data()).unwrap();
+    assert_eq!(ledger.write_acl().unwrap().writers(), vec![key_hex(&new)]);
+    assert!(!ledger.can_write());
+
+    // The holder of the new key writes in the old key's place
+    let entry = peer_entry(&ledger, "test-node-2", &new, json!({ "amount": 2 }));
+    assert!(ledger.check_writer(&entry).is_ok());
+    ledger.add_pending_entry(entry.clone());
+    ledger.process_pending_entries();
+    assert_eq!(ledger.get_last_entry().unwrap().id, entry.id);
+}
+
+async fn start_admin(p2p: Arc<P2PManager>, admin: Arc<Admin>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let config = AdminConfig { api_keys: vec![ADMIN_KEY.to_string()] };
+    let app = gsio_node::api::router(p2p).merge(admin::router(admin, &config).unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    url
+}
+
+#[tokio::test]
+async fn test_admin_rotates_node_key() {
+    let key_file = std::env::temp_dir().join(format!("gsio-node-{}.key", Uuid::new_v4()));
+    let old = config::load_or_create_key(&key_file).unwrap();
+    let p2p = Arc::new(P2PManager::new(
+        "test-node-1".to_string(),
+        SharedLedger::with_signing_key("test-node-1".to_string(), old.clone()),
+    ));
+    let admin = Arc::new(Admin::new(p2p.clone(), RuntimeConfig::default()).with_key_file(&key_file));
+    let url = start_admin(p2p.clone(), admin).await;
+    let http = reqwest::Client::new();
+
+    let response = http.post(format!("{url}/admin/key/rotate")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["old_key"], key_hex(&old));
+    assert_eq!(body["restart_required"], true);
+
+    // The key file now holds the new key, and the rotation is on the chain
+    let new = config::load_or_create_key(&key_file).unwrap();
+    assert_eq!(body["new_key"], key_hex(&new));
+    assert!(!key_file.with_extension("next").exists());
+    let entry = p2p.ledger.get_entry_by_id(body["entry_id"].as_str().unwrap()).unwrap();
+    assert_eq!(KeyRotation::from_data(&entry.data).unwrap().unwrap().new_key, key_hex(&new));
+    assert!(p2p.ledger.key_rotations().is_retired(&key_hex(&old)));
+
+    // The node can't write with the old key, nor rotate it again
+    let response = http.post(format!("{url}/api/ledger")).json(&json!({ "message": "Test entry" })).send().await.unwrap();
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["code"], "key_rotated");
+    let response = http.post(format!("{url}/admin/key/rotate")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert!(response.status().is_client_error());
+    assert_eq!(config::load_or_create_key(&key_file).unwrap(), new);
+    std::fs::remove_file(&key_file).ok();
+
+    // Without a key file there is nothing to rotate
+    let p2p = Arc::new(P2PManager::new("test-node-2".to_string(), SharedLedger::new("test-node-2".to_string())));
+    let url = start_admin(p2p.clone(), Arc::new(Admin::new(p2p, RuntimeConfig::default()))).await;
+    let response = http.post(format!("{url}/admin/key/rotate")).bearer_auth(ADMIN_KEY).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+use gsio_client::{GsioClient, GsioClientError, TypedEntry};
+use gsio_node::api::SchemaList;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::schema::{JsonSchema, SchemaConfig, SchemaError, SchemaRegistry};
+use gsio_node::validation::{ErrorCode, ValidationPolicy};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChatMessage {
+    author: String,
+    text: String,
+}
+
+fn chat_schema() -> JsonValue {
+    json!({
+        "title": "Chat message",
+        "type": "object",
+        "required": ["author", "text"],
+        "properties": {
+            "author": { "type": "string", "minLength": 1 },
+            "text": { "type": "string", "maxLength": 280 },
+        },
+        "additionalProperties": false,
+    })
+}
+
+fn code(policy: &dyn ValidationPolicy, data: JsonValue) -> Option<ErrorCode> {
+    policy.validate(&data).err().map(|e| e.code)
+}
+
+#[test]
+fn test_json_schema_subset() {
+    let schema = JsonSchema::parse(json!({
+        "type": "object",
+        "properties": {
+            "count": { "type": "integer", "minimum": 1, "exclusiveMaximum": 10 },
+            "tags": { "type": "array", "items": { "enum": ["a", "b"] }, "maxItems": 2 },
+            "note": { "type": ["string", "null"] },
+            "version": { "const": 1 },
+        },
+    }))
+    .unwrap();
+
+    assert!(schema.validate(&json!({ "count": 3, "tags": ["a"], "note": null, "version": 1 }), "payload").is_ok());
+    let error = |value: JsonValue| schema.validate(&value, "payload").unwrap_err();
+    assert_eq!(error(json!({ "count": 1.5 })), "payload.count must be of type integer");
+    assert_eq!(error(json!({ "count": 0 })), "payload.count must be at least 1");
+    assert_eq!(error(json!({ "count": 10 })), "payload.count must be less than 10");
+    assert_eq!(error(json!({ "tags": ["a", "c"] })), r#"payload.tags[1] must be one of ["a","b"]"#);
+    assert_eq!(error(json!({ "tags": ["a", "a", "b"] })), "payload.tags must have at most 2 items");
+    assert_eq!(error(json!({ "note": 5 })), "payload.note must be of type string or null");
+    assert_eq!(error(json!({ "version": 2 })), "payload.version must be 1");
+    assert_eq!(error(json!([])), "payload must be of type object");
+
+    // Keywords that aren't supported are refused rather than ignored
+    assert_eq!(
+        JsonSchema::parse(json!({ "properties": { "name": { "pattern": "^a" } } })).unwrap_err(),
+        "Unsupported keyword pattern in schema.properties.name"
+    );
+    assert!(JsonSchema::parse(json!({ "type": "date" })).is_err());
+    assert!(JsonSchema::parse(json!("object")).is_err());
+}
+
+#[test]
+fn test_registry_validates_payloads() {
+    let registry = SchemaRegistry::default();
+    assert_eq!(registry.register("chat_message", Some(chat_schema())), Ok(true));
+    assert_eq!(registry.register("note", None), Ok(true));
+
+    let message = json!({ "kind": "chat_message", "payload": { "author": "alice", "text": "hello" } });
+    assert_eq!(code(&registry, message), None);
+    assert_eq!(
+        code(&registry, json!({ "kind": "chat_message", "payload": { "author": "alice" } })),
+        Some(ErrorCode::SchemaMismatch)
+    );
+    assert_eq!(
+        code(&registry, json!({ "kind": "chat_message", "payload": { "author": "alice", "text": "hi", "extra": 1 } })),
+        Some(ErrorCode::SchemaMismatch)
+    );
+    assert_eq!(code(&registry, json!({ "kind": "chat_message" })), Some(ErrorCode::SchemaMismatch));
+    assert_eq!(code(&registry, json!({ "kind": "note", "payload": [1, 2] })), None);
+    assert_eq!(code(&registry, json!({ "kind": 5 })), Some(ErrorCode::InvalidFieldType));
+
+    // Kinds nobody registered and untyped entries are accepted by default
+    assert_eq!(code(&registry, json!({ "kind": "other", "payload": {} })), None);
+    assert_eq!(code(&registry, json!({ "message": "hello" })), None);
+
+    let strict = SchemaRegistry::new(true, false);
+    assert_eq!(code(&strict, json!({ "kind": "other", "payload": {} })), Some(ErrorCode::UnknownKind));
+    assert_eq!(code(&strict, json!({ "message": "hello" })), Some(ErrorCode::MissingField));
+
+    // A kind's schema can't change once registered
+    assert_eq!(registry.register("chat_message", Some(chat_schema())), Ok(false));
+    assert_eq!(
+        registry.register("chat_message", None),
+        Err(SchemaError::Conflict("chat_message".to_string()))
+    );
+    assert!(matches!(registry.register("chat message", None), Err(SchemaError::Invalid(_))));
+    assert!(matches!(registry.register("bad", Some(json!({ "oneOf": [] }))), Err(SchemaError::Invalid(_))));
+}
+
+#[test]
+fn test_registry_from_config() {
+    let schema_file = std::env::temp_dir().join(format!("gsio-schema-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&schema_file, chat_schema().to_string()).unwrap();
+    let config: SchemaConfig = toml::from_str(&format!(
+        r#"
+        require_kind = true
+
+        [kinds.chat_message]
+        schema_file = "{}"
+
+        [kinds.counter]
+        schema = {{ type = "integer", minimum = 0 }}
+
+        [kinds.note]
+        "#,
+        schema_file.display()
+    ))
+    .unwrap();
+    let registry = SchemaRegistry::from_config(&config).unwrap();
+    std::fs::remove_file(&schema_file).unwrap();
+
+    assert!(registry.requires_kind());
+    assert!(registry.allows_unknown_kinds());
+    assert_eq!(registry.get("chat_message"), Some(Some(chat_schema())));
+    assert_eq!(registry.get("note"), Some(None));
+    assert_eq!(registry.get("other"), None);
+    assert_eq!(code(&registry, json!({ "kind": "counter", "payload": -1 })), Some(ErrorCode::SchemaMismatch));
+
+    let both: SchemaConfig = toml::from_str(
+        r#"
+        [kinds.counter]
+        schema = { type = "integer" }
+        schema_file = "counter.json"
+        "#,
+    )
+    .unwrap();
+    assert!(SchemaRegistry::from_config(&both).is_err());
+}
+
+#[tokio::test]
+async fn test_typed_entries_over_api() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let url = start_server(p2p.clone()).await;
+    let client = GsioClient::new(&url).unwrap();
+
+    assert!(client.get_schema("chat_message").await.unwrap().is_none());
+    let registered = client.register_schema("chat_message", Some(chat_schema())).await.unwrap();
+    assert_eq!(registered.schema, Some(chat_schema()));
+    assert_eq!(client.get_schema("chat_message").await.unwrap(), Some(registered));
+    // Registering the same schema again is fine, a different one isn't
+    client.register_schema("chat_message", Some(chat_schema())).await.unwrap();
+    assert!(matches!(
+        client.register_schema("chat_message", None).await,
+        Err(GsioClientError::ServerError { .. })
+    ));
+
+    let message = ChatMessage { author: "alice".to_string(), text: "hello".to_string() };
+    let entry = client.add_typed_entry("chat_message", &message).await.unwrap();
+    assert_eq!((entry.kind.as_str(), &entry.payload), ("chat_message", &message));
+    client.add_ledger_entry(json!({ "message": "untyped" })).await.unwrap();
+
+    // The node refuses payloads that don't match the schema
+    let http = reqwest::Client::new();
+    let response = http
+        .post(format!("{url}/api/ledger"))
+        .json(&json!({ "kind": "chat_message", "payload": { "author": "" , "text": "hello" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["code"], "schema_mismatch");
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    let entries: Vec<TypedEntry<ChatMessage>> = client.get_typed_entries("chat_message").await.unwrap();
+    assert_eq!(entries, vec![entry]);
+
+    let list: SchemaList = http.get(format!("{url}/api/schemas")).send().await.unwrap().json().await.unwrap();
+    assert!(list.allow_unknown_kinds);
+    assert_eq!(list.kinds.keys().collect::<Vec<_>>(), vec!["chat_message"]);
+
+    let response = http
+        .put(format!("{url}/api/schemas/other"))
+        .json(&json!({ "schema": { "type": "object", "if": {} } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,55 @@
+#![cfg(unix)]
+
+use gsio_node::service;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+// The service manager is configured through process-wide environment
+// variables, so everything runs in a single test to avoid races.
+#[test]
+fn test_sd_notify_protocol() {
+    // Without a notify socket there is nobody to tell
+    unsafe {
+        std::env::remove_var("NOTIFY_SOCKET");
+        std::env::remove_var("WATCHDOG_USEC");
+        std::env::remove_var("WATCHDOG_PID");
+    }
+    assert!(!service::notify_ready().unwrap());
+    assert!(service::watchdog_interval().is_none());
+
+    // Bind a socket to stand in for systemd
+    let socket_path = std::env::temp_dir().join(format!("gsio-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+    let manager = UnixDatagram::bind(&socket_path).unwrap();
+    manager.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    unsafe {
+        std::env::set_var("NOTIFY_SOCKET", &socket_path);
+    }
+
+    let mut buf = [0u8; 256];
+
+    // Readiness
+    assert!(service::notify_ready().unwrap());
+    let len = manager.recv(&mut buf).unwrap();
+    let message = std::str::from_utf8(&buf[..len]).unwrap();
+    assert!(message.lines().any(|line| line == "READY=1"));
+
+    // Watchdog pings
+    assert!(service::notify_watchdog().unwrap());
+    let len = manager.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+    // Watchdog interval is half the configured timeout
+    unsafe {
+        std::env::set_var("WATCHDOG_USEC", "10000000");
+    }
+    assert_eq!(service::watchdog_interval(), Some(Duration::from_secs(5)));
+
+    // A watchdog meant for another process is ignored
+    unsafe {
+        std::env::set_var("WATCHDOG_PID", (std::process::id() + 1).to_string());
+    }
+    assert!(service::watchdog_interval().is_none());
+
+    let _ = std::fs::remove_file(&socket_path);
+}
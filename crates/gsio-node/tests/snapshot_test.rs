@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use gsio_node::consensus::{ConsensusConfig, Validator};
+use gsio_node::ledger::{Ledger, SharedLedger, Snapshot};
+use gsio_node::p2p::P2PManager;
+use rand::rngs::OsRng;
+use serde_json::json;
+
+mod common;
+
+use common::start_server;
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// The key `ledger` signs its snapshots with
+fn signer(ledger: &Ledger) -> VerifyingKey {
+    VerifyingKey::from_bytes(&hex::decode(ledger.public_key()).unwrap().try_into().unwrap()).unwrap()
+}
+
+fn ledger_with_entries(node_id: &str, count: usize) -> Ledger {
+    let mut ledger = Ledger::new(node_id.to_string());
+    for i in 1..=count {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    ledger
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let source = ledger_with_entries("test-node-1", 5);
+    let snapshot = source.create_snapshot(3).unwrap();
+    assert_eq!(snapshot.height, 3);
+    assert_eq!(snapshot.checkpoint.id, source.get_entries()[2].id);
+    assert!(snapshot.verify(&signer(&source)).is_ok());
+    assert!(snapshot.consensus.is_none());
+
+    // The snapshot survives being stored as a blob
+    let bytes = snapshot.to_bytes();
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+
+    // A node that doesn't trust the source won't start from it
+    let mut ledger = Ledger::new("test-node-2".to_string());
+    assert!(ledger.restore_from_snapshot(&snapshot).is_err());
+
+    // One that does starts from the checkpoint with the history before it counted as pruned
+    ledger.set_checkpoint_keys(&[source.public_key()]).unwrap();
+    ledger.restore_from_snapshot(&snapshot).unwrap();
+    assert_eq!(ledger.height(), 3);
+    assert_eq!(ledger.get_entries().len(), 1);
+    assert_eq!(ledger.get_retention_info().pruned_entries, 2);
+    assert_eq!(ledger.get_node_key("test-node-1"), Some(source.public_key()));
+    assert!(ledger.get_known_nodes().contains("test-node-1"));
+
+    // Later entries link on from the checkpoint
+    for entry in source.get_entries_after(&snapshot.checkpoint.hash).unwrap() {
+        ledger.add_pending_entry(entry.clone());
+    }
+    assert_eq!(ledger.process_pending_entries().len(), 2);
+    assert_eq!(ledger.height(), 5);
+    assert_eq!(ledger.get_last_entry().unwrap().hash, source.get_last_entry().unwrap().hash);
+    let entry = ledger.add_entry(json!({ "message": "Test entry 6" })).unwrap();
+    assert_eq!(entry.previous_hash, source.get_last_entry().unwrap().hash);
+}
+
+#[test]
+fn test_invalid_snapshots_are_rejected() {
+    let source = ledger_with_entries("test-node-1", 3);
+    assert!(source.create_snapshot(0).is_err());
+    assert!(source.create_snapshot(4).is_err());
+    assert!(Ledger::new("test-node-1".to_string()).create_snapshot(1).is_err());
+    let snapshot = source.create_snapshot(2).unwrap();
+
+    // Changing any field breaks the hash
+    let key = signer(&source);
+    let mut altered = snapshot.clone();
+    altered.height = 1;
+    assert!(altered.verify(&key).is_err());
+    let mut altered = snapshot.clone();
+    altered.checkpoint.data = json!({ "message": "Forged entry" });
+    assert!(altered.verify(&key).is_err());
+
+    // Recomputing the hash breaks the signature
+    let mut altered = snapshot.clone();
+    altered.node_keys.insert("test-node-1".to_string(), SharedLedger::new("test-node-9".to_string()).public_key());
+    altered.hash = altered.calculate_hash();
+    assert!(altered.verify(&key).is_err());
+    assert!(Snapshot::from_bytes(b"not a snapshot").is_err());
+
+    // And signing it again takes a key the restoring node doesn't trust
+    let forger = Ledger::new("test-node-9".to_string());
+    altered.signer = forger.public_key();
+    altered.signature = forger.sign_message(altered.hash.as_bytes());
+    let mut ledger = Ledger::new("test-node-2".to_string());
+    ledger.set_checkpoint_keys(&[source.public_key()]).unwrap();
+    assert!(ledger.restore_from_snapshot(&altered).is_err());
+    assert!(ledger.set_checkpoint_keys(&["not a key".to_string()]).is_err());
+
+    // Only an empty ledger can be restored
+    let mut ledger = ledger_with_entries("test-node-2", 1);
+    ledger.set_checkpoint_keys(&[source.public_key()]).unwrap();
+    assert!(ledger.restore_from_snapshot(&snapshot).is_err());
+    assert_eq!(ledger.height(), 1);
+}
+
+#[test]
+fn test_poa_snapshot_carries_validators() {
+    let key = SigningKey::generate(&mut OsRng);
+    let validator = |node_id: &str, key: &SigningKey| Validator {
+        node_id: node_id.to_string(),
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+    };
+    let config = ConsensusConfig { validators: vec![validator("test-node-1", &key)], ..ConsensusConfig::default() };
+    let mut source = Ledger::with_signing_key("test-node-1".to_string(), key.clone());
+    source.set_consensus(config.build().unwrap());
+    source.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    source.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    // The validator set is only known at the tip
+    assert!(source.create_snapshot(1).is_err());
+    let snapshot = source.create_snapshot(2).unwrap();
+    assert!(snapshot.consensus.is_some());
+
+    // A validator's snapshot is trusted without listing its key
+    let mut ledger = Ledger::new("test-node-4".to_string());
+    ledger.set_consensus(config.build().unwrap());
+    ledger.restore_from_snapshot(&snapshot).unwrap();
+
+    // A node configured with an outdated set picks up the one from the snapshot, if it trusts the key
+    let outdated = ConsensusConfig {
+        validators: vec![validator("test-node-9", &SigningKey::generate(&mut OsRng))],
+        ..ConsensusConfig::default()
+    };
+    let mut ledger = Ledger::new("test-node-2".to_string());
+    ledger.set_consensus(outdated.build().unwrap());
+    assert!(ledger.restore_from_snapshot(&snapshot).is_err());
+    ledger.set_checkpoint_keys(&[source.public_key()]).unwrap();
+    ledger.restore_from_snapshot(&snapshot).unwrap();
+    assert_eq!(ledger.validators().unwrap().node_ids(), vec!["test-node-1".to_string()]);
+
+    // Longest chain keeps no state, so it can't take the snapshot
+    let mut ledger = Ledger::new("test-node-3".to_string());
+    ledger.set_checkpoint_keys(&[source.public_key()]).unwrap();
+    assert!(ledger.restore_from_snapshot(&snapshot).is_err());
+}
+
+#[tokio::test]
+async fn test_fast_sync_from_snapshot() {
+    let source = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())));
+    for i in 1..=3 {
+        source.add_local_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    let url = start_server(source.clone()).await;
+
+    // Fetch a snapshot of the tip over the API
+    let response = reqwest::get(format!("{url}/api/ledger/snapshot")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let snapshot: Snapshot = response.json().await.unwrap();
+    assert_eq!(snapshot.height, 3);
+    let response = reqwest::get(format!("{url}/api/ledger/snapshot?height=9")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // The source moves on before the new node joins
+    let latest = source.add_local_entry(json!({ "message": "Test entry 4" })).unwrap();
+
+    let ledger = SharedLedger::new("test-node-2".to_string());
+    ledger.set_checkpoint_keys(&[source.ledger.public_key()]).unwrap();
+    ledger.restore_from_snapshot(&snapshot).unwrap();
+    let node = Arc::new(P2PManager::new("test-node-2".to_string(), ledger));
+    node.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+
+    // Only the entry after the checkpoint is synced
+    wait_for(Duration::from_secs(5), || node.ledger.get_entry_by_id(&latest.id).is_some()).await;
+    assert_eq!(node.ledger.get_entries().len(), 2);
+    assert_eq!(node.ledger.get_retention_info().pruned_entries, 2);
+
+    node.leave().await;
+}
+
+#[tokio::test]
+async fn test_snapshot_of_empty_ledger() {
+    let p2p = Arc::new(P2PManager::new("test-node-1".to_string(), SharedLedger::new("test-node-1".to_string())));
+    let url = start_server(p2p).await;
+
+    let response = reqwest::get(format!("{url}/api/ledger/snapshot")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
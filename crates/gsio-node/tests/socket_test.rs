@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use axum::Router;
+use std::time::Duration;
+use futures::StreamExt;
+use gsio_client::{ErrorCode, GsioClient, GsioClientError, GsioSocketClient};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{NodeMode, P2PManager};
+use gsio_node::socket;
+use serde_json::json;
+use socketioxide::SocketIo;
+use tokio::net::TcpListener;
+
+async fn start_server(p2p: Arc<P2PManager>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (layer, io) = SocketIo::builder().build_layer();
+    socket::register_root_namespace(&io, p2p, None);
+    let app: Router = Router::new().layer(layer);
+
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_socket_client_round_trip() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id.clone())));
+    let client = GsioSocketClient::connect(&start_server(p2p.clone()).await).await.unwrap();
+
+    // Add entries through the Socket.IO events
+    let data = json!({ "message": "Test entry 1" });
+    let entry = client.add_ledger_entry(data.clone()).await.unwrap();
+    assert_eq!(entry.data, data);
+    assert_eq!(entry.creator_node_id, node_id);
+    client.add_ledger_entry(json!({ "message": "Test entry 2" })).await.unwrap();
+
+    // Read them back
+    let entries = client.get_ledger().await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, entry.id);
+    assert_eq!(p2p.ledger.get_entries().len(), 2);
+
+    // Known nodes include this node
+    let nodes = client.get_known_nodes().await.unwrap();
+    assert_eq!(nodes, vec![node_id]);
+
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_socket_client_surfaces_errors() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(
+        P2PManager::new(node_id.clone(), SharedLedger::new(node_id))
+            .with_mode(NodeMode::Follower { writable_node: None }),
+    );
+    let client = GsioSocketClient::connect(&start_server(p2p.clone()).await).await.unwrap();
+
+    let result = client.add_ledger_entry(json!({ "message": "rejected" })).await;
+    assert!(matches!(result, Err(GsioClientError::ServerError { code: Some(ErrorCode::ReadOnly), .. })));
+    assert!(p2p.ledger.get_entries().is_empty());
+
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_socket_client_range_queries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioSocketClient::connect(&start_server(p2p.clone()).await).await.unwrap();
+
+    for i in 0..3 {
+        p2p.ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    let entries = p2p.ledger.get_entries();
+
+    let page = client.get_ledger_paginated(1, 1).await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, entries[1].id);
+
+    let newer = client.get_entries_since(entries[0].timestamp).await.unwrap();
+    assert_eq!(newer.len(), 2);
+
+    let entry = client.get_entry_by_id(&entries[2].id).await.unwrap().unwrap();
+    assert_eq!(entry.hash, entries[2].hash);
+    assert!(client.get_entry_by_id("missing").await.unwrap().is_none());
+
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_subscribe_entries() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let url = start_server(p2p.clone()).await;
+    let client = GsioClient::new(&url).unwrap();
+
+    let mut first = client.subscribe_entries().await.unwrap();
+    let mut second = client.subscribe_entries().await.unwrap();
+
+    // New entries reach every subscriber, in order
+    let entry = p2p.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    p2p.ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+
+    for subscription in [&mut first, &mut second] {
+        let received: Vec<_> = tokio::time::timeout(Duration::from_secs(5), subscription.by_ref().take(2).collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(received[0].id, entry.id);
+        assert_eq!(received[1].data, json!({ "message": "Test entry 2" }));
+    }
+
+    // Closing one subscription leaves the other running
+    first.close().await.unwrap();
+    p2p.add_local_entry(json!({ "message": "Test entry 3" })).unwrap();
+    let next = tokio::time::timeout(Duration::from_secs(5), second.next()).await.unwrap().unwrap();
+    assert_eq!(next.data, json!({ "message": "Test entry 3" }));
+}
+
+#[tokio::test]
+async fn test_subscribe_entries_matching() {
+    let node_id = "test-node-1".to_string();
+    let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)));
+    let client = GsioClient::new(&start_server(p2p.clone()).await).unwrap();
+
+    let fields = json!({ "kind": "sensor" }).as_object().unwrap().clone();
+    let mut sensors = client.subscribe_entries_matching(fields).await.unwrap();
+
+    // Only entries with a matching field are sent
+    p2p.ledger.add_entry(json!({ "kind": "transfer", "amount": 5 })).unwrap();
+    p2p.ledger.add_entry(json!({ "message": "no kind" })).unwrap();
+    let entry = p2p.ledger.add_entry(json!({ "kind": "sensor", "reading": 21.5 })).unwrap();
+
+    let next = tokio::time::timeout(Duration::from_secs(5), sensors.next()).await.unwrap().unwrap();
+    assert_eq!(next.id, entry.id);
+    assert_eq!(next.data, json!({ "kind": "sensor", "reading": 21.5 }));
+}
+
+#[tokio::test]
+async fn test_subscribe_entries_unreachable_node() {
+    let client = GsioClient::new("http://127.0.0.1:1").unwrap();
+    let result = client.subscribe_entries().await;
+    assert!(matches!(result, Err(GsioClientError::ConnectionError(_))));
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use chrono::Utc;
+use ed25519_dalek::{Signer, SigningKey};
+use gsio_client::{Address, GsioClient};
+use gsio_node::ledger::{Ledger, SharedLedger};
+use gsio_node::p2p::P2PManager;
+use gsio_node::staking::{StakingConfig, Transaction, TransactionType};
+use gsio_node::validation::ErrorCode;
+use gsio_types::TransactionStatus;
+use rand::rngs::OsRng;
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+fn address(key: &SigningKey) -> String {
+    Address::from_public_key(&key.verifying_key().to_bytes()).to_string()
+}
+
+/// Entry data recording a transaction from `key`'s address, signed with `key`
+fn transaction(key: &SigningKey, transaction_type: TransactionType, recipient: &str, amount: u64, nonce: u64) -> JsonValue {
+    let mut transaction = Transaction {
+        id: Uuid::new_v4().to_string(),
+        transaction_type,
+        amount,
+        fee: 1,
+        nonce,
+        sender: address(key),
+        recipient: recipient.to_string(),
+        timestamp: Utc::now(),
+        status: TransactionStatus::Pending,
+        signature: None,
+        data: None,
+    };
+    transaction.signature = Some(hex::encode(key.sign(&transaction.signing_bytes()).to_bytes()));
+    json!({
+        "type": "transaction",
+        "transaction": transaction,
+        "public_key": hex::encode(key.verifying_key().to_bytes()),
+    })
+}
+
+/// A ledger where `key`'s address was sent 1000 by another account
+fn funded_ledger(key: &SigningKey) -> Ledger {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    ledger.set_staking_config(StakingConfig { unbonding_period: 2 });
+    let faucet = SigningKey::generate(&mut OsRng);
+    ledger.add_entry(transaction(&faucet, TransactionType::Transfer, &address(key), 1_000, 0)).unwrap();
+    ledger
+}
+
+#[test]
+fn test_stake_and_unstake() {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut ledger = funded_ledger(&key);
+    let me = address(&key);
+    let info = ledger.stake(&me).unwrap();
+    assert_eq!((info.balance, info.staked), (1_000, 0));
+
+    // Staking can't go beyond the balance, fee included
+    let error = ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 1_000, 0)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::InsufficientFunds);
+    ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 600, 0)).unwrap();
+    let info = ledger.stake(&me).unwrap();
+    assert_eq!((info.balance, info.staked), (399, 600));
+
+    // Nonces are used once, and only the sender can be the recipient
+    let error = ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 10, 0)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::DoubleSpend);
+    let other = address(&SigningKey::generate(&mut OsRng));
+    let error = ledger.add_entry(transaction(&key, TransactionType::Stake, &other, 10, 1)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::Rejected);
+
+    // Only what is staked can be unstaked
+    let error = ledger.add_entry(transaction(&key, TransactionType::Unstake, &me, 601, 1)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::InsufficientFunds);
+    ledger.add_entry(transaction(&key, TransactionType::Unstake, &me, 600, 1)).unwrap();
+    let info = ledger.stake(&me).unwrap();
+    assert_eq!((info.balance, info.staked, info.unbonding_total()), (398, 0, 600));
+    assert_eq!(info.unbonding[0].release_height, ledger.height() + 2);
+
+    // Unbonding amounts return to the balance once the period is over
+    ledger.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    assert_eq!(ledger.stake(&me).unwrap().unbonding_total(), 600);
+    ledger.add_entry(json!({ "message": "Test entry 2" })).unwrap();
+    let info = ledger.stake(&me).unwrap();
+    assert_eq!((info.balance, info.unbonding_total()), (998, 0));
+}
+
+#[test]
+fn test_staking_requires_sender_signature() {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut ledger = funded_ledger(&key);
+    let me = address(&key);
+
+    let mut unsigned = transaction(&key, TransactionType::Stake, &me, 100, 0);
+    unsigned["transaction"]["signature"] = JsonValue::Null;
+    assert_eq!(ledger.add_entry(unsigned).unwrap_err().code, ErrorCode::MissingSignature);
+
+    // Someone else's key doesn't do, even for a transaction it signed
+    let mut forged = transaction(&key, TransactionType::Stake, &me, 100, 0);
+    forged["public_key"] = json!(hex::encode(SigningKey::generate(&mut OsRng).verifying_key().to_bytes()));
+    assert_eq!(ledger.add_entry(forged).unwrap_err().code, ErrorCode::InvalidSignature);
+
+    let mut tampered = transaction(&key, TransactionType::Stake, &me, 100, 0);
+    tampered["transaction"]["amount"] = json!(200);
+    assert_eq!(ledger.add_entry(tampered).unwrap_err().code, ErrorCode::InvalidSignature);
+    assert_eq!(ledger.stake(&me).unwrap().staked, 0);
+}
+
+#[test]
+fn test_double_spend_is_refused() {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut ledger = funded_ledger(&key);
+    let me = address(&key);
+    let (alice, bob) = (address(&SigningKey::generate(&mut OsRng)), address(&SigningKey::generate(&mut OsRng)));
+
+    ledger.add_entry(transaction(&key, TransactionType::Transfer, &alice, 600, 0)).unwrap();
+    assert!(ledger.is_spent(&me, 0));
+    assert!(!ledger.is_spent(&me, 1));
+
+    // The same nonce can't pay someone else, nor be staked
+    let error = ledger.add_entry(transaction(&key, TransactionType::Transfer, &bob, 600, 0)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::DoubleSpend);
+    let error = ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 100, 0)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::DoubleSpend);
+    ledger.add_entry(transaction(&key, TransactionType::Transfer, &bob, 100, 1)).unwrap();
+    assert_eq!(ledger.stake(&bob).unwrap().balance, 100);
+
+    // Nonces spent before a snapshot stay spent
+    let snapshot = ledger.create_snapshot(ledger.height()).unwrap();
+    let mut restored = Ledger::new("test-node-2".to_string());
+    restored.set_checkpoint_keys(&[ledger.public_key()]).unwrap();
+    restored.restore_from_snapshot(&snapshot).unwrap();
+    let error = restored.add_entry(transaction(&key, TransactionType::Transfer, &bob, 100, 1)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::DoubleSpend);
+}
+
+#[test]
+fn test_snapshot_carries_stakes() {
+    let key = SigningKey::generate(&mut OsRng);
+    let mut ledger = funded_ledger(&key);
+    let me = address(&key);
+    ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 600, 0)).unwrap();
+
+    let snapshot = ledger.create_snapshot(ledger.height()).unwrap();
+    assert!(!snapshot.stakes.is_empty());
+    let mut restored = Ledger::new("test-node-2".to_string());
+    restored.set_checkpoint_keys(&[ledger.public_key()]).unwrap();
+    restored.restore_from_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.stake(&me).unwrap(), ledger.stake(&me).unwrap());
+    let error = restored.add_entry(transaction(&key, TransactionType::Stake, &me, 600, 1)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::InsufficientFunds);
+
+    // Snapshots without transactions hash as before
+    let mut unstaked = Ledger::new("test-node-3".to_string());
+    unstaked.add_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let snapshot = unstaked.create_snapshot(1).unwrap();
+    assert!(!serde_json::to_value(&snapshot).unwrap().as_object().unwrap().contains_key("stakes"));
+}
+
+#[tokio::test]
+async fn test_stake_api() {
+    let key = SigningKey::generate(&mut OsRng);
+    let me = address(&key);
+    let ledger = SharedLedger::new("test-node-1".to_string());
+    let faucet = SigningKey::generate(&mut OsRng);
+    ledger.add_entry(transaction(&faucet, TransactionType::Transfer, &me, 1_000, 0)).unwrap();
+    ledger.add_entry(transaction(&key, TransactionType::Stake, &me, 600, 0)).unwrap();
+    let p2p = Arc::new(P2PManager::new("test-node-1".to_string(), ledger));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, gsio_node::api::router(p2p)).await.unwrap();
+    });
+
+    let client = GsioClient::new(&url).unwrap();
+    let info = client.get_stake(&me).await.unwrap();
+    assert_eq!((info.address, info.balance, info.staked), (me, 399, 600));
+    assert!(client.get_stake("not-an-address").await.is_err());
+}
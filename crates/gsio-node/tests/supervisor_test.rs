@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::supervisor::{RestartPolicy, Supervisor, TaskState, Tasks};
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+fn supervisor() -> Supervisor {
+    Supervisor::new("test").with_restart_delay(Duration::from_millis(10), Duration::from_millis(40))
+}
+
+#[tokio::test]
+async fn test_panicking_task_is_restarted() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let supervisor = supervisor().spawn(move || {
+        let runs = counted.clone();
+        async move {
+            // The first two runs crash, the third finishes
+            if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                panic!("Test panic");
+            }
+        }
+    });
+
+    tokio::time::timeout(Duration::from_secs(5), supervisor).await.unwrap().unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_finished_task_is_not_restarted() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let supervisor = supervisor().spawn(move || {
+        let runs = counted.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    supervisor.await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_aborting_supervisor_stops_task() {
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let counted = ticks.clone();
+    let supervisor = supervisor().spawn(move || {
+        let ticks = counted.clone();
+        async move {
+            loop {
+                ticks.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    });
+
+    wait_for(Duration::from_secs(5), || ticks.load(Ordering::SeqCst) > 0).await;
+    supervisor.abort();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let stopped_at = ticks.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+}
+
+#[tokio::test]
+async fn test_aborting_registry_stops_its_tasks() {
+    let tasks = Tasks::new();
+    let ticks = Arc::new(AtomicUsize::new(0));
+    for name in ["test-1", "test-2"] {
+        let counted = ticks.clone();
+        Supervisor::new(name).with_policy(RestartPolicy::Always).with_tasks(&tasks).spawn(move || {
+            let ticks = counted.clone();
+            async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        });
+    }
+
+    wait_for(Duration::from_secs(5), || ticks.load(Ordering::SeqCst) > 1).await;
+    tasks.abort_all();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let stopped_at = ticks.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+}
+
+#[tokio::test]
+async fn test_returning_task_is_restarted_under_always() {
+    let tasks = Tasks::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let supervisor = supervisor().with_policy(RestartPolicy::Always).with_tasks(&tasks).spawn(move || {
+        let runs = counted.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    wait_for(Duration::from_secs(5), || runs.load(Ordering::SeqCst) >= 3).await;
+    supervisor.abort();
+    let task = &tasks.report()["test"];
+    assert!(task.restarts >= 2);
+    assert!(task.last_panic.is_none());
+    assert!(!tasks.any_failed());
+}
+
+#[tokio::test]
+async fn test_panicking_task_fails_under_never() {
+    let tasks = Tasks::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let supervisor = supervisor().with_policy(RestartPolicy::Never).with_tasks(&tasks).spawn(move || {
+        let runs = counted.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            panic!("Test panic");
+        }
+    });
+
+    supervisor.await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    let task = &tasks.report()["test"];
+    assert_eq!(task.state, TaskState::Failed);
+    assert_eq!(task.restarts, 0);
+    assert_eq!(task.last_panic.as_deref(), Some("Test panic"));
+    assert!(task.last_panic_at.is_some());
+    assert!(tasks.any_failed());
+}
+
+#[tokio::test]
+async fn test_restarts_are_reported() {
+    let tasks = Tasks::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let supervisor = supervisor().with_tasks(&tasks).spawn(move || {
+        let runs = counted.clone();
+        async move {
+            let run = runs.fetch_add(1, Ordering::SeqCst);
+            if run < 2 {
+                panic!("Test panic {run}");
+            }
+        }
+    });
+
+    supervisor.await.unwrap();
+    let task = &tasks.report()["test"];
+    assert_eq!(task.state, TaskState::Finished);
+    assert_eq!(task.restarts, 2);
+    assert_eq!(task.last_panic.as_deref(), Some("Test panic 1"));
+    assert!(!tasks.any_failed());
+}
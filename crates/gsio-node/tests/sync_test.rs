@@ -0,0 +1,295 @@
+use std::sync::Arc;
+use std::time::Duration;
+use gsio_node::ledger::{ChainTip, Ledger, RetentionPolicy, SharedLedger, SyncPage, GENESIS_HASH};
+use gsio_node::p2p::{MessageType, P2PManager, P2PMessage, MAX_SYNC_PAGE_SIZE};
+use gsio_node::sync::{self, Segment, SyncCoordinator};
+use serde_json::{json, Value as JsonValue};
+
+mod common;
+
+use common::start_server;
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+fn shared_ledger_with_entries(node_id: &str, count: usize) -> SharedLedger {
+    let ledger = SharedLedger::new(node_id.to_string());
+    for i in 1..=count {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+    ledger
+}
+
+/// A ledger for `node_id` holding copies of the first `count` entries of `source`, which node `source_id` created
+fn copy_of(node_id: &str, source_id: &str, source: &SharedLedger, count: usize) -> SharedLedger {
+    let ledger = SharedLedger::new(node_id.to_string());
+    ledger.add_node_key(source_id.to_string(), &source.public_key()).unwrap();
+    for entry in source.get_entries().into_iter().take(count) {
+        ledger.add_pending_entry(entry);
+    }
+    ledger.process_pending_entries();
+    ledger
+}
+
+fn sync_request(payload: JsonValue) -> P2PMessage {
+    P2PMessage::new(MessageType::LedgerSyncRequest, "test-node-2".to_string(), "test-node-1".to_string(), payload)
+}
+
+#[test]
+fn test_locator_finds_common_ancestor() {
+    let mut a = Ledger::new("test-node-1".to_string());
+    for i in 1..=40 {
+        a.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    // The locator starts at the tip and thins out further back
+    let locator = a.sync_locator();
+    assert_eq!(locator[0], a.get_last_entry().unwrap().hash);
+    assert!(locator.len() < 20);
+    assert_eq!(a.common_ancestor(&locator), 40);
+
+    // A node that shares the first 25 entries and then went its own way
+    let mut b = Ledger::new("test-node-2".to_string());
+    b.add_node_key("test-node-1".to_string(), &a.public_key()).unwrap();
+    for entry in &a.get_entries()[..25] {
+        b.add_pending_entry(entry.clone());
+    }
+    b.process_pending_entries();
+    for i in 1..=30 {
+        b.add_entry(json!({ "message": format!("Other entry {i}") })).unwrap();
+    }
+    let ancestor = a.common_ancestor(&b.sync_locator());
+    assert!(ancestor <= 25 && ancestor > 10, "ancestor {ancestor} should be close below the fork");
+    assert_eq!(a.common_ancestor(&["unknown".to_string()]), 0);
+}
+
+#[test]
+fn test_sync_pages() {
+    let mut ledger = Ledger::new("test-node-1".to_string());
+    for i in 1..=10 {
+        ledger.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    let page = ledger.sync_page(3, 4);
+    assert_eq!((page.from, page.height, page.entries.len()), (3, 10, 4));
+    assert_eq!(page.entries[0].id, ledger.get_entries()[2].id);
+    assert!(ledger.sync_page(11, 4).entries.is_empty());
+
+    // Pruned history is skipped
+    ledger.set_retention_policy(RetentionPolicy::KeepLast { entries: 5 });
+    ledger.apply_retention();
+    let page = ledger.sync_page(1, 4);
+    assert_eq!((page.from, page.height), (6, 10));
+    assert_eq!(page.entries[0].id, ledger.get_entries()[0].id);
+}
+
+#[test]
+fn test_missing_entries_from_tip() {
+    let mut a = Ledger::new("test-node-1".to_string());
+    assert_eq!(a.chain_tip(), ChainTip { hash: GENESIS_HASH.to_string(), height: 0 });
+    for i in 1..=5 {
+        a.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap();
+    }
+
+    // An empty peer needs everything, one that is up to date nothing
+    let empty = Ledger::new("test-node-2".to_string()).chain_tip();
+    assert_eq!(a.missing_entries(&empty).len(), 5);
+    assert!(a.missing_entries(&a.chain_tip()).is_empty());
+
+    // A peer part of the way along only gets the entries after its tip
+    let behind = ChainTip { hash: a.get_entries()[2].hash.clone(), height: 3 };
+    let missing = a.missing_entries(&behind);
+    assert_eq!(missing.len(), 2);
+    assert_eq!(missing[0].previous_hash, behind.hash);
+
+    // A peer on a shorter branch gets the whole chain to compare against, one on a longer branch nothing
+    let forked = ChainTip { hash: "unknown".to_string(), height: 4 };
+    assert_eq!(a.missing_entries(&forked).len(), 5);
+    let ahead = ChainTip { hash: "unknown".to_string(), height: 6 };
+    assert!(a.missing_entries(&ahead).is_empty());
+}
+
+#[test]
+fn test_sync_request_pages() {
+    let node = P2PManager::new("test-node-1".to_string(), shared_ledger_with_entries("test-node-1", MAX_SYNC_PAGE_SIZE + 10));
+
+    // A peer that asks for too much gets at most a full page
+    let reply = node.handle_message(sync_request(json!({ "from": 1, "limit": 10_000 }))).unwrap();
+    assert!(matches!(reply.message_type, MessageType::LedgerSyncResponse));
+    let page: SyncPage = serde_json::from_value(reply.payload).unwrap();
+    assert_eq!(page.entries.len(), MAX_SYNC_PAGE_SIZE);
+    assert_eq!(page.height, MAX_SYNC_PAGE_SIZE + 10);
+
+    // A locator picks up after the newest entry both chains share
+    let shared = node.ledger.get_entries()[99].hash.clone();
+    let reply = node.handle_message(sync_request(json!({ "locator": ["unknown", shared], "limit": 5 }))).unwrap();
+    let page: SyncPage = serde_json::from_value(reply.payload).unwrap();
+    assert_eq!((page.from, page.entries.len()), (101, 5));
+
+    // Without either, the sync starts from the beginning
+    let reply = node.handle_message(sync_request(json!({}))).unwrap();
+    let page: SyncPage = serde_json::from_value(reply.payload).unwrap();
+    assert_eq!(page.from, 1);
+}
+
+#[test]
+fn test_sync_response_asks_for_next_page() {
+    let source = shared_ledger_with_entries("test-node-2", 25);
+    let ledger = SharedLedger::new("test-node-1".to_string());
+    ledger.add_node_key("test-node-2".to_string(), &source.public_key()).unwrap();
+    let node = P2PManager::new("test-node-1".to_string(), ledger).with_sync_page_size(10);
+    let response = |from: usize| {
+        P2PMessage::new(
+            MessageType::LedgerSyncResponse,
+            "test-node-2".to_string(),
+            "test-node-1".to_string(),
+            serde_json::to_value(source.sync_page(from, 10)).unwrap(),
+        )
+    };
+
+    // Each page is added before the next one is requested
+    let reply = node.handle_message(response(1)).unwrap();
+    assert!(matches!(reply.message_type, MessageType::LedgerSyncRequest));
+    assert_eq!(reply.payload, json!({ "from": 11, "limit": 10 }));
+    assert_eq!(node.ledger.get_entries().len(), 10);
+
+    let reply = node.handle_message(response(11)).unwrap();
+    assert_eq!(reply.payload["from"], 21);
+
+    // The page that reaches the peer's height ends the sync
+    assert!(node.handle_message(response(21)).is_none());
+    assert_eq!(node.ledger.get_entries().len(), 25);
+}
+
+#[tokio::test]
+async fn test_paged_sync_between_nodes() {
+    let source = Arc::new(P2PManager::new("test-node-1".to_string(), shared_ledger_with_entries("test-node-1", 45)));
+    let url = start_server(source.clone()).await;
+
+    let node = Arc::new(
+        P2PManager::new("test-node-2".to_string(), SharedLedger::new("test-node-2".to_string())).with_sync_page_size(10),
+    );
+    node.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+
+    wait_for(Duration::from_secs(5), || node.ledger.get_entries().len() == 45).await;
+    assert_eq!(node.ledger.get_last_entry().unwrap().hash, source.ledger.get_last_entry().unwrap().hash);
+
+    node.leave().await;
+}
+
+#[test]
+fn test_split_segments() {
+    let segment = |from, to| Segment { from, to };
+    assert_eq!(sync::split(1, 25, 10), vec![segment(1, 10), segment(11, 20), segment(21, 25)]);
+    assert_eq!(sync::split(5, 5, 10), vec![segment(5, 5)]);
+    assert!(sync::split(6, 5, 10).is_empty());
+}
+
+#[test]
+fn test_skeleton_page() {
+    let ledger = shared_ledger_with_entries("test-node-1", 45);
+    let entries = ledger.get_entries();
+    let page = ledger.sync_skeleton_page(1, 1, 10, 1000);
+    assert_eq!(page.entries.len(), 1);
+    let ends: Vec<String> = [9, 19, 29, 39, 44].iter().map(|&i| entries[i].hash.clone()).collect();
+    assert_eq!(page.skeleton, ends);
+
+    // Long chains are covered over several skeletons, and a node that is up to date gets none
+    assert_eq!(ledger.sync_skeleton_page(1, 1, 10, 2).skeleton, ends[..2]);
+    assert_eq!(ledger.sync_skeleton_page(41, 1, 10, 1000).skeleton, vec![entries[44].hash.clone()]);
+    assert!(ledger.sync_skeleton_page(46, 1, 10, 1000).skeleton.is_empty());
+
+    // Only peers asking for a skeleton get one
+    let node = P2PManager::new("test-node-1".to_string(), ledger);
+    let reply = node.handle_message(sync_request(json!({ "from": 1, "limit": 1, "skeleton": 20 }))).unwrap();
+    let page: SyncPage = serde_json::from_value(reply.payload).unwrap();
+    assert_eq!(page.skeleton, vec![entries[19].hash.clone(), entries[39].hash.clone(), entries[44].hash.clone()]);
+    let reply = node.handle_message(sync_request(json!({ "from": 1, "limit": 1 }))).unwrap();
+    assert!(reply.payload.get("skeleton").is_none());
+}
+
+#[test]
+fn test_verify_segment() {
+    let entries = shared_ledger_with_entries("test-node-1", 10).get_entries();
+    let last = entries[9].hash.clone();
+    assert!(sync::verify_segment(&entries, true, &last).is_ok());
+    assert!(sync::verify_segment(&entries[..9], true, &last).is_err());
+    assert!(sync::verify_segment(&[], true, &last).is_err());
+
+    // Entries that don't link up, or that were altered, don't count
+    let mut gapped = entries.clone();
+    gapped.remove(4);
+    assert!(sync::verify_segment(&gapped, true, &last).is_err());
+    assert!(sync::verify_segment(&gapped, false, &last).is_ok());
+    let mut altered = entries.clone();
+    altered[3].data = json!({ "message": "Altered" });
+    assert!(sync::verify_segment(&altered, true, &last).is_err());
+}
+
+/// Start a server for each of `ledgers` and connect a node that leaves its ledger to a coordinated sync to all of them
+async fn connect_to_sources(ledgers: Vec<(&str, SharedLedger)>) -> (Arc<P2PManager>, Vec<String>) {
+    let node = Arc::new(
+        P2PManager::new("test-node-9".to_string(), SharedLedger::new("test-node-9".to_string()))
+            .with_sync_page_size(10)
+            .with_initial_sync(),
+    );
+    let mut peers = Vec::new();
+    for (node_id, ledger) in ledgers {
+        let url = start_server(Arc::new(P2PManager::new(node_id.to_string(), ledger))).await;
+        node.dial_peer(url, Duration::from_millis(100), Duration::from_secs(5));
+        peers.push(node_id.to_string());
+    }
+    wait_for(Duration::from_secs(5), || node.connected_peer_ids() == peers).await;
+    (node, peers)
+}
+
+#[tokio::test]
+async fn test_parallel_sync_from_peers() {
+    let source = shared_ledger_with_entries("test-node-1", 45);
+    let (node, peers) = connect_to_sources(vec![
+        ("test-node-1", source.clone()),
+        ("test-node-2", copy_of("test-node-2", "test-node-1", &source, 45)),
+        ("test-node-3", copy_of("test-node-3", "test-node-1", &source, 45)),
+    ])
+    .await;
+
+    // Connecting alone doesn't sync while the initial sync is still to come
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(node.ledger.get_entries().is_empty());
+
+    let report = SyncCoordinator::new(&node).sync(&peers).await.unwrap();
+    assert_eq!((report.peers, report.segments, report.added, report.retries), (3, 5, 45, 0));
+    assert_eq!(node.ledger.get_last_entry().unwrap().hash, source.get_last_entry().unwrap().hash);
+    assert!(node.last_sync().is_some());
+
+    // Once caught up, another sync has nothing to do
+    let report = SyncCoordinator::new(&node).sync(&peers).await.unwrap();
+    assert_eq!(report.segments, 0);
+    node.finish_initial_sync().await;
+
+    node.leave().await;
+}
+
+#[tokio::test]
+async fn test_parallel_sync_skips_other_branches() {
+    let source = shared_ledger_with_entries("test-node-1", 45);
+    // Node 2 shares the first 20 entries, then went its own way
+    let forked = copy_of("test-node-2", "test-node-1", &source, 20);
+    for i in 1..=20 {
+        forked.add_entry(json!({ "message": format!("Other entry {i}") })).unwrap();
+    }
+    let (node, peers) = connect_to_sources(vec![("test-node-1", source.clone()), ("test-node-2", forked)]).await;
+
+    // Node 2's segment past the fork doesn't end at the skeleton's hash, so node 1 is asked for it
+    let report = SyncCoordinator::new(&node).sync(&peers).await.unwrap();
+    assert_eq!((report.segments, report.added, report.retries), (5, 45, 1));
+    assert_eq!(node.ledger.get_last_entry().unwrap().hash, source.get_last_entry().unwrap().hash);
+
+    node.leave().await;
+}
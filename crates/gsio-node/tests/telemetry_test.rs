@@ -0,0 +1,131 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::{extract::State, routing::post, Json, Router};
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::{MessageType, P2PManager, P2PMessage};
+use gsio_node::telemetry::OtlpLayer;
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+use tracing_subscriber::layer::SubscriberExt;
+
+fn new_node(node_id: &str) -> P2PManager {
+    P2PManager::new(node_id.to_string(), SharedLedger::new(node_id.to_string()))
+}
+
+/// Serve a stand-in OTLP/HTTP collector, returning its URL and the export requests it receives
+async fn start_collector() -> (String, Arc<Mutex<Vec<JsonValue>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+
+    let app = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(received): State<Arc<Mutex<Vec<JsonValue>>>>, Json(body): Json<JsonValue>| async move {
+                received.lock().unwrap().push(body);
+                Json(json!({}))
+            }),
+        )
+        .with_state(received.clone());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    (format!("http://{addr}"), received)
+}
+
+/// Every span exported so far
+fn exported_spans(requests: &Mutex<Vec<JsonValue>>) -> Vec<JsonValue> {
+    let requests = requests.lock().unwrap();
+    let resource_spans = requests.iter().flat_map(|r| r["resourceSpans"].as_array().unwrap().iter());
+    let scope_spans = resource_spans.flat_map(|r| r["scopeSpans"].as_array().unwrap().iter());
+    scope_spans.flat_map(|s| s["spans"].as_array().unwrap().iter().cloned()).collect()
+}
+
+/// The string value of a span attribute
+fn attribute<'a>(span: &'a JsonValue, key: &str) -> Option<&'a str> {
+    let attributes = span["attributes"].as_array()?;
+    attributes.iter().find(|a| a["key"] == key)?["value"]["stringValue"].as_str()
+}
+
+/// The first exported span named `name` whose `key` attribute is `value`
+fn find_span(spans: &[JsonValue], name: &str, key: &str, value: &str) -> Option<JsonValue> {
+    spans.iter().find(|s| s["name"] == name && attribute(s, key) == Some(value)).cloned()
+}
+
+/// Poll `condition` until it holds, failing the test after `timeout`
+async fn wait_for(timeout: Duration, condition: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !condition() {
+        assert!(tokio::time::Instant::now() < deadline, "condition not met within {timeout:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_entry_spans_are_exported() {
+    let (url, requests) = start_collector().await;
+    let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(&url, "gsio-test", "test-node-1"));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // An entry written here, and one announced by a peer
+    let node = new_node("test-node-1");
+    let local = node.add_local_entry(json!({ "message": "Test entry 1" })).unwrap();
+    let peer = new_node("test-node-2");
+    let remote = peer.add_local_entry(json!({ "message": "Test entry 2" })).unwrap();
+    let announce = P2PMessage::new(
+        MessageType::EntryAnnounce,
+        "test-node-2".to_string(),
+        "test-node-1".to_string(),
+        serde_json::to_value(&remote).unwrap(),
+    );
+    let message_id = announce.message_id.clone();
+    node.handle_message(announce);
+
+    wait_for(Duration::from_secs(10), || {
+        find_span(&exported_spans(&requests), "ledger.add_pending_entry", "entry_id", &remote.id).is_some()
+    })
+    .await;
+    let spans = exported_spans(&requests);
+
+    // IDs that were only known once the entry was made are recorded too
+    let added = find_span(&spans, "ledger.add_entry", "entry_id", &local.id).unwrap();
+    assert!(added.get("parentSpanId").is_none());
+    assert!(find_span(&spans, "p2p.broadcast_entry", "entry_id", &local.id).is_some());
+
+    // Handling a peer's announce nests the ledger work under the message
+    let message = find_span(&spans, "p2p.message", "message_id", &message_id).unwrap();
+    assert_eq!(attribute(&message, "peer_id"), Some("test-node-2"));
+    assert_eq!(attribute(&message, "message_type"), Some("EntryAnnounce"));
+    let pending = find_span(&spans, "ledger.add_pending_entry", "entry_id", &remote.id).unwrap();
+    assert_eq!(attribute(&pending, "creator"), Some("test-node-2"));
+    assert_eq!(pending["traceId"], message["traceId"]);
+    assert_eq!(pending["parentSpanId"], message["spanId"]);
+    assert!(pending["endTimeUnixNano"].as_str().unwrap().parse::<u128>().unwrap()
+        >= pending["startTimeUnixNano"].as_str().unwrap().parse::<u128>().unwrap());
+
+    // Spans are exported under the node's identity
+    let resource = &requests.lock().unwrap()[0]["resourceSpans"][0]["resource"];
+    assert!(resource["attributes"].as_array().unwrap().contains(&json!({
+        "key": "service.instance.id",
+        "value": { "stringValue": "test-node-1" },
+    })));
+}
+
+#[tokio::test]
+async fn test_events_are_attached_to_their_span() {
+    let (url, requests) = start_collector().await;
+    let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(&url, "gsio-test", "test-node-1"));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    tracing::info_span!("test.outer", entry_id = "entry-1").in_scope(|| {
+        tracing::warn!(reason = "testing", "Something happened");
+    });
+
+    wait_for(Duration::from_secs(10), || !exported_spans(&requests).is_empty()).await;
+    let span = find_span(&exported_spans(&requests), "test.outer", "entry_id", "entry-1").unwrap();
+    let event = &span["events"][0];
+    assert_eq!(event["name"], "Something happened");
+    assert_eq!(attribute(event, "reason"), Some("testing"));
+    assert_eq!(attribute(event, "level"), Some("WARN"));
+}
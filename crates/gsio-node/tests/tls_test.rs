@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use axum_server::tls_rustls::RustlsConfig;
+use gsio_client::GsioClient;
+use gsio_node::api;
+use gsio_node::ledger::SharedLedger;
+use gsio_node::p2p::P2PManager;
+use gsio_node::tls::TlsConfig;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use serde_json::json;
+use uuid::Uuid;
+
+/// A CA and a certificate it signed, as PEM
+struct Issued {
+    ca: String,
+    cert: String,
+    key: String,
+}
+
+fn issue(name: &str) -> Issued {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca: Certificate = params.self_signed(&ca_key).unwrap();
+
+    let key = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(vec![name.to_string()]).unwrap().signed_by(&key, &ca, &ca_key).unwrap();
+    Issued { ca: ca.pem(), cert: cert.pem(), key: key.serialize_pem() }
+}
+
+fn write_pem(contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("gsio-node-{}.pem", Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn tls_config(server: &Issued) -> TlsConfig {
+    TlsConfig {
+        cert_path: Some(write_pem(&server.cert)),
+        key_path: Some(write_pem(&server.key)),
+        client_ca_path: None,
+    }
+}
+
+async fn start_tls_server(p2p: Arc<P2PManager>, tls: &TlsConfig) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = RustlsConfig::from_config(tls.server_config().unwrap());
+    let server = axum_server::from_tcp_rustls(listener, config).serve(api::router(p2p).into_make_service());
+    tokio::spawn(server);
+    format!("https://localhost:{port}")
+}
+
+fn new_node() -> Arc<P2PManager> {
+    let node_id = "test-node-1".to_string();
+    Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)))
+}
+
+#[tokio::test]
+async fn test_https() {
+    let server = issue("localhost");
+    let p2p = new_node();
+    let url = start_tls_server(p2p.clone(), &tls_config(&server)).await;
+
+    // A client that trusts the node's CA
+    let client = GsioClient::builder(&url).add_root_certificate(server.ca.as_bytes()).build().unwrap();
+    client.add_ledger_entry(json!({ "message": "Test entry" })).await.unwrap();
+    assert_eq!(client.get_ledger().await.unwrap().len(), 1);
+
+    // One that doesn't is refused, unless it skips verification
+    let untrusting = GsioClient::builder(&url).max_retries(0).build().unwrap();
+    assert!(untrusting.get_ledger().await.is_err());
+    let insecure = GsioClient::builder(&url).danger_accept_invalid_certs(true).build().unwrap();
+    assert_eq!(insecure.get_ledger().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_client_certificates() {
+    let server = issue("localhost");
+    let client_cert = issue("gsio-client");
+    let tls = TlsConfig { client_ca_path: Some(write_pem(&client_cert.ca)), ..tls_config(&server) };
+    assert!(tls.requires_client_certificates());
+    let url = start_tls_server(new_node(), &tls).await;
+
+    let client = GsioClient::builder(&url)
+        .add_root_certificate(server.ca.as_bytes())
+        .client_certificate(format!("{}{}", client_cert.cert, client_cert.key))
+        .build()
+        .unwrap();
+    assert!(client.get_ledger().await.unwrap().is_empty());
+
+    // Clients without a certificate from the CA are refused
+    let anonymous = GsioClient::builder(&url).add_root_certificate(server.ca.as_bytes()).max_retries(0).build().unwrap();
+    assert!(anonymous.get_ledger().await.is_err());
+    let stranger = issue("gsio-client");
+    let stranger = GsioClient::builder(&url)
+        .add_root_certificate(server.ca.as_bytes())
+        .client_certificate(format!("{}{}", stranger.cert, stranger.key))
+        .max_retries(0)
+        .build()
+        .unwrap();
+    assert!(stranger.get_ledger().await.is_err());
+}
+
+#[test]
+fn test_invalid_tls_config() {
+    let server = issue("localhost");
+    let missing_key = TlsConfig { key_path: None, ..tls_config(&server) };
+    assert!(missing_key.is_enabled());
+    assert!(missing_key.server_config().is_err());
+
+    let swapped = TlsConfig {
+        cert_path: Some(write_pem(&server.key)),
+        key_path: Some(write_pem(&server.cert)),
+        client_ca_path: None,
+    };
+    assert!(swapped.server_config().is_err());
+    assert!(tls_config(&server).grpc_config().is_ok());
+}
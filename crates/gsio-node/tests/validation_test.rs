@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use ed25519_dalek::SigningKey;
+use gsio_node::api;
+use gsio_node::audit::AuditFilter;
+use gsio_node::error::GsioNodeError;
+use gsio_node::ledger::{Limits, SharedLedger};
+use gsio_node::p2p::{EntryRejection, MessageType, P2PManager, P2PMessage};
+use gsio_node::validation::{
+    sign_data, ErrorCode, FieldType, MaxPayloadSize, PolicySet, RequireSignature, RequiredFields, Schema,
+    TransactionAddresses, ValidationConfig, ValidationError, ValidationPolicy,
+};
+use rand::rngs::OsRng;
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+
+fn code(policy: &dyn ValidationPolicy, data: JsonValue) -> Option<ErrorCode> {
+    policy.validate(&data).err().map(|e| e.code)
+}
+
+#[test]
+fn test_builtin_policies() {
+    let size = MaxPayloadSize(32);
+    assert_eq!(code(&size, json!({ "message": "short" })), None);
+    assert_eq!(code(&size, json!({ "message": "x".repeat(32) })), Some(ErrorCode::PayloadTooLarge));
+
+    let required = RequiredFields(vec!["type".to_string(), "transaction.amount".to_string()]);
+    assert_eq!(code(&required, json!({ "type": "transfer", "transaction": { "amount": 5 } })), None);
+    assert_eq!(code(&required, json!({ "type": "transfer", "transaction": {} })), Some(ErrorCode::MissingField));
+    assert_eq!(code(&required, json!("not an object")), Some(ErrorCode::MissingField));
+
+    let schema = Schema(BTreeMap::from([
+        ("type".to_string(), FieldType::String),
+        ("transaction.amount".to_string(), FieldType::Integer),
+    ]));
+    assert_eq!(code(&schema, json!({ "type": "transfer", "transaction": { "amount": 5 } })), None);
+    // Fields that aren't present aren't checked
+    assert_eq!(code(&schema, json!({ "message": "hello" })), None);
+    assert_eq!(code(&schema, json!({ "transaction": { "amount": 1.5 } })), Some(ErrorCode::InvalidFieldType));
+    assert_eq!(code(&schema, json!([1, 2, 3])), Some(ErrorCode::InvalidFieldType));
+}
+
+#[test]
+fn test_signature_policy() {
+    let key = SigningKey::generate(&mut OsRng);
+    let signed = sign_data(json!({ "message": "hello" }), &key);
+    assert_eq!(code(&RequireSignature, signed.clone()), None);
+
+    assert_eq!(code(&RequireSignature, json!({ "message": "hello" })), Some(ErrorCode::MissingSignature));
+
+    let mut tampered = signed.clone();
+    tampered["message"] = json!("goodbye");
+    assert_eq!(code(&RequireSignature, tampered), Some(ErrorCode::InvalidSignature));
+
+    let mut wrong_key = signed;
+    wrong_key["public_key"] = json!(hex::encode(SigningKey::generate(&mut OsRng).verifying_key().to_bytes()));
+    assert_eq!(code(&RequireSignature, wrong_key), Some(ErrorCode::InvalidSignature));
+}
+
+#[test]
+fn test_address_policy() {
+    let transaction = |sender: &str, recipient: &str| {
+        json!({ "type": "transaction", "transaction": { "sender": sender, "recipient": recipient, "amount": 5 } })
+    };
+    let address = "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w8";
+    let legacy = "gsio_000102030405060708090a0b0c0d0e0f10111213";
+    let typo = "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w9";
+
+    assert_eq!(code(&TransactionAddresses, transaction(address, address)), None);
+    assert_eq!(code(&TransactionAddresses, transaction(legacy, address)), None);
+    assert_eq!(code(&TransactionAddresses, transaction(address, typo)), Some(ErrorCode::InvalidAddress));
+    assert_eq!(code(&TransactionAddresses, transaction("gsio_0011", address)), Some(ErrorCode::InvalidAddress));
+    assert_eq!(
+        code(&TransactionAddresses, json!({ "type": "transaction", "transaction": {} })),
+        Some(ErrorCode::InvalidAddress)
+    );
+    // Other entries aren't checked
+    assert_eq!(code(&TransactionAddresses, json!({ "type": "note", "sender": typo })), None);
+
+    let config: ValidationConfig = toml::from_str("check_addresses = true").unwrap();
+    assert_eq!(config.policy().len(), 1);
+    assert_eq!(code(&config.policy(), transaction(typo, address)), Some(ErrorCode::InvalidAddress));
+    assert_eq!(ErrorCode::InvalidAddress.as_str(), "invalid_address");
+}
+
+#[test]
+fn test_policy_set_and_config() {
+    let config: ValidationConfig = toml::from_str(
+        r#"
+        max_payload_bytes = 64
+        required_fields = ["type"]
+        field_types = { type = "string" }
+        "#,
+    )
+    .unwrap();
+    let policy = config.policy();
+    assert_eq!(policy.len(), 3);
+
+    assert_eq!(code(&policy, json!({ "type": "note" })), None);
+    assert_eq!(code(&policy, json!({ "message": "hello" })), Some(ErrorCode::MissingField));
+    assert_eq!(code(&policy, json!({ "type": 5 })), Some(ErrorCode::InvalidFieldType));
+    assert_eq!(code(&policy, json!({ "type": "x".repeat(64) })), Some(ErrorCode::PayloadTooLarge));
+
+    // No rules accepts anything
+    assert!(ValidationConfig::default().policy().is_empty());
+    assert_eq!(code(&PolicySet::new(), json!(null)), None);
+
+    assert!(toml::from_str::<ValidationConfig>("field_types = { type = \"date\" }").is_err());
+}
+
+#[test]
+fn test_ledger_rejects_invalid_entries() {
+    let node_id = "test-node-1".to_string();
+    let ledger = SharedLedger::new(node_id.clone());
+    ledger.set_validation_policy(Arc::new(RequiredFields(vec!["message".to_string()])));
+
+    let err = ledger.add_entry(json!({ "note": "no message" })).unwrap_err();
+    assert_eq!(err.code, ErrorCode::MissingField);
+    assert!(ledger.get_entries().is_empty());
+    ledger.add_entry(json!({ "message": "Test entry" })).unwrap();
+
+    // Client submissions get the structured error back
+    let p2p = P2PManager::new(node_id, ledger);
+    let err = p2p.add_local_entry(json!({ "note": "no message" })).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::MissingField);
+    assert!(matches!(err, GsioNodeError::Invalid(ValidationError { code: ErrorCode::MissingField, .. })));
+}
+
+#[test]
+fn test_peer_entries_are_validated() {
+    // A node without rules creates entries the other node's policy refuses
+    let sender = SharedLedger::new("test-node-1".to_string());
+    let accepted = sender.add_entry(json!({ "message": "Test entry" })).unwrap();
+    let refused = sender.add_entry(json!({ "note": "no message" })).unwrap();
+
+    let receiver_ledger = SharedLedger::new("test-node-2".to_string());
+    receiver_ledger.add_node_key("test-node-1".to_string(), &sender.public_key()).unwrap();
+    receiver_ledger.set_validation_policy(Arc::new(RequiredFields(vec!["message".to_string()])));
+    let receiver = P2PManager::new("test-node-2".to_string(), receiver_ledger);
+
+    let sync = P2PMessage::new(
+        MessageType::LedgerSyncResponse,
+        "test-node-1".to_string(),
+        "test-node-2".to_string(),
+        json!({ "from": 1, "height": 2, "entries": [accepted, refused] }),
+    );
+    let reply = receiver.handle_message(sync).unwrap();
+
+    // The valid entry is added and the sender is told why the other wasn't
+    assert_eq!(receiver.ledger.get_entries().len(), 1);
+    assert!(matches!(reply.message_type, MessageType::EntryRejected));
+    assert_eq!(reply.recipient_id, "test-node-1");
+    let rejections: Vec<EntryRejection> = serde_json::from_value(reply.payload["rejections"].clone()).unwrap();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].entry_id, refused.id);
+    assert_eq!(rejections[0].error.code, ErrorCode::MissingField);
+    assert_eq!(reply.payload["rejections"][0]["code"], "missing_field");
+
+    // Entries that pass don't get a reply
+    let announce = P2PMessage::new(
+        MessageType::EntryAnnounce,
+        "test-node-1".to_string(),
+        "".to_string(),
+        serde_json::to_value(&accepted).unwrap(),
+    );
+    assert!(receiver.handle_message(announce).is_none());
+}
+
+#[tokio::test]
+async fn test_api_returns_error_codes() {
+    let node_id = "test-node-1".to_string();
+    let ledger = SharedLedger::new(node_id.clone());
+    ledger.set_validation_policy(Arc::new(MaxPayloadSize(32)));
+    let p2p = Arc::new(P2PManager::new(node_id, ledger));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = api::router(p2p);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("{url}/api/ledger"))
+        .json(&json!({ "message": "x".repeat(32) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: JsonValue = response.json().await.unwrap();
+    assert_eq!(body["code"], "payload_too_large");
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn test_repeated_announcements_are_ignored() {
+    let sender = SharedLedger::new("test-node-1".to_string());
+    let mut refused = sender.add_entry(json!({ "note": "no message" })).unwrap();
+
+    let receiver_ledger = SharedLedger::new("test-node-2".to_string());
+    receiver_ledger.add_node_key("test-node-1".to_string(), &sender.public_key()).unwrap();
+    receiver_ledger.set_validation_policy(Arc::new(RequiredFields(vec!["message".to_string()])));
+    let receiver = P2PManager::new("test-node-2".to_string(), receiver_ledger);
+    let announce = |entry: &gsio_node::ledger::LedgerEntry| {
+        P2PMessage::new(MessageType::EntryAnnounce, "test-node-1".to_string(), "".to_string(), serde_json::to_value(entry).unwrap())
+    };
+
+    // Only the first copy of an announcement is checked
+    assert!(matches!(receiver.handle_message(announce(&refused)).unwrap().message_type, MessageType::EntryRejected));
+    assert!(receiver.handle_message(announce(&refused)).is_none());
+
+    // The same entry with another signature is a new announcement
+    refused.sign("test-node-3".to_string(), &SigningKey::generate(&mut OsRng));
+    assert!(receiver.handle_message(announce(&refused)).is_some());
+}
+
+#[test]
+fn test_peer_messages_over_the_limits() {
+    let sender = SharedLedger::new("test-node-1".to_string());
+    let entries: Vec<_> = (0..3).map(|i| sender.add_entry(json!({ "message": format!("Test entry {i}") })).unwrap()).collect();
+
+    let receiver_ledger = SharedLedger::new("test-node-2".to_string());
+    receiver_ledger.add_node_key("test-node-1".to_string(), &sender.public_key()).unwrap();
+    receiver_ledger.set_limits(Limits { max_sync_entries: 2, max_entry_bytes: 1024, ..Limits::default() });
+    let receiver = P2PManager::new("test-node-2".to_string(), receiver_ledger);
+
+    // A page with more entries than allowed is dropped whole
+    let sync = P2PMessage::new(
+        MessageType::LedgerSyncResponse,
+        "test-node-1".to_string(),
+        "test-node-2".to_string(),
+        json!({ "from": 1, "height": 3, "entries": entries }),
+    );
+    assert!(receiver.handle_message(sync).is_none());
+    assert!(receiver.ledger.get_entries().is_empty());
+    let events = receiver.audit().query(&AuditFilter::default(), 1).unwrap();
+    assert_eq!(events[0].details["code"], "too_many_entries");
+
+    // An entry over the size limit is rejected like any invalid entry
+    let large = sender.add_entry(json!({ "message": "x".repeat(2048) })).unwrap();
+    let sync = P2PMessage::new(
+        MessageType::LedgerSyncResponse,
+        "test-node-1".to_string(),
+        "test-node-2".to_string(),
+        json!({ "from": 1, "height": 4, "entries": [entries[0], large] }),
+    );
+    let reply = receiver.handle_message(sync).unwrap();
+    let rejections: Vec<EntryRejection> = serde_json::from_value(reply.payload["rejections"].clone()).unwrap();
+    assert_eq!(rejections[0].entry_id, large.id);
+    assert_eq!(rejections[0].error.code, ErrorCode::PayloadTooLarge);
+    assert_eq!(receiver.ledger.get_entries().len(), 1);
+}
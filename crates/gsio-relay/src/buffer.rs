@@ -0,0 +1,205 @@
+//! Deliveries kept until their recipient acknowledges them.
+//!
+//! Every payload forwarded to a node is numbered and kept in the node's
+//! buffer, whether or not the node is connected. The node acknowledges what
+//! it received with an `ack` frame, and whatever is still unacknowledged is
+//! replayed when it registers again. A buffer holds a limited number of
+//! payloads for a limited time; the oldest go first.
+
+use std::collections::VecDeque;
+
+use rmpv::Value;
+
+/// Prefix of the storage keys buffered payloads are kept under
+pub const STORAGE_PREFIX: &str = "buffer/";
+
+/// How much each node's buffer holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferLimits {
+    /// Most payloads kept per node; 0 turns buffering off
+    pub messages: usize,
+    /// Seconds a payload is kept
+    pub ttl: u64,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        Self { messages: 100, ttl: 300 }
+    }
+}
+
+/// A payload waiting to be acknowledged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buffered {
+    pub seq: u64,
+    pub from: String,
+    pub payload: Value,
+    /// Unix time in seconds after which the payload is dropped
+    pub expires: u64,
+}
+
+/// A change to a buffer, to be mirrored in storage
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Stored { node_id: String, message: Buffered },
+    Dropped { node_id: String, seq: u64 },
+}
+
+/// The payloads waiting for one node, oldest first
+#[derive(Debug, Default)]
+pub struct Buffer {
+    next_seq: u64,
+    messages: VecDeque<Buffered>,
+}
+
+impl Buffer {
+    /// Number the payload and keep it, returning its sequence number
+    pub fn push(&mut self, node_id: &str, from: String, payload: Value, limits: BufferLimits, now: u64, changes: &mut Vec<Change>) -> u64 {
+        self.next_seq = self.next_seq.max(1);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if limits.messages == 0 {
+            return seq;
+        }
+
+        let message = Buffered { seq, from, payload, expires: now + limits.ttl };
+        changes.push(Change::Stored { node_id: node_id.to_string(), message: message.clone() });
+        self.messages.push_back(message);
+        while self.messages.len() > limits.messages {
+            self.drop_front(node_id, changes);
+        }
+        seq
+    }
+
+    /// Drop the payloads up to and including `seq`
+    pub fn ack(&mut self, node_id: &str, seq: u64, changes: &mut Vec<Change>) {
+        while self.messages.front().is_some_and(|message| message.seq <= seq) {
+            self.drop_front(node_id, changes);
+        }
+    }
+
+    /// Drop the payloads that expired by `now`
+    pub fn expire(&mut self, node_id: &str, now: u64, changes: &mut Vec<Change>) {
+        while self.messages.front().is_some_and(|message| message.expires <= now) {
+            self.drop_front(node_id, changes);
+        }
+    }
+
+    /// Put back a payload read from storage; payloads have to be restored in order
+    pub fn restore(&mut self, message: Buffered) {
+        self.next_seq = self.next_seq.max(message.seq + 1);
+        self.messages.push_back(message);
+    }
+
+    /// The payloads waiting, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &Buffered> {
+        self.messages.iter()
+    }
+
+    fn drop_front(&mut self, node_id: &str, changes: &mut Vec<Change>) {
+        if let Some(message) = self.messages.pop_front() {
+            changes.push(Change::Dropped { node_id: node_id.to_string(), seq: message.seq });
+        }
+    }
+}
+
+/// The storage key a payload for `node_id` is kept under; keys sort by sequence number
+pub fn storage_key(node_id: &str, seq: u64) -> String {
+    format!("{STORAGE_PREFIX}{node_id}/{seq:020}")
+}
+
+impl Buffered {
+    /// The payload as stored, a msgpack map
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let map = Value::Map(vec![
+            ("from".into(), self.from.as_str().into()),
+            ("expires".into(), self.expires.into()),
+            ("payload".into(), self.payload.clone()),
+        ]);
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &map).expect("writing to a Vec can't fail");
+        bytes
+    }
+
+    /// Read a stored payload back, along with the node it is for
+    pub fn from_stored(key: &str, bytes: &[u8]) -> Option<(String, Buffered)> {
+        let (node_id, seq) = key.strip_prefix(STORAGE_PREFIX)?.rsplit_once('/')?;
+        let value = rmpv::decode::read_value(&mut &bytes[..]).ok()?;
+        let message = Buffered {
+            seq: seq.parse().ok()?,
+            from: value["from"].as_str()?.to_string(),
+            payload: value["payload"].clone(),
+            expires: value["expires"].as_u64()?,
+        };
+        Some((node_id.to_string(), message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: BufferLimits = BufferLimits { messages: 2, ttl: 60 };
+
+    fn push(buffer: &mut Buffer, payload: &str, now: u64, changes: &mut Vec<Change>) -> u64 {
+        buffer.push("node-b", "node-a".to_string(), payload.into(), LIMITS, now, changes)
+    }
+
+    fn seqs(buffer: &Buffer) -> Vec<u64> {
+        buffer.messages().map(|message| message.seq).collect()
+    }
+
+    #[test]
+    fn test_buffer_keeps_the_newest_payloads() {
+        let mut buffer = Buffer::default();
+        let mut changes = Vec::new();
+        assert_eq!(push(&mut buffer, "one", 0, &mut changes), 1);
+        assert_eq!(push(&mut buffer, "two", 0, &mut changes), 2);
+        assert_eq!(push(&mut buffer, "three", 0, &mut changes), 3);
+        assert_eq!(seqs(&buffer), vec![2, 3]);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[3], Change::Dropped { node_id: "node-b".to_string(), seq: 1 });
+
+        changes.clear();
+        buffer.ack("node-b", 2, &mut changes);
+        assert_eq!(seqs(&buffer), vec![3]);
+        assert_eq!(changes, vec![Change::Dropped { node_id: "node-b".to_string(), seq: 2 }]);
+    }
+
+    #[test]
+    fn test_payloads_expire() {
+        let mut buffer = Buffer::default();
+        let mut changes = Vec::new();
+        push(&mut buffer, "one", 0, &mut changes);
+        push(&mut buffer, "two", 30, &mut changes);
+        buffer.expire("node-b", 59, &mut changes);
+        assert_eq!(seqs(&buffer), vec![1, 2]);
+        buffer.expire("node-b", 60, &mut changes);
+        assert_eq!(seqs(&buffer), vec![2]);
+    }
+
+    #[test]
+    fn test_buffering_can_be_turned_off() {
+        let mut buffer = Buffer::default();
+        let mut changes = Vec::new();
+        let off = BufferLimits { messages: 0, ttl: 60 };
+        assert_eq!(buffer.push("node-b", "node-a".to_string(), "one".into(), off, 0, &mut changes), 1);
+        assert_eq!(buffer.push("node-b", "node-a".to_string(), "two".into(), off, 0, &mut changes), 2);
+        assert!(buffer.messages().next().is_none());
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_stored_payloads_round_trip() {
+        let message = Buffered { seq: 7, from: "node-a".to_string(), payload: Value::Binary(vec![1, 2]), expires: 90 };
+        let key = storage_key("node/b", 7);
+        assert_eq!(key, "buffer/node/b/00000000000000000007");
+        assert_eq!(Buffered::from_stored(&key, &message.to_bytes()), Some(("node/b".to_string(), message.clone())));
+        assert_eq!(Buffered::from_stored("secret", &message.to_bytes()), None);
+
+        // Numbering carries on after the restored payloads
+        let mut buffer = Buffer::default();
+        buffer.restore(message);
+        assert_eq!(push(&mut buffer, "next", 0, &mut Vec::new()), 8);
+    }
+}
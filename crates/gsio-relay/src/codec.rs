@@ -0,0 +1,152 @@
+//! Frames as WebSocket messages.
+//!
+//! Text messages carry frames as JSON. Binary messages carry the same frames
+//! as msgpack maps with the same keys, which spares nodes JSON's overhead on
+//! ledger sync. Payloads pass through untouched, binary data included, and
+//! each connection is sent frames in the encoding it last used.
+
+use rmpv::Value;
+
+use crate::rendezvous::Frame;
+
+/// Largest message the relay accepts, in bytes; Cloudflare caps WebSocket
+/// messages at 1 MiB, so nodes split larger payloads into fragments
+pub const MAX_FRAME_BYTES: usize = 1 << 20;
+
+/// How a connection encodes its frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Msgpack,
+}
+
+/// The body of a WebSocket message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Decode a frame, along with the encoding it came in
+pub fn decode(body: &Body) -> Result<(Frame, Encoding), String> {
+    let len = match body {
+        Body::Text(text) => text.len(),
+        Body::Binary(bytes) => bytes.len(),
+    };
+    if len > MAX_FRAME_BYTES {
+        return Err(format!("Frame of {len} bytes is over the {MAX_FRAME_BYTES} byte limit"));
+    }
+
+    match body {
+        Body::Text(text) => serde_json::from_str(text)
+            .map(|frame| (frame, Encoding::Json))
+            .map_err(|e| format!("Invalid frame: {e}")),
+        Body::Binary(bytes) => {
+            let value = rmpv::decode::read_value(&mut bytes.as_slice()).map_err(|e| format!("Invalid msgpack: {e}"))?;
+            rmpv::ext::from_value(value)
+                .map(|frame| (frame, Encoding::Msgpack))
+                .map_err(|e| format!("Invalid frame: {e}"))
+        }
+    }
+}
+
+/// Encode a frame for a connection using `encoding`.
+///
+/// Binary payload data reaches JSON connections as arrays of byte values.
+pub fn encode(frame: &Frame, encoding: Encoding) -> Result<Body, String> {
+    match encoding {
+        Encoding::Json => serde_json::to_string(frame).map(Body::Text).map_err(|e| e.to_string()),
+        Encoding::Msgpack => {
+            let mut bytes = Vec::new();
+            rmpv::encode::write_value(&mut bytes, &to_msgpack(frame)).map_err(|e| e.to_string())?;
+            Ok(Body::Binary(bytes))
+        }
+    }
+}
+
+/// The frame as a msgpack map keyed like its JSON
+fn to_msgpack(frame: &Frame) -> Value {
+    let fields = match frame {
+        Frame::Register { node_id } => vec![("type", "register".into()), ("node_id", node_id.as_str().into())],
+        Frame::Registered { node_id, nodes } => vec![
+            ("type", "registered".into()),
+            ("node_id", node_id.as_str().into()),
+            ("nodes", Value::Array(nodes.iter().map(|node| node.as_str().into()).collect())),
+        ],
+        Frame::NodeJoined { node_id } => vec![("type", "node_joined".into()), ("node_id", node_id.as_str().into())],
+        Frame::NodeLeft { node_id } => vec![("type", "node_left".into()), ("node_id", node_id.as_str().into())],
+        Frame::Forward { to, payload } => {
+            vec![("type", "forward".into()), ("to", to.as_str().into()), ("payload", payload.clone())]
+        }
+        Frame::Deliver { from, seq, payload } => vec![
+            ("type", "deliver".into()),
+            ("from", from.as_str().into()),
+            ("seq", (*seq).into()),
+            ("payload", payload.clone()),
+        ],
+        Frame::Ack { seq } => vec![("type", "ack".into()), ("seq", (*seq).into())],
+        Frame::Error { message } => vec![("type", "error".into()), ("message", message.as_str().into())],
+    };
+    Value::Map(fields.into_iter().map(|(key, value)| (key.into(), value)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward(payload: Value) -> Frame {
+        Frame::Forward { to: "node-b".to_string(), payload }
+    }
+
+    #[test]
+    fn test_frames_round_trip_in_both_encodings() {
+        let payload = Value::Map(vec![("kind".into(), "message".into()), ("n".into(), 1.into())]);
+        let frames = [
+            Frame::Registered { node_id: "node-a".to_string(), nodes: vec!["node-b".to_string()] },
+            forward(payload.clone()),
+            Frame::Deliver { from: "node-a".to_string(), seq: 3, payload },
+            Frame::Ack { seq: 3 },
+            Frame::Error { message: "nope".to_string() },
+        ];
+        for frame in frames {
+            for encoding in [Encoding::Json, Encoding::Msgpack] {
+                let body = encode(&frame, encoding).unwrap();
+                assert_eq!(decode(&body).unwrap(), (frame.clone(), encoding));
+            }
+        }
+    }
+
+    #[test]
+    fn test_msgpack_frames_are_keyed_maps() {
+        let Body::Binary(bytes) = encode(&Frame::NodeLeft { node_id: "node-a".to_string() }, Encoding::Msgpack).unwrap() else {
+            panic!("expected a binary message");
+        };
+        let value = rmpv::decode::read_value(&mut bytes.as_slice()).unwrap();
+        assert_eq!(value["type"].as_str(), Some("node_left"));
+        assert_eq!(value["node_id"].as_str(), Some("node-a"));
+    }
+
+    #[test]
+    fn test_binary_payloads_pass_through() {
+        let frame = forward(Value::Binary(vec![0, 159, 255]));
+        let body = encode(&frame, Encoding::Msgpack).unwrap();
+        assert_eq!(decode(&body).unwrap().0, frame);
+
+        // JSON connections see the bytes as numbers
+        assert_eq!(
+            encode(&frame, Encoding::Json).unwrap(),
+            Body::Text(r#"{"type":"forward","to":"node-b","payload":[0,159,255]}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_oversized_and_invalid_frames_are_refused() {
+        let oversized = forward(Value::Binary(vec![0; MAX_FRAME_BYTES]));
+        let error = decode(&encode(&oversized, Encoding::Msgpack).unwrap()).unwrap_err();
+        assert!(error.contains("limit"), "{error}");
+
+        assert!(decode(&Body::Binary(vec![0xc1])).is_err());
+        assert!(decode(&Body::Binary(vec![0x93, 1, 2, 3])).is_err());
+        assert!(decode(&Body::Text("{}".to_string())).is_err());
+    }
+}
@@ -1,29 +1,268 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use futures::StreamExt;
+use serde::Deserialize;
 use worker::*;
 
+mod buffer;
+mod codec;
+mod rendezvous;
+mod token;
+
+use buffer::{BufferLimits, Buffered, Change};
+use codec::{Body, Encoding};
+use rendezvous::{Frame, Rendezvous};
+
+/// Name of the Durable Object binding in `wrangler.toml`
+const HUB_BINDING: &str = "RELAY_HUB";
+
+/// Storage key of a channel's secret
+const SECRET_KEY: &str = "secret";
+
+/// Largest buffered payload kept in storage, in bytes; Durable Object storage
+/// takes values of up to 128 KiB, so bigger payloads are only buffered in memory
+const MAX_STORED_BYTES: usize = 128 * 1024;
+
 #[event(fetch)]
-async fn fetch(req: HttpRequest, _env: Env, _ctx: Context) -> Result<worker::Response> {
-    let upgrade_header = match req.headers().get("Upgrade") {
-        Some(h) => h.to_str().unwrap(),
-        None => "",
+async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+    let path = req.path();
+    let Some(channel) = channel_name(&path) else {
+        return Response::error("Not found", 404);
     };
-    if upgrade_header != "websocket" {
-        return worker::Response::error("Expected Upgrade: websocket", 426);
+    if req.method() != Method::Post {
+        let upgrade_header = req.headers().get("Upgrade")?.unwrap_or_default();
+        if upgrade_header != "websocket" {
+            return Response::error("Expected Upgrade: websocket", 426);
+        }
+        if token_param(&req.url()?).is_none() {
+            return Response::error("Joining a channel takes a token", 401);
+        }
+    }
+
+    // Nodes on the same channel connect to the same hub, so any two can reach each other
+    let hub = env.durable_object(HUB_BINDING)?.id_from_name(channel)?.get_stub()?;
+    hub.fetch_with_request(req).await
+}
+
+/// The channel named by a `/channels/<name>` path
+fn channel_name(path: &str) -> Option<&str> {
+    let name = path.strip_prefix("/channels/")?;
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(name)
+}
+
+/// The `token` query parameter of a join request
+fn token_param(url: &Url) -> Option<String> {
+    url.query_pairs().find(|(key, _)| key == "token").map(|(_, token)| token.into_owned())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateChannel {
+    secret: String,
+}
+
+/// A node's WebSocket and the encoding it speaks
+struct Connection {
+    socket: WebSocket,
+    encoding: Encoding,
+}
+
+/// One channel: holds its secret and the WebSocket of every connected node,
+/// routes frames between them, and keeps the payloads waiting for each node
+#[durable_object]
+pub struct RelayHub {
+    state: State,
+    secret: Option<String>,
+    restored: bool,
+    rendezvous: Rc<RefCell<Rendezvous<u64>>>,
+    connections: Rc<RefCell<HashMap<u64, Connection>>>,
+    next_connection: u64,
+}
+
+#[durable_object]
+impl DurableObject for RelayHub {
+    fn new(state: State, env: Env) -> Self {
+        let defaults = BufferLimits::default();
+        let limits = BufferLimits {
+            messages: var(&env, "BUFFER_MESSAGES").unwrap_or(defaults.messages),
+            ttl: var(&env, "BUFFER_TTL_SECS").unwrap_or(defaults.ttl),
+        };
+        Self {
+            state,
+            secret: None,
+            restored: false,
+            rendezvous: Rc::new(RefCell::new(Rendezvous::new().with_limits(limits))),
+            connections: Rc::new(RefCell::new(HashMap::new())),
+            next_connection: 0,
+        }
+    }
+
+    async fn fetch(&mut self, mut req: Request) -> Result<Response> {
+        let path = req.path();
+        let Some(channel) = channel_name(&path) else {
+            return Response::error("Not found", 404);
+        };
+        if req.method() == Method::Post {
+            return self.create(&mut req).await;
+        }
+
+        let Some(secret) = self.secret().await else {
+            return Response::error(format!("No channel {channel}"), 404);
+        };
+        let token = token_param(&req.url()?).unwrap_or_default();
+        let now = Date::now().as_millis() / 1000;
+        let node_id = match token::verify(&token, channel, &secret, now) {
+            Ok(node_id) => node_id,
+            Err(e) => return Response::error(e.to_string(), 401),
+        };
+
+        self.restore().await?;
+
+        let ws = WebSocketPair::new()?;
+        let client = ws.client;
+        let server = ws.server;
+        server.accept()?;
+
+        let connection = self.next_connection;
+        self.next_connection += 1;
+        let socket = server.clone();
+        self.connections.borrow_mut().insert(connection, Connection { socket, encoding: Encoding::Json });
+        self.rendezvous.borrow_mut().admit(connection, node_id);
+
+        let rendezvous = self.rendezvous.clone();
+        let connections = self.connections.clone();
+        let mut storage = self.state.storage();
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut event_stream = server.events().expect("could not open stream");
+            while let Some(Ok(WebsocketEvent::Message(msg))) = event_stream.next().await {
+                let body = match (msg.text(), msg.bytes()) {
+                    (Some(text), _) => Body::Text(text),
+                    (None, Some(bytes)) => Body::Binary(bytes),
+                    (None, None) => continue,
+                };
+                let frames = match codec::decode(&body) {
+                    Ok((frame, encoding)) => {
+                        if let Some(open) = connections.borrow_mut().get_mut(&connection) {
+                            open.encoding = encoding;
+                        }
+                        let mut routing = rendezvous.borrow_mut();
+                        routing.expire(Date::now().as_millis() / 1000);
+                        routing.receive(connection, frame)
+                    }
+                    Err(message) => vec![(connection, Frame::Error { message })],
+                };
+                send(&connections.borrow(), frames);
+                let changes = rendezvous.borrow_mut().take_changes();
+                persist(&mut storage, changes).await;
+            }
+
+            connections.borrow_mut().remove(&connection);
+            let frames = rendezvous.borrow_mut().disconnect(connection);
+            send(&connections.borrow(), frames);
+        });
+        Response::from_websocket(client)
+    }
+}
+
+impl RelayHub {
+    /// Load the payloads buffered before the object was last evicted
+    async fn restore(&mut self) -> Result<()> {
+        if self.restored {
+            return Ok(());
+        }
+        let stored = self.state.storage().list_with_options(ListOptions::new().prefix(buffer::STORAGE_PREFIX)).await?;
+        let mut rendezvous = self.rendezvous.borrow_mut();
+        for entry in stored.entries() {
+            let entry = js_sys::Array::from(&entry?);
+            let key = entry.get(0).as_string().unwrap_or_default();
+            let bytes = js_sys::Uint8Array::new(&entry.get(1)).to_vec();
+            match Buffered::from_stored(&key, &bytes) {
+                Some((node_id, message)) => rendezvous.restore(node_id, message),
+                None => console_log!("Ignoring unreadable buffered payload {}", key),
+            }
+        }
+        self.restored = true;
+        Ok(())
     }
 
-    let ws = WebSocketPair::new()?;
-    let client = ws.client;
-    let server = ws.server;
-    server.accept()?;
+    /// The channel's secret, if it has been created
+    async fn secret(&mut self) -> Option<String> {
+        if self.secret.is_none() {
+            self.secret = self.state.storage().get(SECRET_KEY).await.ok();
+        }
+        self.secret.clone()
+    }
 
-    wasm_bindgen_futures::spawn_local(async move {
-        let mut event_stream = server.events().expect("could not open stream");
-        while let Some(event) = event_stream.next().await {
-            match event.expect("received error in websocket") {
-                WebsocketEvent::Message(msg) => server.send(&format!("echo: {:?}", &msg.text().unwrap())).unwrap(),
-                WebsocketEvent::Close(event) => console_log!("{:?}", event),
+    /// Create the channel with the secret in the request body.
+    ///
+    /// The first secret sticks; creating a channel that exists is a conflict.
+    async fn create(&mut self, req: &mut Request) -> Result<Response> {
+        let Ok(CreateChannel { secret }) = req.json().await else {
+            return Response::error(r#"Expected {"secret": "..."}"#, 400);
+        };
+        if secret.len() < token::MIN_SECRET_LEN {
+            return Response::error(format!("The secret must be at least {} characters", token::MIN_SECRET_LEN), 400);
+        }
+        if self.secret().await.is_some() {
+            return Response::error("The channel already exists", 409);
+        }
+
+        self.state.storage().put(SECRET_KEY, &secret).await?;
+        self.secret = Some(secret);
+        Ok(Response::empty()?.with_status(201))
+    }
+}
+
+/// A number from a `wrangler.toml` var, if it is set and valid
+fn var<T: std::str::FromStr>(env: &Env, name: &str) -> Option<T> {
+    env.var(name).ok()?.to_string().parse().ok()
+}
+
+/// Mirror buffer changes in storage
+async fn persist(storage: &mut Storage, changes: Vec<Change>) {
+    let mut dropped = Vec::new();
+    for change in changes {
+        match change {
+            Change::Stored { node_id, message } => {
+                let bytes = message.to_bytes();
+                if bytes.len() > MAX_STORED_BYTES {
+                    continue;
+                }
+                let key = buffer::storage_key(&node_id, message.seq);
+                if let Err(e) = storage.put_raw(&key, js_sys::Uint8Array::from(bytes.as_slice())).await {
+                    console_log!("Failed to store buffered payload {}: {}", key, e);
+                }
             }
+            Change::Dropped { node_id, seq } => dropped.push(buffer::storage_key(&node_id, seq)),
         }
-    });
-    worker::Response::from_websocket(client)
-}
\ No newline at end of file
+    }
+    // Storage deletes at most 128 keys at a time
+    for keys in dropped.chunks(128) {
+        if let Err(e) = storage.delete_multiple(keys.to_vec()).await {
+            console_log!("Failed to drop buffered payloads: {}", e);
+        }
+    }
+}
+
+/// Send each frame on its connection in the connection's encoding, skipping
+/// connections that have closed
+fn send(connections: &HashMap<u64, Connection>, frames: Vec<(u64, Frame)>) {
+    for (id, frame) in frames {
+        let Some(connection) = connections.get(&id) else {
+            continue;
+        };
+        let sent = match codec::encode(&frame, connection.encoding) {
+            Ok(Body::Text(text)) => connection.socket.send_with_str(text).map_err(|e| e.to_string()),
+            Ok(Body::Binary(bytes)) => connection.socket.send_with_bytes(bytes).map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            console_log!("Failed to send to connection {}: {}", id, e);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Routing between nodes that registered with the relay.
+//!
+//! A connection is admitted as the node ID its access token was issued to.
+//! The node claims that ID with a `register` frame, and from then on can
+//! `forward` payloads to other nodes, which receive them as `deliver` frames.
+//! The relay doesn't look inside payloads, which may be anything msgpack can
+//! hold; nodes run their own handshake through it and check each other's keys.
+//! Payloads for a node that is away wait in its buffer; see [`crate::buffer`].
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::{Buffer, BufferLimits, Buffered, Change};
+
+/// A frame exchanged between a node and the relay, as JSON text or msgpack
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Frame {
+    /// Sent by a node to claim its node ID on the connection
+    Register { node_id: String },
+    /// Sent to a node once it's registered, with the other registered nodes
+    Registered { node_id: String, nodes: Vec<String> },
+    /// Another node registered
+    NodeJoined { node_id: String },
+    /// A registered node went away
+    NodeLeft { node_id: String },
+    /// Sent by a node to have `payload` delivered to node `to`
+    Forward { to: String, payload: Value },
+    /// A payload forwarded by node `from`, numbered for acknowledging
+    Deliver { from: String, seq: u64, payload: Value },
+    /// Sent by a node once it has the deliveries up to and including `seq`
+    Ack { seq: u64 },
+    /// Why the relay refused the last frame
+    Error { message: String },
+}
+
+/// Which node each connection registered as, and the payloads waiting for each node.
+///
+/// `C` identifies a connection. Handling a frame returns the frames to send
+/// and the connections to send them on. Payloads can be forwarded to any
+/// node that has registered before, and wait in its buffer if it is away.
+#[derive(Debug)]
+pub struct Rendezvous<C> {
+    nodes: HashMap<String, C>,
+    connections: HashMap<C, String>,
+    admitted: HashMap<C, String>,
+    buffers: HashMap<String, Buffer>,
+    limits: BufferLimits,
+    now: u64,
+    changes: Vec<Change>,
+}
+
+impl<C> Default for Rendezvous<C> {
+    fn default() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            connections: HashMap::new(),
+            admitted: HashMap::new(),
+            buffers: HashMap::new(),
+            limits: BufferLimits::default(),
+            now: 0,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl<C: Copy + Eq + Hash> Rendezvous<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffer payloads within `limits` instead of the defaults
+    pub fn with_limits(mut self, limits: BufferLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Move the clock to Unix time `now`, in seconds, dropping expired payloads.
+    ///
+    /// Payloads are buffered from the time of the last call.
+    pub fn expire(&mut self, now: u64) {
+        self.now = now;
+        for (node_id, buffer) in &mut self.buffers {
+            buffer.expire(node_id, now, &mut self.changes);
+        }
+    }
+
+    /// Put back a payload read from storage
+    pub fn restore(&mut self, node_id: String, message: Buffered) {
+        self.buffers.entry(node_id).or_default().restore(message);
+    }
+
+    /// The buffer changes since the last call, to be mirrored in storage
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// IDs of the registered nodes, sorted
+    pub fn nodes(&self) -> Vec<String> {
+        let mut nodes: Vec<String> = self.nodes.keys().cloned().collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Let `connection` register as `node_id`, and as no other node
+    pub fn admit(&mut self, connection: C, node_id: String) {
+        self.admitted.insert(connection, node_id);
+    }
+
+    /// Handle a frame received on `connection`
+    pub fn receive(&mut self, connection: C, frame: Frame) -> Vec<(C, Frame)> {
+        match frame {
+            Frame::Register { node_id } => self.register(connection, node_id),
+            Frame::Forward { to, payload } => {
+                let Some(from) = self.connections.get(&connection) else {
+                    return vec![(connection, error("Register before forwarding"))];
+                };
+                let Some(buffer) = self.buffers.get_mut(&to) else {
+                    return vec![(connection, error(&format!("Node {to} has never registered with the relay")))];
+                };
+                let seq = buffer.push(&to, from.clone(), payload.clone(), self.limits, self.now, &mut self.changes);
+                match self.nodes.get(&to) {
+                    Some(target) => vec![(*target, Frame::Deliver { from: from.clone(), seq, payload })],
+                    None => Vec::new(),
+                }
+            }
+            Frame::Ack { seq } => {
+                let Some(node_id) = self.connections.get(&connection) else {
+                    return vec![(connection, error("Register before acknowledging"))];
+                };
+                if let Some(buffer) = self.buffers.get_mut(node_id) {
+                    buffer.ack(node_id, seq, &mut self.changes);
+                }
+                Vec::new()
+            }
+            _ => vec![(connection, error("Nodes can only register, forward and acknowledge"))],
+        }
+    }
+
+    /// Forget `connection` once it has closed
+    pub fn disconnect(&mut self, connection: C) -> Vec<(C, Frame)> {
+        self.admitted.remove(&connection);
+        match self.connections.remove(&connection) {
+            Some(node_id) => {
+                self.nodes.remove(&node_id);
+                self.announce(Frame::NodeLeft { node_id })
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Register `connection` as `node_id`.
+    ///
+    /// A node that registers again, say after reconnecting, takes its ID over
+    /// from the old connection, which is told so. Payloads still waiting for
+    /// the node are delivered again after `registered`.
+    fn register(&mut self, connection: C, node_id: String) -> Vec<(C, Frame)> {
+        if let Some(registered) = self.connections.get(&connection) {
+            if *registered == node_id {
+                return Vec::new();
+            }
+            return vec![(connection, error(&format!("Already registered as {registered}")))];
+        }
+        if self.admitted.get(&connection) != Some(&node_id) {
+            return vec![(connection, error(&format!("The connection's token wasn't issued to {node_id}")))];
+        }
+
+        let mut frames = Vec::new();
+        if let Some(previous) = self.nodes.insert(node_id.clone(), connection) {
+            self.connections.remove(&previous);
+            frames.push((previous, error(&format!("Node {node_id} registered on another connection"))));
+        } else {
+            frames.extend(self.announce(Frame::NodeJoined { node_id: node_id.clone() }));
+        }
+        let nodes = self.nodes().into_iter().filter(|id| *id != node_id).collect();
+        self.connections.insert(connection, node_id.clone());
+        let buffer = self.buffers.entry(node_id.clone()).or_default();
+        frames.push((connection, Frame::Registered { node_id, nodes }));
+        frames.extend(buffer.messages().map(|message| {
+            let Buffered { seq, from, payload, .. } = message.clone();
+            (connection, Frame::Deliver { from, seq, payload })
+        }));
+        frames
+    }
+
+    /// Send `frame` to every registered node
+    fn announce(&self, frame: Frame) -> Vec<(C, Frame)> {
+        self.connections.keys().map(|connection| (*connection, frame.clone())).collect()
+    }
+}
+
+fn error(message: &str) -> Frame {
+    Frame::Error { message: message.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hello() -> Value {
+        Value::from("hello")
+    }
+
+    fn register(rendezvous: &mut Rendezvous<u32>, connection: u32, node_id: &str) -> Vec<(u32, Frame)> {
+        rendezvous.admit(connection, node_id.to_string());
+        rendezvous.receive(connection, Frame::Register { node_id: node_id.to_string() })
+    }
+
+    #[test]
+    fn test_frames_are_tagged() {
+        let frame: Frame = serde_json::from_value(json!({ "type": "forward", "to": "node-b", "payload": { "n": 1 } })).unwrap();
+        let payload = Value::Map(vec![("n".into(), 1.into())]);
+        assert_eq!(frame, Frame::Forward { to: "node-b".to_string(), payload });
+        let joined = serde_json::to_value(Frame::NodeJoined { node_id: "node-a".to_string() }).unwrap();
+        assert_eq!(joined, json!({ "type": "node_joined", "node_id": "node-a" }));
+    }
+
+    #[test]
+    fn test_payloads_are_forwarded_between_registered_nodes() {
+        let mut rendezvous = Rendezvous::new();
+        assert_eq!(
+            register(&mut rendezvous, 1, "node-a"),
+            vec![(1, Frame::Registered { node_id: "node-a".to_string(), nodes: vec![] })]
+        );
+        assert_eq!(
+            register(&mut rendezvous, 2, "node-b"),
+            vec![
+                (1, Frame::NodeJoined { node_id: "node-b".to_string() }),
+                (2, Frame::Registered { node_id: "node-b".to_string(), nodes: vec!["node-a".to_string()] }),
+            ]
+        );
+
+        let forwarded = rendezvous.receive(2, Frame::Forward { to: "node-a".to_string(), payload: hello() });
+        assert_eq!(forwarded, vec![(1, Frame::Deliver { from: "node-b".to_string(), seq: 1, payload: hello() })]);
+
+        let missing = rendezvous.receive(2, Frame::Forward { to: "node-c".to_string(), payload: hello() });
+        assert!(matches!(&missing[..], [(2, Frame::Error { .. })]));
+
+        assert_eq!(rendezvous.disconnect(1), vec![(2, Frame::NodeLeft { node_id: "node-a".to_string() })]);
+        assert_eq!(rendezvous.nodes(), vec!["node-b"]);
+        assert!(rendezvous.disconnect(1).is_empty());
+    }
+
+    #[test]
+    fn test_unregistered_connections_are_refused() {
+        let mut rendezvous = Rendezvous::new();
+        register(&mut rendezvous, 1, "node-a");
+
+        let forwarded = rendezvous.receive(2, Frame::Forward { to: "node-a".to_string(), payload: hello() });
+        assert!(matches!(&forwarded[..], [(2, Frame::Error { .. })]));
+        let spoofed = rendezvous.receive(2, Frame::Deliver { from: "node-b".to_string(), seq: 1, payload: hello() });
+        assert!(matches!(&spoofed[..], [(2, Frame::Error { .. })]));
+
+        // A connection keeps the ID it registered
+        assert!(register(&mut rendezvous, 1, "node-a").is_empty());
+        assert!(matches!(&register(&mut rendezvous, 1, "node-c")[..], [(1, Frame::Error { .. })]));
+    }
+
+    #[test]
+    fn test_reconnecting_node_takes_over_its_id() {
+        let mut rendezvous = Rendezvous::new();
+        register(&mut rendezvous, 1, "node-a");
+        register(&mut rendezvous, 2, "node-b");
+
+        let frames = register(&mut rendezvous, 3, "node-a");
+        assert!(matches!(&frames[0], (1, Frame::Error { .. })));
+        assert_eq!(frames[1], (3, Frame::Registered { node_id: "node-a".to_string(), nodes: vec!["node-b".to_string()] }));
+
+        // The old connection closing doesn't take the node away
+        assert!(rendezvous.disconnect(1).is_empty());
+        let forwarded = rendezvous.receive(2, Frame::Forward { to: "node-a".to_string(), payload: hello() });
+        assert_eq!(forwarded, vec![(3, Frame::Deliver { from: "node-b".to_string(), seq: 1, payload: hello() })]);
+    }
+
+    #[test]
+    fn test_connections_register_as_their_token_allows() {
+        let mut rendezvous = Rendezvous::new();
+        let unadmitted = rendezvous.receive(1, Frame::Register { node_id: "node-a".to_string() });
+        assert!(matches!(&unadmitted[..], [(1, Frame::Error { .. })]));
+
+        rendezvous.admit(2, "node-b".to_string());
+        let impersonating = rendezvous.receive(2, Frame::Register { node_id: "node-a".to_string() });
+        assert!(matches!(&impersonating[..], [(2, Frame::Error { .. })]));
+        assert!(rendezvous.nodes().is_empty());
+
+        let registered = rendezvous.receive(2, Frame::Register { node_id: "node-b".to_string() });
+        assert!(matches!(&registered[..], [(2, Frame::Registered { .. })]));
+        assert_eq!(rendezvous.nodes(), vec!["node-b"]);
+    }
+
+    #[test]
+    fn test_payloads_wait_for_nodes_that_are_away() {
+        let mut rendezvous = Rendezvous::new();
+        register(&mut rendezvous, 1, "node-a");
+        register(&mut rendezvous, 2, "node-b");
+        rendezvous.disconnect(2);
+
+        let forward = |payload: &str| Frame::Forward { to: "node-b".to_string(), payload: payload.into() };
+        assert!(rendezvous.receive(1, forward("one")).is_empty());
+        assert!(rendezvous.receive(1, forward("two")).is_empty());
+        assert_eq!(rendezvous.take_changes().len(), 2);
+
+        // The payloads are delivered once node-b is back, until it acknowledges them
+        let deliver = |connection, seq, payload: &str| {
+            (connection, Frame::Deliver { from: "node-a".to_string(), seq, payload: payload.into() })
+        };
+        assert_eq!(register(&mut rendezvous, 3, "node-b")[2..], [deliver(3, 1, "one"), deliver(3, 2, "two")]);
+        assert!(rendezvous.receive(3, Frame::Ack { seq: 1 }).is_empty());
+        rendezvous.disconnect(3);
+        assert_eq!(register(&mut rendezvous, 4, "node-b")[2..], [deliver(4, 2, "two")]);
+
+        // Until they expire
+        rendezvous.expire(BufferLimits::default().ttl);
+        rendezvous.disconnect(4);
+        assert_eq!(register(&mut rendezvous, 5, "node-b").len(), 2);
+    }
+
+    #[test]
+    fn test_restored_payloads_are_delivered() {
+        let mut rendezvous = Rendezvous::new().with_limits(BufferLimits { messages: 10, ttl: 60 });
+        let message = Buffered { seq: 4, from: "node-a".to_string(), payload: hello(), expires: 60 };
+        rendezvous.restore("node-b".to_string(), message);
+
+        // node-b counts as registered before, so it can be sent more
+        register(&mut rendezvous, 1, "node-a");
+        assert!(rendezvous.receive(1, Frame::Forward { to: "node-b".to_string(), payload: hello() }).is_empty());
+        let seqs: Vec<u64> = register(&mut rendezvous, 2, "node-b")
+            .into_iter()
+            .filter_map(|(_, frame)| match frame {
+                Frame::Deliver { seq, .. } => Some(seq),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(seqs, vec![4, 5]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Access tokens for relay channels.
+//!
+//! A channel is created with a secret, and whoever holds the secret issues
+//! tokens to the nodes allowed to join it. A token is
+//! `<node_id>.<expires>.<signature>`, where `expires` is a Unix time in
+//! seconds and `signature` is the hex HMAC-SHA256, keyed with the channel
+//! secret, of `<channel>\n<node_id>\n<expires>`. A token only works on the
+//! channel it was issued for, and only lets its holder register as `node_id`.
+
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Shortest secret a channel can be created with
+pub const MIN_SECRET_LEN: usize = 16;
+
+/// Why a token was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    /// Not a `<node_id>.<expires>.<signature>` token
+    Malformed,
+    /// Issued for another channel or signed with another secret
+    BadSignature,
+    /// Past its expiry time
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "Malformed token"),
+            TokenError::BadSignature => write!(f, "Token signature doesn't match the channel"),
+            TokenError::Expired => write!(f, "Token has expired"),
+        }
+    }
+}
+
+/// Check `token` against the channel's secret at Unix time `now`, returning
+/// the node ID it was issued to
+pub fn verify(token: &str, channel: &str, secret: &str, now: u64) -> Result<String, TokenError> {
+    let mut parts = token.rsplitn(3, '.');
+    let (Some(signature), Some(expires), Some(node_id)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(TokenError::Malformed);
+    };
+    let signature = hex::decode(signature).map_err(|_| TokenError::Malformed)?;
+    let expires: u64 = expires.parse().map_err(|_| TokenError::Malformed)?;
+    if node_id.is_empty() {
+        return Err(TokenError::Malformed);
+    }
+
+    mac(channel, secret, node_id, expires)
+        .verify_slice(&signature)
+        .map_err(|_| TokenError::BadSignature)?;
+    if expires <= now {
+        return Err(TokenError::Expired);
+    }
+    Ok(node_id.to_string())
+}
+
+fn mac(channel: &str, secret: &str, node_id: &str, expires: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{channel}\n{node_id}\n{expires}").as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "correct horse battery staple";
+
+    fn sign(channel: &str, secret: &str, node_id: &str, expires: u64) -> String {
+        let signature = mac(channel, secret, node_id, expires).finalize().into_bytes();
+        format!("{node_id}.{expires}.{}", hex::encode(signature))
+    }
+
+    #[test]
+    fn test_token_is_bound_to_channel_and_node() {
+        let token = sign("mesh", SECRET, "node-a", 2_000);
+        assert_eq!(verify(&token, "mesh", SECRET, 1_000), Ok("node-a".to_string()));
+        assert_eq!(verify(&token, "other", SECRET, 1_000), Err(TokenError::BadSignature));
+        assert_eq!(verify(&token, "mesh", "another secret!!", 1_000), Err(TokenError::BadSignature));
+        assert_eq!(verify(&token, "mesh", SECRET, 2_000), Err(TokenError::Expired));
+
+        // Changing the node ID or expiry breaks the signature
+        let signature = token.rsplit('.').next().unwrap();
+        assert_eq!(verify(&format!("node-b.2000.{signature}"), "mesh", SECRET, 1_000), Err(TokenError::BadSignature));
+        assert_eq!(verify(&format!("node-a.9000.{signature}"), "mesh", SECRET, 1_000), Err(TokenError::BadSignature));
+    }
+
+    #[test]
+    fn test_node_ids_may_contain_dots() {
+        let token = sign("mesh", SECRET, "node.example.com", 2_000);
+        assert_eq!(verify(&token, "mesh", SECRET, 1_000), Ok("node.example.com".to_string()));
+    }
+
+    #[test]
+    fn test_malformed_tokens() {
+        for token in ["", "node-a", "node-a.2000", ".2000.00", "node-a.soon.00", "node-a.2000.not-hex"] {
+            assert_eq!(verify(token, "mesh", SECRET, 1_000), Err(TokenError::Malformed), "{token}");
+        }
+    }
+
+    #[test]
+    fn test_tokens_match_gsio_client() {
+        // gsio-client's `relay_token` is checked against the same token
+        let token = "node-a.1700000000.d75d6c0f2ff0ca076d5d9d613d15a176e70cd3a6c25ee1bc205644c06c01533b";
+        assert_eq!(sign("mesh", SECRET, "node-a", 1_700_000_000), token);
+        assert_eq!(verify(token, "mesh", SECRET, 1_600_000_000), Ok("node-a".to_string()));
+    }
+}
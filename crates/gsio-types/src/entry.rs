@@ -0,0 +1,256 @@
+//! Ledger entries.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Represents a single entry in the distributed ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Unique identifier for the entry
+    pub id: String,
+    /// Timestamp when the entry was created
+    pub timestamp: DateTime<Utc>,
+    /// Lamport clock, higher than that of every entry the creator had seen,
+    /// so entries can be ordered where timestamps from drifting clocks can't
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub clock: u64,
+    /// Network the entry was created on; entries from before chain IDs
+    /// existed, and from nodes without one, carry none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<String>,
+    /// The actual data stored in the entry
+    pub data: JsonValue,
+    /// Hash of the previous entry in the chain
+    #[serde(default)]
+    pub previous_hash: String,
+    /// Hash of this entry
+    pub hash: String,
+    /// Node ID that created this entry
+    pub creator_node_id: String,
+    /// Signatures from nodes that have validated this entry
+    #[serde(default)]
+    pub signatures: HashMap<String, String>,
+    /// Blob reference the entry was stored with, when `data` has been
+    /// rehydrated from it; the hash covers the reference, not the data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<JsonValue>,
+}
+
+impl LedgerEntry {
+    /// Create a new ledger entry
+    pub fn new(data: JsonValue, previous_hash: String, creator_node_id: String) -> Self {
+        let timestamp = Utc::now();
+        // Several entries can be created in the same millisecond, so add a random suffix
+        let id = format!("{}-{}-{}", creator_node_id, timestamp.timestamp_millis(), Uuid::new_v4().simple());
+
+        let mut entry = Self {
+            id,
+            timestamp,
+            clock: 0,
+            chain_id: None,
+            data,
+            previous_hash,
+            hash: String::new(),
+            creator_node_id,
+            signatures: HashMap::new(),
+            blob: None,
+        };
+
+        // Calculate the hash of this entry
+        entry.hash = entry.calculate_hash();
+
+        entry
+    }
+
+    /// Set the entry's Lamport clock, rehashing it. Signatures over the old hash are dropped.
+    pub fn with_clock(mut self, clock: u64) -> Self {
+        self.clock = clock;
+        self.hash = self.calculate_hash();
+        self.signatures.clear();
+        self
+    }
+
+    /// Set the network the entry belongs to, rehashing it. Signatures over the old hash are dropped.
+    pub fn with_chain_id(mut self, chain_id: Option<String>) -> Self {
+        self.chain_id = chain_id;
+        self.hash = self.calculate_hash();
+        self.signatures.clear();
+        self
+    }
+
+    /// Calculate the hash of this entry
+    pub fn calculate_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+
+        // Hash the entry fields
+        hasher.update(self.id.as_bytes());
+        hasher.update(self.timestamp.to_rfc3339().as_bytes());
+        hasher.update(self.blob.as_ref().unwrap_or(&self.data).to_string().as_bytes());
+        hasher.update(self.previous_hash.as_bytes());
+        hasher.update(self.creator_node_id.as_bytes());
+        // Entries from before the clock existed keep their hashes
+        if self.clock != 0 {
+            hasher.update(self.clock.to_string().as_bytes());
+        }
+        if let Some(chain_id) = &self.chain_id {
+            hasher.update(chain_id.as_bytes());
+        }
+
+        // Convert the hash to a hex string
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Add a signature from a node that has validated this entry
+    pub fn add_signature(&mut self, node_id: String, signature: String) {
+        self.signatures.insert(node_id, signature);
+    }
+
+    /// Sign the entry hash with `key` on behalf of `node_id`
+    pub fn sign(&mut self, node_id: String, key: &SigningKey) {
+        let signature = key.sign(self.hash.as_bytes());
+        self.add_signature(node_id, hex::encode(signature.to_bytes()));
+    }
+
+    /// Check that `node_id` has signed this entry with the given public key
+    pub fn verify_signature(&self, node_id: &str, key: &VerifyingKey) -> bool {
+        let Some(signature) = self.signatures.get(node_id) else {
+            return false;
+        };
+        let Ok(bytes) = hex::decode(signature) else {
+            return false;
+        };
+        let Ok(signature) = Signature::from_slice(&bytes) else {
+            return false;
+        };
+
+        key.verify(self.hash.as_bytes(), &signature).is_ok()
+    }
+
+    /// Check that the entry's contents match its hash, and that data fetched
+    /// from a blob matches the reference the entry was stored with
+    pub fn is_valid(&self) -> bool {
+        let data_matches = match self.blob.as_ref().and_then(blob_hash) {
+            Some(hash) => blake3::hash(self.data.to_string().as_bytes()).to_hex().as_str() == hash,
+            None => self.blob.is_none(),
+        };
+        data_matches && self.hash == self.calculate_hash()
+    }
+
+    /// Hash of the blob holding the entry's data, if the node offloaded it
+    /// and the data hasn't been fetched yet
+    pub fn pending_blob(&self) -> Option<&str> {
+        match self.blob {
+            Some(_) => None,
+            None => blob_hash(&self.data),
+        }
+    }
+
+    /// The entry without its data, as served to light clients
+    pub fn header(&self) -> EntryHeader {
+        EntryHeader {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash.clone(),
+            hash: self.hash.clone(),
+            creator_node_id: self.creator_node_id.clone(),
+        }
+    }
+}
+
+fn is_zero(clock: &u64) -> bool {
+    *clock == 0
+}
+
+/// The blob hash in a blob reference, like `{"$blob": "<hash>", "size": 1024}`
+fn blob_hash(reference: &JsonValue) -> Option<&str> {
+    reference.get("size")?;
+    reference.get("$blob")?.as_str()
+}
+
+/// Links an entry into the chain without carrying its data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryHeader {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub previous_hash: String,
+    pub hash: String,
+    pub creator_node_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rehydrated_entry_is_checked_against_its_blob() {
+        let data = serde_json::json!({ "message": "hello" });
+        let hash = blake3::hash(data.to_string().as_bytes()).to_hex().to_string();
+        let reference = serde_json::json!({ "$blob": hash, "size": data.to_string().len() });
+        let mut entry = LedgerEntry::new(reference, "0".repeat(64), "test-node-1".to_string());
+        assert_eq!(entry.pending_blob(), Some(hash.as_str()));
+        assert!(entry.is_valid());
+
+        entry.blob = Some(std::mem::replace(&mut entry.data, data));
+        assert_eq!(entry.pending_blob(), None);
+        assert!(entry.is_valid());
+
+        entry.data = serde_json::json!({ "message": "tampered" });
+        assert!(!entry.is_valid());
+    }
+
+    #[test]
+    fn test_entries_keep_their_wire_format() {
+        let entry = LedgerEntry::new(serde_json::json!({ "message": "hello" }), "0".repeat(64), "test-node-1".to_string());
+        let mut json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["creator_node_id"], "test-node-1");
+        assert_eq!(json["timestamp"], serde_json::json!(entry.timestamp));
+        assert!(json.get("blob").is_none());
+
+        // Signatures and the previous hash may be left out
+        let object = json.as_object_mut().unwrap();
+        object.remove("signatures");
+        object.remove("previous_hash");
+        let parsed: LedgerEntry = serde_json::from_value(json).unwrap();
+        assert!(parsed.signatures.is_empty());
+        assert_eq!(parsed.header().creator_node_id, "test-node-1");
+    }
+
+    #[test]
+    fn test_clock_is_hashed_once_set() {
+        let entry = LedgerEntry::new(serde_json::json!({ "message": "hello" }), "0".repeat(64), "test-node-1".to_string());
+        let unclocked = entry.hash.clone();
+        assert!(serde_json::to_value(&entry).unwrap().get("clock").is_none());
+
+        let clocked = entry.with_clock(3);
+        assert_ne!(clocked.hash, unclocked);
+        assert!(clocked.is_valid());
+        assert_eq!(serde_json::to_value(&clocked).unwrap()["clock"], 3);
+
+        let mut tampered = clocked.clone();
+        tampered.clock = 1;
+        assert!(!tampered.is_valid());
+    }
+
+    #[test]
+    fn test_chain_id_is_hashed_once_set() {
+        let entry = LedgerEntry::new(serde_json::json!({ "message": "hello" }), "0".repeat(64), "test-node-1".to_string());
+        let unchained = entry.hash.clone();
+        assert!(serde_json::to_value(&entry).unwrap().get("chain_id").is_none());
+
+        let chained = entry.with_chain_id(Some("gsio-test".to_string()));
+        assert_ne!(chained.hash, unchained);
+        assert!(chained.is_valid());
+        assert_eq!(serde_json::to_value(&chained).unwrap()["chain_id"], "gsio-test");
+
+        // Moving the entry to another network breaks its hash
+        let mut moved = chained.clone();
+        moved.chain_id = Some("gsio-other".to_string());
+        assert!(!moved.is_valid());
+    }
+}
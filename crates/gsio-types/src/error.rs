@@ -0,0 +1,123 @@
+//! Why a node refused an entry or a request.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Why an entry or request was rejected, sent to clients and peers alongside
+/// the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The node is a follower and doesn't accept writes
+    ReadOnly,
+    /// Only validators create entries under proof of authority
+    NotValidator,
+    /// Only holders of a key on the channel's write ACL create entries
+    NotWriter,
+    /// The serialized data is larger than allowed
+    PayloadTooLarge,
+    /// A required field is missing
+    MissingField,
+    /// A field has the wrong JSON type
+    InvalidFieldType,
+    /// The data isn't signed
+    MissingSignature,
+    /// The signature doesn't match the data and public key
+    InvalidSignature,
+    /// Rejected by a custom policy
+    Rejected,
+    /// The client sent too many requests
+    RateLimited,
+    /// The entry's kind isn't registered and unknown kinds aren't accepted
+    UnknownKind,
+    /// The entry's payload doesn't match the schema registered for its kind
+    SchemaMismatch,
+    /// A transaction names an address whose checksum doesn't match
+    InvalidAddress,
+    /// A peer sent more entries in one message than the node accepts
+    TooManyEntries,
+    /// The node holds as many pending entries as it accepts
+    PendingFull,
+    /// The entry or its data is signed with a key that was rotated to a new one
+    KeyRotated,
+    /// A staking transaction's sender can't cover its amount and fee
+    InsufficientFunds,
+    /// A transaction pays less than the node's minimum fee
+    FeeTooLow,
+    /// A transaction reuses a nonce its sender already spent
+    DoubleSpend,
+    /// The entry belongs to the chain of another network
+    WrongChain,
+    /// The request is malformed
+    BadRequest,
+    /// The request lacks valid credentials
+    Unauthorized,
+    /// The requested entry, blob or resource doesn't exist
+    NotFound,
+    /// The request conflicts with the node's current state
+    Conflict,
+    /// A peer or service the request depends on couldn't be reached
+    Unavailable,
+    /// The node failed to handle a valid request
+    Internal,
+}
+
+impl ErrorCode {
+    /// The code as sent on the wire, e.g. `payload_too_large`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::NotValidator => "not_validator",
+            ErrorCode::NotWriter => "not_writer",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::MissingField => "missing_field",
+            ErrorCode::InvalidFieldType => "invalid_field_type",
+            ErrorCode::MissingSignature => "missing_signature",
+            ErrorCode::InvalidSignature => "invalid_signature",
+            ErrorCode::Rejected => "rejected",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::UnknownKind => "unknown_kind",
+            ErrorCode::SchemaMismatch => "schema_mismatch",
+            ErrorCode::InvalidAddress => "invalid_address",
+            ErrorCode::TooManyEntries => "too_many_entries",
+            ErrorCode::PendingFull => "pending_full",
+            ErrorCode::KeyRotated => "key_rotated",
+            ErrorCode::InsufficientFunds => "insufficient_funds",
+            ErrorCode::FeeTooLow => "fee_too_low",
+            ErrorCode::DoubleSpend => "double_spend",
+            ErrorCode::WrongChain => "wrong_chain",
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Internal => "internal",
+        }
+    }
+}
+
+/// An entry that failed validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ValidationError {
+    /// Create a new validation error
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code.as_str())
+    }
+}
+
+impl std::error::Error for ValidationError {}
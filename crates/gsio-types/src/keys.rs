@@ -0,0 +1,27 @@
+//! Public keys as every gsio crate writes them: Ed25519, hex-encoded.
+
+use ed25519_dalek::VerifyingKey;
+
+/// Read a hex-encoded Ed25519 public key
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid public key {key}"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key {key}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_parse_public_key() {
+        let key = SigningKey::from_bytes(&[1; 32]).verifying_key();
+        assert_eq!(parse_public_key(&hex::encode(key.to_bytes())), Ok(key));
+        assert_eq!(parse_public_key(&hex::encode(key.to_bytes()).to_uppercase()), Ok(key));
+        assert!(parse_public_key("not hex").unwrap_err().contains("not hex"));
+        assert!(parse_public_key(&hex::encode([1; 31])).is_err());
+    }
+}
@@ -0,0 +1,27 @@
+//! Data models shared by every gsio crate.
+//!
+//! Nodes, clients and wallets exchange these types over HTTP, Socket.IO and
+//! the p2p network, so they are defined once here and each crate re-exports
+//! them. A field added to one of them changes the wire format for all.
+
+mod entry;
+mod error;
+mod keys;
+mod merkle;
+mod message;
+mod query;
+mod rotation;
+mod staking;
+mod transaction;
+
+pub use entry::{EntryHeader, LedgerEntry};
+pub use error::{ErrorCode, ValidationError};
+pub use keys::parse_public_key;
+pub use merkle::{merkle_root, verify_proof, MerkleProof, MerkleTree, ProofStep, Side, EMPTY_ROOT};
+pub use message::{
+    EntryRejection, MessageType, P2PMessage, ProtocolVersions, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use query::{Filter, FilterOp, Query, QueryPage, DEFAULT_QUERY_LIMIT, MAX_QUERY_LIMIT};
+pub use rotation::{KeyRotation, KEY_ROTATION_KEY};
+pub use staking::{StakeInfo, Unbonding};
+pub use transaction::{Transaction, TransactionStatus, TransactionType};
@@ -0,0 +1,202 @@
+//! Merkle tree over ledger entry hashes, so a node can prove an entry is in
+//! its chain without sending the whole chain, and light clients can check
+//! the proof against a root they compute from the headers they hold.
+//!
+//! Leaves are `SHA-256(0x00 || entry hash)` and interior nodes are
+//! `SHA-256(0x01 || left || right)`, so a leaf can never be passed off as an
+//! interior node. A node without a sibling is carried up to the next level
+//! unchanged.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Root of a tree with no leaves
+pub const EMPTY_ROOT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Which side of the path a sibling hash sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// One step from a leaf towards the root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// Hex-encoded hash of the sibling node
+    pub hash: String,
+    /// Side the sibling is on
+    pub side: Side,
+}
+
+/// Proof that an entry is included in a ledger with a given Merkle root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// ID of the entry
+    pub entry_id: String,
+    /// Hash of the entry
+    pub entry_hash: String,
+    /// Position of the entry in the chain
+    pub index: usize,
+    /// Sibling hashes from the leaf up to the root
+    pub path: Vec<ProofStep>,
+}
+
+type Hash = [u8; 32];
+
+/// Merkle tree built from the entry hashes of a chain
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Each level of the tree, from the leaves up to the root
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    /// Build a tree over entry hashes, in chain order
+    pub fn new<'a>(entry_hashes: impl IntoIterator<Item = &'a str>) -> Self {
+        let leaves: Vec<Hash> = entry_hashes.into_iter().map(leaf_hash).collect();
+        let mut levels = vec![leaves];
+
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    /// Add the hash of the entry appended to the chain, updating only the
+    /// nodes on the path from the new leaf to the root
+    pub fn push(&mut self, entry_hash: &str) {
+        self.levels[0].push(leaf_hash(entry_hash));
+
+        let mut depth = 0;
+        while self.levels[depth].len() > 1 {
+            let level = &self.levels[depth];
+            let last = level.len() - 1;
+            let parent = if last % 2 == 1 { node_hash(&level[last - 1], &level[last]) } else { level[last] };
+            if depth + 1 == self.levels.len() {
+                self.levels.push(Vec::new());
+            }
+            let next = &mut self.levels[depth + 1];
+            match next.get_mut(last / 2) {
+                Some(node) => *node = parent,
+                None => next.push(parent),
+            }
+            depth += 1;
+        }
+    }
+
+    /// Number of leaves in the tree
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Whether the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// Hex-encoded root hash
+    pub fn root(&self) -> String {
+        match self.levels.last().unwrap().first() {
+            Some(root) => hex::encode(root),
+            None => EMPTY_ROOT.to_string(),
+        }
+    }
+
+    /// Sibling hashes from the leaf at `index` up to the root
+    pub fn path(&self, index: usize) -> Option<Vec<ProofStep>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            // The last node of an odd level has no sibling and is carried up
+            if let Some(hash) = level.get(sibling) {
+                let side = if sibling < position { Side::Left } else { Side::Right };
+                path.push(ProofStep { hash: hex::encode(hash), side });
+            }
+            position /= 2;
+        }
+
+        Some(path)
+    }
+}
+
+impl Default for MerkleTree {
+    fn default() -> Self {
+        Self { levels: vec![Vec::new()] }
+    }
+}
+
+/// Compute the Merkle root over entry hashes, in chain order
+pub fn merkle_root<'a>(entry_hashes: impl IntoIterator<Item = &'a str>) -> String {
+    MerkleTree::new(entry_hashes).root()
+}
+
+/// Check that `proof` leads from its entry hash to `root`
+pub fn verify_proof(proof: &MerkleProof, root: &str) -> bool {
+    let mut hash = leaf_hash(&proof.entry_hash);
+    for step in &proof.path {
+        let Some(sibling) = decode_hash(&step.hash) else {
+            return false;
+        };
+        hash = match step.side {
+            Side::Left => node_hash(&sibling, &hash),
+            Side::Right => node_hash(&hash, &sibling),
+        };
+    }
+    hex::encode(hash) == root
+}
+
+fn leaf_hash(entry_hash: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(entry_hash.as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn decode_hash(hash: &str) -> Option<Hash> {
+    hex::decode(hash).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_matches_rebuilding() {
+        let hashes: Vec<String> = (0..13).map(|i| format!("{i:064x}")).collect();
+        let mut tree = MerkleTree::default();
+        assert_eq!(tree.root(), EMPTY_ROOT);
+        for (i, hash) in hashes.iter().enumerate() {
+            tree.push(hash);
+            let rebuilt = MerkleTree::new(hashes[..=i].iter().map(String::as_str));
+            assert_eq!(tree.root(), rebuilt.root());
+            for index in 0..=i {
+                assert_eq!(tree.path(index), rebuilt.path(index));
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Messages nodes send each other over the p2p network.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::ValidationError;
+
+/// Newest version of the p2p protocol this build speaks
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest version of the p2p protocol this build still speaks. Version 1 is
+/// the protocol from before nodes negotiated versions.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The protocol versions a node speaks, sent in its handshake data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersions {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Default for ProtocolVersions {
+    fn default() -> Self {
+        Self { min: MIN_PROTOCOL_VERSION, max: PROTOCOL_VERSION }
+    }
+}
+
+impl ProtocolVersions {
+    /// What nodes from before version negotiation speak
+    pub const LEGACY: Self = Self { min: 1, max: 1 };
+
+    /// The newest version both sides speak, if they have one in common
+    pub fn negotiate(&self, other: &Self) -> Option<u32> {
+        let version = self.max.min(other.max);
+        (version >= self.min.max(other.min)).then_some(version)
+    }
+
+    /// Whether `version` is one this side speaks
+    pub fn contains(&self, version: u32) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+}
+
+/// Types of messages that can be sent between nodes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MessageType {
+    /// Announce a new node joining the network
+    NodeAnnounce,
+    /// Request the list of known nodes
+    NodeListRequest,
+    /// Response with the list of known nodes
+    NodeListResponse,
+    /// Announce a new ledger entry
+    EntryAnnounce,
+    /// Request a specific ledger entry
+    EntryRequest,
+    /// Response with a requested ledger entry
+    EntryResponse,
+    /// Request all ledger entries
+    LedgerSyncRequest,
+    /// Response with all ledger entries
+    LedgerSyncResponse,
+    /// Announce that the sender switched its chain to a competing branch
+    ChainReorg,
+    /// Periodic keep-alive so peers can tell the connection is healthy
+    Heartbeat,
+    /// The sender is shutting down and closing its connections
+    NodeLeave,
+    /// Entries the sender refused under its validation policy
+    EntryRejected,
+    /// Sent by the accepting node: proof of its own key and a challenge for the connecting node
+    AuthChallenge,
+    /// Sent by the connecting node: its signature over the accepting node's challenge
+    AuthResponse,
+    /// Another message sealed for the recipient, see `gsio_node::envelope`
+    Encrypted,
+    /// The sender refused the handshake, with its reason and the protocol versions it speaks
+    HandshakeRejected,
+}
+
+/// A message sent between nodes in the p2p network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct P2PMessage {
+    /// Type of message
+    pub message_type: MessageType,
+    /// Unique ID for this message
+    pub message_id: String,
+    /// ID of the node that sent this message
+    pub sender_id: String,
+    /// ID of the node that should receive this message (empty for broadcast)
+    pub recipient_id: String,
+    /// The actual message payload
+    pub payload: JsonValue,
+    /// Protocol version the message is written in; messages from nodes
+    /// that predate versioning carry none and are version 1
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    /// `message_id` of the request this message answers, if it's a reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+    /// Network the sender is on, if it has a chain ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<String>,
+}
+
+fn legacy_version() -> u32 {
+    ProtocolVersions::LEGACY.max
+}
+
+impl P2PMessage {
+    /// Create a new p2p message
+    pub fn new(
+        message_type: MessageType,
+        sender_id: String,
+        recipient_id: String,
+        payload: JsonValue,
+    ) -> Self {
+        Self {
+            message_type,
+            message_id: Uuid::new_v4().to_string(),
+            sender_id,
+            recipient_id,
+            payload,
+            version: PROTOCOL_VERSION,
+            in_reply_to: None,
+            chain_id: None,
+        }
+    }
+
+    /// Mark the message as the reply to `request`
+    pub fn reply_to(mut self, request: &P2PMessage) -> Self {
+        self.in_reply_to = Some(request.message_id.clone());
+        self
+    }
+
+    /// Mark the message as sent on the network `chain_id`
+    pub fn with_chain_id(mut self, chain_id: Option<String>) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+}
+
+/// An entry a node refused under its validation policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryRejection {
+    /// ID of the refused entry
+    pub entry_id: String,
+    #[serde(flatten)]
+    pub error: ValidationError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_version() {
+        let ours = ProtocolVersions::default();
+        assert_eq!(ours.negotiate(&ours), Some(PROTOCOL_VERSION));
+        assert_eq!(ours.negotiate(&ProtocolVersions::LEGACY), Some(1));
+        assert_eq!(ours.negotiate(&ProtocolVersions { min: 1, max: 9 }), Some(PROTOCOL_VERSION));
+        assert_eq!(ours.negotiate(&ProtocolVersions { min: PROTOCOL_VERSION + 1, max: 9 }), None);
+        assert!(ours.contains(1) && !ours.contains(PROTOCOL_VERSION + 1));
+    }
+
+    #[test]
+    fn test_messages_without_a_version_are_version_1() {
+        let message = P2PMessage::new(MessageType::Heartbeat, "a".to_string(), String::new(), serde_json::json!({}));
+        let mut json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["version"], PROTOCOL_VERSION);
+
+        json.as_object_mut().unwrap().remove("version");
+        let legacy: P2PMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.version, 1);
+    }
+
+    #[test]
+    fn test_replies_name_their_request() {
+        let request = P2PMessage::new(MessageType::EntryRequest, "a".to_string(), "b".to_string(), serde_json::json!({}));
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("in_reply_to").is_none());
+
+        let reply = P2PMessage::new(MessageType::EntryResponse, "b".to_string(), "a".to_string(), serde_json::json!({}))
+            .reply_to(&request);
+        let json = serde_json::to_value(&reply).unwrap();
+        assert_eq!(json["in_reply_to"], request.message_id.as_str());
+    }
+}
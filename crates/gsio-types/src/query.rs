@@ -0,0 +1,376 @@
+//! Queries over ledger entries, evaluated by the node.
+//!
+//! A query narrows entries by creator and time range and by conditions on
+//! their data. Conditions name a value inside `data` with a JSONPath-like
+//! path: `$` is the data itself, `.name` or `["name"]` picks an object field
+//! and `[0]` an array element, so `$.items[0].price` is the price of the first
+//! item. An entry matches when every condition holds.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::LedgerEntry;
+
+/// Entries a query returns when it doesn't set a limit
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// Most entries a query returns; larger limits are lowered to this
+pub const MAX_QUERY_LIMIT: usize = 1000;
+
+/// How a [`Filter`] compares the value at its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    /// Equal to the filter's value; numbers compare by value, so `1` equals `1.0`
+    Eq,
+    /// Present but not equal to the filter's value
+    Ne,
+    /// Greater than the filter's value; numbers compare numerically, strings lexicographically
+    Gt,
+    /// Greater than or equal to the filter's value
+    Gte,
+    /// Less than the filter's value
+    Lt,
+    /// Less than or equal to the filter's value
+    Lte,
+    /// A string containing the filter's value, or an array with an element equal to it
+    Contains,
+    /// Present, whatever its value; the filter's value is ignored
+    Exists,
+}
+
+impl fmt::Display for FilterOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            FilterOp::Eq => "eq",
+            FilterOp::Ne => "ne",
+            FilterOp::Gt => "gt",
+            FilterOp::Gte => "gte",
+            FilterOp::Lt => "lt",
+            FilterOp::Lte => "lte",
+            FilterOp::Contains => "contains",
+            FilterOp::Exists => "exists",
+        };
+        f.write_str(op)
+    }
+}
+
+/// A condition on the value at `path` in an entry's data.
+///
+/// Only [`FilterOp::Exists`] says anything about a path that's missing; any
+/// other filter on a missing path doesn't match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Filter {
+    pub path: String,
+    pub op: FilterOp,
+    #[serde(default)]
+    pub value: JsonValue,
+}
+
+impl Filter {
+    /// Compare the value at `path` with `value`
+    pub fn new(path: impl Into<String>, op: FilterOp, value: impl Into<JsonValue>) -> Self {
+        Self { path: path.into(), op, value: value.into() }
+    }
+
+    pub fn eq(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Eq, value)
+    }
+
+    pub fn ne(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Ne, value)
+    }
+
+    pub fn gt(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Gt, value)
+    }
+
+    pub fn gte(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Gte, value)
+    }
+
+    pub fn lt(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Lt, value)
+    }
+
+    pub fn lte(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Lte, value)
+    }
+
+    pub fn contains(path: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Self::new(path, FilterOp::Contains, value)
+    }
+
+    pub fn exists(path: impl Into<String>) -> Self {
+        Self::new(path, FilterOp::Exists, JsonValue::Null)
+    }
+
+    /// Whether `data` satisfies the filter; a path that doesn't parse never matches
+    pub fn matches(&self, data: &JsonValue) -> bool {
+        let Ok(segments) = parse_path(&self.path) else { return false };
+        let Some(found) = select(data, &segments) else { return false };
+
+        match self.op {
+            FilterOp::Eq => json_eq(found, &self.value),
+            FilterOp::Ne => !json_eq(found, &self.value),
+            FilterOp::Gt => compare(found, &self.value).is_some_and(|o| o.is_gt()),
+            FilterOp::Gte => compare(found, &self.value).is_some_and(|o| o.is_ge()),
+            FilterOp::Lt => compare(found, &self.value).is_some_and(|o| o.is_lt()),
+            FilterOp::Lte => compare(found, &self.value).is_some_and(|o| o.is_le()),
+            FilterOp::Contains => match (found, &self.value) {
+                (JsonValue::String(s), JsonValue::String(part)) => s.contains(part.as_str()),
+                (JsonValue::Array(items), value) => items.iter().any(|item| json_eq(item, value)),
+                _ => false,
+            },
+            FilterOp::Exists => true,
+        }
+    }
+}
+
+/// Which ledger entries to return, and which page of them.
+///
+/// Built up with chained calls:
+///
+/// ```
+/// use gsio_types::{Filter, Query};
+///
+/// let query = Query::new()
+///     .creator("node-1")
+///     .filter(Filter::eq("$.kind", "transfer"))
+///     .filter(Filter::gte("$.amount", 100))
+///     .limit(20);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Query {
+    /// Conditions on entry data, all of which have to hold
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<Filter>,
+    /// Only entries created by this node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    /// Only entries created strictly after this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries created at or before this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+    /// Number of matching entries to skip
+    pub offset: usize,
+    /// Most entries to return, [`DEFAULT_QUERY_LIMIT`] if unset and at most [`MAX_QUERY_LIMIT`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// A query matching every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a condition on entry data
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn creator(mut self, node_id: impl Into<String>) -> Self {
+        self.creator = Some(node_id.into());
+        self
+    }
+
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Number of entries a page of results holds at most
+    pub fn page_size(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT)
+    }
+
+    /// Check that every filter's path parses
+    pub fn validate(&self) -> Result<(), String> {
+        for filter in &self.filters {
+            parse_path(&filter.path).map_err(|e| format!("Invalid path {:?} in {} filter: {e}", filter.path, filter.op))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the entry's creator and timestamp fall within the query
+    pub fn matches_metadata(&self, entry: &LedgerEntry) -> bool {
+        self.creator.as_ref().is_none_or(|creator| entry.creator_node_id == *creator)
+            && self.since.is_none_or(|since| entry.timestamp > since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+    }
+
+    /// Whether `data` satisfies every filter
+    pub fn matches_data(&self, data: &JsonValue) -> bool {
+        self.filters.iter().all(|filter| filter.matches(data))
+    }
+
+    /// Whether the entry matches the query. The data of an offloaded entry
+    /// has to be fetched first, since the filters only see the blob reference.
+    pub fn matches(&self, entry: &LedgerEntry) -> bool {
+        self.matches_metadata(entry) && self.matches_data(&entry.data)
+    }
+}
+
+/// A page of entries matching a [`Query`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryPage {
+    /// Matching entries, in chain order
+    pub entries: Vec<LedgerEntry>,
+    /// Offset to query from for the next page, if there are more matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+}
+
+/// A step along a filter path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+/// Parse a path like `$.items[0].price` or `$["odd key"]`; the leading `$` is optional
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    // `name` on its own is short for `$.name`
+    let bare;
+    let mut rest = match path.strip_prefix('$') {
+        Some(rest) => rest,
+        None if path.is_empty() || path.starts_with(['.', '[']) => path,
+        None => {
+            bare = format!(".{path}");
+            &bare
+        }
+    };
+
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err("empty field name".to_string());
+            }
+            segments.push(Segment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix("[\"") {
+            let end = after.find("\"]").ok_or("unclosed `[\"`")?;
+            segments.push(Segment::Field(after[..end].to_string()));
+            rest = &after[end + 2..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or("unclosed `[`")?;
+            let index = after[..end].parse().map_err(|_| format!("invalid index `{}`", &after[..end]))?;
+            segments.push(Segment::Index(index));
+            rest = &after[end + 1..];
+        } else {
+            return Err(format!("unexpected `{rest}`"));
+        }
+    }
+    Ok(segments)
+}
+
+/// The value `segments` lead to inside `data`, if there is one
+fn select<'a>(data: &'a JsonValue, segments: &[Segment]) -> Option<&'a JsonValue> {
+    segments.iter().try_fold(data, |value, segment| match segment {
+        Segment::Field(name) => value.as_object()?.get(name),
+        Segment::Index(index) => value.as_array()?.get(*index),
+    })
+}
+
+fn json_eq(a: &JsonValue, b: &JsonValue) -> bool {
+    match (a, b) {
+        (JsonValue::Number(x), JsonValue::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// Order two numbers or two strings; other values don't compare
+fn compare(a: &JsonValue, b: &JsonValue) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (JsonValue::Number(x), JsonValue::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (JsonValue::String(x), JsonValue::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_paths() {
+        let data = json!({ "items": [{ "price": 5 }, { "price": 12 }], "odd key": true, "kind": "transfer" });
+
+        let select_path = |path: &str| select(&data, &parse_path(path).unwrap()).cloned();
+        assert_eq!(select_path("$"), Some(data.clone()));
+        assert_eq!(select_path("$.items[1].price"), Some(json!(12)));
+        assert_eq!(select_path("items[0].price"), Some(json!(5)));
+        assert_eq!(select_path("kind"), Some(json!("transfer")));
+        assert_eq!(select_path("$[\"odd key\"]"), Some(json!(true)));
+        assert_eq!(select_path("$.items[2]"), None);
+        assert_eq!(select_path("$.kind.name"), None);
+
+        assert!(parse_path("$.").is_err());
+        assert!(parse_path("$kind").is_err());
+        assert!(parse_path("$.items[x]").is_err());
+        assert!(parse_path("$.items[0").is_err());
+    }
+
+    #[test]
+    fn test_filters() {
+        let data = json!({ "amount": 100, "memo": "rent for may", "tags": ["home", "monthly"], "due": "2025-06-01" });
+
+        assert!(Filter::eq("$.amount", 100.0).matches(&data));
+        assert!(Filter::ne("$.amount", 50).matches(&data));
+        assert!(!Filter::ne("$.missing", 50).matches(&data));
+        assert!(Filter::gt("$.amount", 99).matches(&data));
+        assert!(Filter::gte("$.amount", 100).matches(&data));
+        assert!(!Filter::lt("$.amount", 100).matches(&data));
+        assert!(Filter::lte("$.due", "2025-06-01").matches(&data));
+        assert!(!Filter::gt("$.amount", "99").matches(&data));
+        assert!(Filter::contains("$.memo", "rent").matches(&data));
+        assert!(Filter::contains("$.tags", "monthly").matches(&data));
+        assert!(!Filter::contains("$.amount", 1).matches(&data));
+        assert!(Filter::exists("$.tags[1]").matches(&data));
+        assert!(!Filter::exists("$.tags[2]").matches(&data));
+    }
+
+    #[test]
+    fn test_query_matches_entry() {
+        let entry = LedgerEntry::new(json!({ "kind": "transfer", "amount": 10 }), "0".repeat(64), "node-1".to_string());
+
+        assert!(Query::new().matches(&entry));
+        assert!(Query::new().creator("node-1").filter(Filter::eq("kind", "transfer")).matches(&entry));
+        assert!(!Query::new().creator("node-2").matches(&entry));
+        assert!(!Query::new().filter(Filter::gt("amount", 10)).matches(&entry));
+        assert!(Query::new().until(entry.timestamp).matches(&entry));
+        assert!(!Query::new().since(entry.timestamp).matches(&entry));
+
+        let query = Query::new().filter(Filter::eq("$.kind", "transfer")).limit(5000);
+        assert_eq!(query.page_size(), MAX_QUERY_LIMIT);
+        let parsed: Query = serde_json::from_value(serde_json::to_value(&query).unwrap()).unwrap();
+        assert_eq!(parsed, query);
+        assert!(Query::new().filter(Filter::eq("$..kind", 1)).validate().is_err());
+    }
+}
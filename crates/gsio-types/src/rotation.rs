@@ -0,0 +1,92 @@
+//! Statements retiring a key in favour of a new one.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::parse_public_key;
+
+/// Key of the rotation statement in the data of an entry recording one
+pub const KEY_ROTATION_KEY: &str = "key_rotation";
+
+/// A key's holder naming the key that replaces it, signed with the old key.
+///
+/// Recorded on a ledger under [`KEY_ROTATION_KEY`], it retires `old_key`:
+/// nodes accept `new_key` wherever they accepted `old_key` and refuse
+/// `old_key` for anything signed after the statement. Entries signed before
+/// it stay valid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyRotation {
+    /// Hex-encoded public key being retired
+    pub old_key: String,
+    /// Hex-encoded public key replacing it
+    pub new_key: String,
+    /// Hex-encoded signature by `old_key` over [`KeyRotation::signing_bytes`]
+    pub signature: String,
+}
+
+impl KeyRotation {
+    /// Retire `old` in favour of `new`, signing the statement with `old`
+    pub fn new(old: &SigningKey, new: &VerifyingKey) -> Self {
+        let old_key = hex::encode(old.verifying_key().to_bytes());
+        let new_key = hex::encode(new.to_bytes());
+        let signature = hex::encode(old.sign(&Self::signing_bytes(&old_key, &new_key)).to_bytes());
+        Self { old_key, new_key, signature }
+    }
+
+    /// Bytes the old key signs. Signers that don't hold an ed25519-dalek
+    /// key, such as wallets and external signers, sign these and build the
+    /// statement themselves.
+    pub fn signing_bytes(old_key: &str, new_key: &str) -> Vec<u8> {
+        format!("gsio-key-rotation:{}:{}", old_key.to_lowercase(), new_key.to_lowercase()).into_bytes()
+    }
+
+    /// Entry data that records this statement
+    pub fn to_entry_data(&self) -> JsonValue {
+        json!({ KEY_ROTATION_KEY: self })
+    }
+
+    /// The statement carried by entry data, if any
+    pub fn from_data(data: &JsonValue) -> Option<Result<Self, String>> {
+        let rotation = data.get(KEY_ROTATION_KEY)?;
+        Some(serde_json::from_value(rotation.clone()).map_err(|e| format!("Invalid key rotation: {e}")))
+    }
+
+    /// Check that both keys are valid and different and that the old key signed the statement
+    pub fn verify(&self) -> Result<(), String> {
+        let old = parse_public_key(&self.old_key)?;
+        parse_public_key(&self.new_key)?;
+        if self.old_key.eq_ignore_ascii_case(&self.new_key) {
+            return Err("A key can't be rotated to itself".to_string());
+        }
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| "Invalid key rotation signature encoding".to_string())?;
+        old.verify(&Self::signing_bytes(&self.old_key, &self.new_key), &signature)
+            .map_err(|_| "Key rotation isn't signed by the old key".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_rotation() {
+        let old = SigningKey::from_bytes(&[1; 32]);
+        let new = SigningKey::from_bytes(&[2; 32]);
+        let rotation = KeyRotation::new(&old, &new.verifying_key());
+        rotation.verify().unwrap();
+        assert_eq!(KeyRotation::from_data(&rotation.to_entry_data()), Some(Ok(rotation.clone())));
+        assert_eq!(KeyRotation::from_data(&json!({ "message": "hello" })), None);
+
+        // Only the old key can retire itself
+        let forged = KeyRotation { signature: KeyRotation::new(&new, &new.verifying_key()).signature, ..rotation.clone() };
+        assert!(forged.verify().is_err());
+        let swapped = KeyRotation { old_key: rotation.new_key.clone(), new_key: rotation.old_key.clone(), ..rotation };
+        assert!(swapped.verify().is_err());
+        assert!(KeyRotation::new(&old, &old.verifying_key()).verify().is_err());
+    }
+}
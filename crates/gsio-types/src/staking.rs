@@ -0,0 +1,30 @@
+//! Stakes locked by `Stake` transactions.
+
+use serde::{Deserialize, Serialize};
+
+/// Stake on its way back to the balance after an `Unstake` transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unbonding {
+    pub amount: u64,
+    /// Ledger height from which the amount is part of the balance again
+    pub release_height: usize,
+}
+
+/// An address's balance and stake, as of a node's chain tip
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakeInfo {
+    pub address: String,
+    /// What the address can spend or stake: credits minus what it sent, paid in fees and staked
+    pub balance: u64,
+    /// Stake locked by `Stake` transactions and not yet unstaked
+    pub staked: u64,
+    /// Unstaked amounts still unbonding, oldest first
+    pub unbonding: Vec<Unbonding>,
+}
+
+impl StakeInfo {
+    /// Total still unbonding
+    pub fn unbonding_total(&self) -> u64 {
+        self.unbonding.iter().fold(0, |total, unbonding| total.saturating_add(unbonding.amount))
+    }
+}
@@ -0,0 +1,98 @@
+//! Value transfers between accounts.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// Transaction type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TransactionType {
+    Transfer,
+    Stake,
+    Unstake,
+    // Other transaction types can be added here
+}
+
+impl TransactionType {
+    /// The type's name, as node fee rules refer to it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionType::Transfer => "Transfer",
+            TransactionType::Stake => "Stake",
+            TransactionType::Unstake => "Unstake",
+        }
+    }
+}
+
+/// Transaction status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    Pending,
+    Confirmed,
+    Failed,
+}
+
+/// Transaction record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: String,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+    pub fee: u64,
+    /// Position among the sender's transactions; each nonce is only honoured once per sender
+    #[serde(default)]
+    pub nonce: u64,
+    pub sender: String,
+    pub recipient: String,
+    pub timestamp: DateTime<Utc>,
+    pub status: TransactionStatus,
+    pub signature: Option<String>,
+    pub data: Option<JsonValue>,
+}
+
+/// Fields of a transaction covered by its signature
+#[derive(Serialize)]
+struct SigningPayload<'a> {
+    id: &'a str,
+    transaction_type: &'a TransactionType,
+    amount: u64,
+    fee: u64,
+    /// Left out when zero, so transactions signed before nonces existed still verify
+    #[serde(skip_serializing_if = "is_zero")]
+    nonce: u64,
+    sender: &'a str,
+    recipient: &'a str,
+    timestamp: String,
+    data: &'a Option<JsonValue>,
+}
+
+impl Transaction {
+    /// Canonical byte encoding of the signed fields.
+    ///
+    /// Status and signature are excluded since they change after signing.
+    /// Object keys in `data` serialize in sorted order, so the encoding is
+    /// stable across serialization round trips.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let payload = SigningPayload {
+            id: &self.id,
+            transaction_type: &self.transaction_type,
+            amount: self.amount,
+            fee: self.fee,
+            nonce: self.nonce,
+            sender: &self.sender,
+            recipient: &self.recipient,
+            timestamp: self.timestamp.to_rfc3339(),
+            data: &self.data,
+        };
+        serde_json::to_vec(&payload).expect("transaction fields are always serializable")
+    }
+
+    /// Length of `data` serialized as JSON, which fees are charged by
+    pub fn payload_size(&self) -> usize {
+        self.data.as_ref().map_or(0, |data| data.to_string().len())
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
@@ -4,17 +4,27 @@
 //! It allows creating and managing wallets, generating and storing keys,
 //! signing transactions, and tracking balances.
 
-use chrono::{DateTime, Utc};
-use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, SignatureError};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use bip39::Mnemonic;
+use chrono::Utc;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, SignatureError, Verifier};
+use gsio_client::{Address, ErrorCode, FeeFloor, FeeRules, GsioClient, GsioClientError, LedgerEntry};
+pub use gsio_types::{KeyRotation, Transaction, TransactionStatus, TransactionType};
+use hmac::{Hmac, Mac};
 use rand::rngs::OsRng;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use sha2::Sha512;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 /// Error type for GSIO wallet operations
@@ -40,38 +50,227 @@ pub enum WalletError {
 
     #[error("Insufficient funds: required {0}, available {1}")]
     InsufficientFunds(u64, u64),
+
+    #[error("Failed to decrypt wallet key: wrong passphrase or corrupted file")]
+    DecryptionFailed,
+
+    #[error("Node error: {0}")]
+    NodeError(#[from] GsioClientError),
+
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+
+    #[error("Invalid derivation index: {0}")]
+    InvalidDerivationIndex(u32),
+
+    #[error("Transaction not signed: {0}")]
+    UnsignedTransaction(String),
+
+    #[error("Transaction {0} was not confirmed in time")]
+    ConfirmationTimeout(String),
+
+    /// The node already has a transaction from the sender with this nonce
+    #[error("Nonce {1} of {0} was already spent")]
+    DoubleSpend(String, u64),
+
+    #[error("Fee rules haven't been fetched from a node")]
+    FeeRulesUnknown,
+
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+
+    /// The node's chain reorganized under every attempt to read it
+    #[error("The ledger kept changing while syncing")]
+    ChainChanged,
+
+    #[error("Invalid contact alias: {0}")]
+    InvalidAlias(String),
 }
 
-/// Transaction type
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum TransactionType {
-    Transfer,
-    Stake,
-    Unstake,
-    // Other transaction types can be added here
+/// Signs for an account whose key may live outside the wallet, such as in an
+/// HSM, the OS keychain or a remote signing service
+pub trait Signer: Send + Sync {
+    /// The Ed25519 public key signatures verify against
+    fn public_key(&self) -> [u8; 32];
+
+    /// Sign `message` with Ed25519, returning the signature
+    fn sign(&self, message: &[u8]) -> Result<[u8; 64], WalletError>;
 }
 
-/// Transaction status
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum TransactionStatus {
-    Pending,
-    Confirmed,
-    Failed,
+impl Signer for Keypair {
+    fn public_key(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<[u8; 64], WalletError> {
+        Ok(ed25519_dalek::Signer::sign(self, message).to_bytes())
+    }
 }
 
-/// Transaction record
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Transaction {
-    pub id: String,
-    pub transaction_type: TransactionType,
-    pub amount: u64,
-    pub fee: u64,
-    pub sender: String,
-    pub recipient: String,
-    pub timestamp: DateTime<Utc>,
-    pub status: TransactionStatus,
-    pub signature: Option<String>,
-    pub data: Option<JsonValue>,
+/// Derive the gsio address for a public key
+pub fn address_from_public_key(public_key: &PublicKey) -> String {
+    Address::from_public_key(&public_key.to_bytes()).to_string()
+}
+
+/// Whether `address` is a gsio address with a checksum that matches
+pub fn is_valid_address(address: &str) -> bool {
+    Address::parse(address).is_ok()
+}
+
+/// `address` written the current way, if it is an address at all. Ledger
+/// entries and wallet files from before addresses had a checksum still
+/// name accounts `gsio_<hex>`.
+fn canonical_address(address: &str) -> Option<String> {
+    Address::parse_any(address).ok().map(|address| address.to_string())
+}
+
+/// Offset marking a SLIP-0010 child index as hardened
+const HARDENED: u32 = 0x8000_0000;
+
+/// Coin type in the account derivation path `m/44'/9999'/index'`
+const COIN_TYPE: u32 = 9999;
+
+/// SLIP-0010 extended private key for ed25519
+#[derive(Clone)]
+struct ExtendedKey {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// Derive the master key for a BIP39 seed
+    fn master(seed: &[u8]) -> Self {
+        Self::from_hmac(b"ed25519 seed", &[seed])
+    }
+
+    /// Derive a hardened child; ed25519 has no non-hardened derivation
+    fn derive_hardened(&self, index: u32) -> Self {
+        Self::from_hmac(&self.chain_code, &[&[0], &self.secret, &(index | HARDENED).to_be_bytes()])
+    }
+
+    /// Derive the key for an account, at `m/44'/9999'/index'`
+    fn derive_account(&self, index: u32) -> Self {
+        [44, COIN_TYPE, index].iter().fold(self.clone(), |key, &i| key.derive_hardened(i))
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        for chunk in data {
+            mac.update(chunk);
+        }
+        let output = mac.finalize().into_bytes();
+
+        // The left half is the private key; the right half is the chain code
+        let mut secret = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        secret.copy_from_slice(&output[..32]);
+        chain_code.copy_from_slice(&output[32..]);
+        Self { secret, chain_code }
+    }
+
+    fn keypair(&self) -> Result<Keypair, WalletError> {
+        let secret = SecretKey::from_bytes(&self.secret)?;
+        let public = PublicKey::from(&secret);
+        Ok(Keypair { secret, public })
+    }
+
+    fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.secret);
+        bytes[32..].copy_from_slice(&self.chain_code);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WalletError> {
+        if bytes.len() != 64 {
+            return Err(WalletError::InvalidWalletData("Invalid master key length".to_string()));
+        }
+        let mut secret = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        secret.copy_from_slice(&bytes[..32]);
+        chain_code.copy_from_slice(&bytes[32..]);
+        Ok(Self { secret, chain_code })
+    }
+}
+
+/// Verify a transaction's signature against the sender's hex-encoded public key
+pub fn verify_transaction(transaction: &Transaction, public_key: &str) -> Result<(), WalletError> {
+    let public_key = PublicKey::from_bytes(
+        &hex::decode(public_key).map_err(|e| WalletError::InvalidWalletData(format!("Invalid public key: {e}")))?,
+    )?;
+
+    // The key must belong to the sender, not just any signer
+    if Address::parse_any(&transaction.sender) != Ok(Address::from_public_key(&public_key.to_bytes())) {
+        return Err(WalletError::KeyNotFound(transaction.sender.clone()));
+    }
+
+    let signature = transaction
+        .signature
+        .as_ref()
+        .ok_or_else(|| WalletError::InvalidWalletData("Transaction is not signed".to_string()))?;
+    verify_signature(&public_key, &transaction.signing_bytes(), signature)
+}
+
+/// Prepended to messages before they are signed, so a signed message can
+/// never pass for a signed transaction or entry
+const MESSAGE_PREFIX: &[u8] = b"GSIO Signed Message:\n";
+
+fn message_signing_bytes(message: &[u8]) -> Vec<u8> {
+    [MESSAGE_PREFIX, message].concat()
+}
+
+/// Verify a hex-encoded signature over `message`, as made by
+/// `Wallet::sign_message`, against a hex-encoded public key
+pub fn verify_message(message: &[u8], signature: &str, public_key: &str) -> Result<(), WalletError> {
+    let public_key = PublicKey::from_bytes(
+        &hex::decode(public_key).map_err(|e| WalletError::InvalidWalletData(format!("Invalid public key: {e}")))?,
+    )?;
+    verify_signature(&public_key, &message_signing_bytes(message), signature)
+}
+
+fn verify_signature(public_key: &PublicKey, message: &[u8], signature: &str) -> Result<(), WalletError> {
+    let signature = Signature::from_bytes(
+        &hex::decode(signature).map_err(|e| WalletError::InvalidWalletData(format!("Invalid signature: {e}")))?,
+    )?;
+    public_key.verify(message, &signature)?;
+    Ok(())
+}
+
+/// Extract a transaction from a ledger entry, if the entry records one.
+///
+/// Transactions are stored as `{"type": "transaction", "transaction": {...}}`,
+/// optionally with the sender's hex-encoded `public_key` alongside.
+fn ledger_transaction(entry: &LedgerEntry) -> Option<(Transaction, Option<&str>)> {
+    if entry.data.get("type").and_then(|t| t.as_str()) != Some("transaction") {
+        return None;
+    }
+    let transaction = serde_json::from_value(entry.data.get("transaction")?.clone()).ok()?;
+    let public_key = entry.data.get("public_key").and_then(|k| k.as_str());
+    Some((transaction, public_key))
+}
+
+/// Read a node's whole chain a page at a time, each page asked for by the
+/// hash of the last entry before it, so entries can't be skipped or counted
+/// twice when the chain grows in between. If that entry leaves the chain, as
+/// in a reorg, the chain is read again from the start.
+async fn fetch_chain(client: &GsioClient) -> Result<Vec<LedgerEntry>, WalletError> {
+    for _ in 0..SYNC_ATTEMPTS {
+        let mut entries = client.get_ledger_paginated(0, SYNC_PAGE_SIZE).await?;
+        let mut done = entries.len() < SYNC_PAGE_SIZE;
+        while !done {
+            let last = &entries[entries.len() - 1].hash;
+            let Some(page) = client.get_ledger_after(last, SYNC_PAGE_SIZE).await? else {
+                break;
+            };
+            done = page.len() < SYNC_PAGE_SIZE;
+            entries.extend(page);
+        }
+        if done {
+            return Ok(entries);
+        }
+        warn!("The ledger changed while syncing, reading it again");
+    }
+    Err(WalletError::ChainChanged)
 }
 
 /// Wallet account information
@@ -79,25 +278,186 @@ pub struct Transaction {
 pub struct Account {
     pub address: String,
     pub public_key: String,
+    /// What the account can spend or stake
     pub balance: u64,
+    /// Locked by `Stake` transactions and not yet unstaked
+    #[serde(default)]
+    pub staked: u64,
+    /// Unstaked but still in the node's unbonding period, so not yet part of `balance`
+    #[serde(default)]
+    pub unbonding: u64,
+    /// Nonce after the highest one the account has used on the ledger
     pub nonce: u64,
+    /// Nonce the next transaction created from the account gets; ahead of
+    /// `nonce` while created transactions are unconfirmed
+    #[serde(default)]
+    pub next_nonce: u64,
     pub transactions: Vec<String>,
+    /// Index the account's key was derived at, if it came from the wallet's mnemonic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_index: Option<u32>,
+}
+
+/// Number of ledger entries fetched per request while syncing
+const SYNC_PAGE_SIZE: usize = 500;
+
+/// Times a sync reads the chain before giving up on it changing under it
+const SYNC_ATTEMPTS: usize = 3;
+
+/// How long to wait for a submitted transaction to appear in the ledger
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay between confirmation checks
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Current version of the on-disk wallet format
+const WALLET_FILE_VERSION: u32 = 2;
+
+/// Secret key encrypted under a passphrase-derived key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptedKey {
+    public_key: String,
+    /// Argon2 salt
+    salt: String,
+    /// AES-GCM nonce
+    nonce: String,
+    ciphertext: String,
+}
+
+/// On-disk representation of a wallet.
+///
+/// Derived keys aren't stored; they are derived again from the master key
+/// using each account's `derivation_index`.
+#[derive(Debug, Serialize, Deserialize)]
+struct WalletFile {
+    version: u32,
+    accounts: HashMap<String, Account>,
+    /// Keys that weren't derived from the master key
+    #[serde(default)]
+    keys: Vec<EncryptedKey>,
+    #[serde(default)]
+    master: Option<EncryptedKey>,
+    /// Version 1 wallets held a single key
+    #[serde(default, skip_serializing)]
+    key: Option<EncryptedKey>,
+    /// Address book, by alias
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    contacts: BTreeMap<String, String>,
+}
+
+/// Derive an AES-256 key from a passphrase with Argon2
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, WalletError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| WalletError::InvalidWalletData(format!("Key derivation failed: {e}")))?;
+    Aes256Gcm::new_from_slice(&key)
+        .map_err(|e| WalletError::InvalidWalletData(format!("Invalid key length: {e}")))
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, WalletError> {
+    hex::decode(value).map_err(|e| WalletError::InvalidWalletData(format!("Invalid {field}: {e}")))
+}
+
+impl EncryptedKey {
+    fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self, WalletError> {
+        Self::encrypt_secret(keypair.secret.as_bytes(), &keypair.public, passphrase)
+    }
+
+    fn encrypt_master(master: &ExtendedKey, passphrase: &str) -> Result<Self, WalletError> {
+        Self::encrypt_secret(&master.to_bytes(), &master.keypair()?.public, passphrase)
+    }
+
+    fn decrypt(&self, passphrase: &str) -> Result<Keypair, WalletError> {
+        let secret = SecretKey::from_bytes(&self.decrypt_secret(passphrase)?)?;
+        let public = PublicKey::from(&secret);
+        self.check_public_key(&public)?;
+        Ok(Keypair { secret, public })
+    }
+
+    fn decrypt_master(&self, passphrase: &str) -> Result<ExtendedKey, WalletError> {
+        let master = ExtendedKey::from_bytes(&self.decrypt_secret(passphrase)?)?;
+        self.check_public_key(&master.keypair()?.public)?;
+        Ok(master)
+    }
+
+    fn encrypt_secret(secret: &[u8], public: &PublicKey, passphrase: &str) -> Result<Self, WalletError> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let cipher = derive_key(passphrase, &salt)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), secret)
+            .map_err(|_| WalletError::InvalidWalletData("Encryption failed".to_string()))?;
+
+        Ok(Self {
+            public_key: hex::encode(public.to_bytes()),
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn decrypt_secret(&self, passphrase: &str) -> Result<Vec<u8>, WalletError> {
+        let salt = decode_hex("salt", &self.salt)?;
+        let nonce = decode_hex("nonce", &self.nonce)?;
+        let ciphertext = decode_hex("ciphertext", &self.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(WalletError::InvalidWalletData("Invalid nonce length".to_string()));
+        }
+
+        let cipher = derive_key(passphrase, &salt)?;
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| WalletError::DecryptionFailed)
+    }
+
+    fn check_public_key(&self, public: &PublicKey) -> Result<(), WalletError> {
+        if hex::encode(public.to_bytes()) != self.public_key {
+            return Err(WalletError::InvalidWalletData("Public key does not match secret key".to_string()));
+        }
+        Ok(())
+    }
 }
 
 /// GSIO Wallet for managing keys and transactions
 pub struct Wallet {
-    keypair: Option<Keypair>,
+    /// Signing keys by address
+    keys: HashMap<String, Keypair>,
+    /// Signers for keys the wallet doesn't hold, by address
+    signers: HashMap<String, Box<dyn Signer>>,
+    /// HD master key, present when the wallet came from a mnemonic
+    master: Option<ExtendedKey>,
     accounts: HashMap<String, Account>,
+    /// Address book, by alias
+    contacts: BTreeMap<String, String>,
     wallet_path: Option<PathBuf>,
+    /// Fees the node last asked for
+    fee_rules: Option<FeeRules>,
+    /// Minimum fee the node last reported
+    fee_floor: Option<FeeFloor>,
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Wallet {
     /// Create a new empty wallet
     pub fn new() -> Self {
         Self {
-            keypair: None,
+            keys: HashMap::new(),
+            signers: HashMap::new(),
+            master: None,
             accounts: HashMap::new(),
+            contacts: BTreeMap::new(),
             wallet_path: None,
+            fee_rules: None,
+            fee_floor: None,
         }
     }
 
@@ -105,49 +465,226 @@ impl Wallet {
     pub fn generate_keypair(&mut self) -> Result<String, WalletError> {
         let mut csprng = OsRng;
         let keypair = Keypair::generate(&mut csprng);
+        Ok(self.add_key(keypair, None))
+    }
+
+    /// Generate a new HD wallet seed from a fresh 24-word BIP39 mnemonic.
+    ///
+    /// Account 0 is derived straight away. Returns the phrase; passing it to
+    /// `restore_from_mnemonic` recovers the same accounts.
+    pub fn generate_with_mnemonic(&mut self) -> Result<String, WalletError> {
+        let mut entropy = [0u8; 32];
+        OsRng.fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| WalletError::InvalidMnemonic(e.to_string()))?;
+
+        let phrase = mnemonic.to_string();
+        self.restore_from_mnemonic(&phrase)?;
+        Ok(phrase)
+    }
+
+    /// Restore the HD seed for a BIP39 mnemonic, returning the address of account 0
+    pub fn restore_from_mnemonic(&mut self, phrase: &str) -> Result<String, WalletError> {
+        let mnemonic = Mnemonic::parse_normalized(phrase).map_err(|e| WalletError::InvalidMnemonic(e.to_string()))?;
+        self.master = Some(ExtendedKey::master(&mnemonic.to_seed("")));
+        self.derive_account(0)
+    }
+
+    /// Derive the account at `index` from the wallet's mnemonic, returning its address
+    pub fn derive_account(&mut self, index: u32) -> Result<String, WalletError> {
+        if index >= HARDENED {
+            return Err(WalletError::InvalidDerivationIndex(index));
+        }
+        let master = self
+            .master
+            .as_ref()
+            .ok_or_else(|| WalletError::KeyNotFound("No mnemonic loaded".to_string()))?;
 
-        let address = format!("gsio_{}", hex::encode(&keypair.public.to_bytes()[0..20]));
-        self.keypair = Some(keypair);
+        let keypair = master.derive_account(index).keypair()?;
+        Ok(self.add_key(keypair, Some(index)))
+    }
+
+    /// Add an account that signs through `signer`, returning its address.
+    ///
+    /// The key stays with the signer: saving the wallet keeps the account but
+    /// not the signer, so add the signer again after loading to sign for it.
+    pub fn add_signer(&mut self, signer: impl Signer + 'static) -> Result<String, WalletError> {
+        let public = PublicKey::from_bytes(&signer.public_key())?;
+        let address = self.add_account(&public, None);
+        self.signers.insert(address.clone(), Box::new(signer));
+        Ok(address)
+    }
+
+    /// Import a key exported from another wallet, returning its address.
+    ///
+    /// `key` is what `export_key` or `export_encrypted_key` returned; keys
+    /// exported encrypted need the passphrase they were encrypted under.
+    pub fn import_key(&mut self, key: &str, passphrase: Option<&str>) -> Result<String, WalletError> {
+        let key = key.trim();
+        let keypair = if key.starts_with('{') {
+            let encrypted: EncryptedKey = serde_json::from_str(key)?;
+            let passphrase = passphrase
+                .ok_or_else(|| WalletError::InvalidWalletData("The key is encrypted; a passphrase is needed".to_string()))?;
+            encrypted.decrypt(passphrase)?
+        } else {
+            let secret = SecretKey::from_bytes(&decode_hex("secret key", key)?)?;
+            Keypair { public: PublicKey::from(&secret), secret }
+        };
+        Ok(self.add_key(keypair, None))
+    }
+
+    /// The hex-encoded secret key of `address`. Anyone who sees it can spend
+    /// from the account; prefer `export_encrypted_key`.
+    pub fn export_key(&self, address: &str) -> Result<String, WalletError> {
+        Ok(hex::encode(self.own_key(address)?.secret.as_bytes()))
+    }
+
+    /// The secret key of `address` encrypted under `passphrase`, as JSON, the
+    /// same way wallet files store it
+    pub fn export_encrypted_key(&self, address: &str, passphrase: &str) -> Result<String, WalletError> {
+        Ok(serde_json::to_string(&EncryptedKey::encrypt(self.own_key(address)?, passphrase)?)?)
+    }
 
-        // Create a new account for this keypair
+    /// The key the wallet holds for `address`; keys behind an added signer can't be exported
+    fn own_key(&self, address: &str) -> Result<&Keypair, WalletError> {
+        self.keys.get(address).ok_or_else(|| WalletError::KeyNotFound(address.to_string()))
+    }
+
+    /// Hold `keypair` and create its account if needed, returning the address
+    fn add_key(&mut self, keypair: Keypair, derivation_index: Option<u32>) -> String {
+        let address = self.add_account(&keypair.public, derivation_index);
+        self.keys.insert(address.clone(), keypair);
+        address
+    }
+
+    /// Create the account for `public` if needed, returning its address
+    fn add_account(&mut self, public: &PublicKey, derivation_index: Option<u32>) -> String {
+        let address = address_from_public_key(public);
         let account = Account {
             address: address.clone(),
-            public_key: hex::encode(self.keypair.as_ref().unwrap().public.to_bytes()),
+            public_key: hex::encode(public.to_bytes()),
             balance: 0,
+            staked: 0,
+            unbonding: 0,
             nonce: 0,
+            next_nonce: 0,
             transactions: Vec::new(),
+            derivation_index,
         };
 
-        self.accounts.insert(address.clone(), account);
+        self.accounts.entry(address.clone()).or_insert(account);
+        address
+    }
 
-        Ok(address)
+    /// What signs for `address`: the wallet's own key if it holds one, else an added signer
+    fn signer(&self, address: &str) -> Option<&dyn Signer> {
+        match self.keys.get(address) {
+            Some(keypair) => Some(keypair),
+            None => self.signers.get(address).map(|signer| signer.as_ref()),
+        }
+    }
+
+    /// Set the file the wallet is saved to
+    pub fn set_path(&mut self, path: &Path) {
+        self.wallet_path = Some(path.to_path_buf());
     }
 
-    /// Load wallet from file
-    pub fn load(&mut self, path: &Path) -> Result<(), WalletError> {
-        // This is a stub implementation
+    /// Load wallet from file, decrypting the secret keys with the passphrase
+    pub fn load(&mut self, path: &Path, passphrase: &str) -> Result<(), WalletError> {
         info!("Loading wallet from: {:?}", path);
+
+        let contents = fs::read(path)?;
+        let file: WalletFile = serde_json::from_slice(&contents)?;
+        if file.version == 0 || file.version > WALLET_FILE_VERSION {
+            return Err(WalletError::InvalidWalletData(format!(
+                "Unsupported wallet version: {}",
+                file.version
+            )));
+        }
+
+        let master = match &file.master {
+            Some(master) => Some(master.decrypt_master(passphrase)?),
+            None => None,
+        };
+
+        let mut keys = HashMap::new();
+        for key in file.keys.iter().chain(&file.key) {
+            let keypair = key.decrypt(passphrase)?;
+            keys.insert(address_from_public_key(&keypair.public), keypair);
+        }
+        for account in file.accounts.values() {
+            let Some(index) = account.derivation_index else { continue };
+            let master = master.as_ref().ok_or_else(|| {
+                WalletError::InvalidWalletData(format!("Account {} is derived but the wallet has no master key", account.address))
+            })?;
+            let keypair = master.derive_account(index).keypair()?;
+            keys.insert(address_from_public_key(&keypair.public), keypair);
+        }
+
+        self.keys = keys;
+        self.master = master;
+        // Accounts and contacts saved before addresses had a checksum are renamed
+        self.accounts = file
+            .accounts
+            .into_values()
+            .map(|mut account| {
+                account.address = canonical_address(&account.address).unwrap_or(account.address);
+                (account.address.clone(), account)
+            })
+            .collect();
+        self.contacts = file
+            .contacts
+            .into_iter()
+            .map(|(alias, address)| (alias, canonical_address(&address).unwrap_or(address)))
+            .collect();
         self.wallet_path = Some(path.to_path_buf());
 
-        // In a real implementation, this would load the wallet data from the file
-        // For now, we'll just return Ok to indicate success
         Ok(())
     }
 
-    /// Save wallet to file
-    pub fn save(&self) -> Result<(), WalletError> {
-        // This is a stub implementation
-        if let Some(path) = &self.wallet_path {
-            info!("Saving wallet to: {:?}", path);
-            // In a real implementation, this would save the wallet data to the file
-        } else {
-            return Err(WalletError::IoError(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Wallet path not set",
-            )));
+    /// Save wallet to file, encrypting the secret keys with the passphrase
+    pub fn save(&self, passphrase: &str) -> Result<(), WalletError> {
+        let path = self.wallet_path.as_ref().ok_or_else(|| {
+            WalletError::IoError(io::Error::new(io::ErrorKind::NotFound, "Wallet path not set"))
+        })?;
+        info!("Saving wallet to: {:?}", path);
+
+        let mut keys = Vec::new();
+        for (address, keypair) in &self.keys {
+            let derived = self.accounts.get(address).is_some_and(|a| a.derivation_index.is_some());
+            if !derived {
+                keys.push(EncryptedKey::encrypt(keypair, passphrase)?);
+            }
+        }
+
+        let file = WalletFile {
+            version: WALLET_FILE_VERSION,
+            accounts: self.accounts.clone(),
+            keys,
+            master: match &self.master {
+                Some(master) => Some(EncryptedKey::encrypt_master(master, passphrase)?),
+                None => None,
+            },
+            key: None,
+            contacts: self.contacts.clone(),
+        };
+        let contents = serde_json::to_vec_pretty(&file)?;
+
+        // Write to a temporary file and rename so a crash never leaves a truncated wallet
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut tmp = options.open(&tmp_path)?;
+            tmp.write_all(&contents)?;
+            tmp.sync_all()?;
         }
+        fs::rename(&tmp_path, path)?;
 
-        // For now, we'll just return Ok to indicate success
         Ok(())
     }
 
@@ -158,15 +695,93 @@ impl Wallet {
             .ok_or_else(|| WalletError::WalletNotFound(address.to_string()))
     }
 
+    /// All accounts in the wallet, ordered by address
+    pub fn accounts(&self) -> Vec<&Account> {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by(|a, b| a.address.cmp(&b.address));
+        accounts
+    }
+
+    /// Save `address` in the address book under `alias`, replacing what the alias pointed at before.
+    ///
+    /// Aliases are letters, digits, `-`, `_` and `.`, and can't start with
+    /// `gsio1` or `gsio_` so they are never mistaken for addresses. The
+    /// address must have a matching checksum.
+    pub fn add_contact(&mut self, alias: &str, address: &str) -> Result<(), WalletError> {
+        let lower = alias.to_ascii_lowercase();
+        let valid_alias = !alias.is_empty()
+            && alias.len() <= 64
+            && !lower.starts_with("gsio1")
+            && !lower.starts_with("gsio_")
+            && alias.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_alias {
+            return Err(WalletError::InvalidAlias(alias.to_string()));
+        }
+        let address = Address::parse(address).map_err(|e| WalletError::InvalidAddress(format!("{address}: {e}")))?;
+
+        self.contacts.insert(alias.to_string(), address.to_string());
+        Ok(())
+    }
+
+    /// Remove a contact, returning the address it pointed at
+    pub fn remove_contact(&mut self, alias: &str) -> Option<String> {
+        self.contacts.remove(alias)
+    }
+
+    /// The address book, by alias
+    pub fn contacts(&self) -> &BTreeMap<String, String> {
+        &self.contacts
+    }
+
+    /// The address `recipient` stands for: the address of a contact, or
+    /// `recipient` itself if it is an address
+    pub fn resolve_recipient(&self, recipient: &str) -> Result<String, WalletError> {
+        if let Some(address) = self.contacts.get(recipient) {
+            return Ok(address.clone());
+        }
+        match Address::parse(recipient) {
+            Ok(address) => Ok(address.to_string()),
+            Err(e) if recipient.starts_with("gsio1") || recipient.starts_with("GSIO1") => {
+                Err(WalletError::InvalidAddress(format!("{recipient}: {e}")))
+            }
+            Err(_) => Err(WalletError::InvalidAddress(format!("{recipient} is neither a contact nor an address"))),
+        }
+    }
+
     /// Get account balance
     pub fn get_balance(&self, address: &str) -> Result<u64, WalletError> {
         let account = self.get_account(address)?;
         Ok(account.balance)
     }
 
-    /// Create a new transaction
+    /// Fetch the fees a node asks for, which `estimate_fee` prices transactions with
+    pub async fn fetch_fee_rules(&mut self, client: &GsioClient) -> Result<&FeeRules, WalletError> {
+        let rules = client.get_fee_rules().await?;
+        Ok(self.fee_rules.insert(rules))
+    }
+
+    /// Fetch the minimum fee a node takes, which `estimate_fee` won't go below
+    pub async fn fetch_fee_floor(&mut self, client: &GsioClient) -> Result<&FeeFloor, WalletError> {
+        let floor = client.get_fee_floor().await?;
+        Ok(self.fee_floor.insert(floor))
+    }
+
+    /// The fee the node asks of a transaction of `transaction_type` carrying
+    /// `payload_size` bytes of data, as given by `Transaction::payload_size`,
+    /// and at least its minimum fee if that was fetched
+    pub fn estimate_fee(&self, transaction_type: &TransactionType, payload_size: usize) -> Result<u64, WalletError> {
+        let rules = self.fee_rules.as_ref().ok_or(WalletError::FeeRulesUnknown)?;
+        let min_fee = self.fee_floor.as_ref().map_or(0, |floor| floor.min_fee);
+        Ok(rules.fee(transaction_type.as_str(), payload_size).max(min_fee))
+    }
+
+    /// Create a new transaction with the sender's next nonce.
+    ///
+    /// `recipient` is an address or the alias of a contact. Every transaction
+    /// created from an account gets a nonce of its own, so a transaction
+    /// replayed or signed twice with the same nonce only counts once.
     pub fn create_transaction(
-        &self,
+        &mut self,
         sender: &str,
         recipient: &str,
         amount: u64,
@@ -176,71 +791,326 @@ impl Wallet {
     ) -> Result<Transaction, WalletError> {
         // Check if sender account exists
         let sender_account = self.get_account(sender)?;
+        let recipient = self.resolve_recipient(recipient)?;
 
+        // Unstaking spends stake, everything else the balance
+        let required = match transaction_type {
+            TransactionType::Unstake if sender_account.staked < amount => {
+                return Err(WalletError::InsufficientFunds(amount, sender_account.staked));
+            }
+            TransactionType::Unstake => fee,
+            _ => amount + fee,
+        };
         // Check if sender has enough funds
-        if sender_account.balance < amount + fee {
+        if sender_account.balance < required {
             return Err(WalletError::InsufficientFunds(
-                amount + fee,
+                required,
                 sender_account.balance,
             ));
         }
 
+        let nonce = sender_account.nonce.max(sender_account.next_nonce);
+
         // Create transaction
         let transaction = Transaction {
             id: Uuid::new_v4().to_string(),
             transaction_type,
             amount,
             fee,
+            nonce,
             sender: sender.to_string(),
-            recipient: recipient.to_string(),
+            recipient,
             timestamp: Utc::now(),
             status: TransactionStatus::Pending,
             signature: None,
             data,
         };
+        if let Some(account) = self.accounts.get_mut(sender) {
+            account.next_nonce = nonce + 1;
+        }
 
         Ok(transaction)
     }
 
     /// Sign a transaction
     pub fn sign_transaction(&self, transaction: &mut Transaction) -> Result<(), WalletError> {
-        // This is a stub implementation
-        if self.keypair.is_none() {
-            return Err(WalletError::KeyNotFound("No keypair loaded".to_string()));
-        }
+        // Only the sender's own key may sign
+        let signer = self
+            .signer(&transaction.sender)
+            .ok_or_else(|| WalletError::KeyNotFound(transaction.sender.clone()))?;
 
-        // In a real implementation, this would sign the transaction with the keypair
-        // For now, we'll just set a dummy signature
-        transaction.signature = Some("dummy_signature".to_string());
+        let signature = signer.sign(&transaction.signing_bytes())?;
+        transaction.signature = Some(hex::encode(signature));
 
         Ok(())
     }
 
-    /// Submit a transaction to the network
-    pub async fn submit_transaction(&self, transaction: &Transaction) -> Result<String, WalletError> {
-        // This is a stub implementation
+    /// Sign an arbitrary message with the key of `address`, returning the
+    /// hex-encoded Ed25519 signature; `verify_message` checks it
+    pub fn sign_message(&self, address: &str, message: &[u8]) -> Result<String, WalletError> {
+        let signer = self.signer(address).ok_or_else(|| WalletError::KeyNotFound(address.to_string()))?;
+        Ok(hex::encode(signer.sign(&message_signing_bytes(message))?))
+    }
+
+    /// Replace the key of `address` with a newly generated one, returning the
+    /// new account's address and the rotation statement signed by the old key.
+    ///
+    /// The rotation takes effect once the statement's `to_entry_data()` is
+    /// added to a node's ledger; nodes then refuse data signed with the old
+    /// key. The old account stays in the wallet. The new key is random, not
+    /// derived, so the wallet's mnemonic doesn't recover it: keep the wallet
+    /// file or export the key.
+    pub fn rotate_key(&mut self, address: &str) -> Result<(String, KeyRotation), WalletError> {
+        let signer = self.signer(address).ok_or_else(|| WalletError::KeyNotFound(address.to_string()))?;
+        let old_key = hex::encode(signer.public_key());
+        let keypair = Keypair::generate(&mut OsRng);
+        let new_key = hex::encode(keypair.public.to_bytes());
+        let signature = hex::encode(signer.sign(&KeyRotation::signing_bytes(&old_key, &new_key))?);
+
+        let new_address = self.add_key(keypair, None);
+        Ok((new_address, KeyRotation { old_key, new_key, signature }))
+    }
+
+    /// Sign ledger entry data with the key of `address`, so it passes nodes
+    /// that require signed entries.
+    ///
+    /// Adds the hex-encoded `public_key` and a `signature` over the data
+    /// without its `signature` field, like `gsio_node::validation::sign_data`.
+    pub fn sign_entry(&self, address: &str, mut data: JsonValue) -> Result<JsonValue, WalletError> {
+        let signer = self.signer(address).ok_or_else(|| WalletError::KeyNotFound(address.to_string()))?;
+        let Some(object) = data.as_object_mut() else {
+            return Err(WalletError::InvalidWalletData("Only JSON objects can be signed".to_string()));
+        };
+        object.insert("public_key".to_string(), hex::encode(signer.public_key()).into());
+        object.remove("signature");
+
+        let signature = signer.sign(data.to_string().as_bytes())?;
+        data["signature"] = hex::encode(signature).into();
+        Ok(data)
+    }
+
+    /// Submit a signed transaction to a node and wait for it to be confirmed.
+    ///
+    /// The transaction's status is updated as it goes: `Failed` if the node
+    /// rejects it, `Confirmed` once it appears in the node's ledger. On
+    /// confirmation the affected accounts are updated the same way `sync` would.
+    pub async fn submit_transaction(
+        &mut self,
+        client: &GsioClient,
+        transaction: &mut Transaction,
+    ) -> Result<String, WalletError> {
+        if transaction.signature.is_none() {
+            return Err(WalletError::UnsignedTransaction(transaction.id.clone()));
+        }
         info!("Submitting transaction: {:?}", transaction);
 
-        // In a real implementation, this would submit the transaction to the network
-        // For now, we'll just return the transaction ID to indicate success
+        // Include the sender's key so other wallets can verify the transaction when syncing
+        let public_key = self.get_account(&transaction.sender)?.public_key.clone();
+        let mut payload = serde_json::json!({
+            "type": "transaction",
+            "transaction": transaction,
+            "public_key": public_key,
+        });
+        // Transactions signed elsewhere go in unsigned
+        if self.signer(&transaction.sender).is_some() {
+            payload = self.sign_entry(&transaction.sender, payload)?;
+        }
+
+        let entry = match client.add_ledger_entry(payload).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                transaction.status = TransactionStatus::Failed;
+                if e.code() == Some(ErrorCode::DoubleSpend) {
+                    return Err(WalletError::DoubleSpend(transaction.sender.clone(), transaction.nonce));
+                }
+                return Err(e.into());
+            }
+        };
+
+        self.wait_for_confirmation(client, &entry.id, transaction).await?;
+        transaction.status = TransactionStatus::Confirmed;
+        self.apply_confirmed_transaction(transaction);
+
         Ok(transaction.id.clone())
     }
 
+    /// Poll the node until the entry recording `transaction` is in its ledger
+    async fn wait_for_confirmation(
+        &self,
+        client: &GsioClient,
+        entry_id: &str,
+        transaction: &Transaction,
+    ) -> Result<(), WalletError> {
+        let deadline = tokio::time::Instant::now() + CONFIRMATION_TIMEOUT;
+        loop {
+            if client.get_entry_by_id(entry_id).await?.is_some() {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(WalletError::ConfirmationTimeout(transaction.id.clone()));
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Record a confirmed transaction against the accounts it touches
+    fn apply_confirmed_transaction(&mut self, transaction: &Transaction) {
+        if let Some(sender) = self.accounts.get_mut(&transaction.sender) {
+            let debit = match transaction.transaction_type {
+                TransactionType::Unstake => transaction.fee,
+                _ => transaction.amount.saturating_add(transaction.fee),
+            };
+            sender.balance = sender.balance.saturating_sub(debit);
+            sender.nonce = sender.nonce.max(transaction.nonce + 1);
+            sender.next_nonce = sender.next_nonce.max(sender.nonce);
+            sender.transactions.push(transaction.id.clone());
+            match transaction.transaction_type {
+                TransactionType::Transfer => {}
+                TransactionType::Stake => {
+                    sender.staked = sender.staked.saturating_add(transaction.amount);
+                    return;
+                }
+                TransactionType::Unstake => {
+                    // Unbonding until the next sync says otherwise
+                    sender.staked = sender.staked.saturating_sub(transaction.amount);
+                    sender.unbonding = sender.unbonding.saturating_add(transaction.amount);
+                    return;
+                }
+            }
+        }
+        if let Some(recipient) = self.accounts.get_mut(&transaction.recipient) {
+            recipient.balance = recipient.balance.saturating_add(transaction.amount);
+            if transaction.recipient != transaction.sender {
+                recipient.transactions.push(transaction.id.clone());
+            }
+        }
+    }
+
     /// Get transaction history for an account
     pub fn get_transaction_history(&self, address: &str) -> Result<Vec<String>, WalletError> {
         let account = self.get_account(address)?;
         Ok(account.transactions.clone())
     }
+
+    /// Recompute account balances, nonces and transaction history from a node's ledger
+    pub async fn sync(&mut self, client: &GsioClient) -> Result<(), WalletError> {
+        let entries = fetch_chain(client).await?;
+        info!("Syncing wallet against {} ledger entries", entries.len());
+        // Only the node knows which unstaked amounts are still unbonding
+        for address in self.apply_ledger_entries(&entries) {
+            let stake = client.get_stake(&address).await?;
+            if let Some(account) = self.accounts.get_mut(&address) {
+                account.unbonding = stake.unbonding_total();
+                account.balance = account.balance.saturating_sub(account.unbonding);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild account state from the transactions recorded in `entries`.
+    ///
+    /// Transactions are only counted once and only if the sender's signature
+    /// verifies, using the key stored in the entry or, for our own accounts,
+    /// the account's key. Of the transactions a sender signed with the same
+    /// nonce, only the first in the ledger counts.
+    ///
+    /// `Stake` moves the amount from the balance to `staked` and `Unstake`
+    /// moves it back, as if unbonding were over; returns the accounts that
+    /// unstaked, whose unbonding amounts only the node knows.
+    fn apply_ledger_entries(&mut self, entries: &[LedgerEntry]) -> HashSet<String> {
+        let mut credits: HashMap<String, u64> = HashMap::new();
+        let mut debits: HashMap<String, u64> = HashMap::new();
+        let mut unstaked = HashSet::new();
+        for account in self.accounts.values_mut() {
+            account.nonce = 0;
+            account.staked = 0;
+            account.unbonding = 0;
+            account.transactions.clear();
+        }
+
+        let mut seen = HashSet::new();
+        let mut used_nonces = HashSet::new();
+        for entry in entries {
+            let Some((mut transaction, public_key)) = ledger_transaction(entry) else {
+                continue;
+            };
+            if !seen.insert(transaction.id.clone()) {
+                continue;
+            }
+
+            // Match accounts by address whichever way the transaction wrote it;
+            // the signature is over the addresses as written, so keep those for verifying
+            let sender = canonical_address(&transaction.sender).unwrap_or_else(|| transaction.sender.clone());
+            let public_key = public_key.or_else(|| self.accounts.get(&sender).map(|a| a.public_key.as_str()));
+            if public_key.is_none_or(|key| verify_transaction(&transaction, key).is_err()) {
+                continue;
+            }
+            transaction.sender = sender;
+            transaction.recipient = canonical_address(&transaction.recipient).unwrap_or(transaction.recipient);
+            if !used_nonces.insert((transaction.sender.clone(), transaction.nonce)) {
+                continue;
+            }
+
+            if let Some(sender) = self.accounts.get_mut(&transaction.sender) {
+                sender.nonce = sender.nonce.max(transaction.nonce + 1);
+                sender.transactions.push(transaction.id.clone());
+                let (debit, credit) = match transaction.transaction_type {
+                    TransactionType::Transfer => (transaction.amount.saturating_add(transaction.fee), 0),
+                    TransactionType::Stake => {
+                        sender.staked = sender.staked.saturating_add(transaction.amount);
+                        (transaction.amount.saturating_add(transaction.fee), 0)
+                    }
+                    TransactionType::Unstake => {
+                        sender.staked = sender.staked.saturating_sub(transaction.amount);
+                        unstaked.insert(sender.address.clone());
+                        (transaction.fee, transaction.amount)
+                    }
+                };
+                *debits.entry(sender.address.clone()).or_default() += debit;
+                *credits.entry(sender.address.clone()).or_default() += credit;
+            }
+            if !matches!(transaction.transaction_type, TransactionType::Transfer) {
+                continue;
+            }
+            if let Some(recipient) = self.accounts.get_mut(&transaction.recipient) {
+                *credits.entry(recipient.address.clone()).or_default() += transaction.amount;
+                if transaction.recipient != transaction.sender {
+                    recipient.transactions.push(transaction.id.clone());
+                }
+            }
+        }
+
+        for account in self.accounts.values_mut() {
+            let credit = credits.get(&account.address).copied().unwrap_or(0);
+            let debit = debits.get(&account.address).copied().unwrap_or(0);
+            account.balance = credit.saturating_sub(debit);
+            // Nonces handed out to transactions that aren't on the ledger yet stay taken
+            account.next_nonce = account.next_nonce.max(account.nonce);
+        }
+        unstaked
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use gsio_node::api;
+    use gsio_node::fees::{self, FeeConfig};
+    use gsio_node::ledger::SharedLedger;
+    use gsio_node::mempool::MempoolConfig;
+    use gsio_node::p2p::{NodeMode, P2PManager};
+    use gsio_node::validation::{RequireSignature, ValidationPolicy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    const RECIPIENT: &str = "gsio1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn78x0w8";
 
     #[test]
     fn test_wallet_creation() {
         let wallet = Wallet::new();
-        assert!(wallet.keypair.is_none());
+        assert!(wallet.keys.is_empty());
+        assert!(wallet.master.is_none());
         assert!(wallet.accounts.is_empty());
     }
 
@@ -248,9 +1118,735 @@ mod tests {
     fn test_keypair_generation() {
         let mut wallet = Wallet::new();
         let address = wallet.generate_keypair().unwrap();
-        assert!(wallet.keypair.is_some());
+        assert!(wallet.keys.contains_key(&address));
         assert!(wallet.accounts.contains_key(&address));
     }
 
-    // More tests would be added here in a real implementation
+    #[test]
+    fn test_accounts_listed_by_address() {
+        let mut wallet = Wallet::new();
+        let mut addresses = vec![wallet.generate_keypair().unwrap(), wallet.generate_keypair().unwrap()];
+        addresses.sort();
+        let listed: Vec<&str> = wallet.accounts().iter().map(|a| a.address.as_str()).collect();
+        assert_eq!(listed, addresses);
+    }
+
+    #[test]
+    fn test_mnemonic_round_trip() {
+        let mut wallet = Wallet::new();
+        let phrase = wallet.generate_with_mnemonic().unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 24);
+
+        // The phrase alone recovers the same key
+        let mut restored = Wallet::new();
+        let address = restored.restore_from_mnemonic(&phrase).unwrap();
+        assert!(wallet.accounts.contains_key(&address));
+        assert_eq!(restored.keys[&address].to_bytes(), wallet.keys[&address].to_bytes());
+
+        assert!(matches!(
+            Wallet::new().restore_from_mnemonic("not a valid mnemonic phrase"),
+            Err(WalletError::InvalidMnemonic(_))
+        ));
+    }
+
+    #[test]
+    fn test_slip10_derivation() {
+        // SLIP-0010 ed25519 test vector 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed);
+        assert_eq!(
+            hex::encode(master.secret),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.keypair().unwrap().public.to_bytes()),
+            "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"
+        );
+
+        // m/0'/1'
+        let child = master.derive_hardened(0).derive_hardened(1);
+        assert_eq!(
+            hex::encode(child.secret),
+            "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2"
+        );
+        assert_eq!(
+            hex::encode(child.chain_code),
+            "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14"
+        );
+    }
+
+    #[test]
+    fn test_derive_accounts() {
+        let mut wallet = Wallet::new();
+        let phrase = wallet.generate_with_mnemonic().unwrap();
+        let first = wallet.derive_account(1).unwrap();
+        let second = wallet.derive_account(2).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(wallet.accounts.len(), 3);
+        assert_eq!(wallet.get_account(&second).unwrap().derivation_index, Some(2));
+
+        // Each account signs with its own key
+        wallet.accounts.get_mut(&first).unwrap().balance = 1_000;
+        let mut transaction = wallet
+            .create_transaction(&first, &second, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+        verify_transaction(&transaction, &wallet.get_account(&first).unwrap().public_key).unwrap();
+        assert!(verify_transaction(&transaction, &wallet.get_account(&second).unwrap().public_key).is_err());
+
+        // Derivation is deterministic
+        let mut restored = Wallet::new();
+        restored.restore_from_mnemonic(&phrase).unwrap();
+        assert_eq!(restored.derive_account(2).unwrap(), second);
+
+        // Derived accounts survive a save and load without storing their keys
+        let path = temp_wallet_path();
+        wallet.set_path(&path);
+        wallet.save("correct horse battery staple").unwrap();
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "correct horse battery staple").unwrap();
+        assert_eq!(loaded.keys[&first].to_bytes(), wallet.keys[&first].to_bytes());
+        assert_eq!(loaded.derive_account(3).unwrap(), wallet.derive_account(3).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(wallet.derive_account(HARDENED), Err(WalletError::InvalidDerivationIndex(_))));
+        assert!(matches!(Wallet::new().derive_account(0), Err(WalletError::KeyNotFound(_))));
+    }
+
+    fn temp_wallet_path() -> PathBuf {
+        std::env::temp_dir().join(format!("gsio-wallet-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = temp_wallet_path();
+        let mut wallet = Wallet::new();
+        let address = wallet.generate_keypair().unwrap();
+        wallet.set_path(&path);
+        wallet.save("correct horse battery staple").unwrap();
+
+        // The secret key is not stored in the clear
+        let contents = fs::read_to_string(&path).unwrap();
+        let secret = hex::encode(wallet.keys[&address].secret.as_bytes());
+        assert!(!contents.contains(&secret));
+
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "correct horse battery staple").unwrap();
+        assert!(loaded.accounts.contains_key(&address));
+        assert_eq!(loaded.keys[&address].to_bytes(), wallet.keys[&address].to_bytes());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_version_1_wallet() {
+        let path = temp_wallet_path();
+        let mut wallet = Wallet::new();
+        let address = wallet.generate_keypair().unwrap();
+        let key = EncryptedKey::encrypt(&wallet.keys[&address], "passphrase").unwrap();
+        let file = serde_json::json!({ "version": 1, "accounts": wallet.accounts, "key": key });
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "passphrase").unwrap();
+        assert_eq!(loaded.keys[&address].to_bytes(), wallet.keys[&address].to_bytes());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_with_wrong_passphrase() {
+        let path = temp_wallet_path();
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair().unwrap();
+        wallet.set_path(&path);
+        wallet.save("correct horse battery staple").unwrap();
+
+        let mut loaded = Wallet::new();
+        let result = loaded.load(&path, "wrong passphrase");
+        assert!(matches!(result, Err(WalletError::DecryptionFailed)));
+        assert!(loaded.keys.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_and_import_key() {
+        let mut wallet = Wallet::new();
+        let address = wallet.generate_keypair().unwrap();
+
+        let mut imported = Wallet::new();
+        assert_eq!(imported.import_key(&wallet.export_key(&address).unwrap(), None).unwrap(), address);
+        assert_eq!(imported.keys[&address].to_bytes(), wallet.keys[&address].to_bytes());
+
+        let encrypted = wallet.export_encrypted_key(&address, "export passphrase").unwrap();
+        assert!(!encrypted.contains(&wallet.export_key(&address).unwrap()));
+        let mut imported = Wallet::new();
+        assert!(matches!(imported.import_key(&encrypted, None), Err(WalletError::InvalidWalletData(_))));
+        assert!(matches!(imported.import_key(&encrypted, Some("wrong")), Err(WalletError::DecryptionFailed)));
+        assert_eq!(imported.import_key(&encrypted, Some("export passphrase")).unwrap(), address);
+
+        assert!(matches!(wallet.export_key(RECIPIENT), Err(WalletError::KeyNotFound(_))));
+        assert!(imported.import_key("not hex", None).is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_message() {
+        let mut wallet = Wallet::new();
+        let address = wallet.generate_keypair().unwrap();
+        let public_key = wallet.get_account(&address).unwrap().public_key.clone();
+
+        let signature = wallet.sign_message(&address, b"hello").unwrap();
+        verify_message(b"hello", &signature, &public_key).unwrap();
+        assert!(matches!(verify_message(b"hello!", &signature, &public_key), Err(WalletError::SignatureError(_))));
+        assert!(matches!(wallet.sign_message(RECIPIENT, b"hello"), Err(WalletError::KeyNotFound(_))));
+
+        // A signed message can't be passed off as a signed transaction
+        let (mut wallet, address) = funded_wallet();
+        let mut transaction = wallet.create_transaction(&address, RECIPIENT, 1, 1, TransactionType::Transfer, None).unwrap();
+        transaction.signature = Some(wallet.sign_message(&address, &transaction.signing_bytes()).unwrap());
+        let public_key = wallet.get_account(&address).unwrap().public_key.clone();
+        assert!(matches!(verify_transaction(&transaction, &public_key), Err(WalletError::SignatureError(_))));
+    }
+
+    fn funded_wallet() -> (Wallet, String) {
+        let mut wallet = Wallet::new();
+        let address = wallet.generate_keypair().unwrap();
+        wallet.accounts.get_mut(&address).unwrap().balance = 1_000;
+        (wallet, address)
+    }
+
+    #[test]
+    fn test_sign_and_verify_transaction() {
+        let (mut wallet, address) = funded_wallet();
+        let public_key = wallet.get_account(&address).unwrap().public_key.clone();
+
+        let mut transaction = wallet
+            .create_transaction(
+                &address,
+                RECIPIENT,
+                100,
+                1,
+                TransactionType::Transfer,
+                Some(serde_json::json!({ "memo": "rent", "a": 1 })),
+            )
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+
+        let signature = transaction.signature.clone().unwrap();
+        assert_eq!(signature.len(), 128);
+        verify_transaction(&transaction, &public_key).unwrap();
+
+        // Signatures survive a serialization round trip
+        let round_tripped: Transaction = serde_json::from_str(&serde_json::to_string(&transaction).unwrap()).unwrap();
+        verify_transaction(&round_tripped, &public_key).unwrap();
+
+        // Status changes don't invalidate the signature
+        transaction.status = TransactionStatus::Confirmed;
+        verify_transaction(&transaction, &public_key).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_transaction_fails_verification() {
+        let (mut wallet, address) = funded_wallet();
+        let public_key = wallet.get_account(&address).unwrap().public_key.clone();
+
+        let mut transaction = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+
+        transaction.amount = 900;
+        assert!(matches!(
+            verify_transaction(&transaction, &public_key),
+            Err(WalletError::SignatureError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_key_of_another_account() {
+        let (mut wallet, address) = funded_wallet();
+        let (other, other_address) = funded_wallet();
+        let other_key = other.get_account(&other_address).unwrap().public_key.clone();
+
+        let mut transaction = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+
+        assert!(matches!(verify_transaction(&transaction, &other_key), Err(WalletError::KeyNotFound(_))));
+
+        // A wallet can't sign for an address it doesn't hold
+        assert!(matches!(other.sign_transaction(&mut transaction), Err(WalletError::KeyNotFound(_))));
+    }
+
+    fn transaction_entry(transaction: &Transaction, public_key: Option<&str>) -> LedgerEntry {
+        let mut data = serde_json::json!({ "type": "transaction", "transaction": transaction });
+        if let Some(public_key) = public_key {
+            data["public_key"] = serde_json::json!(public_key);
+        }
+        LedgerEntry::new(data, String::new(), "test-node-1".to_string())
+    }
+
+    #[test]
+    fn test_apply_ledger_entries() {
+        let (mut wallet, address) = funded_wallet();
+        let (mut other, other_address) = funded_wallet();
+        let other_key = other.get_account(&other_address).unwrap().public_key.clone();
+
+        // Incoming payment signed by another wallet
+        let mut incoming = other
+            .create_transaction(&other_address, &address, 500, 5, TransactionType::Transfer, None)
+            .unwrap();
+        other.sign_transaction(&mut incoming).unwrap();
+
+        // Outgoing payment signed by this wallet
+        let mut outgoing = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut outgoing).unwrap();
+
+        // A forged payment that doesn't verify against the claimed key
+        let mut forged = incoming.clone();
+        forged.id = Uuid::new_v4().to_string();
+        forged.amount = 10_000;
+
+        let entries = vec![
+            transaction_entry(&incoming, Some(&other_key)),
+            transaction_entry(&outgoing, None),
+            transaction_entry(&incoming, Some(&other_key)),
+            transaction_entry(&forged, Some(&other_key)),
+            LedgerEntry {
+                data: serde_json::json!({ "message": "not a transaction" }),
+                ..transaction_entry(&outgoing, None)
+            },
+        ];
+        wallet.apply_ledger_entries(&entries);
+
+        // The ledger replaces the local balance rather than adding to it
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!(account.balance, 500 - 101);
+        assert_eq!(account.nonce, 1);
+        assert_eq!(account.transactions, vec![incoming.id.clone(), outgoing.id.clone()]);
+
+        // Syncing again is idempotent
+        wallet.apply_ledger_entries(&entries);
+        assert_eq!(wallet.get_balance(&address).unwrap(), 399);
+        assert_eq!(wallet.get_transaction_history(&address).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_staking() {
+        let (mut wallet, address) = funded_wallet();
+        let (mut other, other_address) = funded_wallet();
+        let other_key = other.get_account(&other_address).unwrap().public_key.clone();
+        let mut incoming = other
+            .create_transaction(&other_address, &address, 1_000, 0, TransactionType::Transfer, None)
+            .unwrap();
+        other.sign_transaction(&mut incoming).unwrap();
+        let mut stake = wallet
+            .create_transaction(&address, &address, 600, 1, TransactionType::Stake, None)
+            .unwrap();
+        wallet.sign_transaction(&mut stake).unwrap();
+
+        // Staked amounts leave the balance without being credited back
+        let mut entries = vec![transaction_entry(&incoming, Some(&other_key)), transaction_entry(&stake, None)];
+        wallet.apply_ledger_entries(&entries);
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!((account.balance, account.staked), (399, 600));
+
+        // Only what is staked can be unstaked, and the fee comes from the balance
+        assert!(matches!(
+            wallet.create_transaction(&address, &address, 601, 1, TransactionType::Unstake, None),
+            Err(WalletError::InsufficientFunds(601, 600))
+        ));
+        assert!(matches!(
+            wallet.create_transaction(&address, &address, 600, 400, TransactionType::Unstake, None),
+            Err(WalletError::InsufficientFunds(400, 399))
+        ));
+        let mut unstake = wallet
+            .create_transaction(&address, &address, 600, 1, TransactionType::Unstake, None)
+            .unwrap();
+        wallet.sign_transaction(&mut unstake).unwrap();
+
+        // Unstaking returns the amount; which part is still unbonding is up to the node
+        entries.push(transaction_entry(&unstake, None));
+        let unstaked = wallet.apply_ledger_entries(&entries);
+        assert_eq!(unstaked, HashSet::from([address.clone()]));
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!((account.balance, account.staked, account.unbonding), (998, 0, 0));
+
+        // A confirmed unstake is unbonding until the next sync
+        wallet.apply_ledger_entries(&entries[..2]);
+        wallet.apply_confirmed_transaction(&unstake);
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!((account.balance, account.staked, account.unbonding), (398, 0, 600));
+    }
+
+    #[test]
+    fn test_nonces_are_assigned_in_order() {
+        let (mut wallet, address) = funded_wallet();
+        let (mut other, other_address) = funded_wallet();
+        let create = |wallet: &mut Wallet, sender: &str| {
+            wallet
+                .create_transaction(sender, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+                .unwrap()
+        };
+        assert_eq!(create(&mut wallet, &address).nonce, 0);
+        assert_eq!(create(&mut wallet, &address).nonce, 1);
+        assert_eq!(create(&mut other, &other_address).nonce, 0);
+        assert_eq!(wallet.get_account(&address).unwrap().next_nonce, 2);
+
+        // A failed transaction doesn't use up a nonce
+        assert!(wallet.create_transaction(&address, RECIPIENT, 5_000, 1, TransactionType::Transfer, None).is_err());
+        assert_eq!(create(&mut wallet, &address).nonce, 2);
+
+        // The nonce is signed
+        let public_key = wallet.get_account(&address).unwrap().public_key.clone();
+        let mut transaction = create(&mut wallet, &address);
+        wallet.sign_transaction(&mut transaction).unwrap();
+        transaction.nonce = 0;
+        assert!(verify_transaction(&transaction, &public_key).is_err());
+    }
+
+    #[test]
+    fn test_transactions_reusing_a_nonce_count_once() {
+        let (mut wallet, address) = funded_wallet();
+        let (mut other, other_address) = funded_wallet();
+        let other_key = other.get_account(&other_address).unwrap().public_key.clone();
+
+        let mut incoming = other
+            .create_transaction(&other_address, &address, 500, 5, TransactionType::Transfer, None)
+            .unwrap();
+        other.sign_transaction(&mut incoming).unwrap();
+        let mut first = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut first).unwrap();
+
+        // The same nonce spent again from a copy of the wallet
+        let mut double_spend = first.clone();
+        double_spend.id = Uuid::new_v4().to_string();
+        double_spend.recipient = Address::from_public_key(&[0xee; 32]).to_string();
+        wallet.sign_transaction(&mut double_spend).unwrap();
+
+        wallet.apply_ledger_entries(&[
+            transaction_entry(&incoming, Some(&other_key)),
+            transaction_entry(&first, None),
+            transaction_entry(&double_spend, None),
+        ]);
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!(account.balance, 399);
+        assert_eq!(account.transactions, vec![incoming.id.clone(), first.id.clone()]);
+        assert_eq!((account.nonce, account.next_nonce), (1, 1));
+
+        let next = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        assert_eq!(next.nonce, 1);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_fee() {
+        let (mut wallet, address) = funded_wallet();
+        assert!(matches!(
+            wallet.estimate_fee(&TransactionType::Transfer, 0),
+            Err(WalletError::FeeRulesUnknown)
+        ));
+
+        let fees = FeeConfig { base: 2, per_byte: 1, types: [("Stake".to_string(), 10)].into() };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, fees::router(Arc::new(fees))).await.unwrap();
+        });
+        let client = GsioClient::new(&format!("http://{addr}")).unwrap();
+        wallet.fetch_fee_rules(&client).await.unwrap();
+
+        assert_eq!(wallet.estimate_fee(&TransactionType::Transfer, 0).unwrap(), 2);
+        assert_eq!(wallet.estimate_fee(&TransactionType::Stake, 0).unwrap(), 10);
+        let data = serde_json::json!({ "memo": "rent" });
+        let transaction = wallet
+            .create_transaction(&address, RECIPIENT, 1, 0, TransactionType::Transfer, Some(data))
+            .unwrap();
+        assert_eq!(transaction.payload_size(), 15);
+        assert_eq!(wallet.estimate_fee(&transaction.transaction_type, transaction.payload_size()).unwrap(), 17);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_fee_respects_fee_floor() {
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(
+            P2PManager::new(node_id.clone(), SharedLedger::new(node_id))
+                .with_mempool_config(MempoolConfig { min_fee: 5 }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = api::router(p2p).merge(fees::router(Arc::new(FeeConfig::default())));
+            axum::serve(listener, app).await.unwrap();
+        });
+        let client = GsioClient::new(&format!("http://{addr}")).unwrap();
+
+        let mut wallet = Wallet::new();
+        wallet.fetch_fee_rules(&client).await.unwrap();
+        assert_eq!(wallet.estimate_fee(&TransactionType::Transfer, 0).unwrap(), 1);
+        assert_eq!(wallet.fetch_fee_floor(&client).await.unwrap().min_fee, 5);
+        assert_eq!(wallet.estimate_fee(&TransactionType::Transfer, 0).unwrap(), 5);
+    }
+
+    async fn start_node(mode: NodeMode) -> GsioClient {
+        let node_id = "test-node-1".to_string();
+        let p2p = Arc::new(P2PManager::new(node_id.clone(), SharedLedger::new(node_id)).with_mode(mode));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, api::router(p2p)).await.unwrap();
+        });
+        GsioClient::new(&format!("http://{addr}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_submit_transaction() {
+        let client = start_node(NodeMode::Writer).await;
+        let (mut wallet, address) = funded_wallet();
+
+        let mut transaction = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+
+        // Unsigned transactions are never sent
+        assert!(matches!(
+            wallet.submit_transaction(&client, &mut transaction).await,
+            Err(WalletError::UnsignedTransaction(_))
+        ));
+
+        wallet.sign_transaction(&mut transaction).unwrap();
+        let id = wallet.submit_transaction(&client, &mut transaction).await.unwrap();
+        assert_eq!(id, transaction.id);
+        assert!(matches!(transaction.status, TransactionStatus::Confirmed));
+
+        let account = wallet.get_account(&address).unwrap();
+        assert_eq!(account.balance, 899);
+        assert_eq!(account.nonce, 1);
+        assert_eq!(account.transactions, vec![id.clone()]);
+
+        // The node stored the transaction along with the sender's key, so a fresh sync agrees
+        let entries = client.get_ledger().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        wallet.sync(&client).await.unwrap();
+        assert_eq!(wallet.get_account(&address).unwrap().transactions, vec![id]);
+        assert_eq!(wallet.get_account(&address).unwrap().nonce, 1);
+    }
+
+    #[tokio::test]
+    async fn test_double_spend_is_refused() {
+        let client = start_node(NodeMode::Writer).await;
+        let (mut wallet, address) = funded_wallet();
+        let mut first = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut first).unwrap();
+        wallet.submit_transaction(&client, &mut first).await.unwrap();
+
+        // The same nonce spent on another payment, as a second copy of the wallet would
+        let mut second = wallet
+            .create_transaction(&address, RECIPIENT, 200, 1, TransactionType::Transfer, None)
+            .unwrap();
+        second.nonce = first.nonce;
+        wallet.sign_transaction(&mut second).unwrap();
+        let result = wallet.submit_transaction(&client, &mut second).await;
+        assert!(matches!(result, Err(WalletError::DoubleSpend(sender, 0)) if sender == address));
+        assert!(matches!(second.status, TransactionStatus::Failed));
+        assert_eq!(client.get_ledger().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_transaction_is_marked_failed() {
+        let client = start_node(NodeMode::Follower { writable_node: None }).await;
+        let (mut wallet, address) = funded_wallet();
+
+        let mut transaction = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+
+        let result = wallet.submit_transaction(&client, &mut transaction).await;
+        assert!(matches!(result, Err(WalletError::NodeError(_))));
+        assert!(matches!(transaction.status, TransactionStatus::Failed));
+        assert_eq!(wallet.get_balance(&address).unwrap(), 1_000);
+        assert!(wallet.get_transaction_history(&address).unwrap().is_empty());
+    }
+
+    /// A key held somewhere else, counting the signatures asked of it
+    struct RemoteSigner {
+        keypair: Keypair,
+        signed: Arc<AtomicUsize>,
+    }
+
+    impl Signer for RemoteSigner {
+        fn public_key(&self) -> [u8; 32] {
+            self.keypair.public.to_bytes()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<[u8; 64], WalletError> {
+            self.signed.fetch_add(1, Ordering::SeqCst);
+            Signer::sign(&self.keypair, message)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_signer() {
+        let signed = Arc::new(AtomicUsize::new(0));
+        let remote = || {
+            let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+            let keypair = Keypair { public: PublicKey::from(&secret), secret };
+            RemoteSigner { keypair, signed: signed.clone() }
+        };
+        let mut wallet = Wallet::new();
+        let address = wallet.add_signer(remote()).unwrap();
+        assert_eq!(address, address_from_public_key(&remote().keypair.public));
+        assert!(!wallet.keys.contains_key(&address));
+        wallet.accounts.get_mut(&address).unwrap().balance = 1_000;
+
+        let mut transaction = wallet
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        wallet.sign_transaction(&mut transaction).unwrap();
+        verify_transaction(&transaction, &wallet.get_account(&address).unwrap().public_key).unwrap();
+        assert_eq!(signed.load(Ordering::SeqCst), 1);
+
+        // Entries it signs pass nodes that require signatures
+        let entry = wallet.sign_entry(&address, serde_json::json!({ "message": "hello", "signature": "stale" })).unwrap();
+        RequireSignature.validate(&entry).unwrap();
+        assert!(wallet.sign_entry(&address, serde_json::json!("not an object")).is_err());
+
+        // Submitted transactions are signed entries too
+        let node_id = "test-node-1".to_string();
+        let ledger = SharedLedger::new(node_id.clone());
+        ledger.set_validation_policy(Arc::new(RequireSignature));
+        let p2p = Arc::new(P2PManager::new(node_id, ledger));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, api::router(p2p)).await.unwrap();
+        });
+        let client = GsioClient::new(&format!("http://{addr}")).unwrap();
+        wallet.submit_transaction(&client, &mut transaction).await.unwrap();
+        assert!(matches!(transaction.status, TransactionStatus::Confirmed));
+
+        // The signer isn't saved with the wallet
+        let path = temp_wallet_path();
+        wallet.set_path(&path);
+        wallet.save("correct horse battery staple").unwrap();
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "correct horse battery staple").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded.sign_transaction(&mut transaction), Err(WalletError::KeyNotFound(_))));
+        assert_eq!(loaded.add_signer(remote()).unwrap(), address);
+        assert_eq!(loaded.get_balance(&address).unwrap(), 899);
+        loaded.sign_transaction(&mut transaction).unwrap();
+    }
+
+    #[test]
+    fn test_contacts() {
+        let (mut wallet, address) = funded_wallet();
+        wallet.add_contact("alice", RECIPIENT).unwrap();
+        assert_eq!(wallet.resolve_recipient("alice").unwrap(), RECIPIENT);
+        assert_eq!(wallet.resolve_recipient(RECIPIENT).unwrap(), RECIPIENT);
+
+        let transaction = wallet
+            .create_transaction(&address, "alice", 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        assert_eq!(transaction.recipient, RECIPIENT);
+
+        // Upper case addresses are fine, and are recorded in lower case
+        assert_eq!(wallet.resolve_recipient(&RECIPIENT.to_uppercase()).unwrap(), RECIPIENT);
+
+        // Typos are caught before a transaction is made
+        let typo = RECIPIENT.replace("78x0w8", "78x0w9");
+        let legacy = "gsio_000102030405060708090a0b0c0d0e0f10111213";
+        for recipient in ["bob", "gsio_0011", legacy, &typo, &RECIPIENT.replacen('q', "Q", 1), &format!("{RECIPIENT}0")] {
+            assert!(matches!(
+                wallet.create_transaction(&address, recipient, 100, 1, TransactionType::Transfer, None),
+                Err(WalletError::InvalidAddress(_))
+            ));
+        }
+        assert!(matches!(wallet.add_contact("bob", "gsio_0011"), Err(WalletError::InvalidAddress(_))));
+        assert!(matches!(wallet.add_contact("bob", &typo), Err(WalletError::InvalidAddress(e)) if e.contains("checksum")));
+        for alias in ["", "gsio_bob", "gsio1bob", "GSIO1bob", "bob smith", &"b".repeat(65)] {
+            assert!(matches!(wallet.add_contact(alias, RECIPIENT), Err(WalletError::InvalidAlias(_))));
+        }
+
+        // The address book is saved with the wallet
+        let path = temp_wallet_path();
+        wallet.set_path(&path);
+        wallet.save("correct horse battery staple").unwrap();
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "correct horse battery staple").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.contacts(), wallet.contacts());
+
+        assert_eq!(loaded.remove_contact("alice").as_deref(), Some(RECIPIENT));
+        assert!(loaded.resolve_recipient("alice").is_err());
+    }
+
+    #[test]
+    fn test_legacy_addresses() {
+        let (mut wallet, address) = funded_wallet();
+        let legacy = format!("gsio_{}", hex::encode(Address::parse(&address).unwrap().as_bytes()));
+
+        // Wallet files from before checksums name the account the old way
+        let path = temp_wallet_path();
+        wallet.set_path(&path);
+        wallet.add_contact("alice", RECIPIENT).unwrap();
+        wallet.save("correct horse battery staple").unwrap();
+        let contents = fs::read_to_string(&path).unwrap().replace(&address, &legacy).replace(
+            RECIPIENT,
+            "gsio_000102030405060708090a0b0c0d0e0f10111213",
+        );
+        fs::write(&path, contents).unwrap();
+        let mut loaded = Wallet::new();
+        loaded.load(&path, "correct horse battery staple").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get_account(&address).unwrap().address, address);
+        assert_eq!(loaded.contacts()["alice"], RECIPIENT);
+
+        // Transactions on the ledger that were signed with the old address still count
+        let mut transaction = loaded
+            .create_transaction(&address, RECIPIENT, 100, 1, TransactionType::Transfer, None)
+            .unwrap();
+        transaction.sender = legacy;
+        let signature = ed25519_dalek::Signer::sign(&loaded.keys[&address], &transaction.signing_bytes());
+        transaction.signature = Some(hex::encode(signature.to_bytes()));
+        let public_key = loaded.get_account(&address).unwrap().public_key.clone();
+        verify_transaction(&transaction, &public_key).unwrap();
+
+        loaded.apply_ledger_entries(&[transaction_entry(&transaction, None)]);
+        assert_eq!(loaded.get_account(&address).unwrap().transactions, vec![transaction.id.clone()]);
+        assert_eq!(loaded.get_account(&address).unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn test_rotate_key() {
+        let mut wallet = Wallet::new();
+        let old = wallet.generate_keypair().unwrap();
+        let (new, rotation) = wallet.rotate_key(&old).unwrap();
+        assert_ne!(new, old);
+        rotation.verify().unwrap();
+        assert_eq!(rotation.old_key, wallet.get_account(&old).unwrap().public_key);
+        assert_eq!(rotation.new_key, wallet.get_account(&new).unwrap().public_key);
+
+        // Both accounts are kept, and the new one can sign
+        assert_eq!(wallet.accounts().len(), 2);
+        let signature = wallet.sign_message(&new, b"hello").unwrap();
+        verify_message(b"hello", &signature, &rotation.new_key).unwrap();
+        assert!(matches!(wallet.rotate_key("gsio1unknown"), Err(WalletError::KeyNotFound(_))));
+    }
+
+    #[test]
+    fn test_save_without_path() {
+        let wallet = Wallet::new();
+        assert!(matches!(wallet.save("passphrase"), Err(WalletError::IoError(_))));
+    }
 }